@@ -0,0 +1,238 @@
+// The control-flow graph of a method for the cfg subcommand: the code is split into basic blocks,
+// which are connected by fall-through, branch and exception edges and printed as a Graphviz dot
+// graph.
+
+use std::{collections::BTreeSet, error::Error, fmt::Write};
+
+use crate::{
+    disasm::instruction_text,
+    instruction::{decode_all, Instruction, Operands},
+    parse::{Class, ExceptionTableItem, Method},
+};
+
+// a run of instructions that is only entered at the first and only left after the last one
+pub struct BasicBlock {
+    pub start_pc: usize,
+    // exclusive
+    pub end_pc: usize,
+    pub instructions: Vec<Instruction>,
+    pub successors: Vec<Edge>,
+}
+
+pub struct Edge {
+    pub target_pc: usize,
+    pub kind: EdgeKind,
+}
+
+pub enum EdgeKind {
+    FallThrough,
+    Branch,
+    // a tableswitch or lookupswitch case
+    Case(i32),
+    Default,
+    // the handler of an exception table entry, None for finally blocks
+    Exception(Option<u16>),
+}
+
+pub fn basic_blocks(
+    code: &[u8],
+    exception_table: &[ExceptionTableItem],
+) -> Result<Vec<BasicBlock>, Box<dyn Error>> {
+    let instructions = decode_all(code)?;
+
+    // a new block starts at every branch target, after every branch and at the boundaries of
+    // every protected range, so each block is either completely covered by a handler or not at all
+    let mut leaders = BTreeSet::new();
+    leaders.insert(0);
+    for instruction in instructions.iter() {
+        let targets = instruction.branch_targets();
+        if !targets.is_empty() || instruction.is_unconditional_transfer() {
+            leaders.insert(instruction.pc + instruction.length);
+        }
+        leaders.extend(targets);
+    }
+    for item in exception_table.iter() {
+        leaders.insert(item.start_pc);
+        leaders.insert(item.end_pc);
+        leaders.insert(item.handler_pc);
+    }
+    leaders.retain(|pc| *pc < code.len());
+
+    let mut blocks: Vec<BasicBlock> = vec![];
+    for instruction in instructions.into_iter() {
+        if leaders.contains(&instruction.pc) {
+            blocks.push(BasicBlock {
+                start_pc: instruction.pc,
+                end_pc: instruction.pc,
+                instructions: vec![],
+                successors: vec![],
+            });
+        }
+        let block = blocks
+            .last_mut()
+            .ok_or("code does not start with an instruction")?;
+        block.end_pc = instruction.pc + instruction.length;
+        block.instructions.push(instruction);
+    }
+
+    for block in blocks.iter_mut() {
+        let last = block
+            .instructions
+            .last()
+            .ok_or("basic block without instructions")?;
+        let mut successors = vec![];
+        match &last.operands {
+            Operands::TableSwitch {
+                default,
+                low,
+                targets,
+                ..
+            } => {
+                for (i, target) in targets.iter().enumerate() {
+                    successors.push(Edge {
+                        target_pc: *target,
+                        kind: EdgeKind::Case(low.wrapping_add(i as i32)),
+                    });
+                }
+                successors.push(Edge {
+                    target_pc: *default,
+                    kind: EdgeKind::Default,
+                });
+            }
+            Operands::LookupSwitch { default, pairs } => {
+                for (r#match, target) in pairs.iter() {
+                    successors.push(Edge {
+                        target_pc: *target,
+                        kind: EdgeKind::Case(*r#match),
+                    });
+                }
+                successors.push(Edge {
+                    target_pc: *default,
+                    kind: EdgeKind::Default,
+                });
+            }
+            _ => {
+                for target in last.branch_targets() {
+                    successors.push(Edge {
+                        target_pc: target,
+                        kind: EdgeKind::Branch,
+                    });
+                }
+            }
+        }
+        if !last.is_unconditional_transfer() && block.end_pc < code.len() {
+            successors.push(Edge {
+                target_pc: block.end_pc,
+                kind: EdgeKind::FallThrough,
+            });
+        }
+        for item in exception_table.iter() {
+            if item.start_pc <= block.start_pc && block.start_pc < item.end_pc {
+                successors.push(Edge {
+                    target_pc: item.handler_pc,
+                    kind: EdgeKind::Exception(if item.catch_type == 0 {
+                        None
+                    } else {
+                        Some(item.catch_type as u16)
+                    }),
+                });
+            }
+        }
+        block.successors = successors;
+    }
+    Ok(blocks)
+}
+
+// finds a method by its name, or by its name and descriptor like main([Ljava/lang/String;)V if
+// the name alone is ambiguous
+pub fn find_method<'a>(class: &'a Class, name: &str) -> Result<&'a Method, Box<dyn Error>> {
+    let candidates: Vec<&Method> = class
+        .methods
+        .iter()
+        .map(|method| method.as_ref())
+        .filter(|method| {
+            method.name == name
+                || format!("{}{}", method.name, method.descriptor.descriptor()) == name
+        })
+        .collect();
+    match candidates.as_slice() {
+        [method] => Ok(method),
+        [] => Err(format!("no method {name} in {}", class.this_class.name).into()),
+        _ => Err(format!(
+            "method name {name} is ambiguous, use one of: {}",
+            candidates
+                .iter()
+                .map(|method| format!("{}{}", method.name, method.descriptor.descriptor()))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+        .into()),
+    }
+}
+
+// strings in dot are quoted, \l ends a left-justified line of a label
+fn escape_dot(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn method_cfg_dot(class: &Class, method: &Method) -> Result<String, Box<dyn Error>> {
+    let (code, _, _, exception_table) = method
+        .attributes
+        .iter()
+        .find_map(|attribute| attribute.as_code())
+        .ok_or(format!("method {} has no code", method.name))?;
+    let blocks = basic_blocks(&code, &exception_table)?;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        "digraph \"{}\" {{",
+        escape_dot(&format!(
+            "{}.{}{}",
+            class.this_class.name,
+            method.name,
+            method.descriptor.descriptor()
+        ))
+    )?;
+    writeln!(out, "  node [shape=box, fontname=\"monospace\"];")?;
+    for block in blocks.iter() {
+        let mut label = String::new();
+        for instruction in block.instructions.iter() {
+            label.push_str(&escape_dot(&format!(
+                "{}: {}",
+                instruction.pc,
+                instruction_text(class, instruction)
+            )));
+            label.push_str("\\l");
+        }
+        writeln!(out, "  block_{} [label=\"{label}\"];", block.start_pc)?;
+    }
+    for block in blocks.iter() {
+        for edge in block.successors.iter() {
+            let attributes = match &edge.kind {
+                EdgeKind::FallThrough | EdgeKind::Branch => String::new(),
+                EdgeKind::Case(r#match) => format!(" [label=\"{}\"]", r#match),
+                EdgeKind::Default => " [label=\"default\"]".to_owned(),
+                EdgeKind::Exception(catch_type) => {
+                    let catch_type = match catch_type {
+                        Some(index) => class
+                            .constant_pool
+                            .get(*index as usize - 1)
+                            .and_then(|constant| constant.as_class())
+                            .map(|class_info| class_info.name.clone())
+                            .unwrap_or_else(|| format!("#{index}")),
+                        None => "any".to_owned(),
+                    };
+                    format!(" [label=\"{}\", style=dashed]", escape_dot(&catch_type))
+                }
+            };
+            writeln!(
+                out,
+                "  block_{} -> block_{}{attributes};",
+                block.start_pc, edge.target_pc
+            )?;
+        }
+    }
+    writeln!(out, "}}")?;
+    Ok(out)
+}
@@ -0,0 +1,104 @@
+// The classes a class depends on, like jdeps -verbose:class reports them, see the deps subcommand.
+
+use std::{collections::BTreeSet, error::Error, fmt::Write};
+
+use crate::{
+    deserialize::deserialize_class_bytes,
+    jar::{read_jar_classes, JarEntry},
+    parse::{parse, Class, Constant},
+    run::find_class_file,
+};
+
+// adds the classes of all reference types in a field or method descriptor
+fn descriptor_classes(descriptor: &str, classes: &mut BTreeSet<String>) {
+    let mut rest = descriptor;
+    while let Some(start) = rest.find('L') {
+        let name_and_rest = &rest[start + 1..];
+        match name_and_rest.find(';') {
+            Some(end) => {
+                classes.insert(name_and_rest[..end].to_owned());
+                rest = &name_and_rest[end + 1..];
+            }
+            None => break,
+        }
+    }
+}
+
+// a class constant names either a class or an array type, e.g. [Ljava/lang/String;
+fn class_constant_classes(name: &str, classes: &mut BTreeSet<String>) {
+    if name.starts_with('[') {
+        descriptor_classes(name, classes);
+    } else {
+        classes.insert(name.to_owned());
+    }
+}
+
+// every class referenced from the constant pool or by the descriptors of the class, apart from the
+// class itself
+pub fn referenced_classes(class: &Class) -> BTreeSet<String> {
+    let mut classes = BTreeSet::new();
+    for constant in class.constant_pool.iter() {
+        match constant {
+            Constant::Class(class_info) => class_constant_classes(&class_info.name, &mut classes),
+            Constant::FieldRef(class_info, name_and_type)
+            | Constant::MethodRef(class_info, name_and_type)
+            | Constant::InterfaceMethodRef(class_info, name_and_type) => {
+                class_constant_classes(&class_info.name, &mut classes);
+                if let Constant::NameAndType(_, descriptor) = name_and_type.as_ref() {
+                    descriptor_classes(descriptor, &mut classes);
+                }
+            }
+            Constant::NameAndType(_, descriptor) | Constant::MethodType(descriptor) => {
+                descriptor_classes(descriptor, &mut classes)
+            }
+            _ => {}
+        }
+    }
+    for field in class.fields.iter() {
+        descriptor_classes(&field.descriptor.field_type.descriptor(), &mut classes);
+    }
+    for method in class.methods.iter() {
+        descriptor_classes(&method.descriptor.descriptor(), &mut classes);
+    }
+    classes.remove(&class.this_class.name);
+    classes
+}
+
+// lists the dependencies of a class file, or of every class in a jar, together with where they
+// would be loaded from. Classes of a jar may also depend on each other. Returns the listing and
+// the number of dependencies that could not be found
+pub fn dependency_report(file: &str) -> Result<(String, usize), Box<dyn Error>> {
+    let class_files = if file.ends_with(".jar") {
+        read_jar_classes(file)?
+    } else {
+        vec![JarEntry {
+            name: file.to_owned(),
+            bytes: std::fs::read(file)?,
+        }]
+    };
+    let mut classes = vec![];
+    for class_file in class_files.iter() {
+        let class = parse(deserialize_class_bytes(&class_file.bytes)?)
+            .map_err(|error| format!("failed to parse {}: {error}", class_file.name))?;
+        classes.push(class);
+    }
+    let provided: BTreeSet<&String> = classes.iter().map(|class| &class.this_class.name).collect();
+
+    let mut out = String::new();
+    let mut not_found = 0;
+    for class in classes.iter() {
+        writeln!(out, "{}", class.this_class.name)?;
+        for referenced in referenced_classes(class).iter() {
+            let location = if provided.contains(referenced) {
+                file.to_owned()
+            } else if let Some((_, directory)) = find_class_file(referenced) {
+                directory.to_owned()
+            } else {
+                not_found += 1;
+                "not found".to_owned()
+            };
+            writeln!(out, "   -> {referenced:<50} {location}")?;
+        }
+    }
+    Ok((out, not_found))
+}
@@ -1,5 +1,6 @@
 use std::{
     error::Error,
+    fmt::Display,
     fs::File,
     io::{BufReader, Cursor, Read},
 };
@@ -104,6 +105,49 @@ pub enum CPInfo {
         bootstrap_method_attr_index: u16,
         name_and_type_index: u16,
     },
+    // https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.4.10
+    ConstantDynamicInfo {
+        tag: u8,
+        bootstrap_method_attr_index: u16,
+        name_and_type_index: u16,
+    },
+    // https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.4.11
+    ConstantModuleInfo {
+        tag: u8,
+        name_index: u16,
+    },
+    // https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.4.12
+    ConstantPackageInfo {
+        tag: u8,
+        name_index: u16,
+    },
+    // the entry following a long or double, which is valid but unusable
+    Unusable,
+}
+
+impl CPInfo {
+    pub fn tag(&self) -> u8 {
+        match self {
+            CPInfo::ConstantClassInfo { tag, .. }
+            | CPInfo::ConstantFieldRefInfo { tag, .. }
+            | CPInfo::ConstantMethodRefInfo { tag, .. }
+            | CPInfo::ConstantInterfaceMethodRefInfo { tag, .. }
+            | CPInfo::ConstantStringInfo { tag, .. }
+            | CPInfo::ConstantIntegerInfo { tag, .. }
+            | CPInfo::ConstantFloatInfo { tag, .. }
+            | CPInfo::ConstantLongInfo { tag, .. }
+            | CPInfo::ConstantDoubleInfo { tag, .. }
+            | CPInfo::ConstantNameAndTypeInfo { tag, .. }
+            | CPInfo::ConstantUtf8Info { tag, .. }
+            | CPInfo::ConstantMethodHandleInfo { tag, .. }
+            | CPInfo::ConstantMethodTypeInfo { tag, .. }
+            | CPInfo::ConstantInvokeDynamicInfo { tag, .. }
+            | CPInfo::ConstantDynamicInfo { tag, .. }
+            | CPInfo::ConstantModuleInfo { tag, .. }
+            | CPInfo::ConstantPackageInfo { tag, .. } => *tag,
+            CPInfo::Unusable => 0,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -131,16 +175,185 @@ pub struct MethodInfo {
     pub attributes: Vec<AttributeInfo>,
 }
 
-fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dyn Error>> {
-    let tag = rdr.read_u8()?;
+// everything that can go wrong while deserializing a class file. The offsets are the positions
+// in the class file where the offending structure starts.
+#[derive(Debug)]
+pub enum DeserializeError {
+    // the class file could not be read at all
+    Io(std::io::Error),
+    // the class file ended in the middle of the structure described by reading
+    UnexpectedEof {
+        offset: u64,
+        reading: &'static str,
+    },
+    InvalidMagic {
+        offset: u64,
+        magic: u32,
+    },
+    UnsupportedVersion {
+        offset: u64,
+        major_version: u16,
+        minor_version: u16,
+    },
+    UnknownConstantTag {
+        offset: u64,
+        // the constant pool index of the constant
+        index: u16,
+        tag: u8,
+    },
+    // the constant kind was only introduced in a later class file version
+    ConstantTagTooNew {
+        offset: u64,
+        index: u16,
+        tag: u8,
+        major_version: u16,
+    },
+    // a long or double takes up the last constant pool entry, leaving no room for its second one
+    TruncatedConstantPool {
+        offset: u64,
+        index: u16,
+    },
+    TruncatedAttribute {
+        offset: u64,
+        attribute_name_index: u16,
+        attribute_length: u32,
+        available: u64,
+    },
+    TrailingBytes {
+        offset: u64,
+        count: u64,
+    },
+}
+
+impl DeserializeError {
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            DeserializeError::Io(_) => None,
+            DeserializeError::UnexpectedEof { offset, .. }
+            | DeserializeError::InvalidMagic { offset, .. }
+            | DeserializeError::UnsupportedVersion { offset, .. }
+            | DeserializeError::UnknownConstantTag { offset, .. }
+            | DeserializeError::ConstantTagTooNew { offset, .. }
+            | DeserializeError::TruncatedConstantPool { offset, .. }
+            | DeserializeError::TruncatedAttribute { offset, .. }
+            | DeserializeError::TrailingBytes { offset, .. } => Some(*offset),
+        }
+    }
+}
+
+impl Display for DeserializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeserializeError::Io(e) => write!(f, "failed to read class file: {e}"),
+            DeserializeError::UnexpectedEof { offset, reading } => {
+                write!(f, "unexpected end of class file at {offset:#x} while reading {reading}")
+            }
+            DeserializeError::InvalidMagic { offset, magic } => {
+                write!(f, "invalid magic {magic:#010x} at {offset:#x}, expected 0xcafebabe")
+            }
+            DeserializeError::UnsupportedVersion {
+                offset,
+                major_version,
+                minor_version,
+            } => write!(
+                f,
+                "unsupported class file version {major_version}.{minor_version} at {offset:#x}"
+            ),
+            DeserializeError::UnknownConstantTag { offset, index, tag } => write!(
+                f,
+                "unknown tag {tag} of constant pool entry {index} at {offset:#x}"
+            ),
+            DeserializeError::ConstantTagTooNew {
+                offset,
+                index,
+                tag,
+                major_version,
+            } => write!(
+                f,
+                "tag {tag} of constant pool entry {index} at {offset:#x} is not allowed in class file version {major_version}"
+            ),
+            DeserializeError::TruncatedConstantPool { offset, index } => write!(
+                f,
+                "constant pool entry {index} at {offset:#x} needs two entries, but is the last one"
+            ),
+            DeserializeError::TruncatedAttribute {
+                offset,
+                attribute_name_index,
+                attribute_length,
+                available,
+            } => write!(
+                f,
+                "attribute with name index {attribute_name_index} at {offset:#x} is {attribute_length} bytes long, but only {available} bytes are left"
+            ),
+            DeserializeError::TrailingBytes { offset, count } => {
+                write!(f, "{count} unexpected bytes at the end of the class file at {offset:#x}")
+            }
+        }
+    }
+}
+
+impl Error for DeserializeError {}
+
+// the class file version of java 21
+pub const MAX_MAJOR_VERSION: u16 = 65;
+
+// the class file version a kind of constant was introduced in, see JVMS §4.4 table 4.4-B
+fn first_major_version_of_tag(tag: u8) -> u16 {
+    match tag {
+        // CONSTANT_MethodHandle, CONSTANT_MethodType, CONSTANT_InvokeDynamic
+        15 | 16 | 18 => 51,
+        // CONSTANT_Module, CONSTANT_Package
+        19 | 20 => 53,
+        // CONSTANT_Dynamic
+        17 => 55,
+        _ => 45,
+    }
+}
+
+fn read_u8(rdr: &mut Cursor<&[u8]>, reading: &'static str) -> Result<u8, DeserializeError> {
+    let offset = rdr.position();
+    rdr.read_u8()
+        .map_err(|_| DeserializeError::UnexpectedEof { offset, reading })
+}
+
+fn read_u16(rdr: &mut Cursor<&[u8]>, reading: &'static str) -> Result<u16, DeserializeError> {
+    let offset = rdr.position();
+    rdr.read_u16::<BigEndian>()
+        .map_err(|_| DeserializeError::UnexpectedEof { offset, reading })
+}
+
+fn read_u32(rdr: &mut Cursor<&[u8]>, reading: &'static str) -> Result<u32, DeserializeError> {
+    let offset = rdr.position();
+    rdr.read_u32::<BigEndian>()
+        .map_err(|_| DeserializeError::UnexpectedEof { offset, reading })
+}
+
+fn remaining(rdr: &Cursor<&[u8]>) -> u64 {
+    (rdr.get_ref().len() as u64).saturating_sub(rdr.position())
+}
+
+fn deserialize_constant_pool(
+    rdr: &mut Cursor<&[u8]>,
+    index: u16,
+) -> Result<CPInfo, DeserializeError> {
+    let offset = rdr.position();
+    let tag = read_u8(rdr, "constant pool tag")?;
     // println!("tag: {tag}");
 
     match tag {
         // CONSTANT_Utf8
         1 => {
-            let length = rdr.read_u16::<BigEndian>()?;
+            let length = read_u16(rdr, "CONSTANT_Utf8")?;
+            if remaining(rdr) < length as u64 {
+                return Err(DeserializeError::UnexpectedEof {
+                    offset,
+                    reading: "CONSTANT_Utf8",
+                });
+            }
             let mut buf = vec![];
-            rdr.take(length.into()).read_to_end(&mut buf)?;
+            rdr.take(length.into())
+                .read_to_end(&mut buf)
+                .map_err(DeserializeError::Io)?;
             // let str = String::from_utf8(buf.to_owned()).unwrap();
             // println!("{str}");
 
@@ -152,18 +365,18 @@ fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dy
         }
         // CONSTANT_Integer
         3 => {
-            let bytes = rdr.read_u32::<BigEndian>()?;
+            let bytes = read_u32(rdr, "CONSTANT_Integer")?;
             Ok(CPInfo::ConstantIntegerInfo { tag, bytes })
         }
         // CONSTANT_Float
         4 => {
-            let bytes = rdr.read_u32::<BigEndian>()?;
+            let bytes = read_u32(rdr, "CONSTANT_Float")?;
             Ok(CPInfo::ConstantFloatInfo { tag, bytes })
         }
         // CONSTANT_Long
         5 => {
-            let high_bytes = rdr.read_u32::<BigEndian>()?;
-            let low_bytes = rdr.read_u32::<BigEndian>()?;
+            let high_bytes = read_u32(rdr, "CONSTANT_Long")?;
+            let low_bytes = read_u32(rdr, "CONSTANT_Long")?;
 
             Ok(CPInfo::ConstantLongInfo {
                 tag,
@@ -171,12 +384,12 @@ fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dy
                 low_bytes,
             })
         }
-        // CONSTANT_Long
+        // CONSTANT_Double
         6 => {
-            let high_bytes = rdr.read_u32::<BigEndian>()?;
-            let low_bytes = rdr.read_u32::<BigEndian>()?;
+            let high_bytes = read_u32(rdr, "CONSTANT_Double")?;
+            let low_bytes = read_u32(rdr, "CONSTANT_Double")?;
 
-            Ok(CPInfo::ConstantLongInfo {
+            Ok(CPInfo::ConstantDoubleInfo {
                 tag,
                 high_bytes,
                 low_bytes,
@@ -184,20 +397,20 @@ fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dy
         }
         // CONSTANT_Class
         7 => {
-            let name_index = rdr.read_u16::<BigEndian>()?;
+            let name_index = read_u16(rdr, "CONSTANT_Class")?;
 
             Ok(CPInfo::ConstantClassInfo { tag, name_index })
         }
         // CONSTANT_String
         8 => {
-            let string_index = rdr.read_u16::<BigEndian>()?;
+            let string_index = read_u16(rdr, "CONSTANT_String")?;
 
             Ok(CPInfo::ConstantStringInfo { tag, string_index })
         }
         // CONSTANT_Fieldref
         9 => {
-            let class_index = rdr.read_u16::<BigEndian>()?;
-            let name_and_type_index = rdr.read_u16::<BigEndian>()?;
+            let class_index = read_u16(rdr, "CONSTANT_Fieldref")?;
+            let name_and_type_index = read_u16(rdr, "CONSTANT_Fieldref")?;
 
             Ok(CPInfo::ConstantFieldRefInfo {
                 tag,
@@ -207,8 +420,8 @@ fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dy
         }
         // CONSTANT_Methodref
         10 => {
-            let class_index = rdr.read_u16::<BigEndian>()?;
-            let name_and_type_index = rdr.read_u16::<BigEndian>()?;
+            let class_index = read_u16(rdr, "CONSTANT_Methodref")?;
+            let name_and_type_index = read_u16(rdr, "CONSTANT_Methodref")?;
 
             Ok(CPInfo::ConstantMethodRefInfo {
                 tag,
@@ -218,8 +431,8 @@ fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dy
         }
         // CONSTANT_InterfaceMethodref
         11 => {
-            let class_index = rdr.read_u16::<BigEndian>()?;
-            let name_and_type_index = rdr.read_u16::<BigEndian>()?;
+            let class_index = read_u16(rdr, "CONSTANT_InterfaceMethodref")?;
+            let name_and_type_index = read_u16(rdr, "CONSTANT_InterfaceMethodref")?;
 
             Ok(CPInfo::ConstantInterfaceMethodRefInfo {
                 tag,
@@ -229,8 +442,8 @@ fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dy
         }
         // CONSTANT_NameAndType
         12 => {
-            let name_index = rdr.read_u16::<BigEndian>()?;
-            let descriptor_index = rdr.read_u16::<BigEndian>()?;
+            let name_index = read_u16(rdr, "CONSTANT_NameAndType")?;
+            let descriptor_index = read_u16(rdr, "CONSTANT_NameAndType")?;
 
             Ok(CPInfo::ConstantNameAndTypeInfo {
                 tag,
@@ -239,8 +452,8 @@ fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dy
             })
         }
         15 => {
-            let reference_kind = rdr.read_u8()?;
-            let reference_index = rdr.read_u16::<BigEndian>()?;
+            let reference_kind = read_u8(rdr, "CONSTANT_MethodHandle")?;
+            let reference_index = read_u16(rdr, "CONSTANT_MethodHandle")?;
             Ok(CPInfo::ConstantMethodHandleInfo {
                 tag,
                 reference_kind,
@@ -248,36 +461,65 @@ fn deserialize_constant_pool(rdr: &mut Cursor<Vec<u8>>) -> Result<CPInfo, Box<dy
             })
         }
         16 => {
-            let descriptor_index = rdr.read_u16::<BigEndian>()?;
+            let descriptor_index = read_u16(rdr, "CONSTANT_MethodType")?;
             Ok(CPInfo::ConstantMethodTypeInfo {
                 tag,
                 descriptor_index,
             })
         }
         18 => {
-            let bootstrap_method_attr_index = rdr.read_u16::<BigEndian>()?;
-            let name_and_type_index = rdr.read_u16::<BigEndian>()?;
+            let bootstrap_method_attr_index = read_u16(rdr, "CONSTANT_InvokeDynamic")?;
+            let name_and_type_index = read_u16(rdr, "CONSTANT_InvokeDynamic")?;
             Ok(CPInfo::ConstantInvokeDynamicInfo {
                 tag,
                 bootstrap_method_attr_index,
                 name_and_type_index,
             })
         }
-        tag @ _ => todo!("tag {tag} missing"),
+        17 => {
+            let bootstrap_method_attr_index = read_u16(rdr, "CONSTANT_Dynamic")?;
+            let name_and_type_index = read_u16(rdr, "CONSTANT_Dynamic")?;
+            Ok(CPInfo::ConstantDynamicInfo {
+                tag,
+                bootstrap_method_attr_index,
+                name_and_type_index,
+            })
+        }
+        19 => {
+            let name_index = read_u16(rdr, "CONSTANT_Module")?;
+            Ok(CPInfo::ConstantModuleInfo { tag, name_index })
+        }
+        20 => {
+            let name_index = read_u16(rdr, "CONSTANT_Package")?;
+            Ok(CPInfo::ConstantPackageInfo { tag, name_index })
+        }
+        tag => Err(DeserializeError::UnknownConstantTag { offset, index, tag }),
     }
 }
 
 fn deserialize_attributes(
-    rdr: &mut Cursor<Vec<u8>>,
+    rdr: &mut Cursor<&[u8]>,
     attributes_count: u16,
-) -> Result<Vec<AttributeInfo>, Box<dyn Error>> {
+) -> Result<Vec<AttributeInfo>, DeserializeError> {
     let mut attributes = vec![];
     for _ in 0..attributes_count {
-        let attribute_name_index = rdr.read_u16::<BigEndian>()?;
-        let attribute_length = rdr.read_u32::<BigEndian>()?;
-
+        let offset = rdr.position();
+        let attribute_name_index = read_u16(rdr, "attribute_name_index")?;
+        let attribute_length = read_u32(rdr, "attribute_length")?;
+
+        let available = remaining(rdr);
+        if available < attribute_length as u64 {
+            return Err(DeserializeError::TruncatedAttribute {
+                offset,
+                attribute_name_index,
+                attribute_length,
+                available,
+            });
+        }
         let mut buf = vec![];
-        rdr.take(attribute_length.into()).read_to_end(&mut buf)?;
+        rdr.take(attribute_length.into())
+            .read_to_end(&mut buf)
+            .map_err(DeserializeError::Io)?;
         attributes.push(AttributeInfo {
             attribute_name_index,
             attribute_length,
@@ -285,70 +527,103 @@ fn deserialize_attributes(
         })
     }
 
-    return Ok(attributes);
+    Ok(attributes)
 }
 
-pub fn deserialize_class_file(path: String) -> Result<DeserializedClassFile, Box<dyn Error>> {
-    let f = File::open(path)?;
-    let mut reader = BufReader::new(f);
+pub fn deserialize_class_file(path: String) -> Result<DeserializedClassFile, DeserializeError> {
+    let f = File::open(path).map_err(DeserializeError::Io)?;
+    deserialize_class_from(BufReader::new(f))
+}
+
+// deserializes a class file from any source, e.g. a jar entry or a network stream. The reader is
+// read until its end.
+pub fn deserialize_class_from(
+    mut reader: impl Read,
+) -> Result<DeserializedClassFile, DeserializeError> {
     let mut buffer = Vec::new();
 
-    reader.read_to_end(&mut buffer)?;
+    reader
+        .read_to_end(&mut buffer)
+        .map_err(DeserializeError::Io)?;
+
+    deserialize_class_bytes(&buffer)
+}
 
-    let mut rdr = Cursor::new(buffer);
-    let magic = rdr.read_u32::<BigEndian>()?;
+pub fn deserialize_class_bytes(bytes: &[u8]) -> Result<DeserializedClassFile, DeserializeError> {
+    let mut rdr = Cursor::new(bytes);
+    let magic = read_u32(&mut rdr, "magic")?;
     if magic != 0xcafebabe {
         // error case!
-        return Err("no cafebabe :(".into());
+        return Err(DeserializeError::InvalidMagic { offset: 0, magic });
     }
 
-    let minor_version = rdr.read_u16::<BigEndian>()?;
-    let major_version = rdr.read_u16::<BigEndian>()?;
-    // We support java 11 for now, so make sure that major_version is in between 45 and 55
-    if major_version > 55 {
-        return Err("unsupported major_version {major_version}".into());
+    let minor_version = read_u16(&mut rdr, "minor_version")?;
+    let major_version = read_u16(&mut rdr, "major_version")?;
+    // We support up to java 21. Since java 12, a minor_version of 65535 marks class files using
+    // preview features, which only the jvm of exactly that version may accept.
+    if !(45..=MAX_MAJOR_VERSION).contains(&major_version)
+        || (major_version >= 56 && minor_version != 0)
+    {
+        return Err(DeserializeError::UnsupportedVersion {
+            offset: 4,
+            major_version,
+            minor_version,
+        });
     }
 
     // println!("{magic:#0x} {minor_version} {major_version}");
 
-    let constant_pool_count = rdr.read_u16::<BigEndian>()?;
+    let constant_pool_count = read_u16(&mut rdr, "constant_pool_count")?;
     // println!("constant_pool_count: {constant_pool_count}");
     let mut constant_pool: Vec<CPInfo> = Vec::new();
-    let mut it = 0..constant_pool_count - 1;
+    let mut it = 1..constant_pool_count;
     while let Some(i) = it.next() {
-        // println!("index {}", i + 1);
-        let cp_info = deserialize_constant_pool(&mut rdr)?;
-        match cp_info {
-            CPInfo::ConstantLongInfo { .. } => {
-                // for some ultra silly reason, longs and doubles take up two constant pool spaces.
-                // this is super annoying. we therefore insert the cp_info two times, and skip the
-                // next item.
-                constant_pool.push(cp_info.clone());
-                it.next();
-            }
-            _ => {}
+        // println!("index {i}");
+        let offset = rdr.position();
+        let cp_info = deserialize_constant_pool(&mut rdr, i)?;
+        let tag = cp_info.tag();
+        if major_version < first_major_version_of_tag(tag) {
+            return Err(DeserializeError::ConstantTagTooNew {
+                offset,
+                index: i,
+                tag,
+                major_version,
+            });
         }
+        let takes_two_entries = matches!(
+            cp_info,
+            CPInfo::ConstantLongInfo { .. } | CPInfo::ConstantDoubleInfo { .. }
+        );
         // println!("{cp_info:?}");
         constant_pool.push(cp_info);
+        if takes_two_entries {
+            // for some ultra silly reason, longs and doubles take up two constant pool spaces.
+            // the next index is not read from the class file, but still has to exist so that all
+            // later indices stay aligned.
+            if it.next().is_none() {
+                return Err(DeserializeError::TruncatedConstantPool { offset, index: i });
+            }
+            constant_pool.push(CPInfo::Unusable);
+        }
     }
 
-    let access_flags = rdr.read_u16::<BigEndian>()?;
-    let this_class = rdr.read_u16::<BigEndian>()?;
-    let super_class = rdr.read_u16::<BigEndian>()?;
+    let access_flags = read_u16(&mut rdr, "access_flags")?;
+    let this_class = read_u16(&mut rdr, "this_class")?;
+    let super_class = read_u16(&mut rdr, "super_class")?;
 
-    let interfaces_count = rdr.read_u16::<BigEndian>()?;
+    let interfaces_count = read_u16(&mut rdr, "interfaces_count")?;
     let mut interfaces = vec![];
     for _ in 0..interfaces_count {
-        interfaces.push(rdr.read_u16::<BigEndian>()?);
+        interfaces.push(read_u16(&mut rdr, "interfaces")?);
     }
 
-    let fields_count = rdr.read_u16::<BigEndian>()?;
+    let fields_count = read_u16(&mut rdr, "fields_count")?;
     let mut fields = vec![];
     for _ in 0..fields_count {
-        let access_flags = rdr.read_u16::<BigEndian>()?;
-        let name_index = rdr.read_u16::<BigEndian>()?;
-        let descriptor_index = rdr.read_u16::<BigEndian>()?;
-        let attributes_count = rdr.read_u16::<BigEndian>()?;
+        let access_flags = read_u16(&mut rdr, "field_info")?;
+        let name_index = read_u16(&mut rdr, "field_info")?;
+        let descriptor_index = read_u16(&mut rdr, "field_info")?;
+        let attributes_count = read_u16(&mut rdr, "field_info")?;
 
         let attributes = deserialize_attributes(&mut rdr, attributes_count)?;
 
@@ -361,13 +636,13 @@ pub fn deserialize_class_file(path: String) -> Result<DeserializedClassFile, Box
         })
     }
 
-    let methods_count = rdr.read_u16::<BigEndian>()?;
+    let methods_count = read_u16(&mut rdr, "methods_count")?;
     let mut methods = vec![];
     for _ in 0..methods_count {
-        let access_flags = rdr.read_u16::<BigEndian>()?;
-        let name_index = rdr.read_u16::<BigEndian>()?;
-        let descriptor_index = rdr.read_u16::<BigEndian>()?;
-        let attributes_count = rdr.read_u16::<BigEndian>()?;
+        let access_flags = read_u16(&mut rdr, "method_info")?;
+        let name_index = read_u16(&mut rdr, "method_info")?;
+        let descriptor_index = read_u16(&mut rdr, "method_info")?;
+        let attributes_count = read_u16(&mut rdr, "method_info")?;
 
         let attributes = deserialize_attributes(&mut rdr, attributes_count)?;
 
@@ -379,9 +654,17 @@ pub fn deserialize_class_file(path: String) -> Result<DeserializedClassFile, Box
             attributes,
         })
     }
-    let attributes_count = rdr.read_u16::<BigEndian>()?;
+    let attributes_count = read_u16(&mut rdr, "attributes_count")?;
     let attributes = deserialize_attributes(&mut rdr, attributes_count)?;
 
+    let trailing = remaining(&rdr);
+    if trailing > 0 {
+        return Err(DeserializeError::TrailingBytes {
+            offset: rdr.position(),
+            count: trailing,
+        });
+    }
+
     let deserialized_class_file = DeserializedClassFile {
         magic,
         minor_version,
@@ -402,5 +685,5 @@ pub fn deserialize_class_file(path: String) -> Result<DeserializedClassFile, Box
     };
     // println!("deserialize_class_file: {deserialized_class_file:?}");
 
-    return Ok(deserialized_class_file);
+    Ok(deserialized_class_file)
 }
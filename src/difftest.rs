@@ -0,0 +1,176 @@
+// Differential testing against a real JVM, see the difftest subcommand. Every class with a main
+// method is run both by matebabe and by java, and what they print to stdout and stderr as well as
+// their exit status are compared, java being taken as the ground truth:
+//
+//     ok   Main
+//     FAIL Fib
+//        stdout differs at line 3
+//          java:     fib(10) = 55
+//          matebabe: fib(10) = 34
+//
+// Like with run, the classes are looked up relative to the current directory, so both are run from
+// the directory the packages of the class start in. The programs get no input and have to end on
+// their own.
+
+use std::{
+    error::Error,
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use crate::{deserialize::deserialize_class_bytes, parse::parse};
+
+// the classes to run for a target, along with the directory their packages start in, which is
+// where they are run from. A target is either a directory, whose classes with a main method are
+// run, or a single class in the current directory, given by its name or its class file
+pub(crate) fn main_classes(target: &str) -> Result<Vec<(PathBuf, String)>, Box<dyn Error>> {
+    let path = Path::new(target);
+    if !path.is_dir() {
+        let name = target.strip_suffix(".class").unwrap_or(target);
+        return Ok(vec![(PathBuf::from("."), name.replace('.', "/"))]);
+    }
+    let mut class_files = vec![];
+    collect_files(path, "class", &mut class_files)?;
+    class_files.sort();
+    let mut classes = vec![];
+    for class_file in class_files.iter() {
+        let class = parse(deserialize_class_bytes(&std::fs::read(class_file)?)?)
+            .map_err(|error| format!("failed to parse {}: {error}", class_file.display()))?;
+        let has_main = class.methods.iter().any(|method| {
+            method.access.r#static
+                && method.name == "main"
+                && method.descriptor.descriptor() == "([Ljava/lang/String;)V"
+        });
+        if !has_main {
+            continue;
+        }
+        let name = class.this_class.name.to_owned();
+        // e.g. tests/Main.class of a class tests/Main starts in the parent of tests
+        let root = class_file
+            .ancestors()
+            .nth(name.split('/').count())
+            .filter(|_| class_file.ends_with(name.clone() + ".class"))
+            .ok_or(format!(
+                "{} should be in a directory matching its package",
+                class_file.display()
+            ))?;
+        let root = if root.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            root
+        };
+        classes.push((root.to_owned(), name));
+    }
+    Ok(classes)
+}
+
+// the files with an extension in a directory and its subdirectories
+pub(crate) fn collect_files(
+    directory: &Path,
+    extension: &str,
+    files: &mut Vec<PathBuf>,
+) -> Result<(), Box<dyn Error>> {
+    for entry in std::fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(&path, extension, files)?;
+        } else if path.extension().is_some_and(|found| found == extension) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn exit_status(output: &Output) -> String {
+    match output.status.code() {
+        Some(code) => code.to_string(),
+        None => "killed by a signal".to_owned(),
+    }
+}
+
+// the first line the outputs differ in, counting from 1, if they differ at all
+pub(crate) fn first_difference<'a>(
+    expected: &'a str,
+    actual: &'a str,
+) -> Option<(usize, &'a str, &'a str)> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    for line in 1.. {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return None,
+            (expected_line, actual_line) if expected_line != actual_line => {
+                return Some((
+                    line,
+                    expected_line.unwrap_or("<end of output>"),
+                    actual_line.unwrap_or("<end of output>"),
+                ))
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+// runs the classes of target with matebabe, the command line up to the name of the class, and with
+// java, the path to the java executable. Returns the report and the number of classes that behaved
+// differently
+pub fn difftest(
+    target: &str,
+    matebabe: &[String],
+    java: &str,
+) -> Result<(String, usize), Box<dyn Error>> {
+    let classes = main_classes(target)?;
+    if classes.is_empty() {
+        return Err(format!("no class with a main method in {target}").into());
+    }
+
+    let mut out = String::new();
+    let mut failed = 0;
+    for (directory, class) in classes.iter() {
+        let (program, arguments) = matebabe.split_first().ok_or("no matebabe command")?;
+        let actual = Command::new(program)
+            .args(arguments)
+            .arg(class)
+            .current_dir(directory)
+            .output()
+            .map_err(|e| format!("could not run {program}: {e}"))?;
+        let expected = Command::new(java)
+            .arg("-cp")
+            .arg(directory)
+            .arg(class.replace('/', "."))
+            .output()
+            .map_err(|e| format!("could not run {java}: {e}"))?;
+
+        let mut differences = String::new();
+        for (stream, expected, actual) in [
+            ("stdout", &expected.stdout, &actual.stdout),
+            ("stderr", &expected.stderr, &actual.stderr),
+        ] {
+            let expected = String::from_utf8_lossy(expected);
+            let actual = String::from_utf8_lossy(actual);
+            if let Some((line, expected_line, actual_line)) = first_difference(&expected, &actual) {
+                writeln!(differences, "   {stream} differs at line {line}")?;
+                writeln!(differences, "     java:     {expected_line}")?;
+                writeln!(differences, "     matebabe: {actual_line}")?;
+            }
+        }
+        if expected.status.code() != actual.status.code() {
+            writeln!(
+                differences,
+                "   exit status differs: java {}, matebabe {}",
+                exit_status(&expected),
+                exit_status(&actual)
+            )?;
+        }
+
+        if differences.is_empty() {
+            writeln!(out, "ok   {class}")?;
+        } else {
+            failed += 1;
+            writeln!(out, "FAIL {class}")?;
+            out.push_str(&differences);
+        }
+    }
+    Ok((out, failed))
+}
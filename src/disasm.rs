@@ -0,0 +1,337 @@
+// javap style listings of a class: the methods for the disasm subcommand and the constant pool
+// for parse -v.
+
+use std::{error::Error, fmt::Write};
+
+use crate::{
+    deserialize::CPInfo,
+    instruction::{decode_all, Instruction, Operands},
+    parse::{Class, Constant, Method},
+};
+
+fn name_and_type_value(name_and_type: &Constant) -> String {
+    match name_and_type {
+        Constant::NameAndType(name, descriptor) => format!("{name}:{descriptor}"),
+        _ => "?".to_owned(),
+    }
+}
+
+// the resolved value of a constant, with the names of any constants it refers to filled in
+fn constant_value(constant: &Constant) -> String {
+    match constant {
+        Constant::Class(class_info) => class_info.name.clone(),
+        Constant::Utf8(value) | Constant::String(value) => String::from_utf16_lossy(value),
+        Constant::FieldRef(class_info, name_and_type)
+        | Constant::MethodRef(class_info, name_and_type)
+        | Constant::InterfaceMethodRef(class_info, name_and_type) => {
+            format!("{}.{}", class_info.name, name_and_type_value(name_and_type))
+        }
+        Constant::NameAndType(..) => name_and_type_value(constant),
+        Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type)
+        | Constant::Dynamic(bootstrap_method_attr_index, name_and_type) => {
+            format!(
+                "#{bootstrap_method_attr_index}:{}",
+                name_and_type_value(name_and_type)
+            )
+        }
+        Constant::MethodHandle(ref_kind, reference) => {
+            format!("{ref_kind:?} {}", constant_value(reference))
+        }
+        Constant::MethodType(descriptor) => descriptor.clone(),
+        Constant::Module(name) | Constant::Package(name) => name.clone(),
+        Constant::Integer(value) => format!("{value}"),
+        Constant::Long(value) => format!("{value}l"),
+        Constant::Float(value) => format!("{value}f"),
+        Constant::Double(value) => format!("{value}d"),
+        Constant::Unusable => "unusable".to_owned(),
+        Constant::Placeholder => "unresolved".to_owned(),
+    }
+}
+
+// a short description of a constant, like the comments javap prints next to an instruction
+pub fn describe_constant(constant: &Constant) -> String {
+    let kind = match constant {
+        Constant::Class(_) => "class",
+        Constant::Utf8(_) => "utf8",
+        Constant::String(_) => "String",
+        Constant::FieldRef(..) => "Field",
+        Constant::MethodRef(..) => "Method",
+        Constant::InterfaceMethodRef(..) => "InterfaceMethod",
+        Constant::NameAndType(..) => "NameAndType",
+        Constant::InvokeDynamic(..) => "InvokeDynamic",
+        Constant::Dynamic(..) => "Dynamic",
+        Constant::MethodHandle(..) => "MethodHandle",
+        Constant::MethodType(_) => "MethodType",
+        Constant::Module(_) => "Module",
+        Constant::Package(_) => "Package",
+        Constant::Integer(_) => "int",
+        Constant::Long(_) => "long",
+        Constant::Float(_) => "float",
+        Constant::Double(_) => "double",
+        Constant::Unusable | Constant::Placeholder => return constant_value(constant),
+    };
+    format!("{kind} {}", constant_value(constant))
+}
+
+// the constant pool as a numbered table like javap -v prints it: the raw entry refers to other
+// entries by index, while the resolved one has their names
+pub fn constant_pool_table(
+    raw_constant_pool: &[CPInfo],
+    class: &Class,
+) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
+    writeln!(out, "Constant pool:")?;
+    let width = format!("#{}", raw_constant_pool.len()).len();
+    for (i, (raw, constant)) in raw_constant_pool
+        .iter()
+        .zip(class.constant_pool.iter())
+        .enumerate()
+    {
+        let (kind, references) = match raw {
+            CPInfo::ConstantClassInfo { name_index, .. } => ("Class", format!("#{name_index}")),
+            CPInfo::ConstantFieldRefInfo {
+                class_index,
+                name_and_type_index,
+                ..
+            } => ("Fieldref", format!("#{class_index}.#{name_and_type_index}")),
+            CPInfo::ConstantMethodRefInfo {
+                class_index,
+                name_and_type_index,
+                ..
+            } => (
+                "Methodref",
+                format!("#{class_index}.#{name_and_type_index}"),
+            ),
+            CPInfo::ConstantInterfaceMethodRefInfo {
+                class_index,
+                name_and_type_index,
+                ..
+            } => (
+                "InterfaceMethodref",
+                format!("#{class_index}.#{name_and_type_index}"),
+            ),
+            CPInfo::ConstantStringInfo { string_index, .. } => {
+                ("String", format!("#{string_index}"))
+            }
+            CPInfo::ConstantNameAndTypeInfo {
+                name_index,
+                descriptor_index,
+                ..
+            } => ("NameAndType", format!("#{name_index}:#{descriptor_index}")),
+            CPInfo::ConstantMethodHandleInfo {
+                reference_kind,
+                reference_index,
+                ..
+            } => (
+                "MethodHandle",
+                format!("{reference_kind}:#{reference_index}"),
+            ),
+            CPInfo::ConstantMethodTypeInfo {
+                descriptor_index, ..
+            } => ("MethodType", format!("#{descriptor_index}")),
+            CPInfo::ConstantInvokeDynamicInfo {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+                ..
+            } => (
+                "InvokeDynamic",
+                format!("#{bootstrap_method_attr_index}:#{name_and_type_index}"),
+            ),
+            CPInfo::ConstantDynamicInfo {
+                bootstrap_method_attr_index,
+                name_and_type_index,
+                ..
+            } => (
+                "Dynamic",
+                format!("#{bootstrap_method_attr_index}:#{name_and_type_index}"),
+            ),
+            CPInfo::ConstantModuleInfo { name_index, .. } => ("Module", format!("#{name_index}")),
+            CPInfo::ConstantPackageInfo { name_index, .. } => ("Package", format!("#{name_index}")),
+            // these hold their value directly, so there is nothing to cross-reference
+            CPInfo::ConstantUtf8Info { .. } => ("Utf8", String::new()),
+            CPInfo::ConstantIntegerInfo { .. } => ("Integer", String::new()),
+            CPInfo::ConstantFloatInfo { .. } => ("Float", String::new()),
+            CPInfo::ConstantLongInfo { .. } => ("Long", String::new()),
+            CPInfo::ConstantDoubleInfo { .. } => ("Double", String::new()),
+            CPInfo::Unusable => {
+                writeln!(out, "{:>width$} = (unusable)", format!("#{}", i + 1))?;
+                continue;
+            }
+        };
+        let index = format!("#{}", i + 1);
+        if references.is_empty() {
+            writeln!(
+                out,
+                "{index:>width$} = {kind:<18} {}",
+                constant_value(constant)
+            )?;
+        } else {
+            writeln!(
+                out,
+                "{index:>width$} = {kind:<18} {references:<14} // {}",
+                constant_value(constant)
+            )?;
+        }
+    }
+    Ok(out)
+}
+
+fn describe_index(class: &Class, index: u16) -> String {
+    match class.constant_pool.get((index as usize).wrapping_sub(1)) {
+        Some(constant) => format!("#{index:<5}// {}", describe_constant(constant)),
+        None => format!("#{index:<5}// invalid constant pool index"),
+    }
+}
+
+fn array_type_name(atype: i32) -> &'static str {
+    match atype {
+        4 => "boolean",
+        5 => "char",
+        6 => "float",
+        7 => "double",
+        8 => "byte",
+        9 => "short",
+        10 => "int",
+        11 => "long",
+        _ => "invalid",
+    }
+}
+
+// the instruction on a single line, with switches only summarized, e.g. "bipush          42"
+pub fn instruction_text(class: &Class, instruction: &Instruction) -> String {
+    let mnemonic = if instruction.wide {
+        format!("wide {}", instruction.mnemonic())
+    } else {
+        instruction.mnemonic().to_owned()
+    };
+    let operands = match &instruction.operands {
+        Operands::None => return mnemonic,
+        Operands::Immediate(value) if instruction.opcode == 0xbc => {
+            array_type_name(*value).to_owned()
+        }
+        Operands::Immediate(value) => format!("{value}"),
+        Operands::ConstantPoolIndex(index) => describe_index(class, *index),
+        Operands::LocalVariable(index) => format!("{index}"),
+        Operands::Iinc { index, constant } => format!("{index}, {constant}"),
+        Operands::Branch(target) => format!("{target}"),
+        Operands::InvokeInterface { index, count } => {
+            format!("{}, count {count}", describe_index(class, *index))
+        }
+        Operands::MultiANewArray { index, dimensions } => {
+            format!("{}, dimensions {dimensions}", describe_index(class, *index))
+        }
+        Operands::TableSwitch { low, high, .. } => format!("{{ // {low} to {high} }}"),
+        Operands::LookupSwitch { pairs, .. } => format!("{{ // {} }}", pairs.len()),
+    };
+    format!("{mnemonic:<16}{operands}")
+}
+
+fn write_instruction(
+    out: &mut String,
+    class: &Class,
+    instruction: &Instruction,
+) -> Result<(), Box<dyn Error>> {
+    // switches list their targets on the following lines
+    match &instruction.operands {
+        Operands::TableSwitch {
+            default,
+            low,
+            high,
+            targets,
+        } => {
+            writeln!(
+                out,
+                "{:>8}: {:<16}{{ // {low} to {high}",
+                instruction.pc,
+                instruction.mnemonic()
+            )?;
+            for (i, target) in targets.iter().enumerate() {
+                writeln!(out, "{:>24}: {target}", *low as i64 + i as i64)?;
+            }
+            writeln!(out, "{:>24}: {default}", "default")?;
+            writeln!(out, "{:>11}", "}")?;
+        }
+        Operands::LookupSwitch { default, pairs } => {
+            writeln!(
+                out,
+                "{:>8}: {:<16}{{ // {}",
+                instruction.pc,
+                instruction.mnemonic(),
+                pairs.len()
+            )?;
+            for (r#match, target) in pairs.iter() {
+                writeln!(out, "{:>24}: {target}", r#match)?;
+            }
+            writeln!(out, "{:>24}: {default}", "default")?;
+            writeln!(out, "{:>11}", "}")?;
+        }
+        _ => writeln!(
+            out,
+            "{:>8}: {}",
+            instruction.pc,
+            instruction_text(class, instruction)
+        )?,
+    }
+    Ok(())
+}
+
+fn write_method(out: &mut String, class: &Class, method: &Method) -> Result<(), Box<dyn Error>> {
+    writeln!(out, "  {}{}", method.name, method.descriptor.descriptor())?;
+    let code = match method
+        .attributes
+        .iter()
+        .find_map(|attribute| attribute.as_code())
+    {
+        Some(code) => code,
+        None => {
+            writeln!(out, "    no code")?;
+            return Ok(());
+        }
+    };
+    let (bytes, max_stack, max_locals, exception_table) = code;
+    writeln!(
+        out,
+        "    Code: max_stack={max_stack}, max_locals={max_locals}"
+    )?;
+    for instruction in decode_all(&bytes)?.iter() {
+        write_instruction(out, class, instruction)?;
+    }
+    if !exception_table.is_empty() {
+        writeln!(out, "    Exception table:")?;
+        writeln!(out, "       from    to  target type")?;
+        for item in exception_table.iter() {
+            let catch_type = if item.catch_type == 0 {
+                "any".to_owned()
+            } else {
+                describe_index(class, item.catch_type as u16)
+            };
+            writeln!(
+                out,
+                "      {:>5} {:>5} {:>5}   {catch_type}",
+                item.start_pc, item.end_pc, item.handler_pc
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn disassemble(class: &Class) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
+    let kind = if class.access.interface {
+        "interface"
+    } else {
+        "class"
+    };
+    writeln!(
+        out,
+        "{kind} {} (version {}.{})",
+        class.this_class.name, class.major_version, class.minor_version
+    )?;
+    for (i, method) in class.methods.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        write_method(&mut out, class, method)?;
+    }
+    Ok(out)
+}
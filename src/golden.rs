@@ -0,0 +1,118 @@
+// Golden output tests, see the test subcommand. Every class with a main method in a directory is a
+// test, which passes if it exits normally and what it prints to stdout is exactly what is in the
+// .expected file next to its class file, e.g. tests/Operations.expected for tests/Operations.class.
+// Sources that are newer than their class files, or don't have any yet, are compiled with javac
+// first, which puts the class files next to them. The report ends with a summary:
+//
+//     ok   tests/Operations
+//     FAIL tests/Strings
+//        stdout differs at line 2
+//          expected: abc
+//          actual:   cba
+//
+//     1 passed, 1 failed
+
+use std::{
+    error::Error,
+    fmt::Write,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::difftest::{collect_files, exit_status, first_difference, main_classes};
+
+// the sources of directory whose class file is missing or older than them
+fn outdated_sources(directory: &Path) -> Result<Vec<PathBuf>, Box<dyn Error>> {
+    let mut sources = vec![];
+    collect_files(directory, "java", &mut sources)?;
+    let mut outdated = vec![];
+    for source in sources {
+        let compiled = match std::fs::metadata(source.with_extension("class")) {
+            Ok(class_file) => class_file.modified()? >= std::fs::metadata(&source)?.modified()?,
+            Err(_) => false,
+        };
+        if !compiled {
+            outdated.push(source);
+        }
+    }
+    Ok(outdated)
+}
+
+// runs the tests in directory with matebabe, the command line up to the name of the class, after
+// compiling them with javac, the path to the javac executable. Returns the report and the number
+// of tests that failed
+pub fn run_tests(
+    directory: &str,
+    matebabe: &[String],
+    javac: &str,
+) -> Result<(String, usize), Box<dyn Error>> {
+    if !Path::new(directory).is_dir() {
+        return Err(format!("{directory} is not a directory").into());
+    }
+    let sources = outdated_sources(Path::new(directory))?;
+    if !sources.is_empty() {
+        // javac reports what it failed to compile on stderr itself
+        let status = Command::new(javac)
+            .args(sources.iter())
+            .status()
+            .map_err(|e| format!("could not run {javac}: {e}"))?;
+        if !status.success() {
+            return Err("failed to compile the tests".into());
+        }
+    }
+
+    let classes = main_classes(directory)?;
+    if classes.is_empty() {
+        return Err(format!("no class with a main method in {directory}").into());
+    }
+    let (program, arguments) = matebabe.split_first().ok_or("no matebabe command")?;
+
+    let mut out = String::new();
+    let mut failed = 0;
+    for (root, class) in classes.iter() {
+        let expected_path = root.join(class.to_owned() + ".expected");
+        let failure = match std::fs::read_to_string(&expected_path) {
+            Err(e) => Some(format!(
+                "   could not read {}: {e}\n",
+                expected_path.display()
+            )),
+            Ok(expected) => {
+                let output = Command::new(program)
+                    .args(arguments)
+                    .arg(class)
+                    .current_dir(root)
+                    .output()
+                    .map_err(|e| format!("could not run {program}: {e}"))?;
+                let mut failure = String::new();
+                let actual = String::from_utf8_lossy(&output.stdout);
+                if let Some((line, expected_line, actual_line)) =
+                    first_difference(&expected, &actual)
+                {
+                    writeln!(failure, "   stdout differs at line {line}")?;
+                    writeln!(failure, "     expected: {expected_line}")?;
+                    writeln!(failure, "     actual:   {actual_line}")?;
+                }
+                if !output.status.success() {
+                    writeln!(failure, "   exit status {}", exit_status(&output))?;
+                }
+                // what went wrong is usually on stderr, e.g. an uncaught exception
+                if !failure.is_empty() {
+                    for line in String::from_utf8_lossy(&output.stderr).lines() {
+                        writeln!(failure, "   stderr: {line}")?;
+                    }
+                }
+                Some(failure).filter(|failure| !failure.is_empty())
+            }
+        };
+        match failure {
+            None => writeln!(out, "ok   {class}")?,
+            Some(failure) => {
+                failed += 1;
+                writeln!(out, "FAIL {class}")?;
+                out.push_str(&failure);
+            }
+        }
+    }
+    writeln!(out, "\n{} passed, {failed} failed", classes.len() - failed)?;
+    Ok((out, failed))
+}
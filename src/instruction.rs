@@ -0,0 +1,578 @@
+// Decoding of bytecode instructions and their operands (JVMS §6.5). The verifier, the interpreter
+// and the disassembler all decode instructions through this module, so they agree on the length
+// and the branch targets of every instruction.
+
+use std::{
+    error::Error,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operands {
+    None,
+    // bipush, sipush and newarray
+    Immediate(i32),
+    // ldc, ldc_w, ldc2_w, field and method instructions, new, anewarray, checkcast, instanceof
+    ConstantPoolIndex(u16),
+    // loads, stores and ret, possibly widened
+    LocalVariable(u16),
+    Iinc {
+        index: u16,
+        constant: i16,
+    },
+    // the absolute offset of the branch target
+    Branch(usize),
+    InvokeInterface {
+        index: u16,
+        count: u8,
+    },
+    MultiANewArray {
+        index: u16,
+        dimensions: u8,
+    },
+    TableSwitch {
+        default: usize,
+        low: i32,
+        high: i32,
+        // one target for every value from low to high
+        targets: Vec<usize>,
+    },
+    LookupSwitch {
+        default: usize,
+        // sorted by match
+        pairs: Vec<(i32, usize)>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Instruction {
+    pub pc: usize,
+    pub opcode: u8,
+    // whether the instruction is prefixed by wide, which is then the opcode instead
+    pub wide: bool,
+    // the number of bytes taken up by the instruction, including its operands
+    pub length: usize,
+    pub operands: Operands,
+}
+
+impl Instruction {
+    pub fn mnemonic(&self) -> &'static str {
+        // decoding only succeeds for known opcodes
+        mnemonic(self.opcode).unwrap_or("unknown")
+    }
+
+    // every offset execution may continue at, apart from the next instruction
+    pub fn branch_targets(&self) -> Vec<usize> {
+        match &self.operands {
+            Operands::Branch(target) => vec![*target],
+            Operands::TableSwitch {
+                default, targets, ..
+            } => {
+                let mut branch_targets = vec![*default];
+                branch_targets.extend(targets.iter());
+                branch_targets
+            }
+            Operands::LookupSwitch { default, pairs } => {
+                let mut branch_targets = vec![*default];
+                branch_targets.extend(pairs.iter().map(|(_, target)| *target));
+                branch_targets
+            }
+            _ => vec![],
+        }
+    }
+
+    // the index into the constant pool of the instructions referring to it
+    pub fn constant_pool_index(&self) -> Option<usize> {
+        match self.operands {
+            Operands::ConstantPoolIndex(index)
+            | Operands::InvokeInterface { index, .. }
+            | Operands::MultiANewArray { index, .. } => Some(index as usize),
+            _ => None,
+        }
+    }
+
+    // the index of the local variable of loads, stores, iinc and ret
+    pub fn local_variable(&self) -> Option<usize> {
+        match self.operands {
+            Operands::LocalVariable(index) | Operands::Iinc { index, .. } => Some(index as usize),
+            _ => None,
+        }
+    }
+
+    // the target of goto, jsr and the conditional branches
+    pub fn branch_target(&self) -> Option<usize> {
+        match self.operands {
+            Operands::Branch(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    // whether execution never continues with the next instruction
+    pub fn is_unconditional_transfer(&self) -> bool {
+        matches!(
+            self.opcode,
+            // goto, ret, tableswitch, lookupswitch, <x>return, athrow, goto_w
+            0xa7 | 0xa9 | 0xaa | 0xab | 0xac..=0xb1 | 0xbf | 0xc8
+        )
+    }
+}
+
+pub fn mnemonic(opcode: u8) -> Option<&'static str> {
+    let mnemonic = match opcode {
+        0x00 => "nop",
+        0x01 => "aconst_null",
+        0x02 => "iconst_m1",
+        0x03 => "iconst_0",
+        0x04 => "iconst_1",
+        0x05 => "iconst_2",
+        0x06 => "iconst_3",
+        0x07 => "iconst_4",
+        0x08 => "iconst_5",
+        0x09 => "lconst_0",
+        0x0a => "lconst_1",
+        0x0b => "fconst_0",
+        0x0c => "fconst_1",
+        0x0d => "fconst_2",
+        0x0e => "dconst_0",
+        0x0f => "dconst_1",
+        0x10 => "bipush",
+        0x11 => "sipush",
+        0x12 => "ldc",
+        0x13 => "ldc_w",
+        0x14 => "ldc2_w",
+        0x15 => "iload",
+        0x16 => "lload",
+        0x17 => "fload",
+        0x18 => "dload",
+        0x19 => "aload",
+        0x1a => "iload_0",
+        0x1b => "iload_1",
+        0x1c => "iload_2",
+        0x1d => "iload_3",
+        0x1e => "lload_0",
+        0x1f => "lload_1",
+        0x20 => "lload_2",
+        0x21 => "lload_3",
+        0x22 => "fload_0",
+        0x23 => "fload_1",
+        0x24 => "fload_2",
+        0x25 => "fload_3",
+        0x26 => "dload_0",
+        0x27 => "dload_1",
+        0x28 => "dload_2",
+        0x29 => "dload_3",
+        0x2a => "aload_0",
+        0x2b => "aload_1",
+        0x2c => "aload_2",
+        0x2d => "aload_3",
+        0x2e => "iaload",
+        0x2f => "laload",
+        0x30 => "faload",
+        0x31 => "daload",
+        0x32 => "aaload",
+        0x33 => "baload",
+        0x34 => "caload",
+        0x35 => "saload",
+        0x36 => "istore",
+        0x37 => "lstore",
+        0x38 => "fstore",
+        0x39 => "dstore",
+        0x3a => "astore",
+        0x3b => "istore_0",
+        0x3c => "istore_1",
+        0x3d => "istore_2",
+        0x3e => "istore_3",
+        0x3f => "lstore_0",
+        0x40 => "lstore_1",
+        0x41 => "lstore_2",
+        0x42 => "lstore_3",
+        0x43 => "fstore_0",
+        0x44 => "fstore_1",
+        0x45 => "fstore_2",
+        0x46 => "fstore_3",
+        0x47 => "dstore_0",
+        0x48 => "dstore_1",
+        0x49 => "dstore_2",
+        0x4a => "dstore_3",
+        0x4b => "astore_0",
+        0x4c => "astore_1",
+        0x4d => "astore_2",
+        0x4e => "astore_3",
+        0x4f => "iastore",
+        0x50 => "lastore",
+        0x51 => "fastore",
+        0x52 => "dastore",
+        0x53 => "aastore",
+        0x54 => "bastore",
+        0x55 => "castore",
+        0x56 => "sastore",
+        0x57 => "pop",
+        0x58 => "pop2",
+        0x59 => "dup",
+        0x5a => "dup_x1",
+        0x5b => "dup_x2",
+        0x5c => "dup2",
+        0x5d => "dup2_x1",
+        0x5e => "dup2_x2",
+        0x5f => "swap",
+        0x60 => "iadd",
+        0x61 => "ladd",
+        0x62 => "fadd",
+        0x63 => "dadd",
+        0x64 => "isub",
+        0x65 => "lsub",
+        0x66 => "fsub",
+        0x67 => "dsub",
+        0x68 => "imul",
+        0x69 => "lmul",
+        0x6a => "fmul",
+        0x6b => "dmul",
+        0x6c => "idiv",
+        0x6d => "ldiv",
+        0x6e => "fdiv",
+        0x6f => "ddiv",
+        0x70 => "irem",
+        0x71 => "lrem",
+        0x72 => "frem",
+        0x73 => "drem",
+        0x74 => "ineg",
+        0x75 => "lneg",
+        0x76 => "fneg",
+        0x77 => "dneg",
+        0x78 => "ishl",
+        0x79 => "lshl",
+        0x7a => "ishr",
+        0x7b => "lshr",
+        0x7c => "iushr",
+        0x7d => "lushr",
+        0x7e => "iand",
+        0x7f => "land",
+        0x80 => "ior",
+        0x81 => "lor",
+        0x82 => "ixor",
+        0x83 => "lxor",
+        0x84 => "iinc",
+        0x85 => "i2l",
+        0x86 => "i2f",
+        0x87 => "i2d",
+        0x88 => "l2i",
+        0x89 => "l2f",
+        0x8a => "l2d",
+        0x8b => "f2i",
+        0x8c => "f2l",
+        0x8d => "f2d",
+        0x8e => "d2i",
+        0x8f => "d2l",
+        0x90 => "d2f",
+        0x91 => "i2b",
+        0x92 => "i2c",
+        0x93 => "i2s",
+        0x94 => "lcmp",
+        0x95 => "fcmpl",
+        0x96 => "fcmpg",
+        0x97 => "dcmpl",
+        0x98 => "dcmpg",
+        0x99 => "ifeq",
+        0x9a => "ifne",
+        0x9b => "iflt",
+        0x9c => "ifge",
+        0x9d => "ifgt",
+        0x9e => "ifle",
+        0x9f => "if_icmpeq",
+        0xa0 => "if_icmpne",
+        0xa1 => "if_icmplt",
+        0xa2 => "if_icmpge",
+        0xa3 => "if_icmpgt",
+        0xa4 => "if_icmple",
+        0xa5 => "if_acmpeq",
+        0xa6 => "if_acmpne",
+        0xa7 => "goto",
+        0xa8 => "jsr",
+        0xa9 => "ret",
+        0xaa => "tableswitch",
+        0xab => "lookupswitch",
+        0xac => "ireturn",
+        0xad => "lreturn",
+        0xae => "freturn",
+        0xaf => "dreturn",
+        0xb0 => "areturn",
+        0xb1 => "return",
+        0xb2 => "getstatic",
+        0xb3 => "putstatic",
+        0xb4 => "getfield",
+        0xb5 => "putfield",
+        0xb6 => "invokevirtual",
+        0xb7 => "invokespecial",
+        0xb8 => "invokestatic",
+        0xb9 => "invokeinterface",
+        0xba => "invokedynamic",
+        0xbb => "new",
+        0xbc => "newarray",
+        0xbd => "anewarray",
+        0xbe => "arraylength",
+        0xbf => "athrow",
+        0xc0 => "checkcast",
+        0xc1 => "instanceof",
+        0xc2 => "monitorenter",
+        0xc3 => "monitorexit",
+        0xc4 => "wide",
+        0xc5 => "multianewarray",
+        0xc6 => "ifnull",
+        0xc7 => "ifnonnull",
+        0xc8 => "goto_w",
+        0xc9 => "jsr_w",
+        _ => return None,
+    };
+    Some(mnemonic)
+}
+
+fn read_u8(code: &[u8], pc: usize) -> Result<u8, Box<dyn Error>> {
+    Ok(*code.get(pc).ok_or("code is truncated")?)
+}
+
+fn read_u16(code: &[u8], pc: usize) -> Result<u16, Box<dyn Error>> {
+    let bytes = code.get(pc..pc + 2).ok_or("code is truncated")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_i32(code: &[u8], pc: usize) -> Result<i32, Box<dyn Error>> {
+    let bytes = code.get(pc..pc + 4).ok_or("code is truncated")?;
+    Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+// branch offsets are relative to the branching instruction
+fn branch_target(code: &[u8], pc: usize, offset: i64) -> Result<usize, Box<dyn Error>> {
+    let target = pc as i64 + offset;
+    if target < 0 || target >= code.len() as i64 {
+        return Err(format!("branch target {target} out of bounds at {pc}").into());
+    }
+    Ok(target as usize)
+}
+
+// decodes the instruction at pc
+pub fn decode(code: &[u8], pc: usize) -> Result<Instruction, Box<dyn Error>> {
+    let opcode = read_u8(code, pc)?;
+    let mut wide = false;
+    let (length, operands) = match opcode {
+        // bipush, newarray
+        0x10 => (2, Operands::Immediate(read_u8(code, pc + 1)? as i8 as i32)),
+        0xbc => (2, Operands::Immediate(read_u8(code, pc + 1)? as i32)),
+        // sipush
+        0x11 => (
+            3,
+            Operands::Immediate(read_u16(code, pc + 1)? as i16 as i32),
+        ),
+        // ldc
+        0x12 => (
+            2,
+            Operands::ConstantPoolIndex(read_u8(code, pc + 1)? as u16),
+        ),
+        0x13 | 0x14 | 0xb2..=0xb8 | 0xbb | 0xbd | 0xc0 | 0xc1 => {
+            (3, Operands::ConstantPoolIndex(read_u16(code, pc + 1)?))
+        }
+        // loads, stores, ret
+        0x15..=0x19 | 0x36..=0x3a | 0xa9 => {
+            (2, Operands::LocalVariable(read_u8(code, pc + 1)? as u16))
+        }
+        0x84 => (
+            3,
+            Operands::Iinc {
+                index: read_u8(code, pc + 1)? as u16,
+                constant: read_u8(code, pc + 2)? as i8 as i16,
+            },
+        ),
+        // if<cond>, if_icmp<cond>, if_acmp<cond>, goto, jsr, ifnull, ifnonnull
+        0x99..=0xa8 | 0xc6 | 0xc7 => {
+            let offset = read_u16(code, pc + 1)? as i16 as i64;
+            (3, Operands::Branch(branch_target(code, pc, offset)?))
+        }
+        // goto_w, jsr_w
+        0xc8 | 0xc9 => {
+            let offset = read_i32(code, pc + 1)? as i64;
+            (5, Operands::Branch(branch_target(code, pc, offset)?))
+        }
+        0xb9 => {
+            if read_u8(code, pc + 4)? != 0 {
+                return Err(
+                    format!("invokeinterface without a zero fourth operand at {pc}").into(),
+                );
+            }
+            (
+                5,
+                Operands::InvokeInterface {
+                    index: read_u16(code, pc + 1)?,
+                    count: read_u8(code, pc + 3)?,
+                },
+            )
+        }
+        0xba => (5, Operands::ConstantPoolIndex(read_u16(code, pc + 1)?)),
+        0xc5 => (
+            4,
+            Operands::MultiANewArray {
+                index: read_u16(code, pc + 1)?,
+                dimensions: read_u8(code, pc + 3)?,
+            },
+        ),
+        // wide iinc has a two byte constant, all other wide instructions only the index
+        0xc4 => {
+            wide = true;
+            let index = read_u16(code, pc + 2)?;
+            match read_u8(code, pc + 1)? {
+                0x84 => (
+                    6,
+                    Operands::Iinc {
+                        index,
+                        constant: read_u16(code, pc + 4)? as i16,
+                    },
+                ),
+                0x15..=0x19 | 0x36..=0x3a | 0xa9 => (4, Operands::LocalVariable(index)),
+                _ => return Err(format!("invalid wide instruction at {pc}").into()),
+            }
+        }
+        // tableswitch and lookupswitch are padded to a multiple of four
+        0xaa => {
+            let base = pc + 1 + (3 - pc % 4);
+            let default = branch_target(code, pc, read_i32(code, base)? as i64)?;
+            let low = read_i32(code, base + 4)?;
+            let high = read_i32(code, base + 8)?;
+            if low > high {
+                return Err(format!("tableswitch with low {low} > high {high} at {pc}").into());
+            }
+            let count = (high as i64 - low as i64 + 1) as usize;
+            if base + 12 + count * 4 > code.len() {
+                return Err(format!("instruction at {pc} is truncated").into());
+            }
+            let mut targets = vec![];
+            for i in 0..count {
+                let offset = read_i32(code, base + 12 + i * 4)? as i64;
+                targets.push(branch_target(code, pc, offset)?);
+            }
+            (
+                base + 12 + count * 4 - pc,
+                Operands::TableSwitch {
+                    default,
+                    low,
+                    high,
+                    targets,
+                },
+            )
+        }
+        0xab => {
+            let base = pc + 1 + (3 - pc % 4);
+            let default = branch_target(code, pc, read_i32(code, base)? as i64)?;
+            let npairs = read_i32(code, base + 4)?;
+            if npairs < 0 || base + 8 + npairs as usize * 8 > code.len() {
+                return Err(format!("lookupswitch with {npairs} pairs at {pc}").into());
+            }
+            let mut pairs: Vec<(i32, usize)> = vec![];
+            for i in 0..npairs as usize {
+                let r#match = read_i32(code, base + 8 + i * 8)?;
+                if pairs
+                    .last()
+                    .is_some_and(|(previous, _)| *previous >= r#match)
+                {
+                    return Err(format!("lookupswitch pairs are not sorted at {pc}").into());
+                }
+                let offset = read_i32(code, base + 8 + i * 8 + 4)? as i64;
+                pairs.push((r#match, branch_target(code, pc, offset)?));
+            }
+            (
+                base + 8 + npairs as usize * 8 - pc,
+                Operands::LookupSwitch { default, pairs },
+            )
+        }
+        0x00..=0xc9 => (1, Operands::None),
+        _ => return Err(format!("invalid opcode 0x{opcode:02x} at {pc}").into()),
+    };
+    if pc + length > code.len() {
+        return Err(format!("instruction at {pc} is truncated").into());
+    }
+    let opcode = if wide { code[pc + 1] } else { opcode };
+
+    Ok(Instruction {
+        pc,
+        opcode,
+        wide,
+        length,
+        operands,
+    })
+}
+
+// decodes the whole code of a method, which has to end with a complete instruction
+pub fn decode_all(code: &[u8]) -> Result<Vec<Instruction>, Box<dyn Error>> {
+    let mut instructions = vec![];
+    let mut pc = 0;
+    while pc < code.len() {
+        let instruction = decode(code, pc)?;
+        pc += instruction.length;
+        instructions.push(instruction);
+    }
+    Ok(instructions)
+}
+
+// the code of a method decoded up front, so the interpreter doesn't have to decode an instruction
+// every time it runs it. Branches and exception handlers still go by offsets into the code, which
+// map to the index of the instruction starting there.
+#[derive(Debug)]
+pub struct DecodedCode {
+    pub instructions: Vec<Instruction>,
+    // indexed by offset, None for the offsets within an instruction
+    indices: Vec<Option<usize>>,
+    // the inline caches of the invokevirtual instructions of the interpreter, indexed like
+    // instructions. Each one holds the class of the receiver an invokevirtual ran on last and the
+    // class the method was selected in for it: the id of the field descriptor of the receiver in
+    // the upper half and the id of the class in the method area in the lower one, or EMPTY. Most
+    // call sites only ever see one class of receivers, so the selection can be skipped as long as
+    // the next receiver is of the same class.
+    inline_caches: Vec<AtomicU64>,
+}
+
+// an inline cache that didn't see any receiver yet
+const EMPTY: u64 = u64::MAX;
+
+impl DecodedCode {
+    pub fn new(code: &[u8]) -> Result<DecodedCode, Box<dyn Error>> {
+        let instructions = decode_all(code)?;
+        let mut indices = vec![None; code.len()];
+        for (index, instruction) in instructions.iter().enumerate() {
+            indices[instruction.pc] = Some(index);
+        }
+        let inline_caches = instructions.iter().map(|_| AtomicU64::new(EMPTY)).collect();
+        Ok(DecodedCode {
+            instructions,
+            indices,
+            inline_caches,
+        })
+    }
+
+    // the index of the instruction starting at pc
+    pub fn index_of(&self, pc: usize) -> Option<usize> {
+        *self.indices.get(pc)?
+    }
+
+    // the instruction starting at pc
+    pub fn instruction_at(&self, pc: usize) -> Option<&Instruction> {
+        self.instructions.get(self.index_of(pc)?)
+    }
+
+    // the class the invokevirtual at pc selected the method in, if it last ran on a receiver of
+    // the same class
+    pub fn cached_selection(&self, pc: usize, receiver: u32) -> Option<u32> {
+        // only the thread holding the vm lock interprets, and handing it on orders the accesses
+        let cache = self
+            .inline_caches
+            .get(self.index_of(pc)?)?
+            .load(Ordering::Relaxed);
+        (cache != EMPTY && (cache >> 32) as u32 == receiver).then_some(cache as u32)
+    }
+
+    // replaces whatever the invokevirtual at pc selected before, see inline_caches
+    pub fn cache_selection(&self, pc: usize, receiver: u32, class_id: u32) {
+        if let Some(cache) = self
+            .index_of(pc)
+            .and_then(|index| self.inline_caches.get(index))
+        {
+            cache.store((receiver as u64) << 32 | class_id as u64, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,322 @@
+// Reading the class files of a jar. A jar is a zip archive whose entries are either stored as is
+// or compressed with deflate, both of which are handled here without pulling in a zip crate.
+//
+// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+// https://www.rfc-editor.org/rfc/rfc1951
+
+use std::{error::Error, fs};
+
+const LOCAL_FILE_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+
+fn read_u16_le(bytes: &[u8], offset: usize) -> Result<u16, Box<dyn Error>> {
+    let value = bytes
+        .get(offset..offset + 2)
+        .ok_or("unexpected end of jar")?;
+    Ok(u16::from_le_bytes([value[0], value[1]]))
+}
+
+fn read_u32_le(bytes: &[u8], offset: usize) -> Result<u32, Box<dyn Error>> {
+    let value = bytes
+        .get(offset..offset + 4)
+        .ok_or("unexpected end of jar")?;
+    Ok(u32::from_le_bytes([value[0], value[1], value[2], value[3]]))
+}
+
+pub struct JarEntry {
+    // the path within the jar, e.g. java/lang/Object.class
+    pub name: String,
+    pub bytes: Vec<u8>,
+}
+
+// reads the entries of a jar whose names end with .class, in the order of its central directory
+pub fn read_jar_classes(path: &str) -> Result<Vec<JarEntry>, Box<dyn Error>> {
+    let bytes = fs::read(path)?;
+
+    // the end of central directory record is followed by a comment of at most 65535 bytes
+    let minimum_offset = bytes.len().saturating_sub(22 + u16::MAX as usize);
+    let end_of_central_directory = (minimum_offset..bytes.len().saturating_sub(21))
+        .rev()
+        .find(|offset| {
+            read_u32_le(&bytes, *offset).ok() == Some(END_OF_CENTRAL_DIRECTORY_SIGNATURE)
+        })
+        .ok_or("not a jar: no end of central directory record found")?;
+    let entry_count = read_u16_le(&bytes, end_of_central_directory + 10)?;
+    let mut offset = read_u32_le(&bytes, end_of_central_directory + 16)? as usize;
+
+    let mut classes = vec![];
+    for _ in 0..entry_count {
+        if read_u32_le(&bytes, offset)? != CENTRAL_DIRECTORY_SIGNATURE {
+            return Err("invalid central directory entry in jar".into());
+        }
+        let compression_method = read_u16_le(&bytes, offset + 10)?;
+        let compressed_size = read_u32_le(&bytes, offset + 20)? as usize;
+        let uncompressed_size = read_u32_le(&bytes, offset + 24)? as usize;
+        let name_length = read_u16_le(&bytes, offset + 28)? as usize;
+        let extra_length = read_u16_le(&bytes, offset + 30)? as usize;
+        let comment_length = read_u16_le(&bytes, offset + 32)? as usize;
+        let local_header_offset = read_u32_le(&bytes, offset + 42)? as usize;
+        let name = String::from_utf8(
+            bytes
+                .get(offset + 46..offset + 46 + name_length)
+                .ok_or("unexpected end of jar")?
+                .to_vec(),
+        )?;
+        offset += 46 + name_length + extra_length + comment_length;
+
+        if !name.ends_with(".class") {
+            continue;
+        }
+
+        // the sizes in the local header may be missing, so only its variable lengths are used
+        if read_u32_le(&bytes, local_header_offset)? != LOCAL_FILE_HEADER_SIGNATURE {
+            return Err(format!("invalid local file header for {name} in jar").into());
+        }
+        let data_offset = local_header_offset
+            + 30
+            + read_u16_le(&bytes, local_header_offset + 26)? as usize
+            + read_u16_le(&bytes, local_header_offset + 28)? as usize;
+        let data = bytes
+            .get(data_offset..data_offset + compressed_size)
+            .ok_or("unexpected end of jar")?;
+        let contents = match compression_method {
+            0 => data.to_vec(),
+            8 => inflate(data)?,
+            _ => {
+                return Err(format!(
+                    "unsupported compression method {compression_method} for {name} in jar"
+                )
+                .into())
+            }
+        };
+        if contents.len() != uncompressed_size {
+            return Err(format!("size mismatch for {name} in jar").into());
+        }
+        classes.push(JarEntry {
+            name,
+            bytes: contents,
+        });
+    }
+    Ok(classes)
+}
+
+struct BitReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    // deflate packs bits starting with the least significant one
+    fn bits(&mut self, count: u32) -> Result<u32, Box<dyn Error>> {
+        while self.bit_count < count {
+            let byte = *self
+                .bytes
+                .get(self.position)
+                .ok_or("unexpected end of deflate data")?;
+            self.position += 1;
+            self.bit_buffer |= (byte as u32) << self.bit_count;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1u64 << count) - 1) as u32;
+        self.bit_buffer >>= count;
+        self.bit_count -= count;
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+    }
+}
+
+const MAX_CODE_LENGTH: usize = 15;
+
+// a canonical huffman code, given by the number of codes of every length and the symbols ordered
+// by their code
+struct Huffman {
+    counts: [u16; MAX_CODE_LENGTH + 1],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Huffman {
+        let mut counts = [0u16; MAX_CODE_LENGTH + 1];
+        for length in lengths.iter() {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; MAX_CODE_LENGTH + 2];
+        for length in 1..=MAX_CODE_LENGTH {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; offsets[MAX_CODE_LENGTH + 1] as usize];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, Box<dyn Error>> {
+        // the first code of a length follows the last code of the previous length
+        let mut code: i32 = 0;
+        let mut first: i32 = 0;
+        let mut index: i32 = 0;
+        for length in 1..=MAX_CODE_LENGTH {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first += count;
+            first <<= 1;
+            code <<= 1;
+        }
+        Err("invalid huffman code in deflate data".into())
+    }
+}
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order in which the code lengths of the code length code are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+fn inflate_codes(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literal_lengths: &Huffman,
+    distances: &Huffman,
+) -> Result<(), Box<dyn Error>> {
+    loop {
+        let symbol = literal_lengths.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+        } else if symbol == 256 {
+            return Ok(());
+        } else {
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return Err("invalid length symbol in deflate data".into());
+            }
+            let length =
+                LENGTH_BASE[symbol] as usize + reader.bits(LENGTH_EXTRA[symbol] as u32)? as usize;
+            let symbol = distances.decode(reader)? as usize;
+            if symbol >= DISTANCE_BASE.len() {
+                return Err("invalid distance symbol in deflate data".into());
+            }
+            let distance = DISTANCE_BASE[symbol] as usize
+                + reader.bits(DISTANCE_EXTRA[symbol] as u32)? as usize;
+            if distance > out.len() {
+                return Err("distance too far back in deflate data".into());
+            }
+            // the copied range may overlap with the bytes being written
+            let start = out.len() - distance;
+            for i in 0..length {
+                out.push(out[start + i]);
+            }
+        }
+    }
+}
+
+pub fn inflate(bytes: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut reader = BitReader {
+        bytes,
+        position: 0,
+        bit_buffer: 0,
+        bit_count: 0,
+    };
+    let mut out = vec![];
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            // stored
+            0 => {
+                reader.align_to_byte();
+                let position = reader.position;
+                let length = read_u16_le(bytes, position)?;
+                let complement = read_u16_le(bytes, position + 2)?;
+                if length != !complement {
+                    return Err("invalid stored block length in deflate data".into());
+                }
+                out.extend_from_slice(
+                    bytes
+                        .get(position + 4..position + 4 + length as usize)
+                        .ok_or("unexpected end of deflate data")?,
+                );
+                reader.position = position + 4 + length as usize;
+            }
+            // fixed huffman codes
+            1 => {
+                let mut lengths = [0u8; 288];
+                lengths[..144].fill(8);
+                lengths[144..256].fill(9);
+                lengths[256..280].fill(7);
+                lengths[280..].fill(8);
+                let literal_lengths = Huffman::new(&lengths);
+                let distances = Huffman::new(&[5; 30]);
+                inflate_codes(&mut reader, &mut out, &literal_lengths, &distances)?;
+            }
+            // dynamic huffman codes
+            2 => {
+                let literal_length_count = reader.bits(5)? as usize + 257;
+                let distance_count = reader.bits(5)? as usize + 1;
+                let code_length_count = reader.bits(4)? as usize + 4;
+                let mut code_length_lengths = [0u8; 19];
+                for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+                    code_length_lengths[*index] = reader.bits(3)? as u8;
+                }
+                let code_lengths = Huffman::new(&code_length_lengths);
+
+                let mut lengths = vec![];
+                while lengths.len() < literal_length_count + distance_count {
+                    let symbol = code_lengths.decode(&mut reader)?;
+                    let (length, repeat) = match symbol {
+                        0..=15 => (symbol as u8, 1),
+                        16 => (
+                            *lengths
+                                .last()
+                                .ok_or("repeated code length without a previous one")?,
+                            3 + reader.bits(2)?,
+                        ),
+                        17 => (0, 3 + reader.bits(3)?),
+                        _ => (0, 11 + reader.bits(7)?),
+                    };
+                    for _ in 0..repeat {
+                        lengths.push(length);
+                    }
+                }
+                if lengths.len() > literal_length_count + distance_count {
+                    return Err("too many code lengths in deflate data".into());
+                }
+                let literal_lengths = Huffman::new(&lengths[..literal_length_count]);
+                let distances = Huffman::new(&lengths[literal_length_count..]);
+                inflate_codes(&mut reader, &mut out, &literal_lengths, &distances)?;
+            }
+            _ => return Err("invalid block type in deflate data".into()),
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
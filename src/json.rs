@@ -0,0 +1,495 @@
+// A small JSON emitter for the output of parse --format json. There is no serde to lean on, so
+// the parsed class is converted into a Json value by hand.
+
+use std::fmt::Write;
+
+use crate::parse::{
+    Attribute, Class, ClassAccess, Field, FieldAccess, Method, MethodAccess, StackMapFrame,
+    VerificationType,
+};
+
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(i64),
+    String(String),
+    Array(Vec<Json>),
+    // keeps the order the keys were inserted in
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn object(entries: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    fn string(value: &str) -> Json {
+        Json::String(value.to_owned())
+    }
+
+    fn number(value: impl Into<i64>) -> Json {
+        Json::Number(value.into())
+    }
+
+    fn optional_string(value: &Option<String>) -> Json {
+        match value {
+            Some(value) => Json::string(value),
+            None => Json::Null,
+        }
+    }
+
+    fn strings(values: &[String]) -> Json {
+        Json::Array(values.iter().map(|value| Json::string(value)).collect())
+    }
+
+    // pretty prints the value, indenting nested arrays and objects by two spaces per level
+    pub fn to_string_pretty(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out, 0);
+        out
+    }
+
+    fn write(&self, out: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth + 1);
+        let closing_indent = "  ".repeat(depth);
+        match self {
+            Json::Null => out.push_str("null"),
+            Json::Bool(value) => out.push_str(if *value { "true" } else { "false" }),
+            Json::Number(value) => out.push_str(&value.to_string()),
+            Json::String(value) => write_string(out, value),
+            Json::Array(values) if values.is_empty() => out.push_str("[]"),
+            Json::Array(values) => {
+                out.push_str("[\n");
+                for (i, value) in values.iter().enumerate() {
+                    out.push_str(&indent);
+                    value.write(out, depth + 1);
+                    if i + 1 < values.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                out.push_str(&closing_indent);
+                out.push(']');
+            }
+            Json::Object(entries) if entries.is_empty() => out.push_str("{}"),
+            Json::Object(entries) => {
+                out.push_str("{\n");
+                for (i, (key, value)) in entries.iter().enumerate() {
+                    out.push_str(&indent);
+                    write_string(out, key);
+                    out.push_str(": ");
+                    value.write(out, depth + 1);
+                    if i + 1 < entries.len() {
+                        out.push(',');
+                    }
+                    out.push('\n');
+                }
+                out.push_str(&closing_indent);
+                out.push('}');
+            }
+        }
+    }
+}
+
+// https://www.rfc-editor.org/rfc/rfc8259#section-7
+fn write_string(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                write!(out, "\\u{:04x}", c as u32).expect("writing to a String can't fail")
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn hex(bytes: &[u8]) -> Json {
+    let mut out = String::with_capacity(bytes.len() * 2);
+    for byte in bytes.iter() {
+        write!(out, "{byte:02x}").expect("writing to a String can't fail");
+    }
+    Json::String(out)
+}
+
+// the set access flags by name, e.g. ["public", "static"]
+fn flag_names(flags: &[(bool, &str)]) -> Json {
+    Json::Array(
+        flags
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, name)| Json::string(name))
+            .collect(),
+    )
+}
+
+fn class_access_names(access: &ClassAccess) -> Json {
+    flag_names(&[
+        (access.public, "public"),
+        (access.is_final, "final"),
+        (access.is_super, "super"),
+        (access.interface, "interface"),
+        (access.r#abstract, "abstract"),
+        (access.synthetic, "synthetic"),
+        (access.annotation, "annotation"),
+        (access.r#enum, "enum"),
+        (access.module, "module"),
+    ])
+}
+
+fn field_access_names(access: &FieldAccess) -> Json {
+    flag_names(&[
+        (access.public, "public"),
+        (access.private, "private"),
+        (access.protected, "protected"),
+        (access.r#static, "static"),
+        (access.r#final, "final"),
+        (access.volatile, "volatile"),
+        (access.transient, "transient"),
+        (access.synthetic, "synthetic"),
+        (access.r#enum, "enum"),
+    ])
+}
+
+fn method_access_names(access: &MethodAccess) -> Json {
+    flag_names(&[
+        (access.public, "public"),
+        (access.private, "private"),
+        (access.protected, "protected"),
+        (access.r#static, "static"),
+        (access.r#final, "final"),
+        (access.synchronized, "synchronized"),
+        (access.bridge, "bridge"),
+        (access.varargs, "varargs"),
+        (access.native, "native"),
+        (access.r#abstract, "abstract"),
+        (access.strict, "strict"),
+        (access.synthetic, "synthetic"),
+    ])
+}
+
+fn verification_type_to_json(verification_type: &VerificationType) -> Json {
+    match verification_type {
+        VerificationType::Top => Json::string("top"),
+        VerificationType::Integer => Json::string("integer"),
+        VerificationType::Float => Json::string("float"),
+        VerificationType::Double => Json::string("double"),
+        VerificationType::Long => Json::string("long"),
+        VerificationType::Null => Json::string("null"),
+        VerificationType::UninitializedThis => Json::string("uninitialized_this"),
+        VerificationType::Object(class_index) => {
+            Json::object(vec![("object", Json::number(*class_index))])
+        }
+        VerificationType::Uninitialized(offset) => {
+            Json::object(vec![("uninitialized", Json::number(*offset))])
+        }
+    }
+}
+
+fn verification_types_to_json(verification_types: &[VerificationType]) -> Json {
+    Json::Array(
+        verification_types
+            .iter()
+            .map(verification_type_to_json)
+            .collect(),
+    )
+}
+
+fn stack_map_frame_to_json(frame: &StackMapFrame) -> Json {
+    let offset_delta = ("offset_delta", Json::number(frame.offset_delta()));
+    match frame {
+        StackMapFrame::Same { .. } => {
+            Json::object(vec![("type", Json::string("same")), offset_delta])
+        }
+        StackMapFrame::SameLocals1StackItem { stack, .. } => Json::object(vec![
+            ("type", Json::string("same_locals_1_stack_item")),
+            offset_delta,
+            (
+                "stack",
+                verification_types_to_json(std::slice::from_ref(stack)),
+            ),
+        ]),
+        StackMapFrame::Chop { count, .. } => Json::object(vec![
+            ("type", Json::string("chop")),
+            offset_delta,
+            ("count", Json::number(*count)),
+        ]),
+        StackMapFrame::Append { locals, .. } => Json::object(vec![
+            ("type", Json::string("append")),
+            offset_delta,
+            ("locals", verification_types_to_json(locals)),
+        ]),
+        StackMapFrame::Full { locals, stack, .. } => Json::object(vec![
+            ("type", Json::string("full")),
+            offset_delta,
+            ("locals", verification_types_to_json(locals)),
+            ("stack", verification_types_to_json(stack)),
+        ]),
+    }
+}
+
+fn attributes_to_json(attributes: &[Attribute]) -> Json {
+    Json::Array(attributes.iter().map(attribute_to_json).collect())
+}
+
+fn attribute_to_json(attribute: &Attribute) -> Json {
+    match attribute {
+        Attribute::Code {
+            max_stack,
+            max_locals,
+            bytes,
+            exception_table,
+            attributes,
+        } => Json::object(vec![
+            ("name", Json::string("Code")),
+            ("max_stack", Json::number(*max_stack as i64)),
+            ("max_locals", Json::number(*max_locals as i64)),
+            ("code", hex(bytes)),
+            (
+                "exception_table",
+                Json::Array(
+                    exception_table
+                        .iter()
+                        .map(|item| {
+                            Json::object(vec![
+                                ("start_pc", Json::number(item.start_pc as i64)),
+                                ("end_pc", Json::number(item.end_pc as i64)),
+                                ("handler_pc", Json::number(item.handler_pc as i64)),
+                                ("catch_type", Json::number(item.catch_type as i64)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+            ("attributes", attributes_to_json(attributes)),
+        ]),
+        Attribute::BootstrapMethods(bootstrap_methods) => Json::object(vec![
+            ("name", Json::string("BootstrapMethods")),
+            (
+                "bootstrap_methods",
+                Json::Array(
+                    bootstrap_methods
+                        .iter()
+                        .map(|bootstrap_method| {
+                            Json::object(vec![
+                                ("method_ref", Json::number(bootstrap_method.method_ref)),
+                                (
+                                    "arguments",
+                                    Json::Array(
+                                        bootstrap_method
+                                            .arguments
+                                            .iter()
+                                            .map(|argument| Json::number(*argument))
+                                            .collect(),
+                                    ),
+                                ),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]),
+        Attribute::StackMapTable(frames) => Json::object(vec![
+            ("name", Json::string("StackMapTable")),
+            (
+                "frames",
+                Json::Array(frames.iter().map(stack_map_frame_to_json).collect()),
+            ),
+        ]),
+        Attribute::LineNumberTable(entries) => Json::object(vec![
+            ("name", Json::string("LineNumberTable")),
+            (
+                "line_numbers",
+                Json::Array(
+                    entries
+                        .iter()
+                        .map(|entry| {
+                            Json::object(vec![
+                                ("start_pc", Json::number(entry.start_pc as i64)),
+                                ("line_number", Json::number(entry.line_number)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]),
+        Attribute::Signature(signature) => Json::object(vec![
+            ("name", Json::string("Signature")),
+            ("signature", Json::string(signature)),
+        ]),
+        Attribute::LocalVariableTable(entries) => Json::object(vec![
+            ("name", Json::string("LocalVariableTable")),
+            (
+                "local_variables",
+                Json::Array(
+                    entries
+                        .iter()
+                        .map(|entry| {
+                            Json::object(vec![
+                                ("start_pc", Json::number(entry.start_pc as i64)),
+                                ("length", Json::number(entry.length as i64)),
+                                ("name", Json::string(&entry.name)),
+                                ("descriptor", Json::string(&entry.descriptor)),
+                                ("index", Json::number(entry.index as i64)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]),
+        Attribute::SourceFile(source_file) => Json::object(vec![
+            ("name", Json::string("SourceFile")),
+            ("source_file", Json::string(source_file)),
+        ]),
+        Attribute::InnerClasses(inner_classes) => Json::object(vec![
+            ("name", Json::string("InnerClasses")),
+            (
+                "classes",
+                Json::Array(
+                    inner_classes
+                        .iter()
+                        .map(|inner_class| {
+                            Json::object(vec![
+                                ("inner_class", Json::string(&inner_class.inner_class)),
+                                (
+                                    "outer_class",
+                                    Json::optional_string(&inner_class.outer_class),
+                                ),
+                                ("inner_name", Json::optional_string(&inner_class.inner_name)),
+                                (
+                                    "inner_class_access_flags",
+                                    Json::number(inner_class.inner_class_access_flags),
+                                ),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]),
+        Attribute::EnclosingMethod(enclosing_method) => {
+            let (method_name, method_descriptor) = match &enclosing_method.method {
+                Some((name, descriptor)) => (Json::string(name), Json::string(descriptor)),
+                None => (Json::Null, Json::Null),
+            };
+            Json::object(vec![
+                ("name", Json::string("EnclosingMethod")),
+                ("class", Json::string(&enclosing_method.class)),
+                ("method_name", method_name),
+                ("method_descriptor", method_descriptor),
+            ])
+        }
+        Attribute::NestHost(host) => Json::object(vec![
+            ("name", Json::string("NestHost")),
+            ("host", Json::string(host)),
+        ]),
+        Attribute::NestMembers(members) => Json::object(vec![
+            ("name", Json::string("NestMembers")),
+            ("members", Json::strings(members)),
+        ]),
+        Attribute::Record(components) => Json::object(vec![
+            ("name", Json::string("Record")),
+            (
+                "components",
+                Json::Array(
+                    components
+                        .iter()
+                        .map(|component| {
+                            Json::object(vec![
+                                ("name", Json::string(&component.name)),
+                                ("descriptor", Json::string(&component.descriptor)),
+                                ("attributes", attributes_to_json(&component.attributes)),
+                            ])
+                        })
+                        .collect(),
+                ),
+            ),
+        ]),
+        Attribute::PermittedSubclasses(subclasses) => Json::object(vec![
+            ("name", Json::string("PermittedSubclasses")),
+            ("classes", Json::strings(subclasses)),
+        ]),
+        Attribute::ConstantValue(index) => Json::object(vec![
+            ("name", Json::string("ConstantValue")),
+            ("constant_value", Json::number(*index)),
+        ]),
+        Attribute::Unknown { name, info } => {
+            Json::object(vec![("name", Json::string(name)), ("info", hex(info))])
+        }
+    }
+}
+
+fn field_to_json(field: &Field) -> Json {
+    Json::object(vec![
+        ("name", Json::string(&field.name)),
+        (
+            "descriptor",
+            Json::String(field.descriptor.field_type.descriptor()),
+        ),
+        ("access_flags", Json::number(field.access.flags)),
+        ("access", field_access_names(&field.access)),
+        ("attributes", attributes_to_json(&field.attributes)),
+    ])
+}
+
+fn method_to_json(method: &Method) -> Json {
+    Json::object(vec![
+        ("name", Json::string(&method.name)),
+        ("descriptor", Json::String(method.descriptor.descriptor())),
+        ("access_flags", Json::number(method.access.flags)),
+        ("access", method_access_names(&method.access)),
+        ("attributes", attributes_to_json(&method.attributes)),
+    ])
+}
+
+pub fn class_to_json(class: &Class) -> Json {
+    Json::object(vec![
+        ("minor_version", Json::number(class.minor_version)),
+        ("major_version", Json::number(class.major_version)),
+        ("access_flags", Json::number(class.access.flags)),
+        ("access", class_access_names(&class.access)),
+        ("this_class", Json::string(&class.this_class.name)),
+        (
+            "super_class",
+            match &class.super_class {
+                Some(super_class) => Json::string(&super_class.name),
+                None => Json::Null,
+            },
+        ),
+        (
+            "interfaces",
+            Json::Array(
+                class
+                    .interfaces
+                    .iter()
+                    .map(|interface| Json::string(&interface.name))
+                    .collect(),
+            ),
+        ),
+        (
+            "fields",
+            Json::Array(class.fields.iter().map(field_to_json).collect()),
+        ),
+        (
+            "methods",
+            Json::Array(
+                class
+                    .methods
+                    .iter()
+                    .map(|method| method_to_json(method))
+                    .collect(),
+            ),
+        ),
+        ("attributes", attributes_to_json(&class.attributes)),
+    ])
+}
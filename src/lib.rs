@@ -0,0 +1,24 @@
+// matebabe is a toy JVM. Class files are read by deserialize, turned into a Class by parse and
+// executed by a Vm from run, which embedders create through a VmBuilder:
+//
+//     let mut vm = matebabe::VmBuilder::new().system_property("foo", "bar").build();
+//     let status = vm.run_main("Main")?;
+
+pub mod cfg;
+pub mod deps;
+pub mod deserialize;
+pub mod difftest;
+pub mod disasm;
+pub mod golden;
+pub mod instruction;
+pub mod jar;
+pub mod json;
+pub mod parse;
+pub mod run;
+pub mod serialize;
+pub mod verify;
+
+pub use run::{
+    Collector, GcStatistics, HeapStatistics, JavaValue, LogLevel, LogTarget, MethodStatistics,
+    NativeEnv, RunOptions, ThreadMode, Vm, VmBuilder,
+};
@@ -1,18 +1,171 @@
+// The command line interface of matebabe, see the library for the interpreter itself.
+
 use std::error::Error;
 
-use parse::parse;
-use run::run;
+use matebabe::{
+    cfg, deps, deserialize, difftest, disasm, golden, jar, json,
+    parse::parse,
+    run::{parse_log_spec, Collector, RunOptions, ThreadMode, VmBuilder},
+    verify::{self, VerifyMode},
+};
 
-mod deserialize;
-mod parse;
-mod run;
-mod native;
+// the arguments shared by run and debug
+fn vm_args() -> [clap::Arg; 5] {
+    [
+        clap::arg!(-D <PROPERTY> "set a system property, e.g. -Dfoo=bar")
+            .required(false)
+            .action(clap::ArgAction::Append),
+        clap::arg!(-X <OPTION> "set a non-standard option, e.g. -Xss2048 to allow at most 2048 frames per thread")
+            .required(false)
+            .action(clap::ArgAction::Append),
+        clap::arg!(--log <SPEC> "log what the vm does to stderr, e.g. --log classload=info,interp=trace, taking precedence over MATEBABE_LOG")
+            .required(false),
+        clap::arg!(--trace <FILE> "write the methods entered and left and the instructions run to FILE")
+            .required(false),
+        clap::arg!(--"trace-filter" <CLASSES> "only trace classes matching CLASSES, e.g. com/example/*")
+            .required(false)
+            .action(clap::ArgAction::Append),
+    ]
+}
+
+fn run_options(submatches: &clap::ArgMatches) -> Result<RunOptions, Box<dyn Error>> {
+    let mut options = RunOptions {
+        // like java, ^C runs the shutdown hooks before exiting
+        handle_interrupt: true,
+        ..RunOptions::default()
+    };
+    for property in submatches
+        .get_many::<String>("PROPERTY")
+        .unwrap_or_default()
+    {
+        let (key, value) = property.split_once('=').unwrap_or((property.as_str(), ""));
+        options
+            .system_properties
+            .push((key.to_owned(), value.to_owned()));
+    }
+    for option in submatches.get_many::<String>("OPTION").unwrap_or_default() {
+        if let Some(depth) = option.strip_prefix("ss") {
+            options.max_stack_depth = depth
+                .parse()
+                .map_err(|_| format!("invalid stack depth -X{option}"))?;
+        } else if let Some(mode) = option.strip_prefix("verify:") {
+            options.verify_mode = match mode {
+                "none" => VerifyMode::None,
+                "remote" => VerifyMode::Remote,
+                "all" => VerifyMode::All,
+                _ => return Err(format!("invalid verify mode -X{option}").into()),
+            };
+        } else if let Some(collector) = option.strip_prefix("gc:") {
+            options.collector = match collector {
+                "mark-sweep" => Collector::MarkSweep,
+                "copying" => Collector::Copying,
+                _ => return Err(format!("invalid collector -X{option}").into()),
+            };
+        } else if let Some(thread_mode) = option.strip_prefix("threads:") {
+            options.thread_mode = match thread_mode {
+                "native" => ThreadMode::Native,
+                "green" => ThreadMode::Green,
+                _ => return Err(format!("invalid thread mode -X{option}").into()),
+            };
+        } else {
+            return Err(format!("unknown option -X{option}").into());
+        }
+    }
+    options.trace = submatches.get_one::<String>("trace").cloned();
+    options.trace_filters = submatches
+        .get_many::<String>("trace-filter")
+        .unwrap_or_default()
+        .cloned()
+        .collect();
+    if let Some(spec) = submatches.get_one::<String>("log") {
+        options.log_levels = parse_log_spec(spec)?;
+    }
+    Ok(options)
+}
 
 fn main() -> Result<(), Box<dyn Error>> {
     let cli = clap::Command::new("matebabe")
         .subcommand_required(true)
-        .subcommand(clap::Command::new("parse").arg(clap::arg!(<FILE> "file to parse")))
-        .subcommand(clap::Command::new("run").arg(clap::arg!(<FILE> "file to run")));
+        .subcommand(
+            clap::Command::new("parse")
+                .arg(clap::arg!(<FILE> "file to parse"))
+                .arg(clap::arg!(-v --verbose "print the constant pool"))
+                .arg(
+                    clap::arg!(--format <FORMAT> "print the parsed class in the given format")
+                        .required(false)
+                        .value_parser(["text", "json"])
+                        .default_value("text"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("cfg")
+                .arg(clap::arg!(<FILE> "file containing the method"))
+                .arg(clap::arg!(--method <NAME> "name of the method, optionally followed by its descriptor")),
+        )
+        .subcommand(
+            clap::Command::new("deps").arg(clap::arg!(<FILE> "class file or jar to analyze")),
+        )
+        .subcommand(
+            clap::Command::new("disasm").arg(clap::arg!(<FILE> "file to disassemble")),
+        )
+        .subcommand(
+            clap::Command::new("verify").arg(
+                clap::arg!(<FILE> "class files or jars to verify")
+                    .num_args(1..)
+                    .action(clap::ArgAction::Append),
+            ),
+        )
+        .subcommand(
+            clap::Command::new("run")
+                .arg(clap::arg!(<FILE> "file to run"))
+                .args(vm_args())
+                .arg(clap::arg!(--"verbose-gc" "print a line for every garbage collection and the totals at exit"))
+                .arg(clap::arg!(--"no-heap-dump" "don't print the heap to stdout at exit"))
+                .arg(
+                    clap::arg!(--"heap-dump" <FILE> "write the heap to FILE in the HPROF format at exit")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--"heap-dump-on-out-of-memory" <FILE> "write the heap to FILE in the HPROF format when the first OutOfMemoryError is thrown")
+                        .required(false),
+                )
+                .arg(clap::arg!(--"opcode-histogram" "count how often each opcode runs and print a summary at exit"))
+                .arg(
+                    clap::arg!(--"hot-methods" <N> "print the N methods that ran the most instructions and how often they were called at exit")
+                        .required(false)
+                        .value_parser(clap::value_parser!(usize)),
+                )
+                .arg(
+                    clap::arg!(--profile <FILE> "count the instructions run by each stack of methods and write them to FILE as collapsed stacks for flame graphs")
+                        .required(false),
+                )
+                .arg(
+                    clap::arg!(--"debug-port" <PORT> "wait for a JDWP debugger like jdb to attach on PORT before running main")
+                        .required(false)
+                        .value_parser(clap::value_parser!(u16)),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("debug")
+                .arg(clap::arg!(<FILE> "file to debug"))
+                .args(vm_args()),
+        )
+        .subcommand(
+            clap::Command::new("difftest")
+                .arg(clap::arg!(<TARGET> "class to run, or directory of classes to run those with a main method of"))
+                .arg(
+                    clap::arg!(--java <JAVA> "the java executable to compare with")
+                        .default_value("java"),
+                ),
+        )
+        .subcommand(
+            clap::Command::new("test")
+                .arg(clap::arg!(<DIR> "directory of tests, classes with a main method and a .expected file"))
+                .arg(
+                    clap::arg!(--javac <JAVAC> "the javac executable to compile the tests with")
+                        .default_value("javac"),
+                ),
+        );
 
     let matches = cli.get_matches();
     match matches.subcommand() {
@@ -24,14 +177,146 @@ fn main() -> Result<(), Box<dyn Error>> {
                     .to_string(),
             )?;
 
-            parse(deserialized)?;
+            let raw_constant_pool = deserialized.constant_pool.clone();
+            let class = parse(deserialized)?;
+            if submatches.get_one::<String>("format").expect("defaulted") == "json" {
+                println!("{}", json::class_to_json(&class).to_string_pretty());
+            } else if submatches.get_flag("verbose") {
+                print!(
+                    "{}",
+                    disasm::constant_pool_table(&raw_constant_pool, &class)?
+                );
+            }
+        }
+        Some(("cfg", submatches)) => {
+            let deserialized = deserialize::deserialize_class_file(
+                submatches
+                    .get_one::<String>("FILE")
+                    .expect("required")
+                    .to_string(),
+            )?;
+
+            let class = parse(deserialized)?;
+            let method = cfg::find_method(
+                &class,
+                submatches.get_one::<String>("method").expect("required"),
+            )?;
+            print!("{}", cfg::method_cfg_dot(&class, method)?);
+        }
+        Some(("deps", submatches)) => {
+            let (report, not_found) =
+                deps::dependency_report(submatches.get_one::<String>("FILE").expect("required"))?;
+            print!("{report}");
+            if not_found > 0 {
+                eprintln!("{not_found} referenced classes not found on the class path");
+                std::process::exit(1);
+            }
+        }
+        Some(("disasm", submatches)) => {
+            let deserialized = deserialize::deserialize_class_file(
+                submatches
+                    .get_one::<String>("FILE")
+                    .expect("required")
+                    .to_string(),
+            )?;
+
+            let class = parse(deserialized)?;
+            print!("{}", disasm::disassemble(&class)?);
+        }
+        Some(("verify", submatches)) => {
+            for file in submatches.get_many::<String>("FILE").expect("required") {
+                let class_files = if file.ends_with(".jar") {
+                    jar::read_jar_classes(file)?
+                } else {
+                    vec![jar::JarEntry {
+                        name: file.to_owned(),
+                        bytes: std::fs::read(file)?,
+                    }]
+                };
+                for class_file in class_files.iter() {
+                    let result = deserialize::deserialize_class_bytes(&class_file.bytes)
+                        .map_err(|error| error.into())
+                        .and_then(parse)
+                        .and_then(|class| verify::verify_class(&class));
+                    if let Err(error) = result {
+                        if class_file.name == *file {
+                            eprintln!("{file}: {error}");
+                        } else {
+                            eprintln!("{file}!/{}: {error}", class_file.name);
+                        }
+                        std::process::exit(1);
+                    }
+                }
+            }
         }
         Some(("run", submatches)) => {
             let filename = submatches
                 .get_one::<String>("FILE")
                 .expect("required")
                 .to_string();
-            run(filename)
+            let mut options = run_options(submatches)?;
+            options.verbose_gc = submatches.get_flag("verbose-gc");
+            options.heap_dump_on_out_of_memory = submatches
+                .get_one::<String>("heap-dump-on-out-of-memory")
+                .cloned();
+            options.debug_port = submatches.get_one::<u16>("debug-port").copied();
+            options.profile = submatches.get_one::<String>("profile").cloned();
+            options.opcode_histogram = submatches.get_flag("opcode-histogram");
+            options.hot_methods = submatches.get_one::<usize>("hot-methods").copied();
+            let mut vm = VmBuilder::new().options(options).build();
+            let status = vm.run_main(&filename);
+            if submatches.get_flag("verbose-gc") {
+                let statistics = vm.gc_statistics();
+                println!(
+                    "[gc] {} collections freed {} objects ({}B) in {:.3}ms",
+                    statistics.collections,
+                    statistics.freed_objects,
+                    statistics.freed_bytes,
+                    statistics.duration.as_secs_f64() * 1000.0
+                );
+            }
+            if let Some(path) = submatches.get_one::<String>("heap-dump") {
+                vm.write_heap_dump(path)?;
+            }
+            if !submatches.get_flag("no-heap-dump") {
+                print!("{}", vm.heap_dump());
+            }
+            std::process::exit(status?);
+        }
+        Some(("debug", submatches)) => {
+            let filename = submatches
+                .get_one::<String>("FILE")
+                .expect("required")
+                .to_string();
+            let mut options = run_options(submatches)?;
+            options.debug_prompt = true;
+            let mut vm = VmBuilder::new().options(options).build();
+            std::process::exit(vm.run_main(&filename)?);
+        }
+        Some(("difftest", submatches)) => {
+            let matebabe = std::env::current_exe()?.to_string_lossy().into_owned();
+            let (report, failed) = difftest::difftest(
+                submatches.get_one::<String>("TARGET").expect("required"),
+                &[matebabe, "run".to_owned(), "--no-heap-dump".to_owned()],
+                submatches.get_one::<String>("java").expect("defaulted"),
+            )?;
+            print!("{report}");
+            if failed > 0 {
+                eprintln!("{failed} classes behaved differently than on java");
+                std::process::exit(1);
+            }
+        }
+        Some(("test", submatches)) => {
+            let matebabe = std::env::current_exe()?.to_string_lossy().into_owned();
+            let (report, failed) = golden::run_tests(
+                submatches.get_one::<String>("DIR").expect("required"),
+                &[matebabe, "run".to_owned(), "--no-heap-dump".to_owned()],
+                submatches.get_one::<String>("javac").expect("defaulted"),
+            )?;
+            print!("{report}");
+            if failed > 0 {
+                std::process::exit(1);
+            }
         }
         Some(_) => println!("Command not found :("),
         None => println!("Command not found :("),
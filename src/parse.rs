@@ -2,6 +2,7 @@ use std::{
     error::Error,
     io::{Cursor, Read},
     str::Chars,
+    sync::Arc,
 };
 
 use byteorder::{BigEndian, ReadBytesExt};
@@ -10,32 +11,50 @@ use crate::deserialize::{AttributeInfo, CPInfo, DeserializedClassFile, FieldInfo
 
 #[derive(Debug)]
 pub struct ClassAccess {
+    // the access_flags as they appear in the class file
+    pub flags: u16,
     pub public: bool,
     pub is_final: bool,
     pub is_super: bool,
     pub interface: bool,
+    pub r#abstract: bool,
+    pub synthetic: bool,
+    pub annotation: bool,
+    pub r#enum: bool,
+    pub module: bool,
 }
 
 impl ClassAccess {
-    fn new(access_flags: u16) -> ClassAccess {
+    pub fn new(access_flags: u16) -> ClassAccess {
         let public = access_flags & 0x0001 == 0x0001;
         let is_final = access_flags & 0x0010 == 0x0010;
         let is_super = access_flags & 0x0020 == 0x0020;
         let interface = access_flags & 0x0200 == 0x0200;
-        // TODO: add remaining access flags!
+        let r#abstract = access_flags & 0x0400 == 0x0400;
+        let synthetic = access_flags & 0x1000 == 0x1000;
+        let annotation = access_flags & 0x2000 == 0x2000;
+        let r#enum = access_flags & 0x4000 == 0x4000;
+        let module = access_flags & 0x8000 == 0x8000;
 
-        return ClassAccess {
+        ClassAccess {
+            flags: access_flags,
             public,
             is_final,
             is_super,
             interface,
-        };
+            r#abstract,
+            synthetic,
+            annotation,
+            r#enum,
+            module,
+        }
     }
 }
 
-
 #[derive(Debug, Clone)]
 pub struct FieldAccess {
+    // the access_flags as they appear in the class file
+    pub flags: u16,
     pub public: bool,
     pub private: bool,
     pub protected: bool,
@@ -48,29 +67,36 @@ pub struct FieldAccess {
 }
 
 impl FieldAccess {
-    fn new(access_flags: u16) -> FieldAccess {
+    pub fn new(access_flags: u16) -> FieldAccess {
         let public = access_flags & 0x0001 == 0x0001;
         let private = access_flags & 0x0002 == 0x0002;
         let protected = access_flags & 0x0004 == 0x0004;
         let r#static = access_flags & 0x0008 == 0x0008;
-        // TODO: add remaining access flags!
+        let r#final = access_flags & 0x0010 == 0x0010;
+        let volatile = access_flags & 0x0040 == 0x0040;
+        let transient = access_flags & 0x0080 == 0x0080;
+        let synthetic = access_flags & 0x1000 == 0x1000;
+        let r#enum = access_flags & 0x4000 == 0x4000;
 
-        return FieldAccess {
+        FieldAccess {
+            flags: access_flags,
             public,
             private,
             protected,
             r#static,
-            r#final: false,
-            volatile: false,
-            transient: false,
-            synthetic: false,
-            r#enum: false,
-        };
+            r#final,
+            volatile,
+            transient,
+            synthetic,
+            r#enum,
+        }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct MethodAccess {
+    // the access_flags as they appear in the class file
+    pub flags: u16,
     pub public: bool,
     pub private: bool,
     pub protected: bool,
@@ -86,7 +112,7 @@ pub struct MethodAccess {
 }
 
 impl MethodAccess {
-    fn new(access_flags: u16) -> MethodAccess {
+    pub fn new(access_flags: u16) -> MethodAccess {
         let public = access_flags & 0x0001 == 0x0001;
         let private = access_flags & 0x0002 == 0x0002;
         let protected = access_flags & 0x0004 == 0x0004;
@@ -100,7 +126,8 @@ impl MethodAccess {
         let strict = access_flags & 0x0800 == 0x0800;
         let synthetic = access_flags & 0x1000 == 0x1000;
 
-        return MethodAccess {
+        MethodAccess {
+            flags: access_flags,
             public,
             private,
             protected,
@@ -113,31 +140,78 @@ impl MethodAccess {
             r#abstract,
             strict,
             synthetic,
-        };
+        }
     }
 }
 
 // https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-5.html#jvms-5.4.3.5-220
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum RefKind {
+    GetField,
+    GetStatic,
+    PutField,
+    PutStatic,
+    InvokeVirtual,
     InvokeStatic,
+    InvokeSpecial,
+    NewInvokeSpecial,
+    InvokeInterface,
+}
+
+impl RefKind {
+    pub fn reference_kind(&self) -> u8 {
+        match self {
+            RefKind::GetField => 1,
+            RefKind::GetStatic => 2,
+            RefKind::PutField => 3,
+            RefKind::PutStatic => 4,
+            RefKind::InvokeVirtual => 5,
+            RefKind::InvokeStatic => 6,
+            RefKind::InvokeSpecial => 7,
+            RefKind::NewInvokeSpecial => 8,
+            RefKind::InvokeInterface => 9,
+        }
+    }
+
+    fn new(reference_kind: u8) -> Result<RefKind, Box<dyn Error>> {
+        match reference_kind {
+            1 => Ok(RefKind::GetField),
+            2 => Ok(RefKind::GetStatic),
+            3 => Ok(RefKind::PutField),
+            4 => Ok(RefKind::PutStatic),
+            5 => Ok(RefKind::InvokeVirtual),
+            6 => Ok(RefKind::InvokeStatic),
+            7 => Ok(RefKind::InvokeSpecial),
+            8 => Ok(RefKind::NewInvokeSpecial),
+            9 => Ok(RefKind::InvokeInterface),
+            kind => Err(format!("invalid reference_kind {kind}").into()),
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Constant {
     Class(ClassInfo),
-    Utf8(String),
-    String(String),
+    // the UTF-16 chars, which like those of Java strings may be unpaired surrogates
+    Utf8(Vec<u16>),
+    String(Vec<u16>),
     MethodRef(ClassInfo, Box<crate::parse::Constant>),
+    // behaves just like a MethodRef, see as_method_ref
+    InterfaceMethodRef(ClassInfo, Box<crate::parse::Constant>),
     FieldRef(ClassInfo, Box<crate::parse::Constant>),
     NameAndType(String, String),
     InvokeDynamic(u16, Box<crate::parse::Constant>),
+    Dynamic(u16, Box<crate::parse::Constant>),
     MethodHandle(RefKind, Box<crate::parse::Constant>),
     MethodType(String),
+    Module(String),
+    Package(String),
     Integer(i32),
     Long(i64),
     Float(f32),
     Double(f64),
+    // the second entry of a long or double
+    Unusable,
     Placeholder,
 }
 
@@ -150,18 +224,21 @@ impl Constant {
         }
     }
 
-    fn as_utf8(&self) -> Option<&String> {
+    // the text of a utf8 constant used as a name or descriptor
+    fn as_utf8(&self) -> Result<String, Box<dyn Error>> {
         if let Self::Utf8(v) = self {
-            Some(v)
+            utf8_text(v)
         } else {
-            None
+            Err("no utf8".into())
         }
     }
+    // both method refs and interface method refs
     pub fn as_method_ref(&self) -> Option<(ClassInfo, Box<Constant>)> {
-        if let Self::MethodRef(value1, value2) = self {
-            Some((value1.to_owned(), value2.to_owned()))
-        } else {
-            None
+        match self {
+            Self::MethodRef(value1, value2) | Self::InterfaceMethodRef(value1, value2) => {
+                Some((value1.to_owned(), value2.to_owned()))
+            }
+            _ => None,
         }
     }
     pub fn as_field_ref(&self) -> Option<(ClassInfo, Box<Constant>)> {
@@ -185,6 +262,18 @@ fn parse_or_get_constant(
     deserialized_constant_pool: &Vec<CPInfo>,
     index: u16,
 ) -> Result<Constant, Box<dyn Error>> {
+    if index == 0 || index as usize > constant_pool.len() {
+        return Err(format!("invalid constant pool index {index}").into());
+    }
+    if matches!(
+        deserialized_constant_pool.get((index - 1) as usize),
+        Some(CPInfo::Unusable)
+    ) {
+        return Err(format!(
+            "constant pool index {index} is the second entry of a long or double and can't be used"
+        )
+        .into());
+    }
     if !matches!(
         constant_pool
             .get((index - 1) as usize)
@@ -205,13 +294,16 @@ fn parse_or_get_constant(
         .ok_or("invalid index")?;
 
     let constant = match cp_info {
-        CPInfo::ConstantClassInfo { tag, name_index } => Constant::Class(parse_class_info(
+        CPInfo::ConstantClassInfo {
+            tag: _,
+            name_index: _,
+        } => Constant::Class(parse_class_info(
             cp_info,
             constant_pool,
             deserialized_constant_pool,
         )?),
         CPInfo::ConstantMethodRefInfo {
-            tag,
+            tag: _,
             class_index,
             name_and_type_index,
         } => {
@@ -225,7 +317,7 @@ fn parse_or_get_constant(
             Constant::MethodRef(class.to_owned(), name_and_type.into())
         }
         CPInfo::ConstantFieldRefInfo {
-            tag,
+            tag: _,
             class_index,
             name_and_type_index,
         } => {
@@ -238,31 +330,37 @@ fn parse_or_get_constant(
             )?;
             Constant::FieldRef(class.to_owned(), name_and_type.into())
         }
-        CPInfo::ConstantStringInfo { tag, string_index } => {
+        CPInfo::ConstantStringInfo {
+            tag: _,
+            string_index,
+        } => {
             let string_constant =
                 parse_or_get_constant(constant_pool, deserialized_constant_pool, *string_index)?;
-            let string = string_constant.as_utf8().ok_or("no utf8")?;
-            Constant::String(string.to_owned())
+            match string_constant {
+                Constant::Utf8(string) => Constant::String(string),
+                _ => return Err("no utf8".into()),
+            }
         }
         CPInfo::ConstantNameAndTypeInfo {
-            tag,
+            tag: _,
             name_index,
             descriptor_index,
         } => {
             let name_constant =
                 parse_or_get_constant(constant_pool, deserialized_constant_pool, *name_index)?;
-            let name = name_constant.as_utf8().ok_or("no utf8")?;
+            let name = name_constant.as_utf8()?;
             let descriptor_text_constant = parse_or_get_constant(
                 constant_pool,
                 deserialized_constant_pool,
                 *descriptor_index,
             )?;
-            let descriptor_text = descriptor_text_constant.as_utf8().ok_or("no utf8")?;
+            let descriptor_text = descriptor_text_constant.as_utf8()?;
             Constant::NameAndType(name.to_owned(), descriptor_text.to_owned())
         }
-        info @ CPInfo::ConstantUtf8Info { .. } => Constant::Utf8(parse_utf8_info(info)),
+        CPInfo::ConstantUtf8Info { bytes, .. } => Constant::Utf8(decode_modified_utf8(bytes)?),
+        CPInfo::Unusable => Constant::Unusable,
         CPInfo::ConstantInvokeDynamicInfo {
-            tag,
+            tag: _,
             bootstrap_method_attr_index,
             name_and_type_index,
         } => {
@@ -274,27 +372,48 @@ fn parse_or_get_constant(
 
             Constant::InvokeDynamic(bootstrap_method_attr_index.to_owned(), name_and_type.into())
         }
-        info @ CPInfo::ConstantMethodHandleInfo {
-            tag,
+        CPInfo::ConstantDynamicInfo {
+            tag: _,
+            bootstrap_method_attr_index,
+            name_and_type_index,
+        } => {
+            let name_and_type = parse_or_get_constant(
+                constant_pool,
+                deserialized_constant_pool,
+                *name_and_type_index,
+            )?;
+
+            Constant::Dynamic(bootstrap_method_attr_index.to_owned(), name_and_type.into())
+        }
+        CPInfo::ConstantModuleInfo { tag: _, name_index } => {
+            let name_constant =
+                parse_or_get_constant(constant_pool, deserialized_constant_pool, *name_index)?;
+            let name = name_constant.as_utf8()?;
+            Constant::Module(name.to_owned())
+        }
+        CPInfo::ConstantPackageInfo { tag: _, name_index } => {
+            let name_constant =
+                parse_or_get_constant(constant_pool, deserialized_constant_pool, *name_index)?;
+            let name = name_constant.as_utf8()?;
+            Constant::Package(name.to_owned())
+        }
+        CPInfo::ConstantMethodHandleInfo {
+            tag: _,
             reference_kind,
             reference_index,
         } => {
-            // FIXME: Derive RefKind from reference_kind
-            // FIXME: decide which kind of reference to resolve using RefKind
             // FIXME: somehow check the class file version number for version specific behaviour
 
-            let methodref_or_interface_method_ref =
+            // depending on the kind, this is either a field ref or a (interface) method ref
+            let reference =
                 parse_or_get_constant(constant_pool, deserialized_constant_pool, *reference_index)?;
-            Constant::MethodHandle(
-                RefKind::InvokeStatic,
-                methodref_or_interface_method_ref.into(),
-            )
+            Constant::MethodHandle(RefKind::new(*reference_kind)?, reference.into())
         }
-        CPInfo::ConstantIntegerInfo { tag, bytes } => {
+        CPInfo::ConstantIntegerInfo { tag: _, bytes } => {
             Constant::Integer(Cursor::new(bytes.to_be_bytes()).read_i32::<BigEndian>()?)
         }
         CPInfo::ConstantLongInfo {
-            tag,
+            tag: _,
             high_bytes,
             low_bytes,
         } => Constant::Long(
@@ -302,7 +421,7 @@ fn parse_or_get_constant(
                 .read_i64::<BigEndian>()?,
         ),
         CPInfo::ConstantInterfaceMethodRefInfo {
-            tag,
+            tag: _,
             class_index,
             name_and_type_index,
         } => {
@@ -313,10 +432,10 @@ fn parse_or_get_constant(
                 deserialized_constant_pool,
                 *name_and_type_index,
             )?;
-            Constant::MethodRef(class.to_owned(), name_and_type.into())
+            Constant::InterfaceMethodRef(class.to_owned(), name_and_type.into())
         }
         CPInfo::ConstantMethodTypeInfo {
-            tag,
+            tag: _,
             descriptor_index,
         } => {
             let descriptor_constant = parse_or_get_constant(
@@ -324,14 +443,14 @@ fn parse_or_get_constant(
                 deserialized_constant_pool,
                 *descriptor_index,
             )?;
-            let descriptor = descriptor_constant.as_utf8().ok_or("no utf8")?;
+            let descriptor = descriptor_constant.as_utf8()?;
             Constant::MethodType(descriptor.to_owned())
         }
-        CPInfo::ConstantFloatInfo { tag, bytes } => {
+        CPInfo::ConstantFloatInfo { tag: _, bytes } => {
             Constant::Float(Cursor::new(bytes.to_be_bytes()).read_f32::<BigEndian>()?)
         }
         CPInfo::ConstantDoubleInfo {
-            tag,
+            tag: _,
             high_bytes,
             low_bytes,
         } => Constant::Double(
@@ -345,17 +464,86 @@ fn parse_or_get_constant(
     Ok(constant)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ClassInfo {
     pub name: String,
 }
 
-fn parse_utf8_info(info: &CPInfo) -> String {
-    // FIXME: this all can fail, properage!
+fn parse_utf8_info(info: &CPInfo) -> Result<String, Box<dyn Error>> {
     match info {
-        CPInfo::ConstantUtf8Info { bytes, .. } => String::from_utf8(bytes.to_owned()).unwrap(),
-        _ => unreachable!(),
+        CPInfo::ConstantUtf8Info { bytes, .. } => utf8_text(&decode_modified_utf8(bytes)?),
+        _ => Err("no utf8".into()),
+    }
+}
+
+// names and descriptors, unlike string constants, have to be text
+fn utf8_text(chars: &[u16]) -> Result<String, Box<dyn Error>> {
+    String::from_utf16(chars)
+        .map_err(|_| "ClassFormatError: unpaired surrogate in a name or descriptor".into())
+}
+
+// class files use modified UTF-8 (JVMS §4.4.7): the null char takes two bytes, and chars outside
+// the basic multilingual plane are written as their two UTF-16 surrogates of three bytes each.
+// Every char has exactly one encoding, anything else is malformed.
+pub fn decode_modified_utf8(bytes: &[u8]) -> Result<Vec<u16>, Box<dyn Error>> {
+    let malformed =
+        |index: usize| format!("ClassFormatError: malformed modified UTF-8 at byte {index}");
+    let mut chars = vec![];
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = bytes[index] as u16;
+        // the continuation bytes of the char, which have 10 as their top bits
+        let continuation = |offset: usize| {
+            bytes
+                .get(index + offset)
+                .filter(|b| *b & 0xc0 == 0x80)
+                .map(|b| (*b & 0x3f) as u16)
+                .ok_or_else(|| malformed(index))
+        };
+        let (char, length) = match byte {
+            0x01..=0x7f => (byte, 1),
+            0xc0..=0xdf => (((byte & 0x1f) << 6) | continuation(1)?, 2),
+            0xe0..=0xef => {
+                let char = ((byte & 0x0f) << 12) | (continuation(1)? << 6) | continuation(2)?;
+                (char, 3)
+            }
+            _ => return Err(malformed(index).into()),
+        };
+        if encoded_length(char) != length {
+            return Err(malformed(index).into());
+        }
+        chars.push(char);
+        index += length;
+    }
+    Ok(chars)
+}
+
+fn encoded_length(char: u16) -> usize {
+    match char {
+        0x0001..=0x007f => 1,
+        0x0000 | 0x0080..=0x07ff => 2,
+        _ => 3,
+    }
+}
+
+// the inverse of decode_modified_utf8
+pub fn encode_modified_utf8(chars: &[u16]) -> Vec<u8> {
+    let mut bytes = vec![];
+    for &char in chars {
+        match char {
+            0x0001..=0x007f => bytes.push(char as u8),
+            0x0000 | 0x0080..=0x07ff => {
+                bytes.push(0xc0 | (char >> 6) as u8);
+                bytes.push(0x80 | (char & 0x3f) as u8);
+            }
+            _ => {
+                bytes.push(0xe0 | (char >> 12) as u8);
+                bytes.push(0x80 | ((char >> 6) & 0x3f) as u8);
+                bytes.push(0x80 | (char & 0x3f) as u8);
+            }
+        }
     }
+    bytes
 }
 
 fn parse_class_info(
@@ -368,8 +556,10 @@ fn parse_class_info(
             let name =
                 parse_or_get_constant(constant_pool, deserialized_constant_pool, *name_index)?;
             match name {
-                Constant::Utf8(name_str) => Ok(ClassInfo { name: name_str }),
-                _ => unreachable!(),
+                name @ Constant::Utf8(_) => Ok(ClassInfo {
+                    name: name.as_utf8()?,
+                }),
+                _ => Err("no utf8".into()),
             }
         }
         _ => unreachable!(),
@@ -384,6 +574,31 @@ pub struct Field {
     pub attributes: Vec<Attribute>,
 }
 
+impl Field {
+    // the generic type of the field, if it has a Signature attribute. Malformed signatures are
+    // only reported here and don't prevent the class from loading.
+    pub fn signature(&self) -> Result<Option<ReferenceTypeSignature>, Box<dyn Error>> {
+        match self
+            .attributes
+            .iter()
+            .find_map(|attribute| attribute.as_signature())
+        {
+            Some(signature) => Ok(Some(parse_field_signature(signature)?)),
+            None => Ok(None),
+        }
+    }
+
+    // the constant pool index of the initial value, only used for static fields (JVMS §4.7.2)
+    pub fn constant_value(&self) -> Option<u16> {
+        if !self.access.r#static {
+            return None;
+        }
+        self.attributes
+            .iter()
+            .find_map(|attribute| attribute.as_constant_value())
+    }
+}
+
 fn parse_field(
     field_info: &FieldInfo,
     constant_pool: &Vec<CPInfo>,
@@ -392,12 +607,12 @@ fn parse_field(
     let name_info = constant_pool
         .get((field_info.name_index - 1) as usize)
         .ok_or("failed to get name")?;
-    let name = parse_utf8_info(name_info);
+    let name = parse_utf8_info(name_info)?;
     // println!("name: {name}");
     let descriptor_info = constant_pool
         .get((field_info.descriptor_index - 1) as usize)
         .expect("descriptor to be present");
-    let descriptor_text = parse_utf8_info(descriptor_info);
+    let descriptor_text = parse_utf8_info(descriptor_info)?;
     let descriptor = parse_field_descriptor(&descriptor_text)?;
 
     // println!("descriptor: {descriptor:?}");
@@ -420,15 +635,13 @@ pub struct FieldDescriptor {
     pub field_type: FieldType,
 }
 
-pub fn parse_field_descriptor(
-    field_descriptor: &String,
-) -> Result<FieldDescriptor, Box<dyn Error>> {
+pub fn parse_field_descriptor(field_descriptor: &str) -> Result<FieldDescriptor, Box<dyn Error>> {
     Ok(FieldDescriptor {
         field_type: parse_field_type(&mut field_descriptor.chars())?,
     })
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum FieldType {
     Integer,
     Boolean,
@@ -443,6 +656,30 @@ pub enum FieldType {
 }
 
 impl FieldType {
+    // the descriptor as it appears in a class file, e.g. [Ljava/lang/String;
+    pub fn descriptor(&self) -> String {
+        match self {
+            FieldType::Integer => "I".to_owned(),
+            FieldType::Boolean => "Z".to_owned(),
+            FieldType::Byte => "B".to_owned(),
+            FieldType::Char => "C".to_owned(),
+            FieldType::LongInteger => "J".to_owned(),
+            FieldType::Float => "F".to_owned(),
+            FieldType::Double => "D".to_owned(),
+            FieldType::Short => "S".to_owned(),
+            FieldType::ClassInstance(class_name) => format!("L{class_name};"),
+            FieldType::Array(component_type) => format!("[{}", component_type.descriptor()),
+        }
+    }
+
+    // the number of local variable or operand stack slots a value of this type takes up
+    pub fn width(&self) -> usize {
+        match self {
+            FieldType::LongInteger | FieldType::Double => 2,
+            _ => 1,
+        }
+    }
+
     pub fn as_class_instance(&self) -> Option<&String> {
         if let Self::ClassInstance(v) = self {
             Some(v)
@@ -452,7 +689,7 @@ impl FieldType {
     }
     pub fn as_array(&self) -> Option<&FieldType> {
         if let Self::Array(v) = self {
-            Some(&v)
+            Some(v)
         } else {
             None
         }
@@ -462,7 +699,7 @@ impl FieldType {
 fn parse_field_type(chars: &mut Chars) -> Result<FieldType, Box<dyn Error>> {
     // println!("chars: {chars:?}");
     match chars
-        .nth(0)
+        .next()
         .ok_or("failed to get first char of field_type")?
     {
         'L' => Ok(FieldType::ClassInstance(
@@ -477,10 +714,285 @@ fn parse_field_type(chars: &mut Chars) -> Result<FieldType, Box<dyn Error>> {
         'S' => Ok(FieldType::Short),
         'D' => Ok(FieldType::Double),
         'F' => Ok(FieldType::Float),
-        char @ _ => unreachable!("encountered {char}"),
+        char => unreachable!("encountered {char}"),
     }
 }
 
+// https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.7.9.1
+#[derive(Debug, Clone, PartialEq)]
+pub enum JavaTypeSignature {
+    // one of the primitive field types
+    BaseType(FieldType),
+    ReferenceType(ReferenceTypeSignature),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReferenceTypeSignature {
+    ClassType(ClassTypeSignature),
+    TypeVariable(String),
+    ArrayType(Box<JavaTypeSignature>),
+}
+
+// a class type like java/util/Map<TK;TV;>.Entry<TK;TV;>. The first segment carries the package,
+// the following ones are inner classes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassTypeSignature {
+    pub segments: Vec<SimpleClassTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleClassTypeSignature {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeArgument {
+    // *
+    Any,
+    Exact(ReferenceTypeSignature),
+    // + bound
+    Extends(ReferenceTypeSignature),
+    // - bound
+    Super(ReferenceTypeSignature),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParameter {
+    pub name: String,
+    pub class_bound: Option<ReferenceTypeSignature>,
+    pub interface_bounds: Vec<ReferenceTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub super_class: ClassTypeSignature,
+    pub super_interfaces: Vec<ClassTypeSignature>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub parameters: Vec<JavaTypeSignature>,
+    // None for void methods
+    pub result: Option<JavaTypeSignature>,
+    pub throws: Vec<ReferenceTypeSignature>,
+}
+
+pub fn parse_class_signature(signature: &str) -> Result<ClassSignature, Box<dyn Error>> {
+    let mut chars = signature.chars();
+    let type_parameters = parse_type_parameters(&mut chars)?;
+    let super_class = parse_class_type_signature(&mut chars)?;
+    let mut super_interfaces = vec![];
+    while chars.to_owned().next().is_some() {
+        super_interfaces.push(parse_class_type_signature(&mut chars)?);
+    }
+
+    Ok(ClassSignature {
+        type_parameters,
+        super_class,
+        super_interfaces,
+    })
+}
+
+pub fn parse_method_signature(signature: &str) -> Result<MethodSignature, Box<dyn Error>> {
+    let mut chars = signature.chars();
+    let type_parameters = parse_type_parameters(&mut chars)?;
+    expect_char(&mut chars, '(')?;
+    let mut parameters = vec![];
+    while chars.to_owned().next() != Some(')') {
+        parameters.push(parse_java_type_signature(&mut chars)?);
+    }
+    expect_char(&mut chars, ')')?;
+    let result = if chars.to_owned().next() == Some('V') {
+        chars.next();
+        None
+    } else {
+        Some(parse_java_type_signature(&mut chars)?)
+    };
+    let mut throws = vec![];
+    while chars.to_owned().next() == Some('^') {
+        chars.next();
+        throws.push(parse_reference_type_signature(&mut chars)?);
+    }
+    if let Some(c) = chars.next() {
+        return Err(format!("unexpected {c} at the end of method signature {signature}").into());
+    }
+
+    Ok(MethodSignature {
+        type_parameters,
+        parameters,
+        result,
+        throws,
+    })
+}
+
+pub fn parse_field_signature(signature: &str) -> Result<ReferenceTypeSignature, Box<dyn Error>> {
+    let mut chars = signature.chars();
+    let field_signature = parse_reference_type_signature(&mut chars)?;
+    if let Some(c) = chars.next() {
+        return Err(format!("unexpected {c} at the end of field signature {signature}").into());
+    }
+
+    Ok(field_signature)
+}
+
+fn expect_char(chars: &mut Chars, expected: char) -> Result<(), Box<dyn Error>> {
+    match chars.next() {
+        Some(c) if c == expected => Ok(()),
+        Some(c) => Err(format!("expected {expected} in signature, found {c}").into()),
+        None => Err(format!("expected {expected} in signature, found the end").into()),
+    }
+}
+
+// reads an identifier up to, but not including, one of the signature's special characters
+fn parse_signature_identifier(chars: &mut Chars) -> Result<String, Box<dyn Error>> {
+    let mut identifier = String::new();
+    while let Some(c) = chars.to_owned().next() {
+        if matches!(c, '.' | ';' | '[' | '/' | '<' | '>' | ':') {
+            break;
+        }
+        identifier.push(c);
+        chars.next();
+    }
+    if identifier.is_empty() {
+        return Err("expected an identifier in signature".into());
+    }
+
+    Ok(identifier)
+}
+
+fn parse_type_parameters(chars: &mut Chars) -> Result<Vec<TypeParameter>, Box<dyn Error>> {
+    let mut type_parameters = vec![];
+    if chars.to_owned().next() != Some('<') {
+        return Ok(type_parameters);
+    }
+    chars.next();
+    while chars.to_owned().next() != Some('>') {
+        let name = parse_signature_identifier(chars)?;
+        expect_char(chars, ':')?;
+        // the class bound may be left out if there are only interface bounds
+        let class_bound = if matches!(chars.to_owned().next(), Some('L' | 'T' | '[')) {
+            Some(parse_reference_type_signature(chars)?)
+        } else {
+            None
+        };
+        let mut interface_bounds = vec![];
+        while chars.to_owned().next() == Some(':') {
+            chars.next();
+            interface_bounds.push(parse_reference_type_signature(chars)?);
+        }
+        type_parameters.push(TypeParameter {
+            name,
+            class_bound,
+            interface_bounds,
+        });
+    }
+    expect_char(chars, '>')?;
+
+    Ok(type_parameters)
+}
+
+fn parse_java_type_signature(chars: &mut Chars) -> Result<JavaTypeSignature, Box<dyn Error>> {
+    let base_type = match chars.to_owned().next() {
+        Some('B') => FieldType::Byte,
+        Some('C') => FieldType::Char,
+        Some('D') => FieldType::Double,
+        Some('F') => FieldType::Float,
+        Some('I') => FieldType::Integer,
+        Some('J') => FieldType::LongInteger,
+        Some('S') => FieldType::Short,
+        Some('Z') => FieldType::Boolean,
+        _ => {
+            return Ok(JavaTypeSignature::ReferenceType(
+                parse_reference_type_signature(chars)?,
+            ))
+        }
+    };
+    chars.next();
+
+    Ok(JavaTypeSignature::BaseType(base_type))
+}
+
+fn parse_reference_type_signature(
+    chars: &mut Chars,
+) -> Result<ReferenceTypeSignature, Box<dyn Error>> {
+    match chars.to_owned().next() {
+        Some('L') => Ok(ReferenceTypeSignature::ClassType(
+            parse_class_type_signature(chars)?,
+        )),
+        Some('T') => {
+            chars.next();
+            let name = parse_signature_identifier(chars)?;
+            expect_char(chars, ';')?;
+            Ok(ReferenceTypeSignature::TypeVariable(name))
+        }
+        Some('[') => {
+            chars.next();
+            Ok(ReferenceTypeSignature::ArrayType(Box::new(
+                parse_java_type_signature(chars)?,
+            )))
+        }
+        Some(c) => Err(format!("unexpected {c} in signature").into()),
+        None => Err("unexpected end of signature".into()),
+    }
+}
+
+fn parse_class_type_signature(chars: &mut Chars) -> Result<ClassTypeSignature, Box<dyn Error>> {
+    expect_char(chars, 'L')?;
+    // the package specifier is kept as part of the name of the first segment
+    let mut name = parse_signature_identifier(chars)?;
+    while chars.to_owned().next() == Some('/') {
+        chars.next();
+        name.push('/');
+        name.push_str(&parse_signature_identifier(chars)?);
+    }
+    let mut segments = vec![SimpleClassTypeSignature {
+        name,
+        type_arguments: parse_type_arguments(chars)?,
+    }];
+    while chars.to_owned().next() == Some('.') {
+        chars.next();
+        segments.push(SimpleClassTypeSignature {
+            name: parse_signature_identifier(chars)?,
+            type_arguments: parse_type_arguments(chars)?,
+        });
+    }
+    expect_char(chars, ';')?;
+
+    Ok(ClassTypeSignature { segments })
+}
+
+fn parse_type_arguments(chars: &mut Chars) -> Result<Vec<TypeArgument>, Box<dyn Error>> {
+    let mut type_arguments = vec![];
+    if chars.to_owned().next() != Some('<') {
+        return Ok(type_arguments);
+    }
+    chars.next();
+    while chars.to_owned().next() != Some('>') {
+        let type_argument = match chars.to_owned().next() {
+            Some('*') => {
+                chars.next();
+                TypeArgument::Any
+            }
+            Some('+') => {
+                chars.next();
+                TypeArgument::Extends(parse_reference_type_signature(chars)?)
+            }
+            Some('-') => {
+                chars.next();
+                TypeArgument::Super(parse_reference_type_signature(chars)?)
+            }
+            _ => TypeArgument::Exact(parse_reference_type_signature(chars)?),
+        };
+        type_arguments.push(type_argument);
+    }
+    expect_char(chars, '>')?;
+
+    Ok(type_arguments)
+}
+
 #[derive(Debug, Clone)]
 pub struct ExceptionTableItem {
     pub start_pc: usize,
@@ -489,6 +1001,113 @@ pub struct ExceptionTableItem {
     pub catch_type: usize,
 }
 
+// https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.7.23
+#[derive(Debug, Clone)]
+pub struct BootstrapMethod {
+    // constant pool index of a MethodHandle
+    pub method_ref: u16,
+    // constant pool indices of the static arguments
+    pub arguments: Vec<u16>,
+}
+
+// https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.7.12
+#[derive(Debug, Clone)]
+pub struct LineNumberTableEntry {
+    pub start_pc: usize,
+    pub line_number: u16,
+}
+
+// https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.7.13
+#[derive(Debug, Clone)]
+pub struct LocalVariableTableEntry {
+    // the variable has a value in the code range [start_pc, start_pc + length)
+    pub start_pc: usize,
+    pub length: usize,
+    pub name: String,
+    pub descriptor: String,
+    pub index: usize,
+}
+
+// https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.7.6
+#[derive(Debug, Clone)]
+pub struct InnerClass {
+    pub inner_class: String,
+    // None for local and anonymous classes
+    pub outer_class: Option<String>,
+    // None for anonymous classes
+    pub inner_name: Option<String>,
+    pub inner_class_access_flags: u16,
+}
+
+// https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.7.7
+#[derive(Debug, Clone)]
+pub struct EnclosingMethod {
+    pub class: String,
+    // name and descriptor, None if the class is not enclosed by a method, e.g. in an initializer
+    pub method: Option<(String, String)>,
+}
+
+// https://docs.oracle.com/javase/specs/jvms/se21/html/jvms-4.html#jvms-4.7.30
+#[derive(Debug, Clone)]
+pub struct RecordComponent {
+    pub name: String,
+    pub descriptor: String,
+    pub attributes: Vec<Attribute>,
+}
+
+// https://docs.oracle.com/javase/specs/jvms/se11/html/jvms-4.html#jvms-4.7.4
+#[derive(Debug, Clone, PartialEq)]
+pub enum VerificationType {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    // constant pool index of a Class
+    Object(u16),
+    // offset of the new instruction creating the object
+    Uninitialized(u16),
+}
+
+#[derive(Debug, Clone)]
+pub enum StackMapFrame {
+    Same {
+        offset_delta: u16,
+    },
+    SameLocals1StackItem {
+        offset_delta: u16,
+        stack: VerificationType,
+    },
+    Chop {
+        offset_delta: u16,
+        // the number of locals removed
+        count: u8,
+    },
+    Append {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+    },
+    Full {
+        offset_delta: u16,
+        locals: Vec<VerificationType>,
+        stack: Vec<VerificationType>,
+    },
+}
+
+impl StackMapFrame {
+    pub fn offset_delta(&self) -> u16 {
+        match self {
+            StackMapFrame::Same { offset_delta }
+            | StackMapFrame::SameLocals1StackItem { offset_delta, .. }
+            | StackMapFrame::Chop { offset_delta, .. }
+            | StackMapFrame::Append { offset_delta, .. }
+            | StackMapFrame::Full { offset_delta, .. } => *offset_delta,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Attribute {
     Code {
@@ -496,8 +1115,28 @@ pub enum Attribute {
         max_locals: usize,
         bytes: Vec<u8>,
         exception_table: Vec<ExceptionTableItem>,
+        attributes: Vec<Attribute>,
+    },
+    BootstrapMethods(Vec<BootstrapMethod>),
+    StackMapTable(Vec<StackMapFrame>),
+    LineNumberTable(Vec<LineNumberTableEntry>),
+    // the raw signature, see Class::signature, Field::signature and Method::signature
+    Signature(String),
+    LocalVariableTable(Vec<LocalVariableTableEntry>),
+    SourceFile(String),
+    InnerClasses(Vec<InnerClass>),
+    EnclosingMethod(EnclosingMethod),
+    NestHost(String),
+    NestMembers(Vec<String>),
+    Record(Vec<RecordComponent>),
+    PermittedSubclasses(Vec<String>),
+    // the constant pool index of the initial value of a static field
+    ConstantValue(u16),
+    // attributes matebabe doesn't interpret, kept as is so the class can be written back
+    Unknown {
+        name: String,
+        info: Vec<u8>,
     },
-    Placeholder,
 }
 
 impl Attribute {
@@ -507,6 +1146,7 @@ impl Attribute {
             max_stack,
             max_locals,
             exception_table,
+            ..
         } = self
         {
             Some((
@@ -519,6 +1159,126 @@ impl Attribute {
             None
         }
     }
+
+    pub fn as_bootstrap_methods(&self) -> Option<&Vec<BootstrapMethod>> {
+        if let Self::BootstrapMethods(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_stack_map_table(&self) -> Option<&Vec<StackMapFrame>> {
+        if let Self::StackMapTable(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_line_number_table(&self) -> Option<&Vec<LineNumberTableEntry>> {
+        if let Self::LineNumberTable(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_local_variable_table(&self) -> Option<&Vec<LocalVariableTableEntry>> {
+        if let Self::LocalVariableTable(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_source_file(&self) -> Option<&String> {
+        if let Self::SourceFile(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_signature(&self) -> Option<&String> {
+        if let Self::Signature(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_inner_classes(&self) -> Option<&Vec<InnerClass>> {
+        if let Self::InnerClasses(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_enclosing_method(&self) -> Option<&EnclosingMethod> {
+        if let Self::EnclosingMethod(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_nest_host(&self) -> Option<&String> {
+        if let Self::NestHost(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_nest_members(&self) -> Option<&Vec<String>> {
+        if let Self::NestMembers(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_record(&self) -> Option<&Vec<RecordComponent>> {
+        if let Self::Record(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_permitted_subclasses(&self) -> Option<&Vec<String>> {
+        if let Self::PermittedSubclasses(v) = self {
+            Some(v)
+        } else {
+            None
+        }
+    }
+
+    pub fn as_constant_value(&self) -> Option<u16> {
+        if let Self::ConstantValue(index) = self {
+            Some(*index)
+        } else {
+            None
+        }
+    }
+}
+
+fn parse_utf8_at(constant_pool: &[CPInfo], index: u16) -> Result<String, Box<dyn Error>> {
+    match constant_pool.get((index as usize).wrapping_sub(1)) {
+        Some(info @ CPInfo::ConstantUtf8Info { .. }) => parse_utf8_info(info),
+        _ => Err(format!("expected utf8 at constant pool index {index}").into()),
+    }
+}
+
+fn parse_class_name_at(constant_pool: &[CPInfo], index: u16) -> Result<String, Box<dyn Error>> {
+    match constant_pool.get((index as usize).wrapping_sub(1)) {
+        Some(CPInfo::ConstantClassInfo { name_index, .. }) => {
+            parse_utf8_at(constant_pool, *name_index)
+        }
+        _ => Err(format!("expected a class at constant pool index {index}").into()),
+    }
 }
 
 fn parse_attribute(
@@ -529,7 +1289,7 @@ fn parse_attribute(
         .get((attribute_info.attribute_name_index - 1) as usize)
         .ok_or("expect name to be present")
         .unwrap();
-    let name = parse_utf8_info(name_info);
+    let name = parse_utf8_info(name_info)?;
     // println!("attribute name: {name}");
 
     if name == "Code" {
@@ -557,35 +1317,358 @@ fn parse_attribute(
             })
         }
 
-        // TODO: attributes
+        let mut attributes = vec![];
+        let attributes_count = csr.read_u16::<BigEndian>()?;
+        for _ in 0..attributes_count {
+            let attribute_name_index = csr.read_u16::<BigEndian>()?;
+            let attribute_length = csr.read_u32::<BigEndian>()?;
+            let mut info = vec![];
+            (&mut csr)
+                .take(attribute_length.into())
+                .read_to_end(&mut info)?;
+            attributes.push(parse_attribute(
+                &AttributeInfo {
+                    attribute_name_index,
+                    attribute_length,
+                    info,
+                },
+                constant_pool,
+            )?);
+        }
+
         return Ok(Attribute::Code {
             max_stack: max_stack as usize,
             max_locals: max_locals as usize,
             bytes: code,
             exception_table: et,
+            attributes,
         });
     }
-    Ok(Attribute::Placeholder)
+    if name == "BootstrapMethods" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let num_bootstrap_methods = csr.read_u16::<BigEndian>()?;
+        let mut bootstrap_methods = vec![];
+        for _ in 0..num_bootstrap_methods {
+            let method_ref = csr.read_u16::<BigEndian>()?;
+            let num_bootstrap_arguments = csr.read_u16::<BigEndian>()?;
+            let mut arguments = vec![];
+            for _ in 0..num_bootstrap_arguments {
+                arguments.push(csr.read_u16::<BigEndian>()?);
+            }
+            bootstrap_methods.push(BootstrapMethod {
+                method_ref,
+                arguments,
+            });
+        }
+
+        return Ok(Attribute::BootstrapMethods(bootstrap_methods));
+    }
+    if name == "StackMapTable" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let number_of_entries = csr.read_u16::<BigEndian>()?;
+        let mut frames = vec![];
+        for _ in 0..number_of_entries {
+            let frame_type = csr.read_u8()?;
+            let frame = match frame_type {
+                0..=63 => StackMapFrame::Same {
+                    offset_delta: frame_type as u16,
+                },
+                64..=127 => StackMapFrame::SameLocals1StackItem {
+                    offset_delta: (frame_type - 64) as u16,
+                    stack: parse_verification_type(&mut csr)?,
+                },
+                247 => StackMapFrame::SameLocals1StackItem {
+                    offset_delta: csr.read_u16::<BigEndian>()?,
+                    stack: parse_verification_type(&mut csr)?,
+                },
+                248..=250 => StackMapFrame::Chop {
+                    offset_delta: csr.read_u16::<BigEndian>()?,
+                    count: 251 - frame_type,
+                },
+                251 => StackMapFrame::Same {
+                    offset_delta: csr.read_u16::<BigEndian>()?,
+                },
+                252..=254 => {
+                    let offset_delta = csr.read_u16::<BigEndian>()?;
+                    let mut locals = vec![];
+                    for _ in 0..(frame_type - 251) {
+                        locals.push(parse_verification_type(&mut csr)?);
+                    }
+                    StackMapFrame::Append {
+                        offset_delta,
+                        locals,
+                    }
+                }
+                255 => {
+                    let offset_delta = csr.read_u16::<BigEndian>()?;
+                    let number_of_locals = csr.read_u16::<BigEndian>()?;
+                    let mut locals = vec![];
+                    for _ in 0..number_of_locals {
+                        locals.push(parse_verification_type(&mut csr)?);
+                    }
+                    let number_of_stack_items = csr.read_u16::<BigEndian>()?;
+                    let mut stack = vec![];
+                    for _ in 0..number_of_stack_items {
+                        stack.push(parse_verification_type(&mut csr)?);
+                    }
+                    StackMapFrame::Full {
+                        offset_delta,
+                        locals,
+                        stack,
+                    }
+                }
+                _ => return Err(format!("invalid stack map frame type {frame_type}").into()),
+            };
+            frames.push(frame);
+        }
+
+        return Ok(Attribute::StackMapTable(frames));
+    }
+    if name == "LineNumberTable" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let line_number_table_length = csr.read_u16::<BigEndian>()?;
+        let mut entries = vec![];
+        for _ in 0..line_number_table_length {
+            let start_pc = csr.read_u16::<BigEndian>()?;
+            let line_number = csr.read_u16::<BigEndian>()?;
+            entries.push(LineNumberTableEntry {
+                start_pc: start_pc as usize,
+                line_number,
+            });
+        }
+
+        return Ok(Attribute::LineNumberTable(entries));
+    }
+    if name == "LocalVariableTable" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let local_variable_table_length = csr.read_u16::<BigEndian>()?;
+        let mut entries = vec![];
+        for _ in 0..local_variable_table_length {
+            let start_pc = csr.read_u16::<BigEndian>()?;
+            let length = csr.read_u16::<BigEndian>()?;
+            let name_index = csr.read_u16::<BigEndian>()?;
+            let descriptor_index = csr.read_u16::<BigEndian>()?;
+            let index = csr.read_u16::<BigEndian>()?;
+            entries.push(LocalVariableTableEntry {
+                start_pc: start_pc as usize,
+                length: length as usize,
+                name: parse_utf8_at(constant_pool, name_index)?,
+                descriptor: parse_utf8_at(constant_pool, descriptor_index)?,
+                index: index as usize,
+            });
+        }
+
+        return Ok(Attribute::LocalVariableTable(entries));
+    }
+    if name == "Signature" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let signature_index = csr.read_u16::<BigEndian>()?;
+
+        return Ok(Attribute::Signature(parse_utf8_at(
+            constant_pool,
+            signature_index,
+        )?));
+    }
+    if name == "InnerClasses" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let number_of_classes = csr.read_u16::<BigEndian>()?;
+        let mut classes = vec![];
+        for _ in 0..number_of_classes {
+            let inner_class_info_index = csr.read_u16::<BigEndian>()?;
+            let outer_class_info_index = csr.read_u16::<BigEndian>()?;
+            let inner_name_index = csr.read_u16::<BigEndian>()?;
+            let inner_class_access_flags = csr.read_u16::<BigEndian>()?;
+            classes.push(InnerClass {
+                inner_class: parse_class_name_at(constant_pool, inner_class_info_index)?,
+                outer_class: if outer_class_info_index == 0 {
+                    None
+                } else {
+                    Some(parse_class_name_at(constant_pool, outer_class_info_index)?)
+                },
+                inner_name: if inner_name_index == 0 {
+                    None
+                } else {
+                    Some(parse_utf8_at(constant_pool, inner_name_index)?)
+                },
+                inner_class_access_flags,
+            });
+        }
+
+        return Ok(Attribute::InnerClasses(classes));
+    }
+    if name == "EnclosingMethod" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let class_index = csr.read_u16::<BigEndian>()?;
+        let method_index = csr.read_u16::<BigEndian>()?;
+        let method = if method_index == 0 {
+            None
+        } else {
+            match constant_pool.get((method_index - 1) as usize) {
+                Some(CPInfo::ConstantNameAndTypeInfo {
+                    name_index,
+                    descriptor_index,
+                    ..
+                }) => Some((
+                    parse_utf8_at(constant_pool, *name_index)?,
+                    parse_utf8_at(constant_pool, *descriptor_index)?,
+                )),
+                _ => {
+                    return Err(format!(
+                        "expected a name and type at constant pool index {method_index}"
+                    )
+                    .into())
+                }
+            }
+        };
+
+        return Ok(Attribute::EnclosingMethod(EnclosingMethod {
+            class: parse_class_name_at(constant_pool, class_index)?,
+            method,
+        }));
+    }
+    if name == "NestHost" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let host_class_index = csr.read_u16::<BigEndian>()?;
+
+        return Ok(Attribute::NestHost(parse_class_name_at(
+            constant_pool,
+            host_class_index,
+        )?));
+    }
+    if name == "NestMembers" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let number_of_classes = csr.read_u16::<BigEndian>()?;
+        let mut classes = vec![];
+        for _ in 0..number_of_classes {
+            classes.push(parse_class_name_at(
+                constant_pool,
+                csr.read_u16::<BigEndian>()?,
+            )?);
+        }
+
+        return Ok(Attribute::NestMembers(classes));
+    }
+    if name == "Record" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let components_count = csr.read_u16::<BigEndian>()?;
+        let mut components = vec![];
+        for _ in 0..components_count {
+            let name_index = csr.read_u16::<BigEndian>()?;
+            let descriptor_index = csr.read_u16::<BigEndian>()?;
+            let mut attributes = vec![];
+            let attributes_count = csr.read_u16::<BigEndian>()?;
+            for _ in 0..attributes_count {
+                let attribute_name_index = csr.read_u16::<BigEndian>()?;
+                let attribute_length = csr.read_u32::<BigEndian>()?;
+                let mut info = vec![];
+                (&mut csr)
+                    .take(attribute_length.into())
+                    .read_to_end(&mut info)?;
+                attributes.push(parse_attribute(
+                    &AttributeInfo {
+                        attribute_name_index,
+                        attribute_length,
+                        info,
+                    },
+                    constant_pool,
+                )?);
+            }
+            components.push(RecordComponent {
+                name: parse_utf8_at(constant_pool, name_index)?,
+                descriptor: parse_utf8_at(constant_pool, descriptor_index)?,
+                attributes,
+            });
+        }
+
+        return Ok(Attribute::Record(components));
+    }
+    if name == "PermittedSubclasses" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let number_of_classes = csr.read_u16::<BigEndian>()?;
+        let mut classes = vec![];
+        for _ in 0..number_of_classes {
+            classes.push(parse_class_name_at(
+                constant_pool,
+                csr.read_u16::<BigEndian>()?,
+            )?);
+        }
+
+        return Ok(Attribute::PermittedSubclasses(classes));
+    }
+    if name == "ConstantValue" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+
+        return Ok(Attribute::ConstantValue(csr.read_u16::<BigEndian>()?));
+    }
+    if name == "SourceFile" {
+        let mut csr = Cursor::new(attribute_info.info.to_owned());
+        let sourcefile_index = csr.read_u16::<BigEndian>()?;
+
+        return Ok(Attribute::SourceFile(parse_utf8_at(
+            constant_pool,
+            sourcefile_index,
+        )?));
+    }
+    Ok(Attribute::Unknown {
+        name,
+        info: attribute_info.info.to_owned(),
+    })
 }
 
-#[derive(Debug, Clone, PartialEq)]
+fn parse_verification_type(csr: &mut Cursor<Vec<u8>>) -> Result<VerificationType, Box<dyn Error>> {
+    let tag = csr.read_u8()?;
+    match tag {
+        0 => Ok(VerificationType::Top),
+        1 => Ok(VerificationType::Integer),
+        2 => Ok(VerificationType::Float),
+        3 => Ok(VerificationType::Double),
+        4 => Ok(VerificationType::Long),
+        5 => Ok(VerificationType::Null),
+        6 => Ok(VerificationType::UninitializedThis),
+        7 => Ok(VerificationType::Object(csr.read_u16::<BigEndian>()?)),
+        8 => Ok(VerificationType::Uninitialized(
+            csr.read_u16::<BigEndian>()?,
+        )),
+        _ => Err(format!("invalid verification type tag {tag}").into()),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MethodDescriptor {
     pub parameter_descriptors: Vec<FieldType>,
     pub return_descriptor: ReturnDescriptor,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ReturnDescriptor {
     FieldType(FieldType),
     VoidDescriptor,
 }
 
+impl MethodDescriptor {
+    // the descriptor as it appears in a class file, e.g. (ILjava/lang/String;)V
+    pub fn descriptor(&self) -> String {
+        let mut descriptor = "(".to_owned();
+        for parameter_descriptor in self.parameter_descriptors.iter() {
+            descriptor.push_str(&parameter_descriptor.descriptor());
+        }
+        descriptor.push(')');
+        match &self.return_descriptor {
+            ReturnDescriptor::FieldType(field_type) => {
+                descriptor.push_str(&field_type.descriptor())
+            }
+            ReturnDescriptor::VoidDescriptor => descriptor.push('V'),
+        }
+        descriptor
+    }
+}
+
 pub fn parse_method_descriptor(
     method_descriptor: String,
 ) -> Result<MethodDescriptor, Box<dyn Error>> {
     let mut chars = method_descriptor.chars();
     // FIXME: assert that first char is '('
-    let open = chars.next();
+    chars.next();
 
     let mut parameter_descriptors = vec![];
     while chars.to_owned().next().unwrap() != ')' {
@@ -593,7 +1676,7 @@ pub fn parse_method_descriptor(
         parameter_descriptors.push(field_type);
     }
 
-    let close = chars.next();
+    chars.next();
 
     // parse_return_descriptor
     let return_descriptor = if chars.to_owned().next().unwrap() == 'V' {
@@ -616,6 +1699,82 @@ pub struct Method {
     pub attributes: Vec<Attribute>,
 }
 
+impl Method {
+    fn code_attributes(&self) -> impl Iterator<Item = &Attribute> {
+        self.attributes
+            .iter()
+            .filter_map(|attribute| match attribute {
+                Attribute::Code { attributes, .. } => Some(attributes),
+                _ => None,
+            })
+            .flatten()
+    }
+
+    // the generic parameter, return and exception types, if the method has a Signature attribute
+    pub fn signature(&self) -> Result<Option<MethodSignature>, Box<dyn Error>> {
+        match self
+            .attributes
+            .iter()
+            .find_map(|attribute| attribute.as_signature())
+        {
+            Some(signature) => Ok(Some(parse_method_signature(signature)?)),
+            None => Ok(None),
+        }
+    }
+
+    // the source line of the instruction at pc, if the class was compiled with line numbers
+    // the bytecode of the method, None for abstract and native methods
+    pub fn code(&self) -> Option<&Vec<u8>> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Code { bytes, .. } => Some(bytes),
+                _ => None,
+            })
+    }
+
+    // the handlers of the code of the method, in the order they are tried in
+    pub fn exception_table(&self) -> Option<&Vec<ExceptionTableItem>> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                Attribute::Code {
+                    exception_table, ..
+                } => Some(exception_table),
+                _ => None,
+            })
+    }
+
+    pub fn line_number_table(&self) -> impl Iterator<Item = &LineNumberTableEntry> {
+        self.code_attributes()
+            .filter_map(|attribute| attribute.as_line_number_table())
+            .flatten()
+    }
+
+    pub fn local_variable_table(&self) -> impl Iterator<Item = &LocalVariableTableEntry> {
+        self.code_attributes()
+            .filter_map(|attribute| attribute.as_local_variable_table())
+            .flatten()
+    }
+
+    pub fn line_number(&self, pc: usize) -> Option<u16> {
+        self.line_number_table()
+            .filter(|entry| entry.start_pc <= pc)
+            .max_by_key(|entry| entry.start_pc)
+            .map(|entry| entry.line_number)
+    }
+
+    // the name of the local variable at index while executing pc, if the class was compiled with
+    // local variable names
+    pub fn local_variable_name(&self, index: usize, pc: usize) -> Option<&String> {
+        self.local_variable_table()
+            .find(|entry| {
+                entry.index == index && entry.start_pc <= pc && pc < entry.start_pc + entry.length
+            })
+            .map(|entry| &entry.name)
+    }
+}
+
 fn parse_method(
     field_info: &MethodInfo,
     constant_pool: &Vec<CPInfo>,
@@ -624,12 +1783,12 @@ fn parse_method(
     let name_info = constant_pool
         .get((field_info.name_index - 1) as usize)
         .ok_or("failed to get name")?;
-    let name = parse_utf8_info(name_info);
+    let name = parse_utf8_info(name_info)?;
     // println!("name: {name}");
     let descriptor_info = constant_pool
         .get((field_info.descriptor_index - 1) as usize)
         .expect("descriptor to be present");
-    let descriptor_text = parse_utf8_info(descriptor_info);
+    let descriptor_text = parse_utf8_info(descriptor_info)?;
     let descriptor = parse_method_descriptor(descriptor_text)?;
 
     // println!("descriptor: {descriptor:?}");
@@ -650,16 +1809,90 @@ fn parse_method(
 
 #[derive(Debug)]
 pub struct Class {
+    pub minor_version: u16,
+    pub major_version: u16,
     pub access: ClassAccess,
     pub constant_pool: Vec<Constant>,
     pub this_class: ClassInfo,
     pub super_class: Option<ClassInfo>,
     pub interfaces: Vec<ClassInfo>,
     pub fields: Vec<Field>,
-    pub methods: Vec<Method>,
+    // shared with the frames running them
+    pub methods: Vec<Arc<Method>>,
     pub attributes: Vec<Attribute>,
 }
 
+impl Class {
+    pub fn source_file(&self) -> Option<&String> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| attribute.as_source_file())
+    }
+
+    pub fn inner_classes(&self) -> Option<&Vec<InnerClass>> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| attribute.as_inner_classes())
+    }
+
+    pub fn enclosing_method(&self) -> Option<&EnclosingMethod> {
+        self.attributes
+            .iter()
+            .find_map(|attribute| attribute.as_enclosing_method())
+    }
+
+    // the class claimed to be the host of this class' nest, see GlobalMemory::nest_host
+    pub fn nest_host(&self) -> Option<&String> {
+        // attributes are only recognized from the class file version they were introduced in
+        if self.major_version < 55 {
+            return None;
+        }
+        self.attributes
+            .iter()
+            .find_map(|attribute| attribute.as_nest_host())
+    }
+
+    pub fn nest_members(&self) -> Option<&Vec<String>> {
+        if self.major_version < 55 {
+            return None;
+        }
+        self.attributes
+            .iter()
+            .find_map(|attribute| attribute.as_nest_members())
+    }
+
+    pub fn record_components(&self) -> Option<&Vec<RecordComponent>> {
+        if self.major_version < 60 {
+            return None;
+        }
+        self.attributes
+            .iter()
+            .find_map(|attribute| attribute.as_record())
+    }
+
+    // the classes allowed to directly extend or implement this sealed class
+    pub fn permitted_subclasses(&self) -> Option<&Vec<String>> {
+        if self.major_version < 61 {
+            return None;
+        }
+        self.attributes
+            .iter()
+            .find_map(|attribute| attribute.as_permitted_subclasses())
+    }
+
+    // the type parameters and generic super types, if the class has a Signature attribute
+    pub fn signature(&self) -> Result<Option<ClassSignature>, Box<dyn Error>> {
+        match self
+            .attributes
+            .iter()
+            .find_map(|attribute| attribute.as_signature())
+        {
+            Some(signature) => Ok(Some(parse_class_signature(signature)?)),
+            None => Ok(None),
+        }
+    }
+}
+
 pub fn parse(class_file: DeserializedClassFile) -> Result<Class, Box<dyn Error>> {
     // println!("access_flags: 0x{:04x}", class_file.access_flags);
 
@@ -678,7 +1911,7 @@ pub fn parse(class_file: DeserializedClassFile) -> Result<Class, Box<dyn Error>>
     .to_owned();
     // println!("this_class: {this_class:?}");
 
-    let super_class = if (class_file.super_class == 0) {
+    let super_class = if class_file.super_class == 0 {
         // if super_class is 0, this is most likely java.lang.Object
         None
     } else {
@@ -717,22 +1950,26 @@ pub fn parse(class_file: DeserializedClassFile) -> Result<Class, Box<dyn Error>>
     let mut methods = vec![];
     for method_info in class_file.methods.iter() {
         let method = parse_method(method_info, &class_file.constant_pool)?;
-        methods.push(method);
+        methods.push(Arc::new(method));
     }
 
+    let mut attributes = vec![];
     for attribute_info in class_file.attributes.iter() {
-        parse_attribute(&attribute_info, &class_file.constant_pool)?;
+        attributes.push(parse_attribute(attribute_info, &class_file.constant_pool)?);
     }
 
     for i in 0..constant_pool.len() {
-        if matches!(constant_pool[i], Constant::Placeholder) {
-            let e =
-                parse_or_get_constant(&mut constant_pool, &class_file.constant_pool, i as u16 + 1)?;
+        if matches!(class_file.constant_pool[i], CPInfo::Unusable) {
+            constant_pool[i] = Constant::Unusable;
+        } else if matches!(constant_pool[i], Constant::Placeholder) {
+            parse_or_get_constant(&mut constant_pool, &class_file.constant_pool, i as u16 + 1)?;
         }
     }
     // println!("constants: {:?}", constant_pool);
 
     let class = Class {
+        minor_version: class_file.minor_version,
+        major_version: class_file.major_version,
         access,
         constant_pool,
         this_class,
@@ -740,10 +1977,45 @@ pub fn parse(class_file: DeserializedClassFile) -> Result<Class, Box<dyn Error>>
         interfaces,
         fields,
         methods,
-        attributes: vec![],
+        attributes,
     };
 
     // println!("class {:?}", class);
 
     Ok(class)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_null_and_surrogates() {
+        // the null char, a pair of surrogates for U+1F600 and an unpaired one
+        let bytes = [
+            0x61, 0xc0, 0x80, 0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80, 0xed, 0xa0, 0x80,
+        ];
+        let chars = decode_modified_utf8(&bytes).unwrap();
+        assert_eq!(chars, [0x61, 0x0000, 0xd83d, 0xde00, 0xd800]);
+        assert_eq!(encode_modified_utf8(&chars), bytes);
+    }
+
+    #[test]
+    fn rejects_malformed_modified_utf8() {
+        // truncated
+        assert!(decode_modified_utf8(&[0xe2, 0x82]).is_err());
+        // not a continuation byte
+        assert!(decode_modified_utf8(&[0xc3, 0x41]).is_err());
+        // a zero byte and the four byte form of standard UTF-8
+        assert!(decode_modified_utf8(&[0x00]).is_err());
+        assert!(decode_modified_utf8(&[0xf0, 0x9f, 0x98, 0x80]).is_err());
+        // longer than needed
+        assert!(decode_modified_utf8(&[0xc1, 0x81]).is_err());
+    }
+
+    #[test]
+    fn names_must_not_have_unpaired_surrogates() {
+        assert_eq!(utf8_text(&[0x61, 0x62]).unwrap(), "ab");
+        assert!(utf8_text(&[0xd800]).is_err());
+    }
+}
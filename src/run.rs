@@ -1,184 +1,696 @@
 use std::{
-    arch::global_asm,
-    borrow::{Borrow, BorrowMut},
-    cell::RefCell,
-    collections::HashMap,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
     error::Error,
     fmt::Debug,
-    io::{stdout, Cursor, Stderr, Stdout, Write},
+    fs::{File, OpenOptions},
+    io::{stderr, stdin, stdout, Cursor, ErrorKind, Read, Seek, Write},
     ops::Deref,
-    path::Path,
-    rc::{Rc, Weak},
-    time::SystemTime,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{fence, AtomicBool, AtomicPtr, Ordering},
+        Arc, OnceLock,
+    },
+    time::{Instant, SystemTime},
     vec,
 };
 
 use byteorder::{BigEndian, ReadBytesExt};
 
 use crate::{
-    deserialize::deserialize_class_file,
+    deserialize::{deserialize_class_bytes, deserialize_class_file},
+    instruction::{DecodedCode, Operands},
     parse::{
         parse, parse_field_descriptor, parse_method_descriptor, Attribute, Class as ParsedClass,
-        ClassInfo, Constant, ExceptionTableItem, Field, FieldType, Method, MethodDescriptor,
+        ClassAccess, ClassInfo, Constant, Field, FieldAccess, FieldDescriptor, FieldType, Method,
+        MethodAccess, MethodDescriptor, RefKind,
     },
+    verify::{reference_slots, verify_class, ReferenceSlots, VerifyMode},
 };
 
+mod arithmetic;
+mod conversions;
+mod debug;
+mod gc;
+mod histogram;
+mod hprof;
+mod jdwp;
+mod jni;
+mod logging;
+mod native_memory;
+mod profile;
+mod slots;
+mod symbols;
+mod threads;
+mod trace;
+
+pub use gc::{Collector, GcStatistics};
+use logging::log;
+pub use logging::{parse_log_spec, LogLevel, LogTarget};
+use native_memory::NativeMemory;
+use symbols::{ClassId, Symbols};
+pub use threads::ThreadMode;
+use threads::{Monitor, Scheduler};
+
+// the number of frames a thread may have on its stack, if not configured using -Xss
+pub const DEFAULT_MAX_STACK_DEPTH: usize = 1024;
+
+// the (hardcoded) class path classes are loaded from
+pub const CLASS_PATH: [&str; 2] = [
+    ".",
+    "../../openjdk/jdk11u/build/linux-x86_64-normal-server-release/jdk/modules/java.base",
+];
+
+// the class file of a class and the class path entry it was found in. Later entries take
+// precedence over earlier ones
+pub fn find_class_file(name: &str) -> Option<(PathBuf, &'static str)> {
+    CLASS_PATH.iter().rev().find_map(|directory| {
+        let path = Path::new(directory).join(name.to_owned() + ".class");
+        if path.exists() {
+            Some((path, *directory))
+        } else {
+            None
+        }
+    })
+}
+
 #[derive(Debug)]
 struct ThreadMemory {
     jvm_stack: Vec<Frame>,
+    // once jvm_stack grows beyond this, a java/lang/StackOverflowError is thrown
+    max_stack_depth: usize,
 }
 
 #[derive(Debug)]
 struct Frame {
     local_variables: Vec<u32>,
     operand_stack: Vec<u32>,
-    constant_pool: Weak<RuntimeConstantPool>,
-    code_bytes: Option<Vec<u8>>,
-    exception_table: Option<Vec<ExceptionTableItem>>,
+    // the operand_stack may never grow beyond this, see the Code attribute
+    max_stack: usize,
+    constant_pool: Arc<RuntimeConstantPool>,
+    // None for native methods
+    code: Option<Arc<DecodedCode>>,
     instruction_counter: usize,
-    class_name: String,
-    method: Option<Method>,
+    // the class declaring the method, see class_name
+    class_id: ClassId,
+    method: Option<Arc<Method>>,
     running_native: bool,
+    // the object whose monitor the synchronized method of this frame entered
+    monitor: Option<HeapRef>,
+    // whether the method was traced as entered, see trace
+    entered: bool,
+    // the node of the call tree of the profiler the instructions of this frame count towards
+    profile_node: Option<usize>,
+    // the index of method among the methods of its class, see InstanceKlass::method_counters
+    method_index: usize,
+    // added to the counters of the method once the frame is popped
+    instructions_run: u64,
 }
 
 impl Frame {
     fn new(
         global_memory: &mut GlobalMemory,
-        class_name: String,
+        class_id: ClassId,
         method_name: String,
         type_descriptor: MethodDescriptor,
     ) -> Result<Frame, Box<dyn Error>> {
-        let mut class_name = class_name;
-        let mut current_class;
-        let mut current_method = None;
+        let classes = &global_memory.method_area.classes;
+        let mut class_id = class_id;
         // attempt to resolve methods - we should probably somehow precompute this?
-        current_class = Some(
-            global_memory
-                .method_area
-                .classes
-                .get(&class_name)
-                .ok_or(format!("Class not found {} :(", class_name))?,
-        );
-        if current_class.unwrap().as_array_klass().is_some() {
-            class_name = "java/lang/Object".to_owned();
+        let class = classes
+            .by_id(class_id)
+            .ok_or_else(|| format!("Class not found {} :(", classes.name(class_id)))?;
+        if class.as_array_klass().is_some() {
+            class_id = classes
+                .lookup_id("java/lang/Object")
+                .ok_or("Class not found java/lang/Object :(")?;
         }
+        let searched_class_id = class_id;
 
-        while current_method.is_none() {
-            current_class = Some(
-                global_memory
-                    .method_area
-                    .classes
-                    .get(&class_name)
-                    .ok_or(format!("Class not found {} :(", class_name))?,
-            );
-            // println!("current_class: {}", current_class.unwrap().get_name());
-            let parsed_class = current_class
-                .unwrap()
+        // the super classes come first, see JVMS §5.4.3.3
+        let mut found_in_class_chain = false;
+        loop {
+            let parsed_class = classes
+                .by_id(class_id)
+                .ok_or_else(|| format!("Class not found {} :(", classes.name(class_id)))?
                 .as_instance_klass()
                 .unwrap()
                 .parsed_class
                 .as_ref()
                 .ok_or("no parsed_class")?;
-            current_method = parsed_class
+            if parsed_class
                 .methods
                 .iter()
-                .filter(|method| method.name == method_name && method.descriptor == type_descriptor)
-                .next();
-
-            if current_method.is_none() {
-                class_name = parsed_class
-                    .as_ref()
-                    .super_class
-                    .as_ref()
-                    .ok_or(format!(
-                        "method {method_name} {type_descriptor:?} not found"
-                    ))?
-                    .name
-                    .to_owned();
+                .any(|method| method.name == method_name && method.descriptor == type_descriptor)
+            {
+                found_in_class_chain = true;
+                break;
+            }
+            match parsed_class.super_class.as_ref() {
+                Some(super_class) => {
+                    class_id = classes
+                        .lookup_id(&super_class.name)
+                        .ok_or(format!("Class not found {} :(", super_class.name))?
+                }
+                None => break,
             }
         }
+        // otherwise one of the superinterfaces has to provide a default method
+        if !found_in_class_chain {
+            let class_name = global_memory.method_area.find_maximally_specific_method(
+                global_memory.method_area.classes.name(searched_class_id),
+                &method_name,
+                &type_descriptor,
+            )?;
+            // interfaces are loaded together with their implementing class, but not linked
+            global_memory.ensure_linked(&class_name)?;
+            class_id = global_memory.method_area.classes.id(&class_name);
+        }
+
+        let class_name = global_memory.method_area.classes.name(class_id);
+        let current_class = global_memory
+            .method_area
+            .classes
+            .by_id(class_id)
+            .ok_or(format!("Class not found {} :(", class_name))?;
+        let current_method = current_class
+            .as_instance_klass()
+            .unwrap()
+            .parsed_class
+            .as_ref()
+            .ok_or("no parsed_class")?
+            .methods
+            .iter()
+            .enumerate()
+            .find(|(_, method)| method.name == method_name && method.descriptor == type_descriptor);
+        if current_method.is_some_and(|(_, method)| method.access.r#abstract) {
+            return Err(format!("AbstractMethodError: {class_name}.{method_name}").into());
+        }
 
-        let current_method = current_method.unwrap();
-        let mut code_bytes = None;
-        let mut exception_table = None;
+        let (method_index, current_method) = current_method.ok_or("method not found")?;
+        let mut code = None;
+        // native methods only need room for their arguments, including this
+        let mut max_stack = 0;
+        let mut max_locals = 1 + type_descriptor
+            .parameter_descriptors
+            .iter()
+            .map(|field_type| field_type.width())
+            .sum::<usize>();
         if !current_method.access.native {
-            // println!("current_class: {current_class:?}, current_method: {current_method:?}");
-            let code = current_method
+            let code_attribute = current_method
                 .attributes
                 .iter()
-                .filter(|attr| matches!(attr, Attribute::Code { .. }))
-                .next()
-                .ok_or("no code 1 :(")?;
-            // println!("current_method: {current_method:?}");
-            let code = code.as_code().ok_or("no code 2 :(")?.to_owned();
-            exception_table = Some(code.3);
-            code_bytes = Some(code.0);
+                .find_map(|attribute| match attribute {
+                    Attribute::Code {
+                        max_stack,
+                        max_locals,
+                        ..
+                    } => Some((*max_stack, *max_locals)),
+                    _ => None,
+                })
+                .ok_or("no code :(")?;
+            max_stack = code_attribute.0;
+            max_locals = code_attribute.1;
+            // decoded when the class was linked
+            code = current_class
+                .as_instance_klass()
+                .and_then(|klass| klass.decoded_code.get(method_index))
+                .cloned()
+                .flatten();
+            if code.is_none() {
+                return Err(format!("no decoded code for {class_name}.{method_name}").into());
+            }
         }
 
         let current_frame = Frame {
-            constant_pool: Rc::downgrade(
-                &current_class
-                    .unwrap()
-                    .as_instance_klass()
-                    .ok_or("not an InstanceKlass")?
-                    .constant_pool
-                    .to_owned()
-                    .ok_or("couldnt find stuff")?,
-            ),
-            local_variables: vec![0; 20],
-            operand_stack: vec![],
-            code_bytes,
-            exception_table,
+            constant_pool: current_class
+                .as_instance_klass()
+                .ok_or("not an InstanceKlass")?
+                .constant_pool
+                .to_owned()
+                .ok_or("couldnt find stuff")?,
+            local_variables: vec![0; max_locals],
+            operand_stack: Vec::with_capacity(max_stack),
+            max_stack,
+            code,
             instruction_counter: 0,
-            class_name: class_name.to_owned(),
-            method: Some(current_method.to_owned()),
+            class_id,
+            method: Some(Arc::clone(current_method)),
             running_native: false,
+            monitor: None,
+            entered: false,
+            profile_node: None,
+            method_index,
+            instructions_run: 0,
+        };
+        log!(
+            Interp,
+            Debug,
+            "new frame for {class_name}.{}{}",
+            current_method.name,
+            current_method.descriptor.descriptor()
+        );
+        if let Some(counters) = global_memory
+            .method_area
+            .classes
+            .by_id_mut(class_id)
+            .and_then(|class| class.as_mut_instance_klass())
+            .and_then(|klass| klass.method_counters.get_mut(method_index))
+        {
+            counters.calls += 1;
+        }
+        Ok(current_frame)
+    }
+
+    // like new, for where the vm itself invokes a method of a class it knows by name
+    fn for_class_name(
+        global_memory: &mut GlobalMemory,
+        class_name: &str,
+        method_name: String,
+        type_descriptor: MethodDescriptor,
+    ) -> Result<Frame, Box<dyn Error>> {
+        let class_id = global_memory.method_area.classes.id(class_name);
+        Frame::new(global_memory, class_id, method_name, type_descriptor)
+    }
+
+    // the name of the class declaring the method, for messages and natives
+    fn class_name(&self) -> &str {
+        match &self.constant_pool.class {
+            Some(class) => &class.this_class.name,
+            None => "stub",
+        }
+    }
+
+    // describes the method of this frame for error messages
+    fn method_display_name(&self) -> String {
+        match &self.method {
+            Some(method) => format!("{}.{}", self.class_name(), method.name),
+            None => self.class_name().to_owned(),
+        }
+    }
+
+    // the SourceFile attribute of the class declaring the method
+    fn source_file(&self) -> Option<String> {
+        self.constant_pool.class.as_ref()?.source_file().cloned()
+    }
+
+    // the source line of the current instruction, see the LineNumberTable attribute
+    fn line_number(&self) -> Option<u16> {
+        self.method.as_ref()?.line_number(self.instruction_counter)
+    }
+
+    // describes the current position in the source for traces, like StackTraceElement.toString
+    fn location(&self) -> String {
+        let source = if self
+            .method
+            .as_ref()
+            .is_some_and(|method| method.access.native)
+        {
+            "Native Method".to_owned()
+        } else {
+            match (self.source_file(), self.line_number()) {
+                (Some(source_file), Some(line_number)) => format!("{source_file}:{line_number}"),
+                (Some(source_file), None) => source_file,
+                (None, _) => "Unknown Source".to_owned(),
+            }
         };
-        // println!(
-        // "new frame for method {}.{}({:?}): {:?}",
-        // class_name, method_name, type_descriptor, current_frame
-        // );
-        return Ok(current_frame);
+        format!(
+            "{}({source}) at bytecode index {}",
+            self.method_display_name(),
+            self.instruction_counter
+        )
+    }
+
+    // describes a local variable for error messages, with its name from the LocalVariableTable
+    // attribute if there is one
+    fn local_variable_display_name(&self, index: usize) -> String {
+        let name = self
+            .method
+            .as_ref()
+            .and_then(|method| method.local_variable_name(index, self.instruction_counter));
+        match name {
+            Some(name) => format!("{index} ({name})"),
+            None => format!("{index}"),
+        }
+    }
+
+    fn get_local_variable(&self, index: usize) -> Result<u32, Box<dyn Error>> {
+        match self.local_variables.get(index) {
+            Some(value) => Ok(*value),
+            None => Err(format!(
+                "VerifyError: local variable {} exceeds max_locals of {}",
+                self.local_variable_display_name(index),
+                self.location()
+            )
+            .into()),
+        }
+    }
+
+    fn set_local_variable(&mut self, index: usize, value: u32) -> Result<(), Box<dyn Error>> {
+        let local_variable_display_name = self.local_variable_display_name(index);
+        let location = self.location();
+        match self.local_variables.get_mut(index) {
+            Some(local_variable) => {
+                *local_variable = value;
+                Ok(())
+            }
+            None => Err(format!(
+                "VerifyError: local variable {local_variable_display_name} exceeds max_locals of {location}"
+            )
+            .into()),
+        }
+    }
+
+    // ints take up a single slot, their bits reinterpreted as an u32
+    fn push_int(&mut self, value: i32) {
+        self.operand_stack.push(value as u32);
+    }
+
+    fn pop_int(&mut self) -> Result<i32, Box<dyn Error>> {
+        let value = self
+            .operand_stack
+            .pop()
+            .ok_or("no item on the operand_stack")?;
+        Ok(value as i32)
+    }
+
+    // longs and doubles are split into two slots on the operand_stack, see slots
+    fn push_long(&mut self, value: i64) {
+        self.operand_stack.extend(slots::long_to_slots(value));
+    }
+
+    fn pop_long(&mut self) -> Result<i64, Box<dyn Error>> {
+        let low = self
+            .operand_stack
+            .pop()
+            .ok_or("no item on the operand_stack")?;
+        let high = self
+            .operand_stack
+            .pop()
+            .ok_or("no item on the operand_stack")?;
+        Ok(slots::long_from_slots(high, low))
+    }
+
+    fn push_double(&mut self, value: f64) {
+        self.operand_stack.extend(slots::double_to_slots(value));
+    }
+
+    fn pop_double(&mut self) -> Result<f64, Box<dyn Error>> {
+        let low = self
+            .operand_stack
+            .pop()
+            .ok_or("no item on the operand_stack")?;
+        let high = self
+            .operand_stack
+            .pop()
+            .ok_or("no item on the operand_stack")?;
+        Ok(slots::double_from_slots(high, low))
+    }
+
+    // pops the arguments of a method invocation off the operand_stack, in the order they are
+    // going to be stored in the local variables of the invoked method. Longs and doubles take
+    // up two slots, just like in the local variables.
+    fn pop_arguments(
+        &mut self,
+        type_descriptor: &MethodDescriptor,
+    ) -> Result<Vec<u32>, Box<dyn Error>> {
+        let width = type_descriptor
+            .parameter_descriptors
+            .iter()
+            .map(|field_type| field_type.width())
+            .sum::<usize>();
+        if width > self.operand_stack.len() {
+            return Err("arguments are not on the stack".into());
+        }
+        Ok(self
+            .operand_stack
+            .split_off(self.operand_stack.len() - width))
     }
 
     fn new_stub() -> Result<Frame, Box<dyn Error>> {
         let current_frame = Frame {
-            constant_pool: Rc::downgrade(&Rc::new(RuntimeConstantPool { pool: vec![] })),
-            local_variables: vec![0; 20],
+            constant_pool: Arc::new(RuntimeConstantPool::new(None)),
+            local_variables: vec![],
             operand_stack: vec![],
-            code_bytes: None,
-            exception_table: None,
+            max_stack: 0,
+            code: None,
             instruction_counter: 0,
-            class_name: "stub".to_owned(),
+            class_id: ClassId::STUB,
             method: None,
             running_native: true,
+            monitor: None,
+            entered: false,
+            profile_node: None,
+            method_index: 0,
+            instructions_run: 0,
         };
-        return Ok(current_frame);
+        Ok(current_frame)
     }
 }
 
-#[derive(Debug)]
+// the default one is empty, it is only what is left in the place of the real one while another
+// thread holds the vm lock, see threads
+#[derive(Debug, Default)]
 struct GlobalMemory {
     heap: Heap,
     method_area: MethodArea,
+    system_properties: HashMap<String, String>,
+    // files opened from java land, keyed by the fd stored in their java/io/FileDescriptor
+    open_files: HashMap<i32, File>,
+    next_file_descriptor: i32,
+    // what Unsafe.allocateMemory hands out, see native_memory
+    native_memory: NativeMemory,
+    // set once java land requested the vm to halt, see java/lang/Shutdown.halt0
+    exit_status: Option<i32>,
+    // set while java/lang/Shutdown runs the shutdown hooks, see thread_start0
+    shutting_down: bool,
+    // whether run_main handles ^C, and the flag its handler sets, see install_interrupt_handler
+    handle_interrupt: bool,
+    interrupted: Arc<AtomicBool>,
+    // classes spun for lambdas and method references, keyed by their class name
+    lambda_classes: HashMap<String, LambdaClass>,
+    natives: HashMap<NativeKey, NativeMethod>,
+    // the handles of the shared libraries loaded by System.loadLibrary, see jni
+    native_libraries: Vec<usize>,
+    // string literals and the strings passed to String.intern, keyed by their chars
+    interned_strings: HashMap<Vec<u16>, HeapRef>,
+    // the objects JNI natives hold on to with NewGlobalRef, which are roots for the gc
+    jni_global_references: Vec<HeapRef>,
+    // whether threads run on host threads of their own, see threads
+    thread_mode: ThreadMode,
+    // the lock only the thread running java code holds, see threads
+    scheduler: Arc<Scheduler>,
+    // the id of the thread holding it
+    current_thread: u64,
+    // the thread objects of the main thread and the threads started from java land that haven't
+    // ended yet, keyed by their id
+    threads: HashMap<u64, HeapRef>,
+    // the ids of the threads in threads that were daemons when they started, the vm doesn't wait
+    // for them to end
+    daemon_threads: HashSet<u64>,
+    // the threads that handed the lock on, with their stack if the gc can use it, see threads
+    suspended_threads: HashMap<u64, Option<Thread>>,
+    // the green threads that aren't running, see threads
+    green_threads: VecDeque<Box<Thread>>,
+    // the host threads of the threads started from java land, which are joined before the vm is
+    // dropped, see threads::stop
+    host_threads: Vec<std::thread::JoinHandle<()>>,
+    // the monitors entered or waited on, keyed by their object
+    monitors: HashMap<HeapRef, Monitor>,
+    // the id of the thread running the <clinit> of a class, see init_class
+    initializing_classes: HashMap<String, u64>,
+    // the max_stack_depth of newly created threads
+    max_stack_depth: usize,
+    // which classes are verified before they are linked
+    verify_mode: VerifyMode,
+    collector: Collector,
+    // whether every collection is logged, see gc::collect
+    verbose_gc: bool,
+    gc_statistics: GcStatistics,
+    // where the heap is dumped to once an OutOfMemoryError is thrown, see hprof
+    heap_dump_on_out_of_memory: Option<String>,
+    // the references the gc cleared that the reference handler hasn't picked up yet, linked by
+    // their discovered field, see gc
+    reference_pending_list: HeapRef,
+    // the threads started to process references, see thread_start0
+    reference_handler: Option<u32>,
+    cleaners: Vec<u32>,
+    // the java_clone of the main thread, see initialize_java_lang_classes
+    main_thread_object: Option<HeapRef>,
+    // the port a debugger is waited for on before main runs, and the debugger once it attached,
+    // see jdwp
+    debug_port: Option<u16>,
+    debugger: Option<Box<jdwp::Debugger>>,
+    // whether run_main stops at the prompt of matebabe debug, and the prompt once it did, see
+    // debug
+    debug_prompt_enabled: bool,
+    debug_prompt: Option<Box<debug::DebugPrompt>>,
+    // the file the execution is traced to and the classes it is traced for, and the trace once
+    // the vm is initialized, see trace
+    trace_file: Option<String>,
+    trace_filters: Vec<String>,
+    trace: Option<Box<trace::Trace>>,
+    profiler: Option<Box<profile::Profiler>>,
+    opcode_histogram: Option<Box<histogram::OpcodeHistogram>>,
+    // how many of the methods that ran the most instructions are printed when the vm exits
+    hot_methods: Option<usize>,
 }
 
 impl GlobalMemory {
-    // loads, links and inits a class if required
+    // the JavaValue counterparts of java_string_from_string and friends, for embedders
+    fn new_string_value(&mut self, value: &str) -> Result<JavaValue, Box<dyn Error>> {
+        let objectref = java_string_from_string(self, value.to_owned())?;
+        Ok(JavaValue::Reference(objectref))
+    }
+
+    fn string_value(&self, value: &JavaValue) -> Result<String, Box<dyn Error>> {
+        let objectref = match value {
+            JavaValue::Reference(HeapRef::NULL) => return Err("string is null".into()),
+            JavaValue::Reference(objectref) => *objectref,
+            _ => return Err(format!("{value:?} is not a string").into()),
+        };
+        let descriptor = self
+            .heap
+            .field_descriptor(objectref)
+            .ok_or("object not on heap")?;
+        if descriptor != "Ljava/lang/String;" {
+            return Err(format!("{descriptor} is not a string").into());
+        }
+        string_from_java_string(self, objectref)
+    }
+
+    fn new_array_value(
+        &mut self,
+        array_type: &str,
+        elements: &[JavaValue],
+    ) -> Result<JavaValue, Box<dyn Error>> {
+        let component_type = parse_field_descriptor(array_type)?
+            .field_type
+            .as_array()
+            .ok_or(format!("{array_type} is not an array type"))?
+            .clone();
+        let mut data = vec![];
+        for element in elements.iter() {
+            data.extend(element.slots(&component_type)?);
+        }
+        self.ensure_array(array_type.to_owned())?;
+        let arrayref = if primitive_element_size(array_type).is_some() {
+            let bytes = data
+                .chunks(component_type.width())
+                .flat_map(|slots| native_bytes_of_value(&component_type, slots))
+                .collect();
+            self.heap.store_primitive_array(array_type, bytes)
+        } else {
+            self.heap.store(array_type, data)
+        };
+        Ok(JavaValue::Reference(arrayref))
+    }
+
+    fn array_element_values(&self, value: &JavaValue) -> Result<Vec<JavaValue>, Box<dyn Error>> {
+        let arrayref = match value {
+            JavaValue::Reference(HeapRef::NULL) => return Err("array is null".into()),
+            JavaValue::Reference(arrayref) => *arrayref,
+            _ => return Err(format!("{value:?} is not an array").into()),
+        };
+        let heap_item = self.heap.get(arrayref).ok_or("array not on heap")?;
+        let descriptor = self.heap.descriptor(heap_item);
+        let component_type = parse_field_descriptor(descriptor)?
+            .field_type
+            .as_array()
+            .ok_or(format!("{descriptor} is not an array"))?
+            .clone();
+        if primitive_element_size(descriptor).is_none() {
+            return heap_item
+                .data
+                .iter()
+                .map(|slot| JavaValue::from_slots(&component_type, &[*slot]))
+                .collect();
+        }
+        (0..self.heap.array_length(heap_item))
+            .map(|index| {
+                let slots = heap_item
+                    .element_slots(&component_type, index)
+                    .ok_or("array element out of bounds")?;
+                JavaValue::from_slots(&component_type, &slots)
+            })
+            .collect()
+    }
+
+    // the java/lang/Class object of a type
+    fn class_mirror(&mut self, field_type: &FieldType) -> Result<HeapRef, Box<dyn Error>> {
+        let class_name = match field_type {
+            FieldType::ClassInstance(class_name) => {
+                self.ensure_linked(class_name)?;
+                class_name.to_owned()
+            }
+            FieldType::Array(_) => {
+                let array_type = field_type.descriptor();
+                self.ensure_array(array_type.to_owned())?;
+                array_type
+            }
+            primitive => {
+                return self.ensure_primitive_class(
+                    primitive_class_name(primitive).ok_or("not a primitive type")?,
+                )
+            }
+        };
+        Ok(self
+            .method_area
+            .classes
+            .get(&class_name)
+            .ok_or(format!("Class not found {} :(", class_name))?
+            .get_java_clone()
+            .ok_or("class without java clone")?)
+    }
+
+    // creates the class of a primitive type or void if required, returning its mirror
+    fn ensure_primitive_class(&mut self, name: &str) -> Result<HeapRef, Box<dyn Error>> {
+        if let Some(klass) = self.method_area.classes.get(name) {
+            return klass
+                .get_java_clone()
+                .ok_or("class without java clone".into());
+        }
+        let klass = self
+            .method_area
+            .classes
+            .get("java/lang/Class")
+            .ok_or("class not found in method area 1 :(")?;
+        let klass_java_clone = self.heap.allocate_klass(klass);
+        self.method_area.classes.insert(
+            name,
+            Box::new(PrimitiveKlass {
+                name: name.to_owned(),
+                java_clone: Some(klass_java_clone),
+            }),
+        );
+        Ok(klass_java_clone)
+    }
+
+    // loads, links and inits a class if required. Only use this for the instructions and
+    // actions triggering the initialization of a class (JVMS §5.5): new, getstatic, putstatic,
+    // invokestatic, reflection and the main class. Everything else only needs ensure_linked.
     fn ensure_class(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
-        let maybe_class = self.method_area.classes.get(name.into());
-        if maybe_class.is_some() {
-            if maybe_class.unwrap().is_initialized() {
-                return Ok(());
+        if let Some(class) = self.method_area.classes.get(name) {
+            match class.get_state() {
+                ClassState::Initialized => return Ok(()),
+                ClassState::Initializing => return self.wait_for_initialization(name),
+                ClassState::Erroneous => {
+                    return Err(Box::new(InitializationError::Erroneous {
+                        class_name: name.to_owned(),
+                    }))
+                }
+                ClassState::Loaded | ClassState::Linked => {}
             }
-            // loaded, but maybe not linked and/or initialized
-            self.link_class(name.into())?;
-            self.load_class(name.into())?;
         }
 
+        self.ensure_linked(name)?;
+        self.init_class(name.into())?;
+
+        Ok(())
+    }
+    // loads and links a class if required, without running its <clinit>
+    fn ensure_linked(&mut self, name: &str) -> Result<(), Box<dyn Error>> {
         self.load_class(name.into())?;
         self.link_class(name.into())?;
-        self.init_class(name.into())?;
 
         Ok(())
     }
@@ -186,62 +698,81 @@ impl GlobalMemory {
         if self.method_area.classes.contains_key(&name) {
             return Ok(());
         }
-        // Look for class in (hardcoded) classpath
-        let class_path = vec![
-            ".",
-            "../../openjdk/jdk11u/build/linux-x86_64-normal-server-release/jdk/modules/java.base",
-        ];
-
-        // println!("load_class name: {}", name);
-        let mut path = None;
-        for directory in class_path.iter() {
-            let potential_path = Path::new(directory).join(name.to_owned() + ".class");
-            if potential_path.exists() {
-                path = Some(potential_path)
-            }
-        }
-        let spath = path
-            .ok_or("file not found")?
-            .to_str()
-            .ok_or("not a path")?
-            .to_string();
-        // println!("spath: {spath}");
+        let (path, directory) = find_class_file(&name).ok_or("file not found")?;
+        // the classes of the JDK are trusted, unless everything is verified
+        let needs_verification = match self.verify_mode {
+            VerifyMode::None => false,
+            VerifyMode::Remote => directory == ".",
+            VerifyMode::All => true,
+        };
+        let spath = path.to_str().ok_or("not a path")?.to_string();
+        log!(ClassLoad, Info, "loading {name} from {spath}");
 
         let deserialized = deserialize_class_file(spath)?;
 
         let class = parse(deserialized)?;
+        self.define_class(class, needs_verification)?;
+        Ok(())
+    }
 
+    // adds a parsed class to the method area, loading its super class and interfaces first. This
+    // is where classes from class files and those defined from bytes in java land end up.
+    fn define_class(
+        &mut self,
+        class: ParsedClass,
+        needs_verification: bool,
+    ) -> Result<(), Box<dyn Error>> {
         if let Some(ref class) = class.super_class {
-            // println!("found super class {class:?}, loading it!");
             self.load_class(class.name.to_owned())?;
         }
 
         for interface in class.interfaces.iter() {
-            // println!("found interface {class:?}, loading it!");
             self.load_class(interface.name.to_owned())?;
         }
 
+        // sealed classes and interfaces only allow the classes they list (JVMS §5.3.5)
+        for super_type in class.super_class.iter().chain(class.interfaces.iter()) {
+            let parsed_super_type = self.method_area.parsed_class(&super_type.name)?;
+            if let Some(permitted_subclasses) = parsed_super_type.permitted_subclasses() {
+                if !permitted_subclasses.contains(&class.this_class.name) {
+                    return Err(format!(
+                        "IncompatibleClassChangeError: class {} cannot inherit from sealed class {}",
+                        class.this_class.name, super_type.name
+                    )
+                    .into());
+                }
+            }
+        }
+
         // TODO: load interfaces
         let name = class.this_class.name.to_owned();
-        let rc_class = Rc::new(class);
+        let rc_class = Arc::new(class);
+        let descriptor_id = self.heap.descriptors.intern(&format!("L{name};"));
         self.method_area.add_class(
             name.to_owned(),
             InstanceKlass {
                 name,
+                descriptor_id,
                 parsed_class: Some(rc_class.clone()),
                 fields: None,
                 static_fields: None,
+                vtable: None,
+                vtable_indices: None,
                 constant_pool: None,
                 static_field_values: Some(vec![]),
                 java_clone: None,
-                initialized: false,
+                state: ClassState::Loaded,
+                needs_verification,
+                call_sites: HashMap::new(),
+                method_counters: vec![MethodCounters::default(); rc_class.methods.len()],
+                decoded_code: vec![],
+                reference_slots: vec![],
             },
         );
-        return Ok(());
+        Ok(())
     }
 
     fn link_class(&mut self, class_name: String) -> Result<(), Box<dyn Error>> {
-        // println!("linking class {class_name}");
         let klass = self
             .method_area
             .classes
@@ -251,24 +782,42 @@ impl GlobalMemory {
             .ok_or("not an InstanceKlass")?;
 
         if klass.is_linked() {
-            // println!("Class {class_name} already linked, skip linking :^)");
             return Ok(());
         }
+        log!(ClassLoad, Debug, "linking {class_name}");
 
         let class = klass
             .parsed_class
             .as_ref()
             .ok_or("class exists, but we didnt parse it yet")?
             .to_owned();
+        let needs_verification = klass.needs_verification;
+
+        if let Some(super_class) = class.super_class.as_ref() {
+            self.link_class(super_class.name.to_owned())?;
+        }
 
-        if class.super_class.is_some() {
-            self.link_class(class.super_class.as_ref().unwrap().name.to_owned())?;
+        if needs_verification {
+            verify_class(&class)?;
         }
 
-        let mut pool = vec![];
-        for item in class.constant_pool.iter() {
-            pool.push(item.to_owned())
+        let mut decoded_code = vec![];
+        for method in class.methods.iter() {
+            decoded_code.push(match method.code() {
+                Some(code) => Some(Arc::new(DecodedCode::new(code).map_err(|e| {
+                    format!(
+                        "VerifyError: could not decode {class_name}.{}: {e}",
+                        method.name
+                    )
+                })?)),
+                None => None,
+            });
         }
+        let reference_slots = class
+            .methods
+            .iter()
+            .map(|method| reference_slots(&class, method).ok().flatten())
+            .collect();
 
         let klass = self
             .method_area
@@ -313,13 +862,10 @@ impl GlobalMemory {
                     field_values.push(0);
                 }
                 crate::parse::FieldType::Float => {
-                    field_values.push((0.0 as f32).to_bits());
+                    field_values.push(0.0_f32.to_bits());
                 }
                 crate::parse::FieldType::Double => {
-                    let bits = (0.0 as f64).to_bits();
-                    let mut csr = Cursor::new(bits.to_be_bytes());
-                    field_values.push(csr.read_u32::<BigEndian>()?);
-                    field_values.push(csr.read_u32::<BigEndian>()?);
+                    field_values.extend(slots::double_to_slots(0.0));
                 }
             }
         }
@@ -328,7 +874,7 @@ impl GlobalMemory {
         let klass = self
             .method_area
             .classes
-            .get(&"java/lang/Class".to_owned())
+            .get("java/lang/Class")
             .ok_or("class not found in method area 1 :(")?;
         let klass_java_clone = self.heap.allocate_klass(klass);
 
@@ -339,22 +885,27 @@ impl GlobalMemory {
 
         if let Some(klass) = klass {
             let klass = klass.as_mut_instance_klass().ok_or("not an instance")?;
-            klass.constant_pool = Some(Rc::new(RuntimeConstantPool { pool }));
+            klass.constant_pool = Some(Arc::new(RuntimeConstantPool::new(Some(class.to_owned()))));
             klass
                 .static_field_values
                 .as_mut()
                 .unwrap()
                 .append(&mut field_values);
             klass.java_clone = Some(klass_java_clone);
+            klass.decoded_code = decoded_code;
+            klass.reference_slots = reference_slots;
+            klass.state = ClassState::Linked;
         } else {
             return Err("what?".into());
         }
+        if self.debugger.is_some() {
+            jdwp::class_prepared(self, &class.this_class.name)?;
+        }
 
         Ok(())
     }
 
     fn init_class(&mut self, class_name: String) -> Result<(), Box<dyn Error>> {
-        // println!("init class {class_name}");
         let class = self
             .method_area
             .classes
@@ -362,1842 +913,8569 @@ impl GlobalMemory {
             .ok_or("class not found")?
             .as_instance_klass()
             .unwrap();
-        if class.is_initialized() {
-            // println!("Class {class_name} already linked, skip init :^)");
-            return Ok(());
-        }
-        if class.parsed_class.as_ref().unwrap().super_class.is_some() {
-            self.init_class(
-                class
-                    .parsed_class
-                    .as_ref()
-                    .unwrap()
-                    .super_class
-                    .as_ref()
-                    .unwrap()
-                    .name
-                    .to_owned(),
-            )?;
+        match class.state {
+            ClassState::Loaded => {
+                return Err(format!("class {class_name} has to be linked before init").into())
+            }
+            ClassState::Linked => log!(ClassLoad, Debug, "initializing {class_name}"),
+            ClassState::Initialized => return Ok(()),
+            ClassState::Initializing => return self.wait_for_initialization(&class_name),
+            ClassState::Erroneous => {
+                return Err(Box::new(InitializationError::Erroneous { class_name }))
+            }
         }
-
-        let class = self
-            .method_area
-            .classes
-            .get_mut(&class_name.to_owned())
-            .ok_or("class not found")?
-            .as_mut_instance_klass()
-            .unwrap();
-
-        class.initialized = true;
-
-        if let Some(_) = class
+        let super_class_name = class
             .parsed_class
             .as_ref()
             .unwrap()
-            .deref()
-            .methods
-            .iter()
-            .find(|m| m.name == "<clinit>")
-        {
-            // println!("found clinit method for class");
-            let current_frame = Frame::new(
-                self,
-                class_name.to_owned(),
-                "<clinit>".into(),
-                MethodDescriptor {
-                    parameter_descriptors: vec![],
-                    return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
-                },
-            )?;
-            let mut init_thread = Thread {
-                thread_memory: ThreadMemory { jvm_stack: vec![] },
-                is_throwing: false,
-                java_clone: None,
-            };
-            init_thread.thread_memory.jvm_stack.push(current_frame);
-            init_thread.run(self)?;
+            .super_class
+            .as_ref()
+            .map(|super_class| super_class.name.to_owned());
+        // interfaces don't initialize their superinterfaces, classes only the ones declaring
+        // default methods (JVMS §5.5 step 7)
+        let mut interface_names = vec![];
+        if !class.parsed_class.as_ref().unwrap().access.interface {
+            self.collect_default_method_interfaces(&class_name, &mut interface_names)?;
         }
 
+        // mark the class first, so a <clinit> using the class again doesn't re-enter
+        self.set_class_state(&class_name, ClassState::Initializing)?;
+        self.initializing_classes
+            .insert(class_name.to_owned(), self.current_thread);
+
+        let result = self.run_class_initializers(&class_name, super_class_name, interface_names);
+        // a super class or superinterface that failed makes the class fail as well
+        let state = match result {
+            Ok(()) => ClassState::Initialized,
+            Err(_) => ClassState::Erroneous,
+        };
+        self.set_class_state(&class_name, state)?;
+        self.initializing_classes.remove(&class_name);
+        threads::changed(self);
+        result?;
+
         Ok(())
     }
 
-    fn ensure_array(&mut self, array_type: String) -> Result<(), Box<dyn Error>> {
-        // println!("ensure_array");
-        let arrayklass = self.method_area.classes.get(&array_type);
-        if arrayklass.is_some() {
-            // println!("already initialized");
-            return Ok(());
-        }
-
-        let klass = self
+    // a class being initialized by this thread is only ever used by its own <clinit> (or
+    // whatever it invokes), which is allowed to use the class already. Other threads wait until
+    // the initialization is done (JVMS §5.5 steps 2 and 3).
+    fn wait_for_initialization(&mut self, class_name: &str) -> Result<(), Box<dyn Error>> {
+        let is_initializing = |global_memory: &GlobalMemory| {
+            global_memory
+                .initializing_classes
+                .get(class_name)
+                .is_some_and(|id| *id != global_memory.current_thread)
+        };
+        threads::block_until(None, self, |global_memory| !is_initializing(global_memory));
+        let class = self
             .method_area
             .classes
-            .get(&"java/lang/Class".to_owned())
-            .ok_or("class not found in method area 1 :(")?;
-        let klass_java_clone = self.heap.allocate_klass(klass);
-
-        // remove first character
-        let inner_field_type = array_type.to_owned().chars().skip(1).collect::<String>();
-        // println!("inner?: {}", inner_field_type);
-        let component_type_name;
-        if inner_field_type.starts_with("[") || inner_field_type.len() == 1 {
-            component_type_name = inner_field_type;
-        } else {
-            let d = parse_field_descriptor(&inner_field_type)?;
-            component_type_name = d.field_type.as_class_instance().unwrap().to_owned();
-            self.ensure_class(&component_type_name.to_owned())?;
+            .get(class_name)
+            .ok_or("class not found")?;
+        if let ClassState::Erroneous = class.get_state() {
+            return Err(Box::new(InitializationError::Erroneous {
+                class_name: class_name.to_owned(),
+            }));
         }
-        // len must be over 1 to work around primitive arrays
-        if component_type_name.len() > 1 {
-            let component_type_clone = self
-                .method_area
-                .classes
-                .get(&component_type_name)
-                .unwrap()
-                .get_java_clone()
-                .unwrap();
+        Ok(())
+    }
 
-            let klass = self
-                .method_area
-                .classes
-                .get(&"java/lang/Class".to_owned())
-                .ok_or("class not found in method area 1 :(")?;
-            let offset = klass
-                .as_instance_klass()
-                .unwrap()
-                .field_offset_with_strings(
-                    "java/lang/Class".to_owned(),
-                    "componentType".to_owned(),
-                )?;
-            self.heap
-                .data
-                .get_mut(klass_java_clone as usize)
-                .unwrap()
-                .data[offset as usize] = component_type_clone;
+    // the thread object of the thread holding the lock, see threads
+    fn current_thread_object(&self) -> Option<HeapRef> {
+        if self.current_thread == threads::MAIN_THREAD_ID {
+            return self.main_thread_object;
         }
+        self.threads.get(&self.current_thread).copied()
+    }
 
-        let arrayklass = ArrayKlass {
-            name: array_type.to_owned(),
-            java_clone: Some(klass_java_clone),
+    // the host of the nest class_name belongs to (JVMS §5.4.4). Like newer JVMs, a claimed host
+    // that can't be loaded, is in another package or doesn't list the class as one of its
+    // members is ignored, making the class the host of its own nest.
+    fn nest_host(&mut self, class_name: &str) -> Result<String, Box<dyn Error>> {
+        let class = self.method_area.parsed_class(class_name)?;
+        let host_name = match class.nest_host() {
+            Some(host_name) => host_name.to_owned(),
+            None => return Ok(class_name.to_owned()),
         };
+        // the host is only loaded, not linked or initialized
+        if self.load_class(host_name.to_owned()).is_err() {
+            return Ok(class_name.to_owned());
+        }
+        let host = self.method_area.parsed_class(&host_name)?;
+        let is_member = host
+            .nest_members()
+            .is_some_and(|members| members.iter().any(|member| member == class_name));
+        let package_name =
+            |name: &str| name.rsplit_once('/').map(|(package, _)| package.to_owned());
+        if !is_member || package_name(class_name) != package_name(&host_name) {
+            return Ok(class_name.to_owned());
+        }
 
-        self.method_area
-            .classes
-            .insert(array_type, Box::new(arrayklass));
+        Ok(host_name)
+    }
 
-        return Ok(());
+    // whether the private members of other_class_name are accessible from class_name
+    fn is_nestmate_of(
+        &mut self,
+        class_name: &str,
+        other_class_name: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        if class_name == other_class_name {
+            return Ok(true);
+        }
+        Ok(self.nest_host(class_name)? == self.nest_host(other_class_name)?)
     }
-}
 
-#[derive(Debug)]
-struct Heap {
-    data: Vec<HeapItem>,
-}
+    fn set_class_state(
+        &mut self,
+        class_name: &str,
+        state: ClassState,
+    ) -> Result<(), Box<dyn Error>> {
+        self.method_area
+            .classes
+            .get_mut(class_name)
+            .ok_or("class not found")?
+            .as_mut_instance_klass()
+            .ok_or("not an InstanceKlass")?
+            .state = state;
+        Ok(())
+    }
 
-impl Heap {
-    fn new() -> Heap {
-        let mut h = Heap { data: vec![] };
-        h.store("null".to_owned(), vec![]);
-        return h;
+    // collects the direct and indirect superinterfaces of class_name declaring at least one
+    // non-abstract, non-static method. Superinterfaces come before their subinterfaces.
+    fn collect_default_method_interfaces(
+        &self,
+        class_name: &str,
+        interface_names: &mut Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        let class = self
+            .method_area
+            .classes
+            .get(class_name)
+            .ok_or("class not found")?
+            .as_instance_klass()
+            .ok_or("not an InstanceKlass")?
+            .parsed_class
+            .as_ref()
+            .ok_or("class not parsed")?
+            .clone();
+        for interface in class.interfaces.iter() {
+            self.collect_default_method_interfaces(&interface.name, interface_names)?;
+
+            let interface_class = self
+                .method_area
+                .classes
+                .get(&interface.name)
+                .ok_or("interface not found")?
+                .as_instance_klass()
+                .ok_or("not an InstanceKlass")?
+                .parsed_class
+                .as_ref()
+                .ok_or("interface not parsed")?
+                .clone();
+            let declares_default_method = interface_class
+                .methods
+                .iter()
+                .any(|method| !method.access.r#abstract && !method.access.r#static);
+            if declares_default_method && !interface_names.contains(&interface.name) {
+                interface_names.push(interface.name.to_owned());
+            }
+        }
+        Ok(())
     }
 
-    fn store(&mut self, field_ref: String, data: Vec<u32>) -> u32 {
-        self.data.push(HeapItem {
-            field_descriptor: field_ref,
-            data,
-        });
-        return (self.data.len() - 1) as u32;
+    // sets the static fields with a ConstantValue attribute to their constant
+    fn initialize_constant_fields(&mut self, class_name: &str) -> Result<(), Box<dyn Error>> {
+        let class = self
+            .method_area
+            .classes
+            .get(class_name)
+            .ok_or("class not found")?
+            .as_instance_klass()
+            .ok_or("not an InstanceKlass")?;
+        let parsed_class = class.parsed_class.as_ref().unwrap().clone();
+
+        let mut constant_fields = vec![];
+        let mut offset = 0;
+        for field in class.static_fields.as_ref().unwrap().iter() {
+            if let Some(index) = field.parsed_field.constant_value() {
+                let constant = parsed_class
+                    .constant_pool
+                    .get((index - 1) as usize)
+                    .ok_or("ConstantValue index out of bounds")?
+                    .to_owned();
+                constant_fields.push((offset, constant));
+            }
+            offset += field.field_width;
+        }
+
+        for (offset, constant) in constant_fields {
+            let values = match constant {
+                Constant::Integer(value) => vec![value as u32],
+                Constant::Float(value) => vec![value.to_bits()],
+                Constant::Long(value) => slots::long_to_slots(value).to_vec(),
+                Constant::Double(value) => slots::double_to_slots(value).to_vec(),
+                // the same object as an ldc of the string, see intern_string
+                Constant::String(string) => vec![intern_string(self, string)?.0],
+                constant => {
+                    return Err(format!(
+                        "ClassFormatError: invalid ConstantValue {constant:?} in {class_name}"
+                    )
+                    .into())
+                }
+            };
+            let class = self
+                .method_area
+                .classes
+                .get_mut(class_name)
+                .ok_or("class not found")?
+                .as_mut_instance_klass()
+                .ok_or("not an InstanceKlass")?;
+            let static_field_values = class.static_field_values.as_mut().unwrap();
+            static_field_values[offset..offset + values.len()].copy_from_slice(&values);
+        }
+
+        Ok(())
     }
 
-    fn allocate_klass(&mut self, klass: &Box<dyn Klass>) -> u32 {
-        let mut field_values = vec![];
-        for field in klass
+    // initializes the super class and the superinterfaces, then runs the <clinit> of
+    // class_name. Fails with an InitializationError if any of them threw.
+    fn run_class_initializers(
+        &mut self,
+        class_name: &str,
+        super_class_name: Option<String>,
+        interface_names: Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        // final static fields with a ConstantValue get their value before anything else runs
+        // (JVMS §5.5 step 6)
+        self.initialize_constant_fields(class_name)?;
+
+        if let Some(super_class_name) = super_class_name {
+            self.init_class(super_class_name)?;
+        }
+        for interface_name in interface_names {
+            // interfaces are loaded together with their implementing class, but not linked
+            self.link_class(interface_name.to_owned())?;
+            self.init_class(interface_name)?;
+        }
+
+        let class = self
+            .method_area
+            .classes
+            .get(class_name)
+            .ok_or("class not found")?
             .as_instance_klass()
-            .ok_or("not an InstanceKlass")
-            .unwrap()
-            .fields
-            .to_owned()
+            .unwrap();
+
+        if class
+            .parsed_class
+            .as_ref()
             .unwrap()
+            .deref()
+            .methods
             .iter()
+            .find(|m| m.name == "<clinit>")
+            .is_some()
         {
-            for _ in 0..field.field_width {
-                field_values.push(0);
+            log!(ClassLoad, Debug, "running {class_name}.<clinit>");
+            let current_frame = Frame::for_class_name(
+                self,
+                class_name,
+                "<clinit>".into(),
+                MethodDescriptor {
+                    parameter_descriptors: vec![],
+                    return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+                },
+            )?;
+            // class initializers run on behalf of the thread that triggered them
+            let mut init_thread = Thread::new(
+                self.current_thread,
+                self.max_stack_depth,
+                self.current_thread_object(),
+            );
+            // the frames of the thread that triggered the initialization aren't roots of
+            // init_thread, so it must not collect, even if it runs the outermost loop
+            init_thread.interpreter_depth = 1;
+            // like with invoke_method, what the <clinit> throws ends up on a stub frame
+            init_thread.thread_memory.jvm_stack.push(Frame::new_stub()?);
+            init_thread.thread_memory.jvm_stack.push(current_frame);
+            init_thread.run(self)?;
+            if init_thread.is_throwing {
+                let exception = init_thread.initializer_exception(self)?;
+                return Err(Box::new(InitializationError::Threw {
+                    class_name: class_name.to_owned(),
+                    exception,
+                }));
             }
         }
+        if class_name == "jdk/internal/misc/UnsafeConstants" {
+            self.set_unsafe_constants()?;
+        }
 
-        return self.store(format!("L{};", klass.get_name()), field_values);
+        Ok(())
     }
-}
 
-#[derive(Debug)]
-struct HeapItem {
-    // header
-    field_descriptor: String,
-    // data
-    data: Vec<u32>,
-}
+    // Like in hotspot, the <clinit> of UnsafeConstants sets its fields to 0 and the vm fills in
+    // the actual values afterwards. They have to agree with how native memory and the elements
+    // of arrays of primitives are laid out, see unsafe_read.
+    fn set_unsafe_constants(&mut self) -> Result<(), Box<dyn Error>> {
+        let class = self
+            .method_area
+            .classes
+            .get_mut("jdk/internal/misc/UnsafeConstants")
+            .ok_or("class not found")?
+            .as_mut_instance_klass()
+            .ok_or("not an InstanceKlass")?;
+        for (field_name, value) in [
+            ("ADDRESS_SIZE0", 8),
+            ("PAGE_SIZE", 4096),
+            ("BIG_ENDIAN", 1),
+            ("UNALIGNED_ACCESS", 1),
+        ] {
+            let offset = class.static_field_offset_with_strings(
+                "jdk/internal/misc/UnsafeConstants".to_owned(),
+                field_name.to_owned(),
+            )?;
+            class.static_field_values.as_mut().unwrap()[offset] = value;
+        }
+        Ok(())
+    }
 
-#[derive(Debug)]
-struct MethodArea {
-    classes: HashMap<String, Box<dyn Klass>>,
-}
+    // allocates an array of type array_type, with the nested arrays of the next dimensions
+    // allocated as well. Dimensions without a count are left null. None of the counts may be
+    // negative, multianewarray checks them all before anything is allocated.
+    fn allocate_multi_array(
+        &mut self,
+        array_type: &str,
+        counts: &[i32],
+    ) -> Result<HeapRef, Box<dyn Error>> {
+        self.ensure_array(array_type.to_owned())?;
 
-impl MethodArea {
-    fn add_class(&mut self, class_name: String, mut class: InstanceKlass) {
-        let parsed_class = &**class.parsed_class.as_ref().unwrap();
+        let count = *counts.first().ok_or("no dimensions left")?;
 
-        let mut fields = vec![];
-        let mut static_fields = vec![];
+        if counts.len() == 1 {
+            return Ok(self.heap.store_array(array_type, count as usize));
+        }
+        let mut data = vec![0; count as usize];
+        let component_type = &array_type[1..];
+        for item in data.iter_mut() {
+            *item = self.allocate_multi_array(component_type, &counts[1..])?.0;
+        }
 
-        for field in parsed_class.fields.iter() {
-            match field.descriptor.field_type {
-                crate::parse::FieldType::Integer
-                | crate::parse::FieldType::Boolean
-                | crate::parse::FieldType::Array(_)
-                | crate::parse::FieldType::Byte
-                | crate::parse::FieldType::Char
-                | crate::parse::FieldType::Short
-                | crate::parse::FieldType::Float
-                | crate::parse::FieldType::ClassInstance(_) => {
-                    let klass_field = KlassField {
-                        class_name: class_name.to_owned(),
-                        field_name: field.name.to_owned(),
-                        field_type: field.descriptor.field_type.to_owned(),
-                        field_width: 1,
-                        _parsed_field: field.to_owned(),
-                    };
-                    if field.access.r#static {
-                        static_fields.push(klass_field);
-                    } else {
-                        fields.push(klass_field)
-                    }
-                }
-                crate::parse::FieldType::LongInteger | crate::parse::FieldType::Double => {
-                    let klass_field = KlassField {
-                        class_name: class_name.to_owned(),
-                        field_name: field.name.to_owned(),
-                        field_type: field.descriptor.field_type.to_owned(),
-                        field_width: 2,
-                        _parsed_field: field.to_owned(),
-                    };
-                    if field.access.r#static {
-                        static_fields.push(klass_field);
-                    } else {
-                        fields.push(klass_field)
+        Ok(self.heap.store(array_type, data))
+    }
+
+    fn open_file(&mut self, file: File) -> i32 {
+        let fd = self.next_file_descriptor;
+        self.next_file_descriptor += 1;
+        self.open_files.insert(fd, file);
+        fd
+    }
+
+    fn write_to_file_descriptor(&mut self, fd: i32, bytes: &[u8]) -> Result<(), Box<dyn Error>> {
+        match fd {
+            1 => stdout().write_all(bytes)?,
+            2 => stderr().write_all(bytes)?,
+            _ => self
+                .open_files
+                .get_mut(&fd)
+                .ok_or(format!("fd {fd} is not open for writing"))?
+                .write_all(bytes)?,
+        }
+        Ok(())
+    }
+
+    fn read_from_file_descriptor(
+        &mut self,
+        fd: i32,
+        buf: &mut [u8],
+    ) -> Result<usize, Box<dyn Error>> {
+        let read = match fd {
+            // stdout has to be flushed first, otherwise prompts might show up after the input
+            0 => {
+                stdout().flush()?;
+                loop {
+                    match stdin().read(buf) {
+                        // ^C exits at the next safepoint, which comes before java land sees that
+                        // nothing was read, see install_interrupt_handler
+                        Err(e) if e.kind() == ErrorKind::Interrupted => {
+                            if self.interrupted.load(Ordering::SeqCst) {
+                                break 0;
+                            }
+                        }
+                        read => break read?,
                     }
                 }
             }
+            _ => self
+                .open_files
+                .get_mut(&fd)
+                .ok_or(format!("fd {fd} is not open for reading"))?
+                .read(buf)?,
+        };
+        Ok(read)
+    }
+
+    fn available_from_file_descriptor(&mut self, fd: i32) -> Result<i32, Box<dyn Error>> {
+        match fd {
+            // there is no portable way to peek into stdin, so we don't promise anything
+            0 => Ok(0),
+            _ => {
+                let file = self
+                    .open_files
+                    .get_mut(&fd)
+                    .ok_or(format!("fd {fd} is not open for reading"))?;
+                let position = file.stream_position()?;
+                let length = file.metadata()?.len();
+                Ok(length.saturating_sub(position).min(i32::MAX as u64) as i32)
+            }
+        }
+    }
+
+    fn flush_file_descriptor(&mut self, fd: i32) -> Result<(), Box<dyn Error>> {
+        match fd {
+            0 => {}
+            1 => stdout().flush()?,
+            2 => stderr().flush()?,
+            _ => self
+                .open_files
+                .get_mut(&fd)
+                .ok_or(format!("fd {fd} is not open"))?
+                .sync_all()?,
         }
+        Ok(())
+    }
 
-        if let Some(class_info) = &parsed_class.super_class {
-            let mut parent_fields = self
+    fn ensure_array(&mut self, array_type: String) -> Result<(), Box<dyn Error>> {
+        let arrayklass = self.method_area.classes.get(&array_type);
+        if arrayklass.is_some() {
+            return Ok(());
+        }
+
+        let klass = self
+            .method_area
+            .classes
+            .get("java/lang/Class")
+            .ok_or("class not found in method area 1 :(")?;
+        let klass_java_clone = self.heap.allocate_klass(klass);
+
+        // remove first character
+        let inner_field_type = array_type.to_owned().chars().skip(1).collect::<String>();
+        let component_type_name;
+        if inner_field_type.starts_with("[") {
+            // nested arrays need their component array class to be around as well
+            self.ensure_array(inner_field_type.to_owned())?;
+            component_type_name = inner_field_type;
+        } else if inner_field_type.len() == 1 {
+            component_type_name = inner_field_type;
+        } else {
+            let d = parse_field_descriptor(&inner_field_type)?;
+            component_type_name = d.field_type.as_class_instance().unwrap().to_owned();
+            // creating an array type doesn't initialize its component type
+            self.ensure_linked(&component_type_name.to_owned())?;
+        }
+        // the component type of primitive arrays is the class of the primitive type
+        let component_type_clone = if component_type_name.len() == 1 {
+            let component_type = parse_field_descriptor(&component_type_name)?.field_type;
+            self.ensure_primitive_class(
+                primitive_class_name(&component_type).ok_or("not a primitive type")?,
+            )?
+        } else {
+            self.method_area
                 .classes
-                .get(&class_info.name.to_owned())
+                .get(&component_type_name)
                 .unwrap()
-                .as_instance_klass()
+                .get_java_clone()
                 .unwrap()
-                .fields
-                .clone()
-                .unwrap();
-            parent_fields.append(&mut fields);
-            fields = parent_fields;
         };
 
-        class.fields = Some(fields);
-        class.static_fields = Some(static_fields);
+        let klass = self
+            .method_area
+            .classes
+            .get("java/lang/Class")
+            .ok_or("class not found in method area 1 :(")?;
+        let offset = klass
+            .as_instance_klass()
+            .unwrap()
+            .field_offset_with_strings("java/lang/Class".to_owned(), "componentType".to_owned())?;
+        self.heap.get_mut(klass_java_clone).unwrap().data[offset] = component_type_clone.0;
+
+        let arrayklass = ArrayKlass {
+            name: array_type.to_owned(),
+            java_clone: Some(klass_java_clone),
+        };
+
+        self.method_area
+            .classes
+            .insert(&array_type, Box::new(arrayklass));
 
-        self.classes.insert(class_name, Box::new(class));
+        Ok(())
     }
 }
 
-#[derive(Debug, Clone)]
-struct KlassField {
-    class_name: String,
-    field_name: String,
-    field_type: FieldType,
-    field_width: usize,
-    _parsed_field: Field,
+// A reference to an object on the heap, which is an index into the table of handles of the heap
+// rather than where the object is, so that the gc can move objects around without their
+// references changing. Where references are stored along with other values, like on the operand
+// stack or in the fields of objects, they take up a slot, the number in here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct HeapRef(pub(crate) u32);
+
+impl HeapRef {
+    pub const NULL: HeapRef = HeapRef(0);
+
+    pub fn is_null(self) -> bool {
+        self == HeapRef::NULL
+    }
 }
 
-trait Klass: Debug {
-    fn is_initialized(&self) -> bool;
-    fn get_name(&self) -> &str;
-    fn get_java_clone(&self) -> Option<u32>;
-    fn as_instance_klass(&self) -> Option<&InstanceKlass>;
-    fn as_mut_instance_klass(&mut self) -> Option<&mut InstanceKlass>;
-    fn as_array_klass(&self) -> Option<&ArrayKlass>;
+#[derive(Debug, Default)]
+struct Heap {
+    // the objects, in whatever order the gc left them
+    objects: Vec<HeapItem>,
+    // references are indices into this table, which tells where in objects they are. That way
+    // objects can be moved by the gc without changing their references. The references of freed
+    // objects point nowhere.
+    handles: Vec<Option<usize>>,
+    // the number of objects stored since the last collection, and how many make for the next one,
+    // see gc
+    allocated_since_collection: usize,
+    collection_threshold: usize,
+    // set by System.gc, to collect at the next safepoint regardless
+    collection_requested: bool,
+    // the references and objects freed by the gc, the lowest one last as it is reused first
+    free_handles: Vec<u32>,
+    free_objects: Vec<usize>,
+    // how many objects were stored with a freed reference, and how many freed references at the
+    // end of the table were dropped altogether
+    reused_slots: usize,
+    truncated_slots: usize,
+    // the state of the xorshift generator the identity hashes come from
+    identity_hash_state: u32,
+    // the most bytes the objects took up before a collection. Like a committed heap, the heap is
+    // taken to keep that much around afterwards, see Runtime.totalMemory
+    committed_bytes: usize,
+    // the field descriptors the headers of the objects refer to
+    descriptors: Symbols,
 }
 
-#[derive(Debug)]
-struct InstanceKlass {
-    name: String,
-    parsed_class: Option<Rc<ParsedClass>>,
-    constant_pool: Option<Rc<RuntimeConstantPool>>,
-    static_field_values: Option<Vec<u32>>,
-    fields: Option<Vec<KlassField>>,
-    static_fields: Option<Vec<KlassField>>,
-    java_clone: Option<u32>,
-    initialized: bool,
+// how the heap is filled, see Vm::heap_statistics
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HeapStatistics {
+    // every reference the heap can hand out, including null and the free ones
+    pub slots: usize,
+    pub live_objects: usize,
+    pub free_slots: usize,
+    // since the vm started
+    pub reused_slots: usize,
+    pub truncated_slots: usize,
 }
 
-impl Klass for InstanceKlass {
-    fn is_initialized(&self) -> bool {
-        self.initialized
+impl HeapStatistics {
+    // the share of the slots that are free, between 0 and 1
+    pub fn fragmentation(&self) -> f64 {
+        if self.slots == 0 {
+            return 0.0;
+        }
+        self.free_slots as f64 / self.slots as f64
     }
+}
 
-    fn get_name(&self) -> &str {
-        return self.name.as_str();
+// how often a method was called and how many instructions it ran, see Vm::method_statistics
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodStatistics {
+    pub class_name: String,
+    pub method_name: String,
+    pub descriptor: String,
+    pub calls: u64,
+    // the instructions of the frames still on a stack aren't counted yet
+    pub instructions: u64,
+}
+
+impl Heap {
+    fn new() -> Heap {
+        let mut h = Heap {
+            objects: vec![],
+            handles: vec![],
+            allocated_since_collection: 0,
+            collection_threshold: gc::initial_collection_threshold(),
+            collection_requested: false,
+            free_handles: vec![],
+            free_objects: vec![],
+            reused_slots: 0,
+            truncated_slots: 0,
+            identity_hash_state: 0x2545f491,
+            committed_bytes: 0,
+            descriptors: Symbols::default(),
+        };
+        h.store("null", vec![]);
+        h
     }
 
-    fn get_java_clone(&self) -> Option<u32> {
-        self.java_clone
+    fn store(&mut self, field_descriptor: &str, data: Vec<u32>) -> HeapRef {
+        let descriptor_id = self.descriptors.intern(field_descriptor);
+        self.store_with_id(descriptor_id, data)
     }
 
-    fn as_instance_klass(&self) -> Option<&InstanceKlass> {
-        Some(self)
-    }
-    fn as_mut_instance_klass(&mut self) -> Option<&mut InstanceKlass> {
-        Some(self)
+    // stores an object whose field descriptor was interned already
+    fn store_with_id(&mut self, descriptor_id: u32, data: Vec<u32>) -> HeapRef {
+        self.insert(HeapItem {
+            descriptor_id,
+            identity_hash: 0,
+            data,
+            bytes: vec![],
+        })
     }
 
-    fn as_array_klass(&self) -> Option<&ArrayKlass> {
-        None
+    fn store_int_array(&mut self, elements: &[i32]) -> HeapRef {
+        let bytes = elements
+            .iter()
+            .flat_map(|element| element.to_be_bytes())
+            .collect();
+        self.store_primitive_array("[I", bytes)
     }
-}
 
-impl InstanceKlass {
-    fn is_linked(&self) -> bool {
-        self.constant_pool.is_some()
+    fn store_primitive_array(&mut self, array_type: &str, bytes: Vec<u8>) -> HeapRef {
+        let descriptor_id = self.descriptors.intern(array_type);
+        self.insert(HeapItem {
+            descriptor_id,
+            identity_hash: 0,
+            data: vec![],
+            bytes,
+        })
     }
 
-    fn find_static_field(
-        &self,
-        global_memory: &GlobalMemory,
-        searched_field_name: String,
-    ) -> Result<(String, usize), Box<dyn Error>> {
-        // println!(
-        // "searching for {} in {}",
-        // searched_field_name,
-        // self.get_name()
-        // );
-        if self
-            .static_fields
-            .as_ref()
-            .unwrap()
-            .iter()
-            .find(|field| field.field_name == searched_field_name)
-            .is_some()
-        {
-            return Ok((
-                self.get_name().to_owned(),
-                self.static_field_offset_with_strings(
-                    self.get_name().to_owned(),
-                    searched_field_name,
-                )?,
-            ));
-        }
-
-        if self.parsed_class.as_ref().unwrap().super_class.is_none() {
-            return Err("failed to find static field".into());
+    // stores an array of length elements, all of them 0, false or null
+    fn store_array(&mut self, array_type: &str, length: usize) -> HeapRef {
+        match primitive_element_size(array_type) {
+            Some(size) => self.store_primitive_array(array_type, vec![0; length * size]),
+            None => self.store(array_type, vec![0; length]),
         }
-        let super_class_name = self
-            .parsed_class
-            .as_ref()
-            .unwrap()
-            .super_class
-            .as_ref()
-            .unwrap()
-            .name
-            .to_owned();
-        let super_klass = global_memory
-            .method_area
-            .classes
-            .get(&super_class_name)
-            .ok_or("super class not found")?;
-        return super_klass
-            .as_instance_klass()
-            .unwrap()
-            .find_static_field(global_memory, searched_field_name);
     }
-    fn static_field_offset_with_strings(
-        &self,
-        searched_class_name: String,
-        searched_field_name: String,
-    ) -> Result<usize, Box<dyn Error>> {
-        let mut offset = 0;
 
-        for KlassField {
-            class_name,
-            field_name,
-            field_width,
-            ..
-        } in self.static_fields.as_ref().unwrap().iter()
-        {
-            if searched_class_name != class_name.to_owned() {
-                offset += field_width;
-                continue;
+    fn insert(&mut self, heap_item: HeapItem) -> HeapRef {
+        self.allocated_since_collection += 1;
+        let index = match self.free_objects.pop() {
+            Some(index) => {
+                self.objects[index] = heap_item;
+                index
             }
-            if searched_field_name != field_name.to_owned() {
-                offset += field_width;
-                continue;
+            None => {
+                self.objects.push(heap_item);
+                self.objects.len() - 1
             }
-
-            return Ok(offset);
+        };
+        if let Some(objectref) = self.free_handles.pop() {
+            self.reused_slots += 1;
+            self.handles[objectref as usize] = Some(index);
+            return HeapRef(objectref);
         }
-        // FIXME: 0 is not a error case :^)
-        Err(format!("couldnt calculate static field offset for  \"{searched_class_name}\"\"{searched_field_name}\" because field was not found").into())
-    }
-    fn static_field_offset(&self, field_ref_constant: Constant) -> Result<usize, Box<dyn Error>> {
-        // println!("field_ref_constant {field_ref_constant:?}");
-        let field_ref = field_ref_constant.as_field_ref().unwrap();
-        let searched_class_name = field_ref.0.name;
-        let searched_field_name = field_ref
-            .1
-            .to_owned()
-            .as_name_and_type()
-            .ok_or("not a name_and_type")?
-            .0;
-        self.static_field_offset_with_strings(searched_class_name, searched_field_name)
+        self.handles.push(Some(index));
+        HeapRef((self.handles.len() - 1) as u32)
     }
-    fn field_offset_with_strings(
-        &self,
-        searched_class_name: String,
-        searched_field_name: String,
-    ) -> Result<usize, Box<dyn Error>> {
-        let mut offset = 0;
 
-        for KlassField {
-            class_name,
-            field_name,
-            field_width,
-            ..
-        } in self.fields.as_ref().unwrap().iter()
-        {
-            // if searched_class_name != class_name.to_owned() {
-            //     offset += field_width;
-            //     continue;
-            // }
-            if searched_field_name != field_name.to_owned() {
-                offset += field_width;
-                continue;
-            }
+    fn get(&self, objectref: HeapRef) -> Option<&HeapItem> {
+        let index = (*self.handles.get(objectref.0 as usize)?)?;
+        self.objects.get(index)
+    }
 
-            return Ok(offset);
-        }
-        // FIXME: 0 is not a error case :^)
-        Err(format!("couldnt calculate field offset for  \"{searched_class_name}\"\"{searched_field_name}\" because field was not found: {:?}", self.fields).into())
+    fn get_mut(&mut self, objectref: HeapRef) -> Option<&mut HeapItem> {
+        let index = (*self.handles.get(objectref.0 as usize)?)?;
+        self.objects.get_mut(index)
     }
-    fn field_offset(&self, field_ref_constant: Constant) -> Result<usize, Box<dyn Error>> {
-        // println!("field_ref_constant {field_ref_constant:?}");
-        let field_ref = field_ref_constant.as_field_ref().unwrap();
-        let searched_class_name = field_ref.0.name;
-        let searched_field_name = field_ref
-            .1
-            .to_owned()
-            .as_name_and_type()
-            .ok_or("not a name_and_type")?
-            .0;
-        self.field_offset_with_strings(searched_class_name, searched_field_name)
+
+    // the field descriptor in the header of an object, like Ljava/lang/String; or [I
+    fn descriptor(&self, heap_item: &HeapItem) -> &str {
+        self.descriptors.name(heap_item.descriptor_id)
     }
-}
 
-#[derive(Debug)]
-struct ArrayKlass {
-    name: String,
-    java_clone: Option<u32>,
-}
+    fn field_descriptor(&self, objectref: HeapRef) -> Option<&str> {
+        Some(self.descriptor(self.get(objectref)?))
+    }
 
-impl Klass for ArrayKlass {
-    fn is_initialized(&self) -> bool {
-        true
+    // the name of the class of an object, which is its field descriptor for arrays
+    fn class_name(&self, objectref: HeapRef) -> Option<&str> {
+        let descriptor = self.field_descriptor(objectref)?;
+        Some(
+            descriptor
+                .strip_prefix('L')
+                .and_then(|descriptor| descriptor.strip_suffix(';'))
+                .unwrap_or(descriptor),
+        )
     }
 
-    fn get_name(&self) -> &str {
-        self.name.as_str()
+    // the number of elements of an array
+    fn array_length(&self, heap_item: &HeapItem) -> usize {
+        match primitive_element_size(self.descriptor(heap_item)) {
+            Some(size) => heap_item.bytes.len() / size,
+            None => heap_item.data.len(),
+        }
     }
 
-    fn get_java_clone(&self) -> Option<u32> {
-        self.java_clone
+    // every object together with its reference, null included, by reference
+    fn iter(&self) -> impl Iterator<Item = (HeapRef, &HeapItem)> {
+        self.handles
+            .iter()
+            .enumerate()
+            .filter_map(|(objectref, index)| {
+                Some((HeapRef(objectref as u32), &self.objects[(*index)?]))
+            })
     }
 
-    fn as_instance_klass(&self) -> Option<&InstanceKlass> {
-        None
+    // the identity hash of an object, generated the first time it is asked for and kept in the
+    // header from then on. Unlike the reference it is neither reused after the object is freed,
+    // nor does it tell where the object is. Like in HotSpot it takes 31 bits and is never 0.
+    fn identity_hash(&mut self, objectref: HeapRef) -> Option<u32> {
+        let index = (*self.handles.get(objectref.0 as usize)?)?;
+        if self.objects.get(index)?.identity_hash == 0 {
+            let mut hash = 0;
+            while hash == 0 {
+                // xorshift32 (Marsaglia)
+                let mut state = self.identity_hash_state;
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                self.identity_hash_state = state;
+                hash = state & 0x7fffffff;
+            }
+            self.objects[index].identity_hash = hash;
+        }
+        Some(self.objects[index].identity_hash)
     }
 
-    fn as_mut_instance_klass(&mut self) -> Option<&mut InstanceKlass> {
-        None
+    fn statistics(&self) -> HeapStatistics {
+        HeapStatistics {
+            slots: self.handles.len(),
+            // null is no object
+            live_objects: self.handles.len() - self.free_handles.len() - 1,
+            free_slots: self.free_handles.len(),
+            reused_slots: self.reused_slots,
+            truncated_slots: self.truncated_slots,
+        }
     }
 
-    fn as_array_klass(&self) -> Option<&ArrayKlass> {
-        Some(self)
+    fn allocate_klass(&mut self, klass: &dyn Klass) -> HeapRef {
+        let klass = klass
+            .as_instance_klass()
+            .ok_or("not an InstanceKlass")
+            .unwrap();
+        let width = klass
+            .fields
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(|field| field.field_width)
+            .sum();
+
+        self.store_with_id(klass.descriptor_id, vec![0; width])
     }
 }
 
-#[derive(Debug)]
-struct RuntimeConstantPool {
-    pool: Vec<Constant>,
+// The elements of arrays of primitives take as many bytes as their type does, most significant
+// byte first, in the bytes of the array rather than a slot of data each. None for any other type.
+fn primitive_element_size(array_type: &str) -> Option<usize> {
+    match array_type {
+        "[Z" | "[B" => Some(1),
+        "[C" | "[S" => Some(2),
+        "[I" | "[F" => Some(4),
+        "[J" | "[D" => Some(8),
+        _ => None,
+    }
 }
 
 #[derive(Debug)]
-struct Thread {
-    thread_memory: ThreadMemory,
-    is_throwing: bool,
-    java_clone: Option<u32>,
+struct HeapItem {
+    // header, the field descriptor of the object as interned in Heap::descriptors
+    descriptor_id: u32,
+    // 0 until the identity hash is first asked for, see Heap::identity_hash
+    identity_hash: u32,
+    // data: the fields of objects and the elements of arrays of references
+    data: Vec<u32>,
+    // the elements of arrays of primitives, see primitive_element_size
+    bytes: Vec<u8>,
 }
 
-// FIXME: do proper binding!
-fn run_native_methods(
-    thread: &mut Thread,
-    global_memory: &mut GlobalMemory,
-) -> Result<(), Box<dyn Error>> {
-    let current_frame = thread
-        .thread_memory
-        .jvm_stack
-        .last_mut()
-        .ok_or("no item on jvm stack")?;
-    current_frame.running_native = true;
+impl HeapItem {
+    // the element at index of an array of primitives taking N bytes each
+    fn element<const N: usize>(&self, index: usize) -> Option<[u8; N]> {
+        let start = index.checked_mul(N)?;
+        self.bytes
+            .get(start..start.checked_add(N)?)?
+            .try_into()
+            .ok()
+    }
 
-    match current_frame.class_name.as_str() {
-        "java/lang/Object" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "getClass" => {
-                let this_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?;
-                // FIXME: check if this_ref is null
-                let heap_item = global_memory
-                    .heap
-                    .data
-                    .get(this_ref.to_owned() as usize)
-                    .ok_or("this_ref not found on heap")?;
-                let descriptor = parse_field_descriptor(&heap_item.field_descriptor)?;
-                // println!("descriptor: {descriptor:?}");
+    fn set_element<const N: usize>(&mut self, index: usize, element: [u8; N]) -> Option<()> {
+        let start = index.checked_mul(N)?;
+        self.bytes
+            .get_mut(start..start.checked_add(N)?)?
+            .copy_from_slice(&element);
+        Some(())
+    }
 
-                let class_name = if let Some(name) = descriptor.field_type.as_class_instance() {
-                    name.to_owned()
-                } else if let Some(_) = descriptor.field_type.as_array() {
-                    heap_item.field_descriptor.to_owned()
-                } else {
-                    unreachable!();
-                };
-                let klass_java_clone = global_memory
-                    .method_area
-                    .classes
-                    .get(&class_name.to_owned())
-                    .ok_or("no class 1")?
-                    .get_java_clone()
-                    .unwrap();
-
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    // the elements of an int[]
+    fn int_elements(&self) -> Vec<i32> {
+        self.bytes
+            .chunks_exact(4)
+            .map(|element| i32::from_be_bytes([element[0], element[1], element[2], element[3]]))
+            .collect()
+    }
 
-                frame.operand_stack.push(klass_java_clone);
-            }
-            "registerNatives" => {
-                // noop for now?
-            }
-            "hashCode" => {
-                let this_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?
-                    .to_owned();
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    // the element at index of an array of primitives of the type, in the slots the operand stack
+    // has it in
+    fn element_slots(&self, element_type: &FieldType, index: usize) -> Option<Vec<u32>> {
+        let size = native_size(element_type).ok()?;
+        let start = index.checked_mul(size)?;
+        let bytes = self.bytes.get(start..start.checked_add(size)?)?;
+        Some(value_from_native_bytes(element_type, bytes))
+    }
+}
 
-                if this_ref == 0 {
-                    frame.operand_stack.push(0);
-                } else {
-                    // FIXME: proper hash
-                    frame.operand_stack.push(this_ref);
-                }
-            }
-            "notifyAll" => {
-                // noop for now?
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl")
-            }
-        },
-        "java/lang/Class" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "registerNatives" => {
-                // noop for now?
-            }
-            "initClassName" => {
-                let this_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?;
-                // FIXME: check if this_ref is null
+// the loaded classes, indexed by the id their name is interned as. The names are only looked up
+// where a class is named, like by the constant pool or by natives; what runs keeps the ids.
+#[derive(Debug, Default)]
+struct Classes {
+    names: Symbols,
+    // indexed by id, None for names that were interned before their class was loaded
+    klasses: Vec<Option<Box<dyn Klass>>>,
+}
 
-                let klass = global_memory
-                    .method_area
-                    .classes
-                    .values()
-                    .find(|class| class.get_java_clone().unwrap() == *this_ref)
-                    .unwrap();
+impl Classes {
+    // the id of a class name, which is interned if it wasn't yet, even if the class isn't loaded
+    fn id(&mut self, class_name: &str) -> ClassId {
+        ClassId(self.names.intern(class_name))
+    }
 
-                let class_name = klass.get_name();
+    // the id of a class name, if it was interned already
+    fn lookup_id(&self, class_name: &str) -> Option<ClassId> {
+        self.names.lookup(class_name).map(ClassId)
+    }
 
-                let string_ref = java_string_from_string(global_memory, class_name.to_owned())?;
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    fn name(&self, id: ClassId) -> &str {
+        self.names.name(id.0)
+    }
 
-                frame.operand_stack.push(string_ref);
-            }
-            "desiredAssertionStatus0" => {
-                // no idea what this method does!
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    fn shared_name(&self, id: ClassId) -> Arc<str> {
+        self.names.shared_name(id.0)
+    }
 
-                frame.operand_stack.push(1);
-            }
-            "getPrimitiveClass" => {
-                let primitive_type_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?;
+    fn by_id(&self, id: ClassId) -> Option<&dyn Klass> {
+        self.klasses.get(id.0 as usize)?.as_deref()
+    }
 
-                let text = string_from_java_string(global_memory, *primitive_type_ref)?;
+    fn by_id_mut(&mut self, id: ClassId) -> Option<&mut Box<dyn Klass>> {
+        self.klasses.get_mut(id.0 as usize)?.as_mut()
+    }
 
-                // println!("text: {:?}", text.bytes());
+    fn get(&self, class_name: &str) -> Option<&dyn Klass> {
+        self.by_id(self.lookup_id(class_name)?)
+    }
 
-                let java_clone_ref;
-                // NOTE: for some reason match didn't work here?
-                if text == "int" {
-                    global_memory.ensure_class("java/lang/Integer".into())?;
+    fn get_mut(&mut self, class_name: &str) -> Option<&mut Box<dyn Klass>> {
+        let id = self.lookup_id(class_name)?;
+        self.by_id_mut(id)
+    }
 
-                    java_clone_ref = Some(
-                        global_memory
-                            .method_area
-                            .classes
-                            .get("java/lang/Integer")
-                            .ok_or("class not found")?
-                            .get_java_clone()
-                            .ok_or("no java clone")?,
-                    );
-                } else if text == "float" {
-                    global_memory.ensure_class("java/lang/Float".into())?;
+    fn contains_key(&self, class_name: &str) -> bool {
+        self.get(class_name).is_some()
+    }
 
-                    java_clone_ref = Some(
-                        global_memory
-                            .method_area
-                            .classes
-                            .get("java/lang/Float")
-                            .ok_or("class not found")?
-                            .get_java_clone()
-                            .ok_or("no java clone")?,
-                    );
-                } else if text == "double" {
-                    global_memory.ensure_class("java/lang/Double".into())?;
+    fn insert(&mut self, class_name: &str, klass: Box<dyn Klass>) -> ClassId {
+        let id = self.id(class_name);
+        let index = id.0 as usize;
+        if self.klasses.len() <= index {
+            self.klasses.resize_with(index + 1, || None);
+        }
+        self.klasses[index] = Some(klass);
+        id
+    }
 
-                    java_clone_ref = Some(
-                        global_memory
-                            .method_area
-                            .classes
-                            .get("java/lang/Double")
-                            .ok_or("class not found")?
-                            .get_java_clone()
-                            .ok_or("no java clone")?,
-                    );
-                } else if text == "boolean" {
-                    global_memory.ensure_class("java/lang/Boolean".into())?;
+    fn iter(&self) -> impl Iterator<Item = (&str, &Box<dyn Klass>)> {
+        self.klasses
+            .iter()
+            .enumerate()
+            .filter_map(|(index, klass)| Some((self.names.name(index as u32), klass.as_ref()?)))
+    }
 
-                    java_clone_ref = Some(
-                        global_memory
-                            .method_area
-                            .classes
-                            .get("java/lang/Boolean")
-                            .ok_or("class not found")?
-                            .get_java_clone()
-                            .ok_or("no java clone")?,
-                    );
-                } else {
-                    unimplemented!("{}", text)
-                }
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    fn keys(&self) -> impl Iterator<Item = &str> {
+        self.iter().map(|(class_name, _)| class_name)
+    }
 
-                frame
-                    .operand_stack
-                    .push(java_clone_ref.ok_or("no java_clone found")?);
-            }
-            "isArray" => {
-                let this_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?;
-                // FIXME: check if this_ref is null
+    fn values(&self) -> impl Iterator<Item = &Box<dyn Klass>> {
+        self.klasses.iter().flatten()
+    }
+}
 
-                let klass = global_memory
-                    .method_area
-                    .classes
-                    .values()
-                    .find(|class| {
-                        let maybe_java_clone = class.get_java_clone();
-                        if maybe_java_clone.is_none() {
-                            return false;
-                        }
-                        return maybe_java_clone.unwrap() == *this_ref;
-                    })
-                    .unwrap();
+#[derive(Debug, Default)]
+struct MethodArea {
+    classes: Classes,
+}
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+impl MethodArea {
+    fn add_class(&mut self, class_name: String, mut class: InstanceKlass) {
+        let parsed_class = &**class.parsed_class.as_ref().unwrap();
 
-                frame
-                    .operand_stack
-                    .push(if klass.as_array_klass().is_some() {
-                        1
-                    } else {
-                        0
-                    });
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "java/lang/System" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "registerNatives" => {
-                // noop for now?
-            }
-            "arraycopy" => {
-                let src_ref = current_frame
-                    .local_variables
-                    .get(0)
-                    .ok_or("no item in local_variables")?;
-                let src_pos = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(1)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
-                let dest_ref = current_frame
-                    .local_variables
-                    .get(2)
-                    .ok_or("no item in local_variables")?;
-                let dest_pos = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(3)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
-                let length = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(4)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
-                // println!("{} {} {} ", src_pos, dest_pos, length);
-                // FIXME: handle longs?
-                // FIXME: check if is actually an array
-
-                // println!(
-                //     "{:?}",
-                //     global_memory
-                //         .method_area
-                //         .classes
-                //         .get("java/lang/String")
-                //         .as_ref()
-                //         .unwrap()
-                //         .as_instance_klass()
-                //         .unwrap()
-                //         .static_field_values
-                //         .as_ref()
-                //         .unwrap()
-                // );
-
-                let src_array_data = global_memory
-                    .heap
-                    .data
-                    .get(*src_ref as usize)
-                    .ok_or("array not on heap")?
-                    .data
-                    .to_owned();
-                let target_array = global_memory
-                    .heap
-                    .data
-                    .get_mut(*dest_ref as usize)
-                    .ok_or("array not on heap")?;
+        let mut fields = vec![];
+        let mut static_fields = vec![];
 
-                for i in 0..length {
-                    target_array.data[(dest_pos + i) as usize] =
-                        src_array_data[(src_pos + i) as usize];
+        for field in parsed_class.fields.iter() {
+            match field.descriptor.field_type {
+                crate::parse::FieldType::Integer
+                | crate::parse::FieldType::Boolean
+                | crate::parse::FieldType::Array(_)
+                | crate::parse::FieldType::Byte
+                | crate::parse::FieldType::Char
+                | crate::parse::FieldType::Short
+                | crate::parse::FieldType::Float
+                | crate::parse::FieldType::ClassInstance(_) => {
+                    let klass_field = KlassField {
+                        class_name: class_name.to_owned(),
+                        field_name: field.name.to_owned(),
+                        field_type: field.descriptor.field_type.to_owned(),
+                        field_width: 1,
+                        parsed_field: field.to_owned(),
+                    };
+                    if field.access.r#static {
+                        static_fields.push(klass_field);
+                    } else {
+                        fields.push(klass_field)
+                    }
                 }
-            }
-            "identityHashCode" => {
-                let this_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?
-                    .to_owned();
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
-
-                if this_ref == 0 {
-                    frame.operand_stack.push(0);
-                } else {
-                    // FIXME: proper hash
-                    frame.operand_stack.push(this_ref);
+                crate::parse::FieldType::LongInteger | crate::parse::FieldType::Double => {
+                    let klass_field = KlassField {
+                        class_name: class_name.to_owned(),
+                        field_name: field.name.to_owned(),
+                        field_type: field.descriptor.field_type.to_owned(),
+                        field_width: 2,
+                        parsed_field: field.to_owned(),
+                    };
+                    if field.access.r#static {
+                        static_fields.push(klass_field);
+                    } else {
+                        fields.push(klass_field)
+                    }
                 }
             }
-            "initProperties" => {
-                let properties_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?
-                    .to_owned();
+        }
 
-                let mut put = |key: String, value: String| -> Result<(), Box<dyn Error>> {
-                    let current_frame = thread
-                        .thread_memory
-                        .jvm_stack
-                        .last_mut()
-                        .ok_or("no item on jvm stack")?;
-                    let key = java_string_from_string(global_memory, key.to_owned())?;
-                    let value = java_string_from_string(global_memory, value.to_owned())?;
-                    let mut frame = Frame::new(
-                        global_memory,
-                        "java/util/Properties".to_owned(),
-                        "put".to_owned(),
-                        MethodDescriptor {
-                            parameter_descriptors: vec![
-                                FieldType::ClassInstance("java/lang/Object".to_owned()),
-                                FieldType::ClassInstance("java/lang/Object".to_owned()),
-                            ],
-                            return_descriptor: crate::parse::ReturnDescriptor::FieldType(
-                                FieldType::ClassInstance("java/lang/Object".to_owned()),
-                            ),
-                        },
-                    )?;
-                    frame.local_variables[0] = properties_ref;
-                    frame.local_variables[1] = key;
-                    frame.local_variables[2] = value;
-                    thread.thread_memory.jvm_stack.push(frame);
-                    thread.run(global_memory)?;
-                    // println!("returned from thread");
-                    Ok(())
-                };
-                //  FIXME: initialize properties
-                put("line.separator".to_owned(), "\n".to_owned())?;
-                put("java.home".to_owned(), ".".to_owned())?;
-                put("user.home".to_owned(), ".".to_owned())?;
-                put("user.dir".to_owned(), ".".to_owned())?;
-                put("user.name".to_owned(), ".".to_owned())?;
-                put("file.separator".to_owned(), "/".to_owned())?;
-                put("path.separator".to_owned(), ":".to_owned())?;
-                put("file.encoding".to_owned(), "UTF-8".to_owned())?;
-
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let invoker_frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+        if let Some(class_info) = &parsed_class.super_class {
+            let mut parent_fields = self
+                .classes
+                .get(&class_info.name.to_owned())
+                .unwrap()
+                .as_instance_klass()
+                .unwrap()
+                .fields
+                .clone()
+                .unwrap();
+            parent_fields.append(&mut fields);
+            fields = parent_fields;
+        };
 
-                invoker_frame.operand_stack.push(properties_ref);
-            }
-            "nanoTime" => {
-                let duration_since_epoch = SystemTime::now()
-                    .duration_since(SystemTime::UNIX_EPOCH)
-                    .unwrap();
-                let timestamp_nanos = duration_since_epoch.as_nanos() as u64;
-                let mut csr = Cursor::new(timestamp_nanos.to_be_bytes());
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let invoker_frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+        class.fields = Some(fields);
+        class.static_fields = Some(static_fields);
 
-                invoker_frame
-                    .operand_stack
-                    .push(csr.read_u32::<BigEndian>()?);
-                invoker_frame
-                    .operand_stack
-                    .push(csr.read_u32::<BigEndian>()?);
-            }
+        // interfaces are never the class of a receiver, so they don't need a vtable
+        if !parsed_class.access.interface {
+            let class_id = self.classes.id(&class_name);
+            let (vtable, vtable_indices) = self.build_vtable(class_id, &class_name, parsed_class);
+            class.vtable = Some(vtable);
+            class.vtable_indices = Some(vtable_indices);
+        }
 
-            "setIn0" => {
-                let stream_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?
-                    .to_owned();
+        self.classes.insert(&class_name, Box::new(class));
+    }
 
-                let class = global_memory
-                    .method_area
-                    .classes
-                    .get_mut("java/lang/System")
-                    .unwrap()
-                    .as_mut_instance_klass()
-                    .unwrap();
-                let offset = class.static_field_offset_with_strings(
-                    "java/lang/System".to_owned(),
-                    "in".to_owned(),
-                )?;
-                class.static_field_values.as_mut().unwrap()[offset] = stream_ref;
+    fn build_vtable(
+        &self,
+        class_id: ClassId,
+        class_name: &str,
+        parsed_class: &ParsedClass,
+    ) -> (Vec<VTableEntry>, HashMap<(String, MethodDescriptor), usize>) {
+        let mut vtable = vec![];
+        let mut vtable_indices = HashMap::new();
+        if let Some(class_info) = &parsed_class.super_class {
+            let super_class = self
+                .classes
+                .get(&class_info.name)
+                .unwrap()
+                .as_instance_klass()
+                .unwrap();
+            vtable = super_class.vtable.clone().unwrap();
+            vtable_indices = super_class.vtable_indices.clone().unwrap();
+        }
+
+        for method in parsed_class.methods.iter() {
+            // static and private methods as well as constructors are never selected
+            if method.access.r#static || method.access.private || method.name.starts_with('<') {
+                continue;
             }
-            "setOut0" => {
-                let stream_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?
-                    .to_owned();
+            let entry = VTableEntry { class_id };
+            let key = (method.name.to_owned(), method.descriptor.to_owned());
+            if let Some(index) = vtable_indices.get(&key) {
+                vtable[*index] = entry;
+            } else {
+                vtable_indices.insert(key, vtable.len());
+                vtable.push(entry);
+            }
+        }
 
-                let class = global_memory
-                    .method_area
-                    .classes
-                    .get_mut("java/lang/System")
-                    .unwrap()
-                    .as_mut_instance_klass()
-                    .unwrap();
-                let offset = class.static_field_offset_with_strings(
-                    "java/lang/System".to_owned(),
-                    "out".to_owned(),
-                )?;
-                class.static_field_values.as_mut().unwrap()[offset] = stream_ref;
+        // methods not implemented by the class or its super classes come from the default
+        // methods of the superinterfaces. A more specific superinterface of this class might
+        // override the default method inherited from the super class, so those are looked up
+        // again as well.
+        let mut interface_names = vec![];
+        if self
+            .collect_superinterfaces(parsed_class, &mut interface_names)
+            .is_err()
+        {
+            return (vtable, vtable_indices);
+        }
+        for interface_name in interface_names.iter() {
+            let interface = self
+                .classes
+                .get(interface_name)
+                .and_then(|interface| interface.as_instance_klass())
+                .and_then(|interface| interface.parsed_class.as_ref());
+            let Some(interface) = interface else {
+                continue;
+            };
+            for method in interface.methods.iter() {
+                if method.access.r#static || method.access.private {
+                    continue;
+                }
+                let key = (method.name.to_owned(), method.descriptor.to_owned());
+                let index = vtable_indices.get(&key);
+                if let Some(index) = index {
+                    if !self.is_interface(self.classes.name(vtable[*index].class_id)) {
+                        continue;
+                    }
+                }
+                // abstract or conflicting methods are left to Frame::new to complain about
+                let Ok(selected_interface_name) = self.find_maximally_specific_method_in(
+                    &interface_names,
+                    class_name,
+                    &method.name,
+                    &method.descriptor,
+                ) else {
+                    continue;
+                };
+                let Some(selected_interface_id) = self.classes.lookup_id(&selected_interface_name)
+                else {
+                    continue;
+                };
+                let entry = VTableEntry {
+                    class_id: selected_interface_id,
+                };
+                if let Some(index) = index {
+                    vtable[*index] = entry;
+                } else {
+                    vtable_indices.insert(key, vtable.len());
+                    vtable.push(entry);
+                }
             }
-            "setErr0" => {
-                let stream_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?
-                    .to_owned();
+        }
 
-                let class = global_memory
-                    .method_area
-                    .classes
-                    .get_mut("java/lang/System")
-                    .unwrap()
-                    .as_mut_instance_klass()
-                    .unwrap();
-                let offset = class.static_field_offset_with_strings(
-                    "java/lang/System".to_owned(),
-                    "err".to_owned(),
+        (vtable, vtable_indices)
+    }
+
+    fn is_interface(&self, class_name: &str) -> bool {
+        self.classes
+            .get(class_name)
+            .and_then(|class| class.as_instance_klass())
+            .and_then(|class| class.parsed_class.as_ref())
+            .is_some_and(|class| class.access.interface)
+    }
+
+    // whether a value of field_type can be used where target_type is expected, like checkcast and
+    // instanceof decide it (JVMS §6.5 checkcast). Only references are assignable, primitives only
+    // show up as the component types of arrays, which have to match exactly.
+    fn is_assignable(
+        &self,
+        field_type: &FieldType,
+        target_type: &FieldType,
+    ) -> Result<bool, Box<dyn Error>> {
+        match (field_type, target_type) {
+            (FieldType::ClassInstance(class_name), FieldType::ClassInstance(target_name)) => {
+                if target_name == "java/lang/Object" || self.is_subclass_of(class_name, target_name)
+                {
+                    return Ok(true);
+                }
+                let mut interface_names = vec![];
+                self.collect_superinterfaces(
+                    &*self.parsed_class(class_name)?,
+                    &mut interface_names,
                 )?;
-                class.static_field_values.as_mut().unwrap()[offset] = stream_ref;
+                Ok(interface_names.contains(target_name))
             }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
+            (FieldType::Array(_), FieldType::ClassInstance(target_name)) => Ok(matches!(
+                target_name.as_str(),
+                "java/lang/Object" | "java/lang/Cloneable" | "java/io/Serializable"
+            )),
+            (FieldType::Array(component_type), FieldType::Array(target_component_type)) => {
+                match (component_type.as_ref(), target_component_type.as_ref()) {
+                    (
+                        FieldType::ClassInstance(_) | FieldType::Array(_),
+                        FieldType::ClassInstance(_) | FieldType::Array(_),
+                    ) => self.is_assignable(component_type, target_component_type),
+                    _ => Ok(component_type == target_component_type),
+                }
             }
-        },
-        "java/lang/StringUTF16" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "isBigEndian" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+            _ => Ok(false),
+        }
+    }
 
-                frame.operand_stack.push(1);
+    // whether class_name is super_class_name or one of its subclasses, only following super
+    // classes and not interfaces
+    fn is_subclass_of(&self, class_name: &str, super_class_name: &str) -> bool {
+        let mut class_name = Some(class_name.to_owned());
+        while let Some(name) = class_name {
+            if name == super_class_name {
+                return true;
             }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "java/lang/Float" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "floatToRawIntBits" => {
-                let float_read_as_u32 = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(0)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_u32::<BigEndian>()?;
-
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+            class_name = self
+                .classes
+                .get(&name)
+                .and_then(|class| class.as_instance_klass())
+                .and_then(|class| class.parsed_class.as_ref())
+                .and_then(|class| class.super_class.as_ref())
+                .map(|super_class| super_class.name.to_owned());
+        }
+        false
+    }
 
-                frame.operand_stack.push(float_read_as_u32);
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "java/lang/Double" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "doubleToRawLongBits" => {
-                let double_part1 = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(0)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_u32::<BigEndian>()?;
-                let double_part2 = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(1)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_u32::<BigEndian>()?;
+    fn declares_private_method(
+        &self,
+        class_name: &str,
+        method_name: &str,
+        method_descriptor: &MethodDescriptor,
+    ) -> bool {
+        self.classes
+            .get(class_name)
+            .and_then(|class| class.as_instance_klass())
+            .and_then(|class| class.parsed_class.as_ref())
+            .is_some_and(|class| {
+                class.methods.iter().any(|method| {
+                    method.access.private
+                        && method.name == method_name
+                        && method.descriptor == *method_descriptor
+                })
+            })
+    }
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    // the SourceFile attribute of a loaded class
+    fn source_file(&self, class_name: &str) -> Option<String> {
+        self.classes
+            .get(class_name)?
+            .as_instance_klass()?
+            .parsed_class
+            .as_ref()?
+            .source_file()
+            .cloned()
+    }
 
-                frame.operand_stack.push(double_part1);
-                frame.operand_stack.push(double_part2);
-            }
-            "longBitsToDouble" => {
-                let long_part1 = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(0)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_u32::<BigEndian>()?;
-                let long_part2 = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(1)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_u32::<BigEndian>()?;
+    fn vtable_index(
+        &self,
+        class_name: &str,
+        method_name: &str,
+        method_descriptor: &MethodDescriptor,
+    ) -> Option<usize> {
+        // arrays only have the methods of java/lang/Object, see vtable_entry
+        let class_name = if class_name.starts_with('[') {
+            "java/lang/Object"
+        } else {
+            class_name
+        };
+        self.classes
+            .get(class_name)?
+            .as_instance_klass()?
+            .vtable_indices
+            .as_ref()?
+            .get(&(method_name.to_owned(), method_descriptor.to_owned()))
+            .copied()
+    }
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    // the class declaring the method selected by index for an instance of class_name
+    fn vtable_entry(&self, class_name: &str, index: usize) -> Option<ClassId> {
+        // arrays only inherit the methods of java/lang/Object
+        let class_name = if class_name.starts_with('[') {
+            "java/lang/Object"
+        } else {
+            class_name
+        };
+        Some(
+            self.classes
+                .get(class_name)?
+                .as_instance_klass()?
+                .vtable
+                .as_ref()?
+                .get(index)?
+                .class_id,
+        )
+    }
 
-                frame.operand_stack.push(long_part1);
-                frame.operand_stack.push(long_part2);
+    // collects all direct and indirect superinterfaces of class_name and its super classes
+    fn collect_superinterfaces(
+        &self,
+        class: &ParsedClass,
+        interface_names: &mut Vec<String>,
+    ) -> Result<(), Box<dyn Error>> {
+        for interface in class.interfaces.iter() {
+            if !interface_names.contains(&interface.name) {
+                interface_names.push(interface.name.to_owned());
+                let interface = self.parsed_class(&interface.name)?;
+                self.collect_superinterfaces(&interface, interface_names)?;
             }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
+        }
+        if let Some(super_class) = class.super_class.as_ref() {
+            let super_class = self.parsed_class(&super_class.name)?;
+            self.collect_superinterfaces(&super_class, interface_names)?;
+        }
+        Ok(())
+    }
+
+    // whether super_class_name is a proper super class of class_name
+    fn is_super_class(
+        &self,
+        class_name: &str,
+        super_class_name: &str,
+    ) -> Result<bool, Box<dyn Error>> {
+        let mut class = self.parsed_class(class_name)?;
+        while let Some(super_class) = class.super_class.as_ref() {
+            if super_class.name == super_class_name {
+                return Ok(true);
             }
-        },
-        "java/lang/Throwable" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "fillInStackTrace" => {
-                // FIXME: dependant on other impls, not doing it for now
-                let this_ref = *current_frame
-                    .local_variables
-                    .get(0)
-                    .ok_or("no item in local_variables")?;
+            class = self.parsed_class(&super_class.name)?;
+        }
+        Ok(false)
+    }
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    // the name of the class a java/lang/Class object stands for
+    fn class_name_of_mirror(&self, class_ref: u32) -> Option<String> {
+        self.classes
+            .values()
+            .find(|class| class.get_java_clone() == Some(HeapRef(class_ref)))
+            .map(|class| class.get_name().to_owned())
+    }
 
-                frame.operand_stack.push(this_ref);
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "jdk/internal/misc/Unsafe" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "registerNatives" => {
-                // noop for now?
-            }
-            // NOTE: no idea what these values should actually be.
-            "arrayBaseOffset0" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    fn parsed_class(&self, class_name: &str) -> Result<Arc<ParsedClass>, Box<dyn Error>> {
+        Ok(self
+            .classes
+            .get(class_name)
+            .ok_or(format!("Class not found {} :(", class_name))?
+            .as_instance_klass()
+            .ok_or("not an InstanceKlass")?
+            .parsed_class
+            .as_ref()
+            .ok_or("no parsed_class")?
+            .clone())
+    }
 
-                frame.operand_stack.push(0);
-            }
-            "arrayIndexScale0" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    // looks for the maximally-specific superinterface method of class_name (JVMS §5.4.3.3)
+    // and returns the name of the interface declaring it. Exactly one of them has to be a
+    // default method.
+    fn find_maximally_specific_method(
+        &self,
+        class_name: &str,
+        method_name: &str,
+        type_descriptor: &MethodDescriptor,
+    ) -> Result<String, Box<dyn Error>> {
+        let mut interface_names = vec![];
+        self.collect_superinterfaces(&*self.parsed_class(class_name)?, &mut interface_names)?;
+        self.find_maximally_specific_method_in(
+            &interface_names,
+            class_name,
+            method_name,
+            type_descriptor,
+        )
+    }
 
-                frame.operand_stack.push(0);
+    // like find_maximally_specific_method, for the already collected superinterfaces of
+    // class_name
+    fn find_maximally_specific_method_in(
+        &self,
+        interface_names: &[String],
+        class_name: &str,
+        method_name: &str,
+        type_descriptor: &MethodDescriptor,
+    ) -> Result<String, Box<dyn Error>> {
+        // (interface name, whether the method is abstract)
+        let mut candidates = vec![];
+        for interface_name in interface_names.iter() {
+            let method = self
+                .classes
+                .get(interface_name)
+                .ok_or(format!("Class not found {} :(", interface_name))?
+                .as_instance_klass()
+                .ok_or("not an InstanceKlass")?
+                .parsed_class
+                .as_ref()
+                .ok_or("no parsed_class")?
+                .methods
+                .iter()
+                .find(|method| {
+                    method.name == method_name
+                        && &method.descriptor == type_descriptor
+                        && !method.access.private
+                        && !method.access.r#static
+                });
+            if let Some(method) = method {
+                candidates.push((interface_name.to_owned(), method.access.r#abstract));
             }
-            "addressSize0" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+        }
 
-                frame.operand_stack.push(0);
+        // drop every candidate overridden by a candidate of one of its subinterfaces
+        let mut maximally_specific = vec![];
+        for (interface_name, is_abstract) in candidates.iter() {
+            let mut is_overridden = false;
+            for (other_interface_name, _) in candidates.iter() {
+                if other_interface_name == interface_name {
+                    continue;
+                }
+                let mut superinterfaces = vec![];
+                self.collect_superinterfaces(
+                    &*self.parsed_class(other_interface_name)?,
+                    &mut superinterfaces,
+                )?;
+                if superinterfaces.contains(interface_name) {
+                    is_overridden = true;
+                    break;
+                }
             }
-            "isBigEndian0" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
-
-                frame.operand_stack.push(1);
+            if !is_overridden {
+                maximally_specific.push((interface_name.to_owned(), *is_abstract));
             }
-            "unalignedAccess0" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+        }
 
-                frame.operand_stack.push(1);
+        let default_methods = maximally_specific
+            .iter()
+            .filter(|(_, is_abstract)| !is_abstract)
+            .map(|(interface_name, _)| interface_name.to_owned())
+            .collect::<Vec<_>>();
+        match default_methods.len() {
+            1 => Ok(default_methods[0].to_owned()),
+            0 if !maximally_specific.is_empty() => {
+                Err(format!("AbstractMethodError: {class_name}.{method_name}").into())
             }
-            "objectFieldOffset1" => {
-                let c = current_frame
-                    .local_variables
-                    .get(1)
-                    .ok_or("no item in local_variables")?;
-                let name_ref = current_frame
-                    .local_variables
-                    .get(2)
-                    .ok_or("no item in local_variables")?;
+            0 => Err(format!("method {method_name} {type_descriptor:?} not found").into()),
+            _ => Err(format!(
+                "IncompatibleClassChangeError: conflicting default methods for {class_name}.{method_name}: {default_methods:?}"
+            )
+            .into()),
+        }
+    }
+}
 
-                let field_name = string_from_java_string(global_memory, *name_ref)?;
+#[derive(Debug, Clone)]
+struct VTableEntry {
+    // the class or interface declaring the selected method
+    class_id: ClassId,
+}
 
-                let klass = global_memory
-                    .method_area
-                    .classes
-                    .values()
-                    .find(|class| {
-                        let clone = class.get_java_clone();
-                        clone.is_some() && clone.unwrap() == *c
-                    })
-                    .ok_or("class not found?")?;
-
-                let offset = klass
-                    .as_instance_klass()
-                    .unwrap()
-                    .field_offset_with_strings(
-                        klass.get_name().to_owned(),
-                        field_name.to_owned(),
-                    )?;
+#[derive(Debug, Clone)]
+struct KlassField {
+    class_name: String,
+    field_name: String,
+    field_type: FieldType,
+    field_width: usize,
+    parsed_field: Field,
+}
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+// the steps a class goes through, see JVMS §5.3 - §5.5
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassState {
+    Loaded,
+    Linked,
+    // <clinit> is running
+    Initializing,
+    Initialized,
+    // <clinit> (or the one of a super class) failed, so the class cannot be used anymore
+    Erroneous,
+}
 
-                // return value is a long  but I dont really care
-                frame.operand_stack.push(0);
-                frame.operand_stack.push(offset as u32);
+// why a class could not be initialized (JVMS §5.5 steps 5 and 11). The instruction or reflective
+// call that triggered the initialization completes by throwing it, see
+// Thread::throw_initialization_error.
+#[derive(Debug)]
+enum InitializationError {
+    // the <clinit> of class_name threw exception, an Error or the ExceptionInInitializerError
+    // wrapping what it threw otherwise
+    Threw {
+        class_name: String,
+        exception: HeapRef,
+    },
+    // class_name is Erroneous since an earlier attempt, which is a NoClassDefFoundError
+    Erroneous {
+        class_name: String,
+    },
+}
+
+impl std::fmt::Display for InitializationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InitializationError::Threw { class_name, .. } => {
+                write!(f, "the initializer of {class_name} threw an exception")
             }
-            "storeFence" => {
-                // noop
+            InitializationError::Erroneous { class_name } => {
+                write!(
+                    f,
+                    "NoClassDefFoundError: Could not initialize class {class_name}"
+                )
             }
-            "compareAndSetInt" | "compareAndSetObject" => {
-                let object_ref = current_frame
-                    .local_variables
-                    .get(1)
-                    .ok_or("no item in local_variables")?;
-                let offset_part1 = *current_frame
-                    .local_variables
-                    .get(2)
-                    .ok_or("no item in local_variables")? as u64;
-                let offset_part2 = *current_frame
-                    .local_variables
-                    .get(3)
-                    .ok_or("no item in local_variables")? as u64;
-                let offset = Cursor::new((offset_part1 << 32 | offset_part2).to_be_bytes())
-                    .read_i64::<BigEndian>()?;
-                let expected = current_frame
-                    .local_variables
-                    .get(4)
-                    .ok_or("no item in local_variables")?;
-                let x = current_frame
-                    .local_variables
-                    .get(5)
-                    .ok_or("no item in local_variables")?;
+        }
+    }
+}
 
-                // println!("{object_ref} {offset} {expected} {x}");
+impl Error for InitializationError {}
 
-                let value_at_offset = global_memory
+trait Klass: Debug + Send {
+    fn get_state(&self) -> ClassState;
+    fn get_name(&self) -> &str;
+    fn get_java_clone(&self) -> Option<HeapRef>;
+    fn as_instance_klass(&self) -> Option<&InstanceKlass>;
+    fn as_mut_instance_klass(&mut self) -> Option<&mut InstanceKlass>;
+    fn as_array_klass(&self) -> Option<&ArrayKlass>;
+}
+
+#[derive(Debug)]
+struct InstanceKlass {
+    name: String,
+    // the field descriptor of the instances, as interned in Heap::descriptors
+    descriptor_id: u32,
+    parsed_class: Option<Arc<ParsedClass>>,
+    constant_pool: Option<Arc<RuntimeConstantPool>>,
+    static_field_values: Option<Vec<u32>>,
+    fields: Option<Vec<KlassField>>,
+    static_fields: Option<Vec<KlassField>>,
+    // the methods selected for invokevirtual and invokeinterface. Subclasses keep the indices
+    // of their super class, overriding methods replace the entry of the overridden one.
+    vtable: Option<Vec<VTableEntry>>,
+    // the index into the vtable for every method name and descriptor; invokeinterface has no
+    // fixed index to go by, so it looks up the receiver's entry here
+    vtable_indices: Option<HashMap<(String, MethodDescriptor), usize>>,
+    java_clone: Option<HeapRef>,
+    state: ClassState,
+    // whether the class still has to pass the verifier when it is linked
+    needs_verification: bool,
+    // resolved invokedynamic call sites, keyed by the index of their InvokeDynamic constant
+    call_sites: HashMap<u16, CallSite>,
+    // how often the methods were called and how many instructions they ran, indexed like the
+    // methods of parsed_class
+    method_counters: Vec<MethodCounters>,
+    // the decoded code of the methods, indexed like the methods of parsed_class and None for the
+    // abstract and native ones. Decoded when the class is linked.
+    decoded_code: Vec<Option<Arc<DecodedCode>>>,
+    // which slots hold references before every instruction of the methods, see gc. Indexed like
+    // decoded_code and None where the verifier can't tell, e.g. without stack maps. Computed when
+    // the class is linked.
+    reference_slots: Vec<Option<BTreeMap<usize, ReferenceSlots>>>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct MethodCounters {
+    calls: u64,
+    // only counted once a frame of the method is popped, so the frames still on a stack are
+    // missing
+    instructions: u64,
+}
+
+// the result of running the bootstrap method of an invokedynamic instruction. Instead of
+// actually running the bootstrap methods, the well known ones are recognized and handled
+// natively.
+#[derive(Debug, Clone)]
+enum CallSite {
+    // java/lang/invoke/StringConcatFactory; \u{1} in the recipe marks an argument, \u{2} a
+    // constant
+    StringConcat {
+        recipe: Vec<u16>,
+        constants: Vec<Constant>,
+    },
+    // java/lang/invoke/LambdaMetafactory; every invocation creates a new instance of the spun
+    // class, holding the captured arguments
+    Lambda {
+        class_name: String,
+    },
+}
+
+// a class implementing a functional interface, spun up for a lambda or a method reference.
+// Its only method is native and forwards to the implementation method.
+#[derive(Debug, Clone)]
+struct LambdaClass {
+    interface_method_descriptor: MethodDescriptor,
+    // the interface method descriptor with the type arguments of the functional interface in
+    // place of their erasure, e.g. (Ljava/lang/Integer;)Ljava/lang/Integer; for a
+    // Function<Integer, Integer>, which the arguments and the result are adapted from and to
+    instantiated_method_descriptor: MethodDescriptor,
+    // how many of the parameters of the implementation method the captured arguments fill
+    captured_count: usize,
+    implementation_kind: RefKind,
+    implementation_class: String,
+    implementation_name: String,
+    implementation_descriptor: MethodDescriptor,
+}
+
+impl Klass for InstanceKlass {
+    fn get_state(&self) -> ClassState {
+        self.state
+    }
+
+    fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn get_java_clone(&self) -> Option<HeapRef> {
+        self.java_clone
+    }
+
+    fn as_instance_klass(&self) -> Option<&InstanceKlass> {
+        Some(self)
+    }
+    fn as_mut_instance_klass(&mut self) -> Option<&mut InstanceKlass> {
+        Some(self)
+    }
+
+    fn as_array_klass(&self) -> Option<&ArrayKlass> {
+        None
+    }
+}
+
+impl InstanceKlass {
+    fn is_linked(&self) -> bool {
+        self.state != ClassState::Loaded
+    }
+
+    fn find_static_field(
+        &self,
+        global_memory: &GlobalMemory,
+        searched_field_name: String,
+    ) -> Result<(String, usize), Box<dyn Error>> {
+        if self
+            .static_fields
+            .as_ref()
+            .unwrap()
+            .iter()
+            .find(|field| field.field_name == searched_field_name)
+            .is_some()
+        {
+            return Ok((
+                self.get_name().to_owned(),
+                self.static_field_offset_with_strings(
+                    self.get_name().to_owned(),
+                    searched_field_name,
+                )?,
+            ));
+        }
+
+        if self.parsed_class.as_ref().unwrap().super_class.is_none() {
+            return Err("failed to find static field".into());
+        }
+        let super_class_name = self
+            .parsed_class
+            .as_ref()
+            .unwrap()
+            .super_class
+            .as_ref()
+            .unwrap()
+            .name
+            .to_owned();
+        let super_klass = global_memory
+            .method_area
+            .classes
+            .get(&super_class_name)
+            .ok_or("super class not found")?;
+        super_klass
+            .as_instance_klass()
+            .unwrap()
+            .find_static_field(global_memory, searched_field_name)
+    }
+    fn static_field_offset_with_strings(
+        &self,
+        searched_class_name: String,
+        searched_field_name: String,
+    ) -> Result<usize, Box<dyn Error>> {
+        let mut offset = 0;
+
+        for KlassField {
+            class_name,
+            field_name,
+            field_width,
+            ..
+        } in self.static_fields.as_ref().unwrap().iter()
+        {
+            if searched_class_name != *class_name {
+                offset += field_width;
+                continue;
+            }
+            if searched_field_name != *field_name {
+                offset += field_width;
+                continue;
+            }
+
+            return Ok(offset);
+        }
+        // FIXME: 0 is not a error case :^)
+        Err(format!("couldnt calculate static field offset for  \"{searched_class_name}\"\"{searched_field_name}\" because field was not found").into())
+    }
+    #[allow(dead_code)]
+    fn static_field_offset(&self, field_ref_constant: Constant) -> Result<usize, Box<dyn Error>> {
+        let field_ref = field_ref_constant.as_field_ref().unwrap();
+        let searched_class_name = field_ref.0.name;
+        let searched_field_name = field_ref
+            .1
+            .to_owned()
+            .as_name_and_type()
+            .ok_or("not a name_and_type")?
+            .0;
+        self.static_field_offset_with_strings(searched_class_name, searched_field_name)
+    }
+    fn field_offset_with_strings(
+        &self,
+        searched_class_name: String,
+        searched_field_name: String,
+    ) -> Result<usize, Box<dyn Error>> {
+        self.find_field(&searched_class_name, &searched_field_name)
+            .map(|(offset, _)| offset)
+    }
+    // the offset of an instance field and the field itself
+    fn find_field(
+        &self,
+        searched_class_name: &str,
+        searched_field_name: &str,
+    ) -> Result<(usize, &KlassField), Box<dyn Error>> {
+        // the fields of the super classes are laid out first, so a field declared by the searched
+        // class itself wins, and otherwise the last declaration with that name is the one of the
+        // nearest super class (JVMS §5.4.3.2). This only holds if self is the searched class or
+        // one of its super classes, which is why getfield and putfield resolve the offset on the
+        // referenced class rather than on the class of the object.
+        let mut offset = 0;
+        let mut inherited = None;
+
+        for field in self.fields.as_ref().unwrap().iter() {
+            if searched_field_name == field.field_name {
+                if searched_class_name == field.class_name {
+                    return Ok((offset, field));
+                }
+                inherited = Some((offset, field));
+            }
+            offset += field.field_width;
+        }
+        if let Some(inherited) = inherited {
+            return Ok(inherited);
+        }
+        Err(format!("couldnt calculate field offset for  \"{searched_class_name}\"\"{searched_field_name}\" because field was not found: {:?}", self.fields).into())
+    }
+    // the offset of the instance field a field_ref refers to, and whether it is volatile
+    fn field_offset(&self, field_ref_constant: Constant) -> Result<(usize, bool), Box<dyn Error>> {
+        let field_ref = field_ref_constant.as_field_ref().unwrap();
+        let searched_class_name = field_ref.0.name;
+        let searched_field_name = field_ref
+            .1
+            .to_owned()
+            .as_name_and_type()
+            .ok_or("not a name_and_type")?
+            .0;
+        let (offset, field) = self.find_field(&searched_class_name, &searched_field_name)?;
+        Ok((offset, field.parsed_field.access.volatile))
+    }
+    // whether a static field declared by this class is volatile
+    fn is_volatile_static_field(&self, searched_field_name: &str) -> bool {
+        self.static_fields.as_ref().is_some_and(|static_fields| {
+            static_fields.iter().any(|field| {
+                field.class_name == self.name
+                    && field.field_name == searched_field_name
+                    && field.parsed_field.access.volatile
+            })
+        })
+    }
+}
+
+#[derive(Debug)]
+struct ArrayKlass {
+    name: String,
+    java_clone: Option<HeapRef>,
+}
+
+impl Klass for ArrayKlass {
+    fn get_state(&self) -> ClassState {
+        // array classes have nothing to initialize
+        ClassState::Initialized
+    }
+
+    fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn get_java_clone(&self) -> Option<HeapRef> {
+        self.java_clone
+    }
+
+    fn as_instance_klass(&self) -> Option<&InstanceKlass> {
+        None
+    }
+
+    fn as_mut_instance_klass(&mut self) -> Option<&mut InstanceKlass> {
+        None
+    }
+
+    fn as_array_klass(&self) -> Option<&ArrayKlass> {
+        Some(self)
+    }
+}
+
+// the class of a primitive type or void, like int.class. It only exists to have a mirror.
+#[derive(Debug)]
+struct PrimitiveKlass {
+    name: String,
+    java_clone: Option<HeapRef>,
+}
+
+impl Klass for PrimitiveKlass {
+    fn get_state(&self) -> ClassState {
+        ClassState::Initialized
+    }
+
+    fn get_name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    fn get_java_clone(&self) -> Option<HeapRef> {
+        self.java_clone
+    }
+
+    fn as_instance_klass(&self) -> Option<&InstanceKlass> {
+        None
+    }
+
+    fn as_mut_instance_klass(&mut self) -> Option<&mut InstanceKlass> {
+        None
+    }
+
+    fn as_array_klass(&self) -> Option<&ArrayKlass> {
+        None
+    }
+}
+
+#[derive(Debug)]
+struct RuntimeConstantPool {
+    // the class the constants are from, None for stub frames
+    class: Option<Arc<ParsedClass>>,
+    // what the instructions referring to the constants resolved them to, indexed like pool. An
+    // entry is only filled in once resolving it succeeded, and never changes after that.
+    resolved: Vec<OnceLock<ResolvedConstant>>,
+}
+
+impl RuntimeConstantPool {
+    fn new(class: Option<Arc<ParsedClass>>) -> RuntimeConstantPool {
+        let len = class.as_ref().map_or(0, |class| class.constant_pool.len());
+        RuntimeConstantPool {
+            class,
+            resolved: (0..len).map(|_| OnceLock::new()).collect(),
+        }
+    }
+
+    // the constants, indexed by their constant pool index - 1
+    fn pool(&self) -> &[Constant] {
+        self.class
+            .as_ref()
+            .map_or(&[], |class| class.constant_pool.as_slice())
+    }
+
+    // the constant at a constant pool index, as resolved before
+    fn resolved(&self, index: usize) -> Option<ResolvedConstant> {
+        self.resolved.get(index.checked_sub(1)?)?.get().cloned()
+    }
+
+    fn resolve(&self, index: usize, resolved: ResolvedConstant) {
+        if let Some(entry) = index
+            .checked_sub(1)
+            .and_then(|index| self.resolved.get(index))
+        {
+            // resolving the same constant again comes to the same result
+            let _ = entry.set(resolved);
+        }
+    }
+}
+
+// a constant as resolved by ldc, the field instructions and the invoke instructions, so running
+// the same instruction again doesn't look up the classes and fields or parse the descriptors again
+#[derive(Debug, Clone)]
+enum ResolvedConstant {
+    // an interned String or the mirror of a class, both of which are never collected
+    Reference(HeapRef),
+    // the offset is the same in all subclasses of the referenced class
+    InstanceField(ResolvedField),
+    // the field and the class declaring it, which is only resolved once the class is initialized
+    // so that no other thread gets past an initialization that is still running
+    StaticField(ClassId, ResolvedField),
+    Method(ResolvedMethod),
+}
+
+#[derive(Debug, Clone)]
+struct ResolvedField {
+    offset: usize,
+    // 2 for longs and doubles
+    width: usize,
+    volatile: bool,
+}
+
+#[derive(Debug, Clone)]
+struct ResolvedMethod {
+    // the class the method is selected in by invokestatic and invokespecial, and the referenced
+    // class for invokevirtual and invokeinterface
+    class_id: ClassId,
+    name: String,
+    descriptor: MethodDescriptor,
+    // the index of the referenced method in the vtables, for invokevirtual
+    vtable_index: Option<usize>,
+}
+
+// the instance field a field_ref refers to, for getfield and putfield. The class of the object is
+// the referenced class or one of its subclasses, so that class is linked already.
+fn resolve_instance_field(
+    global_memory: &GlobalMemory,
+    constant_pool: &RuntimeConstantPool,
+    index: usize,
+) -> Result<ResolvedField, Box<dyn Error>> {
+    if let Some(ResolvedConstant::InstanceField(field)) = constant_pool.resolved(index) {
+        return Ok(field);
+    }
+    let constant = constant_pool
+        .pool()
+        .get(index - 1)
+        .ok_or("expected ur mom 5")?
+        .to_owned();
+    let (class_info, name_and_type) = constant.as_field_ref().ok_or("expected field_ref")?;
+    let (_, field_descriptor_text) = name_and_type
+        .as_name_and_type()
+        .ok_or("expected name_and_type")?;
+    let field_descriptor = parse_field_descriptor(&field_descriptor_text)?;
+    let class_name = class_info.name;
+    let (offset, volatile) = global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .ok_or(format!("didnt find class {class_name} in method_area"))?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .field_offset(constant)?;
+    let field = ResolvedField {
+        offset,
+        width: field_descriptor.field_type.width(),
+        volatile,
+    };
+    constant_pool.resolve(index, ResolvedConstant::InstanceField(field.to_owned()));
+    Ok(field)
+}
+
+// the static field a field_ref refers to and the class declaring it, for getstatic and putstatic,
+// which initialize the referenced class
+fn resolve_static_field(
+    global_memory: &mut GlobalMemory,
+    constant_pool: &RuntimeConstantPool,
+    index: usize,
+) -> Result<(ClassId, ResolvedField), Box<dyn Error>> {
+    if let Some(ResolvedConstant::StaticField(class_id, field)) = constant_pool.resolved(index) {
+        return Ok((class_id, field));
+    }
+    let field_ref_constant = constant_pool
+        .pool()
+        .get(index - 1)
+        .ok_or("expected ur mom 3")?
+        .to_owned();
+    let (class_info, name_and_type) = field_ref_constant
+        .as_field_ref()
+        .ok_or(format!("not a field_ref 1 {:?}", field_ref_constant))?;
+    let (name, field_descriptor_text) = name_and_type
+        .as_name_and_type()
+        .ok_or("not a NameAndType")?;
+    let type_descriptor = parse_field_descriptor(&field_descriptor_text)?;
+
+    global_memory.ensure_class(class_info.name.as_str())?;
+
+    // the field might be declared in a super class
+    let class = global_memory
+        .method_area
+        .classes
+        .get(&class_info.name)
+        .ok_or("class not found in method area")?;
+    let initialized = class.get_state() == ClassState::Initialized;
+    let (name_of_class_with_field, offset) = class
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .find_static_field(global_memory, name.to_owned())?;
+    let volatile = global_memory
+        .method_area
+        .classes
+        .get(&name_of_class_with_field)
+        .ok_or("class not found in method area")?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .is_volatile_static_field(&name);
+    let field = ResolvedField {
+        offset,
+        width: type_descriptor.field_type.width(),
+        volatile,
+    };
+    let class_id = global_memory
+        .method_area
+        .classes
+        .id(&name_of_class_with_field);
+    if initialized {
+        constant_pool.resolve(
+            index,
+            ResolvedConstant::StaticField(class_id, field.to_owned()),
+        );
+    }
+    Ok((class_id, field))
+}
+
+#[derive(Debug)]
+struct Thread {
+    thread_memory: ThreadMemory,
+    is_throwing: bool,
+    java_clone: Option<HeapRef>,
+    // see threads, MAIN_THREAD_ID for the thread that created the vm
+    id: u64,
+    // how many interpreter loops are running inside each other, only the outermost one collects
+    interpreter_depth: usize,
+    // how many backward branches are left until the next thread gets to run, and the depth of the
+    // stack and the instruction_counter they are noticed by, see threads::safepoint
+    slice_left: usize,
+    last_instruction: (usize, usize),
+    // set while a green thread is run by the scheduler, which its outermost loop returns to when
+    // it yields, see threads::run_green_threads
+    driven: bool,
+    // set by a native that blocked and has to run again once the thread is resumed
+    yielding: bool,
+    // when a thread in Thread.sleep wakes up, see threads::thread_sleep
+    sleeping_until: Option<Instant>,
+}
+
+// the natives of the vm itself. Like any native, they run in a frame of their own, with the
+// arguments in its local variables, and push their result onto the operand stack of the invoker.
+type BuiltinNative = fn(&mut Thread, &mut GlobalMemory) -> Result<(), Box<dyn Error>>;
+
+// natives supplied by host applications through Vm::register_native. They get the arguments and
+// return the result as JavaValues, `this` being the first argument of instance methods. They are
+// called on whichever host thread the java thread invoking them runs on, see threads.
+pub type HostNative =
+    dyn Fn(&mut NativeEnv, &[JavaValue]) -> Result<JavaValue, Box<dyn Error>> + Send + Sync;
+
+#[derive(Clone)]
+enum NativeMethod {
+    Builtin(BuiltinNative),
+    Host(Arc<HostNative>),
+    // the address of a function of a JNI shared library, see jni
+    Jni(usize),
+}
+
+impl Debug for NativeMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NativeMethod::Builtin(_) => write!(f, "Builtin"),
+            NativeMethod::Host(_) => write!(f, "Host"),
+            NativeMethod::Jni(function) => write!(f, "Jni({function:#x})"),
+        }
+    }
+}
+
+// the natives are keyed by class name, method name and method descriptor
+type NativeKey = (String, String, String);
+
+fn run_native_methods(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    current_frame.running_native = true;
+
+    if let Some(lambda_class) = global_memory
+        .lambda_classes
+        .get(current_frame.class_name())
+        .cloned()
+    {
+        return run_lambda_method(thread, global_memory, lambda_class);
+    }
+
+    let method = current_frame
+        .method
+        .as_ref()
+        .ok_or("native frame without a method")?;
+    let key = (
+        current_frame.class_name().to_owned(),
+        method.name.to_owned(),
+        method.descriptor.descriptor(),
+    );
+    log!(Native, Debug, "calling {}.{}{}", key.0, key.1, key.2);
+    let native = match global_memory.natives.get(&key) {
+        Some(native) => native.clone(),
+        None => {
+            // natives that aren't known yet may come from a library loaded by System.loadLibrary
+            let function = jni::find_native(global_memory, &key.0, &key.1, &key.2)
+                .ok_or_else(|| format!("UnsatisfiedLinkError: {}.{}{}", key.0, key.1, key.2))?;
+            log!(
+                Native,
+                Info,
+                "bound {}.{}{} to a JNI function",
+                key.0,
+                key.1,
+                key.2
+            );
+            global_memory
+                .natives
+                .insert(key, NativeMethod::Jni(function as usize));
+            NativeMethod::Jni(function as usize)
+        }
+    };
+    match native {
+        NativeMethod::Builtin(function) => function(thread, global_memory),
+        NativeMethod::Host(function) => run_host_native(thread, global_memory, function.as_ref()),
+        NativeMethod::Jni(function) => {
+            jni::run_jni_native(thread, global_memory, function as *const std::ffi::c_void)
+        }
+    }
+}
+
+fn run_host_native(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    function: &HostNative,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let method = current_frame
+        .method
+        .as_ref()
+        .ok_or("native frame without a method")?;
+
+    let mut arguments = vec![];
+    let mut index = 0;
+    if !method.access.r#static {
+        arguments.push(JavaValue::Reference(HeapRef(
+            *current_frame
+                .local_variables
+                .first()
+                .ok_or("no item in local_variables")?,
+        )));
+        index = 1;
+    }
+    for field_type in method.descriptor.parameter_descriptors.iter() {
+        let slots = current_frame
+            .local_variables
+            .get(index..index + field_type.width())
+            .ok_or("no item in local_variables")?;
+        arguments.push(JavaValue::from_slots(field_type, slots)?);
+        index += field_type.width();
+    }
+    let return_descriptor = method.descriptor.return_descriptor.clone();
+
+    let result = function(&mut NativeEnv { global_memory }, &arguments)?;
+
+    if let crate::parse::ReturnDescriptor::FieldType(field_type) = return_descriptor {
+        return thread.return_value(&result.slots(&field_type)?);
+    }
+    Ok(())
+}
+
+// what natives of host applications can access of the vm
+pub struct NativeEnv<'a> {
+    global_memory: &'a mut GlobalMemory,
+}
+
+impl NativeEnv<'_> {
+    pub fn new_string(&mut self, value: &str) -> Result<JavaValue, Box<dyn Error>> {
+        self.global_memory.new_string_value(value)
+    }
+
+    pub fn get_string(&self, value: &JavaValue) -> Result<String, Box<dyn Error>> {
+        self.global_memory.string_value(value)
+    }
+
+    pub fn new_array(
+        &mut self,
+        array_type: &str,
+        elements: &[JavaValue],
+    ) -> Result<JavaValue, Box<dyn Error>> {
+        self.global_memory.new_array_value(array_type, elements)
+    }
+
+    pub fn array_elements(&self, value: &JavaValue) -> Result<Vec<JavaValue>, Box<dyn Error>> {
+        self.global_memory.array_element_values(value)
+    }
+}
+
+fn builtin_natives() -> HashMap<NativeKey, NativeMethod> {
+    let builtins: &[(&str, &str, &str, BuiltinNative)] = &[
+        (
+            "java/lang/Object",
+            "getClass",
+            "()Ljava/lang/Class;",
+            object_get_class,
+        ),
+        ("java/lang/Object", "registerNatives", "()V", native_noop),
+        ("java/lang/Object", "hashCode", "()I", object_hash_code),
+        (
+            "java/lang/Object",
+            "clone",
+            "()Ljava/lang/Object;",
+            object_clone,
+        ),
+        ("java/lang/Object", "wait", "(J)V", threads::object_wait),
+        ("java/lang/Object", "notify", "()V", threads::object_notify),
+        ("java/lang/Object", "notifyAll", "()V", threads::object_notify_all),
+        ("java/lang/Class", "registerNatives", "()V", native_noop),
+        (
+            "java/lang/Class",
+            "forName0",
+            "(Ljava/lang/String;ZLjava/lang/ClassLoader;Ljava/lang/Class;)Ljava/lang/Class;",
+            class_for_name0,
+        ),
+        (
+            "java/lang/Class",
+            "initClassName",
+            "()Ljava/lang/String;",
+            class_init_class_name,
+        ),
+        (
+            "java/lang/Class",
+            "desiredAssertionStatus0",
+            "(Ljava/lang/Class;)Z",
+            class_desired_assertion_status0,
+        ),
+        (
+            "java/lang/Class",
+            "getPrimitiveClass",
+            "(Ljava/lang/String;)Ljava/lang/Class;",
+            class_get_primitive_class,
+        ),
+        ("java/lang/Class", "isArray", "()Z", class_is_array),
+        ("java/lang/Class", "isInterface", "()Z", class_is_interface),
+        ("java/lang/Class", "isPrimitive", "()Z", class_is_primitive),
+        (
+            "java/lang/Class",
+            "isAssignableFrom",
+            "(Ljava/lang/Class;)Z",
+            class_is_assignable_from,
+        ),
+        (
+            "java/lang/Class",
+            "isInstance",
+            "(Ljava/lang/Object;)Z",
+            class_is_instance,
+        ),
+        (
+            "java/lang/Class",
+            "getSuperclass",
+            "()Ljava/lang/Class;",
+            class_get_superclass,
+        ),
+        (
+            "java/lang/Class",
+            "getInterfaces0",
+            "()[Ljava/lang/Class;",
+            class_get_interfaces0,
+        ),
+        (
+            "java/lang/Class",
+            "getDeclaredMethods0",
+            "(Z)[Ljava/lang/reflect/Method;",
+            class_get_declared_methods0,
+        ),
+        (
+            "java/lang/Class",
+            "getDeclaredConstructors0",
+            "(Z)[Ljava/lang/reflect/Constructor;",
+            class_get_declared_constructors0,
+        ),
+        (
+            "java/lang/Class",
+            "getModifiers",
+            "()I",
+            class_get_modifiers,
+        ),
+        ("java/lang/System", "registerNatives", "()V", native_noop),
+        (
+            "java/lang/System",
+            "arraycopy",
+            "(Ljava/lang/Object;ILjava/lang/Object;II)V",
+            system_arraycopy,
+        ),
+        (
+            "java/lang/System",
+            "identityHashCode",
+            "(Ljava/lang/Object;)I",
+            system_identity_hash_code,
+        ),
+        (
+            "java/lang/System",
+            "initProperties",
+            "(Ljava/util/Properties;)Ljava/util/Properties;",
+            system_init_properties,
+        ),
+        ("java/lang/System", "nanoTime", "()J", system_nano_time),
+        (
+            "java/lang/System",
+            "setIn0",
+            "(Ljava/io/InputStream;)V",
+            system_set_in0,
+        ),
+        (
+            "java/lang/System",
+            "setOut0",
+            "(Ljava/io/PrintStream;)V",
+            system_set_out0,
+        ),
+        (
+            "java/lang/System",
+            "setErr0",
+            "(Ljava/io/PrintStream;)V",
+            system_set_err0,
+        ),
+        (
+            "java/lang/System",
+            "mapLibraryName",
+            "(Ljava/lang/String;)Ljava/lang/String;",
+            system_map_library_name,
+        ),
+        (
+            "java/lang/String",
+            "intern",
+            "()Ljava/lang/String;",
+            string_intern,
+        ),
+        (
+            "java/lang/StringUTF16",
+            "isBigEndian",
+            "()Z",
+            string_utf16_is_big_endian,
+        ),
+        (
+            "java/lang/Float",
+            "floatToRawIntBits",
+            "(F)I",
+            float_float_to_raw_int_bits,
+        ),
+        (
+            "java/lang/Float",
+            "intBitsToFloat",
+            "(I)F",
+            float_int_bits_to_float,
+        ),
+        (
+            "java/lang/Double",
+            "doubleToRawLongBits",
+            "(D)J",
+            double_double_to_raw_long_bits,
+        ),
+        (
+            "java/lang/Double",
+            "longBitsToDouble",
+            "(J)D",
+            double_long_bits_to_double,
+        ),
+        ("java/lang/StrictMath", "sin", "(D)D", strict_math_sin),
+        ("java/lang/StrictMath", "cos", "(D)D", strict_math_cos),
+        ("java/lang/StrictMath", "tan", "(D)D", strict_math_tan),
+        ("java/lang/StrictMath", "asin", "(D)D", strict_math_asin),
+        ("java/lang/StrictMath", "acos", "(D)D", strict_math_acos),
+        ("java/lang/StrictMath", "atan", "(D)D", strict_math_atan),
+        ("java/lang/StrictMath", "log", "(D)D", strict_math_log),
+        ("java/lang/StrictMath", "log10", "(D)D", strict_math_log10),
+        ("java/lang/StrictMath", "sqrt", "(D)D", strict_math_sqrt),
+        ("java/lang/StrictMath", "sinh", "(D)D", strict_math_sinh),
+        ("java/lang/StrictMath", "cosh", "(D)D", strict_math_cosh),
+        ("java/lang/StrictMath", "tanh", "(D)D", strict_math_tanh),
+        ("java/lang/StrictMath", "expm1", "(D)D", strict_math_expm1),
+        ("java/lang/StrictMath", "log1p", "(D)D", strict_math_log1p),
+        ("java/lang/StrictMath", "atan2", "(DD)D", strict_math_atan2),
+        (
+            "java/lang/StrictMath",
+            "IEEEremainder",
+            "(DD)D",
+            strict_math_ieee_remainder,
+        ),
+        (
+            "java/lang/Throwable",
+            "fillInStackTrace",
+            "(I)Ljava/lang/Throwable;",
+            throwable_fill_in_stack_trace,
+        ),
+        (
+            "java/lang/StackTraceElement",
+            "initStackTraceElements",
+            "([Ljava/lang/StackTraceElement;Ljava/lang/Throwable;)V",
+            stack_trace_element_init_stack_trace_elements,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "registerNatives",
+            "()V",
+            native_noop,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "arrayBaseOffset0",
+            "(Ljava/lang/Class;)I",
+            unsafe_array_base_offset0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "arrayIndexScale0",
+            "(Ljava/lang/Class;)I",
+            unsafe_array_index_scale0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "addressSize0",
+            "()I",
+            unsafe_address_size0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "isBigEndian0",
+            "()Z",
+            unsafe_is_big_endian0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "unalignedAccess0",
+            "()Z",
+            unsafe_unaligned_access0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "allocateMemory0",
+            "(J)J",
+            unsafe_allocate_memory0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "reallocateMemory0",
+            "(JJ)J",
+            unsafe_reallocate_memory0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "freeMemory0",
+            "(J)V",
+            unsafe_free_memory0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "setMemory0",
+            "(Ljava/lang/Object;JJB)V",
+            unsafe_set_memory0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "copyMemory0",
+            "(Ljava/lang/Object;JLjava/lang/Object;JJ)V",
+            unsafe_copy_memory0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "copySwapMemory0",
+            "(Ljava/lang/Object;JLjava/lang/Object;JJJ)V",
+            unsafe_copy_swap_memory0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "ensureClassInitialized0",
+            "(Ljava/lang/Class;)V",
+            unsafe_ensure_class_initialized0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "shouldBeInitialized0",
+            "(Ljava/lang/Class;)Z",
+            unsafe_should_be_initialized0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "defineClass0",
+            "(Ljava/lang/String;[BIILjava/lang/ClassLoader;Ljava/security/ProtectionDomain;)Ljava/lang/Class;",
+            unsafe_define_class0,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "pageSize",
+            "()I",
+            unsafe_page_size,
+        ),
+        (
+            "java/util/concurrent/atomic/AtomicLong",
+            "VMSupportsCS8",
+            "()Z",
+            atomic_long_vm_supports_cs8,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "objectFieldOffset1",
+            "(Ljava/lang/Class;Ljava/lang/String;)J",
+            unsafe_object_field_offset1,
+        ),
+        ("jdk/internal/misc/Unsafe", "loadFence", "()V", unsafe_load_fence),
+        ("jdk/internal/misc/Unsafe", "storeFence", "()V", unsafe_store_fence),
+        ("jdk/internal/misc/Unsafe", "fullFence", "()V", unsafe_full_fence),
+        (
+            "jdk/internal/misc/Unsafe",
+            "compareAndSetInt",
+            "(Ljava/lang/Object;JII)Z",
+            unsafe_compare_and_set_int_or_object,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "compareAndSetObject",
+            "(Ljava/lang/Object;JLjava/lang/Object;Ljava/lang/Object;)Z",
+            unsafe_compare_and_set_int_or_object,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "compareAndSetLong",
+            "(Ljava/lang/Object;JJJ)Z",
+            unsafe_compare_and_set_long,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getInt",
+            "(Ljava/lang/Object;J)I",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putInt",
+            "(Ljava/lang/Object;JI)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getObject",
+            "(Ljava/lang/Object;J)Ljava/lang/Object;",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putObject",
+            "(Ljava/lang/Object;JLjava/lang/Object;)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getBoolean",
+            "(Ljava/lang/Object;J)Z",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putBoolean",
+            "(Ljava/lang/Object;JZ)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getByte",
+            "(Ljava/lang/Object;J)B",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putByte",
+            "(Ljava/lang/Object;JB)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getShort",
+            "(Ljava/lang/Object;J)S",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putShort",
+            "(Ljava/lang/Object;JS)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getChar",
+            "(Ljava/lang/Object;J)C",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putChar",
+            "(Ljava/lang/Object;JC)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getFloat",
+            "(Ljava/lang/Object;J)F",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putFloat",
+            "(Ljava/lang/Object;JF)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getLong",
+            "(Ljava/lang/Object;J)J",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putLong",
+            "(Ljava/lang/Object;JJ)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getDouble",
+            "(Ljava/lang/Object;J)D",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putDouble",
+            "(Ljava/lang/Object;JD)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getIntVolatile",
+            "(Ljava/lang/Object;J)I",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putIntVolatile",
+            "(Ljava/lang/Object;JI)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getObjectVolatile",
+            "(Ljava/lang/Object;J)Ljava/lang/Object;",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putObjectVolatile",
+            "(Ljava/lang/Object;JLjava/lang/Object;)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getBooleanVolatile",
+            "(Ljava/lang/Object;J)Z",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putBooleanVolatile",
+            "(Ljava/lang/Object;JZ)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getByteVolatile",
+            "(Ljava/lang/Object;J)B",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putByteVolatile",
+            "(Ljava/lang/Object;JB)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getShortVolatile",
+            "(Ljava/lang/Object;J)S",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putShortVolatile",
+            "(Ljava/lang/Object;JS)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getCharVolatile",
+            "(Ljava/lang/Object;J)C",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putCharVolatile",
+            "(Ljava/lang/Object;JC)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getFloatVolatile",
+            "(Ljava/lang/Object;J)F",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putFloatVolatile",
+            "(Ljava/lang/Object;JF)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getLongVolatile",
+            "(Ljava/lang/Object;J)J",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putLongVolatile",
+            "(Ljava/lang/Object;JJ)V",
+            unsafe_put_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "getDoubleVolatile",
+            "(Ljava/lang/Object;J)D",
+            unsafe_get_value,
+        ),
+        (
+            "jdk/internal/misc/Unsafe",
+            "putDoubleVolatile",
+            "(Ljava/lang/Object;JD)V",
+            unsafe_put_value,
+        ),
+        (
+            "java/lang/Shutdown",
+            "beforeHalt",
+            "()V",
+            shutdown_before_halt,
+        ),
+        ("java/lang/Shutdown", "halt0", "(I)V", shutdown_halt0),
+        (
+            "java/lang/Runtime",
+            "availableProcessors",
+            "()I",
+            runtime_available_processors,
+        ),
+        ("java/lang/Runtime", "gc", "()V", runtime_gc),
+        (
+            "java/lang/Runtime",
+            "freeMemory",
+            "()J",
+            runtime_free_memory,
+        ),
+        (
+            "java/lang/Runtime",
+            "totalMemory",
+            "()J",
+            runtime_total_memory,
+        ),
+        ("java/lang/Runtime", "maxMemory", "()J", runtime_max_memory),
+        ("java/lang/Thread", "registerNatives", "()V", native_noop),
+        (
+            "java/lang/Thread",
+            "currentThread",
+            "()Ljava/lang/Thread;",
+            thread_current_thread,
+        ),
+        (
+            "java/lang/Thread",
+            "setPriority0",
+            "(I)V",
+            threads::thread_set_priority0,
+        ),
+        ("java/lang/Thread", "sleep", "(J)V", threads::thread_sleep),
+        ("java/lang/Thread", "yield", "()V", threads::thread_yield),
+        ("java/lang/Thread", "start0", "()V", thread_start0),
+        ("java/lang/Thread", "isAlive", "()Z", thread_is_alive),
+        (
+            "java/lang/ref/Reference",
+            "getAndClearReferencePendingList",
+            "()Ljava/lang/ref/Reference;",
+            reference_get_and_clear_reference_pending_list,
+        ),
+        (
+            "java/lang/ref/Reference",
+            "hasReferencePendingList",
+            "()Z",
+            reference_has_reference_pending_list,
+        ),
+        (
+            "java/lang/ref/Reference",
+            "waitForReferencePendingList",
+            "()V",
+            native_noop,
+        ),
+        (
+            "java/lang/ref/Reference",
+            "refersTo0",
+            "(Ljava/lang/Object;)Z",
+            reference_refers_to0,
+        ),
+        (
+            "java/lang/ref/PhantomReference",
+            "refersTo0",
+            "(Ljava/lang/Object;)Z",
+            reference_refers_to0,
+        ),
+        ("java/lang/ref/Reference", "clear0", "()V", reference_clear0),
+        ("jdk/internal/misc/VM", "initialize", "()V", native_noop),
+        (
+            "jdk/internal/misc/VM",
+            "initializeFromArchive",
+            "(Ljava/lang/Class;)V",
+            native_noop,
+        ),
+        (
+            "java/lang/reflect/Array",
+            "newArray",
+            "(Ljava/lang/Class;I)Ljava/lang/Object;",
+            array_new_array,
+        ),
+        ("java/io/FileInputStream", "initIDs", "()V", native_noop),
+        (
+            "java/io/FileInputStream",
+            "open0",
+            "(Ljava/lang/String;)V",
+            file_input_stream_open0,
+        ),
+        (
+            "java/io/FileInputStream",
+            "read0",
+            "()I",
+            file_input_stream_read0,
+        ),
+        (
+            "java/io/FileInputStream",
+            "readBytes",
+            "([BII)I",
+            file_input_stream_read_bytes,
+        ),
+        (
+            "java/io/FileInputStream",
+            "available0",
+            "()I",
+            file_input_stream_available0,
+        ),
+        ("java/io/FileOutputStream", "initIDs", "()V", native_noop),
+        (
+            "java/io/FileOutputStream",
+            "open0",
+            "(Ljava/lang/String;Z)V",
+            file_output_stream_open0,
+        ),
+        (
+            "java/io/FileOutputStream",
+            "write",
+            "(IZ)V",
+            file_output_stream_write,
+        ),
+        (
+            "java/io/FileOutputStream",
+            "writeBytes",
+            "([BIIZ)V",
+            file_output_stream_write_bytes,
+        ),
+        ("java/io/FileDescriptor", "initIDs", "()V", native_noop),
+        (
+            "java/io/FileDescriptor",
+            "getAppend",
+            "(I)Z",
+            file_descriptor_get_append,
+        ),
+        (
+            "java/io/FileDescriptor",
+            "getHandle",
+            "(I)J",
+            file_descriptor_get_handle,
+        ),
+        (
+            "java/io/FileDescriptor",
+            "sync",
+            "()V",
+            file_descriptor_sync,
+        ),
+        (
+            "java/io/FileDescriptor",
+            "close0",
+            "()V",
+            file_descriptor_close0,
+        ),
+        (
+            "jdk/internal/misc/Signal",
+            "findSignal0",
+            "(Ljava/lang/String;)I",
+            signal_find_signal0,
+        ),
+        (
+            "jdk/internal/misc/Signal",
+            "handle0",
+            "(IJ)J",
+            signal_handle0,
+        ),
+        (
+            "java/security/AccessController",
+            "getStackAccessControlContext",
+            "()Ljava/security/AccessControlContext;",
+            access_controller_get_stack_access_control_context,
+        ),
+        (
+            "java/security/AccessController",
+            "doPrivileged",
+            "(Ljava/security/PrivilegedAction;)Ljava/lang/Object;",
+            access_controller_do_privileged,
+        ),
+        (
+            "java/security/AccessController",
+            "doPrivileged",
+            "(Ljava/security/PrivilegedExceptionAction;)Ljava/lang/Object;",
+            access_controller_do_privileged,
+        ),
+        (
+            "java/security/AccessController",
+            "doPrivileged",
+            "(Ljava/security/PrivilegedAction;Ljava/security/AccessControlContext;)Ljava/lang/Object;",
+            access_controller_do_privileged,
+        ),
+        (
+            "java/security/AccessController",
+            "doPrivileged",
+            "(Ljava/security/PrivilegedExceptionAction;Ljava/security/AccessControlContext;)Ljava/lang/Object;",
+            access_controller_do_privileged,
+        ),
+        (
+            "java/security/AccessController",
+            "getInheritedAccessControlContext",
+            "()Ljava/security/AccessControlContext;",
+            access_controller_get_stack_access_control_context,
+        ),
+        (
+            "jdk/internal/reflect/Reflection",
+            "getCallerClass",
+            "()Ljava/lang/Class;",
+            reflection_get_caller_class,
+        ),
+        (
+            "jdk/internal/reflect/Reflection",
+            "getClassAccessFlags",
+            "(Ljava/lang/Class;)I",
+            reflection_get_class_access_flags,
+        ),
+        (
+            "jdk/internal/reflect/NativeMethodAccessorImpl",
+            "invoke0",
+            "(Ljava/lang/reflect/Method;Ljava/lang/Object;[Ljava/lang/Object;)Ljava/lang/Object;",
+            native_method_accessor_impl_invoke0,
+        ),
+        (
+            "jdk/internal/reflect/NativeConstructorAccessorImpl",
+            "newInstance0",
+            "(Ljava/lang/reflect/Constructor;[Ljava/lang/Object;)Ljava/lang/Object;",
+            native_constructor_accessor_impl_new_instance0,
+        ),
+        (
+            "java/lang/ClassLoader",
+            "registerNatives",
+            "()V",
+            native_noop,
+        ),
+        (
+            "jdk/internal/perf/Perf",
+            "registerNatives",
+            "()V",
+            native_noop,
+        ),
+        (
+            "jdk/internal/perf/Perf",
+            "createLong",
+            "(Ljava/lang/String;IIJ)Ljava/nio/ByteBuffer;",
+            perf_create_long,
+        ),
+        (
+            "jdk/internal/perf/Perf",
+            "highResCounter",
+            "()J",
+            system_nano_time,
+        ),
+        (
+            "jdk/internal/perf/Perf",
+            "highResFrequency",
+            "()J",
+            perf_high_res_frequency,
+        ),
+        (
+            "java/lang/ClassLoader",
+            "defineClass1",
+            "(Ljava/lang/ClassLoader;Ljava/lang/String;[BIILjava/security/ProtectionDomain;Ljava/lang/String;)Ljava/lang/Class;",
+            class_loader_define_class1,
+        ),
+        (
+            "java/lang/ClassLoader",
+            "defineClass2",
+            "(Ljava/lang/ClassLoader;Ljava/lang/String;Ljava/nio/ByteBuffer;IILjava/security/ProtectionDomain;Ljava/lang/String;)Ljava/lang/Class;",
+            class_loader_define_class2,
+        ),
+        (
+            "java/lang/ClassLoader",
+            "findLoadedClass0",
+            "(Ljava/lang/String;)Ljava/lang/Class;",
+            class_loader_find_loaded_class0,
+        ),
+        (
+            "java/lang/ClassLoader",
+            "findBootstrapClass",
+            "(Ljava/lang/String;)Ljava/lang/Class;",
+            class_loader_find_bootstrap_class,
+        ),
+        (
+            "java/lang/ClassLoader",
+            "findBuiltinLib",
+            "(Ljava/lang/String;)Ljava/lang/String;",
+            class_loader_find_builtin_lib,
+        ),
+        (
+            "java/lang/ClassLoader$NativeLibrary",
+            "load0",
+            "(Ljava/lang/String;Z)Z",
+            jni::native_library_load0,
+        ),
+        (
+            "java/lang/ClassLoader$NativeLibrary",
+            "findEntry",
+            "(Ljava/lang/String;)J",
+            jni::native_library_find_entry,
+        ),
+        (
+            "java/lang/ClassLoader$NativeLibrary",
+            "unload",
+            "(Ljava/lang/String;ZJ)V",
+            jni::native_library_unload,
+        ),
+        ("java/io/UnixFileSystem", "initIDs", "()V", native_noop),
+        (
+            "java/io/UnixFileSystem",
+            "getBooleanAttributes0",
+            "(Ljava/io/File;)I",
+            unix_file_system_get_boolean_attributes0,
+        ),
+        (
+            "java/io/UnixFileSystem",
+            "canonicalize0",
+            "(Ljava/lang/String;)Ljava/lang/String;",
+            unix_file_system_canonicalize0,
+        ),
+    ];
+    builtins
+        .iter()
+        .map(|&(class_name, method_name, descriptor, function)| {
+            (
+                (
+                    class_name.to_owned(),
+                    method_name.to_owned(),
+                    descriptor.to_owned(),
+                ),
+                NativeMethod::Builtin(function),
+            )
+        })
+        .collect()
+}
+
+// registerNatives and the like have nothing to do here, and initIDs only looks like a
+// memorization optimisation in openjdk
+fn native_noop(
+    _thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    Ok(())
+}
+
+fn object_get_class(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    // FIXME: check if this_ref is null
+    let class_name = global_memory
+        .heap
+        .class_name(HeapRef(*this_ref))
+        .ok_or("this_ref not found on heap")?
+        .to_owned();
+    let klass_java_clone = global_memory
+        .method_area
+        .classes
+        .get(&class_name.to_owned())
+        .ok_or("no class 1")?
+        .get_java_clone()
+        .unwrap();
+
+    thread.return_value(&[klass_java_clone.0])
+}
+
+fn object_hash_code(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?
+        .to_owned();
+    if this_ref == 0 {
+        return thread.return_value(&[0]);
+    }
+    let hash = global_memory
+        .heap
+        .identity_hash(HeapRef(this_ref))
+        .ok_or("object not on heap")?;
+    thread.return_value(&[hash])
+}
+
+// a shallow copy of the object or array, which gets an identity hash of its own
+fn object_clone(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?
+        .to_owned();
+
+    let heap_item = global_memory
+        .heap
+        .get(HeapRef(this_ref))
+        .ok_or("this_ref not found on heap")?;
+    let descriptor_id = heap_item.descriptor_id;
+    let field_descriptor = global_memory.heap.descriptor(heap_item).to_owned();
+    let data = heap_item.data.to_owned();
+    let bytes = heap_item.bytes.to_owned();
+
+    // arrays are always Cloneable, objects only if their class implements it
+    if let Some(class_name) = parse_field_descriptor(&field_descriptor)?
+        .field_type
+        .as_class_instance()
+    {
+        let mut interface_names = vec![];
+        global_memory.method_area.collect_superinterfaces(
+            &*global_memory.method_area.parsed_class(class_name)?,
+            &mut interface_names,
+        )?;
+        if !interface_names
+            .iter()
+            .any(|name| name == "java/lang/Cloneable")
+        {
+            return thread.throw_exception(global_memory, "java/lang/CloneNotSupportedException");
+        }
+    }
+
+    let clone_ref = global_memory.heap.insert(HeapItem {
+        descriptor_id,
+        identity_hash: 0,
+        data,
+        bytes,
+    });
+    thread.return_value(&[clone_ref.0])
+}
+
+fn class_init_class_name(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    // FIXME: check if this_ref is null
+
+    let klass = global_memory
+        .method_area
+        .classes
+        .values()
+        .find(|class| class.get_java_clone() == Some(HeapRef(*this_ref)))
+        .unwrap();
+
+    // the binary name, e.g. java.lang.String or [Ljava.lang.String; for an array class
+    let class_name = klass.get_name().replace('/', ".");
+
+    let string_ref = java_string_from_string(global_memory, class_name)?;
+    thread.return_value(&[string_ref.0])
+}
+
+// the class loader is ignored, every class comes from the class path
+fn class_for_name0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let name_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let initialize = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?
+        != 0;
+    let class_name = string_from_java_string(global_memory, HeapRef(name_ref))?.replace('.', "/");
+    if class_name.starts_with('[') {
+        global_memory.ensure_array(class_name.to_owned())?;
+    } else {
+        if !global_memory.method_area.classes.contains_key(&class_name)
+            && find_class_file(&class_name).is_none()
+        {
+            return Err(format!("ClassNotFoundException: {}", class_name.replace('/', ".")).into());
+        }
+        if initialize {
+            if let Err(error) = global_memory.ensure_class(&class_name) {
+                return thread.throw_initialization_error(global_memory, error);
+            }
+        } else {
+            global_memory.ensure_linked(&class_name)?;
+        }
+    }
+    let klass_java_clone = global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .and_then(|klass| klass.get_java_clone())
+        .ok_or(format!("class {class_name} has no mirror"))?;
+
+    thread.return_value(&[klass_java_clone.0])
+}
+
+fn class_desired_assertion_status0(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    // no idea what this method does!
+    thread.return_value(&[1])
+}
+
+fn class_get_primitive_class(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let primitive_type_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+
+    let text = string_from_java_string(global_memory, HeapRef(*primitive_type_ref))?;
+
+    if !is_primitive_class_name(&text) {
+        return Err(format!("{text} is not a primitive type").into());
+    }
+    let java_clone_ref = global_memory.ensure_primitive_class(&text)?;
+
+    thread.return_value(&[java_clone_ref.0])
+}
+
+fn class_is_array(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    // FIXME: check if this_ref is null
+
+    let klass = global_memory
+        .method_area
+        .classes
+        .values()
+        .find(|class| {
+            let maybe_java_clone = class.get_java_clone();
+            if maybe_java_clone.is_none() {
+                return false;
+            }
+            maybe_java_clone.unwrap() == HeapRef(*this_ref)
+        })
+        .unwrap();
+
+    thread.return_value(&[if klass.as_array_klass().is_some() {
+        1
+    } else {
+        0
+    }])
+}
+
+// the names of the classes standing for the primitive types and void, like int.class
+fn is_primitive_class_name(class_name: &str) -> bool {
+    matches!(
+        class_name,
+        "boolean" | "byte" | "char" | "short" | "int" | "long" | "float" | "double" | "void"
+    )
+}
+
+// the name of the class of a primitive type, like Class.getName returns it
+fn primitive_class_name(field_type: &FieldType) -> Option<&'static str> {
+    match field_type {
+        FieldType::Integer => Some("int"),
+        FieldType::Boolean => Some("boolean"),
+        FieldType::Byte => Some("byte"),
+        FieldType::Char => Some("char"),
+        FieldType::LongInteger => Some("long"),
+        FieldType::Float => Some("float"),
+        FieldType::Double => Some("double"),
+        FieldType::Short => Some("short"),
+        FieldType::ClassInstance(_) | FieldType::Array(_) => None,
+    }
+}
+
+// the name of the array class with elements of the class component_name, e.g. [I for int
+fn array_type_of_component(component_name: &str) -> String {
+    let component_descriptor = match component_name {
+        "boolean" => "Z".to_owned(),
+        "byte" => "B".to_owned(),
+        "char" => "C".to_owned(),
+        "short" => "S".to_owned(),
+        "int" => "I".to_owned(),
+        "long" => "J".to_owned(),
+        "float" => "F".to_owned(),
+        "double" => "D".to_owned(),
+        array_type if array_type.starts_with('[') => array_type.to_owned(),
+        class_name => format!("L{class_name};"),
+    };
+    format!("[{component_descriptor}")
+}
+
+// the type of the values of a class that isn't primitive, used to check them for assignability
+fn class_field_type(class_name: &str) -> Result<FieldType, Box<dyn Error>> {
+    if class_name.starts_with('[') {
+        Ok(parse_field_descriptor(class_name)?.field_type)
+    } else {
+        Ok(FieldType::ClassInstance(class_name.to_owned()))
+    }
+}
+
+// the class a java/lang/Class argument of a native stands for. Arguments other than this have
+// to be checked for null first.
+fn mirror_argument_class_name(
+    thread: &Thread,
+    global_memory: &GlobalMemory,
+    index: usize,
+) -> Result<String, Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let class_ref = *current_frame
+        .local_variables
+        .get(index)
+        .ok_or("no item in local_variables")?;
+    Ok(global_memory
+        .method_area
+        .class_name_of_mirror(class_ref)
+        .ok_or("no class for java clone")?)
+}
+
+fn class_is_interface(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let class_name = mirror_argument_class_name(thread, global_memory, 0)?;
+    let is_interface = global_memory.method_area.is_interface(&class_name);
+
+    thread.return_value(&[is_interface as u32])
+}
+
+fn class_is_primitive(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let class_name = mirror_argument_class_name(thread, global_memory, 0)?;
+
+    thread.return_value(&[is_primitive_class_name(&class_name) as u32])
+}
+
+// whether a value of the class passed can be assigned to this class. Primitive classes are only
+// assignable from themselves.
+fn class_is_assignable_from(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let other_ref = *thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    if other_ref == 0 {
+        return thread.throw_exception(global_memory, "java/lang/NullPointerException");
+    }
+    let class_name = mirror_argument_class_name(thread, global_memory, 0)?;
+    let other_class_name = mirror_argument_class_name(thread, global_memory, 1)?;
+    let is_assignable =
+        if is_primitive_class_name(&class_name) || is_primitive_class_name(&other_class_name) {
+            class_name == other_class_name
+        } else {
+            global_memory.method_area.is_assignable(
+                &class_field_type(&other_class_name)?,
+                &class_field_type(&class_name)?,
+            )?
+        };
+
+    thread.return_value(&[is_assignable as u32])
+}
+
+// the instanceof of reflection, null is no instance of anything
+fn class_is_instance(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let objectref = *thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let class_name = mirror_argument_class_name(thread, global_memory, 0)?;
+    let is_instance = if objectref == 0 || is_primitive_class_name(&class_name) {
+        false
+    } else {
+        let field_descriptor = &global_memory
+            .heap
+            .field_descriptor(HeapRef(objectref))
+            .ok_or("object not on heap")?;
+        global_memory.method_area.is_assignable(
+            &parse_field_descriptor(field_descriptor)?.field_type,
+            &class_field_type(&class_name)?,
+        )?
+    };
+
+    thread.return_value(&[is_instance as u32])
+}
+
+// null for Object, interfaces and primitive classes, Object for array classes
+fn class_get_superclass(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let class_name = mirror_argument_class_name(thread, global_memory, 0)?;
+    let super_class_name = if class_name.starts_with('[') {
+        Some("java/lang/Object".to_owned())
+    } else if is_primitive_class_name(&class_name)
+        || global_memory.method_area.is_interface(&class_name)
+    {
+        None
+    } else {
+        global_memory
+            .method_area
+            .parsed_class(&class_name)?
+            .super_class
+            .as_ref()
+            .map(|super_class| super_class.name.to_owned())
+    };
+    let super_class_ref = match super_class_name {
+        Some(super_class_name) => {
+            global_memory.class_mirror(&FieldType::ClassInstance(super_class_name))?
+        }
+        None => HeapRef::NULL,
+    };
+
+    thread.return_value(&[super_class_ref.0])
+}
+
+// the interfaces a class implements directly, in the order of its class file. Arrays implement
+// Cloneable and Serializable.
+fn class_get_interfaces0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let class_name = mirror_argument_class_name(thread, global_memory, 0)?;
+    let interface_names = if class_name.starts_with('[') {
+        vec![
+            "java/lang/Cloneable".to_owned(),
+            "java/io/Serializable".to_owned(),
+        ]
+    } else if is_primitive_class_name(&class_name) {
+        vec![]
+    } else {
+        global_memory
+            .method_area
+            .parsed_class(&class_name)?
+            .interfaces
+            .iter()
+            .map(|interface| interface.name.to_owned())
+            .collect()
+    };
+    let mut interface_refs = vec![];
+    for interface_name in interface_names {
+        interface_refs.push(
+            global_memory
+                .class_mirror(&FieldType::ClassInstance(interface_name))?
+                .0,
+        );
+    }
+    global_memory.ensure_array("[Ljava/lang/Class;".to_owned())?;
+    let array_ref = global_memory
+        .heap
+        .store("[Ljava/lang/Class;", interface_refs);
+
+    thread.return_value(&[array_ref.0])
+}
+
+// the methods a class declares itself, without its constructors and static initializer
+fn class_get_declared_methods0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let public_only = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?
+        != 0;
+
+    let class_name = global_memory
+        .method_area
+        .class_name_of_mirror(this_ref)
+        .ok_or("no class for java clone")?;
+    // array classes declare no methods of their own
+    let parsed_class = global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .and_then(|class| class.as_instance_klass())
+        .and_then(|class| class.parsed_class.clone());
+    let mut method_refs = vec![];
+    if let Some(parsed_class) = parsed_class {
+        global_memory.ensure_class("java/lang/reflect/Method")?;
+        for (slot, method) in parsed_class.methods.iter().enumerate() {
+            if method.name == "<init>"
+                || method.name == "<clinit>"
+                || (public_only && !method.access.public)
+            {
+                continue;
+            }
+            method_refs.push(new_method_object(global_memory, HeapRef(this_ref), slot, method)?.0);
+        }
+    }
+    global_memory.ensure_array("[Ljava/lang/reflect/Method;".to_owned())?;
+    let array_ref = global_memory
+        .heap
+        .store("[Ljava/lang/reflect/Method;", method_refs);
+
+    thread.return_value(&[array_ref.0])
+}
+
+// the constructors of a class, interfaces and array classes have none
+fn class_get_declared_constructors0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let public_only = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?
+        != 0;
+
+    let class_name = global_memory
+        .method_area
+        .class_name_of_mirror(this_ref)
+        .ok_or("no class for java clone")?;
+    let parsed_class = global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .and_then(|class| class.as_instance_klass())
+        .and_then(|class| class.parsed_class.clone());
+    let mut constructor_refs = vec![];
+    if let Some(parsed_class) = parsed_class {
+        global_memory.ensure_class("java/lang/reflect/Constructor")?;
+        for (slot, method) in parsed_class.methods.iter().enumerate() {
+            if method.name != "<init>" || (public_only && !method.access.public) {
+                continue;
+            }
+            constructor_refs
+                .push(new_constructor_object(global_memory, HeapRef(this_ref), slot, method)?.0);
+        }
+    }
+    global_memory.ensure_array("[Ljava/lang/reflect/Constructor;".to_owned())?;
+    let array_ref = global_memory
+        .heap
+        .store("[Ljava/lang/reflect/Constructor;", constructor_refs);
+
+    thread.return_value(&[array_ref.0])
+}
+
+// the modifiers of a class as the java language sees them: those of its InnerClasses entry for
+// member classes, which may be private, protected or static, otherwise its access_flags without
+// ACC_SUPER. Array classes are public, final and abstract.
+fn class_get_modifiers(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+
+    let class_name = global_memory
+        .method_area
+        .class_name_of_mirror(this_ref)
+        .ok_or("no class for java clone")?;
+    let modifiers = match global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .and_then(|class| class.as_instance_klass())
+        .and_then(|class| class.parsed_class.as_ref())
+    {
+        Some(parsed_class) => {
+            parsed_class
+                .inner_classes()
+                .and_then(|inner_classes| {
+                    inner_classes
+                        .iter()
+                        .find(|inner_class| inner_class.inner_class == class_name)
+                })
+                .map(|inner_class| inner_class.inner_class_access_flags)
+                .unwrap_or(parsed_class.access.flags)
+                & !0x0020
+        }
+        None => 0x0001 | 0x0010 | 0x0400,
+    };
+
+    thread.return_value(&[modifiers as u32])
+}
+
+// creates the java/lang/reflect/Constructor for an <init> method, see new_method_object
+fn new_constructor_object(
+    global_memory: &mut GlobalMemory,
+    class_ref: HeapRef,
+    slot: usize,
+    method: &Method,
+) -> Result<HeapRef, Box<dyn Error>> {
+    let mut parameter_type_refs = vec![];
+    for field_type in method.descriptor.parameter_descriptors.iter() {
+        parameter_type_refs.push(global_memory.class_mirror(field_type)?.0);
+    }
+    global_memory.ensure_array("[Ljava/lang/Class;".to_owned())?;
+    let parameter_types_ref = global_memory
+        .heap
+        .store("[Ljava/lang/Class;", parameter_type_refs);
+    let exception_types_ref = global_memory.heap.store("[Ljava/lang/Class;", vec![]);
+    let signature_ref = match method
+        .attributes
+        .iter()
+        .find_map(|attribute| match attribute {
+            Attribute::Signature(signature) => Some(signature.to_owned()),
+            _ => None,
+        }) {
+        Some(signature) => java_string_from_string(global_memory, signature)?,
+        None => HeapRef::NULL,
+    };
+
+    let klass = global_memory
+        .method_area
+        .classes
+        .get("java/lang/reflect/Constructor")
+        .ok_or("class not found in method area")?;
+    let constructor_ref = global_memory.heap.allocate_klass(klass);
+    for (field_name, value) in [
+        ("clazz", class_ref),
+        ("slot", HeapRef(slot as u32)),
+        ("parameterTypes", HeapRef(parameter_types_ref.0)),
+        ("exceptionTypes", HeapRef(exception_types_ref.0)),
+        ("modifiers", HeapRef(method.access.flags as u32)),
+        ("signature", HeapRef(signature_ref.0)),
+    ] {
+        set_field_value(
+            global_memory,
+            constructor_ref.0,
+            "java/lang/reflect/Constructor",
+            field_name,
+            value.0,
+        )?;
+    }
+    Ok(constructor_ref)
+}
+
+// creates the java/lang/reflect/Method for a method of the class class_ref stands for. The slot
+// is the index of the method in the methods of its class file, which invoke0 goes by.
+fn new_method_object(
+    global_memory: &mut GlobalMemory,
+    class_ref: HeapRef,
+    slot: usize,
+    method: &Method,
+) -> Result<HeapRef, Box<dyn Error>> {
+    let mut parameter_type_refs = vec![];
+    for field_type in method.descriptor.parameter_descriptors.iter() {
+        parameter_type_refs.push(global_memory.class_mirror(field_type)?.0);
+    }
+    let return_type_ref = match &method.descriptor.return_descriptor {
+        crate::parse::ReturnDescriptor::VoidDescriptor => {
+            global_memory.ensure_primitive_class("void")?
+        }
+        crate::parse::ReturnDescriptor::FieldType(field_type) => {
+            global_memory.class_mirror(field_type)?
+        }
+    };
+    global_memory.ensure_array("[Ljava/lang/Class;".to_owned())?;
+    let parameter_types_ref = global_memory
+        .heap
+        .store("[Ljava/lang/Class;", parameter_type_refs);
+    // the Exceptions attribute isn't parsed, so no method declares any checked exceptions
+    let exception_types_ref = global_memory.heap.store("[Ljava/lang/Class;", vec![]);
+    // Class.searchMethods compares the names by reference, so they have to be interned
+    let name_ref = intern_string(global_memory, method.name.encode_utf16().collect())?;
+    let signature_ref = match method
+        .attributes
+        .iter()
+        .find_map(|attribute| match attribute {
+            Attribute::Signature(signature) => Some(signature.to_owned()),
+            _ => None,
+        }) {
+        Some(signature) => java_string_from_string(global_memory, signature)?,
+        None => HeapRef::NULL,
+    };
+
+    let klass = global_memory
+        .method_area
+        .classes
+        .get("java/lang/reflect/Method")
+        .ok_or("class not found in method area")?;
+    let method_ref = global_memory.heap.allocate_klass(klass);
+    for (field_name, value) in [
+        ("clazz", class_ref),
+        ("slot", HeapRef(slot as u32)),
+        ("name", HeapRef(name_ref.0)),
+        ("returnType", HeapRef(return_type_ref.0)),
+        ("parameterTypes", HeapRef(parameter_types_ref.0)),
+        ("exceptionTypes", HeapRef(exception_types_ref.0)),
+        ("modifiers", HeapRef(method.access.flags as u32)),
+        ("signature", HeapRef(signature_ref.0)),
+    ] {
+        set_field_value(
+            global_memory,
+            method_ref.0,
+            "java/lang/reflect/Method",
+            field_name,
+            value.0,
+        )?;
+    }
+    Ok(method_ref)
+}
+
+fn system_arraycopy(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let src_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let src_pos = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")? as i32;
+    let dest_ref = *current_frame
+        .local_variables
+        .get(2)
+        .ok_or("no item in local_variables")?;
+    let dest_pos = *current_frame
+        .local_variables
+        .get(3)
+        .ok_or("no item in local_variables")? as i32;
+    let length = *current_frame
+        .local_variables
+        .get(4)
+        .ok_or("no item in local_variables")? as i32;
+
+    if src_ref == 0 || dest_ref == 0 {
+        return thread.throw_exception(global_memory, "java/lang/NullPointerException");
+    }
+    let src_descriptor = global_memory
+        .heap
+        .field_descriptor(HeapRef(src_ref))
+        .ok_or("array not on heap")?
+        .to_owned();
+    let dest_descriptor = global_memory
+        .heap
+        .field_descriptor(HeapRef(dest_ref))
+        .ok_or("array not on heap")?
+        .to_owned();
+    let src_component_type = parse_field_descriptor(&src_descriptor)?
+        .field_type
+        .as_array()
+        .cloned();
+    let dest_component_type = parse_field_descriptor(&dest_descriptor)?
+        .field_type
+        .as_array()
+        .cloned();
+
+    // both have to be arrays, either of the same primitive type or both of references
+    let (src_component_type, dest_component_type) = match (src_component_type, dest_component_type)
+    {
+        (Some(src_component_type), Some(dest_component_type)) => {
+            (src_component_type, dest_component_type)
+        }
+        _ => return thread.throw_exception(global_memory, "java/lang/ArrayStoreException"),
+    };
+    let is_reference = |field_type: &FieldType| {
+        matches!(
+            field_type,
+            FieldType::ClassInstance(_) | FieldType::Array(_)
+        )
+    };
+    let copies_references = is_reference(&src_component_type);
+    if copies_references != is_reference(&dest_component_type)
+        || (!copies_references && src_component_type != dest_component_type)
+    {
+        return thread.throw_exception(global_memory, "java/lang/ArrayStoreException");
+    }
+
+    let src_length = global_memory
+        .heap
+        .array_length(global_memory.heap.get(HeapRef(src_ref)).unwrap());
+    let dest_length = global_memory
+        .heap
+        .array_length(global_memory.heap.get(HeapRef(dest_ref)).unwrap());
+    if src_pos < 0
+        || dest_pos < 0
+        || length < 0
+        || src_pos as usize + length as usize > src_length
+        || dest_pos as usize + length as usize > dest_length
+    {
+        return thread.throw_exception(global_memory, "java/lang/ArrayIndexOutOfBoundsException");
+    }
+
+    // copying from a snapshot of the source handles src and dest being the same array with
+    // overlapping regions, just as if it went through a temporary array
+    let (src_pos, dest_pos, length) = (src_pos as usize, dest_pos as usize, length as usize);
+    // the elements of arrays of primitives are copied byte for byte
+    if let Some(size) = primitive_element_size(&src_descriptor) {
+        let elements = global_memory.heap.get(HeapRef(src_ref)).unwrap().bytes
+            [src_pos * size..(src_pos + length) * size]
+            .to_owned();
+        global_memory
+            .heap
+            .get_mut(HeapRef(dest_ref))
+            .ok_or("array not on heap")?
+            .bytes[dest_pos * size..(dest_pos + length) * size]
+            .copy_from_slice(&elements);
+        return Ok(());
+    }
+    let elements = global_memory.heap.get(HeapRef(src_ref)).unwrap().data
+        [src_pos..src_pos + length]
+        .to_owned();
+
+    // unless every element of the source fits by its type, each one is checked on its own. The
+    // ones before the first that doesn't fit are copied anyway.
+    let mut copied = length;
+    if !global_memory
+        .method_area
+        .is_assignable(&src_component_type, &dest_component_type)?
+    {
+        for (index, objectref) in elements.iter().enumerate() {
+            if *objectref == 0 {
+                continue;
+            }
+            let element_type = parse_field_descriptor(
+                global_memory
                     .heap
-                    .data
-                    .get(*object_ref as usize)
-                    .ok_or("not on the heap")?
-                    .data
-                    .get(offset as usize)
-                    .ok_or("not on the heap")?;
-                // println!("{value_at_offset}");
-                let mut successful = 0;
-                if value_at_offset == expected {
-                    global_memory
-                        .heap
-                        .data
-                        .get_mut(*object_ref as usize)
-                        .ok_or("not on the heap")?
-                        .data[offset as usize] = *x;
-                    successful = 1;
+                    .field_descriptor(HeapRef(*objectref))
+                    .ok_or("array element not on heap")?,
+            )?
+            .field_type;
+            if !global_memory
+                .method_area
+                .is_assignable(&element_type, &dest_component_type)?
+            {
+                copied = index;
+                break;
+            }
+        }
+    }
+
+    let dest_array = global_memory
+        .heap
+        .get_mut(HeapRef(dest_ref))
+        .ok_or("array not on heap")?;
+    dest_array.data[dest_pos..dest_pos + copied].copy_from_slice(&elements[..copied]);
+
+    if copied < length {
+        return thread.throw_exception(global_memory, "java/lang/ArrayStoreException");
+    }
+    Ok(())
+}
+
+fn system_identity_hash_code(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?
+        .to_owned();
+    if this_ref == 0 {
+        return thread.return_value(&[0]);
+    }
+    let hash = global_memory
+        .heap
+        .identity_hash(HeapRef(this_ref))
+        .ok_or("object not on heap")?;
+    thread.return_value(&[hash])
+}
+
+fn system_init_properties(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let properties_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?
+        .to_owned();
+
+    let system_properties = global_memory.system_properties.clone();
+    let mut put = |key: String, value: String| -> Result<(), Box<dyn Error>> {
+        let key = java_string_from_string(global_memory, key.to_owned())?;
+        let value = java_string_from_string(global_memory, value.to_owned())?;
+        let mut frame = Frame::for_class_name(
+            global_memory,
+            "java/util/Properties",
+            "put".to_owned(),
+            MethodDescriptor {
+                parameter_descriptors: vec![
+                    FieldType::ClassInstance("java/lang/Object".to_owned()),
+                    FieldType::ClassInstance("java/lang/Object".to_owned()),
+                ],
+                return_descriptor: crate::parse::ReturnDescriptor::FieldType(
+                    FieldType::ClassInstance("java/lang/Object".to_owned()),
+                ),
+            },
+        )?;
+        frame.local_variables[0] = properties_ref;
+        frame.local_variables[1] = key.0;
+        frame.local_variables[2] = value.0;
+        thread.thread_memory.jvm_stack.push(frame);
+        // the outermost run annotates errors with the whole stack
+        thread.interpret(global_memory)?;
+        Ok(())
+    };
+    for (key, value) in system_properties {
+        put(key, value)?;
+    }
+
+    thread.return_value(&[properties_ref])
+}
+
+fn system_nano_time(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let duration_since_epoch = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap();
+    let timestamp_nanos = duration_since_epoch.as_nanos() as u64;
+    thread.return_value(&slots::long_to_slots(timestamp_nanos as i64))
+}
+
+fn system_set_in0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let stream_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?
+        .to_owned();
+
+    let class = global_memory
+        .method_area
+        .classes
+        .get_mut("java/lang/System")
+        .unwrap()
+        .as_mut_instance_klass()
+        .unwrap();
+    let offset =
+        class.static_field_offset_with_strings("java/lang/System".to_owned(), "in".to_owned())?;
+    class.static_field_values.as_mut().unwrap()[offset] = stream_ref;
+    Ok(())
+}
+
+fn system_set_out0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let stream_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?
+        .to_owned();
+
+    let class = global_memory
+        .method_area
+        .classes
+        .get_mut("java/lang/System")
+        .unwrap()
+        .as_mut_instance_klass()
+        .unwrap();
+    let offset =
+        class.static_field_offset_with_strings("java/lang/System".to_owned(), "out".to_owned())?;
+    class.static_field_values.as_mut().unwrap()[offset] = stream_ref;
+    Ok(())
+}
+
+fn system_set_err0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let stream_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?
+        .to_owned();
+
+    let class = global_memory
+        .method_area
+        .classes
+        .get_mut("java/lang/System")
+        .unwrap()
+        .as_mut_instance_klass()
+        .unwrap();
+    let offset =
+        class.static_field_offset_with_strings("java/lang/System".to_owned(), "err".to_owned())?;
+    class.static_field_values.as_mut().unwrap()[offset] = stream_ref;
+    Ok(())
+}
+
+fn string_intern(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let value = java_string_chars(global_memory, HeapRef(this_ref))?;
+    // the string itself becomes the canonical one if there is none yet
+    let interned_ref = *global_memory
+        .interned_strings
+        .entry(value)
+        .or_insert(HeapRef(this_ref));
+
+    thread.return_value(&[interned_ref.0])
+}
+
+fn string_utf16_is_big_endian(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&[1])
+}
+
+fn float_float_to_raw_int_bits(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let float_read_as_u32 = Cursor::new(
+        current_frame
+            .local_variables
+            .first()
+            .ok_or("no item in local_variables")?
+            .to_be_bytes(),
+    )
+    .read_u32::<BigEndian>()?;
+
+    thread.return_value(&[float_read_as_u32])
+}
+
+// a float is kept as its bits already, so they are handed back unchanged
+fn float_int_bits_to_float(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let bits = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+
+    thread.return_value(&[bits])
+}
+
+fn double_double_to_raw_long_bits(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let value = double_argument(thread, 0)?;
+    push_long_result(thread, value.to_bits() as i64)
+}
+
+fn double_long_bits_to_double(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let bits = long_argument(thread, 0)?;
+    push_double_result(thread, f64::from_bits(bits as u64))
+}
+
+// the double argument of a native at the given slot of its local variables, high word first
+fn double_argument(thread: &Thread, index: usize) -> Result<f64, Box<dyn Error>> {
+    let bits = long_argument(thread, index)?;
+    Ok(f64::from_bits(bits as u64))
+}
+
+// the long argument of a native at the given slot of its local variables, high word first
+fn long_argument(thread: &Thread, index: usize) -> Result<i64, Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let high = *current_frame
+        .local_variables
+        .get(index)
+        .ok_or("no item in local_variables")?;
+    let low = *current_frame
+        .local_variables
+        .get(index + 1)
+        .ok_or("no item in local_variables")?;
+    Ok(slots::long_from_slots(high, low))
+}
+
+// pushes the double a native returns onto the operand stack of its invoker, the high word first
+fn push_double_result(thread: &mut Thread, value: f64) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&slots::double_to_slots(value))
+}
+
+// the natives of StrictMath map to the functions of the platform's libm, which Rust uses as
+// well. Their results may differ from fdlibm's in the last bit. exp, pow, cbrt and hypot are
+// ported to java in jdk/internal/math/FdLibm already.
+fn strict_math_unary(thread: &mut Thread, function: fn(f64) -> f64) -> Result<(), Box<dyn Error>> {
+    let value = double_argument(thread, 0)?;
+    push_double_result(thread, function(value))
+}
+
+fn strict_math_sin(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::sin)
+}
+
+fn strict_math_cos(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::cos)
+}
+
+fn strict_math_tan(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::tan)
+}
+
+fn strict_math_asin(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::asin)
+}
+
+fn strict_math_acos(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::acos)
+}
+
+fn strict_math_atan(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::atan)
+}
+
+fn strict_math_log(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::ln)
+}
+
+fn strict_math_log10(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::log10)
+}
+
+fn strict_math_sqrt(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::sqrt)
+}
+
+fn strict_math_sinh(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::sinh)
+}
+
+fn strict_math_cosh(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::cosh)
+}
+
+fn strict_math_tanh(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::tanh)
+}
+
+fn strict_math_expm1(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::exp_m1)
+}
+
+fn strict_math_log1p(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    strict_math_unary(thread, f64::ln_1p)
+}
+
+fn strict_math_atan2(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let y = double_argument(thread, 0)?;
+    let x = double_argument(thread, 2)?;
+    push_double_result(thread, y.atan2(x))
+}
+
+// x - n * y, where n is the integer closest to x / y, preferring the even one on a tie. Follows
+// __ieee754_remainder of fdlibm, which computes it exactly.
+fn strict_math_ieee_remainder(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let x = double_argument(thread, 0)?;
+    let y = double_argument(thread, 2)?;
+    if x.is_nan() || y.is_nan() || x.is_infinite() || y == 0.0 {
+        return push_double_result(thread, f64::NAN);
+    }
+    let p = y.abs();
+    // x is reduced to less than 2p first, which leaves n odd or even just like before
+    let mut remainder = if p <= f64::MAX / 2.0 { x % (p + p) } else { x };
+    if remainder.abs() == p {
+        return push_double_result(thread, 0.0 * x);
+    }
+    remainder = remainder.abs();
+    if p < 2.0 * f64::MIN_POSITIVE {
+        if remainder + remainder > p {
+            remainder -= p;
+            if remainder + remainder >= p {
+                remainder -= p;
+            }
+        }
+    } else {
+        let p_half = 0.5 * p;
+        if remainder > p_half {
+            remainder -= p;
+            if remainder >= p_half {
+                remainder -= p;
+            }
+        }
+    }
+    // the sign of x carries over to the remainder
+    let result = if x.is_sign_negative() {
+        -remainder
+    } else {
+        remainder
+    };
+    push_double_result(thread, result)
+}
+
+fn throwable_fill_in_stack_trace(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+
+    // collect class name, method name and bytecode index of every frame, the
+    // innermost first. The frames filling in the stack trace and constructing the
+    // throwable itself are left out, just like hotspot does.
+    let mut stack_trace = vec![];
+    let mut skipping = true;
+    for frame in thread.thread_memory.jvm_stack.iter().rev() {
+        let method = match &frame.method {
+            Some(method) => method,
+            // stub frames
+            None => continue,
+        };
+        if skipping
+            && (method.name == "fillInStackTrace"
+                || (method.name == "<init>" && frame.local_variables.first() == Some(&this_ref)))
+        {
+            continue;
+        }
+        skipping = false;
+        // native frames have no bytecode index
+        let bytecode_index = if frame.code.is_some() {
+            frame.instruction_counter as i32
+        } else {
+            -1
+        };
+        let line_number = match frame.line_number() {
+            Some(line_number) => line_number as i32,
+            None => -1,
+        };
+        stack_trace.push((
+            frame.class_name().to_owned(),
+            method.name.to_owned(),
+            bytecode_index,
+            line_number,
+        ));
+    }
+
+    // the backtrace is an opaque Object[] { String[] class names, String[] method
+    // names, int[] bytecode indices, int[] line numbers }, see
+    // StackTraceElement.initStackTraceElements
+    let mut class_names = vec![];
+    let mut method_names = vec![];
+    let mut bytecode_indices = vec![];
+    let mut line_numbers = vec![];
+    for (class_name, method_name, bytecode_index, line_number) in stack_trace.iter() {
+        class_names.push(java_string_from_string(global_memory, class_name.to_owned())?.0);
+        method_names.push(java_string_from_string(global_memory, method_name.to_owned())?.0);
+        bytecode_indices.push(*bytecode_index);
+        line_numbers.push(*line_number);
+    }
+    global_memory.ensure_array("[Ljava/lang/String;".to_owned())?;
+    let class_names_ref = global_memory.heap.store("[Ljava/lang/String;", class_names);
+    let method_names_ref = global_memory
+        .heap
+        .store("[Ljava/lang/String;", method_names);
+    let bytecode_indices_ref = global_memory.heap.store_int_array(&bytecode_indices);
+    let line_numbers_ref = global_memory.heap.store_int_array(&line_numbers);
+    let backtrace_ref = global_memory.heap.store(
+        "[Ljava/lang/Object;",
+        vec![
+            class_names_ref.0,
+            method_names_ref.0,
+            bytecode_indices_ref.0,
+            line_numbers_ref.0,
+        ],
+    );
+
+    set_field_value(
+        global_memory,
+        this_ref,
+        "java/lang/Throwable",
+        "backtrace",
+        backtrace_ref.0,
+    )?;
+    set_field_value(
+        global_memory,
+        this_ref,
+        "java/lang/Throwable",
+        "depth",
+        stack_trace.len() as u32,
+    )?;
+
+    thread.return_value(&[this_ref])
+}
+
+fn stack_trace_element_init_stack_trace_elements(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let elements_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let throwable_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+
+    let backtrace_ref = get_field_value(
+        global_memory,
+        throwable_ref,
+        "java/lang/Throwable",
+        "backtrace",
+    )?;
+    let backtrace = &global_memory
+        .heap
+        .get(HeapRef(backtrace_ref))
+        .ok_or("backtrace not on heap")?
+        .data;
+    let (class_names_ref, method_names_ref, bytecode_indices_ref, line_numbers_ref) = (
+        *backtrace.first().ok_or("malformed backtrace")?,
+        *backtrace.get(1).ok_or("malformed backtrace")?,
+        *backtrace.get(2).ok_or("malformed backtrace")?,
+        *backtrace.get(3).ok_or("malformed backtrace")?,
+    );
+    let elements = global_memory
+        .heap
+        .get(HeapRef(elements_ref))
+        .ok_or("elements not on heap")?
+        .data
+        .to_owned();
+    let class_names = global_memory
+        .heap
+        .get(HeapRef(class_names_ref))
+        .ok_or("class names not on heap")?
+        .data
+        .to_owned();
+    let method_names = global_memory
+        .heap
+        .get(HeapRef(method_names_ref))
+        .ok_or("method names not on heap")?
+        .data
+        .to_owned();
+    let bytecode_indices = global_memory
+        .heap
+        .get(HeapRef(bytecode_indices_ref))
+        .ok_or("bytecode indices not on heap")?
+        .int_elements();
+    let line_numbers = global_memory
+        .heap
+        .get(HeapRef(line_numbers_ref))
+        .ok_or("line numbers not on heap")?
+        .int_elements();
+
+    for (index, element_ref) in elements.iter().enumerate() {
+        let class_names_ref = *class_names
+            .get(index)
+            .ok_or("backtrace is shorter than elements")?;
+        let class_name = string_from_java_string(global_memory, HeapRef(class_names_ref))?;
+        let declaring_class_ref =
+            java_string_from_string(global_memory, class_name.replace('/', "."))?;
+        let class_java_clone = match global_memory.method_area.classes.get(&class_name) {
+            Some(klass) => klass.get_java_clone().unwrap_or(HeapRef::NULL),
+            None => HeapRef::NULL,
+        };
+        // native methods are marked as such by -2, unknown lines are -1
+        let line_number = if bytecode_indices[index] == -1 {
+            -2
+        } else {
+            line_numbers[index]
+        };
+        let file_name_ref = match global_memory.method_area.source_file(&class_name) {
+            Some(source_file) => java_string_from_string(global_memory, source_file)?,
+            None => HeapRef::NULL,
+        };
+
+        set_field_value(
+            global_memory,
+            *element_ref,
+            "java/lang/StackTraceElement",
+            "declaringClassObject",
+            class_java_clone.0,
+        )?;
+        set_field_value(
+            global_memory,
+            *element_ref,
+            "java/lang/StackTraceElement",
+            "declaringClass",
+            declaring_class_ref.0,
+        )?;
+        set_field_value(
+            global_memory,
+            *element_ref,
+            "java/lang/StackTraceElement",
+            "methodName",
+            method_names[index],
+        )?;
+        set_field_value(
+            global_memory,
+            *element_ref,
+            "java/lang/StackTraceElement",
+            "lineNumber",
+            line_number as u32,
+        )?;
+        set_field_value(
+            global_memory,
+            *element_ref,
+            "java/lang/StackTraceElement",
+            "fileName",
+            file_name_ref.0,
+        )?;
+    }
+    Ok(())
+}
+
+fn unsafe_array_base_offset0(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&[0])
+}
+
+// the elements of arrays of primitives take up as many bytes as their type does, those of arrays
+// of references a slot of data each. Either way arrays start at offset 0.
+fn unsafe_array_index_scale0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let class_name = mirror_argument_class_name(thread, global_memory, 1)?;
+
+    thread.return_value(&[primitive_element_size(&class_name).unwrap_or(1) as u32])
+}
+
+fn unsafe_address_size0(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    // the addresses of native_memory are longs, so getAddress and putAddress use getLong and putLong
+    thread.return_value(&[8])
+}
+
+fn unsafe_is_big_endian0(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&[1])
+}
+
+fn unsafe_unaligned_access0(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&[1])
+}
+
+fn atomic_long_vm_supports_cs8(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    // both halves of a long live in the same heap item, so compareAndSetLong is always atomic
+    thread.return_value(&[1])
+}
+
+fn unsafe_object_field_offset1(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let c = current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let name_ref = current_frame
+        .local_variables
+        .get(2)
+        .ok_or("no item in local_variables")?;
+
+    let field_name = string_from_java_string(global_memory, HeapRef(*name_ref))?;
+
+    let klass = global_memory
+        .method_area
+        .classes
+        .values()
+        .find(|class| {
+            let clone = class.get_java_clone();
+            clone.is_some() && clone.unwrap() == HeapRef(*c)
+        })
+        .ok_or("class not found?")?;
+
+    let offset = klass
+        .as_instance_klass()
+        .unwrap()
+        .field_offset_with_strings(klass.get_name().to_owned(), field_name.to_owned())?;
+
+    thread.return_value(&slots::long_to_slots(offset as i64))
+}
+
+fn unsafe_compare_and_set_int_or_object(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let offset = unsafe_offset_argument(thread, 2)?;
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let object_ref = current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let expected = current_frame
+        .local_variables
+        .get(4)
+        .ok_or("no item in local_variables")?;
+    let x = current_frame
+        .local_variables
+        .get(5)
+        .ok_or("no item in local_variables")?;
+
+    let (object_ref, expected, x) = (*object_ref, *expected, *x);
+
+    let field_type = unsafe_value_type(thread)?;
+    let value_at_offset = unsafe_read(global_memory, object_ref, offset, &field_type)?;
+    let mut successful = 0;
+    if value_at_offset == [expected] {
+        unsafe_write(global_memory, object_ref, offset, &field_type, &[x])?;
+        successful = 1;
+    }
+
+    thread.return_value(&[successful])
+}
+
+fn unsafe_compare_and_set_long(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let offset = unsafe_offset_argument(thread, 2)?;
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let object_ref = current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let expected_part1 = current_frame
+        .local_variables
+        .get(4)
+        .ok_or("no item in local_variables")?;
+    let expected_part2 = current_frame
+        .local_variables
+        .get(5)
+        .ok_or("no item in local_variables")?;
+    let x_part1 = current_frame
+        .local_variables
+        .get(6)
+        .ok_or("no item in local_variables")?;
+    let x_part2 = current_frame
+        .local_variables
+        .get(7)
+        .ok_or("no item in local_variables")?;
+
+    let (object_ref, expected, x) = (
+        *object_ref,
+        [*expected_part1, *expected_part2],
+        [*x_part1, *x_part2],
+    );
+
+    let value_at_offset = unsafe_read(global_memory, object_ref, offset, &FieldType::LongInteger)?;
+    let mut successful = 0;
+    if value_at_offset == expected {
+        unsafe_write(
+            global_memory,
+            object_ref,
+            offset,
+            &FieldType::LongInteger,
+            &x,
+        )?;
+        successful = 1;
+    }
+
+    thread.return_value(&[successful])
+}
+
+// the offset argument of an Unsafe native at the given index of its local variables. Offsets
+// are indices into the slots of the data of an object, see objectFieldOffset1, or into the bytes
+// of an array of primitives, see arrayIndexScale0.
+fn unsafe_offset_argument(thread: &Thread, index: usize) -> Result<usize, Box<dyn Error>> {
+    Ok(usize::try_from(long_argument(thread, index)?)?)
+}
+
+// reads the value at the offset of an object or array and returns it. Only one thread runs at a
+// time, so every access is as good as a volatile one, see getfield.
+fn unsafe_get_value(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let object_ref = *thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let offset = unsafe_offset_argument(thread, 2)?;
+    let field_type = match unsafe_native_method(thread)?
+        .descriptor
+        .return_descriptor
+        .to_owned()
+    {
+        crate::parse::ReturnDescriptor::FieldType(field_type) => field_type,
+        crate::parse::ReturnDescriptor::VoidDescriptor => {
+            return Err("Unsafe getter returns void".into())
+        }
+    };
+
+    let values = unsafe_read(global_memory, object_ref, offset, &field_type)?;
+
+    thread.return_value(&values)
+}
+
+// writes the value argument to the offset of an object or array
+fn unsafe_put_value(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let field_type = unsafe_value_type(thread)?;
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let object_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let values = current_frame
+        .local_variables
+        .get(4..4 + field_type.width())
+        .ok_or("no item in local_variables")?
+        .to_vec();
+    let offset = unsafe_offset_argument(thread, 2)?;
+
+    unsafe_write(global_memory, object_ref, offset, &field_type, &values)
+}
+
+// The slots of the value of the type at the offset of an object. Without an object the offset is
+// an address of native_memory, and for arrays of primitives it counts bytes, so that e.g. an int
+// can be read out of a byte[] just like the jdk does.
+fn unsafe_read(
+    global_memory: &GlobalMemory,
+    object_ref: u32,
+    offset: usize,
+    field_type: &FieldType,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    if object_ref == 0 || is_primitive_array(global_memory, object_ref) {
+        let bytes =
+            unsafe_memory_bytes(global_memory, object_ref, offset, native_size(field_type)?)?;
+        return Ok(value_from_native_bytes(field_type, &bytes));
+    }
+    Ok(global_memory
+        .heap
+        .get(HeapRef(object_ref))
+        .ok_or("not on the heap")?
+        .data
+        .get(offset..offset + field_type.width())
+        .ok_or("offset out of bounds")?
+        .to_vec())
+}
+
+fn unsafe_write(
+    global_memory: &mut GlobalMemory,
+    object_ref: u32,
+    offset: usize,
+    field_type: &FieldType,
+    values: &[u32],
+) -> Result<(), Box<dyn Error>> {
+    if object_ref == 0 || is_primitive_array(global_memory, object_ref) {
+        let bytes = native_bytes_of_value(field_type, values);
+        return unsafe_write_memory_bytes(global_memory, object_ref, offset, &bytes);
+    }
+    global_memory
+        .heap
+        .get_mut(HeapRef(object_ref))
+        .ok_or("not on the heap")?
+        .data
+        .get_mut(offset..offset + values.len())
+        .ok_or("offset out of bounds")?
+        .copy_from_slice(values);
+    Ok(())
+}
+
+fn is_primitive_array(global_memory: &GlobalMemory, objectref: u32) -> bool {
+    global_memory
+        .heap
+        .field_descriptor(HeapRef(objectref))
+        .and_then(primitive_element_size)
+        .is_some()
+}
+
+// the type of the value an Unsafe setter or compareAndSet writes, its last parameter
+fn unsafe_value_type(thread: &Thread) -> Result<FieldType, Box<dyn Error>> {
+    Ok(unsafe_native_method(thread)?
+        .descriptor
+        .parameter_descriptors
+        .last()
+        .ok_or("Unsafe setter without a value")?
+        .clone())
+}
+
+// the Unsafe method running in the current frame, whose descriptor tells the type of the value
+// that is read or written
+fn unsafe_native_method(thread: &Thread) -> Result<Arc<Method>, Box<dyn Error>> {
+    thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?
+        .method
+        .clone()
+        .ok_or("no method in the current frame".into())
+}
+
+// how many bytes a value of the type takes in native memory
+fn native_size(field_type: &FieldType) -> Result<usize, Box<dyn Error>> {
+    match field_type {
+        FieldType::Boolean | FieldType::Byte => Ok(1),
+        FieldType::Char | FieldType::Short => Ok(2),
+        FieldType::Integer | FieldType::Float => Ok(4),
+        FieldType::LongInteger | FieldType::Double => Ok(8),
+        FieldType::ClassInstance(_) | FieldType::Array(_) => {
+            Err("references can't be stored in native memory".into())
+        }
+    }
+}
+
+// the bytes of the value in the slots, most significant first
+fn native_bytes_of_value(field_type: &FieldType, slots: &[u32]) -> Vec<u8> {
+    let value = slots
+        .iter()
+        .fold(0u64, |value, slot| value << 32 | *slot as u64);
+    let size = native_size(field_type).unwrap_or(8);
+    value.to_be_bytes()[8 - size..].to_vec()
+}
+
+// the slots of the value in the bytes, with bytes and shorts sign extended to ints like the
+// operand stack has them
+fn value_from_native_bytes(field_type: &FieldType, bytes: &[u8]) -> Vec<u32> {
+    let value = bytes
+        .iter()
+        .fold(0u64, |value, byte| value << 8 | *byte as u64);
+    match field_type {
+        FieldType::Byte => vec![conversions::i2b(value as i32) as u32],
+        FieldType::Short => vec![conversions::i2s(value as i32) as u32],
+        FieldType::LongInteger | FieldType::Double => slots::long_to_slots(value as i64).to_vec(),
+        _ => vec![value as u32],
+    }
+}
+
+// The bytes the bulk operations of Unsafe see at the offset of an object. Without an object the
+// offset is an address of native_memory, otherwise the object has to be an array of primitives,
+// whose elements are laid out one after the other, most significant byte first.
+fn unsafe_memory_bytes(
+    global_memory: &GlobalMemory,
+    object_ref: u32,
+    offset: usize,
+    length: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if object_ref == 0 {
+        return Ok(global_memory
+            .native_memory
+            .slice(offset as u64, length)?
+            .to_vec());
+    }
+    Ok(primitive_array_bytes(global_memory, object_ref)?
+        .get(offset..offset + length)
+        .ok_or("offset out of bounds")?
+        .to_vec())
+}
+
+fn unsafe_write_memory_bytes(
+    global_memory: &mut GlobalMemory,
+    object_ref: u32,
+    offset: usize,
+    bytes: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    if object_ref == 0 {
+        global_memory
+            .native_memory
+            .slice_mut(offset as u64, bytes.len())?
+            .copy_from_slice(bytes);
+        return Ok(());
+    }
+    // checks that it is an array of primitives
+    primitive_array_bytes(global_memory, object_ref)?;
+    global_memory
+        .heap
+        .get_mut(HeapRef(object_ref))
+        .ok_or("not on the heap")?
+        .bytes
+        .get_mut(offset..offset + bytes.len())
+        .ok_or("offset out of bounds")?
+        .copy_from_slice(bytes);
+    Ok(())
+}
+
+fn primitive_array_bytes(
+    global_memory: &GlobalMemory,
+    array_ref: u32,
+) -> Result<&[u8], Box<dyn Error>> {
+    let heap_item = global_memory
+        .heap
+        .get(HeapRef(array_ref))
+        .ok_or("not on the heap")?;
+    let field_descriptor = global_memory.heap.descriptor(heap_item);
+    if primitive_element_size(field_descriptor).is_none() {
+        return Err(format!("{field_descriptor} is not an array of primitives").into());
+    }
+    Ok(&heap_item.bytes)
+}
+
+fn unsafe_allocate_memory0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let size = unsafe_offset_argument(thread, 1)?;
+    let Some(address) = global_memory.native_memory.allocate(size as u64) else {
+        return throw_native_out_of_memory(thread, global_memory, size);
+    };
+    push_long_result(thread, address as i64)
+}
+
+fn unsafe_reallocate_memory0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let address = unsafe_offset_argument(thread, 1)?;
+    let size = unsafe_offset_argument(thread, 3)?;
+    let Some(new_address) = global_memory
+        .native_memory
+        .reallocate(address as u64, size as u64)?
+    else {
+        return throw_native_out_of_memory(thread, global_memory, size);
+    };
+    push_long_result(thread, new_address as i64)
+}
+
+// like hotspot, running out of native memory is an OutOfMemoryError rather than the end of the vm
+fn throw_native_out_of_memory(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    size: usize,
+) -> Result<(), Box<dyn Error>> {
+    thread.throw_exception_with_message(
+        global_memory,
+        "java/lang/OutOfMemoryError",
+        &format!("Unable to allocate {size} bytes"),
+    )
+}
+
+fn unsafe_free_memory0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let address = unsafe_offset_argument(thread, 1)?;
+    global_memory.native_memory.free(address as u64)
+}
+
+fn unsafe_set_memory0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let object_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let value = *current_frame
+        .local_variables
+        .get(6)
+        .ok_or("no item in local_variables")? as u8;
+    let offset = unsafe_offset_argument(thread, 2)?;
+    let length = unsafe_offset_argument(thread, 4)?;
+
+    unsafe_write_memory_bytes(global_memory, object_ref, offset, &vec![value; length])
+}
+
+fn unsafe_copy_memory0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let source_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let destination_ref = *current_frame
+        .local_variables
+        .get(4)
+        .ok_or("no item in local_variables")?;
+    let source_offset = unsafe_offset_argument(thread, 2)?;
+    let destination_offset = unsafe_offset_argument(thread, 5)?;
+    let length = unsafe_offset_argument(thread, 7)?;
+
+    let bytes = unsafe_memory_bytes(global_memory, source_ref, source_offset, length)?;
+    unsafe_write_memory_bytes(global_memory, destination_ref, destination_offset, &bytes)
+}
+
+// like copyMemory0, but reverses the bytes of every element of element_size bytes, which is how
+// buffers of another byte order are copied from and to arrays
+fn unsafe_copy_swap_memory0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let source_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let destination_ref = *current_frame
+        .local_variables
+        .get(4)
+        .ok_or("no item in local_variables")?;
+    let source_offset = unsafe_offset_argument(thread, 2)?;
+    let destination_offset = unsafe_offset_argument(thread, 5)?;
+    let length = unsafe_offset_argument(thread, 7)?;
+    let element_size = unsafe_offset_argument(thread, 9)?;
+    if element_size == 0 {
+        return Err("element size of 0".into());
+    }
+
+    let mut bytes = unsafe_memory_bytes(global_memory, source_ref, source_offset, length)?;
+    for element in bytes.chunks_mut(element_size) {
+        element.reverse();
+    }
+    unsafe_write_memory_bytes(global_memory, destination_ref, destination_offset, &bytes)
+}
+
+fn unsafe_ensure_class_initialized0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let class_name = mirror_argument_class_name(thread, global_memory, 1)?;
+    // arrays and primitive types have no <clinit> to run
+    if global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .and_then(|klass| klass.as_instance_klass())
+        .is_some()
+    {
+        if let Err(error) = global_memory.ensure_class(&class_name) {
+            return thread.throw_initialization_error(global_memory, error);
+        }
+    }
+    Ok(())
+}
+
+fn unsafe_should_be_initialized0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let class_name = mirror_argument_class_name(thread, global_memory, 1)?;
+    let state = global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .ok_or("class not found in method area")?
+        .get_state();
+
+    thread.return_value(&[(state != ClassState::Initialized) as u32])
+}
+
+fn unsafe_define_class0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let arguments = current_frame
+        .local_variables
+        .get(1..6)
+        .ok_or("no item in local_variables")?
+        .to_vec();
+
+    let Some(class_ref) = define_class_from_java_bytes(
+        thread,
+        global_memory,
+        arguments[4],
+        arguments[0],
+        arguments[1],
+        arguments[2] as i32,
+        arguments[3] as i32,
+    )?
+    else {
+        return Ok(());
+    };
+
+    thread.return_value(&[class_ref])
+}
+
+// defines a class from length bytes at offset of a byte array, see define_class_from_bytes. None
+// is returned if an exception was thrown.
+fn define_class_from_java_bytes(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    loader_ref: u32,
+    name_ref: u32,
+    bytes_ref: u32,
+    offset: i32,
+    length: i32,
+) -> Result<Option<u32>, Box<dyn Error>> {
+    if bytes_ref == 0 {
+        thread.throw_exception(global_memory, "java/lang/NullPointerException")?;
+        return Ok(None);
+    }
+    let array_bytes = &global_memory
+        .heap
+        .get(HeapRef(bytes_ref))
+        .ok_or("not on the heap")?
+        .bytes;
+    let Some(bytes) = usize::try_from(offset)
+        .ok()
+        .zip(usize::try_from(length).ok())
+        .and_then(|(offset, length)| array_bytes.get(offset..offset.checked_add(length)?))
+    else {
+        thread.throw_exception(global_memory, "java/lang/ArrayIndexOutOfBoundsException")?;
+        return Ok(None);
+    };
+    let bytes = bytes.to_vec();
+
+    define_class_from_bytes(thread, global_memory, loader_ref, name_ref, &bytes)
+}
+
+// defines a class from the bytes of a class file, returning its mirror. The class is linked, but
+// not initialized. If name_ref isn't null, the class has to have that name. None is returned if an
+// exception was thrown because the bytes are no class file, the name doesn't match or a class of
+// that name exists already.
+//
+// There is only one namespace for all classes, no matter which loader defined them. The defining
+// loader is only remembered in the mirror, for Class.getClassLoader and Class.getModule.
+fn define_class_from_bytes(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    loader_ref: u32,
+    name_ref: u32,
+    bytes: &[u8],
+) -> Result<Option<u32>, Box<dyn Error>> {
+    let class = match deserialize_class_bytes(bytes)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(parse)
+    {
+        Ok(class) => class,
+        Err(error) => {
+            thread.throw_exception_with_message(
+                global_memory,
+                "java/lang/ClassFormatError",
+                &error.to_string(),
+            )?;
+            return Ok(None);
+        }
+    };
+    let class_name = class.this_class.name.to_owned();
+    if name_ref != 0 {
+        let expected_name =
+            string_from_java_string(global_memory, HeapRef(name_ref))?.replace('.', "/");
+        if expected_name != class_name {
+            thread.throw_exception_with_message(
+                global_memory,
+                "java/lang/NoClassDefFoundError",
+                &format!("{expected_name} (wrong name: {class_name})"),
+            )?;
+            return Ok(None);
+        }
+    }
+    if global_memory.method_area.classes.contains_key(&class_name) {
+        thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/LinkageError",
+            &format!(
+                "duplicate class definition for {}",
+                class_name.replace('/', ".")
+            ),
+        )?;
+        return Ok(None);
+    }
+
+    // the classes defined by java land don't come from the JDK
+    let needs_verification = global_memory.verify_mode != VerifyMode::None;
+    global_memory.define_class(class, needs_verification)?;
+    global_memory.ensure_linked(&class_name)?;
+    let class_ref = global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .and_then(|klass| klass.get_java_clone())
+        .ok_or(format!("class {class_name} has no mirror"))?;
+    set_field_value(
+        global_memory,
+        class_ref.0,
+        "java/lang/Class",
+        "classLoader",
+        loader_ref,
+    )?;
+    // there are no named modules, so classes of a loader are in the unnamed module it creates
+    if loader_ref != 0 {
+        let module_ref = get_field_value(
+            global_memory,
+            loader_ref,
+            "java/lang/ClassLoader",
+            "unnamedModule",
+        )?;
+        set_field_value(
+            global_memory,
+            class_ref.0,
+            "java/lang/Class",
+            "module",
+            module_ref,
+        )?;
+    }
+    Ok(Some(class_ref.0))
+}
+
+// static Class<?> defineClass1(ClassLoader loader, String name, byte[] b, int off, int len,
+//                              ProtectionDomain pd, String source)
+fn class_loader_define_class1(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let arguments = current_frame
+        .local_variables
+        .get(0..5)
+        .ok_or("no item in local_variables")?
+        .to_vec();
+
+    let Some(class_ref) = define_class_from_java_bytes(
+        thread,
+        global_memory,
+        arguments[0],
+        arguments[1],
+        arguments[2],
+        arguments[3] as i32,
+        arguments[4] as i32,
+    )?
+    else {
+        return Ok(());
+    };
+
+    thread.return_value(&[class_ref])
+}
+
+// like defineClass1, but the bytes are in a direct ByteBuffer, starting off bytes after its
+// address. ClassLoader.defineClass copies heap buffers into an array and uses defineClass1.
+fn class_loader_define_class2(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let arguments = current_frame
+        .local_variables
+        .get(0..5)
+        .ok_or("no item in local_variables")?
+        .to_vec();
+    let (loader_ref, name_ref, buffer_ref) = (arguments[0], arguments[1], arguments[2]);
+    let (offset, length) = (arguments[3] as i32, arguments[4] as i32);
+    if buffer_ref == 0 {
+        return thread.throw_exception(global_memory, "java/lang/NullPointerException");
+    }
+
+    let address_offset = global_memory
+        .method_area
+        .classes
+        .get("java/nio/Buffer")
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .field_offset_with_strings("java/nio/Buffer".to_owned(), "address".to_owned())?;
+    let data = &global_memory
+        .heap
+        .get(HeapRef(buffer_ref))
+        .ok_or("buffer not on heap")?
+        .data;
+    let address = slots::long_from_slots(
+        *data
+            .get(address_offset)
+            .ok_or("field offset out of bounds")?,
+        *data
+            .get(address_offset + 1)
+            .ok_or("field offset out of bounds")?,
+    ) as u64;
+    let bytes = global_memory
+        .native_memory
+        .slice(address + u64::try_from(offset)?, usize::try_from(length)?)?
+        .to_vec();
+
+    let Some(class_ref) =
+        define_class_from_bytes(thread, global_memory, loader_ref, name_ref, &bytes)?
+    else {
+        return Ok(());
+    };
+
+    thread.return_value(&[class_ref])
+}
+
+// The performance counters of the jdk, like the time ClassLoader.loadClass spends in its parent,
+// are backed by buffers the vm shares with monitoring tools. Nobody is watching here, so every
+// counter gets a ByteBuffer of its own.
+fn perf_create_long(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    global_memory.ensure_class("java/nio/ByteBuffer")?;
+    let result = thread.invoke_method(
+        global_memory,
+        "java/nio/ByteBuffer".to_owned(),
+        "allocate".to_owned(),
+        MethodDescriptor {
+            parameter_descriptors: vec![FieldType::Integer],
+            return_descriptor: crate::parse::ReturnDescriptor::FieldType(FieldType::ClassInstance(
+                "java/nio/ByteBuffer".to_owned(),
+            )),
+        },
+        vec![8],
+    )?;
+    if thread.is_throwing {
+        return Ok(());
+    }
+
+    thread.return_value(&result)
+}
+
+// highResCounter counts nanoseconds, see system_nano_time
+fn perf_high_res_frequency(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    push_long_result(thread, 1_000_000_000)
+}
+
+// the mirror of a class of that name if it was loaded already, by whatever loader, or null
+fn class_loader_find_loaded_class0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let name_ref = *thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let class_name = string_from_java_string(global_memory, HeapRef(name_ref))?.replace('.', "/");
+    let class_ref = global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .and_then(|klass| klass.get_java_clone())
+        .unwrap_or(HeapRef::NULL);
+
+    thread.return_value(&[class_ref.0])
+}
+
+// the mirror of a class the vm loads from its class path, or null if there is no such class file.
+// Loaders without a parent delegate to this.
+fn class_loader_find_bootstrap_class(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let name_ref = *thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let class_name = string_from_java_string(global_memory, HeapRef(name_ref))?.replace('.', "/");
+    let class_ref = if global_memory.method_area.classes.contains_key(&class_name)
+        || find_class_file(&class_name).is_some()
+    {
+        global_memory.ensure_linked(&class_name)?;
+        global_memory
+            .method_area
+            .classes
+            .get(&class_name)
+            .and_then(|klass| klass.get_java_clone())
+            .ok_or(format!("class {class_name} has no mirror"))?
+    } else {
+        HeapRef::NULL
+    };
+
+    thread.return_value(&[class_ref.0])
+}
+
+// the fences of Unsafe, which VarHandle.acquireFence and friends end up in as well
+fn unsafe_load_fence(
+    _thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    fence(Ordering::Acquire);
+    Ok(())
+}
+
+fn unsafe_store_fence(
+    _thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    fence(Ordering::Release);
+    Ok(())
+}
+
+fn unsafe_full_fence(
+    _thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    fence(Ordering::SeqCst);
+    Ok(())
+}
+
+// native_memory doesn't come in pages, but java/nio wants to align buffers to something
+fn unsafe_page_size(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&[4096])
+}
+
+// Shutdown.exit, which System.exit ends up in, calls this before running the shutdown hooks
+fn shutdown_before_halt(
+    _thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    global_memory.shutting_down = true;
+    Ok(())
+}
+
+fn shutdown_halt0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let status = Cursor::new(
+        current_frame
+            .local_variables
+            .first()
+            .ok_or("no item in local_variables")?
+            .to_be_bytes(),
+    )
+    .read_i32::<BigEndian>()?;
+
+    global_memory.exit_status = Some(status);
+    threads::changed(global_memory);
+    Ok(())
+}
+
+fn runtime_available_processors(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    // For now, let's not report the actual number of processors.
+    thread.return_value(&[1])
+}
+
+// natives can't collect themselves, as their invoker may hold references the gc doesn't know
+// about, so the interpreter does so before the next instruction
+fn runtime_gc(
+    _thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    global_memory.heap.collection_requested = true;
+    Ok(())
+}
+
+// the bytes the heap takes up, whether the objects in it are still reachable or not
+fn runtime_total_memory(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let used_bytes = gc::heap_bytes(&global_memory.heap);
+    let total_bytes = global_memory.heap.committed_bytes.max(used_bytes);
+    push_long_result(thread, total_bytes as i64)
+}
+
+// what is left of the total memory, until the heap grows again
+fn runtime_free_memory(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let used_bytes = gc::heap_bytes(&global_memory.heap);
+    let total_bytes = global_memory.heap.committed_bytes.max(used_bytes);
+    push_long_result(thread, (total_bytes - used_bytes) as i64)
+}
+
+// the heap has no limit, which Runtime.maxMemory reports as Long.MAX_VALUE
+fn runtime_max_memory(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    push_long_result(thread, i64::MAX)
+}
+
+// pushes the long a native returns onto the operand stack of its invoker, the high word first
+fn push_long_result(thread: &mut Thread, value: i64) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&slots::long_to_slots(value))
+}
+
+fn thread_current_thread(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let java_clone = thread.java_clone.ok_or("thread without object")?;
+
+    thread.return_value(&[java_clone.0])
+}
+
+// the threads the JDK starts itself to process references are run by the vm whenever there are
+// references pending, see process_pending_references. Every other thread gets a thread of its
+// own, see threads.
+fn thread_start0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let class_name = global_memory
+        .heap
+        .field_descriptor(HeapRef(this_ref))
+        .ok_or("thread not on heap")?
+        .trim_start_matches('L')
+        .trim_end_matches(';')
+        .to_owned();
+    if class_name == "java/lang/ref/Reference$ReferenceHandler" {
+        global_memory.reference_handler = Some(this_ref);
+        return Ok(());
+    }
+    let target_ref = get_field_value(global_memory, this_ref, "java/lang/Thread", "target")?;
+    let is_cleaner = global_memory
+        .heap
+        .field_descriptor(HeapRef(target_ref))
+        .is_some_and(|descriptor| descriptor == "Ljdk/internal/ref/CleanerImpl;");
+    if is_cleaner {
+        global_memory.cleaners.push(target_ref);
+        return Ok(());
+    }
+    // the shutdown hooks are started one after the other and then joined, so running each one
+    // to completion right away is all it takes
+    if global_memory.shutting_down {
+        return thread.run_to_completion(global_memory, this_ref, &class_name);
+    }
+    threads::start(global_memory, HeapRef(this_ref), class_name)
+}
+
+// the class declaring the run method of a thread object of class class_name, selected like
+// invokevirtual would
+fn thread_run_method(
+    global_memory: &GlobalMemory,
+    class_name: &str,
+) -> Result<ClassId, Box<dyn Error>> {
+    let no_arguments = MethodDescriptor {
+        parameter_descriptors: vec![],
+        return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+    };
+    Ok(global_memory
+        .method_area
+        .vtable_index("java/lang/Thread", "run", &no_arguments)
+        .and_then(|index| global_memory.method_area.vtable_entry(class_name, index))
+        .ok_or(format!("{class_name} has no run method"))?)
+}
+
+fn thread_is_alive(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let is_alive = thread.java_clone == Some(HeapRef(this_ref))
+        || threads::is_alive(global_memory, HeapRef(this_ref));
+
+    thread.return_value(&[is_alive as u32])
+}
+
+// the interrupted flag of the vm whose run_main installed the SIGINT handler last, see
+// install_interrupt_handler
+static INTERRUPT_TARGET: AtomicPtr<AtomicBool> = AtomicPtr::new(std::ptr::null_mut());
+
+extern "C" fn handle_interrupt(_signal: libc::c_int) {
+    // the flags stored are never freed, see install_interrupt_handler
+    if let Some(interrupted) = unsafe { INTERRUPT_TARGET.load(Ordering::SeqCst).as_ref() } {
+        interrupted.store(true, Ordering::SeqCst);
+    }
+}
+
+// like java, ^C runs the shutdown hooks before exiting: the handler sets the interrupted flag of
+// the vm, which the thread holding the vm lock handles at its next safepoint just like the
+// Terminator of the JDK would, by exiting with 130. The handler is reset to the default action
+// once it ran, so a second ^C ends the process right away, e.g. when a hook hangs. It doesn't
+// restart system calls either, so that a read blocking on stdin returns, see
+// read_from_file_descriptor. Returns the action to restore once run_main is done.
+fn install_interrupt_handler(interrupted: &Arc<AtomicBool>) -> std::io::Result<libc::sigaction> {
+    // leaked rather than freed when the vm is dropped, as a handler running on another thread
+    // might still use it
+    let target = Arc::into_raw(interrupted.clone()) as *mut AtomicBool;
+    INTERRUPT_TARGET.store(target, Ordering::SeqCst);
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handle_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND;
+        libc::sigemptyset(&mut action.sa_mask);
+        let mut previous: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(libc::SIGINT, &action, &mut previous) != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        Ok(previous)
+    }
+}
+
+fn reference_get_and_clear_reference_pending_list(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let pending_list = std::mem::take(&mut global_memory.reference_pending_list);
+
+    thread.return_value(&[pending_list.0])
+}
+
+fn reference_has_reference_pending_list(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&[!global_memory.reference_pending_list.is_null() as u32])
+}
+
+fn reference_refers_to0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let objectref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let referent = get_field_value(
+        global_memory,
+        this_ref,
+        "java/lang/ref/Reference",
+        "referent",
+    )?;
+
+    thread.return_value(&[(referent == objectref) as u32])
+}
+
+fn reference_clear0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    set_field_value(
+        global_memory,
+        this_ref,
+        "java/lang/ref/Reference",
+        "referent",
+        0,
+    )
+}
+
+fn array_new_array(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let class_ref = current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    // FIXME: check if class_ref is null
+    let length = Cursor::new(
+        current_frame
+            .local_variables
+            .get(1)
+            .ok_or("no item in local_variables")?
+            .to_be_bytes(),
+    )
+    .read_i32::<BigEndian>()?;
+
+    let klass = global_memory
+        .method_area
+        .classes
+        .values()
+        .find(|class| {
+            let maybe_java_clone = class.get_java_clone();
+            if maybe_java_clone.is_none() {
+                return false;
+            }
+            maybe_java_clone.unwrap() == HeapRef(*class_ref)
+        })
+        .unwrap();
+    if klass.get_name() == "void" {
+        return thread.throw_exception(global_memory, "java/lang/IllegalArgumentException");
+    }
+    let array_type = array_type_of_component(klass.get_name());
+    global_memory.ensure_array(array_type.to_owned())?;
+    let objectref = global_memory.heap.store_array(&array_type, length as usize);
+
+    thread.return_value(&[objectref.0])
+}
+
+fn file_input_stream_open0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let name_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+
+    let name = string_from_java_string(global_memory, HeapRef(name_ref))?;
+    let file = match File::open(&name) {
+        Ok(file) => file,
+        Err(e) => {
+            return thread.throw_exception_with_message(
+                global_memory,
+                "java/io/FileNotFoundException",
+                &file_not_found_message(&name, &e),
+            )
+        }
+    };
+    let fd = global_memory.open_file(file);
+    set_file_descriptor(global_memory, "java/io/FileInputStream", this_ref, fd)?;
+    Ok(())
+}
+
+fn file_input_stream_read0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let fd = get_file_descriptor(global_memory, "java/io/FileInputStream", this_ref)?;
+
+    let mut buf = [0u8; 1];
+    let read = global_memory.read_from_file_descriptor(fd, &mut buf)?;
+    // the byte is returned as an int in the range 0 to 255, or -1 on end of file
+    let result: i32 = if read == 0 { -1 } else { buf[0] as i32 };
+
+    thread.return_value(&[Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?])
+}
+
+fn file_input_stream_read_bytes(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let byte_array_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let off = Cursor::new(
+        current_frame
+            .local_variables
+            .get(2)
+            .ok_or("no item in local_variables")?
+            .to_be_bytes(),
+    )
+    .read_i32::<BigEndian>()?;
+    let len = Cursor::new(
+        current_frame
+            .local_variables
+            .get(3)
+            .ok_or("no item in local_variables")?
+            .to_be_bytes(),
+    )
+    .read_i32::<BigEndian>()?;
+    let fd = get_file_descriptor(global_memory, "java/io/FileInputStream", this_ref)?;
+
+    let result: i32 = if len <= 0 {
+        0
+    } else {
+        let mut buf = vec![0u8; len as usize];
+        let read = global_memory.read_from_file_descriptor(fd, &mut buf)?;
+        if read == 0 {
+            -1
+        } else {
+            global_memory
+                .heap
+                .get_mut(HeapRef(byte_array_ref))
+                .ok_or("byte array not on heap")?
+                .bytes
+                .get_mut(off as usize..off as usize + read)
+                .ok_or("offset out of bounds")?
+                .copy_from_slice(&buf[..read]);
+            read as i32
+        }
+    };
+
+    thread.return_value(&[Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?])
+}
+
+fn file_input_stream_available0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let fd = get_file_descriptor(global_memory, "java/io/FileInputStream", this_ref)?;
+    let available = global_memory.available_from_file_descriptor(fd)?;
+
+    thread.return_value(&[Cursor::new(available.to_be_bytes()).read_u32::<BigEndian>()?])
+}
+
+fn file_output_stream_open0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let name_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let append = *current_frame
+        .local_variables
+        .get(2)
+        .ok_or("no item in local_variables")?
+        != 0;
+
+    let name = string_from_java_string(global_memory, HeapRef(name_ref))?;
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(append)
+        .truncate(!append)
+        .open(&name);
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            return thread.throw_exception_with_message(
+                global_memory,
+                "java/io/FileNotFoundException",
+                &file_not_found_message(&name, &e),
+            )
+        }
+    };
+    let fd = global_memory.open_file(file);
+    set_file_descriptor(global_memory, "java/io/FileOutputStream", this_ref, fd)?;
+    Ok(())
+}
+
+// like the message of java, the path followed by the reason it could not be opened, e.g.
+// "out/log.txt (No such file or directory)"
+fn file_not_found_message(name: &str, error: &std::io::Error) -> String {
+    let reason = error.to_string();
+    let reason = match reason.rfind(" (os error ") {
+        Some(index) => &reason[..index],
+        None => &reason,
+    };
+    format!("{name} ({reason})")
+}
+
+fn file_output_stream_write(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let byte = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")? as u8;
+
+    let fd = get_file_descriptor(global_memory, "java/io/FileOutputStream", this_ref)?;
+    global_memory.write_to_file_descriptor(fd, &[byte])?;
+    Ok(())
+}
+
+fn file_output_stream_write_bytes(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    // FIXME: check if this_ref is null
+    let fd = get_file_descriptor(global_memory, "java/io/FileOutputStream", this_ref)?;
+
+    let byte_array_ref = current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let off = Cursor::new(
+        current_frame
+            .local_variables
+            .get(2)
+            .ok_or("no item in local_variables")?
+            .to_be_bytes(),
+    )
+    .read_i32::<BigEndian>()?;
+    let len = Cursor::new(
+        current_frame
+            .local_variables
+            .get(3)
+            .ok_or("no item in local_variables")?
+            .to_be_bytes(),
+    )
+    .read_i32::<BigEndian>()?;
+
+    let bytes = global_memory
+        .heap
+        .get(HeapRef(*byte_array_ref))
+        .ok_or("this is not valid")?
+        .bytes
+        .iter()
+        .skip(off as usize)
+        .take(len as usize)
+        .copied()
+        .collect::<Vec<u8>>();
+    global_memory.write_to_file_descriptor(fd, &bytes)?;
+    Ok(())
+}
+
+fn file_descriptor_get_append(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let fd = Cursor::new(
+        current_frame
+            .local_variables
+            .first()
+            .ok_or("no item in local_variables")?
+            .to_be_bytes(),
+    )
+    .read_i32::<BigEndian>()?;
+
+    let mut result: i32 = 0;
+    if fd == 1 || fd == 2 {
+        result = 1;
+    }
+
+    thread.return_value(&[Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?])
+}
+
+fn file_descriptor_get_handle(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&slots::long_to_slots(-1))
+}
+
+fn file_descriptor_sync(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let fd = get_descriptor_value(global_memory, this_ref)?;
+    global_memory.flush_file_descriptor(fd)?;
+    Ok(())
+}
+
+fn file_descriptor_close0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let fd = get_descriptor_value(global_memory, this_ref)?;
+    // closing stdin, stdout or stderr would break all further output of the vm
+    if fd > 2 {
+        global_memory.open_files.remove(&fd);
+    }
+    set_descriptor_value(global_memory, this_ref, -1)?;
+    Ok(())
+}
+
+fn signal_find_signal0(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    // fixme: signal code mapping
+    thread.return_value(&[1])
+}
+
+fn signal_handle0(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&slots::long_to_slots(19))
+}
+
+// there are no protection domains to restrict anything, which both the stack and the inherited
+// context express as null
+fn access_controller_get_stack_access_control_context(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&[0])
+}
+
+// runs action.run() with all permissions, which are never checked anyway, ignoring the context
+// the overloads taking one get. Checked exceptions thrown by a PrivilegedExceptionAction are
+// wrapped in a PrivilegedActionException, everything else is passed on as it is.
+fn access_controller_do_privileged(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let action_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let is_exception_action = current_frame
+        .method
+        .as_ref()
+        .and_then(|method| method.descriptor.parameter_descriptors.first())
+        == Some(&FieldType::ClassInstance(
+            "java/security/PrivilegedExceptionAction".to_owned(),
+        ));
+    if action_ref == 0 {
+        return thread.throw_exception(global_memory, "java/lang/NullPointerException");
+    }
+    let class_name = parse_field_descriptor(
+        global_memory
+            .heap
+            .field_descriptor(HeapRef(action_ref))
+            .ok_or("action not on heap")?,
+    )?
+    .field_type
+    .as_class_instance()
+    .ok_or("action is not a class instance")?
+    .to_owned();
+
+    let result = thread.invoke_method(
+        global_memory,
+        class_name,
+        "run".to_owned(),
+        MethodDescriptor {
+            parameter_descriptors: vec![],
+            return_descriptor: crate::parse::ReturnDescriptor::FieldType(FieldType::ClassInstance(
+                "java/lang/Object".to_owned(),
+            )),
+        },
+        vec![action_ref],
+    )?;
+    // a thrown exception stays on our frame and is passed on to the invoker from there
+    if thread.is_throwing {
+        if !is_exception_action {
+            return Ok(());
+        }
+        let exception_ref = *thread
+            .thread_memory
+            .jvm_stack
+            .last()
+            .ok_or("no item on jvm stack")?
+            .operand_stack
+            .last()
+            .ok_or("no exception on the operand_stack")?;
+        let exception_type = parse_field_descriptor(
+            global_memory
+                .heap
+                .field_descriptor(HeapRef(exception_ref))
+                .ok_or("exception not on heap")?,
+        )?
+        .field_type;
+        let is_checked = global_memory.method_area.is_assignable(
+            &exception_type,
+            &FieldType::ClassInstance("java/lang/Exception".to_owned()),
+        )? && !global_memory.method_area.is_assignable(
+            &exception_type,
+            &FieldType::ClassInstance("java/lang/RuntimeException".to_owned()),
+        )?;
+        if !is_checked {
+            return Ok(());
+        }
+        thread
+            .thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?
+            .operand_stack
+            .pop();
+        thread.is_throwing = false;
+        return thread.throw_new_exception(
+            global_memory,
+            "java/security/PrivilegedActionException",
+            vec![FieldType::ClassInstance("java/lang/Exception".to_owned())],
+            vec![exception_ref],
+        );
+    }
+    thread.return_value(&result)
+}
+
+// the class of the method that invoked the method calling getCallerClass
+fn reflection_get_caller_class(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let caller = thread
+        .thread_memory
+        .jvm_stack
+        .iter()
+        .rev()
+        .filter(|frame| frame.method.is_some())
+        .nth(2)
+        .ok_or("getCallerClass has no caller")?;
+    let class_ref = global_memory
+        .method_area
+        .classes
+        .by_id(caller.class_id)
+        .ok_or(format!("Class not found {} :(", caller.class_name()))?
+        .get_java_clone()
+        .ok_or("class without java clone")?;
+
+    thread.return_value(&[class_ref.0])
+}
+
+// the access_flags of a class as they appear in its class file, which Method.invoke checks the
+// caller's access against. Array classes are public, final and abstract, like primitive ones.
+fn reflection_get_class_access_flags(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let class_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+
+    let class_name = global_memory
+        .method_area
+        .class_name_of_mirror(class_ref)
+        .ok_or("no class for java clone")?;
+    let flags = match global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .and_then(|class| class.as_instance_klass())
+        .and_then(|class| class.parsed_class.as_ref())
+    {
+        Some(parsed_class) => parsed_class.access.flags as u32,
+        None => 0x0001 | 0x0010 | 0x0400,
+    };
+
+    thread.return_value(&[flags])
+}
+
+// Method.invoke, once the access checks passed. The arguments are unboxed to fit the parameter
+// types and the result is boxed again. Non-private instance methods are looked up in the class
+// of the receiver, like invokevirtual does.
+fn native_method_accessor_impl_invoke0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let method_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let receiver_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let arguments_ref = *current_frame
+        .local_variables
+        .get(2)
+        .ok_or("no item in local_variables")?;
+
+    let class_ref = get_field_value(
+        global_memory,
+        method_ref,
+        "java/lang/reflect/Method",
+        "clazz",
+    )?;
+    let slot = get_field_value(
+        global_memory,
+        method_ref,
+        "java/lang/reflect/Method",
+        "slot",
+    )?;
+    let class_name = global_memory
+        .method_area
+        .class_name_of_mirror(class_ref)
+        .ok_or("no class for java clone")?;
+    let method = global_memory
+        .method_area
+        .parsed_class(&class_name)?
+        .methods
+        .get(slot as usize)
+        .ok_or("no method at slot")?
+        .to_owned();
+
+    let mut slots = vec![];
+    let target_class_name = if method.access.r#static {
+        // invoking a static method initializes its class, like invokestatic
+        if let Err(error) = global_memory.ensure_class(&class_name) {
+            return thread.throw_initialization_error(global_memory, error);
+        }
+        class_name
+    } else {
+        if receiver_ref == 0 {
+            return thread.throw_exception(global_memory, "java/lang/NullPointerException");
+        }
+        let receiver_field_descriptor = global_memory
+            .heap
+            .field_descriptor(HeapRef(receiver_ref))
+            .ok_or("receiver not on heap")?
+            .to_owned();
+        let receiver_type = parse_field_descriptor(&receiver_field_descriptor)?.field_type;
+        if !global_memory.method_area.is_assignable(
+            &receiver_type,
+            &FieldType::ClassInstance(class_name.to_owned()),
+        )? {
+            let message_ref = java_string_from_string(
+                global_memory,
+                "object is not an instance of declaring class".to_owned(),
+            )?;
+            return thread.throw_new_exception(
+                global_memory,
+                "java/lang/IllegalArgumentException",
+                vec![FieldType::ClassInstance("java/lang/String".to_owned())],
+                vec![message_ref.0],
+            );
+        }
+        slots.push(receiver_ref);
+        if method.access.private {
+            class_name
+        } else if let Some(receiver_class_name) = receiver_type.as_class_instance() {
+            receiver_class_name.to_owned()
+        } else {
+            receiver_field_descriptor
+        }
+    };
+    match unbox_arguments(global_memory, &method.descriptor, arguments_ref)? {
+        Some(arguments) => slots.extend(arguments),
+        None => {
+            let message_ref =
+                java_string_from_string(global_memory, "argument type mismatch".to_owned())?;
+            return thread.throw_new_exception(
+                global_memory,
+                "java/lang/IllegalArgumentException",
+                vec![FieldType::ClassInstance("java/lang/String".to_owned())],
+                vec![message_ref.0],
+            );
+        }
+    }
+
+    let result = thread.invoke_method(
+        global_memory,
+        target_class_name,
+        method.name.to_owned(),
+        method.descriptor.to_owned(),
+        slots,
+    )?;
+    if thread.is_throwing {
+        // invoke_method left the exception on this frame, it is thrown on wrapped in an
+        // InvocationTargetException
+        let target_ref = thread
+            .thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?
+            .operand_stack
+            .pop()
+            .ok_or("no exception on the operand_stack")?;
+        thread.is_throwing = false;
+        return thread.throw_new_exception(
+            global_memory,
+            "java/lang/reflect/InvocationTargetException",
+            vec![FieldType::ClassInstance("java/lang/Throwable".to_owned())],
+            vec![target_ref],
+        );
+    }
+    let result_ref = match &method.descriptor.return_descriptor {
+        crate::parse::ReturnDescriptor::VoidDescriptor => 0,
+        crate::parse::ReturnDescriptor::FieldType(field_type) => {
+            let boxed = box_value(thread, global_memory, field_type, result)?;
+            if thread.is_throwing {
+                return Ok(());
+            }
+            *boxed.first().ok_or("no result")?
+        }
+    };
+
+    thread.return_value(&[result_ref])
+}
+
+// Constructor.newInstance, once the access checks passed: allocates an instance of the
+// declaring class and runs the constructor on it with the unboxed arguments
+fn native_constructor_accessor_impl_new_instance0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let constructor_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let arguments_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+
+    let class_ref = get_field_value(
+        global_memory,
+        constructor_ref,
+        "java/lang/reflect/Constructor",
+        "clazz",
+    )?;
+    let slot = get_field_value(
+        global_memory,
+        constructor_ref,
+        "java/lang/reflect/Constructor",
+        "slot",
+    )?;
+    let class_name = global_memory
+        .method_area
+        .class_name_of_mirror(class_ref)
+        .ok_or("no class for java clone")?;
+    let parsed_class = global_memory.method_area.parsed_class(&class_name)?;
+    let method = parsed_class
+        .methods
+        .get(slot as usize)
+        .ok_or("no method at slot")?
+        .to_owned();
+    // ReflectionFactory already refuses abstract classes, but not their constructors being
+    // invoked directly through the accessor
+    if parsed_class.access.r#abstract || parsed_class.access.interface {
+        return thread.throw_exception(global_memory, "java/lang/InstantiationException");
+    }
+
+    let Some(arguments) = unbox_arguments(global_memory, &method.descriptor, arguments_ref)? else {
+        let message_ref =
+            java_string_from_string(global_memory, "argument type mismatch".to_owned())?;
+        return thread.throw_new_exception(
+            global_memory,
+            "java/lang/IllegalArgumentException",
+            vec![FieldType::ClassInstance("java/lang/String".to_owned())],
+            vec![message_ref.0],
+        );
+    };
+    // like new, this initializes the class
+    if let Err(error) = global_memory.ensure_class(&class_name) {
+        return thread.throw_initialization_error(global_memory, error);
+    }
+    let klass = global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .ok_or("class not found in method area")?;
+    let objectref = global_memory.heap.allocate_klass(klass);
+
+    thread.invoke_method(
+        global_memory,
+        class_name,
+        "<init>".to_owned(),
+        method.descriptor.to_owned(),
+        [vec![objectref.0], arguments].concat(),
+    )?;
+    if thread.is_throwing {
+        let target_ref = thread
+            .thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?
+            .operand_stack
+            .pop()
+            .ok_or("no exception on the operand_stack")?;
+        thread.is_throwing = false;
+        return thread.throw_new_exception(
+            global_memory,
+            "java/lang/reflect/InvocationTargetException",
+            vec![FieldType::ClassInstance("java/lang/Throwable".to_owned())],
+            vec![target_ref],
+        );
+    }
+
+    thread.return_value(&[objectref.0])
+}
+
+fn system_map_library_name(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let name_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let name = string_from_java_string(global_memory, HeapRef(name_ref))?;
+    let library_name = format!(
+        "{}{name}{}",
+        std::env::consts::DLL_PREFIX,
+        std::env::consts::DLL_SUFFIX
+    );
+    let library_name_ref = java_string_from_string(global_memory, library_name)?;
+
+    thread.return_value(&[library_name_ref.0])
+}
+
+// there are no libraries linked into the vm itself, so there is never a builtin one
+fn class_loader_find_builtin_lib(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.return_value(&[0])
+}
+
+// the attributes of java.io.FileSystem
+const BA_EXISTS: i32 = 0x01;
+const BA_REGULAR: i32 = 0x02;
+const BA_DIRECTORY: i32 = 0x04;
+const BA_HIDDEN: i32 = 0x08;
+
+fn unix_file_system_get_boolean_attributes0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let file_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let path_ref = get_field_value(global_memory, file_ref, "java/io/File", "path")?;
+    let path = string_from_java_string(global_memory, HeapRef(path_ref))?;
+
+    let mut attributes = 0;
+    if let Ok(metadata) = std::fs::metadata(&path) {
+        attributes |= BA_EXISTS;
+        if metadata.is_file() {
+            attributes |= BA_REGULAR;
+        }
+        if metadata.is_dir() {
+            attributes |= BA_DIRECTORY;
+        }
+        let file_name = Path::new(&path).file_name();
+        if file_name.is_some_and(|name| name.to_string_lossy().starts_with('.')) {
+            attributes |= BA_HIDDEN;
+        }
+    }
+
+    thread.return_value(&[attributes as u32])
+}
+
+// paths that don't exist can be canonicalized as well, they are left as they are
+fn unix_file_system_canonicalize0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let path_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let path = string_from_java_string(global_memory, HeapRef(path_ref))?;
+    let canonical_path = match std::fs::canonicalize(&path) {
+        Ok(canonical_path) => canonical_path.to_str().ok_or("not a path")?.to_owned(),
+        Err(_) => path,
+    };
+    let canonical_path_ref = java_string_from_string(global_memory, canonical_path)?;
+
+    thread.return_value(&[canonical_path_ref.0])
+}
+
+// reads the int fd of a java/io/FileDescriptor object
+fn get_descriptor_value(
+    global_memory: &GlobalMemory,
+    file_descriptor_ref: u32,
+) -> Result<i32, Box<dyn Error>> {
+    let fd_offset = global_memory
+        .method_area
+        .classes
+        .get("java/io/FileDescriptor")
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .unwrap()
+        .field_offset_with_strings("java/io/FileDescriptor".to_owned(), "fd".to_owned())?;
+    let fd = global_memory
+        .heap
+        .get(HeapRef(file_descriptor_ref))
+        .ok_or("file descriptor not on heap")?
+        .data
+        .get(fd_offset)
+        .ok_or("what is going on with this heap data?")?;
+
+    Ok(Cursor::new(fd.to_be_bytes()).read_i32::<BigEndian>()?)
+}
+
+fn set_descriptor_value(
+    global_memory: &mut GlobalMemory,
+    file_descriptor_ref: u32,
+    fd: i32,
+) -> Result<(), Box<dyn Error>> {
+    let fd_offset = global_memory
+        .method_area
+        .classes
+        .get("java/io/FileDescriptor")
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .unwrap()
+        .field_offset_with_strings("java/io/FileDescriptor".to_owned(), "fd".to_owned())?;
+    global_memory
+        .heap
+        .get_mut(HeapRef(file_descriptor_ref))
+        .ok_or("file descriptor not on heap")?
+        .data[fd_offset] = Cursor::new(fd.to_be_bytes()).read_u32::<BigEndian>()?;
+
+    Ok(())
+}
+
+// looks up the java/io/FileDescriptor referenced by the `fd` field of a file stream
+fn get_file_descriptor_ref(
+    global_memory: &GlobalMemory,
+    stream_class_name: &str,
+    stream_ref: u32,
+) -> Result<u32, Box<dyn Error>> {
+    // assumption: class is not extended
+    let stream_fd_offset = global_memory
+        .method_area
+        .classes
+        .get(stream_class_name)
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .unwrap()
+        .field_offset_with_strings(stream_class_name.to_owned(), "fd".to_owned())?;
+
+    Ok(*global_memory
+        .heap
+        .get(HeapRef(stream_ref))
+        .ok_or("stream not on heap")?
+        .data
+        .get(stream_fd_offset)
+        .ok_or("what is going on with this heap data?")?)
+}
+
+fn get_file_descriptor(
+    global_memory: &GlobalMemory,
+    stream_class_name: &str,
+    stream_ref: u32,
+) -> Result<i32, Box<dyn Error>> {
+    let fd_ref = get_file_descriptor_ref(global_memory, stream_class_name, stream_ref)?;
+    get_descriptor_value(global_memory, fd_ref)
+}
+
+fn set_file_descriptor(
+    global_memory: &mut GlobalMemory,
+    stream_class_name: &str,
+    stream_ref: u32,
+    fd: i32,
+) -> Result<(), Box<dyn Error>> {
+    let fd_ref = get_file_descriptor_ref(global_memory, stream_class_name, stream_ref)?;
+    set_descriptor_value(global_memory, fd_ref, fd)
+}
+
+// reads a single slot field of an object, resolving the offset via class_name
+fn get_field_value(
+    global_memory: &GlobalMemory,
+    objectref: u32,
+    class_name: &str,
+    field_name: &str,
+) -> Result<u32, Box<dyn Error>> {
+    let offset = global_memory
+        .method_area
+        .classes
+        .get(class_name)
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .field_offset_with_strings(class_name.to_owned(), field_name.to_owned())?;
+    Ok(*global_memory
+        .heap
+        .get(HeapRef(objectref))
+        .ok_or("object not on heap")?
+        .data
+        .get(offset)
+        .ok_or("field offset out of bounds")?)
+}
+
+fn set_field_value(
+    global_memory: &mut GlobalMemory,
+    objectref: u32,
+    class_name: &str,
+    field_name: &str,
+    value: u32,
+) -> Result<(), Box<dyn Error>> {
+    let offset = global_memory
+        .method_area
+        .classes
+        .get(class_name)
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .field_offset_with_strings(class_name.to_owned(), field_name.to_owned())?;
+    *global_memory
+        .heap
+        .get_mut(HeapRef(objectref))
+        .ok_or("object not on heap")?
+        .data
+        .get_mut(offset)
+        .ok_or("field offset out of bounds")? = value;
+    Ok(())
+}
+
+// the values of String.coder, see String.LATIN1 and String.UTF16
+const STRING_CODER_LATIN1: u32 = 0;
+const STRING_CODER_UTF16: u32 = 1;
+
+// the text of a string, for names and messages. Like java, strings can contain unpaired
+// surrogates, which Rust strings can't, they become U+FFFD.
+fn string_from_java_string(
+    global_memory: &GlobalMemory,
+    objectref: HeapRef,
+) -> Result<String, Box<dyn Error>> {
+    Ok(String::from_utf16_lossy(&java_string_chars(
+        global_memory,
+        objectref,
+    )?))
+}
+
+// the UTF-16 chars of a string, as they are
+fn java_string_chars(
+    global_memory: &GlobalMemory,
+    objectref: HeapRef,
+) -> Result<Vec<u16>, Box<dyn Error>> {
+    let heap_item = global_memory
+        .heap
+        .get(objectref)
+        .ok_or("this_ref not found on heap")?;
+    let bytes_offset = global_memory
+        .method_area
+        .classes
+        .get("java/lang/String")
+        .unwrap()
+        .as_instance_klass()
+        .unwrap()
+        .field_offset_with_strings("java/lang/String".to_owned(), "value".to_owned())?;
+    let coder_offset = global_memory
+        .method_area
+        .classes
+        .get("java/lang/String")
+        .unwrap()
+        .as_instance_klass()
+        .unwrap()
+        .field_offset_with_strings("java/lang/String".to_owned(), "coder".to_owned())?;
+    let bytes_ref = heap_item
+        .data
+        .get(bytes_offset)
+        .ok_or("no data at bytes_offset")?;
+    let coder = heap_item
+        .data
+        .get(coder_offset)
+        .ok_or("no data at coder_offset")?;
+
+    let bytes_bytes = &global_memory
+        .heap
+        .get(HeapRef(*bytes_ref))
+        .as_ref()
+        .ok_or("no bytes for string")?
+        .bytes;
+
+    let chars = match *coder {
+        STRING_CODER_LATIN1 => bytes_bytes.iter().map(|byte| *byte as u16).collect(),
+        // StringUTF16.isBigEndian is true, so the high byte of each char comes first
+        STRING_CODER_UTF16 => bytes_bytes
+            .chunks(2)
+            .map(|pair| ((pair[0] as u16) << 8) | pair.get(1).map_or(0, |b| *b as u16))
+            .collect(),
+        coder => return Err(format!("unknown string coder {coder}").into()),
+    };
+
+    Ok(chars)
+}
+
+// the canonical string object with the chars, which string literals evaluate to
+fn intern_string(
+    global_memory: &mut GlobalMemory,
+    chars: Vec<u16>,
+) -> Result<HeapRef, Box<dyn Error>> {
+    if let Some(objectref) = global_memory.interned_strings.get(&chars) {
+        return Ok(*objectref);
+    }
+    let objectref = java_string_from_chars(global_memory, &chars)?;
+    global_memory.interned_strings.insert(chars, objectref);
+    Ok(objectref)
+}
+
+fn java_string_from_string(
+    global_memory: &mut GlobalMemory,
+    string: String,
+) -> Result<HeapRef, Box<dyn Error>> {
+    java_string_from_chars(global_memory, &string.encode_utf16().collect::<Vec<u16>>())
+}
+
+fn java_string_from_chars(
+    global_memory: &mut GlobalMemory,
+    chars: &[u16],
+) -> Result<HeapRef, Box<dyn Error>> {
+    let klass = global_memory
+        .method_area
+        .classes
+        .get("java/lang/String")
+        .ok_or("class not found in method area 2 :(")?;
+
+    let string_objectref = global_memory.heap.allocate_klass(klass);
+
+    // with compact strings, text that fits into Latin-1 uses a byte per char, anything else two
+    // bytes per UTF-16 char, high byte first as StringUTF16.isBigEndian is true
+    let (bytes, coder) = if chars.iter().all(|c| *c <= 0xff) {
+        let bytes = chars.iter().map(|c| *c as u8).collect::<Vec<u8>>();
+        (bytes, STRING_CODER_LATIN1)
+    } else {
+        let bytes = chars
+            .iter()
+            .flat_map(|c| c.to_be_bytes())
+            .collect::<Vec<u8>>();
+        (bytes, STRING_CODER_UTF16)
+    };
+
+    let array_objectref = global_memory.heap.store_primitive_array("[B", bytes);
+
+    let string_klass = global_memory
+        .method_area
+        .classes
+        .get("java/lang/String")
+        .as_ref()
+        .unwrap()
+        .as_instance_klass()
+        .unwrap();
+
+    let value_field_offset = string_klass
+        .field_offset_with_strings("java/lang/String".to_owned(), "value".to_owned())?;
+    let coder_field_offset = string_klass
+        .field_offset_with_strings("java/lang/String".to_owned(), "coder".to_owned())?;
+
+    global_memory
+        .heap
+        .get_mut(string_objectref.to_owned())
+        .as_mut()
+        .ok_or("no object at byte location")?
+        .data[value_field_offset] = array_objectref.0;
+    global_memory
+        .heap
+        .get_mut(string_objectref.to_owned())
+        .as_mut()
+        .ok_or("no object at byte location")?
+        .data[coder_field_offset] = coder;
+
+    Ok(string_objectref)
+}
+
+// formats a float or double the same way Float.toString and Double.toString do
+fn java_floating_point_to_string<T>(value: T) -> String
+where
+    T: Into<f64> + Copy + std::fmt::Display + std::fmt::LowerExp,
+{
+    let as_double: f64 = value.into();
+    if as_double.is_nan() {
+        return "NaN".to_owned();
+    }
+    if as_double.is_infinite() {
+        return if as_double > 0.0 {
+            "Infinity".to_owned()
+        } else {
+            "-Infinity".to_owned()
+        };
+    }
+    if as_double == 0.0 {
+        return if as_double.is_sign_negative() {
+            "-0.0".to_owned()
+        } else {
+            "0.0".to_owned()
+        };
+    }
+    if as_double.abs() >= 1e-3 && as_double.abs() < 1e7 {
+        let text = format!("{value}");
+        if text.contains('.') {
+            return text;
+        }
+        return format!("{text}.0");
+    }
+    // rust prints 1.5e10 or 1e-5, java expects 1.5E10 and 1.0E-5
+    let text = format!("{value:e}");
+    let (mantissa, exponent) = text.split_once('e').unwrap_or((&text, "0"));
+    if mantissa.contains('.') {
+        format!("{mantissa}E{exponent}")
+    } else {
+        format!("{mantissa}.0E{exponent}")
+    }
+}
+
+// resolves the call site of an invokedynamic instruction by looking at its bootstrap method
+fn resolve_call_site(
+    global_memory: &mut GlobalMemory,
+    class_name: &str,
+    bootstrap_method_attr_index: u16,
+    name: &str,
+    type_descriptor: &MethodDescriptor,
+) -> Result<CallSite, Box<dyn Error>> {
+    let klass = global_memory
+        .method_area
+        .classes
+        .get(class_name)
+        .ok_or("class not found in method area")?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?;
+    let constant_pool = klass.constant_pool.as_ref().ok_or("class is not linked")?;
+    let bootstrap_methods = klass
+        .parsed_class
+        .as_ref()
+        .ok_or("class has no parsed class")?
+        .attributes
+        .iter()
+        .find_map(|attribute| attribute.as_bootstrap_methods())
+        .ok_or("class has no BootstrapMethods attribute")?;
+    let bootstrap_method = bootstrap_methods
+        .get(bootstrap_method_attr_index as usize)
+        .ok_or("no bootstrap method at bootstrap_method_attr_index")?;
+
+    let (class_info, name_and_type) = match constant_pool
+        .pool()
+        .get((bootstrap_method.method_ref - 1) as usize)
+        .ok_or("no constant at method_ref")?
+    {
+        Constant::MethodHandle(_, reference) => reference
+            .as_method_ref()
+            .ok_or("bootstrap method handle does not reference a method")?,
+        _ => return Err("bootstrap method is not a MethodHandle".into()),
+    };
+    let (bootstrap_name, _) = name_and_type
+        .as_name_and_type()
+        .ok_or("not a NameAndType")?;
+
+    let mut arguments = vec![];
+    for argument in bootstrap_method.arguments.iter() {
+        arguments.push(
+            constant_pool
+                .pool()
+                .get((argument - 1) as usize)
+                .ok_or("no constant for bootstrap argument")?
+                .to_owned(),
+        );
+    }
+
+    match (class_info.name.as_str(), bootstrap_name.as_str()) {
+        ("java/lang/invoke/StringConcatFactory", "makeConcatWithConstants") => {
+            let recipe = match arguments.first() {
+                Some(Constant::String(recipe)) => recipe.to_owned(),
+                _ => return Err("makeConcatWithConstants expects a recipe".into()),
+            };
+            Ok(CallSite::StringConcat {
+                recipe,
+                constants: arguments.into_iter().skip(1).collect(),
+            })
+        }
+        ("java/lang/invoke/StringConcatFactory", "makeConcat") => Ok(CallSite::StringConcat {
+            recipe: vec![1; type_descriptor.parameter_descriptors.len()],
+            constants: vec![],
+        }),
+        // altMetafactory only adds flags for serializable lambdas, marker interfaces and
+        // bridges, which are ignored
+        ("java/lang/invoke/LambdaMetafactory", "metafactory" | "altMetafactory") => {
+            let interface_method_descriptor = match arguments.first() {
+                Some(Constant::MethodType(descriptor)) => {
+                    parse_method_descriptor(descriptor.to_owned())?
+                }
+                _ => return Err("metafactory expects a samMethodType".into()),
+            };
+            let (implementation_kind, implementation) = match arguments.get(1) {
+                Some(Constant::MethodHandle(kind, reference)) => (
+                    kind.to_owned(),
+                    reference
+                        .as_method_ref()
+                        .ok_or("implMethod does not reference a method")?,
+                ),
+                _ => return Err("metafactory expects an implMethod".into()),
+            };
+            let instantiated_method_descriptor = match arguments.get(2) {
+                Some(Constant::MethodType(descriptor)) => {
+                    parse_method_descriptor(descriptor.to_owned())?
+                }
+                _ => return Err("metafactory expects an instantiatedMethodType".into()),
+            };
+            let (implementation_name, implementation_descriptor) = implementation
+                .1
+                .as_name_and_type()
+                .ok_or("not a NameAndType")?;
+            let interface_name = match &type_descriptor.return_descriptor {
+                crate::parse::ReturnDescriptor::FieldType(FieldType::ClassInstance(name)) => {
+                    name.to_owned()
                 }
+                _ => return Err("metafactory call site does not return an interface".into()),
+            };
+
+            let lambda_class_name = spin_lambda_class(
+                global_memory,
+                class_name,
+                &interface_name,
+                name,
+                &type_descriptor.parameter_descriptors,
+                LambdaClass {
+                    interface_method_descriptor,
+                    instantiated_method_descriptor,
+                    captured_count: type_descriptor.parameter_descriptors.len(),
+                    implementation_kind,
+                    implementation_class: implementation.0.name,
+                    implementation_name,
+                    implementation_descriptor: parse_method_descriptor(implementation_descriptor)?,
+                },
+            )?;
+            Ok(CallSite::Lambda {
+                class_name: lambda_class_name,
+            })
+        }
+        (bootstrap_class, bootstrap_name) => Err(format!(
+            "bootstrap method {bootstrap_class}.{bootstrap_name} is not supported"
+        )
+        .into()),
+    }
+}
+
+// creates, links and inits a class implementing interface_name for a lambda defined in
+// class_name. The captured arguments are stored in its fields, the interface method is native
+// and handled by run_lambda_method.
+fn spin_lambda_class(
+    global_memory: &mut GlobalMemory,
+    class_name: &str,
+    interface_name: &str,
+    interface_method_name: &str,
+    captured: &[FieldType],
+    lambda_class: LambdaClass,
+) -> Result<String, Box<dyn Error>> {
+    global_memory.ensure_linked(interface_name)?;
+
+    let lambda_class_name = format!(
+        "{class_name}$$Lambda${}",
+        global_memory.lambda_classes.len() + 1
+    );
+
+    let mut fields = vec![];
+    for (index, field_type) in captured.iter().enumerate() {
+        fields.push(Field {
+            // private final
+            access: FieldAccess::new(0x0012),
+            name: format!("arg${}", index + 1),
+            descriptor: FieldDescriptor {
+                field_type: field_type.to_owned(),
+            },
+            attributes: vec![],
+        });
+    }
+
+    let parsed_class = ParsedClass {
+        minor_version: 0,
+        major_version: 55,
+        // final synthetic
+        access: ClassAccess::new(0x1010),
+        constant_pool: vec![],
+        this_class: ClassInfo {
+            name: lambda_class_name.to_owned(),
+        },
+        super_class: Some(ClassInfo {
+            name: "java/lang/Object".to_owned(),
+        }),
+        interfaces: vec![ClassInfo {
+            name: interface_name.to_owned(),
+        }],
+        fields,
+        methods: vec![Arc::new(Method {
+            // public native
+            access: MethodAccess::new(0x0101),
+            name: interface_method_name.to_owned(),
+            descriptor: lambda_class.interface_method_descriptor.to_owned(),
+            attributes: vec![],
+        })],
+        attributes: vec![],
+    };
+
+    let method_counters = vec![MethodCounters::default(); parsed_class.methods.len()];
+    let descriptor_id = global_memory
+        .heap
+        .descriptors
+        .intern(&format!("L{lambda_class_name};"));
+    global_memory.method_area.add_class(
+        lambda_class_name.to_owned(),
+        InstanceKlass {
+            name: lambda_class_name.to_owned(),
+            descriptor_id,
+            parsed_class: Some(Arc::new(parsed_class)),
+            fields: None,
+            static_fields: None,
+            vtable: None,
+            vtable_indices: None,
+            constant_pool: None,
+            static_field_values: Some(vec![]),
+            java_clone: None,
+            state: ClassState::Loaded,
+            // spun by the vm itself, and without any code to verify
+            needs_verification: false,
+            call_sites: HashMap::new(),
+            method_counters,
+            decoded_code: vec![],
+            reference_slots: vec![],
+        },
+    );
+    global_memory
+        .lambda_classes
+        .insert(lambda_class_name.to_owned(), lambda_class);
+    global_memory.ensure_class(&lambda_class_name)?;
+
+    Ok(lambda_class_name)
+}
+
+// forwards the interface method of a spun lambda class to its implementation method. The
+// captured arguments are passed first, followed by the arguments of the interface method. Like
+// LambdaMetafactory, those and the result are adapted to the types the implementation method
+// and the interface method expect, see adapt_lambda_value.
+fn run_lambda_method(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    lambda_class: LambdaClass,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let interface_arguments = current_frame
+        .local_variables
+        .get(1..)
+        .ok_or("no item in local_variables")?
+        .to_vec();
+
+    let mut arguments = global_memory
+        .heap
+        .get(HeapRef(this_ref))
+        .ok_or("this_ref not found on heap")?
+        .data
+        .to_owned();
+    // the receiver is the first parameter of an instance method here
+    let mut implementation_parameters = vec![];
+    if matches!(
+        lambda_class.implementation_kind,
+        RefKind::InvokeSpecial | RefKind::InvokeVirtual | RefKind::InvokeInterface
+    ) {
+        implementation_parameters.push(FieldType::ClassInstance(
+            lambda_class.implementation_class.to_owned(),
+        ));
+    }
+    implementation_parameters.extend(
+        lambda_class
+            .implementation_descriptor
+            .parameter_descriptors
+            .iter()
+            .cloned(),
+    );
+    let mut offset = 0;
+    for (field_type, implementation_type) in lambda_class
+        .instantiated_method_descriptor
+        .parameter_descriptors
+        .iter()
+        .zip(
+            implementation_parameters
+                .iter()
+                .skip(lambda_class.captured_count),
+        )
+    {
+        let slots = interface_arguments
+            .get(offset..offset + field_type.width())
+            .ok_or("not enough local_variables")?
+            .to_vec();
+        offset += field_type.width();
+        let Some(slots) = adapt_lambda_value(
+            thread,
+            global_memory,
+            slots,
+            field_type,
+            implementation_type,
+            field_type,
+        )?
+        else {
+            // the exception is passed on from this native frame
+            return Ok(());
+        };
+        arguments.extend(slots);
+    }
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    let result = match lambda_class.implementation_kind {
+        RefKind::InvokeStatic | RefKind::InvokeSpecial => thread.invoke_method(
+            global_memory,
+            lambda_class.implementation_class.to_owned(),
+            lambda_class.implementation_name.to_owned(),
+            lambda_class.implementation_descriptor.to_owned(),
+            arguments,
+        )?,
+        RefKind::InvokeVirtual | RefKind::InvokeInterface => {
+            let objectref = *arguments
+                .first()
+                .ok_or("no receiver for method reference")?;
+            if objectref == 0 {
+                return thread.throw_exception(global_memory, "java/lang/NullPointerException");
+            }
+            let receiver_class_name = global_memory
+                .heap
+                .class_name(HeapRef(objectref))
+                .ok_or("receiver not found on heap")?
+                .to_owned();
+            thread.invoke_method(
+                global_memory,
+                receiver_class_name,
+                lambda_class.implementation_name.to_owned(),
+                lambda_class.implementation_descriptor.to_owned(),
+                arguments,
+            )?
+        }
+        RefKind::NewInvokeSpecial => {
+            if let Err(error) = global_memory.ensure_class(&lambda_class.implementation_class) {
+                return thread.throw_initialization_error(global_memory, error);
+            }
+            let klass = global_memory
+                .method_area
+                .classes
+                .get(&lambda_class.implementation_class)
+                .ok_or("class not found in method area")?;
+            let objectref = global_memory.heap.allocate_klass(klass);
+            arguments.insert(0, objectref.0);
+            thread.invoke_method(
+                global_memory,
+                lambda_class.implementation_class.to_owned(),
+                "<init>".to_owned(),
+                lambda_class.implementation_descriptor.to_owned(),
+                arguments,
+            )?;
+            vec![objectref.0]
+        }
+        kind => {
+            return Err(format!("unsupported lambda implementation kind {kind:?}").into());
+        }
+    };
 
-                frame.operand_stack.push(successful);
+    if thread.is_throwing {
+        // invoke_method left the exception on this native frame, it is passed on from there
+        return Ok(());
+    }
+    let (
+        crate::parse::ReturnDescriptor::FieldType(interface_return),
+        crate::parse::ReturnDescriptor::FieldType(instantiated_return),
+    ) = (
+        &lambda_class.interface_method_descriptor.return_descriptor,
+        &lambda_class
+            .instantiated_method_descriptor
+            .return_descriptor,
+    )
+    else {
+        // the result of the implementation method, if any, is dropped
+        return Ok(());
+    };
+    let implementation_return = match lambda_class.implementation_kind {
+        RefKind::NewInvokeSpecial => {
+            FieldType::ClassInstance(lambda_class.implementation_class.to_owned())
+        }
+        _ => match lambda_class.implementation_descriptor.return_descriptor {
+            crate::parse::ReturnDescriptor::FieldType(field_type) => field_type,
+            crate::parse::ReturnDescriptor::VoidDescriptor => {
+                return Err("lambda implementation method returns void".into())
             }
-            "compareAndSetLong" => {
-                let object_ref = current_frame
-                    .local_variables
-                    .get(1)
-                    .ok_or("no item in local_variables")?;
-                let offset_part1 = *current_frame
-                    .local_variables
-                    .get(2)
-                    .ok_or("no item in local_variables")? as u64;
-                let offset_part2 = *current_frame
-                    .local_variables
-                    .get(3)
-                    .ok_or("no item in local_variables")? as u64;
-                let offset = Cursor::new((offset_part1 << 32 | offset_part2).to_be_bytes())
-                    .read_i64::<BigEndian>()?;
-                let expected_part1 = current_frame
-                    .local_variables
-                    .get(4)
-                    .ok_or("no item in local_variables")?;
-                let expected_part2 = current_frame
-                    .local_variables
-                    .get(5)
-                    .ok_or("no item in local_variables")?;
-                let x_part1 = current_frame
-                    .local_variables
-                    .get(6)
-                    .ok_or("no item in local_variables")?;
-                let x_part2 = current_frame
-                    .local_variables
-                    .get(7)
-                    .ok_or("no item in local_variables")?;
+        },
+    };
+    match adapt_lambda_value(
+        thread,
+        global_memory,
+        result,
+        &implementation_return,
+        interface_return,
+        instantiated_return,
+    )? {
+        Some(result) => thread.return_value(&result),
+        // the exception is passed on from this native frame
+        None => Ok(()),
+    }
+}
 
-                let value_at_offset_part1 = global_memory
-                    .heap
-                    .data
-                    .get(*object_ref as usize)
-                    .ok_or("not on the heap")?
-                    .data
-                    .get(offset as usize)
-                    .ok_or("not on the heap")?;
-                let value_at_offset_part2 = global_memory
-                    .heap
-                    .data
-                    .get(*object_ref as usize)
-                    .ok_or("not on the heap")?
-                    .data
-                    .get(offset as usize + 2)
-                    .ok_or("not on the heap")?;
-                let mut successful = 0;
-                if value_at_offset_part1 == expected_part1
-                    && value_at_offset_part1 == expected_part2
-                {
-                    global_memory
-                        .heap
-                        .data
-                        .get_mut(*object_ref as usize)
-                        .ok_or("not on the heap")?
-                        .data[offset as usize] = *x_part1;
-                    global_memory
-                        .heap
-                        .data
-                        .get_mut(*object_ref as usize)
-                        .ok_or("not on the heap")?
-                        .data[offset as usize + 1] = *x_part2;
-                    successful = 1;
+// converts the slots of a value of type field_type passed to or returned from a lambda to
+// target_type, like the code LambdaMetafactory generates: primitives are widened or boxed,
+// references are cast or unboxed and widened. A reference is unboxed from the box class of
+// instantiated_type if it is one, or else from that of target_type. None once an exception was
+// thrown because the value doesn't fit.
+fn adapt_lambda_value(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    slots: Vec<u32>,
+    field_type: &FieldType,
+    target_type: &FieldType,
+    instantiated_type: &FieldType,
+) -> Result<Option<Vec<u32>>, Box<dyn Error>> {
+    let is_reference = |field_type: &FieldType| box_class_name(field_type).is_none();
+    match (is_reference(field_type), is_reference(target_type)) {
+        (false, false) => {
+            let value = JavaValue::from_slots(field_type, &slots)?;
+            let widened = widen_primitive(value, target_type).ok_or_else(|| {
+                format!(
+                    "lambda can not adapt {} to {}",
+                    field_type.descriptor(),
+                    target_type.descriptor()
+                )
+            })?;
+            Ok(Some(widened.slots(target_type)?))
+        }
+        (false, true) => {
+            let boxed = box_value(thread, global_memory, field_type, slots)?;
+            Ok((!thread.is_throwing).then_some(boxed))
+        }
+        (true, false) => {
+            let objectref = *slots.first().ok_or("value is missing slots")?;
+            if objectref == 0 {
+                thread.throw_exception(global_memory, "java/lang/NullPointerException")?;
+                return Ok(None);
+            }
+            let unboxed_type = instantiated_type
+                .as_class_instance()
+                .and_then(|class_name| unboxed_type(class_name))
+                .unwrap_or_else(|| target_type.to_owned());
+            let unboxed = unbox_value(global_memory, objectref, &unboxed_type)?
+                .map(|slots| JavaValue::from_slots(&unboxed_type, &slots))
+                .transpose()?
+                .and_then(|value| widen_primitive(value, target_type));
+            match unboxed {
+                Some(value) => Ok(Some(value.slots(target_type)?)),
+                None => {
+                    throw_class_cast_exception(thread, global_memory, objectref, target_type)?;
+                    Ok(None)
                 }
+            }
+        }
+        (true, true) => {
+            let objectref = *slots.first().ok_or("value is missing slots")?;
+            if objectref == 0 {
+                return Ok(Some(slots));
+            }
+            let value_type = parse_field_descriptor(
+                global_memory
+                    .heap
+                    .field_descriptor(HeapRef(objectref))
+                    .ok_or("object not on heap")?,
+            )?
+            .field_type;
+            if global_memory
+                .method_area
+                .is_assignable(&value_type, target_type)?
+            {
+                return Ok(Some(slots));
+            }
+            throw_class_cast_exception(thread, global_memory, objectref, target_type)?;
+            Ok(None)
+        }
+    }
+}
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+fn throw_class_cast_exception(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    objectref: u32,
+    target_type: &FieldType,
+) -> Result<(), Box<dyn Error>> {
+    let class_name = global_memory
+        .heap
+        .class_name(HeapRef(objectref))
+        .ok_or("object not on heap")?
+        .replace('/', ".");
+    let target_name = match target_type {
+        FieldType::ClassInstance(class_name) => class_name.replace('/', "."),
+        _ => target_type.descriptor(),
+    };
+    thread.throw_exception_with_message(
+        global_memory,
+        "java/lang/ClassCastException",
+        &format!("class {class_name} cannot be cast to class {target_name}"),
+    )
+}
+
+// a primitive value converted to field_type by a widening primitive conversion (JLS §5.1.2), or
+// kept as it is if it already has that type. None if neither applies.
+fn widen_primitive(value: JavaValue, field_type: &FieldType) -> Option<JavaValue> {
+    let integral = match value {
+        JavaValue::Byte(value) => value as i64,
+        JavaValue::Short(value) => value as i64,
+        JavaValue::Char(value) => value as i64,
+        JavaValue::Int(value) => value as i64,
+        JavaValue::Long(value) => value,
+        _ => 0,
+    };
+    let widened = match (value, field_type) {
+        (JavaValue::Boolean(_), FieldType::Boolean)
+        | (JavaValue::Byte(_), FieldType::Byte)
+        | (JavaValue::Short(_), FieldType::Short)
+        | (JavaValue::Char(_), FieldType::Char)
+        | (JavaValue::Int(_), FieldType::Integer)
+        | (JavaValue::Long(_), FieldType::LongInteger)
+        | (JavaValue::Float(_), FieldType::Float)
+        | (JavaValue::Double(_), FieldType::Double) => value,
+        (JavaValue::Byte(value), FieldType::Short) => JavaValue::Short(value as i16),
+        (JavaValue::Byte(_) | JavaValue::Short(_) | JavaValue::Char(_), FieldType::Integer) => {
+            JavaValue::Int(integral as i32)
+        }
+        (
+            JavaValue::Byte(_) | JavaValue::Short(_) | JavaValue::Char(_) | JavaValue::Int(_),
+            FieldType::LongInteger,
+        ) => JavaValue::Long(integral),
+        (
+            JavaValue::Byte(_)
+            | JavaValue::Short(_)
+            | JavaValue::Char(_)
+            | JavaValue::Int(_)
+            | JavaValue::Long(_),
+            FieldType::Float,
+        ) => JavaValue::Float(integral as f32),
+        (
+            JavaValue::Byte(_)
+            | JavaValue::Short(_)
+            | JavaValue::Char(_)
+            | JavaValue::Int(_)
+            | JavaValue::Long(_),
+            FieldType::Double,
+        ) => JavaValue::Double(integral as f64),
+        (JavaValue::Float(value), FieldType::Double) => JavaValue::Double(value as f64),
+        _ => return None,
+    };
+    Some(widened)
+}
+
+// the class primitive values of field_type are wrapped in when they are used as an object
+fn box_class_name(field_type: &FieldType) -> Option<&'static str> {
+    match field_type {
+        FieldType::Integer => Some("java/lang/Integer"),
+        FieldType::Boolean => Some("java/lang/Boolean"),
+        FieldType::Byte => Some("java/lang/Byte"),
+        FieldType::Char => Some("java/lang/Character"),
+        FieldType::LongInteger => Some("java/lang/Long"),
+        FieldType::Float => Some("java/lang/Float"),
+        FieldType::Double => Some("java/lang/Double"),
+        FieldType::Short => Some("java/lang/Short"),
+        FieldType::ClassInstance(_) | FieldType::Array(_) => None,
+    }
+}
+
+// the primitive type the box class class_name wraps, the inverse of box_class_name
+fn unboxed_type(class_name: &str) -> Option<FieldType> {
+    let field_type = match class_name {
+        "java/lang/Integer" => FieldType::Integer,
+        "java/lang/Boolean" => FieldType::Boolean,
+        "java/lang/Byte" => FieldType::Byte,
+        "java/lang/Character" => FieldType::Char,
+        "java/lang/Long" => FieldType::LongInteger,
+        "java/lang/Float" => FieldType::Float,
+        "java/lang/Double" => FieldType::Double,
+        "java/lang/Short" => FieldType::Short,
+        _ => return None,
+    };
+    Some(field_type)
+}
+
+// wraps a primitive value using the valueOf method of its box class, references are returned as
+// they are. valueOf does not throw, but the box class might fail to initialize.
+fn box_value(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    field_type: &FieldType,
+    slots: Vec<u32>,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    let Some(box_class_name) = box_class_name(field_type) else {
+        return Ok(slots);
+    };
+    global_memory.ensure_class(box_class_name)?;
+    thread.invoke_method(
+        global_memory,
+        box_class_name.to_owned(),
+        "valueOf".to_owned(),
+        MethodDescriptor {
+            parameter_descriptors: vec![field_type.to_owned()],
+            return_descriptor: crate::parse::ReturnDescriptor::FieldType(FieldType::ClassInstance(
+                box_class_name.to_owned(),
+            )),
+        },
+        slots,
+    )
+}
+
+// the slots of the value objectref boxes if field_type is primitive, or objectref itself if it
+// can be assigned to field_type. None if the value does not fit field_type.
+fn unbox_value(
+    global_memory: &GlobalMemory,
+    objectref: u32,
+    field_type: &FieldType,
+) -> Result<Option<Vec<u32>>, Box<dyn Error>> {
+    if objectref == 0 {
+        return Ok(box_class_name(field_type).is_none().then(|| vec![0]));
+    }
+    let heap_item = global_memory
+        .heap
+        .get(HeapRef(objectref))
+        .ok_or("object not on heap")?;
+    let value_type = parse_field_descriptor(global_memory.heap.descriptor(heap_item))?.field_type;
+    let Some(box_class_name) = box_class_name(field_type) else {
+        return Ok(global_memory
+            .method_area
+            .is_assignable(&value_type, field_type)?
+            .then(|| vec![objectref]));
+    };
+    if value_type.as_class_instance().map(String::as_str) != Some(box_class_name) {
+        return Ok(None);
+    }
+    let offset = global_memory
+        .method_area
+        .classes
+        .get(box_class_name)
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .field_offset_with_strings(box_class_name.to_owned(), "value".to_owned())?;
+    Ok(Some(
+        heap_item
+            .data
+            .get(offset..offset + field_type.width())
+            .ok_or("field offset out of bounds")?
+            .to_vec(),
+    ))
+}
+
+// the slots of the elements of an Object[] passed to a reflectively invoked method, unboxed
+// according to its parameter types. None if there are too few or too many of them, or one does
+// not fit its parameter.
+fn unbox_arguments(
+    global_memory: &GlobalMemory,
+    descriptor: &MethodDescriptor,
+    arguments_ref: u32,
+) -> Result<Option<Vec<u32>>, Box<dyn Error>> {
+    // a method without parameters may be invoked with null instead of an empty array
+    let argument_refs = if arguments_ref == 0 {
+        vec![]
+    } else {
+        global_memory
+            .heap
+            .get(HeapRef(arguments_ref))
+            .ok_or("arguments not on heap")?
+            .data
+            .to_owned()
+    };
+    if argument_refs.len() != descriptor.parameter_descriptors.len() {
+        return Ok(None);
+    }
+    let mut slots = vec![];
+    for (argument_ref, field_type) in argument_refs
+        .iter()
+        .zip(descriptor.parameter_descriptors.iter())
+    {
+        match unbox_value(global_memory, *argument_ref, field_type)? {
+            Some(value) => slots.extend(value),
+            None => return Ok(None),
+        }
+    }
+    Ok(Some(slots))
+}
+
+// prints an exception similar to Throwable.printStackTrace, without running any java code
+// the class name and message of an exception, like Throwable.toString
+fn describe_exception(
+    global_memory: &GlobalMemory,
+    exception_ref: u32,
+) -> Result<String, Box<dyn Error>> {
+    let descriptor = global_memory
+        .heap
+        .field_descriptor(HeapRef(exception_ref))
+        .ok_or("exception not on heap")?;
+    let class_name = parse_field_descriptor(descriptor)?
+        .field_type
+        .as_class_instance()
+        .ok_or("exception is not a class instance")?
+        .replace('/', ".");
+
+    let message_ref = get_field_value(
+        global_memory,
+        exception_ref,
+        "java/lang/Throwable",
+        "detailMessage",
+    )?;
+    if message_ref == 0 {
+        Ok(class_name)
+    } else {
+        let message = string_from_java_string(global_memory, HeapRef(message_ref))?;
+        Ok(format!("{class_name}: {message}"))
+    }
+}
 
-                frame.operand_stack.push(successful);
+fn print_uncaught_exception(
+    global_memory: &GlobalMemory,
+    thread_name: &str,
+    exception_ref: u32,
+) -> Result<(), Box<dyn Error>> {
+    let description = describe_exception(global_memory, exception_ref)?;
+    stdout().flush()?;
+    eprintln!("Exception in thread \"{thread_name}\" {description}");
+
+    // see fillInStackTrace for the layout of the backtrace
+    let backtrace_ref = get_field_value(
+        global_memory,
+        exception_ref,
+        "java/lang/Throwable",
+        "backtrace",
+    )?;
+    if backtrace_ref == 0 {
+        return Ok(());
+    }
+    let backtrace = &global_memory
+        .heap
+        .get(HeapRef(backtrace_ref))
+        .ok_or("backtrace not on heap")?
+        .data;
+    let class_names = &global_memory
+        .heap
+        .get(HeapRef(*backtrace.first().ok_or("malformed backtrace")?))
+        .ok_or("class names not on heap")?
+        .data;
+    let method_names = &global_memory
+        .heap
+        .get(HeapRef(*backtrace.get(1).ok_or("malformed backtrace")?))
+        .ok_or("method names not on heap")?
+        .data;
+    let bytecode_indices = global_memory
+        .heap
+        .get(HeapRef(*backtrace.get(2).ok_or("malformed backtrace")?))
+        .ok_or("bytecode indices not on heap")?
+        .int_elements();
+    let line_numbers = global_memory
+        .heap
+        .get(HeapRef(*backtrace.get(3).ok_or("malformed backtrace")?))
+        .ok_or("line numbers not on heap")?
+        .int_elements();
+    for (index, class_name_ref) in class_names.iter().enumerate() {
+        let internal_class_name = string_from_java_string(global_memory, HeapRef(*class_name_ref))?;
+        let class_name = internal_class_name.replace('/', ".");
+        let method_name = string_from_java_string(global_memory, HeapRef(method_names[index]))?;
+        let source_file = global_memory.method_area.source_file(&internal_class_name);
+        let line_number = line_numbers[index];
+        if bytecode_indices[index] == -1 {
+            eprintln!("\tat {class_name}.{method_name}(Native Method)");
+        } else if let Some(source_file) = source_file {
+            if line_number >= 0 {
+                eprintln!("\tat {class_name}.{method_name}({source_file}:{line_number})");
+            } else {
+                eprintln!("\tat {class_name}.{method_name}({source_file})");
             }
-            "getIntVolatile" | "getObjectVolatile" => {
-                let object_ref = current_frame
-                    .local_variables
-                    .get(1)
-                    .ok_or("no item in local_variables")?;
-                let offset_part1 = *current_frame
-                    .local_variables
-                    .get(2)
-                    .ok_or("no item in local_variables")? as u64;
-                let offset_part2 = *current_frame
-                    .local_variables
-                    .get(3)
-                    .ok_or("no item in local_variables")? as u64;
+        } else {
+            eprintln!("\tat {class_name}.{method_name}(Unknown Source)");
+        }
+    }
+    Ok(())
+}
 
-                let offset = Cursor::new((offset_part1 << 32 | offset_part2).to_be_bytes())
-                    .read_i64::<BigEndian>()?;
+impl Thread {
+    fn new(id: u64, max_stack_depth: usize, java_clone: Option<HeapRef>) -> Thread {
+        Thread {
+            thread_memory: ThreadMemory {
+                jvm_stack: vec![],
+                max_stack_depth,
+            },
+            is_throwing: false,
+            java_clone,
+            id,
+            interpreter_depth: 0,
+            slice_left: threads::SLICE,
+            last_instruction: (0, 0),
+            driven: false,
+            yielding: false,
+            sleeping_until: None,
+        }
+    }
 
-                let value_at_offset = global_memory
-                    .heap
-                    .data
-                    .get(*object_ref as usize)
-                    .ok_or("not on the heap")?
-                    .data
-                    .get(offset as usize)
-                    .ok_or("not on the heap")?;
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    fn handle_exception(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        objectref: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        hprof::dump_on_out_of_memory(self, global_memory, objectref)?;
+        let current_frame = self
+            .thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?;
+        let descriptor = global_memory
+            .heap
+            .field_descriptor(HeapRef(objectref))
+            .ok_or("no ref")?;
+        let field_info_name = parse_field_descriptor(descriptor)?
+            .field_type
+            .as_class_instance()
+            .ok_or("not a class?")?
+            .to_owned();
+
+        // an exception passed on from an invoked method arrives after the invoke instruction
+        // has already advanced the instruction_counter past itself
+        let throwing_instruction_counter = if self.is_throwing {
+            current_frame.instruction_counter - 1
+        } else {
+            current_frame.instruction_counter
+        };
 
-                frame.operand_stack.push(*value_at_offset);
+        let method = current_frame.method.clone().ok_or("no method")?;
+        let mut found_handler = false;
+        for item in method.exception_table().ok_or("no exception_table")?.iter() {
+            // a catch_type of 0 catches everything, which is used to implement finally
+            let catches_exception = if item.catch_type == 0 {
+                true
+            } else {
+                let class_info_name = current_frame
+                    .constant_pool
+                    .pool()
+                    .get(item.catch_type - 1)
+                    .ok_or("no constant")?
+                    .as_class()
+                    .ok_or("not a class_info")?
+                    .name
+                    .to_owned();
+                // a handler catches the subclasses of its catch_type as well
+                global_memory
+                    .method_area
+                    .is_subclass_of(&field_info_name, &class_info_name)
+            };
+            if item.start_pc <= throwing_instruction_counter
+                && item.end_pc > throwing_instruction_counter
+                && catches_exception
+            {
+                current_frame.instruction_counter = item.handler_pc;
+                found_handler = true;
+                // the exception might have been thrown by an invoked method
+                self.is_throwing = false;
+                log!(
+                    Interp,
+                    Debug,
+                    "{field_info_name} caught at pc {} of {}",
+                    item.handler_pc,
+                    current_frame.method_display_name()
+                );
+                current_frame.operand_stack.clear();
+                current_frame.operand_stack.push(objectref);
+                break;
             }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
+        }
+        if !found_handler {
+            self.is_throwing = true;
+            let invoker_frame_index = self.thread_memory.jvm_stack.len() - 2;
+            let frame = self
+                .thread_memory
+                .jvm_stack
+                .get_mut(invoker_frame_index)
+                .ok_or("no invoker")?;
+            frame.operand_stack.push(objectref);
+            self.pop_frame(global_memory)?;
+        }
+        Ok(())
+    }
+
+    // pops the current frame, leaving the monitor its synchronized method entered
+    fn pop_frame(&mut self, global_memory: &mut GlobalMemory) -> Result<Frame, Box<dyn Error>> {
+        let depth = self.thread_memory.jvm_stack.len();
+        let frame = self
+            .thread_memory
+            .jvm_stack
+            .pop()
+            .ok_or("no item on jvm stack")?;
+        if let Some(objectref) = frame.monitor {
+            threads::monitor_exit(global_memory, self.id, objectref);
+        }
+        if global_memory.trace.is_some() {
+            trace::frame_popped(self, global_memory, &frame, depth)?;
+        }
+        if let Some(counters) = global_memory
+            .method_area
+            .classes
+            .by_id_mut(frame.class_id)
+            .and_then(|class| class.as_mut_instance_klass())
+            .and_then(|klass| klass.method_counters.get_mut(frame.method_index))
+        {
+            counters.instructions += frame.instructions_run;
+        }
+        Ok(frame)
+    }
+
+    // hands the value the method of the current frame returns to its invoker, in slots. The value
+    // has to be what the descriptor of the method says it returns: nothing for void, two slots for
+    // longs and doubles and one for everything else. Natives and stubs return this way as well.
+    fn return_value(&mut self, value: &[u32]) -> Result<(), Box<dyn Error>> {
+        let [invoker_frame, frame] = self
+            .thread_memory
+            .jvm_stack
+            .last_chunk_mut::<2>()
+            .ok_or("no invoker")?;
+        if let Some(method) = frame.method.as_ref() {
+            let width = match &method.descriptor.return_descriptor {
+                crate::parse::ReturnDescriptor::VoidDescriptor => 0,
+                crate::parse::ReturnDescriptor::FieldType(field_type) => field_type.width(),
+            };
+            if value.len() != width {
+                return Err(format!(
+                    "VerifyError: {} returns {} slots, but its descriptor {} takes {width} in {}",
+                    frame.method_display_name(),
+                    value.len(),
+                    method.descriptor.descriptor(),
+                    frame.location()
+                )
+                .into());
             }
-        },
-        "java/lang/Runtime" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "availableProcessors" => {
-                // For now, let's not report the actual number of processors.
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+        }
+        invoker_frame.operand_stack.extend_from_slice(value);
+        Ok(())
+    }
+
+    // a synchronized method enters the monitor of its receiver, or of the mirror of its class if
+    // it is static, before its first instruction runs. Returns false while another thread owns it.
+    fn enter_method_monitor(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+    ) -> Result<bool, Box<dyn Error>> {
+        let current_frame = self
+            .thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?;
+        let Some(method) = current_frame.method.as_ref() else {
+            return Ok(true);
+        };
+        if !method.access.synchronized || current_frame.monitor.is_some() {
+            return Ok(true);
+        }
+        let objectref = if method.access.r#static {
+            global_memory
+                .method_area
+                .classes
+                .by_id(current_frame.class_id)
+                .and_then(|klass| klass.get_java_clone())
+                .ok_or("class without java clone")?
+        } else {
+            HeapRef(
+                *current_frame
+                    .local_variables
+                    .first()
+                    .ok_or("no receiver in local_variables")?,
+            )
+        };
+        if !threads::try_monitor_enter(global_memory, self.id, objectref) {
+            return Ok(false);
+        }
+        current_frame.monitor = Some(objectref);
+        Ok(true)
+    }
+    // runs a method to completion on top of the current frame and returns whatever it left on
+    // the operand stack. If the method threw, the exception is passed on to the current frame
+    // and is_throwing stays set.
+    fn invoke_method(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        class_name: String,
+        method_name: String,
+        type_descriptor: MethodDescriptor,
+        arguments: Vec<u32>,
+    ) -> Result<Vec<u32>, Box<dyn Error>> {
+        let mut frame =
+            Frame::for_class_name(global_memory, &class_name, method_name, type_descriptor)?;
+        for (index, argument) in arguments.iter().enumerate() {
+            frame.set_local_variable(index, *argument)?;
+        }
+        self.thread_memory.jvm_stack.push(Frame::new_stub()?);
+        self.thread_memory.jvm_stack.push(frame);
+        // the outermost run annotates errors with the whole stack
+        self.interpret(global_memory)?;
+
+        let stub = self
+            .thread_memory
+            .jvm_stack
+            .pop()
+            .ok_or("no stub on jvm stack")?;
+        if self.is_throwing {
+            let current_frame = self
+                .thread_memory
+                .jvm_stack
+                .last_mut()
+                .ok_or("no item on jvm stack")?;
+            current_frame.operand_stack.extend(stub.operand_stack);
+            return Ok(vec![]);
+        }
+        Ok(stub.operand_stack)
+    }
+
+    // does what the reference handler thread and the threads of java/lang/ref/Cleaner would do
+    // once references are pending: the handler enqueues them, or cleans them if they are
+    // jdk/internal/ref/Cleaners, and every Cleaner cleans what ended up in its queue. Exceptions
+    // thrown while doing so would end these threads, they are dropped here.
+    fn process_pending_references(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+    ) -> Result<(), Box<dyn Error>> {
+        let no_arguments = MethodDescriptor {
+            parameter_descriptors: vec![],
+            return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+        };
+        self.invoke_method(
+            global_memory,
+            "java/lang/ref/Reference".to_owned(),
+            "processPendingReferences".to_owned(),
+            no_arguments.clone(),
+            vec![],
+        )?;
+        self.drop_exception()?;
+
+        for cleaner_ref in global_memory.cleaners.clone() {
+            let queue_ref = get_field_value(
+                global_memory,
+                cleaner_ref,
+                "jdk/internal/ref/CleanerImpl",
+                "queue",
+            )?;
+            loop {
+                let polled = self.invoke_method(
+                    global_memory,
+                    "java/lang/ref/ReferenceQueue".to_owned(),
+                    "poll".to_owned(),
+                    MethodDescriptor {
+                        parameter_descriptors: vec![],
+                        return_descriptor: crate::parse::ReturnDescriptor::FieldType(
+                            FieldType::ClassInstance("java/lang/ref/Reference".to_owned()),
+                        ),
+                    },
+                    vec![queue_ref],
+                )?;
+                if self.drop_exception()? {
+                    break;
+                }
+                let cleanable_ref = *polled.first().ok_or("poll returned nothing")?;
+                if cleanable_ref == 0 {
+                    break;
+                }
+                // the Cleanables of a Cleaner are PhantomCleanables and the like, whose clean
+                // is selected like invokeinterface would
+                let class_name = global_memory
+                    .heap
+                    .field_descriptor(HeapRef(cleanable_ref))
+                    .ok_or("cleanable not on heap")?
+                    .trim_start_matches('L')
+                    .trim_end_matches(';')
+                    .to_owned();
+                let declaring_class = global_memory
+                    .method_area
+                    .vtable_index(&class_name, "clean", &no_arguments)
+                    .and_then(|index| global_memory.method_area.vtable_entry(&class_name, index))
+                    .ok_or(format!("{class_name} has no clean method"))?;
+                let declaring_class = global_memory
+                    .method_area
+                    .classes
+                    .name(declaring_class)
+                    .to_owned();
+                self.invoke_method(
+                    global_memory,
+                    declaring_class,
+                    "clean".to_owned(),
+                    no_arguments.clone(),
+                    vec![cleanable_ref],
+                )?;
+                self.drop_exception()?;
+            }
+        }
+        Ok(())
+    }
+
+    // runs the run method of a thread object on this thread, as if it was that thread. Like
+    // at the end of a real thread, an exception thrown by it goes to its uncaught exception
+    // handler, and Thread.exit cleans up after it.
+    fn run_to_completion(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        thread_ref: u32,
+        class_name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let no_arguments = MethodDescriptor {
+            parameter_descriptors: vec![],
+            return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+        };
+        let declaring_class = thread_run_method(global_memory, class_name)?;
+        let declaring_class = global_memory
+            .method_area
+            .classes
+            .name(declaring_class)
+            .to_owned();
 
-                frame.operand_stack.push(1);
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "java/lang/Thread" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "registerNatives" => {
-                // noop for now?
-            }
-            "currentThread" => {
-                let java_clone = thread.java_clone.unwrap();
+        let previous_thread = self.java_clone.replace(HeapRef(thread_ref));
+        let result = self.invoke_method(
+            global_memory,
+            declaring_class,
+            "run".to_owned(),
+            no_arguments,
+            vec![thread_ref],
+        );
+        let result = result.and_then(|_| self.end_thread(global_memory, thread_ref));
+        self.java_clone = previous_thread;
+        result
+    }
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    // what happens once the run method of a thread returned or threw
+    fn end_thread(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        thread_ref: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        if self.is_throwing {
+            self.is_throwing = false;
+            let exception_ref = self
+                .thread_memory
+                .jvm_stack
+                .last_mut()
+                .ok_or("no item on jvm stack")?
+                .operand_stack
+                .pop()
+                .ok_or("no exception on the operand_stack")?;
+            self.dispatch_uncaught_exception(global_memory, exception_ref)?;
+        }
+        self.invoke_method(
+            global_memory,
+            "java/lang/Thread".to_owned(),
+            "exit".to_owned(),
+            MethodDescriptor {
+                parameter_descriptors: vec![],
+                return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+            },
+            vec![thread_ref],
+        )?;
+        self.drop_exception()?;
+        Ok(())
+    }
 
-                frame.operand_stack.push(java_clone);
-            }
-            "setPriority0" => {
-                // noop for now?
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "jdk/internal/misc/VM" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "initialize" => {
-                // noop for now?
-            }
-            "initializeFromArchive" => {
-                // noop for now?
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "java/lang/reflect/Array" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "newArray" => {
-                // println!("local_variables: {:?}", current_frame.local_variables);
-                let class_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?;
-                // FIXME: check if class_ref is null
-                let length = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(1)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
-                // println!("length: {}", length);
+    // runs the shutdown hooks once the main thread and all other non-daemon threads ended, which
+    // java/lang/Shutdown would do when the last non-daemon thread ends
+    fn shutdown(&mut self, global_memory: &mut GlobalMemory) -> Result<(), Box<dyn Error>> {
+        global_memory.shutting_down = true;
+        self.invoke_method(
+            global_memory,
+            "java/lang/Shutdown".to_owned(),
+            "shutdown".to_owned(),
+            MethodDescriptor {
+                parameter_descriptors: vec![],
+                return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+            },
+            vec![],
+        )?;
+        self.drop_exception()?;
+        Ok(())
+    }
 
-                let klass = global_memory
-                    .method_area
-                    .classes
-                    .values()
-                    .find(|class| {
-                        let maybe_java_clone = class.get_java_clone();
-                        if maybe_java_clone.is_none() {
-                            return false;
-                        }
-                        // println!("{}", class.get_name());
-                        return maybe_java_clone.unwrap() == *class_ref;
-                    })
-                    .unwrap();
-                // println!("{klass:?}");
-                let data = vec![0; length as usize];
-
-                let objectref = global_memory
-                    .heap
-                    // FIXME: this format wont work for nested arrays
-                    .store(format!("[L{};", klass.get_name()), data);
+    // drops the exception invoke_method passed on to the current frame, if there is one
+    fn drop_exception(&mut self) -> Result<bool, Box<dyn Error>> {
+        if !self.is_throwing {
+            return Ok(false);
+        }
+        self.is_throwing = false;
+        self.thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?
+            .operand_stack
+            .pop()
+            .ok_or("no exception on the operand_stack")?;
+        Ok(true)
+    }
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+    // creates an exception of class_name using its no-args constructor and throws it in the
+    // current frame
+    fn throw_exception(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        class_name: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        self.throw_new_exception(global_memory, class_name, vec![], vec![])
+    }
 
-                frame.operand_stack.push(objectref);
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "java/io/FileInputStream" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "initIDs" => {
-                // looks like a memorization optimisation in openjdk
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "java/io/FileOutputStream" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "initIDs" => {
-                // looks like a memorization optimisation in openjdk
-            }
-            "writeBytes" => {
-                let this_ref = current_frame
-                    .local_variables
-                    .first()
-                    .ok_or("no item in local_variables")?;
-                // FIXME: check if this_ref is null
-                // assumption: class is not extended
-                let fos_fd_offset = global_memory
-                    .method_area
-                    .classes
-                    .get("java/io/FileOutputStream")
-                    .unwrap()
-                    .as_instance_klass()
-                    .unwrap()
-                    .field_offset_with_strings(
-                        "java/io/FileOutputStream".to_owned(),
-                        "fd".to_owned(),
-                    )?;
-                let fd_fd_offset = global_memory
-                    .method_area
-                    .classes
-                    .get("java/io/FileOutputStream")
-                    .unwrap()
-                    .as_instance_klass()
-                    .unwrap()
-                    .field_offset_with_strings(
-                        "java/io/FileDescriptor".to_owned(),
-                        "fd".to_owned(),
-                    )?;
+    // like throw_exception, but using the constructor taking a message
+    fn throw_exception_with_message(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        class_name: &str,
+        message: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let message_ref = java_string_from_string(global_memory, message.to_owned())?;
+        self.throw_new_exception(
+            global_memory,
+            class_name,
+            vec![FieldType::ClassInstance("java/lang/String".to_owned())],
+            vec![message_ref.0],
+        )
+    }
 
-                let fd_ref = global_memory
-                    .heap
-                    .data
-                    .get(*this_ref as usize)
-                    .ok_or("this is not valid")?
-                    .data
-                    .get(fos_fd_offset)
-                    .ok_or("what is going on with this heap data?")?;
-                let fd = Cursor::new(
-                    global_memory
-                        .heap
-                        .data
-                        .get(*fd_ref as usize)
-                        .ok_or("this is not valid")?
-                        .data
-                        .get(fd_fd_offset)
-                        .ok_or("what is going on with this heap data?")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
-                if fd != 1 {
-                    unreachable!();
-                }
-                let byte_array_ref = current_frame
-                    .local_variables
-                    .get(1)
-                    .ok_or("no item in local_variables")?;
-                let off = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(2)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
-                let len = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(3)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
-                let append = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .get(4)
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
+    // like throw_exception, but using the constructor taking the given arguments, e.g. a message
+    fn throw_new_exception(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        class_name: &str,
+        parameter_descriptors: Vec<FieldType>,
+        arguments: Vec<u32>,
+    ) -> Result<(), Box<dyn Error>> {
+        global_memory.ensure_class(class_name)?;
+        let klass = global_memory
+            .method_area
+            .classes
+            .get(class_name)
+            .ok_or("class not found in method area")?;
+        let objectref = global_memory.heap.allocate_klass(klass);
+
+        // the constructor needs some frames of its own, which should not overflow the stack again
+        let max_stack_depth = self.thread_memory.max_stack_depth;
+        self.thread_memory.max_stack_depth = usize::MAX;
+        let result = self.invoke_method(
+            global_memory,
+            class_name.to_owned(),
+            "<init>".to_owned(),
+            MethodDescriptor {
+                parameter_descriptors,
+                return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+            },
+            [vec![objectref.0], arguments].concat(),
+        );
+        self.thread_memory.max_stack_depth = max_stack_depth;
+        result?;
+        if self.is_throwing {
+            // the constructor threw on its own, so that exception is passed on instead
+            return Ok(());
+        }
 
-                let byte_array_data = global_memory
-                    .heap
-                    .data
-                    .get(*byte_array_ref as usize)
-                    .ok_or("this is not valid")?
-                    .data
-                    .to_owned();
-                // println!("OUT: ");
-                stdout().write_all(
-                    &byte_array_data
-                        .iter()
-                        .skip(off as usize)
-                        .take(len as usize)
-                        .map(|b| *b as u8)
-                        .collect::<Vec<u8>>(),
-                )?;
-                // println!("");
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "java/io/FileDescriptor" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "initIDs" => {
-                // looks like a memorization optimisation in openjdk
-            }
-            "getAppend" => {
-                let fd = Cursor::new(
-                    current_frame
-                        .local_variables
-                        .first()
-                        .ok_or("no item in local_variables")?
-                        .to_be_bytes(),
-                )
-                .read_i32::<BigEndian>()?;
+        self.is_throwing = true;
+        self.thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?
+            .operand_stack
+            .push(objectref.0);
+        Ok(())
+    }
 
-                let mut result: i32 = 0;
-                if fd == 1 || fd == 2 {
-                    result = 1;
-                }
+    // takes what the <clinit> running on this thread threw off its stub frame. Anything but an
+    // Error is wrapped in an ExceptionInInitializerError (JVMS §5.5 step 11)
+    fn initializer_exception(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+    ) -> Result<HeapRef, Box<dyn Error>> {
+        let stub = self
+            .thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?;
+        let exception = HeapRef(stub.operand_stack.pop().ok_or("no exception")?);
+        self.is_throwing = false;
+        let class_name = global_memory
+            .heap
+            .class_name(exception)
+            .ok_or("exception has no class")?
+            .to_owned();
+        if global_memory
+            .method_area
+            .is_subclass_of(&class_name, "java/lang/Error")
+        {
+            return Ok(exception);
+        }
+        self.throw_new_exception(
+            global_memory,
+            "java/lang/ExceptionInInitializerError",
+            vec![FieldType::ClassInstance("java/lang/Throwable".to_owned())],
+            vec![exception.0],
+        )?;
+        // or whatever the constructor threw instead
+        let stub = self
+            .thread_memory
+            .jvm_stack
+            .last_mut()
+            .ok_or("no item on jvm stack")?;
+        let exception = HeapRef(stub.operand_stack.pop().ok_or("no exception")?);
+        self.is_throwing = false;
+        Ok(exception)
+    }
 
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
+    // throws what made the initialization of a class fail in the current frame, see
+    // InitializationError. Any other error is passed on.
+    fn throw_initialization_error(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        error: Box<dyn Error>,
+    ) -> Result<(), Box<dyn Error>> {
+        match *error.downcast::<InitializationError>()? {
+            InitializationError::Threw { exception, .. } => {
+                self.is_throwing = true;
+                self.thread_memory
                     .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
-
-                frame
+                    .last_mut()
+                    .ok_or("no item on jvm stack")?
                     .operand_stack
-                    .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
+                    .push(exception.0);
+                Ok(())
             }
-            "getHandle" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+            InitializationError::Erroneous { class_name } => self.throw_exception_with_message(
+                global_memory,
+                "java/lang/NoClassDefFoundError",
+                &format!(
+                    "Could not initialize class {}",
+                    class_name.replace('/', ".")
+                ),
+            ),
+        }
+    }
 
-                let mut csr = Cursor::new((-1i64).to_be_bytes());
-                frame.operand_stack.push(csr.read_u32::<BigEndian>()?);
-                frame.operand_stack.push(csr.read_u32::<BigEndian>()?);
-            }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
-            }
-        },
-        "jdk/internal/misc/Signal" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "findSignal0" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
+    // the result of initializing a class for the instruction at the instruction counter of the
+    // current frame, instruction_length bytes long. If the initialization failed, the instruction
+    // completes by throwing, and there is no result.
+    fn initialized<T>(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        instruction_length: usize,
+        result: Result<T, Box<dyn Error>>,
+    ) -> Result<Option<T>, Box<dyn Error>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(error) => {
+                self.thread_memory
                     .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
-
-                // fixme: signal code mapping
-                frame
-                    .operand_stack
-                    .push(Cursor::new(1i32.to_be_bytes()).read_u32::<BigEndian>()?);
+                    .last_mut()
+                    .ok_or("no item on jvm stack")?
+                    .instruction_counter += instruction_length;
+                self.throw_initialization_error(global_memory, error)?;
+                Ok(None)
             }
-            "handle0" => {
-                let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                let frame = thread
-                    .thread_memory
-                    .jvm_stack
-                    .get_mut(invoker_frame_index)
-                    .ok_or("no invoker")?;
+        }
+    }
 
-                let mut csr = Cursor::new(19i64.to_be_bytes());
-                frame.operand_stack.push(csr.read_u32::<BigEndian>()?);
-                frame.operand_stack.push(csr.read_u32::<BigEndian>()?);
+    // hands an exception that unwound the whole stack to the uncaught exception handler of the
+    // thread, which prints it to System.err by default
+    fn dispatch_uncaught_exception(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        exception_ref: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut thread_name = "main".to_owned();
+        if let Some(thread_ref) = self.java_clone {
+            let result = self.invoke_method(
+                global_memory,
+                "java/lang/Thread".to_owned(),
+                "dispatchUncaughtException".to_owned(),
+                MethodDescriptor {
+                    parameter_descriptors: vec![FieldType::ClassInstance(
+                        "java/lang/Throwable".to_owned(),
+                    )],
+                    return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+                },
+                vec![thread_ref.0, exception_ref],
+            );
+            if result.is_ok() && !self.is_throwing {
+                return Ok(());
             }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
+            // like hotspot, exceptions thrown by the handler itself are ignored
+            self.is_throwing = false;
+
+            let name_ref =
+                get_field_value(global_memory, thread_ref.0, "java/lang/Thread", "name")?;
+            if name_ref != 0 {
+                thread_name = string_from_java_string(global_memory, HeapRef(name_ref))?;
             }
-        },
-        "java/security/AccessController" => {
-            match current_frame.method.as_ref().unwrap().name.as_str() {
-                "getStackAccessControlContext" => {
-                    let invoker_frame_index = thread.thread_memory.jvm_stack.len() - 2;
-                    let frame = thread
-                        .thread_memory
-                        .jvm_stack
-                        .get_mut(invoker_frame_index)
-                        .ok_or("no invoker")?;
+        }
 
-                    frame.operand_stack.push(0);
-                }
-                method @ _ => {
-                    unimplemented!("{method} has no native impl");
+        // the handler did not work out, so print the exception ourselves
+        print_uncaught_exception(global_memory, &thread_name, exception_ref)
+    }
+
+    // returns the values the call site pushes onto the operand stack
+    fn invoke_call_site(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        call_site: &CallSite,
+        arguments: Vec<(FieldType, Vec<u32>)>,
+    ) -> Result<Vec<u32>, Box<dyn Error>> {
+        match call_site {
+            CallSite::StringConcat { recipe, constants } => {
+                let mut arguments = arguments.iter();
+                let mut constants = constants.iter();
+                let mut chars = vec![];
+                for c in recipe.iter() {
+                    match c {
+                        1 => {
+                            let (field_type, value) =
+                                arguments.next().ok_or("recipe expects more arguments")?;
+                            match self.concat_argument_chars(global_memory, field_type, value)? {
+                                Some(argument_chars) => chars.extend(argument_chars),
+                                // toString threw
+                                None => return Ok(vec![]),
+                            }
+                        }
+                        2 => {
+                            let constant =
+                                constants.next().ok_or("recipe expects more constants")?;
+                            let text = match constant {
+                                Constant::String(constant_chars) => {
+                                    chars.extend_from_slice(constant_chars);
+                                    continue;
+                                }
+                                Constant::Integer(value) => value.to_string(),
+                                Constant::Long(value) => value.to_string(),
+                                Constant::Float(value) => java_floating_point_to_string(*value),
+                                Constant::Double(value) => java_floating_point_to_string(*value),
+                                constant => {
+                                    return Err(format!(
+                                        "unsupported string concat constant {constant:?}"
+                                    )
+                                    .into())
+                                }
+                            };
+                            chars.extend(text.encode_utf16());
+                        }
+                        c => chars.push(*c),
+                    }
                 }
+                let string_ref = java_string_from_chars(global_memory, &chars)?;
+                Ok(vec![string_ref.0])
+            }
+            CallSite::Lambda { class_name } => {
+                let klass = global_memory
+                    .method_area
+                    .classes
+                    .get(class_name)
+                    .ok_or("lambda class not found in method area")?;
+                let objectref = global_memory.heap.allocate_klass(klass);
+                let captured = arguments
+                    .into_iter()
+                    .flat_map(|(_, value)| value)
+                    .collect::<Vec<u32>>();
+                global_memory
+                    .heap
+                    .get_mut(objectref)
+                    .ok_or("lambda not found on heap")?
+                    .data = captured;
+                Ok(vec![objectref.0])
             }
         }
-        "java/lang/ClassLoader" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "registerNatives" => {
-                // noop for now?
+    }
+
+    // converts a value to chars the same way String.valueOf would. Returns None if toString threw.
+    fn concat_argument_chars(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        field_type: &FieldType,
+        value: &[u32],
+    ) -> Result<Option<Vec<u16>>, Box<dyn Error>> {
+        let first = *value.first().ok_or("no value for argument")?;
+        let text = match field_type {
+            FieldType::Integer | FieldType::Short | FieldType::Byte => {
+                Cursor::new(first.to_be_bytes())
+                    .read_i32::<BigEndian>()?
+                    .to_string()
             }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
+            // a char may be a surrogate on its own
+            FieldType::Char => return Ok(Some(vec![first as u16])),
+            FieldType::Boolean => (first != 0).to_string(),
+            FieldType::LongInteger => {
+                let second = *value.get(1).ok_or("no second half for long")?;
+                slots::long_from_slots(first, second).to_string()
             }
-        },
-        "java/io/UnixFileSystem" => match current_frame.method.as_ref().unwrap().name.as_str() {
-            "initIDs" => {
-                // looks like a memorization optimisation in openjdk
+            FieldType::Float => java_floating_point_to_string(f32::from_bits(first)),
+            FieldType::Double => {
+                let second = *value.get(1).ok_or("no second half for double")?;
+                java_floating_point_to_string(slots::double_from_slots(first, second))
             }
-            method @ _ => {
-                unimplemented!("{method} has no native impl");
+            _ if first == 0 => "null".to_owned(),
+            FieldType::ClassInstance(class_name) if class_name == "java/lang/String" => {
+                return Ok(Some(java_string_chars(global_memory, HeapRef(first))?));
             }
-        },
-        _ => {
-            unimplemented!(
-                "{} {}",
-                current_frame.class_name,
-                current_frame.method.as_ref().unwrap().name
-            )
-        }
-    }
-
-    Ok(())
-}
-
-fn string_from_java_string(
-    global_memory: &GlobalMemory,
-    objectref: u32,
-) -> Result<String, Box<dyn Error>> {
-    let heap_item = global_memory
-        .heap
-        .data
-        .get(objectref.to_owned() as usize)
-        .ok_or("this_ref not found on heap")?;
-    let bytes_offset = global_memory
-        .method_area
-        .classes
-        .get("java/lang/String")
-        .unwrap()
-        .as_instance_klass()
-        .unwrap()
-        .field_offset_with_strings("java/lang/String".to_owned(), "value".to_owned())?;
-    let bytes_ref = heap_item
-        .data
-        .get(bytes_offset)
-        .ok_or("no data at bytes_offset")?;
-
-    let bytes_bytes = &global_memory
-        .heap
-        .data
-        .get(*bytes_ref as usize)
-        .as_ref()
-        .ok_or("no bytes for string")?
-        .data;
-
-    let text = String::from_utf8(bytes_bytes.iter().map(|b32| *b32 as u8).collect())?;
-
-    Ok(text)
-}
-
-fn java_string_from_string(
-    global_memory: &mut GlobalMemory,
-    string: String,
-) -> Result<u32, Box<dyn Error>> {
-    let klass = global_memory
-        .method_area
-        .classes
-        .get("java/lang/String")
-        .ok_or("class not found in method area 2 :(")?;
-
-    let string_objectref = global_memory.heap.allocate_klass(klass);
-
-    let bytes = string.bytes().map(|b| b as u32).collect::<Vec<u32>>();
-
-    let array_objectref = global_memory.heap.store("[B".to_owned(), bytes);
-
-    let string_klass = global_memory
-        .method_area
-        .classes
-        .get("java/lang/String")
-        .as_ref()
-        .unwrap()
-        .as_instance_klass()
-        .unwrap();
-
-    let value_field_offset = string_klass
-        .field_offset_with_strings("java/lang/String".to_owned(), "value".to_owned())?;
-    let coder_field_offset = string_klass
-        .field_offset_with_strings("java/lang/String".to_owned(), "coder".to_owned())?;
+            FieldType::ClassInstance(_) | FieldType::Array(_) => {
+                let result = self.invoke_method(
+                    global_memory,
+                    "java/lang/String".to_owned(),
+                    "valueOf".to_owned(),
+                    MethodDescriptor {
+                        parameter_descriptors: vec![FieldType::ClassInstance(
+                            "java/lang/Object".to_owned(),
+                        )],
+                        return_descriptor: crate::parse::ReturnDescriptor::FieldType(
+                            FieldType::ClassInstance("java/lang/String".to_owned()),
+                        ),
+                    },
+                    vec![first],
+                )?;
+                if self.is_throwing {
+                    return Ok(None);
+                }
+                let string_ref = *result.first().ok_or("valueOf returned nothing")?;
+                return Ok(Some(java_string_chars(global_memory, HeapRef(string_ref))?));
+            }
+        };
+        Ok(Some(text.encode_utf16().collect()))
+    }
 
-    global_memory
-        .heap
-        .data
-        .get_mut(string_objectref.to_owned() as usize)
-        .as_mut()
-        .ok_or("no object at byte location")?
-        .data[value_field_offset] = array_objectref;
-    global_memory
-        .heap
-        .data
-        .get_mut(string_objectref.to_owned() as usize)
-        .as_mut()
-        .ok_or("no object at byte location")?
-        .data[coder_field_offset] = 0;
+    // interprets until a native or stub frame is on top of the stack. Errors of the interpreter
+    // itself are annotated with the java stack they occurred in.
+    fn run(&mut self, global_memory: &mut GlobalMemory) -> Result<(), Box<dyn Error>> {
+        match self.interpret(global_memory) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let mut message = e.to_string();
+                for frame in self.thread_memory.jvm_stack.iter().rev() {
+                    if frame.method.is_some() {
+                        message.push_str(&format!("\n\tat {}", frame.location()));
+                    }
+                }
+                Err(message.into())
+            }
+        }
+    }
 
-    return Ok(string_objectref);
-}
+    fn interpret(&mut self, global_memory: &mut GlobalMemory) -> Result<(), Box<dyn Error>> {
+        self.interpreter_depth += 1;
+        let result = self.interpret_instructions(global_memory);
+        self.interpreter_depth -= 1;
+        result
+    }
 
-impl Thread {
-    fn handle_exception(
+    fn interpret_instructions(
         &mut self,
         global_memory: &mut GlobalMemory,
-        objectref: u32,
     ) -> Result<(), Box<dyn Error>> {
-        let current_frame = self
-            .thread_memory
-            .jvm_stack
-            .last_mut()
-            .ok_or("no item on jvm stack")?;
-        let heap_item = global_memory
-            .heap
-            .data
-            .get(objectref as usize)
-            .ok_or("no ref")?;
-        let field_info_name = parse_field_descriptor(&heap_item.field_descriptor)?
-            .field_type
-            .as_class_instance()
-            .ok_or("not a class?")?
-            .to_owned();
-
-        let mut found_handler = false;
-        for item in current_frame.exception_table.as_ref().unwrap().iter() {
-            let class_info_name = current_frame
-                .constant_pool
-                .clone()
-                .upgrade()
-                .ok_or("no constant_pool")?
-                .pool
-                .get((item.catch_type - 1) as usize)
-                .ok_or("no constant")?
-                .as_class()
-                .ok_or("not a class_info")?
-                .name
-                .to_owned();
-            // println!("item: {item:?} {class_info_name} {field_info_name}");
-            if item.start_pc <= current_frame.instruction_counter
-                && item.end_pc > current_frame.instruction_counter
-                && class_info_name == field_info_name
+        loop {
+            // nested loops run for natives and class initializers, in the middle of an instruction
+            // of the outer loop that may still hold references nothing else does. In between two
+            // instructions of the outermost loop, all of them are on the stack.
+            // a green thread yields by returning to the scheduler
+            if threads::safepoint(self, global_memory) {
+                return Ok(());
+            }
+            if self.interpreter_depth == 1 && gc::collection_due(global_memory) {
+                gc::collect(self, global_memory)?;
+            }
+            // the references are processed in between two instructions as well, an exception
+            // being thrown holding off until it is caught
+            if self.interpreter_depth == 1
+                && !global_memory.reference_pending_list.is_null()
+                && global_memory.reference_handler.is_some()
+                && !self.is_throwing
             {
-                current_frame.instruction_counter = item.handler_pc;
-                found_handler = true;
-                // println!("found handler!");
-                current_frame.operand_stack.push(objectref);
-                break;
+                self.process_pending_references(global_memory)?;
             }
-        }
-        if !found_handler {
-            if self.thread_memory.jvm_stack.len() == 1 {
-                // TODO: Handle this case differently
-                // println!("heap dump: ",);
-                // for (idx, heap_item) in global_memory.heap.data.iter().enumerate() {
-                //     println!("  idx: {} item: {:?}", idx, heap_item)
-                // }
-                return Err("nowhere to go to :(".into());
+            // an interrupt exits the vm at the next safepoint, after running the shutdown hooks,
+            // unless they already run, see install_interrupt_handler
+            if !global_memory.shutting_down
+                && global_memory.interrupted.swap(false, Ordering::SeqCst)
+            {
+                global_memory.shutting_down = true;
+                self.invoke_method(
+                    global_memory,
+                    "java/lang/Shutdown".to_owned(),
+                    "exit".to_owned(),
+                    MethodDescriptor {
+                        parameter_descriptors: vec![FieldType::Integer],
+                        return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+                    },
+                    vec![130],
+                )?;
+                self.drop_exception()?;
+            }
+            if global_memory.exit_status.is_some() {
+                // the vm is halting, so there is no point in continuing to interpret anything
+                return Ok(());
+            }
+            if self.thread_memory.jvm_stack.len() > self.thread_memory.max_stack_depth {
+                // drop the frame that did not fit anymore and throw in its invoker instead
+                self.thread_memory.jvm_stack.pop();
+                self.throw_exception(global_memory, "java/lang/StackOverflowError")?;
+                continue;
             }
-            self.is_throwing = true;
-            let invoker_frame_index = self.thread_memory.jvm_stack.len() - 2;
-            let frame = self
-                .thread_memory
-                .jvm_stack
-                .get_mut(invoker_frame_index)
-                .ok_or("no invoker")?;
-            frame.operand_stack.push(objectref);
-            self.thread_memory.jvm_stack.pop();
-        }
-        Ok(())
-    }
-    fn run(&mut self, global_memory: &mut GlobalMemory) -> Result<(), Box<dyn Error>> {
-        loop {
             let current_frame = self
                 .thread_memory
                 .jvm_stack
@@ -2213,6 +9491,31 @@ impl Thread {
                     .pop()
                     .ok_or("nothing to pop here")?;
                 self.handle_exception(global_memory, objectref)?;
+                // the exception may have been passed on to the invoker, which has to handle it
+                // before running any instruction
+                continue;
+            }
+            if !self.enter_method_monitor(global_memory)? {
+                if threads::block(self, global_memory, None) {
+                    return Ok(());
+                }
+                continue;
+            }
+            if global_memory.debugger.is_some() {
+                jdwp::before_instruction(self, global_memory)?;
+            }
+            if global_memory.debug_prompt.is_some() {
+                debug::before_instruction(self, global_memory)?;
+                // the prompt may have been told to quit
+                if global_memory.exit_status.is_some() {
+                    return Ok(());
+                }
+            }
+            if global_memory.trace.is_some() {
+                trace::before_instruction(self, global_memory)?;
+            }
+            if global_memory.profiler.is_some() {
+                profile::before_instruction(self, global_memory);
             }
             let current_frame = self
                 .thread_memory
@@ -2220,154 +9523,136 @@ impl Thread {
                 .last_mut()
                 .ok_or("no item on jvm stack")?;
 
-            if current_frame.code_bytes.is_none() {
+            if current_frame.code.is_none() {
                 run_native_methods(self, global_memory)?;
+                if self.yielding {
+                    // the native blocked, it runs again once the thread is resumed
+                    let native_frame = self
+                        .thread_memory
+                        .jvm_stack
+                        .last_mut()
+                        .ok_or("no item on jvm stack")?;
+                    native_frame.running_native = false;
+                    return Ok(());
+                }
 
-                self.thread_memory.jvm_stack.pop();
+                let native_frame = self.pop_frame(global_memory)?;
+                // a native throws by leaving the exception on its own operand stack, like
+                // throw_exception and invoke_method do, it is passed on to the invoker
+                if self.is_throwing {
+                    let objectref = *native_frame
+                        .operand_stack
+                        .last()
+                        .ok_or("no exception on the operand_stack")?;
+                    self.thread_memory
+                        .jvm_stack
+                        .last_mut()
+                        .ok_or("no item on jvm stack")?
+                        .operand_stack
+                        .push(objectref);
+                }
 
                 continue;
             }
 
-            let code_bytes = current_frame
-                .code_bytes
-                .as_ref()
-                .ok_or("expected code bytes")?;
-            let instruction = code_bytes
-                .get(current_frame.instruction_counter)
+            if current_frame.operand_stack.len() > current_frame.max_stack {
+                return Err(format!(
+                    "VerifyError: operand stack exceeds max_stack of {}",
+                    current_frame.method_display_name()
+                )
+                .into());
+            }
+
+            current_frame.instructions_run += 1;
+            // shared, so the frame can be changed while looking at the instruction
+            let code = current_frame.code.clone().ok_or("expected code")?;
+            let instruction = code
+                .instruction_at(current_frame.instruction_counter)
                 .ok_or("no instruction at instruction_counter")?;
-            // println!(
-            //     "instruction: ptr {} {instruction:#0x} in {} {:?}, {:?} {:?}",
-            //     current_frame.instruction_counter,
-            //     current_frame.class_name,
-            //     current_frame.method.as_ref().unwrap().name,
-            //     current_frame.operand_stack,
-            //     current_frame.local_variables
-            // );
-
-            match instruction {
+            log!(
+                Interp,
+                Trace,
+                "{} pc {}: {}, stack {:?}",
+                current_frame.method_display_name(),
+                current_frame.instruction_counter,
+                instruction.mnemonic(),
+                current_frame.operand_stack
+            );
+            if let Some(histogram) = global_memory.opcode_histogram.as_mut() {
+                histogram.count(instruction.opcode);
+            }
+
+            match instruction.opcode {
                 // aconst_null
                 0x1 => {
                     current_frame.operand_stack.push(0);
                     current_frame.instruction_counter += 1;
                 }
                 // iconst_i
-                instruction @ (0x2 | 0x3 | 0x4 | 0x5 | 0x6 | 0x7 | 0x8) => {
-                    let topush = *instruction as i32 - 0x3;
+                opcode @ (0x2..=0x8) => {
+                    let topush = opcode as i32 - 0x3;
                     current_frame
                         .operand_stack
                         .push(Cursor::new(topush.to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
-                // dconst_f
-                instruction @ (0x9 | 0xa) => {
-                    let topush = (*instruction - 0x9) as u64;
-                    let mut csr = Cursor::new(topush.to_be_bytes());
-                    current_frame
-                        .operand_stack
-                        .push(csr.read_u32::<BigEndian>()?);
-                    current_frame
-                        .operand_stack
-                        .push(csr.read_u32::<BigEndian>()?);
+                // lconst_l
+                opcode @ (0x9 | 0xa) => {
+                    current_frame.push_long((opcode - 0x9) as i64);
                     current_frame.instruction_counter += 1;
                 }
                 // fconst_f
-                instruction @ (0xb | 0xc | 0xd) => {
-                    let topush = (*instruction - 0xb) as f32;
+                opcode @ (0xb..=0xd) => {
+                    let topush = (opcode - 0xb) as f32;
                     current_frame
                         .operand_stack
                         .push(Cursor::new(topush.to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
                 // dconst_f
-                instruction @ (0xe | 0xf) => {
-                    let topush = (*instruction - 0xe) as f64;
-                    let mut csr = Cursor::new(topush.to_be_bytes());
-                    current_frame
-                        .operand_stack
-                        .push(csr.read_u32::<BigEndian>()?);
-                    current_frame
-                        .operand_stack
-                        .push(csr.read_u32::<BigEndian>()?);
-                    current_frame.instruction_counter += 1;
-                }
-                // bipush
-                0x10 => {
-                    current_frame.instruction_counter += 1;
-                    let byte = code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
-
-                    // read this as i8
-                    let as_i8 = Cursor::new(byte.to_be_bytes()).read_i8()?;
-                    current_frame
-                        .operand_stack
-                        .push(Cursor::new((as_i8 as i32).to_be_bytes()).read_u32::<BigEndian>()?);
+                opcode @ (0xe | 0xf) => {
+                    current_frame.push_double((opcode - 0xe) as f64);
                     current_frame.instruction_counter += 1;
                 }
-                // sipush
-                0x11 => {
-                    current_frame.instruction_counter += 1;
-                    let byte1 = *code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")? as u16;
-                    current_frame.instruction_counter += 1;
-                    let byte2 = *code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")? as u16;
-
-                    let mut sign_extended = Cursor::new((byte1 << 8 | byte2).to_be_bytes());
-                    let value = sign_extended.read_i16::<BigEndian>()?;
-                    current_frame
-                        .operand_stack
-                        .push(Cursor::new((value as i32).to_be_bytes()).read_u32::<BigEndian>()?);
-                    current_frame.instruction_counter += 1;
+                // bipush, sipush, sign extended when decoded
+                0x10 | 0x11 => {
+                    let Operands::Immediate(value) = instruction.operands else {
+                        return Err("expected an immediate".into());
+                    };
+                    current_frame.operand_stack.push(value as u32);
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // ldc, ldc_w
-                instruction @ (0x12 | 0x13) => {
-                    current_frame.instruction_counter += 1;
-                    let index: usize;
-                    if *instruction == 0x12 {
-                        // ldc
-                        index = *code_bytes
-                            .get(current_frame.instruction_counter)
-                            .ok_or("no bytes")? as usize;
-                    } else if *instruction == 0x13 {
-                        // ldc_w
-                        let indexbyte1 = (*code_bytes
-                            .get(current_frame.instruction_counter)
-                            .ok_or("no bytes")?) as u16;
-                        current_frame.instruction_counter += 1;
-                        let indexbyte2 = (*code_bytes
-                            .get(current_frame.instruction_counter)
-                            .ok_or("no bytes")?) as u16;
-
-                        index = ((indexbyte1 << 8) | indexbyte2) as usize;
-                    } else {
-                        unreachable!()
+                0x12 | 0x13 => {
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
+                    let constant_pool = &current_frame.constant_pool;
+                    if let Some(ResolvedConstant::Reference(objectref)) =
+                        constant_pool.resolved(index)
+                    {
+                        current_frame.operand_stack.push(objectref.0);
+                        current_frame.instruction_counter += instruction.length;
+                        continue;
                     }
-                    // println!("index: {index}");
-                    let loadable_constant = current_frame
-                        .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
+                    let loadable_constant = constant_pool
+                        .pool()
                         .get(index - 1)
                         .ok_or("expected ur mom 1")?
                         .to_owned();
                     match loadable_constant {
                         Constant::String(string) => {
-                            let string_objectref = java_string_from_string(global_memory, string)?;
-                            current_frame.operand_stack.push(string_objectref);
+                            let string_objectref = intern_string(global_memory, string)?;
+                            constant_pool
+                                .resolve(index, ResolvedConstant::Reference(string_objectref));
+                            current_frame.operand_stack.push(string_objectref.0);
                         }
                         Constant::Integer(value) => {
                             let integer =
                                 Cursor::new(value.to_be_bytes()).read_u32::<BigEndian>()?;
-                            // println!("{}", integer);
                             current_frame.operand_stack.push(integer);
                         }
                         Constant::Class(class_info) => {
-                            // println!("class_info {:?}", class_info);
                             let name;
                             if class_info.name.starts_with("[") {
                                 name = class_info.name.to_owned();
@@ -2376,16 +9661,15 @@ impl Thread {
                                 let inner = fd.field_type.as_array().unwrap();
                                 // FIXME: find most-inner type
                                 if let Some(inner_classname) = inner.as_class_instance() {
-                                    // println!("found inner_classname: {inner_classname:?}");
-                                    global_memory.ensure_class(&inner_classname.to_owned())?;
+                                    global_memory.ensure_linked(&inner_classname.to_owned())?;
                                 } else {
-                                    // println!("inner: {inner:?}");
                                     // unreachable!("inner: {inner:?}");
                                 }
                                 global_memory.ensure_array(name.to_owned())?;
                             } else {
                                 name = class_info.name;
-                                global_memory.ensure_class(&name.to_owned())?;
+                                // class literals don't initialize the class
+                                global_memory.ensure_linked(&name.to_owned())?;
                             }
 
                             let klass_java_clone = global_memory
@@ -2395,104 +9679,105 @@ impl Thread {
                                 .ok_or("no class 2")?
                                 .get_java_clone()
                                 .unwrap();
-                            current_frame.operand_stack.push(klass_java_clone);
+                            constant_pool
+                                .resolve(index, ResolvedConstant::Reference(klass_java_clone));
+                            current_frame.operand_stack.push(klass_java_clone.0);
                         }
                         Constant::Float(value) => {
                             let float = Cursor::new(value.to_be_bytes()).read_u32::<BigEndian>()?;
-                            // println!("{}", float);
                             current_frame.operand_stack.push(float);
                         }
+                        Constant::Dynamic(..) => {
+                            return Err(
+                                "dynamically-computed constants are not supported yet".into()
+                            );
+                        }
                         // FIXME: Some are not actually unreachable
                         _ => unreachable!("{:?}", loadable_constant),
                     }
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // ldc2_w
                 0x14 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
 
                     let loadable_constant = current_frame
                         .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
+                        .pool()
+                        .get(index - 1)
                         .ok_or("expected ur mom 2")?
                         .to_owned();
 
                     match loadable_constant {
-                        Constant::Long(value) => {
-                            let mut csr = Cursor::new(value.to_be_bytes());
-
-                            let part1 = csr.read_u32::<BigEndian>()?;
-                            let part2 = csr.read_u32::<BigEndian>()?;
-
-                            current_frame.operand_stack.push(part1);
-                            current_frame.operand_stack.push(part2);
+                        Constant::Long(value) => current_frame.push_long(value),
+                        Constant::Double(value) => current_frame.push_double(value),
+                        Constant::Dynamic(..) => {
+                            return Err(
+                                "dynamically-computed constants are not supported yet".into()
+                            );
                         }
                         // FIXME: Some are not actually unreachable
                         _ => unreachable!("{:?}", loadable_constant),
                     }
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
-                // iload | aload
-                0x15 | 0x19 => {
-                    current_frame.instruction_counter += 1;
-                    let index = code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
-                    let integer = current_frame.local_variables[*index as usize];
+                // iload | fload | aload, possibly wide
+                0x15 | 0x17 | 0x19 => {
+                    let index = instruction
+                        .local_variable()
+                        .ok_or("expected a local variable")?;
+                    let integer = current_frame.get_local_variable(index)?;
                     current_frame.operand_stack.push(integer);
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
-                // lload
-                0x16 => {
-                    current_frame.instruction_counter += 1;
-                    let index = code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
-                    let value_part1 = current_frame.local_variables[*index as usize];
-                    let value_part2 = current_frame.local_variables[*index as usize];
+                // lload | dload, possibly wide
+                0x16 | 0x18 => {
+                    let index = instruction
+                        .local_variable()
+                        .ok_or("expected a local variable")?;
+                    let value_part1 = current_frame.get_local_variable(index)?;
+                    let value_part2 = current_frame.get_local_variable(index + 1)?;
                     current_frame.operand_stack.push(value_part1);
                     current_frame.operand_stack.push(value_part2);
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // iload_n
-                instruction @ (0x1a | 0x1b | 0x1c | 0x1d) => {
-                    let integer = current_frame.local_variables[(instruction - 0x1a) as usize];
+                opcode @ (0x1a..=0x1d) => {
+                    let integer = current_frame.get_local_variable((opcode - 0x1a) as usize)?;
                     current_frame.operand_stack.push(integer);
 
                     current_frame.instruction_counter += 1;
                 }
                 // lload_n
-                instruction @ (0x1e | 0x1f | 0x20 | 0x21) => {
-                    let index = instruction - 0x1e;
-                    let value_part1 = current_frame.local_variables[index as usize];
-                    let value_part2 = current_frame.local_variables[index as usize];
+                opcode @ (0x1e..=0x21) => {
+                    let index = opcode - 0x1e;
+                    let value_part1 = current_frame.get_local_variable(index as usize)?;
+                    let value_part2 = current_frame.get_local_variable(index as usize + 1)?;
                     current_frame.operand_stack.push(value_part1);
                     current_frame.operand_stack.push(value_part2);
                     current_frame.instruction_counter += 1;
                 }
                 // fload_n
-                instruction @ (0x22 | 0x23 | 0x24 | 0x25) => {
-                    let integer = current_frame.local_variables[(instruction - 0x22) as usize];
+                opcode @ (0x22..=0x25) => {
+                    let integer = current_frame.get_local_variable((opcode - 0x22) as usize)?;
                     current_frame.operand_stack.push(integer);
 
                     current_frame.instruction_counter += 1;
                 }
+                // dload_n
+                opcode @ (0x26..=0x29) => {
+                    let index = opcode - 0x26;
+                    let value_part1 = current_frame.get_local_variable(index as usize)?;
+                    let value_part2 = current_frame.get_local_variable(index as usize + 1)?;
+                    current_frame.operand_stack.push(value_part1);
+                    current_frame.operand_stack.push(value_part2);
+                    current_frame.instruction_counter += 1;
+                }
                 // aload_n
-                instruction @ (0x2a | 0x2b | 0x2c | 0x2d) => {
-                    let integer = current_frame.local_variables[(instruction - 0x2a) as usize];
+                opcode @ (0x2a..=0x2d) => {
+                    let integer = current_frame.get_local_variable((opcode - 0x2a) as usize)?;
                     current_frame.operand_stack.push(integer);
 
                     current_frame.instruction_counter += 1;
@@ -2507,7 +9792,6 @@ impl Thread {
                             .to_be_bytes(),
                     )
                     .read_i32::<BigEndian>()?;
-                    // println!("index: {index}");
                     let arrayref = current_frame
                         .operand_stack
                         .pop()
@@ -2515,8 +9799,7 @@ impl Thread {
 
                     let value = global_memory
                         .heap
-                        .data
-                        .get_mut(arrayref as usize)
+                        .get_mut(HeapRef(arrayref))
                         .ok_or("arrayref not on heap")?
                         .data
                         .get(index as usize)
@@ -2526,6 +9809,35 @@ impl Thread {
 
                     current_frame.instruction_counter += 1;
                 }
+                // laload | daload
+                0x2f | 0x31 => {
+                    let index = Cursor::new(
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?
+                            .to_be_bytes(),
+                    )
+                    .read_i32::<BigEndian>()?;
+                    let arrayref = current_frame
+                        .operand_stack
+                        .pop()
+                        .ok_or("no item on the operand_stack")?;
+
+                    let value = i64::from_be_bytes(
+                        global_memory
+                            .heap
+                            .get(HeapRef(arrayref))
+                            .ok_or("arrayref not on heap")?
+                            .element(index as usize)
+                            .ok_or("arrays not that big")?,
+                    );
+
+                    // a double is stored as the long with its bits
+                    current_frame.push_long(value);
+
+                    current_frame.instruction_counter += 1;
+                }
                 // baload
                 0x33 => {
                     let index = Cursor::new(
@@ -2536,27 +9848,27 @@ impl Thread {
                             .to_be_bytes(),
                     )
                     .read_i32::<BigEndian>()?;
-                    // println!("index: {index}");
                     let arrayref = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack 2")?;
 
-                    let value = global_memory
-                        .heap
-                        .data
-                        .get_mut(arrayref as usize)
-                        .ok_or("arrayref not on heap")?
-                        .data
-                        .get(index as usize)
-                        .ok_or("arrays not that big")?;
+                    // bytes are sign extended, booleans are 0 or 1 anyway
+                    let value = i8::from_be_bytes(
+                        global_memory
+                            .heap
+                            .get(HeapRef(arrayref))
+                            .ok_or("arrayref not on heap")?
+                            .element(index as usize)
+                            .ok_or("arrays not that big")?,
+                    );
 
-                    current_frame.operand_stack.push(*value);
+                    current_frame.operand_stack.push(value as i32 as u32);
 
                     current_frame.instruction_counter += 1;
                 }
-                // caload
-                0x34 => {
+                // iaload, faload, caload, saload
+                opcode @ (0x2e | 0x30 | 0x34 | 0x35) => {
                     let index = Cursor::new(
                         current_frame
                             .operand_stack
@@ -2565,45 +9877,50 @@ impl Thread {
                             .to_be_bytes(),
                     )
                     .read_i32::<BigEndian>()?;
-                    // println!("index: {index}");
                     let arrayref = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack 2")?;
 
-                    let value = global_memory
+                    let heap_item = global_memory
                         .heap
-                        .data
-                        .get_mut(arrayref as usize)
-                        .ok_or("arrayref not on heap")?
-                        .data
-                        .get(index as usize)
-                        .ok_or("arrays not that big")?;
+                        .get(HeapRef(arrayref))
+                        .ok_or("arrayref not on heap")?;
+                    let index = index as usize;
+                    // chars are zero extended, shorts sign extended
+                    let value = match opcode {
+                        0x34 => heap_item
+                            .element(index)
+                            .map(|element| u16::from_be_bytes(element) as u32),
+                        0x35 => heap_item
+                            .element(index)
+                            .map(|element| i16::from_be_bytes(element) as i32 as u32),
+                        _ => heap_item.element(index).map(u32::from_be_bytes),
+                    }
+                    .ok_or("arrays not that big")?;
 
-                    current_frame.operand_stack.push(*value);
+                    current_frame.operand_stack.push(value);
 
                     current_frame.instruction_counter += 1;
                 }
-                // istore | astore
-                0x36 | 0x3a => {
-                    current_frame.instruction_counter += 1;
-                    let index = code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
+                // istore | fstore | astore, possibly wide
+                0x36 | 0x38 | 0x3a => {
+                    let index = instruction
+                        .local_variable()
+                        .ok_or("expected a local variable")?;
                     let integer = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    current_frame.local_variables[*index as usize] = integer;
+                    current_frame.set_local_variable(index, integer)?;
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
-                // lstore
-                0x37 => {
-                    current_frame.instruction_counter += 1;
-                    let index = code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
+                // lstore | dstore, possibly wide
+                0x37 | 0x39 => {
+                    let index = instruction
+                        .local_variable()
+                        .ok_or("expected a local variable")?;
                     let value_part2 = current_frame
                         .operand_stack
                         .pop()
@@ -2613,24 +9930,24 @@ impl Thread {
                         .pop()
                         .ok_or("no item on the operand_stack")?;
 
-                    current_frame.local_variables[*index as usize] = value_part1;
-                    current_frame.local_variables[*index as usize + 1] = value_part2;
+                    current_frame.set_local_variable(index, value_part1)?;
+                    current_frame.set_local_variable(index + 1, value_part2)?;
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // istore_n
-                instruction @ (0x3b | 0x3c | 0x3d | 0x3e) => {
+                opcode @ (0x3b..=0x3e) => {
                     let integer = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    current_frame.local_variables[(instruction - 0x3b) as usize] = integer;
+                    current_frame.set_local_variable((opcode - 0x3b) as usize, integer)?;
 
                     current_frame.instruction_counter += 1;
                 }
-                // lstore
-                instruction @ (0x3f | 0x40 | 0x41 | 0x42) => {
-                    let index = instruction - 0x3f;
+                // lstore_n
+                opcode @ (0x3f..=0x42) => {
+                    let index = opcode - 0x3f;
                     let value_part2 = current_frame
                         .operand_stack
                         .pop()
@@ -2640,23 +9957,50 @@ impl Thread {
                         .pop()
                         .ok_or("no item on the operand_stack")?;
 
-                    current_frame.local_variables[index as usize] = value_part1;
-                    current_frame.local_variables[index as usize + 1] = value_part2;
+                    current_frame.set_local_variable(index as usize, value_part1)?;
+                    current_frame.set_local_variable(index as usize + 1, value_part2)?;
+
+                    current_frame.instruction_counter += 1;
+                }
+                // fstore_n
+                opcode @ (0x43..=0x46) => {
+                    let float = current_frame
+                        .operand_stack
+                        .pop()
+                        .ok_or("no item on the operand_stack")?;
+                    current_frame.set_local_variable((opcode - 0x43) as usize, float)?;
+
+                    current_frame.instruction_counter += 1;
+                }
+                // dstore_n
+                opcode @ (0x47..=0x4a) => {
+                    let index = opcode - 0x47;
+                    let value_part2 = current_frame
+                        .operand_stack
+                        .pop()
+                        .ok_or("no item on the operand_stack")?;
+                    let value_part1 = current_frame
+                        .operand_stack
+                        .pop()
+                        .ok_or("no item on the operand_stack")?;
+
+                    current_frame.set_local_variable(index as usize, value_part1)?;
+                    current_frame.set_local_variable(index as usize + 1, value_part2)?;
 
                     current_frame.instruction_counter += 1;
                 }
                 // astore_n
-                instruction @ (0x4b | 0x4c | 0x4d | 0x4e) => {
+                opcode @ (0x4b..=0x4e) => {
                     let reference = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    current_frame.local_variables[(instruction - 0x4b) as usize] = reference;
+                    current_frame.set_local_variable((opcode - 0x4b) as usize, reference)?;
 
                     current_frame.instruction_counter += 1;
                 }
-                // bastore, aastore, iastore
-                0x4f | 0x53 | 0x54 => {
+                // iastore, fastore, aastore, bastore
+                opcode @ (0x4f | 0x51 | 0x53 | 0x54) => {
                     // value does not need to be unwrapped, as it will be stored as a java integer
                     // anyway
                     let value = current_frame
@@ -2676,17 +10020,83 @@ impl Thread {
                         .pop()
                         .ok_or("no item on the operand_stack")?;
 
+                    let heap_item = global_memory
+                        .heap
+                        .get_mut(HeapRef(arrayref))
+                        .ok_or("arrayref not on heap")?;
+                    let index = index as usize;
+                    match opcode {
+                        0x53 => heap_item
+                            .data
+                            .get_mut(index)
+                            .map(|element| *element = value),
+                        // the int is truncated to its lowest byte
+                        0x54 => heap_item.set_element(index, [value as u8]),
+                        _ => heap_item.set_element(index, value.to_be_bytes()),
+                    }
+                    .ok_or("arrays not that big")?;
+
+                    current_frame.instruction_counter += 1;
+                }
+                // lastore | dastore
+                0x50 | 0x52 => {
+                    let value = current_frame.pop_long()?;
+                    let index = Cursor::new(
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?
+                            .to_be_bytes(),
+                    )
+                    .read_i32::<BigEndian>()?;
+                    let arrayref = current_frame
+                        .operand_stack
+                        .pop()
+                        .ok_or("no item on the operand_stack")?;
+
+                    global_memory
+                        .heap
+                        .get_mut(HeapRef(arrayref))
+                        .ok_or("arrayref not on heap")?
+                        .set_element(index as usize, value.to_be_bytes())
+                        .ok_or("arrays not that big")?;
+
+                    current_frame.instruction_counter += 1;
+                }
+                // castore
+                0x55 => {
+                    let value = Cursor::new(
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?
+                            .to_be_bytes(),
+                    )
+                    .read_i32::<BigEndian>()?;
+                    let index = Cursor::new(
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?
+                            .to_be_bytes(),
+                    )
+                    .read_i32::<BigEndian>()?;
+                    let arrayref = current_frame
+                        .operand_stack
+                        .pop()
+                        .ok_or("no item on the operand_stack")?;
+
                     global_memory
                         .heap
-                        .data
-                        .get_mut(arrayref as usize)
+                        .get_mut(HeapRef(arrayref))
                         .ok_or("arrayref not on heap")?
-                        .data[index as usize] = value;
+                        .set_element(index as usize, (value as u16).to_be_bytes())
+                        .ok_or("arrays not that big")?;
 
                     current_frame.instruction_counter += 1;
                 }
-                // castore
-                0x55 => {
+                // sastore
+                0x56 => {
                     let value = Cursor::new(
                         current_frame
                             .operand_stack
@@ -2710,10 +10120,10 @@ impl Thread {
 
                     global_memory
                         .heap
-                        .data
-                        .get_mut(arrayref as usize)
+                        .get_mut(HeapRef(arrayref))
                         .ok_or("arrayref not on heap")?
-                        .data[index as usize] = value as u16 as u32;
+                        .set_element(index as usize, (value as i16).to_be_bytes())
+                        .ok_or("arrays not that big")?;
 
                     current_frame.instruction_counter += 1;
                 }
@@ -2725,6 +10135,17 @@ impl Thread {
                         .ok_or("no item on the operand_stack")?;
                     current_frame.instruction_counter += 1;
                 }
+                // pop2
+                0x58 => {
+                    // a long or double takes two slots, so this is the same as popping twice
+                    for _ in 0..2 {
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?;
+                    }
+                    current_frame.instruction_counter += 1;
+                }
                 // dup
                 0x59 => {
                     let top_stack_value = current_frame
@@ -2772,6 +10193,20 @@ impl Thread {
                 }
                 // iadd
                 0x60 => {
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_int()?;
+                    current_frame.push_int(arithmetic::iadd(value1, value2));
+                    current_frame.instruction_counter += 1;
+                }
+                // ladd
+                0x61 => {
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(arithmetic::ladd(value1, value2));
+                    current_frame.instruction_counter += 1;
+                }
+                // fadd
+                0x62 => {
                     let value2 = current_frame
                         .operand_stack
                         .pop()
@@ -2780,89 +10215,29 @@ impl Thread {
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    let result = Cursor::new(value1.to_be_bytes()).read_i32::<BigEndian>()?
-                        + Cursor::new(value2.to_be_bytes()).read_i32::<BigEndian>()?;
+                    let result = Cursor::new(value1.to_be_bytes()).read_f32::<BigEndian>()?
+                        + Cursor::new(value2.to_be_bytes()).read_f32::<BigEndian>()?;
                     current_frame
                         .operand_stack
                         .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
-                // ladd
-                0x61 => {
-                    let value2_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value2_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 32) | value1_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-                    let value2 = Cursor::new(((value2_part1 << 32) | value2_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-
-                    let result = value1 + value2;
-                    let mut csr = Cursor::new(result.to_be_bytes());
-                    let result_part1 = csr.read_u32::<BigEndian>()?;
-                    let result_part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(result_part1);
-                    current_frame.operand_stack.push(result_part2);
-                    current_frame.instruction_counter += 1;
-                }
                 // dadd
                 0x63 => {
-                    let value2_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value2_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 32) | value1_part2).to_be_bytes())
-                        .read_f64::<BigEndian>()?;
-                    let value2 = Cursor::new(((value2_part1 << 32) | value2_part2).to_be_bytes())
-                        .read_f64::<BigEndian>()?;
-
-                    let result = value1 + value2;
-                    let mut csr = Cursor::new(result.to_be_bytes());
-                    let result_part1 = csr.read_u32::<BigEndian>()?;
-                    let result_part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(result_part1);
-                    current_frame.operand_stack.push(result_part2);
+                    let value2 = current_frame.pop_double()?;
+                    let value1 = current_frame.pop_double()?;
+                    current_frame.push_double(value1 + value2);
                     current_frame.instruction_counter += 1;
                 }
                 // isub
                 0x64 => {
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_int()?;
+                    current_frame.push_int(arithmetic::isub(value1, value2));
+                    current_frame.instruction_counter += 1;
+                }
+                // fsub
+                0x66 => {
                     let value2 = current_frame
                         .operand_stack
                         .pop()
@@ -2871,106 +10246,39 @@ impl Thread {
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    let value1 = Cursor::new(value1.to_be_bytes()).read_i32::<BigEndian>()?;
-                    let value2 = Cursor::new(value2.to_be_bytes()).read_i32::<BigEndian>()?;
-                    // println!("{value1} {value2}");
-                    let result = value1 - value2;
-                    // println!("result is {result}");
+                    let result = Cursor::new(value1.to_be_bytes()).read_f32::<BigEndian>()?
+                        - Cursor::new(value2.to_be_bytes()).read_f32::<BigEndian>()?;
                     current_frame
                         .operand_stack
                         .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
+                // dsub
+                0x67 => {
+                    let value2 = current_frame.pop_double()?;
+                    let value1 = current_frame.pop_double()?;
+                    current_frame.push_double(value1 - value2);
+                    current_frame.instruction_counter += 1;
+                }
                 // imul
                 0x68 => {
-                    let value2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    let value1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    // FIXME: not handling overflow properly
-                    let result = Cursor::new(value1.to_be_bytes()).read_i32::<BigEndian>()? as i64
-                        * Cursor::new(value2.to_be_bytes()).read_i32::<BigEndian>()? as i64;
-                    current_frame
-                        .operand_stack
-                        .push(Cursor::new((result as i32).to_be_bytes()).read_u32::<BigEndian>()?);
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_int()?;
+                    current_frame.push_int(arithmetic::imul(value1, value2));
                     current_frame.instruction_counter += 1;
                 }
                 // lsub
                 0x65 => {
-                    let value2_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value2_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 16) | value1_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-                    let value2 = Cursor::new(((value2_part1 << 16) | value2_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-
-                    let result = value1 - value2;
-                    let mut csr = Cursor::new(result.to_be_bytes());
-                    let result_part1 = csr.read_u32::<BigEndian>()?;
-                    let result_part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(result_part1);
-                    current_frame.operand_stack.push(result_part2);
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(arithmetic::lsub(value1, value2));
                     current_frame.instruction_counter += 1;
                 }
                 // lmul
                 0x69 => {
-                    let value2_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value2_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 16) | value1_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-                    let value2 = Cursor::new(((value2_part1 << 16) | value2_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-
-                    let result = value1 * value2;
-                    let mut csr = Cursor::new(result.to_be_bytes());
-                    let result_part1 = csr.read_u32::<BigEndian>()?;
-                    let result_part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(result_part1);
-                    current_frame.operand_stack.push(result_part2);
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(arithmetic::lmul(value1, value2));
                     current_frame.instruction_counter += 1;
                 }
                 // fmul
@@ -2988,26 +10296,46 @@ impl Thread {
                         * Cursor::new(value2.to_be_bytes()).read_f32::<BigEndian>()?;
                     current_frame
                         .operand_stack
-                        .push(Cursor::new((result as f32).to_be_bytes()).read_u32::<BigEndian>()?);
+                        .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
+                    current_frame.instruction_counter += 1;
+                }
+                // dmul
+                0x6b => {
+                    let value2 = current_frame.pop_double()?;
+                    let value1 = current_frame.pop_double()?;
+                    current_frame.push_double(value1 * value2);
                     current_frame.instruction_counter += 1;
                 }
                 // idiv
                 0x6c => {
-                    let value2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    let value1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    // TODO: check if rounding is equals?
-                    let result = Cursor::new(value1.to_be_bytes()).read_i32::<BigEndian>()?
-                        / Cursor::new(value2.to_be_bytes()).read_i32::<BigEndian>()?;
-                    // println!("result is {result}");
-                    current_frame
-                        .operand_stack
-                        .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_int()?;
+                    let Some(result) = arithmetic::idiv(value1, value2) else {
+                        current_frame.instruction_counter += 1;
+                        self.throw_exception_with_message(
+                            global_memory,
+                            "java/lang/ArithmeticException",
+                            "/ by zero",
+                        )?;
+                        continue;
+                    };
+                    current_frame.push_int(result);
+                    current_frame.instruction_counter += 1;
+                }
+                // ldiv
+                0x6d => {
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
+                    let Some(result) = arithmetic::ldiv(value1, value2) else {
+                        current_frame.instruction_counter += 1;
+                        self.throw_exception_with_message(
+                            global_memory,
+                            "java/lang/ArithmeticException",
+                            "/ by zero",
+                        )?;
+                        continue;
+                    };
+                    current_frame.push_long(result);
                     current_frame.instruction_counter += 1;
                 }
                 // fdiv
@@ -3022,14 +10350,36 @@ impl Thread {
                         .ok_or("no item on the operand_stack")?;
                     let result = Cursor::new(value1.to_be_bytes()).read_f32::<BigEndian>()?
                         / Cursor::new(value2.to_be_bytes()).read_f32::<BigEndian>()?;
-                    // println!("result is {result}");
                     current_frame
                         .operand_stack
                         .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
+                // ddiv
+                0x6f => {
+                    let value2 = current_frame.pop_double()?;
+                    let value1 = current_frame.pop_double()?;
+                    current_frame.push_double(value1 / value2);
+                    current_frame.instruction_counter += 1;
+                }
                 // irem
                 0x70 => {
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_int()?;
+                    let Some(result) = arithmetic::irem(value1, value2) else {
+                        current_frame.instruction_counter += 1;
+                        self.throw_exception_with_message(
+                            global_memory,
+                            "java/lang/ArithmeticException",
+                            "/ by zero",
+                        )?;
+                        continue;
+                    };
+                    current_frame.push_int(result);
+                    current_frame.instruction_counter += 1;
+                }
+                // frem
+                0x72 => {
                     let value2 = current_frame
                         .operand_stack
                         .pop()
@@ -3038,153 +10388,115 @@ impl Thread {
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    let result = Cursor::new(value1.to_be_bytes()).read_i32::<BigEndian>()?
-                        % Cursor::new(value2.to_be_bytes()).read_i32::<BigEndian>()?;
-                    // println!("result is {result}");
+                    let result = Cursor::new(value1.to_be_bytes()).read_f32::<BigEndian>()?
+                        % Cursor::new(value2.to_be_bytes()).read_f32::<BigEndian>()?;
                     current_frame
                         .operand_stack
                         .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
+                // drem
+                0x73 => {
+                    let value2 = current_frame.pop_double()?;
+                    let value1 = current_frame.pop_double()?;
+                    current_frame.push_double(value1 % value2);
+                    current_frame.instruction_counter += 1;
+                }
+                // lrem
+                0x71 => {
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
+                    let Some(result) = arithmetic::lrem(value1, value2) else {
+                        current_frame.instruction_counter += 1;
+                        self.throw_exception_with_message(
+                            global_memory,
+                            "java/lang/ArithmeticException",
+                            "/ by zero",
+                        )?;
+                        continue;
+                    };
+                    current_frame.push_long(result);
+                    current_frame.instruction_counter += 1;
+                }
                 // ineg
                 0x74 => {
                     let value = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    let result = -Cursor::new(value.to_be_bytes()).read_i32::<BigEndian>()?;
-                    // println!("result is {result}");
+                    let result = Cursor::new(value.to_be_bytes())
+                        .read_i32::<BigEndian>()?
+                        .wrapping_neg();
                     current_frame
                         .operand_stack
                         .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
-
-                // ishl
-                0x78 => {
-                    let value2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    // println!("value2: {value2}");
-                    let value1 = current_frame
+                // lneg
+                0x75 => {
+                    let value = current_frame.pop_long()?;
+                    current_frame.push_long(value.wrapping_neg());
+                    current_frame.instruction_counter += 1;
+                }
+                // fneg
+                0x76 => {
+                    let value = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-
-                    let result =
-                        Cursor::new(value1.to_be_bytes()).read_i32::<BigEndian>()? << value2;
-
+                    let result = -Cursor::new(value.to_be_bytes()).read_f32::<BigEndian>()?;
                     current_frame
                         .operand_stack
                         .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
+                    current_frame.instruction_counter += 1;
+                }
+                // dneg
+                0x77 => {
+                    let value = current_frame.pop_double()?;
+                    current_frame.push_double(-value);
+                    current_frame.instruction_counter += 1;
+                }
 
+                // ishl
+                0x78 => {
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_int()?;
+                    current_frame.push_int(arithmetic::ishl(value1, value2));
                     current_frame.instruction_counter += 1;
                 }
                 // lshl
                 0x79 => {
-                    let value2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 16) | value1_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-                    let value2 = Cursor::new(value2.to_be_bytes()).read_i32::<BigEndian>()?;
-
-                    let result = value1.overflowing_shl(value2 as u32).0;
-
-                    let mut csr = Cursor::new(result.to_be_bytes());
-                    let result_part1 = csr.read_u32::<BigEndian>()?;
-                    let result_part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(result_part1);
-                    current_frame.operand_stack.push(result_part2);
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(arithmetic::lshl(value1, value2));
                     current_frame.instruction_counter += 1;
                 }
                 // ishr
                 0x7a => {
-                    let value2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    // println!("value2: {value2}");
-                    let value1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-
-                    // Arithmetic! shift -> shift with sign bit preserved
-                    // >> is arithmetic on signed integer types in rust
-                    let result =
-                        Cursor::new(value1.to_be_bytes()).read_i32::<BigEndian>()? >> value2;
-
-                    current_frame
-                        .operand_stack
-                        .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_int()?;
+                    current_frame.push_int(arithmetic::ishr(value1, value2));
+                    current_frame.instruction_counter += 1;
+                }
+                // lshr
+                0x7b => {
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(arithmetic::lshr(value1, value2));
                     current_frame.instruction_counter += 1;
                 }
                 // iushr
                 0x7c => {
-                    let value2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    // Logical! shift. Therefore, we dont read the value1 as i8, so we can just
-                    // shift the bytes
-                    let value1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-
-                    let s = value2 & 0x1f;
-                    // println!("s {value1} {value2} {s}");
-                    let result;
-
-                    result = value1.wrapping_shl(value2 as u32);
-
-                    current_frame.operand_stack.push(result);
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_int()?;
+                    current_frame.push_int(arithmetic::iushr(value1, value2));
                     current_frame.instruction_counter += 1;
                 }
-                // lushl
+                // lushr
                 0x7d => {
-                    let value2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 16) | value1_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-                    let value2 = Cursor::new(value2.to_be_bytes()).read_i32::<BigEndian>()?;
-
-                    let result = value1.wrapping_shr(value2 as u32);
-
-                    let mut csr = Cursor::new(result.to_be_bytes());
-                    let result_part1 = csr.read_u32::<BigEndian>()?;
-                    let result_part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(result_part1);
-                    current_frame.operand_stack.push(result_part2);
+                    let value2 = current_frame.pop_int()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(arithmetic::lushr(value1, value2));
                     current_frame.instruction_counter += 1;
                 }
                 // iand
@@ -3206,39 +10518,9 @@ impl Thread {
                 }
                 // land
                 0x7f => {
-                    let value2_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value2_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 16) | value1_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-                    let value2 = Cursor::new(((value2_part1 << 16) | value2_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-
-                    let result = value1 & value2;
-                    let mut csr = Cursor::new(result.to_be_bytes());
-                    let result_part1 = csr.read_u32::<BigEndian>()?;
-                    let result_part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(result_part1);
-                    current_frame.operand_stack.push(result_part2);
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(value1 & value2);
                     current_frame.instruction_counter += 1;
                 }
                 // ior
@@ -3258,6 +10540,13 @@ impl Thread {
                         .push(Cursor::new(result.to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
+                // lor
+                0x81 => {
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(value1 | value2);
+                    current_frame.instruction_counter += 1;
+                }
                 // ixor
                 0x82 => {
                     let value2 = current_frame
@@ -3277,69 +10566,30 @@ impl Thread {
                 }
                 // lxor
                 0x83 => {
-                    let value2_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value2_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 16) | value1_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-                    let value2 = Cursor::new(((value2_part1 << 16) | value2_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-
-                    let result = value1 ^ value2;
-                    let mut csr = Cursor::new(result.to_be_bytes());
-                    let result_part1 = csr.read_u32::<BigEndian>()?;
-                    let result_part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(result_part1);
-                    current_frame.operand_stack.push(result_part2);
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
+                    current_frame.push_long(value1 ^ value2);
                     current_frame.instruction_counter += 1;
                 }
-                // iinc
+                // iinc, possibly wide
                 0x84 => {
-                    current_frame.instruction_counter += 1;
-                    let index = *code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
-                    current_frame.instruction_counter += 1;
-                    let the_const = Cursor::new(
-                        (*code_bytes
-                            .get(current_frame.instruction_counter)
-                            .ok_or("no bytes")?)
-                        .to_be_bytes(),
-                    )
-                    .read_i8()?;
-
-                    let value = Cursor::new(
-                        current_frame
-                            .local_variables
-                            .get(index as usize)
-                            .ok_or("no variable in local storage index")?
-                            .to_be_bytes(),
-                    )
-                    .read_i32::<BigEndian>()?;
-                    let new_value = value + the_const as i32;
-                    // println!("new_value: {new_value}");
-                    current_frame.local_variables[index as usize] =
-                        Cursor::new(new_value.to_be_bytes()).read_u32::<BigEndian>()?;
-                    current_frame.instruction_counter += 1;
+                    let Operands::Iinc {
+                        index,
+                        constant: the_const,
+                    } = instruction.operands
+                    else {
+                        return Err("expected the operands of iinc".into());
+                    };
+                    let index = index as usize;
+
+                    let value = Cursor::new(current_frame.get_local_variable(index)?.to_be_bytes())
+                        .read_i32::<BigEndian>()?;
+                    let new_value = value.wrapping_add(the_const as i32);
+                    current_frame.set_local_variable(
+                        index,
+                        Cursor::new(new_value.to_be_bytes()).read_u32::<BigEndian>()?,
+                    )?;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // i2l
                 0x85 => {
@@ -3350,16 +10600,8 @@ impl Thread {
                             .ok_or("no item on the operand_stack")?
                             .to_be_bytes(),
                     )
-                    .read_i32::<BigEndian>()? as i64;
-
-                    let mut csr = Cursor::new(value.to_be_bytes());
-
-                    let part1 = csr.read_u32::<BigEndian>()?;
-                    let part2 = csr.read_u32::<BigEndian>()?;
-
-                    current_frame.operand_stack.push(part1);
-                    current_frame.operand_stack.push(part2);
-
+                    .read_i32::<BigEndian>()?;
+                    current_frame.push_long(value as i64);
                     current_frame.instruction_counter += 1;
                 }
                 // i2f
@@ -3380,48 +10622,42 @@ impl Thread {
 
                     current_frame.instruction_counter += 1;
                 }
-                // l2i
-                0x88 => {
-                    let value_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let long_bytes = (value_part1 << 32) + (value_part2);
-                    let value = Cursor::new(long_bytes.to_be_bytes()).read_i64::<BigEndian>()?;
-
-                    current_frame
-                        .operand_stack
-                        .push(Cursor::new((value as i32).to_be_bytes()).read_u32::<BigEndian>()?);
+                // i2d
+                0x87 => {
+                    let value = Cursor::new(
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?
+                            .to_be_bytes(),
+                    )
+                    .read_i32::<BigEndian>()?;
+                    current_frame.push_double(value as f64);
+                    current_frame.instruction_counter += 1;
+                }
+                // l2i
+                0x88 => {
+                    let value = current_frame.pop_long()?;
+                    current_frame.operand_stack.push(
+                        Cursor::new(conversions::l2i(value).to_be_bytes())
+                            .read_u32::<BigEndian>()?,
+                    );
                     current_frame.instruction_counter += 1;
                 }
                 // l2f
                 0x89 => {
-                    let value_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let long_bytes = (value_part1 << 32) + (value_part2);
-                    let value = Cursor::new(long_bytes.to_be_bytes()).read_i64::<BigEndian>()?;
-
+                    let value = current_frame.pop_long()?;
                     current_frame
                         .operand_stack
                         .push(Cursor::new((value as f32).to_be_bytes()).read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
+                // l2d
+                0x8a => {
+                    let value = current_frame.pop_long()?;
+                    current_frame.push_double(value as f64);
+                    current_frame.instruction_counter += 1;
+                }
                 // f2i
                 0x8b => {
                     let value = Cursor::new(
@@ -3431,14 +10667,27 @@ impl Thread {
                             .ok_or("no item on the operand_stack")?
                             .to_be_bytes(),
                     )
-                    .read_f32::<BigEndian>()? as i32;
-                    let mut csr = Cursor::new(value.to_be_bytes());
+                    .read_f32::<BigEndian>()?;
+                    let mut csr = Cursor::new(conversions::f2i(value).to_be_bytes());
 
                     current_frame
                         .operand_stack
                         .push(csr.read_u32::<BigEndian>()?);
                     current_frame.instruction_counter += 1;
                 }
+                // f2l
+                0x8c => {
+                    let value = Cursor::new(
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?
+                            .to_be_bytes(),
+                    )
+                    .read_f32::<BigEndian>()?;
+                    current_frame.push_long(conversions::f2l(value));
+                    current_frame.instruction_counter += 1;
+                }
                 // f2d
                 0x8d => {
                     let value = Cursor::new(
@@ -3448,41 +10697,32 @@ impl Thread {
                             .ok_or("no item on the operand_stack")?
                             .to_be_bytes(),
                     )
-                    .read_f32::<BigEndian>()? as f64;
-                    let mut csr = Cursor::new(value.to_be_bytes());
-
-                    current_frame
-                        .operand_stack
-                        .push(csr.read_u32::<BigEndian>()?);
-                    current_frame
-                        .operand_stack
-                        .push(csr.read_u32::<BigEndian>()?);
+                    .read_f32::<BigEndian>()?;
+                    current_frame.push_double(value as f64);
+                    current_frame.instruction_counter += 1;
+                }
+                // d2i
+                0x8e => {
+                    let value = current_frame.pop_double()?;
+                    current_frame.operand_stack.push(
+                        Cursor::new(conversions::d2i(value).to_be_bytes())
+                            .read_u32::<BigEndian>()?,
+                    );
                     current_frame.instruction_counter += 1;
                 }
                 // d2l
                 0x8f => {
-                    let value_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let long_bytes = (value_part1 << 32) + (value_part2);
-                    let value = Cursor::new(long_bytes.to_be_bytes()).read_f64::<BigEndian>()?;
-
-                    let mut csr = Cursor::new((value as i64).to_be_bytes());
-
-                    current_frame
-                        .operand_stack
-                        .push(csr.read_u32::<BigEndian>()?);
-                    current_frame
-                        .operand_stack
-                        .push(csr.read_u32::<BigEndian>()?);
+                    let value = current_frame.pop_double()?;
+                    current_frame.push_long(conversions::d2l(value));
+                    current_frame.instruction_counter += 1;
+                }
+                // d2f
+                0x90 => {
+                    let value = current_frame.pop_double()?;
+                    current_frame.operand_stack.push(
+                        Cursor::new(conversions::d2f(value).to_be_bytes())
+                            .read_u32::<BigEndian>()?,
+                    );
                     current_frame.instruction_counter += 1;
                 }
                 // i2b
@@ -3494,13 +10734,14 @@ impl Thread {
                             .ok_or("no item on the operand_stack")?
                             .to_be_bytes(),
                     )
-                    .read_i32::<BigEndian>()? as u8 as i32;
-                    current_frame
-                        .operand_stack
-                        .push(Cursor::new(value.to_be_bytes()).read_u32::<BigEndian>()?);
+                    .read_i32::<BigEndian>()?;
+                    current_frame.operand_stack.push(
+                        Cursor::new(conversions::i2b(value).to_be_bytes())
+                            .read_u32::<BigEndian>()?,
+                    );
                     current_frame.instruction_counter += 1;
                 }
-                //i2c
+                // i2c
                 0x92 => {
                     let value = Cursor::new(
                         current_frame
@@ -3509,39 +10750,33 @@ impl Thread {
                             .ok_or("no item on the operand_stack")?
                             .to_be_bytes(),
                     )
-                    .read_i32::<BigEndian>()? as u16 as i32;
-                    current_frame
-                        .operand_stack
-                        .push(Cursor::new(value.to_be_bytes()).read_u32::<BigEndian>()?);
+                    .read_i32::<BigEndian>()?;
+                    current_frame.operand_stack.push(
+                        Cursor::new(conversions::i2c(value).to_be_bytes())
+                            .read_u32::<BigEndian>()?,
+                    );
+                    current_frame.instruction_counter += 1;
+                }
+                // i2s
+                0x93 => {
+                    let value = Cursor::new(
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?
+                            .to_be_bytes(),
+                    )
+                    .read_i32::<BigEndian>()?;
+                    current_frame.operand_stack.push(
+                        Cursor::new(conversions::i2s(value).to_be_bytes())
+                            .read_u32::<BigEndian>()?,
+                    );
                     current_frame.instruction_counter += 1;
                 }
                 // lcmp
                 0x94 => {
-                    let value2_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value2_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-                    let value1_part1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?
-                        as u64;
-
-                    let value1 = Cursor::new(((value1_part1 << 16) | value1_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
-                    let value2 = Cursor::new(((value2_part1 << 16) | value2_part2).to_be_bytes())
-                        .read_i64::<BigEndian>()?;
+                    let value2 = current_frame.pop_long()?;
+                    let value1 = current_frame.pop_long()?;
 
                     let result;
                     if value1 > value2 {
@@ -3556,8 +10791,8 @@ impl Thread {
                     current_frame.operand_stack.push(result as u32);
                     current_frame.instruction_counter += 1;
                 }
-                // fcmp
-                instruction @ (0x95 | 0x96) => {
+                // fcmpl | fcmpg
+                opcode @ (0x95 | 0x96) => {
                     let value2 = Cursor::new(
                         current_frame
                             .operand_stack
@@ -3583,8 +10818,8 @@ impl Thread {
                     } else if value1 < value2 {
                         result = -1;
                     } else {
-                        // TODO: different for fcmpg
-                        if *instruction == 0x95 {
+                        // at least one of the values is NaN
+                        if opcode == 0x95 {
                             result = -1;
                         } else {
                             result = 1;
@@ -3593,20 +10828,34 @@ impl Thread {
                     current_frame.operand_stack.push(result as u32);
                     current_frame.instruction_counter += 1;
                 }
-                // ifeq
-                instruction @ (0x99 | 0x9a | 0x9b | 0x9c | 0x9d | 0x9e) => {
-                    current_frame.instruction_counter += 1;
-                    let branchbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let branchbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
+                // dcmpl | dcmpg
+                opcode @ (0x97 | 0x98) => {
+                    let value2 = current_frame.pop_double()?;
+                    let value1 = current_frame.pop_double()?;
 
-                    let branchoffset =
-                        Cursor::new(((branchbyte1 << 8) | branchbyte2).to_be_bytes())
-                            .read_i16::<BigEndian>()?;
+                    let result;
+                    if value1 > value2 {
+                        result = 1;
+                    } else if value1 == value2 {
+                        result = 0;
+                    } else if value1 < value2 {
+                        result = -1;
+                    } else {
+                        // at least one of the values is NaN
+                        if opcode == 0x97 {
+                            result = -1;
+                        } else {
+                            result = 1;
+                        }
+                    }
+                    current_frame.operand_stack.push(result as u32);
+                    current_frame.instruction_counter += 1;
+                }
+                // ifeq
+                opcode @ (0x99..=0x9e) => {
+                    let target = instruction
+                        .branch_target()
+                        .ok_or("expected a branch target")?;
 
                     let value = Cursor::new(
                         current_frame
@@ -3617,40 +10866,30 @@ impl Thread {
                     )
                     .read_i32::<BigEndian>()?;
                     let mut result = false;
-                    if *instruction == 0x99 {
+                    if opcode == 0x99 {
                         result = value == 0;
-                    } else if *instruction == 0x9a {
+                    } else if opcode == 0x9a {
                         result = value != 0;
-                    } else if *instruction == 0x9b {
+                    } else if opcode == 0x9b {
                         result = value < 0;
-                    } else if *instruction == 0x9c {
+                    } else if opcode == 0x9c {
                         result = value >= 0;
-                    } else if *instruction == 0x9d {
+                    } else if opcode == 0x9d {
                         result = value > 0;
-                    } else if *instruction == 0x9e {
+                    } else if opcode == 0x9e {
                         result = value <= 0;
                     }
                     if result {
-                        current_frame.instruction_counter =
-                            current_frame.instruction_counter - 2 + branchoffset as usize;
+                        current_frame.instruction_counter = target;
                     } else {
-                        current_frame.instruction_counter += 1;
+                        current_frame.instruction_counter += instruction.length;
                     }
                 }
                 // if_icmp<cond>
-                instruction @ (0x9f | 0xa0 | 0xa1 | 0xa2 | 0xa3 | 0xa4) => {
-                    current_frame.instruction_counter += 1;
-                    let branchbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let branchbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let branchoffset =
-                        Cursor::new(((branchbyte1 << 8) | branchbyte2).to_be_bytes())
-                            .read_i16::<BigEndian>()?;
+                opcode @ (0x9f..=0xa4) => {
+                    let target = instruction
+                        .branch_target()
+                        .ok_or("expected a branch target")?;
 
                     let value2 = current_frame
                         .operand_stack
@@ -3662,50 +10901,38 @@ impl Thread {
                         .ok_or("no item on the operand_stack")?;
                     let v1 = Cursor::new(value1.to_be_bytes()).read_i32::<BigEndian>()?;
                     let v2 = Cursor::new(value2.to_be_bytes()).read_i32::<BigEndian>()?;
-                    // println!("compare: {v1} {v2}");
 
                     let mut result = false;
-                    if *instruction == 0x9f {
+                    if opcode == 0x9f {
                         result = v1 == v2;
-                    } else if *instruction == 0xa0 {
+                    } else if opcode == 0xa0 {
                         // ne
                         result = v1 != v2;
-                    } else if *instruction == 0xa1 {
+                    } else if opcode == 0xa1 {
                         // lt
                         result = v1 < v2;
-                    } else if *instruction == 0xa2 {
+                    } else if opcode == 0xa2 {
                         // ge
                         result = v1 >= v2;
-                    } else if *instruction == 0xa3 {
+                    } else if opcode == 0xa3 {
                         // gt
                         result = v1 > v2;
-                    } else if *instruction == 0xa4 {
+                    } else if opcode == 0xa4 {
                         // le
                         result = v1 <= v2;
                     }
 
                     if result {
-                        current_frame.instruction_counter =
-                            ((current_frame.instruction_counter - 2) as isize
-                                + branchoffset as isize) as usize;
+                        current_frame.instruction_counter = target;
                     } else {
-                        current_frame.instruction_counter += 1;
+                        current_frame.instruction_counter += instruction.length;
                     }
                 }
                 // if_acmp<cond>
-                instruction @ (0xa5 | 0xa6) => {
-                    current_frame.instruction_counter += 1;
-                    let branchbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let branchbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let branchoffset =
-                        Cursor::new(((branchbyte1 << 8) | branchbyte2).to_be_bytes())
-                            .read_i16::<BigEndian>()?;
+                opcode @ (0xa5 | 0xa6) => {
+                    let target = instruction
+                        .branch_target()
+                        .ok_or("expected a branch target")?;
 
                     let value2 = current_frame
                         .operand_stack
@@ -3717,736 +10944,804 @@ impl Thread {
                         .ok_or("no item on the operand_stack")?;
 
                     let mut result = false;
-                    if *instruction == 0xa5 {
+                    if opcode == 0xa5 {
                         result = value1 == value2;
-                    } else if *instruction == 0xa6 {
+                    } else if opcode == 0xa6 {
                         result = value1 != value2;
                     }
 
                     if result {
-                        current_frame.instruction_counter =
-                            current_frame.instruction_counter - 2 + branchoffset as usize;
+                        current_frame.instruction_counter = target;
                     } else {
-                        current_frame.instruction_counter += 1;
+                        current_frame.instruction_counter += instruction.length;
                     }
                 }
-                // goto
-                0xa7 => {
-                    current_frame.instruction_counter += 1;
-                    let branchbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let branchbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let branchoffset =
-                        Cursor::new(((branchbyte1 << 8) | branchbyte2).to_be_bytes())
-                            .read_i16::<BigEndian>()?;
-                    // println!("offset: {branchoffset}");
-                    current_frame.instruction_counter =
-                        ((current_frame.instruction_counter - 2) as isize + branchoffset as isize)
-                            as usize;
-                }
-                // lreturn
-                0xad => {
-                    let value2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no return value on operand stack")?;
-                    let value1 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no return value on operand stack")?;
-
-                    let invoker_frame_index = self.thread_memory.jvm_stack.len() - 2;
-                    let frame = self
-                        .thread_memory
-                        .jvm_stack
-                        .get_mut(invoker_frame_index)
-                        .ok_or("no invoker")?;
-
-                    frame.operand_stack.push(value1);
-                    frame.operand_stack.push(value2);
-                    self.thread_memory.jvm_stack.pop();
+                // goto | goto_w
+                0xa7 | 0xc8 => {
+                    let target = instruction
+                        .branch_target()
+                        .ok_or("expected a branch target")?;
+                    current_frame.instruction_counter = target;
                 }
-                // ireturn, areturn, freturn
-                0xac | 0xae | 0xb0 => {
-                    let value = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no return value on operand stack")?;
-
-                    let invoker_frame_index = self.thread_memory.jvm_stack.len() - 2;
-                    let frame = self
-                        .thread_memory
-                        .jvm_stack
-                        .get_mut(invoker_frame_index)
-                        .ok_or("no invoker")?;
-
-                    frame.operand_stack.push(value);
-                    self.thread_memory.jvm_stack.pop();
+                // jsr | jsr_w, the subroutines of class files before java 6. The return address
+                // is the offset of the next instruction, which the subroutine usually keeps in a
+                // local variable with astore until it returns with ret.
+                0xa8 | 0xc9 => {
+                    let target = instruction
+                        .branch_target()
+                        .ok_or("expected a branch target")?;
+                    let return_address = current_frame.instruction_counter + instruction.length;
+                    current_frame.operand_stack.push(return_address as u32);
+                    current_frame.instruction_counter = target;
                 }
-                0xaf => {
-                    let value_part2 = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    let value_part1 = current_frame
+                // ret, possibly wide
+                0xa9 => {
+                    let index = instruction
+                        .local_variable()
+                        .ok_or("expected a local variable")?;
+                    let return_address = current_frame.get_local_variable(index)? as usize;
+                    current_frame.instruction_counter = return_address;
+                }
+                // tableswitch, lookupswitch
+                0xaa | 0xab => {
+                    let key = current_frame
                         .operand_stack
                         .pop()
-                        .ok_or("no item on the operand_stack")?;
-
-                    let invoker_frame_index = self.thread_memory.jvm_stack.len() - 2;
-                    let frame = self
-                        .thread_memory
-                        .jvm_stack
-                        .get_mut(invoker_frame_index)
-                        .ok_or("no invoker")?;
-
-                    frame.operand_stack.push(value_part1);
-                    frame.operand_stack.push(value_part2);
-                    self.thread_memory.jvm_stack.pop();
+                        .ok_or("no key on the operand_stack")? as i32;
+                    current_frame.instruction_counter = match &instruction.operands {
+                        Operands::TableSwitch {
+                            default,
+                            low,
+                            high,
+                            targets,
+                        } => {
+                            if key < *low || key > *high {
+                                *default
+                            } else {
+                                targets[(key as i64 - *low as i64) as usize]
+                            }
+                        }
+                        Operands::LookupSwitch { default, pairs } => {
+                            match pairs.binary_search_by_key(&key, |(r#match, _)| *r#match) {
+                                Ok(index) => pairs[index].1,
+                                Err(_) => *default,
+                            }
+                        }
+                        _ => unreachable!(),
+                    };
                 }
-                // return
-                0xb1 => {
+                // ireturn, lreturn, freturn, dreturn, areturn, return
+                opcode @ 0xac..=0xb1 => {
+                    let width = match opcode {
+                        // lreturn, dreturn
+                        0xad | 0xaf => 2,
+                        // return
+                        0xb1 => 0,
+                        _ => 1,
+                    };
+                    let depth = current_frame
+                        .operand_stack
+                        .len()
+                        .checked_sub(width)
+                        .ok_or("no return value on operand stack")?;
+                    let value = current_frame.operand_stack.split_off(depth);
                     if self.thread_memory.jvm_stack.len() == 1 {
                         break;
                     }
-                    self.thread_memory.jvm_stack.pop();
+                    self.return_value(&value)?;
+                    self.pop_frame(global_memory)?;
                 }
                 // getstatic
                 0xb2 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
-
-                    let field_ref_constant = current_frame
-                        .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
-                        .ok_or("expected ur mom 3")?
-                        .to_owned();
-
-                    let (class_info, name_and_type) = field_ref_constant
-                        .as_field_ref()
-                        .ok_or(format!("not a field_ref 1 {:?}", field_ref_constant))?;
-                    let (name, field_descriptor_text) = name_and_type
-                        .as_name_and_type()
-                        .ok_or("not a NameAndType")?;
-
-                    let type_descriptor = parse_field_descriptor(&field_descriptor_text)?;
-
-                    global_memory.ensure_class(class_info.name.as_str())?;
-
-                    let class = global_memory.method_area.classes.get(&class_info.name);
-                    let class = class.as_ref().unwrap().deref().as_instance_klass().unwrap();
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
+                    let constant_pool = &current_frame.constant_pool;
+                    let resolved = resolve_static_field(global_memory, constant_pool, index);
+                    let Some((class_id, field)) =
+                        self.initialized(global_memory, instruction.length, resolved)?
+                    else {
+                        continue;
+                    };
+                    let current_frame = self
+                        .thread_memory
+                        .jvm_stack
+                        .last_mut()
+                        .ok_or("no item on jvm stack")?;
 
-                    let (name_of_class_with_field, static_field_offset) = class.find_static_field(
-                        global_memory,
-                        field_ref_constant
-                            .as_field_ref()
-                            .unwrap()
-                            .1
-                            .as_name_and_type()
-                            .unwrap()
-                            .0,
-                    )?;
-                    let class_with_field = global_memory
+                    let static_field_values = global_memory
                         .method_area
                         .classes
-                        .get(&name_of_class_with_field)
-                        .as_ref()
-                        .unwrap()
+                        .by_id(class_id)
+                        .ok_or("class not found in method area")?
                         .as_instance_klass()
-                        .unwrap();
-
-                    let v = class_with_field
+                        .ok_or("not an InstanceKlass")?
                         .static_field_values
                         .as_ref()
-                        .unwrap()
-                        .get(static_field_offset as usize)
+                        .ok_or("no static_field_values")?;
+                    let values = static_field_values
+                        .get(field.offset..field.offset + field.width)
                         .ok_or("no value in static_field_values")?;
-                    current_frame.operand_stack.push(*v);
-                    if matches!(
-                        type_descriptor.field_type,
-                        FieldType::Double | FieldType::LongInteger
-                    ) {
-                        let v = class_with_field
-                            .static_field_values
-                            .as_ref()
-                            .unwrap()
-                            .get(static_field_offset as usize + 1)
-                            .ok_or("no value in static_field_values")?;
-                        current_frame.operand_stack.push(*v);
+                    current_frame.operand_stack.extend_from_slice(values);
+                    // see getfield
+                    if field.volatile {
+                        fence(Ordering::Acquire);
                     }
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // putstatic
                 0xb3 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
-
-                    let field_ref_constant = current_frame
-                        .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
-                        .ok_or("expected ur mom 4")?
-                        .to_owned();
-
-                    let (class_info, name_and_type) = field_ref_constant
-                        .as_field_ref()
-                        .ok_or("not a field_ref 2")?;
-                    let (name, field_descriptor_text) = name_and_type
-                        .as_name_and_type()
-                        .ok_or("not a NameAndType")?;
-
-                    let type_descriptor = parse_field_descriptor(&field_descriptor_text)?;
-
-                    global_memory.ensure_class(class_info.name.as_str())?;
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
+                    let constant_pool = &current_frame.constant_pool;
+                    let resolved = resolve_static_field(global_memory, constant_pool, index);
+                    let Some((class_id, field)) =
+                        self.initialized(global_memory, instruction.length, resolved)?
+                    else {
+                        continue;
+                    };
+                    let current_frame = self
+                        .thread_memory
+                        .jvm_stack
+                        .last_mut()
+                        .ok_or("no item on jvm stack")?;
 
-                    let mut class = global_memory.method_area.classes.get_mut(&class_info.name);
-                    let class = class.as_mut().unwrap().as_mut_instance_klass().unwrap();
+                    let static_field_values = global_memory
+                        .method_area
+                        .classes
+                        .by_id_mut(class_id)
+                        .ok_or("class not found in method area")?
+                        .as_mut_instance_klass()
+                        .ok_or("not an InstanceKlass")?
+                        .static_field_values
+                        .as_mut()
+                        .ok_or("no static_field_values")?;
 
-                    let static_field_offset = class.static_field_offset(field_ref_constant)?;
-                    if matches!(
-                        type_descriptor.field_type,
-                        FieldType::Double | FieldType::LongInteger
-                    ) {
-                        let value = current_frame
-                            .operand_stack
-                            .pop()
-                            .ok_or("no popable value here")?;
-                        class.static_field_values.as_mut().unwrap()
-                            [static_field_offset as usize + 1] = value;
+                    // see getfield
+                    if field.volatile {
+                        fence(Ordering::Release);
                     }
-                    let value = current_frame
+                    let values = current_frame
                         .operand_stack
-                        .pop()
+                        .len()
+                        .checked_sub(field.width)
+                        .map(|start| current_frame.operand_stack.split_off(start))
                         .ok_or("no popable value here")?;
-                    class.static_field_values.as_mut().unwrap()[static_field_offset as usize] =
-                        value;
-
-                    current_frame.instruction_counter += 1;
-                }
-                // getfield indexbyte1 indexbyte2
-                0xb4 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
-
-                    let constant = current_frame
-                        .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
-                        .ok_or("expected ur mom 5")?
-                        .to_owned();
-
-                    // println!("constant: {:?}", constant);
-
-                    let objectref = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("value is not on the stack")?;
-
-                    let field_ref = global_memory
-                        .heap
-                        .data
-                        .get(objectref as usize)
-                        .ok_or(format!("object {objectref} not found on heap!"))?
-                        .field_descriptor
-                        .to_owned();
-                    let field_descriptor = parse_field_descriptor(&field_ref)?;
-                    let class_name = field_descriptor
-                        .field_type
-                        .as_class_instance()
-                        .ok_or("not a class instance")?;
-                    // println!("class_name: {class_name} field_descriptor: {field_descriptor:?}");
-                    let offset = global_memory
-                        .method_area
-                        .classes
-                        .get(&class_name.to_owned())
-                        .ok_or(format!("didnt find class {class_name} in method_area"))?
-                        .as_instance_klass()
-                        .unwrap()
-                        .field_offset(constant.to_owned())?;
-
-                    let field_ref = constant
-                        .to_owned()
-                        .as_field_ref()
-                        .ok_or("expected field_ref")?;
-                    let (_, r#type) = field_ref
-                        .1
-                        .as_name_and_type()
-                        .ok_or("expected name_and_type")?;
-                    let fd = parse_field_descriptor(&r#type)?;
-
-                    if matches!(fd.field_type, FieldType::LongInteger | FieldType::Double) {
-                        let value_part1 = global_memory
-                            .heap
-                            .data
-                            .get_mut(objectref as usize)
-                            .ok_or("item not on heap")?
-                            .data[offset];
-                        let value_part2 = global_memory
-                            .heap
-                            .data
-                            .get_mut(objectref as usize)
-                            .ok_or("item not on heap")?
-                            .data[offset + 1];
-
-                        current_frame.operand_stack.push(value_part1);
-                        current_frame.operand_stack.push(value_part2);
-                    } else {
-                        let value = global_memory
-                            .heap
-                            .data
-                            .get_mut(objectref as usize)
-                            .ok_or("item not on heap")?
-                            .data[offset];
-
-                        current_frame.operand_stack.push(value);
+                    static_field_values
+                        .get_mut(field.offset..field.offset + field.width)
+                        .ok_or("no value in static_field_values")?
+                        .copy_from_slice(&values);
+                    if field.volatile {
+                        fence(Ordering::SeqCst);
                     }
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
-                // putfield indexbyte1 indexbyte2
-                0xb5 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
-
-                    let constant = current_frame
-                        .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
-                        .ok_or("expected ur mom 6")?
-                        .to_owned();
-
-                    let field_type = constant
-                        .as_field_ref()
-                        .ok_or("not a field_ref")?
-                        .1
-                        .as_name_and_type()
-                        .ok_or("not a name_and_type")?
-                        .1;
-
-                    let objectref;
-                    let field_descriptor = parse_field_descriptor(&field_type)?;
-                    // println!("{:?}", field_descriptor.field_type);
-                    if matches!(
-                        field_descriptor.field_type,
-                        FieldType::Double | FieldType::LongInteger
-                    ) {
-                        objectref = current_frame
-                            .operand_stack
-                            .get(current_frame.operand_stack.len() - 3)
-                            .unwrap()
-                            .to_owned();
-                    } else {
-                        objectref = current_frame
+                // getfield
+                0xb4 => {
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
+                    let constant_pool = &current_frame.constant_pool;
+                    // the object is an instance of the referenced class or of a subclass, which
+                    // lays out the same fields at the same offsets
+                    let field = resolve_instance_field(global_memory, constant_pool, index)?;
+
+                    let objectref = HeapRef(
+                        current_frame
                             .operand_stack
-                            .get(current_frame.operand_stack.len() - 2)
-                            .unwrap()
-                            .to_owned();
+                            .pop()
+                            .ok_or("value is not on the stack")?,
+                    );
+                    if objectref.is_null() {
+                        current_frame.instruction_counter += instruction.length;
+                        self.throw_exception(global_memory, "java/lang/NullPointerException")?;
+                        continue;
                     }
-
-                    let field_ref = global_memory
+                    let values = global_memory
                         .heap
+                        .get(objectref)
+                        .ok_or("item not on heap")?
                         .data
-                        .get(objectref as usize)
-                        .ok_or(format!("object {objectref} not found on heap!"))?
-                        .field_descriptor
-                        .to_owned();
-                    let class_field_descriptor = parse_field_descriptor(&field_ref)?;
-                    let class_name = class_field_descriptor
-                        .field_type
-                        .as_class_instance()
-                        .ok_or("not a class instance")?;
-                    let offset = global_memory
-                        .method_area
-                        .classes
-                        .get(&class_name.to_owned())
-                        .ok_or(format!("didnt find class {class_name} in method_area"))?
-                        .as_instance_klass()
-                        .unwrap()
-                        .field_offset(constant)?;
-
-                    if matches!(
-                        field_descriptor.field_type,
-                        FieldType::Double | FieldType::LongInteger
-                    ) {
-                        let value = current_frame
+                        .get(field.offset..field.offset + field.width)
+                        .ok_or("field offset out of bounds")?;
+                    current_frame.operand_stack.extend_from_slice(values);
+                    // only one thread runs at a time and it never hands the lock on halfway
+                    // through an instruction, so every access is atomic, longs and doubles
+                    // included, and ordered by handing the lock on. Volatile accesses still fence
+                    // the way the JMM wants them to (JLS §17.4.4): reads acquire, writes release
+                    // and are ordered before any later read.
+                    if field.volatile {
+                        fence(Ordering::Acquire);
+                    }
+
+                    current_frame.instruction_counter += instruction.length;
+                }
+                // putfield
+                0xb5 => {
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
+                    let constant_pool = &current_frame.constant_pool;
+                    let field = resolve_instance_field(global_memory, constant_pool, index)?;
+
+                    // the objectref is below the value, which takes up two slots for longs and
+                    // doubles
+                    let values = current_frame
+                        .operand_stack
+                        .len()
+                        .checked_sub(field.width)
+                        .map(|start| current_frame.operand_stack.split_off(start))
+                        .ok_or("no popable value here")?;
+                    let objectref = HeapRef(
+                        current_frame
                             .operand_stack
                             .pop()
-                            .ok_or("no popable value here")?;
-                        global_memory
-                            .heap
-                            .data
-                            .get_mut(objectref as usize)
-                            .ok_or("this not on heap")?
-                            .data[offset as usize + 1] = value;
+                            .ok_or("objectref is not on the stack")?,
+                    );
+                    if objectref.is_null() {
+                        current_frame.instruction_counter += instruction.length;
+                        self.throw_exception(global_memory, "java/lang/NullPointerException")?;
+                        continue;
+                    }
+
+                    // see getfield
+                    if field.volatile {
+                        fence(Ordering::Release);
                     }
-                    let value = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no popable value here")?;
                     global_memory
                         .heap
-                        .data
-                        .get_mut(objectref as usize)
+                        .get_mut(objectref)
                         .ok_or("this not on heap")?
-                        .data[offset as usize] = value;
-
-                    let _objectref = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no popable value here")?;
+                        .data
+                        .get_mut(field.offset..field.offset + field.width)
+                        .ok_or("field offset out of bounds")?
+                        .copy_from_slice(&values);
+                    if field.volatile {
+                        fence(Ordering::SeqCst);
+                    }
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
-                // invokevirtual indexbyte1 indexbyte2
+                // invokevirtual
                 0xb6 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
-
-                    let (class_info, name_and_type) = current_frame
-                        .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
-                        .ok_or("expected ur mom")?
-                        .to_owned()
-                        .as_method_ref()
-                        .ok_or("not a field ref")?;
-                    let (name, method_descriptor_text) = name_and_type
-                        .as_name_and_type()
-                        .ok_or("not a NameAndType")?;
-                    let type_descriptor = parse_method_descriptor(method_descriptor_text)?;
-
-                    global_memory.ensure_class(class_info.name.as_str())?;
-
-                    // println!("name {name} type_descriptor {type_descriptor:?}");
-
-                    let mut nargs = vec![];
-                    for field_type in type_descriptor.parameter_descriptors.iter() {
-                        if matches!(field_type, FieldType::Double | FieldType::LongInteger) {
-                            let narg = current_frame
-                                .operand_stack
-                                .pop()
-                                .ok_or("nargs is not on the stack")?;
-                            nargs.insert(0, narg);
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
+
+                    let constant_pool = &current_frame.constant_pool;
+                    let method = match constant_pool.resolved(index) {
+                        Some(ResolvedConstant::Method(method)) => method,
+                        _ => {
+                            let (class_info, name_and_type) = constant_pool
+                                .pool()
+                                .get(index - 1)
+                                .ok_or("expected ur mom")?
+                                .to_owned()
+                                .as_method_ref()
+                                .ok_or("not a field ref")?;
+                            let (name, method_descriptor_text) = name_and_type
+                                .as_name_and_type()
+                                .ok_or("not a NameAndType")?;
+                            let type_descriptor = parse_method_descriptor(method_descriptor_text)?;
+
+                            // the receiver exists, so its class has been initialized already.
+                            // Methods of Object can be invoked on arrays, e.g. clone() of an int[]
+                            if class_info.name.starts_with('[') {
+                                global_memory.ensure_array(class_info.name.to_owned())?;
+                            } else {
+                                global_memory.ensure_linked(class_info.name.as_str())?;
+                            }
+                            // the index of the resolved method stays the same in the vtables of
+                            // all subclasses. Private methods aren't in the vtable, they are
+                            // invoked directly.
+                            let vtable_index = global_memory.method_area.vtable_index(
+                                &class_info.name,
+                                &name,
+                                &type_descriptor,
+                            );
+                            let method = ResolvedMethod {
+                                class_id: global_memory.method_area.classes.id(&class_info.name),
+                                name,
+                                descriptor: type_descriptor,
+                                vtable_index,
+                            };
+                            constant_pool
+                                .resolve(index, ResolvedConstant::Method(method.to_owned()));
+                            method
                         }
-                        let narg = current_frame
-                            .operand_stack
-                            .pop()
-                            .ok_or("nargs is not on the stack")?;
-                        nargs.insert(0, narg);
-                    }
+                    };
+
+                    let nargs = current_frame.pop_arguments(&method.descriptor)?;
                     let object_ref = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("object_ref is not on the stack")?;
 
-                    let heap_item = global_memory
+                    let receiver = global_memory
                         .heap
-                        .data
-                        .get(object_ref.to_owned() as usize)
-                        .ok_or("this_ref not found on heap")?;
-                    let descriptor = parse_field_descriptor(&heap_item.field_descriptor)?;
-                    let class_name = if let Some(name) = descriptor.field_type.as_class_instance() {
-                        name.to_owned()
-                    } else if let Some(_) = descriptor.field_type.as_array() {
-                        heap_item.field_descriptor.to_owned()
-                    } else {
-                        unreachable!();
+                        .get(HeapRef(object_ref))
+                        .ok_or("this_ref not found on heap")?
+                        .descriptor_id;
+                    let pc = current_frame.instruction_counter;
+                    let class_id = match code.cached_selection(pc, receiver) {
+                        Some(class_id) => ClassId(class_id),
+                        None => {
+                            let class_name = global_memory
+                                .heap
+                                .class_name(HeapRef(object_ref))
+                                .ok_or("this_ref not found on heap")?;
+                            let receiver_class_id = global_memory
+                                .method_area
+                                .classes
+                                .lookup_id(class_name)
+                                .ok_or(format!("Class not found {class_name} :("))?;
+                            let class_id = match method.vtable_index {
+                                Some(index) => global_memory
+                                    .method_area
+                                    .vtable_entry(class_name, index)
+                                    .ok_or(format!(
+                                        "vtable of {class_name} has no entry {index}"
+                                    ))?,
+                                // private methods are selected without looking at the receiver,
+                                // and can only be invoked from within their nest, see JVMS §5.4.4
+                                None if global_memory.method_area.declares_private_method(
+                                    global_memory.method_area.classes.name(method.class_id),
+                                    &method.name,
+                                    &method.descriptor,
+                                ) =>
+                                {
+                                    let method_class_name = global_memory
+                                        .method_area
+                                        .classes
+                                        .shared_name(method.class_id);
+                                    if !global_memory.is_nestmate_of(
+                                        current_frame.class_name(),
+                                        &method_class_name,
+                                    )? {
+                                        current_frame.instruction_counter += instruction.length;
+                                        self.throw_exception(
+                                            global_memory,
+                                            "java/lang/IllegalAccessError",
+                                        )?;
+                                        continue;
+                                    }
+                                    method.class_id
+                                }
+                                None => receiver_class_id,
+                            };
+                            code.cache_selection(pc, receiver, class_id.0);
+                            class_id
+                        }
                     };
                     let mut new_frame =
-                        Frame::new(global_memory, class_name, name, type_descriptor)?;
-                    // FIXME: this probably doesnt handle longs correctly?
-                    new_frame.local_variables[0] = object_ref;
+                        Frame::new(global_memory, class_id, method.name, method.descriptor)?;
+                    new_frame.set_local_variable(0, object_ref)?;
                     for narg in nargs.iter().enumerate() {
-                        new_frame.local_variables[narg.0 + 1] = *narg.1;
+                        new_frame.set_local_variable(narg.0 + 1, *narg.1)?;
                     }
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
 
                     self.thread_memory.jvm_stack.push(new_frame);
                 }
                 // invokespecial
                 0xb7 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
-
-                    let (class_info, name_and_type) = current_frame
-                        .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
-                        .ok_or("expected ur mom")?
-                        .to_owned()
-                        .as_method_ref()
-                        .ok_or("not a field ref")?;
-                    let (name, method_descriptor_text) = name_and_type
-                        .as_name_and_type()
-                        .ok_or("not a NameAndType")?;
-
-                    global_memory.ensure_class(class_info.name.as_str())?;
-                    let type_descriptor = parse_method_descriptor(method_descriptor_text)?;
-
-                    let mut nargs = vec![];
-                    for field_type in type_descriptor.parameter_descriptors.iter() {
-                        if matches!(field_type, FieldType::Double | FieldType::LongInteger) {
-                            let narg = current_frame
-                                .operand_stack
-                                .pop()
-                                .ok_or("nargs is not on the stack")?;
-                            nargs.insert(0, narg);
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
+                    let constant_pool = &current_frame.constant_pool;
+                    let method = match constant_pool.resolved(index) {
+                        Some(ResolvedConstant::Method(method)) => method,
+                        _ => {
+                            let (class_info, name_and_type) = constant_pool
+                                .pool()
+                                .get(index - 1)
+                                .ok_or("expected ur mom")?
+                                .to_owned()
+                                .as_method_ref()
+                                .ok_or("not a field ref")?;
+                            let (name, method_descriptor_text) = name_and_type
+                                .as_name_and_type()
+                                .ok_or("not a NameAndType")?;
+
+                            global_memory.ensure_linked(class_info.name.as_str())?;
+                            let type_descriptor = parse_method_descriptor(method_descriptor_text)?;
+
+                            // with ACC_SUPER, calls to methods of a super class (super.method())
+                            // start the lookup at the direct super class of the current class,
+                            // skipping anything in between (JVMS §6.5 invokespecial)
+                            let current_class = global_memory
+                                .method_area
+                                .parsed_class(current_frame.class_name())?;
+                            let mut class_name = class_info.name;
+                            if name != "<init>"
+                                && current_class.access.is_super
+                                && !global_memory.method_area.is_interface(&class_name)
+                            {
+                                if let Some(direct_super_class) = current_class.super_class.as_ref()
+                                {
+                                    if global_memory
+                                        .method_area
+                                        .is_super_class(current_frame.class_name(), &class_name)?
+                                    {
+                                        class_name = direct_super_class.name.to_owned();
+                                    }
+                                }
+                            }
+                            let method = ResolvedMethod {
+                                class_id: global_memory.method_area.classes.id(&class_name),
+                                name,
+                                descriptor: type_descriptor,
+                                vtable_index: None,
+                            };
+                            constant_pool
+                                .resolve(index, ResolvedConstant::Method(method.to_owned()));
+                            method
                         }
-                        let narg = current_frame
-                            .operand_stack
-                            .pop()
-                            .ok_or("nargs is not on the stack")?;
-                        nargs.insert(0, narg);
-                    }
+                    };
+
+                    let nargs = current_frame.pop_arguments(&method.descriptor)?;
                     let object_ref = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("object_ref is not on the stack")?;
 
-                    let mut new_frame =
-                        Frame::new(global_memory, class_info.name, name, type_descriptor)?;
-                    // FIXME: this probably doesnt handle longs correctly?
-                    new_frame.local_variables[0] = object_ref;
+                    let mut new_frame = Frame::new(
+                        global_memory,
+                        method.class_id,
+                        method.name,
+                        method.descriptor,
+                    )?;
+                    new_frame.set_local_variable(0, object_ref)?;
                     for narg in nargs.iter().enumerate() {
-                        new_frame.local_variables[narg.0 + 1] = *narg.1;
+                        new_frame.set_local_variable(narg.0 + 1, *narg.1)?;
                     }
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
 
                     self.thread_memory.jvm_stack.push(new_frame);
                 }
                 // invokestatic
                 0xb8 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
-
-                    let (class_info, name_and_type) = current_frame
-                        .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
-                        .ok_or("expected ur mom")?
-                        .to_owned()
-                        .as_method_ref()
-                        .ok_or("not a field ref")?;
-                    let (name, method_descriptor_text) = name_and_type
-                        .as_name_and_type()
-                        .ok_or("not a NameAndType")?;
-
-                    global_memory.ensure_class(class_info.name.as_str())?;
-
-                    let type_descriptor = parse_method_descriptor(method_descriptor_text)?;
-                    // println!("type_descriptor: {type_descriptor:?}");
-                    let mut nargs = vec![];
-
-                    // this loop is probably incorrect, as doubles and stuff take up 2 bytes
-                    for _ in 0..type_descriptor.parameter_descriptors.len() {
-                        let narg = current_frame
-                            .operand_stack
-                            .pop()
-                            .ok_or("nargs is not on the stack")?;
-                        nargs.insert(0, narg);
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
+                    let constant_pool = &current_frame.constant_pool;
+                    let (method, resolved) = match constant_pool.resolved(index) {
+                        Some(ResolvedConstant::Method(method)) => (method, true),
+                        _ => {
+                            let (class_info, name_and_type) = constant_pool
+                                .pool()
+                                .get(index - 1)
+                                .ok_or("expected ur mom")?
+                                .to_owned()
+                                .as_method_ref()
+                                .ok_or("not a field ref")?;
+                            let (name, method_descriptor_text) = name_and_type
+                                .as_name_and_type()
+                                .ok_or("not a NameAndType")?;
+                            let type_descriptor = parse_method_descriptor(method_descriptor_text)?;
+                            let method = ResolvedMethod {
+                                class_id: global_memory.method_area.classes.id(&class_info.name),
+                                name,
+                                descriptor: type_descriptor,
+                                vtable_index: None,
+                            };
+                            (method, false)
+                        }
+                    };
+                    if !resolved {
+                        let class_name = global_memory
+                            .method_area
+                            .classes
+                            .shared_name(method.class_id);
+                        let initialized = global_memory.ensure_class(&class_name);
+                        if self
+                            .initialized(global_memory, instruction.length, initialized)?
+                            .is_none()
+                        {
+                            continue;
+                        }
+                    }
+                    let current_frame = self
+                        .thread_memory
+                        .jvm_stack
+                        .last_mut()
+                        .ok_or("no item on jvm stack")?;
+                    // see resolve_static_field
+                    if !resolved
+                        && global_memory
+                            .method_area
+                            .classes
+                            .by_id(method.class_id)
+                            .is_some_and(|class| class.get_state() == ClassState::Initialized)
+                    {
+                        current_frame
+                            .constant_pool
+                            .resolve(index, ResolvedConstant::Method(method.to_owned()));
                     }
+                    let nargs = current_frame.pop_arguments(&method.descriptor)?;
 
-                    let mut new_frame =
-                        Frame::new(global_memory, class_info.name, name, type_descriptor)?;
-                    // FIXME: this probably doesnt handle longs correctly?
+                    let mut new_frame = Frame::new(
+                        global_memory,
+                        method.class_id,
+                        method.name,
+                        method.descriptor,
+                    )?;
                     for narg in nargs.iter().enumerate() {
-                        new_frame.local_variables[narg.0] = *narg.1;
+                        new_frame.set_local_variable(narg.0, *narg.1)?;
                     }
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
 
                     self.thread_memory.jvm_stack.push(new_frame)
                 }
                 // invokeinterface
                 0xb9 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
+                    // the trailing zero byte is checked when decoding
+                    let Operands::InvokeInterface { index, count } = instruction.operands else {
+                        return Err("expected the operands of invokeinterface".into());
+                    };
+                    let index = index as usize;
+
+                    let constant_pool = &current_frame.constant_pool;
+                    let method = match constant_pool.resolved(index) {
+                        Some(ResolvedConstant::Method(method)) => method,
+                        _ => {
+                            let (interface_info, name_and_type) = constant_pool
+                                .pool()
+                                .get(index - 1)
+                                .ok_or("expected ur mom")?
+                                .to_owned()
+                                .as_method_ref()
+                                .ok_or("not a field ref")?;
+                            let (name, method_descriptor_text) = name_and_type
+                                .as_name_and_type()
+                                .ok_or("not a NameAndType")?;
+                            let method = ResolvedMethod {
+                                class_id: global_memory
+                                    .method_area
+                                    .classes
+                                    .id(&interface_info.name),
+                                name,
+                                descriptor: parse_method_descriptor(method_descriptor_text)?,
+                                vtable_index: None,
+                            };
+                            constant_pool
+                                .resolve(index, ResolvedConstant::Method(method.to_owned()));
+                            method
+                        }
+                    };
+                    let ResolvedMethod {
+                        class_id: interface_id,
+                        name,
+                        descriptor: type_descriptor,
+                        ..
+                    } = method;
+                    let interface_name =
+                        global_memory.method_area.classes.shared_name(interface_id);
+                    // count is redundant with the descriptor, but has to match it, including
+                    // the objectref
+                    let width = 1 + type_descriptor
+                        .parameter_descriptors
+                        .iter()
+                        .map(|field_type| field_type.width())
+                        .sum::<usize>();
+                    if count as usize != width {
+                        return Err(format!(
+                            "VerifyError: invalid count {count} for invokeinterface {}.{name} in {}",
+                            interface_name,
+                            current_frame.method_display_name()
+                        )
+                        .into());
+                    }
+                    let nargs = current_frame.pop_arguments(&type_descriptor)?;
 
-                    let index = (indexbyte1 << 8) | indexbyte2;
+                    let objectref = current_frame
+                        .operand_stack
+                        .pop()
+                        .ok_or("value is not on the stack")?;
 
-                    current_frame.instruction_counter += 1;
-                    let count = *code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
+                    let field_descriptor = parse_field_descriptor(
+                        global_memory
+                            .heap
+                            .field_descriptor(HeapRef(objectref))
+                            .ok_or(format!("object {objectref} not found on heap!"))?,
+                    )?;
+                    // arrays implement Cloneable and Serializable, and only have the methods of
+                    // java/lang/Object
+                    let (class_name, implements_interface) =
+                        if field_descriptor.field_type.as_array().is_some() {
+                            (
+                                "java/lang/Object".to_owned(),
+                                &*interface_name == "java/lang/Cloneable"
+                                    || &*interface_name == "java/io/Serializable",
+                            )
+                        } else {
+                            let class_name = field_descriptor
+                                .field_type
+                                .as_class_instance()
+                                .ok_or("not a class instance")?
+                                .to_owned();
+                            let mut interface_names = vec![];
+                            global_memory.method_area.collect_superinterfaces(
+                                &*global_memory.method_area.parsed_class(&class_name)?,
+                                &mut interface_names,
+                            )?;
+                            let implements_interface =
+                                interface_names.iter().any(|name| **name == *interface_name);
+                            (class_name, implements_interface)
+                        };
+                    // the receiver is only checked at runtime, so this is an exception
+                    if !implements_interface && &*interface_name != "java/lang/Object" {
+                        current_frame.instruction_counter += instruction.length;
+                        self.throw_exception(
+                            global_memory,
+                            "java/lang/IncompatibleClassChangeError",
+                        )?;
+                        continue;
+                    }
 
-                    current_frame.instruction_counter += 1;
-                    let shouldbezero = *code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
+                    // selection happens on the class of the receiver, not on the interface
+                    let class_id =
+                        match global_memory.method_area.vtable_index(
+                            &class_name,
+                            &name,
+                            &type_descriptor,
+                        ) {
+                            Some(index) => global_memory
+                                .method_area
+                                .vtable_entry(&class_name, index)
+                                .ok_or(format!("vtable of {class_name} has no entry {index}"))?,
+                            // private interface methods are selected directly, see invokevirtual
+                            None if global_memory.method_area.declares_private_method(
+                                &interface_name,
+                                &name,
+                                &type_descriptor,
+                            ) =>
+                            {
+                                if !global_memory
+                                    .is_nestmate_of(current_frame.class_name(), &interface_name)?
+                                {
+                                    current_frame.instruction_counter += instruction.length;
+                                    self.throw_exception(
+                                        global_memory,
+                                        "java/lang/IllegalAccessError",
+                                    )?;
+                                    continue;
+                                }
+                                interface_id
+                            }
+                            None => global_memory.method_area.classes.id(&class_name),
+                        };
+
+                    let mut new_frame = Frame::new(global_memory, class_id, name, type_descriptor)?;
+                    new_frame.set_local_variable(0, objectref)?;
+                    for narg in nargs.iter().enumerate() {
+                        new_frame.set_local_variable(narg.0 + 1, *narg.1)?;
+                    }
+                    current_frame.instruction_counter += instruction.length;
+
+                    self.thread_memory.jvm_stack.push(new_frame)
+                }
+                // invokedynamic
+                0xba => {
+                    // the call sites are keyed by the index as it is in the code
+                    let Operands::ConstantPoolIndex(index) = instruction.operands else {
+                        return Err("expected a constant pool index".into());
+                    };
 
-                    let (interface_info, name_and_type) = current_frame
+                    let (bootstrap_method_attr_index, name_and_type) = match current_frame
                         .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
+                        .pool()
                         .get((index - 1) as usize)
                         .ok_or("expected ur mom")?
                         .to_owned()
-                        .as_method_ref()
-                        .ok_or("not a field ref")?;
+                    {
+                        Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type) => {
+                            (bootstrap_method_attr_index, name_and_type)
+                        }
+                        _ => return Err("not an InvokeDynamic".into()),
+                    };
                     let (name, method_descriptor_text) = name_and_type
                         .as_name_and_type()
                         .ok_or("not a NameAndType")?;
-
                     let type_descriptor = parse_method_descriptor(method_descriptor_text)?;
-                    // println!("name: {name} type_descriptor: {type_descriptor:?}");
-                    let mut nargs = vec![];
 
-                    // this loop is probably incorrect, as doubles and stuff take up 2 bytes
-                    for _ in 0..type_descriptor.parameter_descriptors.len() {
-                        let narg = current_frame
+                    let mut arguments = vec![];
+                    for field_type in type_descriptor.parameter_descriptors.iter().rev() {
+                        let mut value = vec![current_frame
                             .operand_stack
                             .pop()
-                            .ok_or("nargs is not on the stack")?;
-                        nargs.insert(0, narg);
+                            .ok_or("argument is not on the stack")?];
+                        if matches!(field_type, FieldType::LongInteger | FieldType::Double) {
+                            value.insert(
+                                0,
+                                current_frame
+                                    .operand_stack
+                                    .pop()
+                                    .ok_or("argument is not on the stack")?,
+                            );
+                        }
+                        arguments.insert(0, (field_type.to_owned(), value));
                     }
 
-                    let objectref = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("value is not on the stack")?;
-
-                    let field_ref = global_memory
-                        .heap
-                        .data
-                        .get(objectref as usize)
-                        .ok_or(format!("object {objectref} not found on heap!"))?
-                        .field_descriptor
-                        .to_owned();
-                    let field_descriptor = parse_field_descriptor(&field_ref)?;
-                    let class_name = field_descriptor
-                        .field_type
-                        .as_class_instance()
-                        .ok_or("not a class instance")?;
+                    let class_id = current_frame.class_id;
+                    let cached_call_site = global_memory
+                        .method_area
+                        .classes
+                        .by_id(class_id)
+                        .ok_or("class not found in method area")?
+                        .as_instance_klass()
+                        .ok_or("not an InstanceKlass")?
+                        .call_sites
+                        .get(&index)
+                        .cloned();
+                    let call_site = match cached_call_site {
+                        Some(call_site) => call_site,
+                        None => {
+                            let call_site = resolve_call_site(
+                                global_memory,
+                                current_frame.class_name(),
+                                bootstrap_method_attr_index,
+                                &name,
+                                &type_descriptor,
+                            )?;
+                            global_memory
+                                .method_area
+                                .classes
+                                .by_id_mut(class_id)
+                                .ok_or("class not found in method area")?
+                                .as_mut_instance_klass()
+                                .ok_or("not an InstanceKlass")?
+                                .call_sites
+                                .insert(index, call_site.clone());
+                            call_site
+                        }
+                    };
 
-                    let mut new_frame =
-                        Frame::new(global_memory, class_name.to_owned(), name, type_descriptor)?;
-                    new_frame.local_variables[0] = objectref;
-                    // FIXME: this probably doesnt handle longs correctly?
-                    for narg in nargs.iter().enumerate() {
-                        new_frame.local_variables[narg.0 + 1] = *narg.1;
+                    // like after any invoke, an exception is handled with the instruction_counter
+                    // past this instruction
+                    current_frame.instruction_counter += instruction.length;
+                    let result = self.invoke_call_site(global_memory, &call_site, arguments)?;
+                    if self.is_throwing {
+                        continue;
                     }
-                    current_frame.instruction_counter += 1;
-
-                    self.thread_memory.jvm_stack.push(new_frame)
+                    let current_frame = self
+                        .thread_memory
+                        .jvm_stack
+                        .last_mut()
+                        .ok_or("no item on jvm stack")?;
+                    current_frame.operand_stack.extend(result);
                 }
                 // new
                 0xbb => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
 
                     let constant = current_frame
                         .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
+                        .pool()
+                        .get(index - 1)
                         .ok_or("expected ur mom")?
                         .to_owned();
                     let class = constant.as_class().ok_or("ClassNotFound :(")?;
 
-                    global_memory.ensure_class(class.name.as_str())?;
+                    let initialized = global_memory.ensure_class(class.name.as_str());
+                    if self
+                        .initialized(global_memory, instruction.length, initialized)?
+                        .is_none()
+                    {
+                        continue;
+                    }
+                    let current_frame = self
+                        .thread_memory
+                        .jvm_stack
+                        .last_mut()
+                        .ok_or("no item on jvm stack")?;
                     let klass = global_memory
                         .method_area
                         .classes
@@ -4454,68 +11749,86 @@ impl Thread {
                         .ok_or("class not found in method area 3 :(")?;
 
                     let objectref = global_memory.heap.allocate_klass(klass);
-                    // println!("objectref new {}", objectref);
-                    current_frame.operand_stack.push(objectref);
+                    current_frame.operand_stack.push(objectref.0);
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // newarray
                 0xbc => {
-                    current_frame.instruction_counter += 1;
-                    let atype = *code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?;
+                    let Operands::Immediate(atype) = instruction.operands else {
+                        return Err("expected the atype of newarray".into());
+                    };
 
-                    let count = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
-                    let data = vec![0; count as usize];
+                    let array_type = match atype {
+                        4 => "[Z",
+                        5 => "[C",
+                        6 => "[F",
+                        7 => "[D",
+                        8 => "[B",
+                        9 => "[S",
+                        10 => "[I",
+                        11 => "[J",
+                        _ => return Err(format!("invalid atype {atype}").into()),
+                    };
 
-                    // FIXME: get type from atype and put it in type field
-                    let objectref = global_memory.heap.store("[B".to_string(), data);
+                    let count = Cursor::new(
+                        current_frame
+                            .operand_stack
+                            .pop()
+                            .ok_or("no item on the operand_stack")?
+                            .to_be_bytes(),
+                    )
+                    .read_i32::<BigEndian>()?;
+                    if count < 0 {
+                        current_frame.instruction_counter += instruction.length;
+                        self.throw_exception_with_message(
+                            global_memory,
+                            "java/lang/NegativeArraySizeException",
+                            &count.to_string(),
+                        )?;
+                        continue;
+                    }
+                    global_memory.ensure_array(array_type.to_owned())?;
+                    let objectref = global_memory.heap.store_array(array_type, count as usize);
 
-                    // println!("objectref newarray: {}", objectref);
-                    current_frame.operand_stack.push(objectref);
+                    current_frame.operand_stack.push(objectref.0);
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // anewarray
                 0xbd => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let index = (indexbyte1 << 8) | indexbyte2;
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
 
                     let constant = current_frame
                         .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
-                        .get((index - 1) as usize)
+                        .pool()
+                        .get(index - 1)
                         .ok_or("expected ur mom")?
                         .to_owned();
                     let class = constant.as_class().ok_or("ClassNotFound :(")?;
 
-                    let count = current_frame
-                        .operand_stack
-                        .pop()
-                        .ok_or("no item on the operand_stack")?;
+                    let count = current_frame.pop_int()?;
+                    if count < 0 {
+                        current_frame.instruction_counter += instruction.length;
+                        self.throw_exception_with_message(
+                            global_memory,
+                            "java/lang/NegativeArraySizeException",
+                            &count.to_string(),
+                        )?;
+                        continue;
+                    }
                     let data = vec![0; count as usize];
 
-                    let objectref = global_memory.heap.store(format!("[L{};", class.name), data);
+                    // the class of the components is an array class itself for arrays of arrays
+                    let array_type = array_type_of_component(&class.name);
+                    let objectref = global_memory.heap.store(&array_type, data);
 
-                    global_memory.ensure_array(format!("[L{};", class.name))?;
-                    current_frame.operand_stack.push(objectref);
+                    global_memory.ensure_array(array_type)?;
+                    current_frame.operand_stack.push(objectref.0);
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 //arraylength
                 0xbe => {
@@ -4523,21 +11836,17 @@ impl Thread {
                         .operand_stack
                         .pop()
                         .ok_or("nothing to pop here")?;
-                    let heap_item = global_memory
-                        .heap
-                        .data
-                        .get(arrayref as usize)
-                        .ok_or("no ref")?;
-                    let field_info = parse_field_descriptor(&heap_item.field_descriptor)?;
+                    let heap_item = global_memory.heap.get(HeapRef(arrayref)).ok_or("no ref")?;
+                    let descriptor = global_memory.heap.descriptor(heap_item);
+                    let field_info = parse_field_descriptor(descriptor)?;
                     if !matches!(field_info.field_type, FieldType::Array(_)) {
-                        // println!("{:?}", field_info.field_type);
                         return Err(format!("expected an array, found {field_info:?}").into());
                     }
-                    let length = heap_item.data.len();
+                    let length = global_memory.heap.array_length(heap_item);
                     let length_bytes =
                         Cursor::new((length as i32).to_be_bytes()).read_u32::<BigEndian>()?;
                     current_frame.operand_stack.push(length_bytes);
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // athrow
                 0xbf => {
@@ -4549,28 +11858,17 @@ impl Thread {
                 }
                 // checkcast
                 0xc0 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
                     let objectref = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
 
-                    let index = ((indexbyte1 << 8) | indexbyte2) as usize;
-                    // println!("index: {indexbyte1} {indexbyte2}");
-
-                    let constant = current_frame
+                    let _constant = current_frame
                         .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
+                        .pool()
                         .get(index - 1)
                         .ok_or("expected ur mom _1")?
                         .to_owned()
@@ -4578,7 +11876,7 @@ impl Thread {
                         .ok_or("not a class constant")?
                         .to_owned();
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                     current_frame.operand_stack.push(objectref);
                     // FIXME: this was the beginning of a implementation, but this seems to be to
                     // complex for now
@@ -4637,28 +11935,17 @@ impl Thread {
                 }
                 // instanceof
                 0xc1 => {
-                    current_frame.instruction_counter += 1;
-                    let indexbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let indexbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
+                    let index = instruction
+                        .constant_pool_index()
+                        .ok_or("expected a constant pool index")?;
                     let objectref = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
 
-                    let index = ((indexbyte1 << 8) | indexbyte2) as usize;
-                    // println!("index: {indexbyte1} {indexbyte2}");
-
                     let constant = current_frame
                         .constant_pool
-                        .clone()
-                        .upgrade()
-                        .ok_or("no constant_pool")?
-                        .pool
+                        .pool()
                         .get(index - 1)
                         .ok_or("expected ur mom _1")?
                         .to_owned()
@@ -4669,13 +11956,13 @@ impl Thread {
                         current_frame.operand_stack.push(0);
                     } else {
                         let t_name = constant.name;
-                        let s_heapitem = global_memory
-                            .heap
-                            .data
-                            .get(objectref as usize)
-                            .ok_or("objectref not on heap")?;
-                        let s_fieldtype =
-                            parse_field_descriptor(&s_heapitem.field_descriptor)?.field_type;
+                        let s_fieldtype = parse_field_descriptor(
+                            global_memory
+                                .heap
+                                .field_descriptor(HeapRef(objectref))
+                                .ok_or("objectref not on heap")?,
+                        )?
+                        .field_type;
                         if matches!(s_fieldtype, FieldType::ClassInstance(_)) {
                             let s_name = s_fieldtype.as_class_instance().unwrap();
                             if *s_name == t_name {
@@ -4688,131 +11975,618 @@ impl Thread {
                         }
                     }
 
-                    current_frame.instruction_counter += 1;
+                    current_frame.instruction_counter += instruction.length;
                     // FIXME: implement - see checkcast
                 }
                 // monitorenter
                 0xc2 => {
-                    // FIXME: Implement
-                    let value = current_frame
+                    let objectref = *current_frame
                         .operand_stack
-                        .pop()
+                        .last()
                         .ok_or("no item on the operand_stack")?;
+                    if objectref != 0
+                        && !threads::try_monitor_enter(global_memory, self.id, HeapRef(objectref))
+                    {
+                        // another thread owns the monitor, so the instruction is run again once
+                        // it may have left it
+                        if threads::block(self, global_memory, None) {
+                            return Ok(());
+                        }
+                        continue;
+                    }
+                    current_frame.operand_stack.pop();
                     current_frame.instruction_counter += 1;
+                    if objectref == 0 {
+                        self.throw_exception(global_memory, "java/lang/NullPointerException")?;
+                    }
                 }
                 // monitorexit
                 0xc3 => {
-                    // FIXME: Implement
-                    let value = current_frame
+                    let objectref = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
                     current_frame.instruction_counter += 1;
+                    if objectref == 0 {
+                        self.throw_exception(global_memory, "java/lang/NullPointerException")?;
+                    } else if !threads::monitor_exit(global_memory, self.id, HeapRef(objectref)) {
+                        self.throw_exception(
+                            global_memory,
+                            "java/lang/IllegalMonitorStateException",
+                        )?;
+                    }
+                }
+                // multianewarray
+                0xc5 => {
+                    let Operands::MultiANewArray { index, dimensions } = instruction.operands
+                    else {
+                        return Err("expected the operands of multianewarray".into());
+                    };
+
+                    let class = current_frame
+                        .constant_pool
+                        .pool()
+                        .get(index as usize - 1)
+                        .ok_or("expected ur mom")?
+                        .to_owned()
+                        .as_class()
+                        .ok_or("not a class constant")?
+                        .to_owned();
+
+                    // the count of the outermost dimension is the deepest on the stack
+                    let mut counts = vec![];
+                    for _ in 0..dimensions {
+                        let count = Cursor::new(
+                            current_frame
+                                .operand_stack
+                                .pop()
+                                .ok_or("no item on the operand_stack")?
+                                .to_be_bytes(),
+                        )
+                        .read_i32::<BigEndian>()?;
+                        counts.insert(0, count);
+                    }
+                    if let Some(count) = counts.iter().find(|count| **count < 0) {
+                        let message = count.to_string();
+                        current_frame.instruction_counter += instruction.length;
+                        self.throw_exception_with_message(
+                            global_memory,
+                            "java/lang/NegativeArraySizeException",
+                            &message,
+                        )?;
+                        continue;
+                    }
+
+                    let arrayref = global_memory.allocate_multi_array(&class.name, &counts)?;
+                    current_frame.operand_stack.push(arrayref.0);
+
+                    current_frame.instruction_counter += instruction.length;
                 }
                 // ifnull
                 0xc6 => {
-                    current_frame.instruction_counter += 1;
-                    let branchbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let branchbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let branchoffset =
-                        Cursor::new(((branchbyte1 << 8) | branchbyte2).to_be_bytes())
-                            .read_i16::<BigEndian>()?;
-
-                    // println!("branchoffset: {}", branchbyte2);
+                    let target = instruction
+                        .branch_target()
+                        .ok_or("expected a branch target")?;
 
                     let value = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    // println!("value: {value}");
 
                     if value == 0 {
-                        current_frame.instruction_counter =
-                            (current_frame.instruction_counter - 2) + branchoffset as usize;
+                        current_frame.instruction_counter = target;
                     } else {
-                        current_frame.instruction_counter += 1;
+                        current_frame.instruction_counter += instruction.length;
                     }
                 }
                 // ifnonnull
                 0xc7 => {
-                    current_frame.instruction_counter += 1;
-                    let branchbyte1 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-                    current_frame.instruction_counter += 1;
-                    let branchbyte2 = (*code_bytes
-                        .get(current_frame.instruction_counter)
-                        .ok_or("no bytes")?) as u16;
-
-                    let branchoffset =
-                        Cursor::new(((branchbyte1 << 8) | branchbyte2).to_be_bytes())
-                            .read_i16::<BigEndian>()?;
-
-                    // println!("branchoffset: {}", branchoffset);
+                    let target = instruction
+                        .branch_target()
+                        .ok_or("expected a branch target")?;
 
                     let value = current_frame
                         .operand_stack
                         .pop()
                         .ok_or("no item on the operand_stack")?;
-                    // println!("value: {value}");
 
                     if value != 0 {
-                        // println!(
-                        //     "{} {}",
-                        //     current_frame.instruction_counter - 2,
-                        //     branchoffset as isize
-                        // );
-                        current_frame.instruction_counter =
-                            ((current_frame.instruction_counter - 2) as isize
-                                + branchoffset as isize) as usize;
+                        current_frame.instruction_counter = target;
                     } else {
-                        current_frame.instruction_counter += 1;
+                        current_frame.instruction_counter += instruction.length;
                     }
                 }
 
-                i @ _ => return Err(format!("unknown instruction {i:#0x}").into()),
+                i => {
+                    if let Some(histogram) = global_memory.opcode_histogram.as_mut() {
+                        histogram.mark_unimplemented(i);
+                    }
+                    return Err(format!(
+                        "unknown instruction {i:#0x} ({})",
+                        instruction.mnemonic()
+                    )
+                    .into());
+                }
             }
-
-            // println!("vm: {:?} {:?}", self, global_memory.heap)
         }
 
         Ok(())
     }
 }
 
+// how a Vm is configured, see VmBuilder
+#[derive(Debug, Clone)]
+pub struct RunOptions {
+    // set in addition to the defaults, which they take precedence over
+    pub system_properties: Vec<(String, String)>,
+    // the number of frames a thread may have on its stack
+    pub max_stack_depth: usize,
+    pub verify_mode: VerifyMode,
+    pub collector: Collector,
+    pub thread_mode: ThreadMode,
+    pub verbose_gc: bool,
+    // the file the heap is dumped to in the HPROF format when the first OutOfMemoryError is
+    // thrown
+    pub heap_dump_on_out_of_memory: Option<String>,
+    // the port run_main waits for a JDWP debugger to attach on
+    pub debug_port: Option<u16>,
+    // whether run_main stops before the first instruction of the main class and reads debugger
+    // commands from stdin
+    pub debug_prompt: bool,
+    // the levels of the log targets, applied after those of the MATEBABE_LOG environment variable,
+    // see logging
+    pub log_levels: Vec<(LogTarget, LogLevel)>,
+    // the file the methods entered and left and the instructions run are written to, for the
+    // classes matching trace_filters, e.g. com/example/*, or all classes if there are none
+    pub trace: Option<String>,
+    pub trace_filters: Vec<String>,
+    // the file the instructions run are written to when the vm exits, as collapsed stacks for
+    // flame graphs
+    pub profile: Option<String>,
+    // whether the opcodes run are counted and summarized on stderr when the vm exits
+    pub opcode_histogram: bool,
+    // how many of the methods that ran the most instructions are printed on stderr when the vm
+    // exits, see Vm::method_statistics
+    pub hot_methods: Option<usize>,
+    // whether run_main handles ^C like java, running the shutdown hooks before exiting with 130,
+    // rather than leaving SIGINT to the embedder
+    pub handle_interrupt: bool,
+}
+
+impl Default for RunOptions {
+    fn default() -> RunOptions {
+        RunOptions {
+            system_properties: vec![],
+            max_stack_depth: DEFAULT_MAX_STACK_DEPTH,
+            verify_mode: VerifyMode::Remote,
+            collector: Collector::MarkSweep,
+            thread_mode: ThreadMode::Native,
+            verbose_gc: false,
+            heap_dump_on_out_of_memory: None,
+            debug_port: None,
+            debug_prompt: false,
+            log_levels: vec![],
+            trace: None,
+            trace_filters: vec![],
+            profile: None,
+            opcode_histogram: false,
+            hot_methods: None,
+            handle_interrupt: false,
+        }
+    }
+}
+
+// a value passed to or returned from Java land by Vm::call_static. References are indices into
+// the heap, 0 being null; strings and arrays are created with Vm::new_string and Vm::new_array.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JavaValue {
+    Void,
+    Boolean(bool),
+    Byte(i8),
+    Char(u16),
+    Short(i16),
+    Int(i32),
+    Long(i64),
+    Float(f32),
+    Double(f64),
+    Reference(HeapRef),
+}
+
+impl JavaValue {
+    pub fn null() -> JavaValue {
+        JavaValue::Reference(HeapRef::NULL)
+    }
+
+    // converts the value into its operand stack slots, checking that it fits the field_type.
+    // Longs and doubles are split into two slots, the high word first.
+    fn slots(&self, field_type: &FieldType) -> Result<Vec<u32>, Box<dyn Error>> {
+        let slots = match (field_type, self) {
+            (FieldType::Boolean, JavaValue::Boolean(value)) => vec![*value as u32],
+            (FieldType::Byte, JavaValue::Byte(value)) => vec![*value as i32 as u32],
+            (FieldType::Char, JavaValue::Char(value)) => vec![*value as u32],
+            (FieldType::Short, JavaValue::Short(value)) => vec![*value as i32 as u32],
+            (FieldType::Integer, JavaValue::Int(value)) => vec![*value as u32],
+            (FieldType::Float, JavaValue::Float(value)) => vec![value.to_bits()],
+            (FieldType::LongInteger, JavaValue::Long(value)) => {
+                slots::long_to_slots(*value).to_vec()
+            }
+            (FieldType::Double, JavaValue::Double(value)) => {
+                slots::double_to_slots(*value).to_vec()
+            }
+            (FieldType::ClassInstance(_) | FieldType::Array(_), JavaValue::Reference(value)) => {
+                vec![value.0]
+            }
+            _ => {
+                return Err(
+                    format!("{self:?} can not be passed as {}", field_type.descriptor()).into(),
+                )
+            }
+        };
+        Ok(slots)
+    }
+
+    // the inverse of slots
+    fn from_slots(field_type: &FieldType, slots: &[u32]) -> Result<JavaValue, Box<dyn Error>> {
+        let slot = |index: usize| -> Result<u32, Box<dyn Error>> {
+            Ok(*slots.get(index).ok_or("value is missing slots")?)
+        };
+        let value = match field_type {
+            FieldType::Boolean => JavaValue::Boolean(slot(0)? != 0),
+            FieldType::Byte => JavaValue::Byte(slot(0)? as i8),
+            FieldType::Char => JavaValue::Char(slot(0)? as u16),
+            FieldType::Short => JavaValue::Short(slot(0)? as i16),
+            FieldType::Integer => JavaValue::Int(slot(0)? as i32),
+            FieldType::Float => JavaValue::Float(f32::from_bits(slot(0)?)),
+            FieldType::LongInteger => JavaValue::Long(slots::long_from_slots(slot(0)?, slot(1)?)),
+            FieldType::Double => JavaValue::Double(slots::double_from_slots(slot(0)?, slot(1)?)),
+            FieldType::ClassInstance(_) | FieldType::Array(_) => {
+                JavaValue::Reference(HeapRef(slot(0)?))
+            }
+        };
+        Ok(value)
+    }
+}
+
+impl From<bool> for JavaValue {
+    fn from(value: bool) -> JavaValue {
+        JavaValue::Boolean(value)
+    }
+}
+
+impl From<i8> for JavaValue {
+    fn from(value: i8) -> JavaValue {
+        JavaValue::Byte(value)
+    }
+}
+
+impl From<i16> for JavaValue {
+    fn from(value: i16) -> JavaValue {
+        JavaValue::Short(value)
+    }
+}
+
+impl From<i32> for JavaValue {
+    fn from(value: i32) -> JavaValue {
+        JavaValue::Int(value)
+    }
+}
+
+impl From<i64> for JavaValue {
+    fn from(value: i64) -> JavaValue {
+        JavaValue::Long(value)
+    }
+}
+
+impl From<f32> for JavaValue {
+    fn from(value: f32) -> JavaValue {
+        JavaValue::Float(value)
+    }
+}
+
+impl From<f64> for JavaValue {
+    fn from(value: f64) -> JavaValue {
+        JavaValue::Double(value)
+    }
+}
+
+impl TryFrom<JavaValue> for bool {
+    type Error = Box<dyn Error>;
+
+    fn try_from(value: JavaValue) -> Result<bool, Box<dyn Error>> {
+        match value {
+            JavaValue::Boolean(value) => Ok(value),
+            _ => Err(format!("{value:?} is not a boolean").into()),
+        }
+    }
+}
+
+impl TryFrom<JavaValue> for i32 {
+    type Error = Box<dyn Error>;
+
+    // like in Java, the smaller integral types widen to an int
+    fn try_from(value: JavaValue) -> Result<i32, Box<dyn Error>> {
+        match value {
+            JavaValue::Byte(value) => Ok(value as i32),
+            JavaValue::Char(value) => Ok(value as i32),
+            JavaValue::Short(value) => Ok(value as i32),
+            JavaValue::Int(value) => Ok(value),
+            _ => Err(format!("{value:?} is not an int").into()),
+        }
+    }
+}
+
+impl TryFrom<JavaValue> for i64 {
+    type Error = Box<dyn Error>;
+
+    fn try_from(value: JavaValue) -> Result<i64, Box<dyn Error>> {
+        match value {
+            JavaValue::Long(value) => Ok(value),
+            _ => Ok(i32::try_from(value).map_err(|_| format!("{value:?} is not a long"))? as i64),
+        }
+    }
+}
+
+impl TryFrom<JavaValue> for f32 {
+    type Error = Box<dyn Error>;
+
+    fn try_from(value: JavaValue) -> Result<f32, Box<dyn Error>> {
+        match value {
+            JavaValue::Float(value) => Ok(value),
+            _ => Err(format!("{value:?} is not a float").into()),
+        }
+    }
+}
+
+impl TryFrom<JavaValue> for f64 {
+    type Error = Box<dyn Error>;
+
+    fn try_from(value: JavaValue) -> Result<f64, Box<dyn Error>> {
+        match value {
+            JavaValue::Float(value) => Ok(value as f64),
+            JavaValue::Double(value) => Ok(value),
+            _ => Err(format!("{value:?} is not a double").into()),
+        }
+    }
+}
+
+// creates a Vm, e.g. VmBuilder::new().system_property("foo", "bar").build()
+#[derive(Debug, Clone, Default)]
+pub struct VmBuilder {
+    options: RunOptions,
+}
+
+impl VmBuilder {
+    pub fn new() -> VmBuilder {
+        VmBuilder::default()
+    }
+
+    pub fn options(mut self, options: RunOptions) -> VmBuilder {
+        self.options = options;
+        self
+    }
+
+    pub fn system_property(mut self, key: &str, value: &str) -> VmBuilder {
+        self.options
+            .system_properties
+            .push((key.to_owned(), value.to_owned()));
+        self
+    }
+
+    pub fn max_stack_depth(mut self, max_stack_depth: usize) -> VmBuilder {
+        self.options.max_stack_depth = max_stack_depth;
+        self
+    }
+
+    pub fn verify_mode(mut self, verify_mode: VerifyMode) -> VmBuilder {
+        self.options.verify_mode = verify_mode;
+        self
+    }
+
+    pub fn collector(mut self, collector: Collector) -> VmBuilder {
+        self.options.collector = collector;
+        self
+    }
+
+    pub fn thread_mode(mut self, thread_mode: ThreadMode) -> VmBuilder {
+        self.options.thread_mode = thread_mode;
+        self
+    }
+
+    pub fn verbose_gc(mut self, verbose_gc: bool) -> VmBuilder {
+        self.options.verbose_gc = verbose_gc;
+        self
+    }
+
+    pub fn heap_dump_on_out_of_memory(mut self, path: &str) -> VmBuilder {
+        self.options.heap_dump_on_out_of_memory = Some(path.to_owned());
+        self
+    }
+
+    pub fn debug_port(mut self, port: u16) -> VmBuilder {
+        self.options.debug_port = Some(port);
+        self
+    }
+
+    pub fn debug_prompt(mut self, debug_prompt: bool) -> VmBuilder {
+        self.options.debug_prompt = debug_prompt;
+        self
+    }
+
+    pub fn log_level(mut self, target: LogTarget, level: LogLevel) -> VmBuilder {
+        self.options.log_levels.push((target, level));
+        self
+    }
+
+    pub fn trace(mut self, path: &str) -> VmBuilder {
+        self.options.trace = Some(path.to_owned());
+        self
+    }
+
+    pub fn trace_filter(mut self, filter: &str) -> VmBuilder {
+        self.options.trace_filters.push(filter.to_owned());
+        self
+    }
+
+    pub fn profile(mut self, path: &str) -> VmBuilder {
+        self.options.profile = Some(path.to_owned());
+        self
+    }
+
+    pub fn opcode_histogram(mut self, opcode_histogram: bool) -> VmBuilder {
+        self.options.opcode_histogram = opcode_histogram;
+        self
+    }
+
+    pub fn hot_methods(mut self, count: usize) -> VmBuilder {
+        self.options.hot_methods = Some(count);
+        self
+    }
+
+    pub fn handle_interrupt(mut self, handle_interrupt: bool) -> VmBuilder {
+        self.options.handle_interrupt = handle_interrupt;
+        self
+    }
+
+    pub fn build(self) -> Vm {
+        Vm::new(self.options)
+    }
+}
+
 #[derive(Debug)]
-struct VM {
+pub struct Vm {
     global_memory: GlobalMemory,
     main_thread: Thread,
+    // whether the classes of java.lang have been set up, which happens before running any code
+    initialized: bool,
+}
+
+// the threads started from java land that are still running, like daemons, have to be stopped
+// before GlobalMemory goes away
+impl Drop for Vm {
+    fn drop(&mut self) {
+        threads::stop(&mut self.global_memory);
+    }
+}
+
+fn default_system_properties() -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    properties.insert("line.separator".to_owned(), "\n".to_owned());
+    properties.insert("java.home".to_owned(), ".".to_owned());
+    properties.insert("user.home".to_owned(), ".".to_owned());
+    properties.insert("user.dir".to_owned(), ".".to_owned());
+    properties.insert("user.name".to_owned(), ".".to_owned());
+    properties.insert("file.separator".to_owned(), "/".to_owned());
+    properties.insert("path.separator".to_owned(), ":".to_owned());
+    properties.insert("file.encoding".to_owned(), "UTF-8".to_owned());
+    // where System.loadLibrary looks for shared libraries
+    properties.insert("java.library.path".to_owned(), ".".to_owned());
+    properties.insert("sun.boot.library.path".to_owned(), ".".to_owned());
+    properties
 }
 
-impl VM {
-    fn new() -> Rc<RefCell<VM>> {
-        let vm = VM {
+impl Vm {
+    fn new(options: RunOptions) -> Vm {
+        let RunOptions {
+            system_properties,
+            max_stack_depth,
+            verify_mode,
+            collector,
+            thread_mode,
+            verbose_gc,
+            heap_dump_on_out_of_memory,
+            debug_port,
+            debug_prompt,
+            log_levels,
+            trace,
+            trace_filters,
+            profile,
+            opcode_histogram,
+            hot_methods,
+            handle_interrupt,
+        } = options;
+        // a spec in the environment that doesn't parse is ignored rather than keeping the vm from
+        // running
+        let environment_levels =
+            match std::env::var("MATEBABE_LOG").map(|spec| parse_log_spec(&spec)) {
+                Ok(Ok(levels)) => levels,
+                Ok(Err(e)) => {
+                    eprintln!("ignoring MATEBABE_LOG: {e}");
+                    vec![]
+                }
+                Err(_) => vec![],
+            };
+        for (target, level) in environment_levels.into_iter().chain(log_levels) {
+            logging::set_level(target, level);
+        }
+        let mut properties = default_system_properties();
+        // properties passed on the command line take precedence over our defaults
+        properties.extend(system_properties);
+
+        Vm {
             global_memory: GlobalMemory {
-                method_area: MethodArea {
-                    classes: HashMap::new(),
-                },
+                method_area: MethodArea::default(),
                 heap: Heap::new(),
+                system_properties: properties,
+                open_files: HashMap::new(),
+                // 0, 1 and 2 are stdin, stdout and stderr
+                next_file_descriptor: 3,
+                native_memory: NativeMemory::new(),
+                exit_status: None,
+                shutting_down: false,
+                handle_interrupt,
+                interrupted: Arc::new(AtomicBool::new(false)),
+                lambda_classes: HashMap::new(),
+                natives: builtin_natives(),
+                native_libraries: vec![],
+                interned_strings: HashMap::new(),
+                jni_global_references: vec![],
+                thread_mode,
+                scheduler: Arc::new(Scheduler::new()),
+                current_thread: threads::MAIN_THREAD_ID,
+                threads: HashMap::new(),
+                daemon_threads: HashSet::new(),
+                suspended_threads: HashMap::new(),
+                green_threads: VecDeque::new(),
+                host_threads: vec![],
+                monitors: HashMap::new(),
+                initializing_classes: HashMap::new(),
+                max_stack_depth,
+                verify_mode,
+                collector,
+                verbose_gc,
+                gc_statistics: GcStatistics::default(),
+                heap_dump_on_out_of_memory,
+                reference_pending_list: HeapRef::NULL,
+                reference_handler: None,
+                cleaners: vec![],
+                main_thread_object: None,
+                debug_port,
+                debugger: None,
+                debug_prompt_enabled: debug_prompt,
+                debug_prompt: None,
+                trace_file: trace,
+                trace_filters,
+                trace: None,
+                profiler: profile.map(|path| Box::new(profile::Profiler::new(&path))),
+                opcode_histogram: opcode_histogram
+                    .then(|| Box::new(histogram::OpcodeHistogram::new())),
+                hot_methods,
             },
-            main_thread: Thread {
-                thread_memory: ThreadMemory {
-                    jvm_stack: Vec::new(),
-                },
-                is_throwing: false,
-                java_clone: None,
-            },
-        };
-
-        let vmref = Rc::new(RefCell::new(vm));
+            main_thread: Thread::new(threads::MAIN_THREAD_ID, max_stack_depth, None),
+            initialized: false,
+        }
+    }
 
-        return vmref;
+    fn ensure_initialized(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.initialized {
+            if let Some(path) = self.global_memory.trace_file.as_ref() {
+                let trace = trace::Trace::new(path, &self.global_memory.trace_filters)?;
+                self.global_memory.trace = Some(Box::new(trace));
+            }
+            self.initialize_java_lang_classes()?;
+            self.initialized = true;
+        }
+        Ok(())
     }
 
     fn initialize_java_lang_classes(&mut self) -> Result<(), Box<dyn Error>> {
@@ -4843,16 +12617,16 @@ impl VM {
         let system_threadgroup = self.global_memory.heap.allocate_klass(thread_group_klass);
         let return_frame = Frame::new_stub()?;
         self.main_thread.thread_memory.jvm_stack.push(return_frame);
-        let mut next_frame = Frame::new(
+        let mut next_frame = Frame::for_class_name(
             &mut self.global_memory,
-            "java/lang/ThreadGroup".to_owned(),
+            "java/lang/ThreadGroup",
             "<init>".into(),
             MethodDescriptor {
                 parameter_descriptors: vec![],
                 return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
             },
         )?;
-        next_frame.local_variables[0] = system_threadgroup;
+        next_frame.local_variables[0] = system_threadgroup.0;
         self.main_thread.thread_memory.jvm_stack.push(next_frame);
         self.main_thread.run(&mut self.global_memory)?;
 
@@ -4866,9 +12640,9 @@ impl VM {
         let main_threadgroup = self.global_memory.heap.allocate_klass(thread_group_klass);
         let return_frame = Frame::new_stub()?;
         self.main_thread.thread_memory.jvm_stack.push(return_frame);
-        let mut next_frame = Frame::new(
+        let mut next_frame = Frame::for_class_name(
             &mut self.global_memory,
-            "java/lang/ThreadGroup".to_owned(),
+            "java/lang/ThreadGroup",
             "<init>".into(),
             MethodDescriptor {
                 parameter_descriptors: vec![
@@ -4878,9 +12652,9 @@ impl VM {
                 return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
             },
         )?;
-        next_frame.local_variables[0] = main_threadgroup;
-        next_frame.local_variables[1] = system_threadgroup;
-        next_frame.local_variables[2] = name_ref;
+        next_frame.local_variables[0] = main_threadgroup.0;
+        next_frame.local_variables[1] = system_threadgroup.0;
+        next_frame.local_variables[2] = name_ref.0;
         self.main_thread.thread_memory.jvm_stack.push(next_frame);
         self.main_thread.run(&mut self.global_memory)?;
 
@@ -4894,6 +12668,10 @@ impl VM {
         let initial_thread_ref = self.global_memory.heap.allocate_klass(thread_klass);
         // initialize thread
         self.main_thread.java_clone = Some(initial_thread_ref);
+        self.global_memory.main_thread_object = Some(initial_thread_ref);
+        self.global_memory
+            .threads
+            .insert(threads::MAIN_THREAD_ID, initial_thread_ref);
 
         let priority_offset = thread_klass
             .as_instance_klass()
@@ -4901,17 +12679,21 @@ impl VM {
             .field_offset_with_strings("java/lang/Thread".to_owned(), "priority".to_owned())?;
         self.global_memory
             .heap
-            .data
-            .get_mut(initial_thread_ref as usize)
+            .get_mut(initial_thread_ref)
             .unwrap()
             .data[priority_offset] = 5;
+        threads::set_eetop(
+            &mut self.global_memory,
+            initial_thread_ref,
+            threads::MAIN_THREAD_ID,
+        )?;
 
         let name_ref = java_string_from_string(&mut self.global_memory, "main".to_owned())?;
         let return_frame = Frame::new_stub()?;
         self.main_thread.thread_memory.jvm_stack.push(return_frame);
-        let mut next_frame = Frame::new(
+        let mut next_frame = Frame::for_class_name(
             &mut self.global_memory,
-            "java/lang/Thread".to_owned(),
+            "java/lang/Thread",
             "<init>".into(),
             MethodDescriptor {
                 parameter_descriptors: vec![
@@ -4921,16 +12703,16 @@ impl VM {
                 return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
             },
         )?;
-        next_frame.local_variables[0] = initial_thread_ref;
-        next_frame.local_variables[1] = system_threadgroup;
-        next_frame.local_variables[2] = name_ref;
+        next_frame.local_variables[0] = initial_thread_ref.0;
+        next_frame.local_variables[1] = system_threadgroup.0;
+        next_frame.local_variables[2] = name_ref.0;
         self.main_thread.thread_memory.jvm_stack.push(next_frame);
         self.main_thread.run(&mut self.global_memory)?;
 
         // init system
-        let current_frame = Frame::new(
+        let current_frame = Frame::for_class_name(
             &mut self.global_memory,
-            "java/lang/System".to_owned(),
+            "java/lang/System",
             "initPhase1".into(),
             MethodDescriptor {
                 parameter_descriptors: vec![],
@@ -4943,13 +12725,58 @@ impl VM {
         Ok(())
     }
 
-    fn run(&mut self, name: String) -> Result<(), Box<dyn Error>> {
-        self.initialize_java_lang_classes()?;
-        self.global_memory.ensure_class(&name)?;
+    // runs the main method of a class like java does, returning the exit status
+    pub fn run_main(&mut self, class_name: &str) -> Result<i32, Box<dyn Error>> {
+        let previous_action = if self.global_memory.handle_interrupt {
+            Some(install_interrupt_handler(&self.global_memory.interrupted)?)
+        } else {
+            None
+        };
+        let status = self.run_main_class(class_name);
+        if let Some(previous_action) = previous_action {
+            unsafe { libc::sigaction(libc::SIGINT, &previous_action, std::ptr::null_mut()) };
+        }
+        // the trace is most interesting when the vm failed
+        if let Some(trace) = self.global_memory.trace.as_mut() {
+            trace.flush()?;
+        }
+        if let Some(profiler) = self.global_memory.profiler.as_ref() {
+            profiler.write()?;
+        }
+        if let Some(histogram) = self.global_memory.opcode_histogram.as_ref() {
+            eprint!("{}", histogram.summary());
+        }
+        if let Some(count) = self.global_memory.hot_methods {
+            eprintln!("[methods]        calls instructions method");
+            for method in self.method_statistics().iter().take(count) {
+                eprintln!(
+                    "[methods] {:>12} {:>12} {}.{}{}",
+                    method.calls,
+                    method.instructions,
+                    method.class_name,
+                    method.method_name,
+                    method.descriptor
+                );
+            }
+        }
+        status
+    }
+
+    fn run_main_class(&mut self, class_name: &str) -> Result<i32, Box<dyn Error>> {
+        self.ensure_initialized()?;
+        // like java with suspend=y, the debugger gets to set its breakpoints before the main class
+        // is even loaded
+        if let Some(port) = self.global_memory.debug_port {
+            jdwp::attach(&self.main_thread, &mut self.global_memory, port)?;
+        }
+        self.global_memory.ensure_class(class_name)?;
+        if self.global_memory.debug_prompt_enabled {
+            self.global_memory.debug_prompt = Some(Box::new(debug::DebugPrompt::new()));
+        }
 
-        let current_frame = Frame::new(
+        let current_frame = Frame::for_class_name(
             &mut self.global_memory,
-            name,
+            class_name,
             "main".into(),
             MethodDescriptor {
                 parameter_descriptors: vec![FieldType::Array(Box::new(FieldType::ClassInstance(
@@ -4961,25 +12788,261 @@ impl VM {
         self.main_thread.thread_memory.jvm_stack.push(current_frame);
         self.main_thread.run(&mut self.global_memory)?;
 
-        Ok(())
+        // like java, the main thread ends once main returned or threw, the exception ending up on
+        // the stub frame below it, and the vm exits once all other non-daemon threads ended as well
+        let main_threw = self.main_thread.is_throwing;
+        if self.global_memory.exit_status.is_none() {
+            let thread_ref = self
+                .global_memory
+                .main_thread_object
+                .ok_or("no main thread object")?;
+            self.main_thread
+                .end_thread(&mut self.global_memory, thread_ref.0)?;
+            threads::exit(&mut self.global_memory, threads::MAIN_THREAD_ID, thread_ref)?;
+        }
+        threads::join_non_daemon_threads(&mut self.main_thread, &mut self.global_memory);
+        if self.global_memory.exit_status.is_none() {
+            self.main_thread.shutdown(&mut self.global_memory)?;
+        }
+        jdwp::vm_death(&mut self.global_memory)?;
+        Ok(self
+            .global_memory
+            .exit_status
+            .unwrap_or(if main_threw { 1 } else { 0 }))
     }
-}
 
-pub fn run(filename: String) {
-    let rt = VM::new();
-    let class_name = filename;
-    let result = (*rt).borrow_mut().run(class_name.to_owned());
-    println!("heap dump: ",);
-    for (idx, heap_item) in rt
-        .deref()
-        .borrow()
-        .global_memory
-        .heap
-        .data
-        .iter()
-        .enumerate()
+    // invokes a static method of a class, initializing the class first if needed. The method is
+    // either given by its name, or by its name and descriptor like max(II)I if the name alone is
+    // ambiguous. An exception thrown by the method is turned into an error.
+    pub fn call_static(
+        &mut self,
+        class_name: &str,
+        method: &str,
+        arguments: &[JavaValue],
+    ) -> Result<JavaValue, Box<dyn Error>> {
+        self.ensure_initialized()?;
+        self.global_memory.ensure_class(class_name)?;
+
+        let parsed_class = self
+            .global_memory
+            .method_area
+            .classes
+            .get(class_name)
+            .ok_or(format!("Class not found {} :(", class_name))?
+            .as_instance_klass()
+            .ok_or(format!("{class_name} is not a class"))?
+            .parsed_class
+            .clone()
+            .ok_or("no parsed_class")?;
+        let candidates: Vec<&Method> = parsed_class
+            .methods
+            .iter()
+            .map(|candidate| candidate.as_ref())
+            .filter(|candidate| {
+                candidate.access.r#static
+                    && (candidate.name == method
+                        || format!("{}{}", candidate.name, candidate.descriptor.descriptor())
+                            == method)
+            })
+            .collect();
+        let found = match candidates.as_slice() {
+            [found] => *found,
+            [] => return Err(format!("no static method {method} in {class_name}").into()),
+            _ => {
+                return Err(format!(
+                    "method name {method} is ambiguous, use one of: {}",
+                    candidates
+                        .iter()
+                        .map(|candidate| format!(
+                            "{}{}",
+                            candidate.name,
+                            candidate.descriptor.descriptor()
+                        ))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                )
+                .into())
+            }
+        };
+
+        let descriptor = found.descriptor.clone();
+        if arguments.len() != descriptor.parameter_descriptors.len() {
+            return Err(format!(
+                "{}{} takes {} arguments, got {}",
+                found.name,
+                descriptor.descriptor(),
+                descriptor.parameter_descriptors.len(),
+                arguments.len()
+            )
+            .into());
+        }
+        let mut slots = vec![];
+        for (argument, field_type) in arguments
+            .iter()
+            .zip(descriptor.parameter_descriptors.iter())
+        {
+            slots.extend(argument.slots(field_type)?);
+        }
+
+        // invoke_method hands a thrown exception to the frame below, which is ours
+        let stack_depth = self.main_thread.thread_memory.jvm_stack.len();
+        self.main_thread
+            .thread_memory
+            .jvm_stack
+            .push(Frame::new_stub()?);
+        let result = self.main_thread.invoke_method(
+            &mut self.global_memory,
+            class_name.to_owned(),
+            found.name.clone(),
+            descriptor.clone(),
+            slots,
+        );
+        let stub = self.main_thread.thread_memory.jvm_stack.pop();
+        self.main_thread
+            .thread_memory
+            .jvm_stack
+            .truncate(stack_depth);
+        let return_slots = result?;
+
+        if self.main_thread.is_throwing {
+            self.main_thread.is_throwing = false;
+            let exception_ref = *stub
+                .ok_or("no stub on jvm stack")?
+                .operand_stack
+                .last()
+                .ok_or("no exception on the operand_stack")?;
+            return Err(describe_exception(&self.global_memory, exception_ref)?.into());
+        }
+        match &descriptor.return_descriptor {
+            crate::parse::ReturnDescriptor::VoidDescriptor => Ok(JavaValue::Void),
+            crate::parse::ReturnDescriptor::FieldType(field_type) => {
+                JavaValue::from_slots(field_type, &return_slots)
+            }
+        }
+    }
+
+    // creates a java/lang/String
+    pub fn new_string(&mut self, value: &str) -> Result<JavaValue, Box<dyn Error>> {
+        self.ensure_initialized()?;
+        self.global_memory.new_string_value(value)
+    }
+
+    pub fn get_string(&self, value: &JavaValue) -> Result<String, Box<dyn Error>> {
+        self.global_memory.string_value(value)
+    }
+
+    // creates an array of the given type, e.g. [I or [Ljava/lang/String;, holding the elements
+    pub fn new_array(
+        &mut self,
+        array_type: &str,
+        elements: &[JavaValue],
+    ) -> Result<JavaValue, Box<dyn Error>> {
+        self.ensure_initialized()?;
+        self.global_memory.new_array_value(array_type, elements)
+    }
+
+    pub fn array_elements(&self, value: &JavaValue) -> Result<Vec<JavaValue>, Box<dyn Error>> {
+        self.global_memory.array_element_values(value)
+    }
+
+    // supplies the implementation of a native method, e.g. for ("Foo", "bar", "(I)I"). It takes
+    // precedence over a native the vm implements itself.
+    pub fn register_native<F>(
+        &mut self,
+        class_name: &str,
+        method_name: &str,
+        descriptor: &str,
+        function: F,
+    ) where
+        F: Fn(&mut NativeEnv, &[JavaValue]) -> Result<JavaValue, Box<dyn Error>>
+            + Send
+            + Sync
+            + 'static,
     {
-        println!("  idx: {} item: {:?}", idx, heap_item)
+        self.global_memory.natives.insert(
+            (
+                class_name.to_owned(),
+                method_name.to_owned(),
+                descriptor.to_owned(),
+            ),
+            NativeMethod::Host(Arc::new(function)),
+        );
+    }
+
+    pub fn heap_statistics(&self) -> HeapStatistics {
+        self.global_memory.heap.statistics()
+    }
+
+    pub fn gc_statistics(&self) -> GcStatistics {
+        self.global_memory.gc_statistics
+    }
+
+    // every method called so far, the ones that ran the most instructions first
+    pub fn method_statistics(&self) -> Vec<MethodStatistics> {
+        let mut statistics = vec![];
+        for klass in self
+            .global_memory
+            .method_area
+            .classes
+            .values()
+            .filter_map(|class| class.as_instance_klass())
+        {
+            let Some(parsed_class) = klass.parsed_class.as_ref() else {
+                continue;
+            };
+            for (method, counters) in parsed_class
+                .methods
+                .iter()
+                .zip(klass.method_counters.iter())
+            {
+                if counters.calls == 0 {
+                    continue;
+                }
+                statistics.push(MethodStatistics {
+                    class_name: klass.name.to_owned(),
+                    method_name: method.name.to_owned(),
+                    descriptor: method.descriptor.descriptor(),
+                    calls: counters.calls,
+                    instructions: counters.instructions,
+                });
+            }
+        }
+        statistics.sort_by(|a, b| {
+            (b.instructions, b.calls)
+                .cmp(&(a.instructions, a.calls))
+                .then_with(|| (&a.class_name, &a.method_name).cmp(&(&b.class_name, &b.method_name)))
+        });
+        statistics
+    }
+
+    // writes the heap to the file at path in the HPROF format, see hprof, and returns how many
+    // bytes were written
+    pub fn write_heap_dump(&self, path: &str) -> Result<usize, Box<dyn Error>> {
+        hprof::write_heap_dump(&self.main_thread, &self.global_memory, path)
+    }
+
+    // every item on the heap, one per line, followed by the roots the gc would start from
+    pub fn heap_dump(&self) -> String {
+        let statistics = self.heap_statistics();
+        let mut dump = format!(
+            "heap dump: {} slots, {} live objects, {} free slots ({:.1}% fragmentation), {} reused and {} truncated slots\n",
+            statistics.slots,
+            statistics.live_objects,
+            statistics.free_slots,
+            statistics.fragmentation() * 100.0,
+            statistics.reused_slots,
+            statistics.truncated_slots
+        );
+        for (HeapRef(idx), heap_item) in self.global_memory.heap.iter() {
+            dump.push_str(&format!("  idx: {} item: {:?}\n", idx, heap_item));
+        }
+        dump.push_str("roots: \n");
+        for root in gc::roots(&self.main_thread, &self.global_memory) {
+            dump.push_str(&format!(
+                "  idx: {} root: {:?}\n",
+                root.objectref, root.kind
+            ));
+        }
+        dump
     }
-    result.unwrap();
 }
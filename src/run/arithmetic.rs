@@ -0,0 +1,162 @@
+// The integer arithmetic of JVMS §6.5, as done by iadd, isub, imul, idiv, irem, ishl, ishr, iushr
+// and their long counterparts. Results that don't fit wrap around, Integer.MIN_VALUE / -1 giving
+// Integer.MIN_VALUE and Integer.MIN_VALUE % -1 giving 0. Only the low 5 bits of the distance of an
+// int shift count, and the low 6 bits of a long shift, so negative distances and those of 32 or 64
+// and more shift by what is left of them. Division by zero has no result, the instructions throw an
+// ArithmeticException instead.
+
+pub(super) fn iadd(value1: i32, value2: i32) -> i32 {
+    value1.wrapping_add(value2)
+}
+
+pub(super) fn isub(value1: i32, value2: i32) -> i32 {
+    value1.wrapping_sub(value2)
+}
+
+pub(super) fn imul(value1: i32, value2: i32) -> i32 {
+    value1.wrapping_mul(value2)
+}
+
+// rounds towards zero
+pub(super) fn idiv(value1: i32, value2: i32) -> Option<i32> {
+    (value2 != 0).then(|| value1.wrapping_div(value2))
+}
+
+// has the sign of value1
+pub(super) fn irem(value1: i32, value2: i32) -> Option<i32> {
+    (value2 != 0).then(|| value1.wrapping_rem(value2))
+}
+
+// wrapping_shl and wrapping_shr only look at the low bits of the distance, like java
+pub(super) fn ishl(value: i32, distance: i32) -> i32 {
+    value.wrapping_shl(distance as u32)
+}
+
+// shifts in the sign bit
+pub(super) fn ishr(value: i32, distance: i32) -> i32 {
+    value.wrapping_shr(distance as u32)
+}
+
+// shifts in zeros
+pub(super) fn iushr(value: i32, distance: i32) -> i32 {
+    (value as u32).wrapping_shr(distance as u32) as i32
+}
+
+pub(super) fn ladd(value1: i64, value2: i64) -> i64 {
+    value1.wrapping_add(value2)
+}
+
+pub(super) fn lsub(value1: i64, value2: i64) -> i64 {
+    value1.wrapping_sub(value2)
+}
+
+pub(super) fn lmul(value1: i64, value2: i64) -> i64 {
+    value1.wrapping_mul(value2)
+}
+
+pub(super) fn ldiv(value1: i64, value2: i64) -> Option<i64> {
+    (value2 != 0).then(|| value1.wrapping_div(value2))
+}
+
+pub(super) fn lrem(value1: i64, value2: i64) -> Option<i64> {
+    (value2 != 0).then(|| value1.wrapping_rem(value2))
+}
+
+// the distance is an int, even for longs
+pub(super) fn lshl(value: i64, distance: i32) -> i64 {
+    value.wrapping_shl(distance as u32)
+}
+
+pub(super) fn lshr(value: i64, distance: i32) -> i64 {
+    value.wrapping_shr(distance as u32)
+}
+
+pub(super) fn lushr(value: i64, distance: i32) -> i64 {
+    (value as u64).wrapping_shr(distance as u32) as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn division_overflows_to_min_value() {
+        assert_eq!(idiv(i32::MIN, -1), Some(i32::MIN));
+        assert_eq!(ldiv(i64::MIN, -1), Some(i64::MIN));
+    }
+
+    #[test]
+    fn remainder_of_min_value_by_minus_one_is_zero() {
+        assert_eq!(irem(i32::MIN, -1), Some(0));
+        assert_eq!(lrem(i64::MIN, -1), Some(0));
+    }
+
+    #[test]
+    fn division_rounds_towards_zero() {
+        assert_eq!(idiv(-7, 2), Some(-3));
+        assert_eq!(irem(-7, 2), Some(-1));
+        assert_eq!(irem(7, -2), Some(1));
+        assert_eq!(ldiv(-7, 2), Some(-3));
+        assert_eq!(lrem(-7, 2), Some(-1));
+    }
+
+    #[test]
+    fn division_by_zero_has_no_result() {
+        assert_eq!(idiv(1, 0), None);
+        assert_eq!(irem(1, 0), None);
+        assert_eq!(ldiv(1, 0), None);
+        assert_eq!(lrem(1, 0), None);
+    }
+
+    #[test]
+    fn int_shifts_mask_the_distance_to_5_bits() {
+        assert_eq!(ishl(1, 32), 1);
+        assert_eq!(ishl(1, 33), 2);
+        assert_eq!(ishr(-8, 33), -4);
+        assert_eq!(iushr(-1, 32), -1);
+        assert_eq!(iushr(-1, 60), 0xf);
+    }
+
+    #[test]
+    fn int_shifts_by_negative_distances() {
+        // -1 is 31 in its low 5 bits
+        assert_eq!(ishl(1, -1), i32::MIN);
+        assert_eq!(ishr(i32::MIN, -1), -1);
+        assert_eq!(iushr(i32::MIN, -1), 1);
+    }
+
+    #[test]
+    fn long_shifts_mask_the_distance_to_6_bits() {
+        assert_eq!(lshl(1, 64), 1);
+        assert_eq!(lshl(1, 65), 2);
+        // 32 is a distance of its own for longs
+        assert_eq!(lshl(1, 32), 1 << 32);
+        assert_eq!(lshr(-8, 65), -4);
+        assert_eq!(lushr(-1, 64), -1);
+        assert_eq!(lushr(-1, 124), 0xf);
+    }
+
+    #[test]
+    fn long_shifts_by_negative_distances() {
+        // -1 is 63 in its low 6 bits
+        assert_eq!(lshl(1, -1), i64::MIN);
+        assert_eq!(lshr(i64::MIN, -1), -1);
+        assert_eq!(lushr(i64::MIN, -1), 1);
+    }
+
+    #[test]
+    fn int_arithmetic_wraps() {
+        assert_eq!(iadd(i32::MAX, 1), i32::MIN);
+        assert_eq!(isub(i32::MIN, 1), i32::MAX);
+        assert_eq!(imul(i32::MAX, 2), -2);
+        assert_eq!(imul(0x10000, 0x10000), 0);
+    }
+
+    #[test]
+    fn long_arithmetic_wraps() {
+        assert_eq!(ladd(i64::MAX, 1), i64::MIN);
+        assert_eq!(lsub(i64::MIN, 1), i64::MAX);
+        assert_eq!(lmul(i64::MAX, 2), -2);
+        assert_eq!(lmul(1 << 32, 1 << 32), 0);
+    }
+}
@@ -0,0 +1,42 @@
+// The narrowing conversions of JVMS §2.11.4, as done by i2b, i2c, i2s, l2i, f2i, f2l, d2i, d2l
+// and d2f. Integers are narrowed by keeping their low bits, which are then sign extended back to
+// an int for bytes and shorts and zero extended for chars, as chars are unsigned. Floating point
+// values are rounded towards zero when converted to integers, NaN becoming 0 and values too large
+// or too small becoming the largest or smallest integer, which is also what as does in rust.
+
+pub(super) fn i2b(value: i32) -> i32 {
+    value as i8 as i32
+}
+
+pub(super) fn i2c(value: i32) -> i32 {
+    value as u16 as i32
+}
+
+pub(super) fn i2s(value: i32) -> i32 {
+    value as i16 as i32
+}
+
+pub(super) fn l2i(value: i64) -> i32 {
+    value as i32
+}
+
+pub(super) fn f2i(value: f32) -> i32 {
+    value as i32
+}
+
+pub(super) fn f2l(value: f32) -> i64 {
+    value as i64
+}
+
+pub(super) fn d2i(value: f64) -> i32 {
+    value as i32
+}
+
+pub(super) fn d2l(value: f64) -> i64 {
+    value as i64
+}
+
+// rounds to the nearest float, which may be an infinity or zero
+pub(super) fn d2f(value: f64) -> f32 {
+    value as f32
+}
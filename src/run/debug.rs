@@ -0,0 +1,336 @@
+// The prompt of matebabe debug, which stops the vm before instructions to look at the frame it is
+// in. It stops before the first instruction of the main class, after a number of steps, and when a
+// method with a breakpoint is about to run its first instruction, which includes jumps back to
+// it. Like jdwp, it runs on the thread that stopped, holding the vm lock, so the other threads
+// stop as well.
+//
+// Slots are plain 32 bit values, so the operand stack is printed as numbers; locals are printed
+// with their name and type when the method has a LocalVariableTable, see javac -g.
+
+use std::{
+    error::Error,
+    io::{self, BufRead, Write},
+};
+
+use crate::{
+    disasm::{describe_constant, instruction_text},
+    instruction::Operands,
+    parse::LocalVariableTableEntry,
+};
+
+use super::{slots, string_from_java_string, threads, Frame, GlobalMemory, HeapRef, Thread};
+
+const HELP: &str = "\
+step [n]                  run n instructions, 1 by default (s)
+continue                  run until the next breakpoint (c)
+break [class.method]      stop when a method is entered, list the breakpoints without one (b)
+delete [class.method]     remove a breakpoint, or all of them (d)
+stack                     print the operand stack
+locals                    print the local variables
+cp [index]                print a constant pool entry, the one of the instruction by default
+where                     print the frames of the thread (w)
+quit                      exit the vm (q)";
+
+#[derive(Debug)]
+pub(super) struct DebugPrompt {
+    // how many instructions are left to run before stopping, 0 while continuing
+    steps_left: usize,
+    // the class names with slashes and the method names, optionally followed by the descriptor
+    breakpoints: Vec<(String, String)>,
+}
+
+impl DebugPrompt {
+    // stops before the next instruction
+    pub(super) fn new() -> DebugPrompt {
+        DebugPrompt {
+            steps_left: 1,
+            breakpoints: vec![],
+        }
+    }
+}
+
+// parses Main.main, java.lang.String.hashCode or Main.add(II)I into the class and method
+fn parse_breakpoint(argument: &str) -> Option<(String, String)> {
+    let (class_and_method, descriptor) = match argument.find('(') {
+        Some(index) => argument.split_at(index),
+        None => (argument, ""),
+    };
+    let (class_name, method) = class_and_method.rsplit_once('.')?;
+    Some((
+        class_name.replace('.', "/"),
+        format!("{method}{descriptor}"),
+    ))
+}
+
+fn is_breakpoint(prompt: &DebugPrompt, frame: &Frame) -> bool {
+    let Some(method) = frame.method.as_ref() else {
+        return false;
+    };
+    prompt.breakpoints.iter().any(|(class_name, name)| {
+        *class_name == frame.class_name()
+            && (*name == method.name
+                || *name == format!("{}{}", method.name, method.descriptor.descriptor()))
+    })
+}
+
+// called before a thread runs an instruction, stopping there if it is time to
+pub(super) fn before_instruction(
+    thread: &Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let Some(prompt) = global_memory.debug_prompt.as_mut() else {
+        return Ok(());
+    };
+    let Some(frame) = thread.thread_memory.jvm_stack.last() else {
+        return Ok(());
+    };
+    if frame.code.is_none() {
+        return Ok(());
+    }
+    let stepped = match prompt.steps_left {
+        0 => false,
+        steps_left => {
+            prompt.steps_left = steps_left - 1;
+            prompt.steps_left == 0
+        }
+    };
+    let hit_breakpoint = frame.instruction_counter == 0 && is_breakpoint(prompt, frame);
+    if !stepped && !hit_breakpoint {
+        return Ok(());
+    }
+    prompt.steps_left = 0;
+    if hit_breakpoint {
+        println!("Breakpoint hit");
+    }
+    print_location(thread, global_memory, frame);
+    run_prompt(thread, global_memory)
+}
+
+fn print_location(thread: &Thread, global_memory: &GlobalMemory, frame: &Frame) {
+    let Some(method) = frame.method.as_ref() else {
+        return;
+    };
+    let pc = frame.instruction_counter;
+    let line = match method.line_number(pc) {
+        Some(line) => format!(", line {line}"),
+        None => String::new(),
+    };
+    let thread_name = if global_memory.threads.len() > 1 {
+        format!("[thread {}] ", thread.id)
+    } else {
+        String::new()
+    };
+    println!(
+        "{thread_name}{}.{}{} pc {pc}{line}: {}",
+        frame.class_name(),
+        method.name,
+        method.descriptor.descriptor(),
+        current_instruction(global_memory, frame)
+    );
+}
+
+fn current_instruction(global_memory: &GlobalMemory, frame: &Frame) -> String {
+    let Some(code) = frame.code.as_ref() else {
+        return "native".to_owned();
+    };
+    let Some(instruction) = code.instruction_at(frame.instruction_counter) else {
+        return format!("no instruction at pc {}", frame.instruction_counter);
+    };
+    match global_memory.method_area.parsed_class(frame.class_name()) {
+        Ok(class) => instruction_text(&class, instruction),
+        // e.g. the classes spun for lambdas
+        Err(_) => instruction.mnemonic().to_owned(),
+    }
+}
+
+// reads commands until one of them lets the vm go on. At the end of the input, the vm runs to
+// completion.
+fn run_prompt(thread: &Thread, global_memory: &mut GlobalMemory) -> Result<(), Box<dyn Error>> {
+    let stdin = io::stdin();
+    let mut line = String::new();
+    loop {
+        print!("(matebabe) ");
+        io::stdout().flush()?;
+        line.clear();
+        if stdin.lock().read_line(&mut line)? == 0 {
+            println!();
+            global_memory.debug_prompt = None;
+            return Ok(());
+        }
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            continue;
+        };
+        let argument = words.next();
+        let frame = thread
+            .thread_memory
+            .jvm_stack
+            .last()
+            .ok_or("no frame to debug")?;
+        let prompt = global_memory
+            .debug_prompt
+            .as_mut()
+            .ok_or("no debug prompt")?;
+        match command {
+            "step" | "s" => {
+                let Ok(steps) = argument.map_or(Ok(1), str::parse::<usize>) else {
+                    println!("not a number of steps: {}", argument.unwrap_or_default());
+                    continue;
+                };
+                prompt.steps_left = steps.max(1);
+                return Ok(());
+            }
+            "continue" | "c" => return Ok(()),
+            "break" | "b" => match argument {
+                None => {
+                    for (class_name, method) in prompt.breakpoints.iter() {
+                        println!("{class_name}.{method}");
+                    }
+                }
+                Some(argument) => match parse_breakpoint(argument) {
+                    Some(breakpoint) => prompt.breakpoints.push(breakpoint),
+                    None => println!("expected class.method, e.g. Main.main"),
+                },
+            },
+            "delete" | "d" => match argument {
+                None => prompt.breakpoints.clear(),
+                Some(argument) => {
+                    let breakpoint = parse_breakpoint(argument);
+                    let count = prompt.breakpoints.len();
+                    prompt
+                        .breakpoints
+                        .retain(|candidate| Some(candidate) != breakpoint.as_ref());
+                    if prompt.breakpoints.len() == count {
+                        println!("no breakpoint {argument}");
+                    }
+                }
+            },
+            "stack" => {
+                if frame.operand_stack.is_empty() {
+                    println!("empty");
+                }
+                // the top of the stack first
+                for (index, value) in frame.operand_stack.iter().enumerate().rev() {
+                    println!("{index:>4}: {} ({value:#010x})", *value as i32);
+                }
+            }
+            "locals" => print_locals(global_memory, frame),
+            "cp" => print_constant(frame, argument),
+            "where" | "w" => {
+                for frame in thread.thread_memory.jvm_stack.iter().rev() {
+                    let Some(method) = frame.method.as_ref() else {
+                        continue;
+                    };
+                    let line = match method.line_number(frame.instruction_counter) {
+                        Some(line) => format!(", line {line}"),
+                        None => String::new(),
+                    };
+                    println!(
+                        "  {}.{}{} pc {}{line}",
+                        frame.class_name(),
+                        method.name,
+                        method.descriptor.descriptor(),
+                        frame.instruction_counter
+                    );
+                }
+            }
+            "quit" | "q" => {
+                global_memory.debug_prompt = None;
+                global_memory.exit_status = Some(1);
+                threads::changed(global_memory);
+                return Ok(());
+            }
+            "help" | "h" => println!("{HELP}"),
+            _ => println!("unknown command {command}, see help"),
+        }
+    }
+}
+
+fn print_locals(global_memory: &GlobalMemory, frame: &Frame) {
+    let pc = frame.instruction_counter;
+    let variables: Vec<&LocalVariableTableEntry> = match frame.method.as_ref() {
+        Some(method) => method
+            .local_variable_table()
+            .filter(|entry| entry.start_pc <= pc && pc < entry.start_pc + entry.length)
+            .collect(),
+        None => vec![],
+    };
+    let mut index = 0;
+    while index < frame.local_variables.len() {
+        let value = frame.local_variables[index];
+        let Some(variable) = variables.iter().find(|variable| variable.index == index) else {
+            println!("{index:>4}: {} ({value:#010x})", value as i32);
+            index += 1;
+            continue;
+        };
+        let low = frame.local_variables.get(index + 1).copied().unwrap_or(0);
+        let text = match variable.descriptor.as_bytes().first() {
+            Some(b'J') => format!("{}", slots::long_from_slots(value, low)),
+            Some(b'D') => format!("{}", slots::double_from_slots(value, low)),
+            Some(b'F') => format!("{}", f32::from_bits(value)),
+            Some(b'Z') => format!("{}", value != 0),
+            Some(b'C') => format!("{:?}", char::from_u32(value).unwrap_or('?')),
+            Some(b'L' | b'[') => describe_reference(global_memory, value),
+            _ => format!("{}", value as i32),
+        };
+        println!(
+            "{index:>4}: {} {} = {text}",
+            variable.descriptor, variable.name
+        );
+        index += match variable.descriptor.as_str() {
+            "J" | "D" => 2,
+            _ => 1,
+        };
+    }
+}
+
+fn describe_reference(global_memory: &GlobalMemory, objectref: u32) -> String {
+    if objectref == 0 {
+        return "null".to_owned();
+    }
+    let Some(descriptor) = global_memory.heap.field_descriptor(HeapRef(objectref)) else {
+        return format!("@{objectref} (not on the heap)");
+    };
+    if descriptor == "Ljava/lang/String;" {
+        if let Ok(string) = string_from_java_string(global_memory, HeapRef(objectref)) {
+            return format!("{string:?}");
+        }
+    }
+    format!("@{objectref} {descriptor}")
+}
+
+fn print_constant(frame: &Frame, argument: Option<&str>) {
+    let index = match argument {
+        Some(argument) => match argument.trim_start_matches('#').parse::<usize>() {
+            Ok(index) => index,
+            Err(_) => {
+                println!("not a constant pool index: {argument}");
+                return;
+            }
+        },
+        None => {
+            let instruction = frame
+                .code
+                .as_ref()
+                .and_then(|code| code.instruction_at(frame.instruction_counter));
+            match instruction.map(|instruction| &instruction.operands) {
+                Some(
+                    Operands::ConstantPoolIndex(index)
+                    | Operands::InvokeInterface { index, .. }
+                    | Operands::MultiANewArray { index, .. },
+                ) => *index as usize,
+                _ => {
+                    println!("the instruction doesn't refer to the constant pool");
+                    return;
+                }
+            }
+        }
+    };
+    match index
+        .checked_sub(1)
+        .and_then(|index| frame.constant_pool.pool().get(index))
+    {
+        Some(constant) => println!("#{index} = {}", describe_constant(constant)),
+        None => println!("no constant #{index}"),
+    }
+}
@@ -0,0 +1,692 @@
+// The garbage collectors of the heap. Once enough was allocated since the last collection, the
+// outermost interpreter loop collects before it executes its next instruction. There are two
+// collectors to choose from:
+//
+// - mark-and-sweep, the default, marks everything reachable from the roots and sweeps the rest by
+//   replacing it with a free item, which releases its data. Objects stay where they are; the free
+//   ones and their references are handed out again by Heap::store, lowest first.
+// - copying evacuates everything reachable from the roots into a new space, breadth first like
+//   Cheney's algorithm, and drops the old one. The objects end up side by side, in the order
+//   they are reachable in.
+//
+// References are handles into a table of where the objects are, which is what allows the
+// copying collector to move them: only the table entry is updated, it forwards the reference to
+// the new place. Freed references at the end of the table are dropped by both collectors.
+//
+// The roots are the references in the local variables and operand stacks of the frames of the
+// thread, the thread object, the static fields and mirrors of all classes, the interned strings,
+// the global references of JNI natives and the pending references together with the threads
+// processing them. Which slots of a frame hold references is taken from the type states the
+// verifier computes for its method when its class is linked; only the frames of stubs, and of methods that can't be
+// verified, are scanned conservatively, taking every slot that looks like a reference to an object
+// on the heap for one. References only held by the host between calls into the vm, like the
+// JavaValues returned by Vm::call_static, are no roots.
+//
+// The referent of an active weak or phantom reference, one of a subclass of
+// java/lang/ref/WeakReference or PhantomReference that isn't enqueued yet, isn't traced. Once
+// everything reachable is found, the references whose referent wasn't are cleared and put on the
+// pending list, from where the reference handler enqueues them, see
+// Thread::process_pending_references. Soft references are to be cleared before the vm runs out of
+// memory, which it never does, so their referents are traced like any other field, as are those
+// of the FinalReferences of finalizers, which the vm never registers.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    time::{Duration, Instant},
+};
+
+use crate::{parse::FieldType, verify::ReferenceSlots};
+
+use super::{
+    get_field_value,
+    logging::{self, log, LogLevel, LogTarget},
+    set_field_value, threads, Frame, GlobalMemory, Heap, HeapItem, HeapRef, Klass, KlassField,
+    Thread,
+};
+
+// selected with -Xgc, see the top of this file
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Collector {
+    #[default]
+    MarkSweep,
+    Copying,
+}
+
+impl Collector {
+    // as passed to -Xgc
+    fn name(&self) -> &'static str {
+        match self {
+            Collector::MarkSweep => "mark-sweep",
+            Collector::Copying => "copying",
+        }
+    }
+}
+
+// the totals over all collections, see Vm::gc_statistics
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct GcStatistics {
+    pub collections: usize,
+    pub duration: Duration,
+    pub freed_objects: usize,
+    pub freed_bytes: usize,
+}
+
+// the size of an object as the gc accounts for it: a header of two words, four bytes for every
+// slot of data and the bytes of the elements of arrays of primitives
+const HEADER_SIZE: usize = 8;
+
+fn object_size(heap_item: &HeapItem) -> usize {
+    HEADER_SIZE + 4 * heap_item.data.len() + heap_item.bytes.len()
+}
+
+// the size of all objects on the heap, including the unreachable ones not collected yet
+pub(super) fn heap_bytes(heap: &Heap) -> usize {
+    heap.iter()
+        .map(|(_, heap_item)| object_size(heap_item))
+        .sum()
+}
+
+// the field_descriptor of the objects left behind by the mark-and-sweep collector
+const FREE: &str = "free";
+
+// the heap is collected once as many objects were allocated since the last collection as survived
+// it, but not before this many
+const MINIMUM_ALLOCATIONS_BETWEEN_COLLECTIONS: usize = 1 << 16;
+
+pub(super) fn initial_collection_threshold() -> usize {
+    MINIMUM_ALLOCATIONS_BETWEEN_COLLECTIONS
+}
+
+// whether the interpreter should collect at its next safepoint
+pub(super) fn collection_due(global_memory: &GlobalMemory) -> bool {
+    (global_memory.heap.collection_requested
+        || global_memory.heap.allocated_since_collection >= global_memory.heap.collection_threshold)
+        && threads::can_collect(global_memory)
+}
+
+// where a root was found
+#[derive(Debug, Clone, PartialEq)]
+pub(super) enum RootKind {
+    // a slot of the frame at that depth of the stack, the bottom frame being at 0
+    LocalVariable {
+        frame: usize,
+        index: usize,
+    },
+    OperandStack {
+        frame: usize,
+        index: usize,
+    },
+    StaticField {
+        class_name: String,
+        field_name: String,
+    },
+    ClassMirror {
+        class_name: String,
+    },
+    InternedString,
+    ThreadObject,
+    JniGlobalReference,
+    // the first reference of the pending list, see the top of this file
+    ReferencePendingList,
+    // the thread objects of the reference handler and the CleanerImpls of java/lang/ref/Cleaner,
+    // whose threads the vm runs itself, see thread_start0
+    ReferenceThread,
+    // a slot of a frame or the thread object of a thread other than the collecting one, see
+    // threads
+    OtherThread,
+}
+
+#[derive(Debug, Clone)]
+pub(super) struct Root {
+    pub(super) kind: RootKind,
+    pub(super) objectref: u32,
+}
+
+fn is_reference_type(field_type: &FieldType) -> bool {
+    matches!(
+        field_type,
+        FieldType::ClassInstance(_) | FieldType::Array(_)
+    )
+}
+
+// the fields holding references together with their values, laid out like allocate_klass does
+fn reference_fields<'a>(fields: &'a [KlassField], values: &[u32]) -> Vec<(&'a KlassField, u32)> {
+    let mut references = vec![];
+    let mut offset = 0;
+    for field in fields.iter() {
+        if is_reference_type(&field.field_type) {
+            if let Some(value) = values.get(offset) {
+                references.push((field, *value));
+            }
+        }
+        offset += field.field_width;
+    }
+    references
+}
+
+fn looks_like_reference(global_memory: &GlobalMemory, value: u32) -> bool {
+    global_memory.heap.get(HeapRef(value)).is_some()
+}
+
+// which slots of the frame hold references, or None if that isn't known
+fn frame_reference_slots(
+    global_memory: &GlobalMemory,
+    frame: &Frame,
+    is_top: bool,
+    is_throwing: bool,
+) -> Option<ReferenceSlots> {
+    // stubs hold whatever the method they called returned
+    let method = frame.method.as_ref()?;
+    if frame.code.is_none() {
+        // natives only have their arguments
+        let mut locals = vec![];
+        if !method.access.r#static {
+            locals.push(true);
+        }
+        for parameter in method.descriptor.parameter_descriptors.iter() {
+            for _ in 0..parameter.width() {
+                locals.push(is_reference_type(parameter));
+            }
+        }
+        locals.resize(frame.local_variables.len(), false);
+        return Some(ReferenceSlots {
+            locals,
+            stack: vec![false; frame.operand_stack.len()],
+        });
+    }
+
+    // computed when the class was linked
+    let slots = global_memory
+        .method_area
+        .classes
+        .by_id(frame.class_id)?
+        .as_instance_klass()?
+        .reference_slots
+        .get(frame.method_index)?
+        .as_ref()?;
+    // the top frame is about to execute the instruction at its instruction_counter. The others
+    // are in the middle of the instruction before it, an invoke which already popped its
+    // arguments, so their operand stack is what remains of the one before that instruction. The
+    // same goes for the top frame if it is throwing, the exception being pushed on top.
+    let stack_length = frame
+        .operand_stack
+        .len()
+        .checked_sub(is_throwing as usize)?;
+    let before = if is_top && !is_throwing {
+        slots.get(&frame.instruction_counter)?
+    } else {
+        slots.range(..frame.instruction_counter).next_back()?.1
+    };
+    if before.stack.len() < stack_length || before.locals.len() != frame.local_variables.len() {
+        return None;
+    }
+    let mut stack = before.stack[..stack_length].to_vec();
+    if is_throwing {
+        stack.push(true);
+    }
+    Some(ReferenceSlots {
+        locals: before.locals.clone(),
+        stack,
+    })
+}
+
+fn frame_roots(
+    global_memory: &GlobalMemory,
+    depth: usize,
+    frame: &Frame,
+    is_top: bool,
+    is_throwing: bool,
+) -> Vec<Root> {
+    let slots =
+        frame_reference_slots(global_memory, frame, is_top, is_throwing).unwrap_or_else(|| {
+            ReferenceSlots {
+                locals: frame
+                    .local_variables
+                    .iter()
+                    .map(|value| looks_like_reference(global_memory, *value))
+                    .collect(),
+                stack: frame
+                    .operand_stack
+                    .iter()
+                    .map(|value| looks_like_reference(global_memory, *value))
+                    .collect(),
+            }
+        });
+    let mut roots = vec![];
+    for (index, (value, is_reference)) in frame
+        .local_variables
+        .iter()
+        .zip(slots.locals.iter())
+        .enumerate()
+    {
+        if *is_reference {
+            roots.push(Root {
+                kind: RootKind::LocalVariable {
+                    frame: depth,
+                    index,
+                },
+                objectref: *value,
+            });
+        }
+    }
+    for (index, (value, is_reference)) in frame
+        .operand_stack
+        .iter()
+        .zip(slots.stack.iter())
+        .enumerate()
+    {
+        if *is_reference {
+            roots.push(Root {
+                kind: RootKind::OperandStack {
+                    frame: depth,
+                    index,
+                },
+                objectref: *value,
+            });
+        }
+    }
+    roots
+}
+
+fn class_roots(klass: &dyn Klass) -> Vec<Root> {
+    let mut roots = vec![];
+    if let Some(objectref) = klass.get_java_clone() {
+        roots.push(Root {
+            kind: RootKind::ClassMirror {
+                class_name: klass.get_name().to_owned(),
+            },
+            objectref: objectref.0,
+        });
+    }
+    if let Some(instance_klass) = klass.as_instance_klass() {
+        if let (Some(static_fields), Some(static_field_values)) = (
+            instance_klass.static_fields.as_ref(),
+            instance_klass.static_field_values.as_ref(),
+        ) {
+            for (field, objectref) in reference_fields(static_fields, static_field_values) {
+                roots.push(Root {
+                    kind: RootKind::StaticField {
+                        class_name: field.class_name.to_owned(),
+                        field_name: field.field_name.to_owned(),
+                    },
+                    objectref,
+                });
+            }
+        }
+    }
+    roots
+}
+
+// every reference the thread and the vm hold on to, apart from null, in no particular order
+pub(super) fn roots<'a>(
+    thread: &'a Thread,
+    global_memory: &'a GlobalMemory,
+) -> impl Iterator<Item = Root> + 'a {
+    let frame_count = thread.thread_memory.jvm_stack.len();
+    let frames =
+        thread
+            .thread_memory
+            .jvm_stack
+            .iter()
+            .enumerate()
+            .flat_map(move |(depth, frame)| {
+                let is_top = depth + 1 == frame_count;
+                frame_roots(
+                    global_memory,
+                    depth,
+                    frame,
+                    is_top,
+                    is_top && thread.is_throwing,
+                )
+            });
+    let thread_object = thread.java_clone.into_iter().map(|objectref| Root {
+        kind: RootKind::ThreadObject,
+        objectref: objectref.0,
+    });
+    let classes = global_memory
+        .method_area
+        .classes
+        .values()
+        .flat_map(|klass| class_roots(klass.as_ref()));
+    let interned_strings = global_memory
+        .interned_strings
+        .values()
+        .map(|objectref| Root {
+            kind: RootKind::InternedString,
+            objectref: objectref.0,
+        });
+    let jni_global_references = global_memory
+        .jni_global_references
+        .iter()
+        .map(|objectref| Root {
+            kind: RootKind::JniGlobalReference,
+            objectref: objectref.0,
+        });
+    let reference_pending_list = std::iter::once(Root {
+        kind: RootKind::ReferencePendingList,
+        objectref: global_memory.reference_pending_list.0,
+    });
+    let reference_threads = global_memory
+        .reference_handler
+        .iter()
+        .chain(global_memory.cleaners.iter())
+        .map(|objectref| Root {
+            kind: RootKind::ReferenceThread,
+            objectref: *objectref,
+        });
+    // the other threads are suspended, or haven't run yet
+    let other_threads = threads::other_threads(global_memory)
+        .flat_map(move |thread| {
+            let frame_count = thread.thread_memory.jvm_stack.len();
+            thread
+                .thread_memory
+                .jvm_stack
+                .iter()
+                .enumerate()
+                .flat_map(move |(depth, frame)| {
+                    let is_top = depth + 1 == frame_count;
+                    frame_roots(
+                        global_memory,
+                        depth,
+                        frame,
+                        is_top,
+                        is_top && thread.is_throwing,
+                    )
+                })
+                .chain(thread.java_clone.map(|objectref| Root {
+                    kind: RootKind::ThreadObject,
+                    objectref: objectref.0,
+                }))
+        })
+        .chain(global_memory.threads.values().map(|objectref| Root {
+            kind: RootKind::ThreadObject,
+            objectref: objectref.0,
+        }))
+        .map(|root| Root {
+            kind: RootKind::OtherThread,
+            objectref: root.objectref,
+        });
+    frames
+        .chain(thread_object)
+        .chain(other_threads)
+        .chain(classes)
+        .chain(interned_strings)
+        .chain(jni_global_references)
+        .chain(reference_pending_list)
+        .chain(reference_threads)
+        .filter(|root| root.objectref != 0)
+}
+
+// the weak and phantom references found while tracing, see the top of this file
+#[derive(Default)]
+struct Discovery {
+    // whether the referents of the instances of a class are left untraced
+    is_weak_class: HashMap<String, bool>,
+    discovered: Vec<u32>,
+}
+
+impl Discovery {
+    fn is_weak_class(&mut self, global_memory: &GlobalMemory, class_name: &str) -> bool {
+        if let Some(is_weak) = self.is_weak_class.get(class_name) {
+            return *is_weak;
+        }
+        let method_area = &global_memory.method_area;
+        let is_weak = method_area.is_subclass_of(class_name, "java/lang/ref/WeakReference")
+            || method_area.is_subclass_of(class_name, "java/lang/ref/PhantomReference");
+        self.is_weak_class.insert(class_name.to_owned(), is_weak);
+        is_weak
+    }
+}
+
+// the references held by an object or array. The referent of an active weak or phantom reference
+// is left out, the reference being discovered instead.
+fn references(
+    global_memory: &GlobalMemory,
+    objectref: u32,
+    heap_item: &HeapItem,
+    discovery: &mut Discovery,
+) -> Result<Vec<u32>, Box<dyn Error>> {
+    let mut references = vec![];
+    let descriptor = global_memory.heap.descriptor(heap_item);
+    if descriptor.starts_with("[L") || descriptor.starts_with("[[") {
+        references.extend(heap_item.data.iter());
+    } else if descriptor.starts_with('L') {
+        let class_name = &descriptor[1..descriptor.len() - 1];
+        let fields = global_memory
+            .method_area
+            .classes
+            .get(class_name)
+            .ok_or(format!("class {class_name} of object on heap not found"))?
+            .as_instance_klass()
+            .ok_or("not an InstanceKlass")?
+            .fields
+            .as_ref()
+            .ok_or(format!("class {class_name} of object on heap not linked"))?;
+        let fields = reference_fields(fields, &heap_item.data);
+        // a reference that has been enqueued already has a next one in its queue
+        let is_discovered = discovery.is_weak_class(global_memory, class_name)
+            && fields.iter().any(|(field, value)| {
+                field.class_name == "java/lang/ref/Reference"
+                    && field.field_name == "next"
+                    && *value == 0
+            });
+        for (field, value) in fields {
+            if is_discovered
+                && field.class_name == "java/lang/ref/Reference"
+                && field.field_name == "referent"
+            {
+                if value != 0 {
+                    discovery.discovered.push(objectref);
+                }
+            } else {
+                references.push(value);
+            }
+        }
+    }
+    // arrays of primitives hold no references
+    Ok(references)
+}
+
+// free is the id of FREE
+fn free_item(free: u32) -> HeapItem {
+    HeapItem {
+        descriptor_id: free,
+        identity_hash: 0,
+        data: vec![],
+        bytes: vec![],
+    }
+}
+
+// frees every object whose reference isn't marked
+fn mark_and_sweep(
+    global_memory: &mut GlobalMemory,
+    roots: Vec<u32>,
+    discovery: &mut Discovery,
+) -> Result<(), Box<dyn Error>> {
+    let mut marked = vec![false; global_memory.heap.handles.len()];
+    let mut work_list = roots;
+    while let Some(objectref) = work_list.pop() {
+        if marked.get(objectref as usize) != Some(&false) {
+            continue;
+        }
+        // a slot of a frame that merely looked like a reference is no object
+        if let Some(heap_item) = global_memory.heap.get(HeapRef(objectref)) {
+            marked[objectref as usize] = true;
+            work_list.extend(references(global_memory, objectref, heap_item, discovery)?);
+        }
+    }
+
+    let heap = &mut global_memory.heap;
+    let free = heap.descriptors.intern(FREE);
+    for (objectref, marked) in marked.iter().enumerate() {
+        if *marked {
+            continue;
+        }
+        if let Some(index) = heap.handles[objectref].take() {
+            heap.objects[index] = free_item(free);
+        }
+    }
+    Ok(())
+}
+
+// moves every object reachable from the roots into a new space, in the order they are reached
+fn copy(
+    global_memory: &mut GlobalMemory,
+    roots: Vec<u32>,
+    discovery: &mut Discovery,
+) -> Result<(), Box<dyn Error>> {
+    let free = global_memory.heap.descriptors.intern(FREE);
+    let mut from_space = std::mem::take(&mut global_memory.heap.objects);
+    let mut to_space = vec![];
+    // the reference of every object in to_space
+    let mut to_space_refs = vec![];
+    // the new place of every reference, which doubles as the forwarding address of moved objects
+    let mut forwarded: Vec<Option<usize>> = vec![None; global_memory.heap.handles.len()];
+    let mut scanned = 0;
+    let mut work_list = roots;
+    loop {
+        for objectref in work_list.drain(..) {
+            if forwarded.get(objectref as usize) != Some(&None) {
+                continue;
+            }
+            // a slot of a frame that merely looked like a reference is no object
+            if let Some(index) = global_memory.heap.handles[objectref as usize] {
+                to_space.push(std::mem::replace(&mut from_space[index], free_item(free)));
+                to_space_refs.push(objectref);
+                forwarded[objectref as usize] = Some(to_space.len() - 1);
+            }
+        }
+        // the objects after the scanned ones still have to be looked into
+        let Some(heap_item) = to_space.get(scanned) else {
+            break;
+        };
+        work_list.extend(references(
+            global_memory,
+            to_space_refs[scanned],
+            heap_item,
+            discovery,
+        )?);
+        scanned += 1;
+    }
+
+    let heap = &mut global_memory.heap;
+    heap.objects = to_space;
+    heap.handles = forwarded;
+    Ok(())
+}
+
+// clears the discovered references whose referent was freed and puts them on the pending list,
+// linked by their discovered field
+fn clear_references(
+    global_memory: &mut GlobalMemory,
+    discovered: Vec<u32>,
+) -> Result<(), Box<dyn Error>> {
+    for objectref in discovered {
+        let referent = get_field_value(
+            global_memory,
+            objectref,
+            "java/lang/ref/Reference",
+            "referent",
+        )?;
+        if global_memory.heap.get(HeapRef(referent)).is_some() {
+            continue;
+        }
+        set_field_value(
+            global_memory,
+            objectref,
+            "java/lang/ref/Reference",
+            "referent",
+            0,
+        )?;
+        set_field_value(
+            global_memory,
+            objectref,
+            "java/lang/ref/Reference",
+            "discovered",
+            global_memory.reference_pending_list.0,
+        )?;
+        global_memory.reference_pending_list = HeapRef(objectref);
+        log!(Gc, Trace, "cleared the referent of {objectref}");
+    }
+    Ok(())
+}
+
+// collects the heap and returns how many objects were freed. With --verbose-gc, a line with the
+// numbers of the collection is printed as well.
+pub(super) fn collect(
+    thread: &Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<usize, Box<dyn Error>> {
+    let start = Instant::now();
+    // a collection requested by System.gc can come with the threshold being reached as well
+    let cause = if global_memory.heap.collection_requested {
+        "System.gc()"
+    } else {
+        "allocation threshold"
+    };
+    let live_before = global_memory.heap.statistics().live_objects;
+    let bytes_before = heap_bytes(&global_memory.heap);
+    let mut roots: Vec<u32> = roots(thread, global_memory)
+        .map(|root| root.objectref)
+        .collect();
+    log!(Gc, Debug, "collecting from {} roots", roots.len());
+    // null is no object, but must not be freed either
+    roots.push(0);
+    let mut discovery = Discovery::default();
+    match global_memory.collector {
+        Collector::MarkSweep => mark_and_sweep(global_memory, roots, &mut discovery)?,
+        Collector::Copying => copy(global_memory, roots, &mut discovery)?,
+    }
+    clear_references(global_memory, discovery.discovered)?;
+
+    let heap = &mut global_memory.heap;
+    let free = heap.descriptors.intern(FREE);
+    while heap.handles.last() == Some(&None) {
+        heap.handles.pop();
+        heap.truncated_slots += 1;
+    }
+    while heap
+        .objects
+        .last()
+        .is_some_and(|heap_item| heap_item.descriptor_id == free)
+    {
+        heap.objects.pop();
+    }
+    heap.free_handles = (0..heap.handles.len() as u32)
+        .rev()
+        .filter(|objectref| heap.handles[*objectref as usize].is_none())
+        .collect();
+    heap.free_objects = (0..heap.objects.len())
+        .rev()
+        .filter(|index| heap.objects[*index].descriptor_id == free)
+        .collect();
+
+    let live = heap.statistics().live_objects;
+    let bytes = heap_bytes(heap);
+    heap.committed_bytes = heap.committed_bytes.max(bytes_before);
+    heap.allocated_since_collection = 0;
+    heap.collection_threshold = live.max(MINIMUM_ALLOCATIONS_BETWEEN_COLLECTIONS);
+    heap.collection_requested = false;
+
+    let duration = start.elapsed();
+    let statistics = &mut global_memory.gc_statistics;
+    statistics.collections += 1;
+    statistics.duration += duration;
+    statistics.freed_objects += live_before - live;
+    statistics.freed_bytes += bytes_before - bytes;
+    if global_memory.verbose_gc || logging::enabled(LogTarget::Gc, LogLevel::Info) {
+        // like the lines of -Xlog:gc, but with the number of objects
+        let line = format!(
+            "GC({}) {} ({cause}) {bytes_before}B->{bytes}B, {live_before}->{live} objects {:.3}ms",
+            statistics.collections - 1,
+            global_memory.collector.name(),
+            duration.as_secs_f64() * 1000.0
+        );
+        if global_memory.verbose_gc {
+            println!("[gc] {line}");
+        }
+        log!(Gc, Info, "{line}");
+    }
+    Ok(live_before - live)
+}
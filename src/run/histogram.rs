@@ -0,0 +1,75 @@
+// The opcode histogram of --opcode-histogram: how often each opcode was run, which opcodes were
+// run into that the interpreter doesn't implement, and which ones never ran at all. It is printed
+// when the vm exits, which is also what happens at the first unimplemented opcode.
+
+use std::fmt::Write;
+
+use crate::instruction::mnemonic;
+
+#[derive(Debug)]
+pub(super) struct OpcodeHistogram {
+    // indexed by opcode
+    executed: [u64; 256],
+    unimplemented: [bool; 256],
+}
+
+impl OpcodeHistogram {
+    pub(super) fn new() -> OpcodeHistogram {
+        OpcodeHistogram {
+            executed: [0; 256],
+            unimplemented: [false; 256],
+        }
+    }
+
+    pub(super) fn count(&mut self, opcode: u8) {
+        self.executed[opcode as usize] += 1;
+    }
+
+    pub(super) fn mark_unimplemented(&mut self, opcode: u8) {
+        self.unimplemented[opcode as usize] = true;
+    }
+
+    // the opcodes from the most to the least run, followed by the unimplemented and the missing
+    // ones
+    pub(super) fn summary(&self) -> String {
+        let total: u64 = self.executed.iter().sum();
+        let mut out = String::new();
+        let _ = writeln!(out, "[opcodes] {total} instructions run");
+        let mut executed: Vec<u8> = (0..=255)
+            .filter(|opcode| self.executed[*opcode as usize] > 0)
+            .collect();
+        executed.sort_by_key(|opcode| std::cmp::Reverse(self.executed[*opcode as usize]));
+        for opcode in executed.iter() {
+            let count = self.executed[*opcode as usize];
+            let _ = writeln!(
+                out,
+                "[opcodes] {:<16} {count:>12} {:>6.2}%",
+                mnemonic(*opcode).unwrap_or("invalid opcode"),
+                count as f64 * 100.0 / total as f64
+            );
+        }
+        for opcode in (0..=255).filter(|opcode| self.unimplemented[*opcode as usize]) {
+            let _ = writeln!(
+                out,
+                "[opcodes] unimplemented: {} ({opcode:#04x})",
+                mnemonic(opcode).unwrap_or("invalid opcode")
+            );
+        }
+        let valid: Vec<u8> = (0..=255)
+            .filter(|opcode| mnemonic(*opcode).is_some())
+            .collect();
+        let never_run: Vec<&str> = valid
+            .iter()
+            .filter(|opcode| self.executed[**opcode as usize] == 0)
+            .filter_map(|opcode| mnemonic(*opcode))
+            .collect();
+        let _ = writeln!(
+            out,
+            "[opcodes] {} of {} opcodes ran, never run: {}",
+            valid.len() - never_run.len(),
+            valid.len(),
+            never_run.join(" ")
+        );
+        out
+    }
+}
@@ -0,0 +1,544 @@
+// Heap dumps in the HPROF binary format of the JDK, which tools like Eclipse MAT and VisualVM
+// open. A dump consists of the strings, the loaded classes and the stack trace of the thread as
+// records of their own, followed by a heap dump segment holding the roots, the classes with their
+// static fields and every object and array on the heap.
+//
+// https://github.com/openjdk/jdk/blob/master/src/hotspot/share/services/heapDumper.cpp
+
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::parse::FieldType;
+
+use super::{
+    gc::{roots, RootKind},
+    slots, GlobalMemory, HeapRef, KlassField, Thread,
+};
+
+// the references are handles no bigger than a u32, but 8 byte identifiers are what the tools
+// expect of dumps of a 64 bit vm
+const IDENTIFIER_SIZE: u32 = 8;
+
+// objects are identified by their reference, strings and stack frames get identifiers of their
+// own above those
+const STRING_IDENTIFIERS: u64 = 1 << 32;
+const FRAME_IDENTIFIERS: u64 = 2 << 32;
+
+// the records
+const UTF8: u8 = 0x01;
+const LOAD_CLASS: u8 = 0x02;
+const STACK_FRAME: u8 = 0x04;
+const STACK_TRACE: u8 = 0x05;
+const HEAP_DUMP_SEGMENT: u8 = 0x1c;
+const HEAP_DUMP_END: u8 = 0x2c;
+
+// the sub records of a heap dump segment
+const ROOT_UNKNOWN: u8 = 0xff;
+const ROOT_JNI_GLOBAL: u8 = 0x01;
+const ROOT_JAVA_FRAME: u8 = 0x03;
+const ROOT_STICKY_CLASS: u8 = 0x05;
+const ROOT_THREAD_OBJECT: u8 = 0x08;
+const CLASS_DUMP: u8 = 0x20;
+const INSTANCE_DUMP: u8 = 0x21;
+const OBJECT_ARRAY_DUMP: u8 = 0x22;
+const PRIMITIVE_ARRAY_DUMP: u8 = 0x23;
+
+// the basic types of fields and array elements
+const OBJECT: u8 = 2;
+const BOOLEAN: u8 = 4;
+const CHAR: u8 = 5;
+const FLOAT: u8 = 6;
+const DOUBLE: u8 = 7;
+const BYTE: u8 = 8;
+const SHORT: u8 = 9;
+const INT: u8 = 10;
+const LONG: u8 = 11;
+
+// objects and classes were not allocated anywhere in particular, so they refer to an empty stack
+// trace. The stack trace of the thread comes after it.
+const EMPTY_STACK_TRACE: u32 = 1;
+const THREAD_STACK_TRACE: u32 = 2;
+const THREAD_SERIAL: u32 = 1;
+
+// a segment is written out once it grows beyond this, the length of a record being a u4
+const MAX_SEGMENT_LENGTH: usize = 1 << 30;
+
+fn basic_type(field_type: &FieldType) -> u8 {
+    match field_type {
+        FieldType::Boolean => BOOLEAN,
+        FieldType::Char => CHAR,
+        FieldType::Float => FLOAT,
+        FieldType::Double => DOUBLE,
+        FieldType::Byte => BYTE,
+        FieldType::Short => SHORT,
+        FieldType::Integer => INT,
+        FieldType::LongInteger => LONG,
+        FieldType::ClassInstance(_) | FieldType::Array(_) => OBJECT,
+    }
+}
+
+// the basic type of the elements of a primitive array like [I, None for arrays of references
+fn array_basic_type(field_descriptor: &str) -> Option<u8> {
+    match field_descriptor {
+        "[Z" => Some(BOOLEAN),
+        "[C" => Some(CHAR),
+        "[F" => Some(FLOAT),
+        "[D" => Some(DOUBLE),
+        "[B" => Some(BYTE),
+        "[S" => Some(SHORT),
+        "[I" => Some(INT),
+        "[J" => Some(LONG),
+        _ => None,
+    }
+}
+
+// how many bytes a value of the basic type takes up in the dump
+fn basic_type_size(basic_type: u8) -> usize {
+    match basic_type {
+        OBJECT => IDENTIFIER_SIZE as usize,
+        BOOLEAN | BYTE => 1,
+        CHAR | SHORT => 2,
+        FLOAT | INT => 4,
+        _ => 8,
+    }
+}
+
+// writes the value in the slots, the high word of longs and doubles coming first like on the
+// operand stack
+fn write_value(out: &mut Vec<u8>, basic_type: u8, slots: &[u32]) {
+    let slot = |index: usize| slots.get(index).copied().unwrap_or(0);
+    match basic_type {
+        OBJECT => out.extend_from_slice(&(slot(0) as u64).to_be_bytes()),
+        BOOLEAN | BYTE => out.push(slot(0) as u8),
+        CHAR | SHORT => out.extend_from_slice(&(slot(0) as u16).to_be_bytes()),
+        FLOAT | INT => out.extend_from_slice(&slot(0).to_be_bytes()),
+        _ => out.extend_from_slice(&slots::long_from_slots(slot(0), slot(1)).to_be_bytes()),
+    }
+}
+
+fn write_u1(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+fn write_u2(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_u4(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_id(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+struct HprofWriter {
+    out: BufWriter<File>,
+    // the identifiers of the strings written so far
+    strings: HashMap<String, u64>,
+    // the sub records of the heap dump segment not written out yet
+    segment: Vec<u8>,
+    written: usize,
+}
+
+impl HprofWriter {
+    fn record(&mut self, tag: u8, body: &[u8]) -> Result<(), Box<dyn Error>> {
+        let mut header = vec![];
+        write_u1(&mut header, tag);
+        // microseconds since the time in the file header
+        write_u4(&mut header, 0);
+        write_u4(
+            &mut header,
+            u32::try_from(body.len()).map_err(|_| "hprof record too long")?,
+        );
+        self.out.write_all(&header)?;
+        self.out.write_all(body)?;
+        self.written += header.len() + body.len();
+        Ok(())
+    }
+
+    // the identifier of the string, writing a UTF8 record for it the first time it is used
+    fn string(&mut self, string: &str) -> Result<u64, Box<dyn Error>> {
+        if let Some(id) = self.strings.get(string) {
+            return Ok(*id);
+        }
+        let id = STRING_IDENTIFIERS + self.strings.len() as u64;
+        let mut body = vec![];
+        write_id(&mut body, id);
+        body.extend_from_slice(string.as_bytes());
+        self.record(UTF8, &body)?;
+        self.strings.insert(string.to_owned(), id);
+        Ok(id)
+    }
+
+    // ends a sub record of the heap dump segment
+    fn end_sub_record(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.segment.len() >= MAX_SEGMENT_LENGTH {
+            self.flush_segment()?;
+        }
+        Ok(())
+    }
+
+    fn flush_segment(&mut self) -> Result<(), Box<dyn Error>> {
+        if !self.segment.is_empty() {
+            let segment = std::mem::take(&mut self.segment);
+            self.record(HEAP_DUMP_SEGMENT, &segment)?;
+        }
+        Ok(())
+    }
+}
+
+// the fields in the order of an instance dump: the ones declared by the class of the object
+// first, then those of its super class and so on. allocate_klass lays them out the other way
+// around, so the offsets of the fields are returned as well.
+fn instance_dump_fields(fields: &[KlassField]) -> Vec<(&KlassField, usize)> {
+    let mut declared_by_class: Vec<Vec<(&KlassField, usize)>> = vec![];
+    let mut offset = 0;
+    for field in fields.iter() {
+        match declared_by_class.last_mut() {
+            Some(declared) if declared[0].0.class_name == field.class_name => {
+                declared.push((field, offset))
+            }
+            _ => declared_by_class.push(vec![(field, offset)]),
+        }
+        offset += field.field_width;
+    }
+    declared_by_class.into_iter().rev().flatten().collect()
+}
+
+// whether the object is a java/lang/OutOfMemoryError, or one of a subclass
+fn is_out_of_memory_error(global_memory: &GlobalMemory, objectref: u32) -> bool {
+    global_memory
+        .heap
+        .class_name(HeapRef(objectref))
+        .is_some_and(|class_name| {
+            global_memory
+                .method_area
+                .is_subclass_of(class_name, "java/lang/OutOfMemoryError")
+        })
+}
+
+// writes the dump requested with RunOptions::heap_dump_on_out_of_memory the first time an
+// OutOfMemoryError is thrown, like -XX:+HeapDumpOnOutOfMemoryError does
+pub(super) fn dump_on_out_of_memory(
+    thread: &Thread,
+    global_memory: &mut GlobalMemory,
+    exception_ref: u32,
+) -> Result<(), Box<dyn Error>> {
+    if global_memory.heap_dump_on_out_of_memory.is_none()
+        || !is_out_of_memory_error(global_memory, exception_ref)
+    {
+        return Ok(());
+    }
+    let path = global_memory
+        .heap_dump_on_out_of_memory
+        .take()
+        .ok_or("no heap dump path")?;
+    eprintln!("Dumping heap to {path} ...");
+    let start = Instant::now();
+    let written = write_heap_dump(thread, global_memory, &path)?;
+    eprintln!(
+        "Heap dump file created [{written} bytes in {:.3} secs]",
+        start.elapsed().as_secs_f64()
+    );
+    Ok(())
+}
+
+// writes every object on the heap to the file at path, with the roots as seen from the thread,
+// and returns how many bytes were written
+pub(super) fn write_heap_dump(
+    thread: &Thread,
+    global_memory: &GlobalMemory,
+    path: &str,
+) -> Result<usize, Box<dyn Error>> {
+    let mut writer = HprofWriter {
+        out: BufWriter::new(
+            File::create(path).map_err(|error| format!("failed to create {path}: {error}"))?,
+        ),
+        strings: HashMap::new(),
+        segment: vec![],
+        written: 0,
+    };
+
+    let mut header = b"JAVA PROFILE 1.0.2\0".to_vec();
+    write_u4(&mut header, IDENTIFIER_SIZE);
+    // milliseconds since the epoch, as two u4s that happen to be a u8
+    let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    write_id(&mut header, millis);
+    writer.out.write_all(&header)?;
+    writer.written += header.len();
+
+    // every class that has a mirror, which is what identifies it in the dump. The class names
+    // are sorted to write the same dump for the same heap.
+    let mut class_names: Vec<&str> = global_memory
+        .method_area
+        .classes
+        .iter()
+        .filter(|(_, klass)| klass.get_java_clone().is_some())
+        .map(|(class_name, _)| class_name)
+        .collect();
+    class_names.sort();
+    let mut class_serials = HashMap::new();
+    let mut mirrors = HashMap::new();
+    for (index, class_name) in class_names.iter().enumerate() {
+        let klass = global_memory
+            .method_area
+            .classes
+            .get(class_name)
+            .ok_or("class not found in method area")?;
+        let mirror = klass.get_java_clone().ok_or("class has no mirror")?;
+        let serial = index as u32 + 1;
+        let name_id = writer.string(class_name)?;
+        let mut body = vec![];
+        write_u4(&mut body, serial);
+        write_id(&mut body, mirror.0 as u64);
+        write_u4(&mut body, EMPTY_STACK_TRACE);
+        write_id(&mut body, name_id);
+        writer.record(LOAD_CLASS, &body)?;
+        class_serials.insert(*class_name, serial);
+        mirrors.insert(*class_name, mirror);
+    }
+
+    let mut body = vec![];
+    write_u4(&mut body, EMPTY_STACK_TRACE);
+    write_u4(&mut body, THREAD_SERIAL);
+    write_u4(&mut body, 0);
+    writer.record(STACK_TRACE, &body)?;
+
+    // the frames of the thread from the top down, leaving out the stubs, which aren't running
+    // any method. frame_numbers maps the depth of a frame to its index in the stack trace.
+    let jvm_stack = &thread.thread_memory.jvm_stack;
+    let mut frame_numbers = HashMap::new();
+    let mut frame_ids = vec![];
+    for (depth, frame) in jvm_stack.iter().enumerate().rev() {
+        let method = match frame.method.as_ref() {
+            Some(method) => method,
+            None => continue,
+        };
+        let frame_id = FRAME_IDENTIFIERS + frame_ids.len() as u64;
+        let method_name_id = writer.string(&method.name)?;
+        let descriptor_id = writer.string(&method.descriptor.descriptor())?;
+        let source_file_id = match frame.source_file() {
+            Some(source_file) => writer.string(&source_file)?,
+            None => 0,
+        };
+        // -3 marks native methods, -1 an unknown line
+        let line_number = if frame.code.is_none() {
+            -3
+        } else {
+            frame.line_number().map(|line| line as i32).unwrap_or(-1)
+        };
+        let mut body = vec![];
+        write_id(&mut body, frame_id);
+        write_id(&mut body, method_name_id);
+        write_id(&mut body, descriptor_id);
+        write_id(&mut body, source_file_id);
+        write_u4(
+            &mut body,
+            *class_serials.get(frame.class_name()).unwrap_or(&0),
+        );
+        write_u4(&mut body, line_number as u32);
+        writer.record(STACK_FRAME, &body)?;
+        frame_numbers.insert(depth, frame_ids.len() as u32);
+        frame_ids.push(frame_id);
+    }
+    let mut body = vec![];
+    write_u4(&mut body, THREAD_STACK_TRACE);
+    write_u4(&mut body, THREAD_SERIAL);
+    write_u4(&mut body, frame_ids.len() as u32);
+    for frame_id in frame_ids.iter() {
+        write_id(&mut body, *frame_id);
+    }
+    writer.record(STACK_TRACE, &body)?;
+
+    // the static fields are part of the class dumps, which are roots themselves
+    for root in roots(thread, global_memory) {
+        let segment = &mut writer.segment;
+        match root.kind {
+            RootKind::LocalVariable { frame, .. } | RootKind::OperandStack { frame, .. } => {
+                match frame_numbers.get(&frame) {
+                    Some(frame_number) => {
+                        write_u1(segment, ROOT_JAVA_FRAME);
+                        write_id(segment, root.objectref as u64);
+                        write_u4(segment, THREAD_SERIAL);
+                        write_u4(segment, *frame_number);
+                    }
+                    None => {
+                        write_u1(segment, ROOT_UNKNOWN);
+                        write_id(segment, root.objectref as u64);
+                    }
+                }
+            }
+            RootKind::StaticField { .. } => continue,
+            RootKind::ClassMirror { .. } => {
+                write_u1(segment, ROOT_STICKY_CLASS);
+                write_id(segment, root.objectref as u64);
+            }
+            RootKind::InternedString
+            | RootKind::ReferencePendingList
+            | RootKind::ReferenceThread
+            | RootKind::OtherThread => {
+                write_u1(segment, ROOT_UNKNOWN);
+                write_id(segment, root.objectref as u64);
+            }
+            RootKind::ThreadObject => {
+                write_u1(segment, ROOT_THREAD_OBJECT);
+                write_id(segment, root.objectref as u64);
+                write_u4(segment, THREAD_SERIAL);
+                write_u4(segment, THREAD_STACK_TRACE);
+            }
+            RootKind::JniGlobalReference => {
+                write_u1(segment, ROOT_JNI_GLOBAL);
+                write_id(segment, root.objectref as u64);
+                write_id(segment, 0);
+            }
+        }
+        writer.end_sub_record()?;
+    }
+
+    let object_mirror = mirrors
+        .get("java/lang/Object")
+        .copied()
+        .unwrap_or(HeapRef::NULL);
+    for class_name in class_names.iter() {
+        let klass = global_memory
+            .method_area
+            .classes
+            .get(class_name)
+            .ok_or("class not found in method area")?;
+        let mirror = klass.get_java_clone().ok_or("class has no mirror")?;
+
+        // array classes have the fields of java/lang/Object, which has none
+        let mut super_mirror = object_mirror;
+        let mut instance_size = 0;
+        let mut static_fields = vec![];
+        let mut instance_fields = vec![];
+        if let Some(instance_klass) = klass.as_instance_klass() {
+            super_mirror = instance_klass
+                .parsed_class
+                .as_ref()
+                .and_then(|parsed_class| parsed_class.super_class.as_ref())
+                .and_then(|super_class| mirrors.get(super_class.name.as_str()))
+                .copied()
+                .unwrap_or(HeapRef::NULL);
+            let fields = instance_klass.fields.as_deref().unwrap_or_default();
+            for field in fields.iter() {
+                let basic_type = basic_type(&field.field_type);
+                instance_size += basic_type_size(basic_type);
+                if field.class_name == **class_name {
+                    instance_fields.push((writer.string(&field.field_name)?, basic_type));
+                }
+            }
+            let values = instance_klass
+                .static_field_values
+                .as_deref()
+                .unwrap_or_default();
+            let mut offset = 0;
+            for field in instance_klass
+                .static_fields
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+            {
+                let basic_type = basic_type(&field.field_type);
+                let slots = values.get(offset..).unwrap_or_default();
+                static_fields.push((writer.string(&field.field_name)?, basic_type, slots));
+                offset += field.field_width;
+            }
+        }
+
+        let segment = &mut writer.segment;
+        write_u1(segment, CLASS_DUMP);
+        write_id(segment, mirror.0 as u64);
+        write_u4(segment, EMPTY_STACK_TRACE);
+        write_id(segment, super_mirror.0 as u64);
+        // the class loader, signers, protection domain and two reserved identifiers
+        for _ in 0..5 {
+            write_id(segment, 0);
+        }
+        write_u4(segment, instance_size as u32);
+        // the constant pool
+        write_u2(segment, 0);
+        write_u2(segment, static_fields.len() as u16);
+        for (name_id, basic_type, slots) in static_fields.iter() {
+            write_id(segment, *name_id);
+            write_u1(segment, *basic_type);
+            write_value(segment, *basic_type, slots);
+        }
+        write_u2(segment, instance_fields.len() as u16);
+        for (name_id, basic_type) in instance_fields.iter() {
+            write_id(segment, *name_id);
+            write_u1(segment, *basic_type);
+        }
+        writer.end_sub_record()?;
+    }
+
+    // the mirrors are dumped as classes rather than as instances of java/lang/Class
+    let mirror_refs: HashSet<HeapRef> = mirrors.values().copied().collect();
+    for (HeapRef(objectref), heap_item) in global_memory.heap.iter() {
+        if mirror_refs.contains(&HeapRef(objectref)) {
+            continue;
+        }
+        let descriptor = global_memory.heap.descriptor(heap_item);
+        let segment = &mut writer.segment;
+        if let Some(basic_type) = array_basic_type(descriptor) {
+            write_u1(segment, PRIMITIVE_ARRAY_DUMP);
+            write_id(segment, objectref as u64);
+            write_u4(segment, EMPTY_STACK_TRACE);
+            write_u4(segment, global_memory.heap.array_length(heap_item) as u32);
+            write_u1(segment, basic_type);
+            // the elements are laid out just like the dump has them
+            segment.extend_from_slice(&heap_item.bytes);
+        } else if descriptor.starts_with('[') {
+            // an array class without a mirror can't be referred to
+            let array_mirror = match mirrors.get(descriptor) {
+                Some(array_mirror) => *array_mirror,
+                None => continue,
+            };
+            write_u1(segment, OBJECT_ARRAY_DUMP);
+            write_id(segment, objectref as u64);
+            write_u4(segment, EMPTY_STACK_TRACE);
+            write_u4(segment, heap_item.data.len() as u32);
+            write_id(segment, array_mirror.0 as u64);
+            for element in heap_item.data.iter() {
+                write_id(segment, *element as u64);
+            }
+        } else {
+            let class_name = &descriptor[1..descriptor.len() - 1];
+            let (class_mirror, fields) = match (
+                mirrors.get(class_name),
+                global_memory
+                    .method_area
+                    .classes
+                    .get(class_name)
+                    .and_then(|klass| klass.as_instance_klass())
+                    .and_then(|klass| klass.fields.as_ref()),
+            ) {
+                (Some(class_mirror), Some(fields)) => (*class_mirror, fields),
+                _ => continue,
+            };
+            let mut values = vec![];
+            for (field, offset) in instance_dump_fields(fields) {
+                let slots = heap_item.data.get(offset..).unwrap_or_default();
+                write_value(&mut values, basic_type(&field.field_type), slots);
+            }
+            write_u1(segment, INSTANCE_DUMP);
+            write_id(segment, objectref as u64);
+            write_u4(segment, EMPTY_STACK_TRACE);
+            write_id(segment, class_mirror.0 as u64);
+            write_u4(segment, values.len() as u32);
+            segment.extend_from_slice(&values);
+        }
+        writer.end_sub_record()?;
+    }
+
+    writer.flush_segment()?;
+    writer.record(HEAP_DUMP_END, &[])?;
+    writer.out.flush()?;
+    Ok(writer.written)
+}
@@ -0,0 +1,1801 @@
+// A debug server speaking the part of JDWP that jdb and IDEs like IntelliJ need to set breakpoints,
+// step through code and look at threads, frames, local variables and objects. With --debug-port,
+// the vm waits for a debugger to attach before running main, like
+// -agentlib:jdwp=transport=dt_socket,server=y,suspend=y does, and reports its start.
+//
+// Everything the debugger asks for is answered by the thread holding the vm lock, see threads:
+// either right where it ran into an event, or when it looks for commands that arrived while it was
+// running, which it does every POLL_INTERVAL instructions. While the vm is suspended, that thread
+// keeps holding the lock, so suspending one thread suspends all of them, and resuming any of them
+// resumes all. Only the frames of the threads the gc can see are known, see
+// threads::other_threads.
+//
+// Objects and threads are identified by their reference, so the gc may free or move an object the
+// debugger still holds on to. Classes are numbered in the order the debugger first hears of them,
+// methods by their index in the class file, and fields by their index in the class file together
+// with the number of their class, as a field is looked up without its class.
+//
+// Breakpoints, steps, the start and death of threads, classes being prepared and the vm dying are
+// reported. Requests for any other event are accepted, but never fire.
+//
+// https://docs.oracle.com/en/java/javase/11/docs/specs/jdwp/jdwp-spec.html
+// https://docs.oracle.com/en/java/javase/11/docs/specs/jdwp/jdwp-protocol.html
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::Arc,
+};
+
+use crate::parse::Method;
+
+use super::{
+    get_field_value, java_string_from_string, primitive_element_size, slots,
+    string_from_java_string, threads, Frame, GlobalMemory, HeapRef, Thread,
+};
+
+// how many instructions a thread runs before it looks for commands of the debugger
+const POLL_INTERVAL: usize = 10_000;
+
+const HANDSHAKE: &[u8] = b"JDWP-Handshake";
+const HEADER_LENGTH: usize = 11;
+const REPLY_FLAG: u8 = 0x80;
+
+// objectIDs, referenceTypeIDs, methodIDs, fieldIDs and frameIDs all take up 8 bytes
+const ID_SIZE: i32 = 8;
+
+// the command sets
+const VIRTUAL_MACHINE: u8 = 1;
+const REFERENCE_TYPE: u8 = 2;
+const CLASS_TYPE: u8 = 3;
+const METHOD: u8 = 6;
+const OBJECT_REFERENCE: u8 = 9;
+const STRING_REFERENCE: u8 = 10;
+const THREAD_REFERENCE: u8 = 11;
+const THREAD_GROUP_REFERENCE: u8 = 12;
+const ARRAY_REFERENCE: u8 = 13;
+const EVENT_REQUEST: u8 = 15;
+const STACK_FRAME: u8 = 16;
+const CLASS_OBJECT_REFERENCE: u8 = 17;
+const EVENT: u8 = 64;
+const COMPOSITE: u8 = 100;
+
+// the errors
+const INVALID_THREAD: u16 = 10;
+const THREAD_NOT_SUSPENDED: u16 = 13;
+const INVALID_OBJECT: u16 = 20;
+const INVALID_CLASS: u16 = 21;
+const INVALID_METHODID: u16 = 23;
+const INVALID_FIELDID: u16 = 25;
+const INVALID_FRAMEID: u16 = 30;
+const INVALID_SLOT: u16 = 35;
+const NOT_IMPLEMENTED: u16 = 99;
+const ABSENT_INFORMATION: u16 = 101;
+const ILLEGAL_ARGUMENT: u16 = 103;
+const INTERNAL: u16 = 113;
+
+// the kinds of events
+const SINGLE_STEP: u8 = 1;
+const BREAKPOINT: u8 = 2;
+const THREAD_START: u8 = 6;
+const THREAD_DEATH: u8 = 7;
+const CLASS_PREPARE: u8 = 8;
+const VM_START: u8 = 90;
+const VM_DEATH: u8 = 99;
+
+const SUSPEND_NONE: u8 = 0;
+const SUSPEND_ALL: u8 = 2;
+
+const STEP_LINE: i32 = 1;
+const STEP_INTO: i32 = 0;
+const STEP_OVER: i32 = 1;
+
+// the tags of the kinds of reference types
+const TYPE_CLASS: u8 = 1;
+const TYPE_INTERFACE: u8 = 2;
+const TYPE_ARRAY: u8 = 3;
+
+// the tags of values, the primitive ones being the characters of their descriptors
+const TAG_ARRAY: u8 = b'[';
+const TAG_OBJECT: u8 = b'L';
+const TAG_STRING: u8 = b's';
+const TAG_THREAD: u8 = b't';
+const TAG_THREAD_GROUP: u8 = b'g';
+const TAG_CLASS_LOADER: u8 = b'l';
+const TAG_CLASS_OBJECT: u8 = b'c';
+
+// ClassStatus
+const STATUS_VERIFIED: i32 = 1;
+const STATUS_PREPARED: i32 = 2;
+const STATUS_INITIALIZED: i32 = 4;
+const STATUS_ERROR: i32 = 8;
+
+const THREAD_STATUS_ZOMBIE: i32 = 0;
+const THREAD_STATUS_RUNNING: i32 = 1;
+const SUSPEND_STATUS_SUSPENDED: i32 = 1;
+
+// the error code a command failed with. Errors of the vm itself become INTERNAL.
+#[derive(Debug)]
+struct ErrorCode(u16);
+
+impl From<Box<dyn Error>> for ErrorCode {
+    fn from(_: Box<dyn Error>) -> ErrorCode {
+        ErrorCode(INTERNAL)
+    }
+}
+
+impl From<&str> for ErrorCode {
+    fn from(_: &str) -> ErrorCode {
+        ErrorCode(INTERNAL)
+    }
+}
+
+type Reply = Result<Vec<u8>, ErrorCode>;
+
+// a place in the code of a method
+#[derive(Debug, Clone, PartialEq)]
+struct Location {
+    class_name: String,
+    method_index: usize,
+    index: u64,
+}
+
+// where a step started, or ended the last time
+#[derive(Debug)]
+struct Step {
+    thread: u64,
+    size: i32,
+    depth: i32,
+    frames: usize,
+    line: Option<u16>,
+    index: u64,
+}
+
+#[derive(Debug)]
+enum Modifier {
+    Count(i32),
+    ThreadOnly(u64),
+    ClassOnly(String),
+    ClassMatch(String),
+    ClassExclude(String),
+    LocationOnly(Location),
+    Step(Step),
+    // filters on exceptions, fields, instances and source names, which the events reported
+    // don't have
+    Ignored,
+}
+
+#[derive(Debug)]
+struct EventRequest {
+    id: i32,
+    event_kind: u8,
+    suspend_policy: u8,
+    modifiers: Vec<Modifier>,
+}
+
+// what is known about the event a thread ran into, to match the requests against
+struct Context<'a> {
+    thread: u64,
+    class_name: Option<&'a str>,
+    location: Option<&'a Location>,
+    // the depth of the stack of the thread and the line it is at, for steps
+    frames: usize,
+    line: Option<u16>,
+}
+
+struct Command {
+    id: u32,
+    command_set: u8,
+    command: u8,
+    data: Vec<u8>,
+}
+
+struct Event {
+    kind: u8,
+    request_id: i32,
+    suspend_policy: u8,
+    // what follows the requestID, which depends on the kind
+    body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub(super) struct Debugger {
+    stream: TcpStream,
+    next_packet_id: u32,
+    requests: Vec<EventRequest>,
+    next_request_id: i32,
+    // the classes in the order they got their id, which is their index plus one
+    class_names: Vec<String>,
+    class_ids: HashMap<String, u64>,
+    // how often the vm was suspended without being resumed
+    suspend_count: u32,
+    instructions_until_poll: usize,
+    // the thread, depth of its stack and instruction the last breakpoint or step was reported
+    // at, which isn't reported again when the thread goes on from there
+    reported_at: Option<(u64, usize, usize)>,
+    // set by VirtualMachine.Dispose
+    disposed: bool,
+}
+
+fn write_u1(out: &mut Vec<u8>, value: u8) {
+    out.push(value);
+}
+
+fn write_u2(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_i4(out: &mut Vec<u8>, value: i32) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_u8(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+fn write_bool(out: &mut Vec<u8>, value: bool) {
+    out.push(value as u8);
+}
+
+fn write_string(out: &mut Vec<u8>, value: &str) {
+    write_i4(out, value.len() as i32);
+    out.extend_from_slice(value.as_bytes());
+}
+
+// the data of a command, read front to back
+struct Reader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn bytes(&mut self, length: usize) -> Result<&[u8], ErrorCode> {
+        let bytes = self
+            .data
+            .get(self.position..self.position + length)
+            .ok_or(ErrorCode(ILLEGAL_ARGUMENT))?;
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn u1(&mut self) -> Result<u8, ErrorCode> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, ErrorCode> {
+        Ok(self.u1()? != 0)
+    }
+
+    fn i4(&mut self) -> Result<i32, ErrorCode> {
+        let bytes = self.bytes(4)?;
+        Ok(i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    fn u8(&mut self) -> Result<u64, ErrorCode> {
+        let bytes = self.bytes(8)?;
+        let mut value = [0; 8];
+        value.copy_from_slice(bytes);
+        Ok(u64::from_be_bytes(value))
+    }
+
+    fn string(&mut self) -> Result<String, ErrorCode> {
+        let length = usize::try_from(self.i4()?).map_err(|_| ErrorCode(ILLEGAL_ARGUMENT))?;
+        Ok(String::from_utf8_lossy(self.bytes(length)?).into_owned())
+    }
+
+    // a count followed by as many items
+    fn count(&mut self) -> Result<usize, ErrorCode> {
+        usize::try_from(self.i4()?).map_err(|_| ErrorCode(ILLEGAL_ARGUMENT))
+    }
+
+    fn reference(&mut self) -> Result<u32, ErrorCode> {
+        u32::try_from(self.u8()?).map_err(|_| ErrorCode(INVALID_OBJECT))
+    }
+}
+
+// whether the class matches a pattern like java.*, *.Main or Main
+fn class_matches(class_name: &str, pattern: &str) -> bool {
+    let class_name = class_name.replace('/', ".");
+    if let Some(suffix) = pattern.strip_prefix('*') {
+        class_name.ends_with(suffix)
+    } else if let Some(prefix) = pattern.strip_suffix('*') {
+        class_name.starts_with(prefix)
+    } else {
+        class_name == pattern
+    }
+}
+
+// whether the thread is where a step ends
+fn step_completed(step: &Step, context: &Context) -> bool {
+    let Some(location) = context.location else {
+        return false;
+    };
+    if context.thread != step.thread {
+        return false;
+    }
+    let moved = match (step.size, step.line, context.line) {
+        (STEP_LINE, Some(line), Some(current_line)) => current_line != line,
+        // STEP_MIN, or a line step through code without line numbers
+        _ => location.index != step.index,
+    };
+    match step.depth {
+        STEP_INTO => context.frames != step.frames || moved,
+        STEP_OVER => context.frames < step.frames || (context.frames == step.frames && moved),
+        _ => context.frames < step.frames,
+    }
+}
+
+// the tag of a value of the type with the descriptor
+fn descriptor_tag(descriptor: &str) -> u8 {
+    match descriptor.as_bytes().first() {
+        Some(b'[') => TAG_ARRAY,
+        Some(b'L') | None => TAG_OBJECT,
+        Some(tag) => *tag,
+    }
+}
+
+// the tag of a reference, telling strings, threads and the like apart from other objects
+fn object_tag(global_memory: &GlobalMemory, objectref: u32) -> u8 {
+    let Some(class_name) = global_memory.heap.class_name(HeapRef(objectref)) else {
+        return TAG_OBJECT;
+    };
+    if class_name.starts_with('[') {
+        return TAG_ARRAY;
+    }
+    let method_area = &global_memory.method_area;
+    if class_name == "java/lang/String" {
+        TAG_STRING
+    } else if class_name == "java/lang/Class" {
+        TAG_CLASS_OBJECT
+    } else if method_area.is_subclass_of(class_name, "java/lang/Thread") {
+        TAG_THREAD
+    } else if method_area.is_subclass_of(class_name, "java/lang/ThreadGroup") {
+        TAG_THREAD_GROUP
+    } else if method_area.is_subclass_of(class_name, "java/lang/ClassLoader") {
+        TAG_CLASS_LOADER
+    } else {
+        TAG_OBJECT
+    }
+}
+
+// writes the value taking up slots, which has the type with the tag, preceded by the tag of the
+// value itself if tagged
+fn write_value(
+    out: &mut Vec<u8>,
+    global_memory: &GlobalMemory,
+    tag: u8,
+    slots: &[u32],
+    tagged: bool,
+) {
+    let slot = |index: usize| slots.get(index).copied().unwrap_or(0);
+    let tag = match tag {
+        TAG_ARRAY | TAG_OBJECT => object_tag(global_memory, slot(0)),
+        tag => tag,
+    };
+    if tagged {
+        write_u1(out, tag);
+    }
+    match tag {
+        b'Z' | b'B' => write_u1(out, slot(0) as u8),
+        b'C' | b'S' => write_u2(out, slot(0) as u16),
+        b'I' | b'F' => write_i4(out, slot(0) as i32),
+        b'J' | b'D' => write_u8(out, slots::long_from_slots(slot(0), slot(1)) as u64),
+        b'V' => {}
+        _ => write_u8(out, slot(0) as u64),
+    }
+}
+
+fn slot_width(tag: u8) -> usize {
+    match tag {
+        b'J' | b'D' => 2,
+        _ => 1,
+    }
+}
+
+// the index of the method in the class file of its class
+fn method_index(global_memory: &GlobalMemory, class_name: &str, method: &Method) -> Option<usize> {
+    global_memory
+        .method_area
+        .parsed_class(class_name)
+        .ok()?
+        .methods
+        .iter()
+        .position(|candidate| {
+            candidate.name == method.name && candidate.descriptor == method.descriptor
+        })
+}
+
+// where the frame is at, None for stub frames
+fn frame_location(global_memory: &GlobalMemory, frame: &Frame) -> Option<Location> {
+    let method = frame.method.as_ref()?;
+    Some(Location {
+        class_name: frame.class_name().to_owned(),
+        method_index: method_index(global_memory, frame.class_name(), method)?,
+        // native methods are at -1
+        index: if frame.code.is_some() {
+            frame.instruction_counter as u64
+        } else {
+            u64::MAX
+        },
+    })
+}
+
+// the thread object of the thread, the one it is running as for threads running shutdown hooks
+fn thread_id(thread: Option<&Thread>, global_memory: &GlobalMemory) -> u64 {
+    thread
+        .and_then(|thread| thread.java_clone)
+        .or_else(|| global_memory.current_thread_object())
+        .unwrap_or(HeapRef::NULL)
+        .0 as u64
+}
+
+// the thread with the thread object, if its frames can be looked at
+fn find_thread<'a>(
+    thread: Option<&'a Thread>,
+    global_memory: &'a GlobalMemory,
+    thread_ref: u64,
+) -> Option<&'a Thread> {
+    let is_thread = |candidate: &&Thread| {
+        candidate.java_clone.map(|objectref| objectref.0 as u64) == Some(thread_ref)
+    };
+    thread
+        .filter(is_thread)
+        .or_else(|| threads::other_threads(global_memory).find(is_thread))
+}
+
+// the frames of a thread that stand for a method, the innermost first, with their index on the
+// stack of the thread, which is their frameID
+fn method_frames(thread: &Thread) -> Vec<(usize, &Frame)> {
+    thread
+        .thread_memory
+        .jvm_stack
+        .iter()
+        .enumerate()
+        .filter(|(_, frame)| frame.method.is_some())
+        .rev()
+        .collect()
+}
+
+fn is_alive(thread: Option<&Thread>, global_memory: &GlobalMemory, thread_ref: u32) -> bool {
+    thread.is_some_and(|thread| thread.java_clone == Some(HeapRef(thread_ref)))
+        || threads::is_alive(global_memory, HeapRef(thread_ref))
+}
+
+// waits for a debugger to attach on the port, reports the start of the vm to it and serves its
+// commands until it resumes the vm
+pub(super) fn attach(
+    thread: &Thread,
+    global_memory: &mut GlobalMemory,
+    port: u16,
+) -> Result<(), Box<dyn Error>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("Listening for transport dt_socket at address: {port}");
+    let (mut stream, _) = listener.accept()?;
+    let mut handshake = [0; HANDSHAKE.len()];
+    stream.read_exact(&mut handshake)?;
+    if handshake != HANDSHAKE {
+        return Err("the debugger did not start with the JDWP handshake".into());
+    }
+    stream.write_all(HANDSHAKE)?;
+    stream.set_nodelay(true)?;
+
+    global_memory.debugger = Some(Box::new(Debugger {
+        stream,
+        next_packet_id: 1,
+        requests: vec![],
+        next_request_id: 1,
+        class_names: vec![],
+        class_ids: HashMap::new(),
+        suspend_count: 0,
+        instructions_until_poll: POLL_INTERVAL,
+        reported_at: None,
+        disposed: false,
+    }));
+    let mut body = vec![];
+    write_u8(&mut body, thread_id(Some(thread), global_memory));
+    report(
+        Some(thread),
+        global_memory,
+        vec![Event {
+            kind: VM_START,
+            request_id: 0,
+            suspend_policy: SUSPEND_ALL,
+            body,
+        }],
+    )
+}
+
+// called before a thread runs an instruction. Reports the breakpoints and steps it ran into, and
+// every now and then serves the commands that arrived while it was running.
+pub(super) fn before_instruction(
+    thread: &Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let Some(debugger) = global_memory.debugger.as_mut() else {
+        return Ok(());
+    };
+    debugger.instructions_until_poll -= 1;
+    if debugger.instructions_until_poll == 0 {
+        debugger.instructions_until_poll = POLL_INTERVAL;
+        serve(Some(thread), global_memory)?;
+    }
+
+    let thread_ref = thread_id(Some(thread), global_memory);
+    let Some(debugger) = global_memory.debugger.as_mut() else {
+        return Ok(());
+    };
+    let Some(frame) = thread.thread_memory.jvm_stack.last() else {
+        return Ok(());
+    };
+    let Some(method) = frame.method.as_ref() else {
+        return Ok(());
+    };
+    let frames = thread.thread_memory.jvm_stack.len();
+    let position = (thread.id, frames, frame.instruction_counter);
+    if let Some(reported_at) = debugger.reported_at {
+        if reported_at == position {
+            return Ok(());
+        }
+        if reported_at.0 == thread.id {
+            debugger.reported_at = None;
+        }
+    }
+    let index = frame.instruction_counter as u64;
+    // the cheap checks first, this runs before every instruction
+    let watched = debugger.requests.iter().any(|request| {
+        request.modifiers.iter().any(|modifier| match modifier {
+            Modifier::LocationOnly(location) => {
+                request.event_kind == BREAKPOINT
+                    && location.index == index
+                    && location.class_name == frame.class_name()
+            }
+            Modifier::Step(step) => request.event_kind == SINGLE_STEP && step.thread == thread_ref,
+            _ => false,
+        })
+    });
+    if !watched {
+        return Ok(());
+    }
+
+    let Some(location) = frame_location(global_memory, frame) else {
+        return Ok(());
+    };
+    let context = Context {
+        thread: thread_ref,
+        class_name: Some(frame.class_name()),
+        location: Some(&location),
+        frames,
+        line: method.line_number(frame.instruction_counter),
+    };
+    let Some(mut debugger) = global_memory.debugger.take() else {
+        return Ok(());
+    };
+    let mut events = vec![];
+    for kind in [SINGLE_STEP, BREAKPOINT] {
+        for (request_id, suspend_policy) in
+            debugger.matching_requests(global_memory, kind, &context)
+        {
+            let mut body = vec![];
+            write_u8(&mut body, thread_ref);
+            debugger.write_location(&mut body, global_memory, &location);
+            events.push(Event {
+                kind,
+                request_id,
+                suspend_policy,
+                body,
+            });
+        }
+    }
+    if !events.is_empty() {
+        debugger.reported_at = Some(position);
+    }
+    global_memory.debugger = Some(debugger);
+    if events.is_empty() {
+        return Ok(());
+    }
+    report(Some(thread), global_memory, events)
+}
+
+// reports a class that was just linked
+pub(super) fn class_prepared(
+    global_memory: &mut GlobalMemory,
+    class_name: &str,
+) -> Result<(), Box<dyn Error>> {
+    let Some(mut debugger) = global_memory.debugger.take() else {
+        return Ok(());
+    };
+    let thread_ref = thread_id(None, global_memory);
+    let context = Context {
+        thread: thread_ref,
+        class_name: Some(class_name),
+        location: None,
+        frames: 0,
+        line: None,
+    };
+    let mut events = vec![];
+    for (request_id, suspend_policy) in
+        debugger.matching_requests(global_memory, CLASS_PREPARE, &context)
+    {
+        let mut body = vec![];
+        write_u8(&mut body, thread_ref);
+        write_u1(&mut body, type_tag(global_memory, class_name));
+        write_u8(&mut body, debugger.class_id(class_name));
+        write_string(&mut body, &signature(class_name));
+        write_i4(&mut body, class_status(global_memory, class_name));
+        events.push(Event {
+            kind: CLASS_PREPARE,
+            request_id,
+            suspend_policy,
+            body,
+        });
+    }
+    global_memory.debugger = Some(debugger);
+    report(None, global_memory, events)
+}
+
+// reports a thread that was started or ended
+pub(super) fn thread_changed(
+    global_memory: &mut GlobalMemory,
+    thread_ref: u32,
+    started: bool,
+) -> Result<(), Box<dyn Error>> {
+    let Some(mut debugger) = global_memory.debugger.take() else {
+        return Ok(());
+    };
+    let kind = if started { THREAD_START } else { THREAD_DEATH };
+    let context = Context {
+        thread: thread_ref as u64,
+        class_name: None,
+        location: None,
+        frames: 0,
+        line: None,
+    };
+    let events = debugger
+        .matching_requests(global_memory, kind, &context)
+        .into_iter()
+        .map(|(request_id, suspend_policy)| {
+            let mut body = vec![];
+            write_u8(&mut body, thread_ref as u64);
+            Event {
+                kind,
+                request_id,
+                suspend_policy,
+                body,
+            }
+        })
+        .collect();
+    global_memory.debugger = Some(debugger);
+    report(None, global_memory, events)
+}
+
+// reports the death of the vm and lets the debugger go
+pub(super) fn vm_death(global_memory: &mut GlobalMemory) -> Result<(), Box<dyn Error>> {
+    let Some(mut debugger) = global_memory.debugger.take() else {
+        return Ok(());
+    };
+    let context = Context {
+        thread: 0,
+        class_name: None,
+        location: None,
+        frames: 0,
+        line: None,
+    };
+    let mut events = vec![Event {
+        kind: VM_DEATH,
+        request_id: 0,
+        suspend_policy: SUSPEND_NONE,
+        body: vec![],
+    }];
+    for (request_id, _) in debugger.matching_requests(global_memory, VM_DEATH, &context) {
+        events.push(Event {
+            kind: VM_DEATH,
+            request_id,
+            suspend_policy: SUSPEND_NONE,
+            body: vec![],
+        });
+    }
+    // the debugger may already be gone
+    let _ = debugger.write_events(events);
+    Ok(())
+}
+
+// sends the events in a single composite event, and serves the commands of the debugger until it
+// resumes the vm if any of them suspends it
+fn report(
+    thread: Option<&Thread>,
+    global_memory: &mut GlobalMemory,
+    events: Vec<Event>,
+) -> Result<(), Box<dyn Error>> {
+    if events.is_empty() {
+        return Ok(());
+    }
+    let Some(debugger) = global_memory.debugger.as_mut() else {
+        return Ok(());
+    };
+    let suspends = events
+        .iter()
+        .any(|event| event.suspend_policy != SUSPEND_NONE);
+    if debugger.write_events(events).is_err() {
+        global_memory.debugger = None;
+        return Ok(());
+    }
+    if suspends {
+        debugger.suspend_count += 1;
+    }
+    serve(thread, global_memory)
+}
+
+// serves the commands of the debugger while the vm is suspended, and those that already arrived
+// otherwise. The debugger is let go once it disposed of the connection or went away.
+fn serve(thread: Option<&Thread>, global_memory: &mut GlobalMemory) -> Result<(), Box<dyn Error>> {
+    let Some(mut debugger) = global_memory.debugger.take() else {
+        return Ok(());
+    };
+    loop {
+        if debugger.suspend_count == 0 && !debugger.has_pending_command() {
+            break;
+        }
+        let command = match debugger.read_command() {
+            Ok(Some(command)) => command,
+            Ok(None) => continue,
+            Err(_) => return Ok(()),
+        };
+        let reply = debugger.handle(
+            thread,
+            global_memory,
+            command.command_set,
+            command.command,
+            &mut Reader {
+                data: &command.data,
+                position: 0,
+            },
+        );
+        if debugger.write_reply(command.id, reply).is_err() || debugger.disposed {
+            return Ok(());
+        }
+    }
+    global_memory.debugger = Some(debugger);
+    Ok(())
+}
+
+fn signature(class_name: &str) -> String {
+    if class_name.starts_with('[') {
+        class_name.to_owned()
+    } else {
+        format!("L{class_name};")
+    }
+}
+
+fn type_tag(global_memory: &GlobalMemory, class_name: &str) -> u8 {
+    if class_name.starts_with('[') {
+        return TYPE_ARRAY;
+    }
+    match global_memory.method_area.parsed_class(class_name) {
+        Ok(class) if class.access.interface => TYPE_INTERFACE,
+        _ => TYPE_CLASS,
+    }
+}
+
+fn class_status(global_memory: &GlobalMemory, class_name: &str) -> i32 {
+    let Some(class) = global_memory.method_area.classes.get(class_name) else {
+        return 0;
+    };
+    match class.get_state() {
+        super::ClassState::Loaded => 0,
+        super::ClassState::Linked | super::ClassState::Initializing => {
+            STATUS_VERIFIED | STATUS_PREPARED
+        }
+        super::ClassState::Initialized => STATUS_VERIFIED | STATUS_PREPARED | STATUS_INITIALIZED,
+        super::ClassState::Erroneous => STATUS_VERIFIED | STATUS_PREPARED | STATUS_ERROR,
+    }
+}
+
+// the classes the debugger may know about: the classes and arrays, but not the primitive types
+fn is_reference_type(class_name: &str, global_memory: &GlobalMemory) -> bool {
+    global_memory
+        .method_area
+        .classes
+        .get(class_name)
+        .is_some_and(|class| class.as_instance_klass().is_some() || class_name.starts_with('['))
+}
+
+// the class of an object or array
+fn class_of(global_memory: &GlobalMemory, objectref: u32) -> Result<String, ErrorCode> {
+    Ok(global_memory
+        .heap
+        .class_name(HeapRef(objectref))
+        .ok_or(ErrorCode(INVALID_OBJECT))?
+        .to_owned())
+}
+
+impl Debugger {
+    fn class_id(&mut self, class_name: &str) -> u64 {
+        if let Some(id) = self.class_ids.get(class_name) {
+            return *id;
+        }
+        self.class_names.push(class_name.to_owned());
+        let id = self.class_names.len() as u64;
+        self.class_ids.insert(class_name.to_owned(), id);
+        id
+    }
+
+    fn class_name(&self, id: u64) -> Result<String, ErrorCode> {
+        usize::try_from(id)
+            .ok()
+            .and_then(|id| id.checked_sub(1))
+            .and_then(|index| self.class_names.get(index))
+            .cloned()
+            .ok_or(ErrorCode(INVALID_CLASS))
+    }
+
+    fn write_location(
+        &mut self,
+        out: &mut Vec<u8>,
+        global_memory: &GlobalMemory,
+        location: &Location,
+    ) {
+        write_u1(out, type_tag(global_memory, &location.class_name));
+        write_u8(out, self.class_id(&location.class_name));
+        write_u8(out, location.method_index as u64 + 1);
+        write_u8(out, location.index);
+    }
+
+    fn read_location(&self, reader: &mut Reader) -> Result<Location, ErrorCode> {
+        let _type_tag = reader.u1()?;
+        let class_name = self.class_name(reader.u8()?)?;
+        let method_index = reader
+            .u8()?
+            .checked_sub(1)
+            .ok_or(ErrorCode(INVALID_METHODID))? as usize;
+        Ok(Location {
+            class_name,
+            method_index,
+            index: reader.u8()?,
+        })
+    }
+
+    // the class and method a methodID of the class with the referenceTypeID stands for
+    fn method(
+        &self,
+        global_memory: &GlobalMemory,
+        class_id: u64,
+        method_id: u64,
+    ) -> Result<Arc<Method>, ErrorCode> {
+        let class_name = self.class_name(class_id)?;
+        let class = global_memory.method_area.parsed_class(&class_name)?;
+        usize::try_from(method_id)
+            .ok()
+            .and_then(|id| id.checked_sub(1))
+            .and_then(|index| class.methods.get(index))
+            .cloned()
+            .ok_or(ErrorCode(INVALID_METHODID))
+    }
+
+    // the class declaring the field with the fieldID, and its name and descriptor
+    fn field(
+        &self,
+        global_memory: &GlobalMemory,
+        field_id: u64,
+    ) -> Result<(String, String, String), ErrorCode> {
+        let class_name = self.class_name(field_id >> 32)?;
+        let class = global_memory.method_area.parsed_class(&class_name)?;
+        let field = ((field_id & 0xffff_ffff) as usize)
+            .checked_sub(1)
+            .and_then(|index| class.fields.get(index))
+            .ok_or(ErrorCode(INVALID_FIELDID))?;
+        Ok((
+            class_name,
+            field.name.to_owned(),
+            field.descriptor.field_type.descriptor(),
+        ))
+    }
+
+    // the requests for events of the kind whose modifiers let the event through. Count modifiers
+    // are counted down, and steps start over where they ended.
+    fn matching_requests(
+        &mut self,
+        global_memory: &GlobalMemory,
+        kind: u8,
+        context: &Context,
+    ) -> Vec<(i32, u8)> {
+        let mut matching = vec![];
+        for request in self
+            .requests
+            .iter_mut()
+            .filter(|request| request.event_kind == kind)
+        {
+            let filtered_out = request.modifiers.iter().any(|modifier| match modifier {
+                Modifier::Count(_) | Modifier::Ignored => false,
+                Modifier::ThreadOnly(thread) => *thread != context.thread,
+                Modifier::ClassOnly(class_name) => context.class_name.is_some_and(|name| {
+                    !global_memory.method_area.is_subclass_of(name, class_name)
+                }),
+                Modifier::ClassMatch(pattern) => context
+                    .class_name
+                    .is_some_and(|name| !class_matches(name, pattern)),
+                Modifier::ClassExclude(pattern) => context
+                    .class_name
+                    .is_some_and(|name| class_matches(name, pattern)),
+                Modifier::LocationOnly(location) => context.location != Some(location),
+                Modifier::Step(step) => !step_completed(step, context),
+            });
+            if filtered_out {
+                continue;
+            }
+            // a count lets the event through once, after it was left out count - 1 times
+            let counted_out = request.modifiers.iter_mut().any(|modifier| match modifier {
+                Modifier::Count(count) => {
+                    *count -= 1;
+                    *count != 0
+                }
+                _ => false,
+            });
+            if counted_out {
+                continue;
+            }
+            for modifier in request.modifiers.iter_mut() {
+                if let (Modifier::Step(step), Some(location)) = (modifier, context.location) {
+                    step.frames = context.frames;
+                    step.line = context.line;
+                    step.index = location.index;
+                }
+            }
+            matching.push((request.id, request.suspend_policy));
+        }
+        matching
+    }
+
+    fn write_events(&mut self, events: Vec<Event>) -> Result<(), Box<dyn Error>> {
+        let mut data = vec![];
+        write_u1(
+            &mut data,
+            events
+                .iter()
+                .map(|event| event.suspend_policy)
+                .max()
+                .unwrap_or(SUSPEND_NONE),
+        );
+        write_i4(&mut data, events.len() as i32);
+        for event in events {
+            write_u1(&mut data, event.kind);
+            write_i4(&mut data, event.request_id);
+            data.extend_from_slice(&event.body);
+        }
+        let id = self.next_packet_id;
+        self.next_packet_id += 1;
+        let mut packet = vec![];
+        write_i4(&mut packet, (HEADER_LENGTH + data.len()) as i32);
+        write_i4(&mut packet, id as i32);
+        write_u1(&mut packet, 0);
+        write_u1(&mut packet, EVENT);
+        write_u1(&mut packet, COMPOSITE);
+        packet.extend_from_slice(&data);
+        self.stream.write_all(&packet)?;
+        Ok(())
+    }
+
+    fn write_reply(&mut self, id: u32, reply: Reply) -> Result<(), Box<dyn Error>> {
+        let (error_code, data) = match reply {
+            Ok(data) => (0, data),
+            Err(ErrorCode(error_code)) => (error_code, vec![]),
+        };
+        let mut packet = vec![];
+        write_i4(&mut packet, (HEADER_LENGTH + data.len()) as i32);
+        write_i4(&mut packet, id as i32);
+        write_u1(&mut packet, REPLY_FLAG);
+        write_u2(&mut packet, error_code);
+        packet.extend_from_slice(&data);
+        self.stream.write_all(&packet)?;
+        Ok(())
+    }
+
+    fn has_pending_command(&mut self) -> bool {
+        if self.stream.set_nonblocking(true).is_err() {
+            return false;
+        }
+        let pending = match self.stream.peek(&mut [0]) {
+            Ok(_) => true,
+            Err(error) => error.kind() != ErrorKind::WouldBlock,
+        };
+        let _ = self.stream.set_nonblocking(false);
+        pending
+    }
+
+    // the next command, None for replies, which the debugger has no reason to send as events
+    // don't get any
+    fn read_command(&mut self) -> Result<Option<Command>, Box<dyn Error>> {
+        let mut header = [0; HEADER_LENGTH];
+        self.stream.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+        let id = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let mut data = vec![0; length.saturating_sub(HEADER_LENGTH)];
+        self.stream.read_exact(&mut data)?;
+        if header[8] & REPLY_FLAG != 0 {
+            return Ok(None);
+        }
+        Ok(Some(Command {
+            id,
+            command_set: header[9],
+            command: header[10],
+            data,
+        }))
+    }
+
+    fn handle(
+        &mut self,
+        thread: Option<&Thread>,
+        global_memory: &mut GlobalMemory,
+        command_set: u8,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        match (command_set, command) {
+            (VIRTUAL_MACHINE, _) => self.virtual_machine(thread, global_memory, command, reader),
+            (REFERENCE_TYPE, _) => self.reference_type(global_memory, command, reader),
+            (CLASS_TYPE, 1) => {
+                // Superclass
+                let class_name = self.class_name(reader.u8()?)?;
+                let class = global_memory.method_area.parsed_class(&class_name)?;
+                let mut out = vec![];
+                match class.super_class.as_ref() {
+                    Some(super_class) => write_u8(&mut out, self.class_id(&super_class.name)),
+                    None => write_u8(&mut out, 0),
+                }
+                Ok(out)
+            }
+            (METHOD, _) => self.method_command(global_memory, command, reader),
+            (OBJECT_REFERENCE, _) => self.object_reference(global_memory, command, reader),
+            (STRING_REFERENCE, 1) => {
+                // Value
+                let string = string_from_java_string(global_memory, HeapRef(reader.reference()?))
+                    .map_err(|_| ErrorCode(INVALID_OBJECT))?;
+                let mut out = vec![];
+                write_string(&mut out, &string);
+                Ok(out)
+            }
+            (THREAD_REFERENCE, _) => self.thread_reference(thread, global_memory, command, reader),
+            (THREAD_GROUP_REFERENCE, _) => {
+                self.thread_group_reference(thread, global_memory, command, reader)
+            }
+            (ARRAY_REFERENCE, _) => self.array_reference(global_memory, command, reader),
+            (EVENT_REQUEST, _) => self.event_request(thread, global_memory, command, reader),
+            (STACK_FRAME, _) => self.stack_frame(thread, global_memory, command, reader),
+            (CLASS_OBJECT_REFERENCE, 1) => {
+                // ReflectedType
+                let class_ref = reader.reference()?;
+                let class_name = global_memory
+                    .method_area
+                    .class_name_of_mirror(class_ref)
+                    .filter(|class_name| is_reference_type(class_name, global_memory))
+                    .ok_or(ErrorCode(INVALID_OBJECT))?;
+                let mut out = vec![];
+                write_u1(&mut out, type_tag(global_memory, &class_name));
+                write_u8(&mut out, self.class_id(&class_name));
+                Ok(out)
+            }
+            _ => Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+    }
+
+    fn virtual_machine(
+        &mut self,
+        thread: Option<&Thread>,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let mut out = vec![];
+        match command {
+            // Version
+            1 => {
+                write_string(&mut out, "matebabe, a toy JVM");
+                write_i4(&mut out, 11);
+                write_i4(&mut out, 0);
+                write_string(&mut out, "11");
+                write_string(&mut out, "matebabe");
+            }
+            // ClassesBySignature
+            2 => {
+                let signature = reader.string()?;
+                let class_name = match signature.strip_prefix('L') {
+                    Some(class_name) => class_name.trim_end_matches(';').to_owned(),
+                    None => signature,
+                };
+                if is_reference_type(&class_name, global_memory) {
+                    write_i4(&mut out, 1);
+                    write_u1(&mut out, type_tag(global_memory, &class_name));
+                    write_u8(&mut out, self.class_id(&class_name));
+                    write_i4(&mut out, class_status(global_memory, &class_name));
+                } else {
+                    write_i4(&mut out, 0);
+                }
+            }
+            // AllClasses, AllClassesWithGeneric
+            3 | 20 => {
+                let mut class_names: Vec<&str> = global_memory
+                    .method_area
+                    .classes
+                    .keys()
+                    .filter(|class_name| is_reference_type(class_name, global_memory))
+                    .collect();
+                class_names.sort();
+                write_i4(&mut out, class_names.len() as i32);
+                for class_name in class_names {
+                    write_u1(&mut out, type_tag(global_memory, class_name));
+                    write_u8(&mut out, self.class_id(class_name));
+                    write_string(&mut out, &signature(class_name));
+                    if command == 20 {
+                        write_string(&mut out, "");
+                    }
+                    write_i4(&mut out, class_status(global_memory, class_name));
+                }
+            }
+            // AllThreads
+            4 => {
+                let mut thread_refs: Vec<HeapRef> =
+                    global_memory.threads.values().copied().collect();
+                if let Some(thread_ref) = thread.and_then(|thread| thread.java_clone) {
+                    if !thread_refs.contains(&thread_ref) {
+                        thread_refs.push(thread_ref);
+                    }
+                }
+                thread_refs.sort();
+                write_i4(&mut out, thread_refs.len() as i32);
+                for thread_ref in thread_refs {
+                    write_u8(&mut out, thread_ref.0 as u64);
+                }
+            }
+            // TopLevelThreadGroups
+            5 => {
+                let mut group_ref = match global_memory.main_thread_object {
+                    Some(thread_ref) => {
+                        get_field_value(global_memory, thread_ref.0, "java/lang/Thread", "group")?
+                    }
+                    None => 0,
+                };
+                while let Ok(parent_ref) =
+                    get_field_value(global_memory, group_ref, "java/lang/ThreadGroup", "parent")
+                {
+                    if parent_ref == 0 {
+                        break;
+                    }
+                    group_ref = parent_ref;
+                }
+                if group_ref == 0 {
+                    write_i4(&mut out, 0);
+                } else {
+                    write_i4(&mut out, 1);
+                    write_u8(&mut out, group_ref as u64);
+                }
+            }
+            // Dispose
+            6 => {
+                self.requests.clear();
+                self.suspend_count = 0;
+                self.disposed = true;
+            }
+            // IDSizes
+            7 => {
+                for _ in 0..5 {
+                    write_i4(&mut out, ID_SIZE);
+                }
+            }
+            // Suspend
+            8 => self.suspend_count += 1,
+            // Resume
+            9 => self.suspend_count = self.suspend_count.saturating_sub(1),
+            // Exit
+            10 => {
+                global_memory.exit_status = Some(reader.i4()?);
+                threads::changed(global_memory);
+                self.suspend_count = 0;
+            }
+            // CreateString
+            11 => {
+                let string = reader.string()?;
+                let string_ref = java_string_from_string(global_memory, string)?;
+                write_u8(&mut out, string_ref.0 as u64);
+            }
+            // Capabilities, CapabilitiesNew: only getting the bytecodes and synthetic attributes of
+            // methods and requesting the death of the vm are supported
+            12 | 17 => {
+                let capabilities = if command == 12 { 7 } else { 32 };
+                for capability in 0..capabilities {
+                    write_bool(&mut out, matches!(capability, 2 | 3 | 13));
+                }
+            }
+            // ClassPaths
+            13 => {
+                write_string(&mut out, ".");
+                write_i4(&mut out, 0);
+                write_i4(&mut out, 0);
+            }
+            // HoldEvents, ReleaseEvents: events are sent as soon as they happen anyway
+            15 | 16 => {}
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+
+    fn reference_type(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let class_id = reader.u8()?;
+        let class_name = self.class_name(class_id)?;
+        let class = global_memory.method_area.parsed_class(&class_name).ok();
+        let mut out = vec![];
+        match command {
+            // Signature, SignatureWithGeneric
+            1 | 13 => {
+                write_string(&mut out, &signature(&class_name));
+                if command == 13 {
+                    write_string(&mut out, "");
+                }
+            }
+            // ClassLoader: all classes share the boot loader
+            2 => write_u8(&mut out, 0),
+            // Modifiers
+            3 => {
+                let flags = class.map_or(0x0411, |class| class.access.flags as i32);
+                write_i4(&mut out, flags);
+            }
+            // Fields, FieldsWithGeneric
+            4 | 14 => {
+                let fields = class.as_ref().map_or(&[][..], |class| &class.fields[..]);
+                write_i4(&mut out, fields.len() as i32);
+                for (index, field) in fields.iter().enumerate() {
+                    write_u8(&mut out, class_id << 32 | (index as u64 + 1));
+                    write_string(&mut out, &field.name);
+                    write_string(&mut out, &field.descriptor.field_type.descriptor());
+                    if command == 14 {
+                        write_string(&mut out, "");
+                    }
+                    write_i4(&mut out, field.access.flags as i32);
+                }
+            }
+            // Methods, MethodsWithGeneric
+            5 | 15 => {
+                let methods = class.as_ref().map_or(&[][..], |class| &class.methods[..]);
+                write_i4(&mut out, methods.len() as i32);
+                for (index, method) in methods.iter().enumerate() {
+                    write_u8(&mut out, index as u64 + 1);
+                    write_string(&mut out, &method.name);
+                    write_string(&mut out, &method.descriptor.descriptor());
+                    if command == 15 {
+                        write_string(&mut out, "");
+                    }
+                    write_i4(&mut out, method.access.flags as i32);
+                }
+            }
+            // GetValues of static fields
+            6 => {
+                let count = reader.count()?;
+                write_i4(&mut out, count as i32);
+                for _ in 0..count {
+                    let (declaring_class, name, descriptor) =
+                        self.field(global_memory, reader.u8()?)?;
+                    let class = global_memory
+                        .method_area
+                        .classes
+                        .get(&declaring_class)
+                        .and_then(|class| class.as_instance_klass())
+                        .ok_or(ErrorCode(INVALID_CLASS))?;
+                    let offset = class
+                        .static_field_offset_with_strings(declaring_class.to_owned(), name)
+                        .map_err(|_| ErrorCode(INVALID_FIELDID))?;
+                    let values = class
+                        .static_field_values
+                        .as_ref()
+                        .ok_or(ErrorCode(INVALID_CLASS))?;
+                    let tag = descriptor_tag(&descriptor);
+                    let slots = values
+                        .get(offset..offset + slot_width(tag))
+                        .ok_or(ErrorCode(INVALID_FIELDID))?;
+                    write_value(&mut out, global_memory, tag, slots, true);
+                }
+            }
+            // SourceFile
+            7 => {
+                let source_file = class
+                    .as_ref()
+                    .and_then(|class| class.source_file())
+                    .ok_or(ErrorCode(ABSENT_INFORMATION))?;
+                write_string(&mut out, source_file);
+            }
+            // Status
+            9 => write_i4(&mut out, class_status(global_memory, &class_name)),
+            // Interfaces
+            10 => {
+                let interfaces: Vec<String> = class.map_or(vec![], |class| {
+                    class
+                        .interfaces
+                        .iter()
+                        .map(|interface| interface.name.to_owned())
+                        .filter(|interface| is_reference_type(interface, global_memory))
+                        .collect()
+                });
+                write_i4(&mut out, interfaces.len() as i32);
+                for interface in interfaces {
+                    write_u8(&mut out, self.class_id(&interface));
+                }
+            }
+            // ClassObject
+            11 => {
+                let class_ref = global_memory
+                    .method_area
+                    .classes
+                    .get(&class_name)
+                    .and_then(|class| class.get_java_clone())
+                    .ok_or(ErrorCode(INVALID_CLASS))?;
+                write_u8(&mut out, class_ref.0 as u64);
+            }
+            // SourceDebugExtension
+            12 => return Err(ErrorCode(ABSENT_INFORMATION)),
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+
+    fn method_command(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let method = self.method(global_memory, reader.u8()?, reader.u8()?)?;
+        let mut out = vec![];
+        match command {
+            // LineTable
+            1 => {
+                let Some(code) = method.code() else {
+                    write_u8(&mut out, u64::MAX);
+                    write_u8(&mut out, u64::MAX);
+                    write_i4(&mut out, 0);
+                    return Ok(out);
+                };
+                write_u8(&mut out, 0);
+                write_u8(&mut out, code.len().saturating_sub(1) as u64);
+                let lines: Vec<_> = method.line_number_table().collect();
+                write_i4(&mut out, lines.len() as i32);
+                for line in lines {
+                    write_u8(&mut out, line.start_pc as u64);
+                    write_i4(&mut out, line.line_number as i32);
+                }
+            }
+            // VariableTable, VariableTableWithGeneric
+            2 | 5 => {
+                let variables: Vec<_> = method.local_variable_table().collect();
+                if variables.is_empty() {
+                    return Err(ErrorCode(ABSENT_INFORMATION));
+                }
+                let arguments: usize = method
+                    .descriptor
+                    .parameter_descriptors
+                    .iter()
+                    .map(|parameter| parameter.width())
+                    .sum();
+                write_i4(
+                    &mut out,
+                    (arguments + !method.access.r#static as usize) as i32,
+                );
+                write_i4(&mut out, variables.len() as i32);
+                for variable in variables {
+                    write_u8(&mut out, variable.start_pc as u64);
+                    write_string(&mut out, &variable.name);
+                    write_string(&mut out, &variable.descriptor);
+                    if command == 5 {
+                        write_string(&mut out, "");
+                    }
+                    write_i4(&mut out, variable.length as i32);
+                    write_i4(&mut out, variable.index as i32);
+                }
+            }
+            // Bytecodes
+            3 => {
+                let code = method.code().map_or(&[][..], |code| &code[..]);
+                write_i4(&mut out, code.len() as i32);
+                out.extend_from_slice(code);
+            }
+            // IsObsolete: classes are never redefined
+            4 => write_bool(&mut out, false),
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+
+    fn object_reference(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let objectref = reader.reference()?;
+        let mut out = vec![];
+        match command {
+            // ReferenceType
+            1 => {
+                let class_name = class_of(global_memory, objectref)?;
+                write_u1(&mut out, type_tag(global_memory, &class_name));
+                write_u8(&mut out, self.class_id(&class_name));
+            }
+            // GetValues
+            2 => {
+                let class_name = class_of(global_memory, objectref)?;
+                let count = reader.count()?;
+                write_i4(&mut out, count as i32);
+                for _ in 0..count {
+                    let (declaring_class, name, descriptor) =
+                        self.field(global_memory, reader.u8()?)?;
+                    let (offset, _) = global_memory
+                        .method_area
+                        .classes
+                        .get(&class_name)
+                        .and_then(|class| class.as_instance_klass())
+                        .ok_or(ErrorCode(INVALID_OBJECT))?
+                        .find_field(&declaring_class, &name)
+                        .map_err(|_| ErrorCode(INVALID_FIELDID))?;
+                    let tag = descriptor_tag(&descriptor);
+                    let slots = global_memory
+                        .heap
+                        .get(HeapRef(objectref))
+                        .and_then(|heap_item| heap_item.data.get(offset..offset + slot_width(tag)))
+                        .ok_or(ErrorCode(INVALID_FIELDID))?;
+                    write_value(&mut out, global_memory, tag, slots, true);
+                }
+            }
+            // DisableCollection, EnableCollection: the gc doesn't know about the debugger
+            7 | 8 => {}
+            // IsCollected
+            9 => write_bool(
+                &mut out,
+                global_memory.heap.get(HeapRef(objectref)).is_none(),
+            ),
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+
+    fn thread_reference(
+        &mut self,
+        thread: Option<&Thread>,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let thread_ref = reader.reference()?;
+        let mut out = vec![];
+        match command {
+            // Name
+            1 => {
+                let name_ref =
+                    get_field_value(global_memory, thread_ref, "java/lang/Thread", "name")
+                        .map_err(|_| ErrorCode(INVALID_THREAD))?;
+                write_string(
+                    &mut out,
+                    &string_from_java_string(global_memory, HeapRef(name_ref))?,
+                );
+            }
+            // Suspend, Resume: see the top of this file
+            2 => self.suspend_count += 1,
+            3 => self.suspend_count = self.suspend_count.saturating_sub(1),
+            // Status
+            4 => {
+                if is_alive(thread, global_memory, thread_ref) {
+                    write_i4(&mut out, THREAD_STATUS_RUNNING);
+                } else {
+                    write_i4(&mut out, THREAD_STATUS_ZOMBIE);
+                }
+                if self.suspend_count > 0 {
+                    write_i4(&mut out, SUSPEND_STATUS_SUSPENDED);
+                } else {
+                    write_i4(&mut out, 0);
+                }
+            }
+            // ThreadGroup
+            5 => {
+                let group_ref =
+                    get_field_value(global_memory, thread_ref, "java/lang/Thread", "group")
+                        .map_err(|_| ErrorCode(INVALID_THREAD))?;
+                write_u8(&mut out, group_ref as u64);
+            }
+            // Frames, FrameCount
+            6 | 7 => {
+                let frames = match find_thread(thread, global_memory, thread_ref as u64) {
+                    Some(thread) => method_frames(thread),
+                    None if self.suspend_count > 0 => vec![],
+                    None => return Err(ErrorCode(THREAD_NOT_SUSPENDED)),
+                };
+                if command == 7 {
+                    write_i4(&mut out, frames.len() as i32);
+                    return Ok(out);
+                }
+                let start = reader.count()?;
+                let length = match reader.i4()? {
+                    -1 => frames.len().saturating_sub(start),
+                    length => usize::try_from(length).map_err(|_| ErrorCode(ILLEGAL_ARGUMENT))?,
+                };
+                let frames = frames
+                    .get(start..start + length)
+                    .ok_or(ErrorCode(ILLEGAL_ARGUMENT))?;
+                let locations: Vec<(usize, Option<Location>)> = frames
+                    .iter()
+                    .map(|(frame_id, frame)| (*frame_id, frame_location(global_memory, frame)))
+                    .collect();
+                write_i4(&mut out, locations.len() as i32);
+                for (frame_id, location) in locations {
+                    write_u8(&mut out, frame_id as u64);
+                    match location {
+                        Some(location) => self.write_location(&mut out, global_memory, &location),
+                        // the method of a class without a parsed class, like a lambda
+                        None => {
+                            write_u1(&mut out, TYPE_CLASS);
+                            write_u8(&mut out, 0);
+                            write_u8(&mut out, 0);
+                            write_u8(&mut out, u64::MAX);
+                        }
+                    }
+                }
+            }
+            // SuspendCount
+            12 => write_i4(&mut out, self.suspend_count as i32),
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+
+    fn thread_group_reference(
+        &mut self,
+        thread: Option<&Thread>,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let group_ref = reader.reference()?;
+        let field = |name: &str| {
+            get_field_value(global_memory, group_ref, "java/lang/ThreadGroup", name)
+                .map_err(|_| ErrorCode(INVALID_OBJECT))
+        };
+        let mut out = vec![];
+        match command {
+            // Name
+            1 => write_string(
+                &mut out,
+                &string_from_java_string(global_memory, HeapRef(field("name")?))?,
+            ),
+            // Parent
+            2 => write_u8(&mut out, field("parent")? as u64),
+            // Children: the live threads in the group and its subgroups
+            3 => {
+                let mut thread_refs: Vec<HeapRef> =
+                    global_memory.threads.values().copied().collect();
+                thread_refs.extend(thread.and_then(|thread| thread.java_clone));
+                thread_refs.sort();
+                thread_refs.dedup();
+                thread_refs.retain(|thread_ref| {
+                    get_field_value(global_memory, thread_ref.0, "java/lang/Thread", "group")
+                        .is_ok_and(|group| group == group_ref)
+                });
+                write_i4(&mut out, thread_refs.len() as i32);
+                for thread_ref in thread_refs {
+                    write_u8(&mut out, thread_ref.0 as u64);
+                }
+                let groups_ref = field("groups")?;
+                let group_count = field("ngroups")? as usize;
+                let groups = global_memory
+                    .heap
+                    .get(HeapRef(groups_ref))
+                    .map_or(&[][..], |groups| {
+                        &groups.data[..group_count.min(groups.data.len())]
+                    });
+                write_i4(&mut out, groups.len() as i32);
+                for group in groups {
+                    write_u8(&mut out, *group as u64);
+                }
+            }
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+
+    fn array_reference(
+        &mut self,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let array_ref = reader.reference()?;
+        let heap_item = global_memory
+            .heap
+            .get(HeapRef(array_ref))
+            .ok_or(ErrorCode(INVALID_OBJECT))?;
+        let descriptor = global_memory.heap.descriptor(heap_item);
+        if !descriptor.starts_with('[') {
+            return Err(ErrorCode(INVALID_OBJECT));
+        }
+        let length = global_memory.heap.array_length(heap_item);
+        let mut out = vec![];
+        match command {
+            // Length
+            1 => write_i4(&mut out, length as i32),
+            // GetValues
+            2 => {
+                let first = reader.count()?;
+                let count = reader.count()?;
+                if first + count > length {
+                    return Err(ErrorCode(ILLEGAL_ARGUMENT));
+                }
+                let tag = descriptor_tag(&descriptor[1..]);
+                write_u1(&mut out, tag);
+                write_i4(&mut out, count as i32);
+                // primitive elements come without a tag, just their bytes like the array has
+                // them, references with the tag of each one
+                match primitive_element_size(descriptor) {
+                    Some(size) => out
+                        .extend_from_slice(&heap_item.bytes[first * size..(first + count) * size]),
+                    None => {
+                        for element in heap_item.data[first..first + count].iter() {
+                            write_value(&mut out, global_memory, tag, &[*element], true);
+                        }
+                    }
+                }
+            }
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+
+    fn event_request(
+        &mut self,
+        thread: Option<&Thread>,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let mut out = vec![];
+        match command {
+            // Set
+            1 => {
+                let event_kind = reader.u1()?;
+                let suspend_policy = reader.u1()?;
+                let mut modifiers = vec![];
+                for _ in 0..reader.count()? {
+                    modifiers.push(match reader.u1()? {
+                        1 => Modifier::Count(reader.i4()?),
+                        // Conditional
+                        2 => {
+                            reader.i4()?;
+                            Modifier::Ignored
+                        }
+                        3 => Modifier::ThreadOnly(reader.u8()?),
+                        4 => Modifier::ClassOnly(self.class_name(reader.u8()?)?),
+                        5 => Modifier::ClassMatch(reader.string()?),
+                        6 => Modifier::ClassExclude(reader.string()?),
+                        7 => Modifier::LocationOnly(self.read_location(reader)?),
+                        // ExceptionOnly
+                        8 => {
+                            reader.u8()?;
+                            reader.bool()?;
+                            reader.bool()?;
+                            Modifier::Ignored
+                        }
+                        // FieldOnly
+                        9 => {
+                            reader.u8()?;
+                            reader.u8()?;
+                            Modifier::Ignored
+                        }
+                        10 => {
+                            let thread_ref = reader.u8()?;
+                            let size = reader.i4()?;
+                            let depth = reader.i4()?;
+                            let stepping = find_thread(thread, global_memory, thread_ref)
+                                .ok_or(ErrorCode(THREAD_NOT_SUSPENDED))?;
+                            let frame = stepping
+                                .thread_memory
+                                .jvm_stack
+                                .last()
+                                .ok_or(ErrorCode(INVALID_THREAD))?;
+                            Modifier::Step(Step {
+                                thread: thread_ref,
+                                size,
+                                depth,
+                                frames: stepping.thread_memory.jvm_stack.len(),
+                                line: frame.method.as_ref().and_then(|method| {
+                                    method.line_number(frame.instruction_counter)
+                                }),
+                                index: frame.instruction_counter as u64,
+                            })
+                        }
+                        // InstanceOnly
+                        11 => {
+                            reader.u8()?;
+                            Modifier::Ignored
+                        }
+                        // SourceNameMatch
+                        12 => {
+                            reader.string()?;
+                            Modifier::Ignored
+                        }
+                        _ => return Err(ErrorCode(ILLEGAL_ARGUMENT)),
+                    });
+                }
+                let id = self.next_request_id;
+                self.next_request_id += 1;
+                self.requests.push(EventRequest {
+                    id,
+                    event_kind,
+                    suspend_policy,
+                    modifiers,
+                });
+                write_i4(&mut out, id);
+            }
+            // Clear
+            2 => {
+                let event_kind = reader.u1()?;
+                let id = reader.i4()?;
+                self.requests
+                    .retain(|request| request.event_kind != event_kind || request.id != id);
+            }
+            // ClearAllBreakpoints
+            3 => self
+                .requests
+                .retain(|request| request.event_kind != BREAKPOINT),
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+
+    fn stack_frame(
+        &mut self,
+        thread: Option<&Thread>,
+        global_memory: &mut GlobalMemory,
+        command: u8,
+        reader: &mut Reader,
+    ) -> Reply {
+        let thread_ref = reader.u8()?;
+        let frame_id = reader.u8()?;
+        let frame = find_thread(thread, global_memory, thread_ref)
+            .ok_or(ErrorCode(THREAD_NOT_SUSPENDED))?
+            .thread_memory
+            .jvm_stack
+            .get(frame_id as usize)
+            .filter(|frame| frame.method.is_some())
+            .ok_or(ErrorCode(INVALID_FRAMEID))?;
+        let mut out = vec![];
+        match command {
+            // GetValues
+            1 => {
+                let count = reader.count()?;
+                write_i4(&mut out, count as i32);
+                for _ in 0..count {
+                    let slot = reader.count()?;
+                    let tag = reader.u1()?;
+                    let slots = frame
+                        .local_variables
+                        .get(slot..slot + slot_width(tag))
+                        .ok_or(ErrorCode(INVALID_SLOT))?;
+                    write_value(&mut out, global_memory, tag, slots, true);
+                }
+            }
+            // ThisObject
+            3 => {
+                let is_static = frame
+                    .method
+                    .as_ref()
+                    .is_some_and(|method| method.access.r#static);
+                let this_ref = match frame.local_variables.first() {
+                    Some(this_ref) if !is_static => *this_ref,
+                    _ => 0,
+                };
+                write_value(&mut out, global_memory, TAG_OBJECT, &[this_ref], true);
+            }
+            _ => return Err(ErrorCode(NOT_IMPLEMENTED)),
+        }
+        Ok(out)
+    }
+}
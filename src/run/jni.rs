@@ -0,0 +1,1070 @@
+// Native methods of JNI shared libraries, as loaded by System.loadLibrary. The libraries are opened
+// with dlopen and their Java_<class>_<method> symbols looked up with dlsym, libc being the only
+// dependency at hand for it: neither libloading nor a foreign function interface like libffi can
+// be added. Natives are called through a function pointer taking as many integer and
+// floating-point arguments as the C calling convention passes in registers, which works for any
+// native whose arguments all fit in them on x86-64 and AArch64, but calling a function through a
+// pointer of another type is undefined behaviour as far as Rust is concerned. That's why this
+// only happens with the jni feature, without it loadLibrary throws an UnsatisfiedLinkError. A
+// native with more arguments throws one when called, and so does loadLibrary on platforms whose
+// calling convention isn't known here. Only the JNI functions needed for strings, arrays,
+// exceptions and registering natives are available; calling any other one throws an
+// InternalError.
+//
+// https://docs.oracle.com/en/java/javase/11/docs/specs/jni/index.html
+
+use std::{
+    error::Error,
+    ffi::{c_char, c_void, CStr, CString},
+};
+
+use crate::parse::{decode_modified_utf8, encode_modified_utf8};
+
+use super::{
+    java_string_chars, java_string_from_chars, primitive_element_size, set_field_value, slots,
+    string_from_java_string, GlobalMemory, HeapRef, JavaValue, NativeMethod, Thread,
+};
+
+const JNI_OK: i32 = 0;
+const JNI_ERR: i32 = -1;
+const JNI_VERSION_1_1: i32 = 0x00010001;
+const JNI_VERSION_10: i32 = 0x000a0000;
+// the modes of the Release<PrimitiveType>ArrayElements functions
+const JNI_COMMIT: i32 = 1;
+const JNI_ABORT: i32 = 2;
+
+const FUNCTION_COUNT: usize = 234;
+
+// the JNIEnv handed to natives. Like the one of jni.h it starts with the function table, the
+// global memory after it is what our JNI functions operate on. An exception thrown by them is
+// pending on the thread calling the native, just like one thrown by a builtin native.
+#[repr(C)]
+struct JniEnv {
+    functions: *const [*const c_void; FUNCTION_COUNT],
+    global_memory: *mut GlobalMemory,
+    thread: *mut Thread,
+}
+
+// the JavaVM handed to JNI_OnLoad, which is only good for calling GetEnv during JNI_OnLoad
+#[repr(C)]
+struct JavaVm {
+    functions: *const [*const c_void; 8],
+    env: *mut JniEnv,
+}
+
+// an entry of the array passed to RegisterNatives
+#[repr(C)]
+struct JniNativeMethod {
+    name: *const c_char,
+    signature: *const c_char,
+    function: *const c_void,
+}
+
+// references are handed out as the number of their handle, null being 0
+fn object(reference: HeapRef) -> *mut c_void {
+    reference.0 as usize as *mut c_void
+}
+
+fn reference(object: *mut c_void) -> HeapRef {
+    HeapRef(object as usize as u32)
+}
+
+unsafe fn global_memory<'a>(env: *mut JniEnv) -> &'a mut GlobalMemory {
+    &mut *(*env).global_memory
+}
+
+unsafe fn thread<'a>(env: *mut JniEnv) -> &'a mut Thread {
+    &mut *(*env).thread
+}
+
+// misusing JNI is undefined behaviour, so the vm gives up like hotspot does with -Xcheck:jni
+fn fatal_error_message(message: &str) -> ! {
+    eprintln!("FATAL ERROR in native method: {message}");
+    std::process::abort();
+}
+
+fn or_fatal<T>(result: Result<T, Box<dyn Error>>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(error) => fatal_error_message(&format!("{error}")),
+    }
+}
+
+// the function the JNIEnv table holds in place of the ones we don't support. All of them take
+// the JNIEnv first; whatever they return is 0 or null, which is what JNI functions return once
+// they threw.
+unsafe extern "C" fn unsupported_function(env: *mut JniEnv) -> usize {
+    or_fatal(thread(env).throw_exception_with_message(
+        global_memory(env),
+        "java/lang/InternalError",
+        "JNI function not supported",
+    ));
+    0
+}
+
+// the one of the JavaVM table, whose functions take the JavaVM first
+unsafe extern "C" fn unsupported_invoke_function(_vm: *mut JavaVm) -> i32 {
+    JNI_ERR
+}
+
+unsafe extern "C" fn get_version(_env: *mut JniEnv) -> i32 {
+    JNI_VERSION_10
+}
+
+// the pending exception is where throw_exception leaves it, on top of the operand stack of the
+// native's frame
+unsafe extern "C" fn exception_occurred(env: *mut JniEnv) -> *mut c_void {
+    let thread = thread(env);
+    if !thread.is_throwing {
+        return object(HeapRef::NULL);
+    }
+    let exception = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .and_then(|frame| frame.operand_stack.last())
+        .unwrap_or_else(|| fatal_error_message("pending exception missing"));
+    object(HeapRef(*exception))
+}
+
+unsafe extern "C" fn exception_clear(env: *mut JniEnv) {
+    or_fatal(thread(env).drop_exception());
+}
+
+unsafe extern "C" fn exception_check(env: *mut JniEnv) -> u8 {
+    thread(env).is_throwing as u8
+}
+
+unsafe extern "C" fn fatal_error(_env: *mut JniEnv, message: *const c_char) {
+    fatal_error_message(&CStr::from_ptr(message).to_string_lossy());
+}
+
+// local references stay valid for as long as the native runs, as the gc doesn't collect in the
+// meantime, so there is nothing to manage
+unsafe extern "C" fn push_local_frame(_env: *mut JniEnv, _capacity: i32) -> i32 {
+    JNI_OK
+}
+
+unsafe extern "C" fn pop_local_frame(_env: *mut JniEnv, result: *mut c_void) -> *mut c_void {
+    result
+}
+
+unsafe extern "C" fn new_reference(_env: *mut JniEnv, object: *mut c_void) -> *mut c_void {
+    object
+}
+
+unsafe extern "C" fn delete_reference(_env: *mut JniEnv, _object: *mut c_void) {}
+
+// global references have to keep their object alive though, see gc
+unsafe extern "C" fn new_global_reference(env: *mut JniEnv, object: *mut c_void) -> *mut c_void {
+    if !reference(object).is_null() {
+        global_memory(env)
+            .jni_global_references
+            .push(reference(object));
+    }
+    object
+}
+
+unsafe extern "C" fn delete_global_reference(env: *mut JniEnv, object: *mut c_void) {
+    let global_references = &mut global_memory(env).jni_global_references;
+    if let Some(index) = global_references
+        .iter()
+        .position(|objectref| *objectref == reference(object))
+    {
+        global_references.swap_remove(index);
+    }
+}
+
+unsafe extern "C" fn is_same_object(_env: *mut JniEnv, a: *mut c_void, b: *mut c_void) -> u8 {
+    (a == b) as u8
+}
+
+unsafe extern "C" fn ensure_local_capacity(_env: *mut JniEnv, _capacity: i32) -> i32 {
+    JNI_OK
+}
+
+fn class_object(
+    global_memory: &GlobalMemory,
+    objectref: HeapRef,
+) -> Result<HeapRef, Box<dyn Error>> {
+    let class_name = global_memory
+        .heap
+        .class_name(objectref)
+        .ok_or("object not on heap")?
+        .to_owned();
+    Ok(global_memory
+        .method_area
+        .classes
+        .get(&class_name)
+        .ok_or(format!("Class not found {} :(", class_name))?
+        .get_java_clone()
+        .ok_or("class without java clone")?)
+}
+
+unsafe extern "C" fn get_object_class(env: *mut JniEnv, object: *mut c_void) -> *mut c_void {
+    self::object(or_fatal(class_object(
+        global_memory(env),
+        reference(object),
+    )))
+}
+
+// the UTF functions of JNI take and return modified UTF-8, like class files use
+unsafe extern "C" fn new_string_utf(env: *mut JniEnv, bytes: *const c_char) -> *mut c_void {
+    let chars = or_fatal(decode_modified_utf8(CStr::from_ptr(bytes).to_bytes()));
+    object(or_fatal(java_string_from_chars(global_memory(env), &chars)))
+}
+
+unsafe extern "C" fn get_string_length(env: *mut JniEnv, string: *mut c_void) -> i32 {
+    let chars = or_fatal(java_string_chars(global_memory(env), reference(string)));
+    chars.len() as i32
+}
+
+unsafe extern "C" fn get_string_utf_length(env: *mut JniEnv, string: *mut c_void) -> i32 {
+    let chars = or_fatal(java_string_chars(global_memory(env), reference(string)));
+    encode_modified_utf8(&chars).len() as i32
+}
+
+unsafe extern "C" fn get_string_utf_chars(
+    env: *mut JniEnv,
+    string: *mut c_void,
+    is_copy: *mut u8,
+) -> *const c_char {
+    let chars = or_fatal(java_string_chars(global_memory(env), reference(string)));
+    if !is_copy.is_null() {
+        *is_copy = 1;
+    }
+    // the null char is encoded as two bytes, so there are no zeros in between
+    or_fatal(CString::new(encode_modified_utf8(&chars)).map_err(|e| e.into())).into_raw()
+}
+
+unsafe extern "C" fn release_string_utf_chars(
+    _env: *mut JniEnv,
+    _string: *mut c_void,
+    chars: *const c_char,
+) {
+    drop(CString::from_raw(chars as *mut c_char));
+}
+
+// the elements of an array of references
+fn array_slots(
+    global_memory: &mut GlobalMemory,
+    array: *mut c_void,
+) -> Result<&mut Vec<u32>, Box<dyn Error>> {
+    let descriptor = global_memory
+        .heap
+        .field_descriptor(reference(array))
+        .ok_or("array not on heap")?;
+    if !descriptor.starts_with('[') || primitive_element_size(descriptor).is_some() {
+        return Err(format!("{descriptor} is not an array of references").into());
+    }
+    let heap_item = global_memory
+        .heap
+        .get_mut(reference(array))
+        .ok_or("array not on heap")?;
+    Ok(&mut heap_item.data)
+}
+
+// the bytes of the elements of an array of primitives
+fn array_bytes(
+    global_memory: &mut GlobalMemory,
+    array: *mut c_void,
+) -> Result<&mut Vec<u8>, Box<dyn Error>> {
+    let descriptor = global_memory
+        .heap
+        .field_descriptor(reference(array))
+        .ok_or("array not on heap")?;
+    if primitive_element_size(descriptor).is_none() {
+        return Err(format!("{descriptor} is not an array of primitives").into());
+    }
+    let heap_item = global_memory
+        .heap
+        .get_mut(reference(array))
+        .ok_or("array not on heap")?;
+    Ok(&mut heap_item.bytes)
+}
+
+unsafe extern "C" fn get_array_length(env: *mut JniEnv, array: *mut c_void) -> i32 {
+    let global_memory = global_memory(env);
+    match global_memory.heap.get(reference(array)) {
+        Some(heap_item) => global_memory.heap.array_length(heap_item) as i32,
+        None => fatal_error_message("array not on heap"),
+    }
+}
+
+unsafe extern "C" fn get_object_array_element(
+    env: *mut JniEnv,
+    array: *mut c_void,
+    index: i32,
+) -> *mut c_void {
+    let slots = or_fatal(array_slots(global_memory(env), array));
+    match slots.get(index as usize) {
+        Some(element) => object(HeapRef(*element)),
+        None => fatal_error_message(&format!("array index {index} out of bounds")),
+    }
+}
+
+unsafe extern "C" fn set_object_array_element(
+    env: *mut JniEnv,
+    array: *mut c_void,
+    index: i32,
+    value: *mut c_void,
+) {
+    let slots = or_fatal(array_slots(global_memory(env), array));
+    match slots.get_mut(index as usize) {
+        Some(element) => *element = reference(value).0,
+        None => fatal_error_message(&format!("array index {index} out of bounds")),
+    }
+}
+
+// the C types of the elements of primitive arrays, e.g. jint for [I, which take as many bytes as
+// the elements do in the array
+trait Primitive: Copy {
+    const ARRAY_TYPE: &'static str;
+    fn from_be_bytes(bytes: &[u8]) -> Self;
+    fn be_bytes(self) -> Vec<u8>;
+}
+
+impl Primitive for u8 {
+    const ARRAY_TYPE: &'static str = "[Z";
+    fn from_be_bytes(bytes: &[u8]) -> u8 {
+        bytes[0]
+    }
+    fn be_bytes(self) -> Vec<u8> {
+        vec![self]
+    }
+}
+
+impl Primitive for i8 {
+    const ARRAY_TYPE: &'static str = "[B";
+    fn from_be_bytes(bytes: &[u8]) -> i8 {
+        bytes[0] as i8
+    }
+    fn be_bytes(self) -> Vec<u8> {
+        vec![self as u8]
+    }
+}
+
+impl Primitive for u16 {
+    const ARRAY_TYPE: &'static str = "[C";
+    fn from_be_bytes(bytes: &[u8]) -> u16 {
+        u16::from_be_bytes([bytes[0], bytes[1]])
+    }
+    fn be_bytes(self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+impl Primitive for i16 {
+    const ARRAY_TYPE: &'static str = "[S";
+    fn from_be_bytes(bytes: &[u8]) -> i16 {
+        i16::from_be_bytes([bytes[0], bytes[1]])
+    }
+    fn be_bytes(self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+impl Primitive for i32 {
+    const ARRAY_TYPE: &'static str = "[I";
+    fn from_be_bytes(bytes: &[u8]) -> i32 {
+        i32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+    fn be_bytes(self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+impl Primitive for i64 {
+    const ARRAY_TYPE: &'static str = "[J";
+    fn from_be_bytes(bytes: &[u8]) -> i64 {
+        i64::from_be_bytes(bytes[..8].try_into().unwrap())
+    }
+    fn be_bytes(self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+impl Primitive for f32 {
+    const ARRAY_TYPE: &'static str = "[F";
+    fn from_be_bytes(bytes: &[u8]) -> f32 {
+        f32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+    }
+    fn be_bytes(self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+impl Primitive for f64 {
+    const ARRAY_TYPE: &'static str = "[D";
+    fn from_be_bytes(bytes: &[u8]) -> f64 {
+        f64::from_be_bytes(bytes[..8].try_into().unwrap())
+    }
+    fn be_bytes(self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
+unsafe extern "C" fn new_array<T: Primitive>(env: *mut JniEnv, length: i32) -> *mut c_void {
+    if length < 0 {
+        fatal_error_message(&format!("negative array size {length}"));
+    }
+    let global_memory = global_memory(env);
+    or_fatal(global_memory.ensure_array(T::ARRAY_TYPE.to_owned()));
+    object(
+        global_memory
+            .heap
+            .store_array(T::ARRAY_TYPE, length as usize),
+    )
+}
+
+fn read_elements<T: Primitive>(
+    global_memory: &mut GlobalMemory,
+    array: *mut c_void,
+) -> Result<Vec<T>, Box<dyn Error>> {
+    let bytes = array_bytes(global_memory, array)?;
+    Ok(bytes
+        .chunks_exact(size_of::<T>())
+        .map(T::from_be_bytes)
+        .collect())
+}
+
+fn write_elements<T: Primitive>(
+    global_memory: &mut GlobalMemory,
+    array: *mut c_void,
+    start: usize,
+    elements: &[T],
+) -> Result<(), Box<dyn Error>> {
+    let bytes = array_bytes(global_memory, array)?;
+    let size = size_of::<T>();
+    if (start + elements.len()) * size > bytes.len() {
+        return Err("array region out of bounds".into());
+    }
+    for (i, element) in elements.iter().enumerate() {
+        let index = (start + i) * size;
+        bytes[index..index + size].copy_from_slice(&element.be_bytes());
+    }
+    Ok(())
+}
+
+// the elements are always copied into a buffer of their own, which is freed again on release
+unsafe extern "C" fn get_array_elements<T: Primitive>(
+    env: *mut JniEnv,
+    array: *mut c_void,
+    is_copy: *mut u8,
+) -> *mut T {
+    let elements = or_fatal(read_elements::<T>(global_memory(env), array));
+    if !is_copy.is_null() {
+        *is_copy = 1;
+    }
+    Box::into_raw(elements.into_boxed_slice()) as *mut T
+}
+
+unsafe extern "C" fn release_array_elements<T: Primitive>(
+    env: *mut JniEnv,
+    array: *mut c_void,
+    elements: *mut T,
+    mode: i32,
+) {
+    let global_memory = global_memory(env);
+    let length = or_fatal(array_bytes(global_memory, array)).len() / size_of::<T>();
+    let buffer = std::ptr::slice_from_raw_parts_mut(elements, length);
+    if mode != JNI_ABORT {
+        or_fatal(write_elements(global_memory, array, 0, &*buffer));
+    }
+    if mode != JNI_COMMIT {
+        drop(Box::from_raw(buffer));
+    }
+}
+
+unsafe extern "C" fn get_array_region<T: Primitive>(
+    env: *mut JniEnv,
+    array: *mut c_void,
+    start: i32,
+    length: i32,
+    buffer: *mut T,
+) {
+    let elements = or_fatal(read_elements::<T>(global_memory(env), array));
+    match elements.get(start as usize..start as usize + length as usize) {
+        Some(region) if start >= 0 && length >= 0 => {
+            std::ptr::copy_nonoverlapping(region.as_ptr(), buffer, region.len())
+        }
+        _ => fatal_error_message("array region out of bounds"),
+    }
+}
+
+unsafe extern "C" fn set_array_region<T: Primitive>(
+    env: *mut JniEnv,
+    array: *mut c_void,
+    start: i32,
+    length: i32,
+    buffer: *const T,
+) {
+    if start < 0 || length < 0 {
+        fatal_error_message("array region out of bounds");
+    }
+    let region = std::slice::from_raw_parts(buffer, length as usize);
+    or_fatal(write_elements(
+        global_memory(env),
+        array,
+        start as usize,
+        region,
+    ));
+}
+
+unsafe extern "C" fn register_natives(
+    env: *mut JniEnv,
+    class: *mut c_void,
+    methods: *const JniNativeMethod,
+    count: i32,
+) -> i32 {
+    let global_memory = global_memory(env);
+    let class_name = global_memory
+        .method_area
+        .classes
+        .iter()
+        .find(|(_, klass)| klass.get_java_clone() == Some(reference(class)))
+        .map(|(name, _)| name.to_owned())
+        .unwrap_or_else(|| fatal_error_message("RegisterNatives on an unknown class"));
+    for method in std::slice::from_raw_parts(methods, count as usize) {
+        global_memory.natives.insert(
+            (
+                class_name.to_owned(),
+                CStr::from_ptr(method.name).to_string_lossy().into_owned(),
+                CStr::from_ptr(method.signature)
+                    .to_string_lossy()
+                    .into_owned(),
+            ),
+            NativeMethod::Jni(method.function as usize),
+        );
+    }
+    JNI_OK
+}
+
+fn function_table() -> [*const c_void; FUNCTION_COUNT] {
+    let mut functions = [unsupported_function as *const c_void; FUNCTION_COUNT];
+    functions[4] = get_version as *const c_void;
+    functions[15] = exception_occurred as *const c_void;
+    functions[17] = exception_clear as *const c_void;
+    functions[18] = fatal_error as *const c_void;
+    functions[19] = push_local_frame as *const c_void;
+    functions[20] = pop_local_frame as *const c_void;
+    functions[21] = new_global_reference as *const c_void;
+    functions[22] = delete_global_reference as *const c_void;
+    functions[23] = delete_reference as *const c_void;
+    functions[24] = is_same_object as *const c_void;
+    functions[25] = new_reference as *const c_void;
+    functions[26] = ensure_local_capacity as *const c_void;
+    functions[31] = get_object_class as *const c_void;
+    functions[164] = get_string_length as *const c_void;
+    functions[167] = new_string_utf as *const c_void;
+    functions[168] = get_string_utf_length as *const c_void;
+    functions[169] = get_string_utf_chars as *const c_void;
+    functions[170] = release_string_utf_chars as *const c_void;
+    functions[171] = get_array_length as *const c_void;
+    functions[173] = get_object_array_element as *const c_void;
+    functions[174] = set_object_array_element as *const c_void;
+    // New<PrimitiveType>Array up to Set<PrimitiveType>ArrayRegion come in groups of eight, in the
+    // order boolean, byte, char, short, int, long, float, double
+    let primitive_functions: [[*const c_void; 5]; 8] = [
+        primitive_array_functions::<u8>(),
+        primitive_array_functions::<i8>(),
+        primitive_array_functions::<u16>(),
+        primitive_array_functions::<i16>(),
+        primitive_array_functions::<i32>(),
+        primitive_array_functions::<i64>(),
+        primitive_array_functions::<f32>(),
+        primitive_array_functions::<f64>(),
+    ];
+    for (i, group) in primitive_functions.iter().enumerate() {
+        for (j, function) in group.iter().enumerate() {
+            functions[175 + j * 8 + i] = *function;
+        }
+    }
+    functions[215] = register_natives as *const c_void;
+    functions[228] = exception_check as *const c_void;
+    functions
+}
+
+fn primitive_array_functions<T: Primitive>() -> [*const c_void; 5] {
+    [
+        new_array::<T> as *const c_void,
+        get_array_elements::<T> as *const c_void,
+        release_array_elements::<T> as *const c_void,
+        get_array_region::<T> as *const c_void,
+        set_array_region::<T> as *const c_void,
+    ]
+}
+
+unsafe extern "C" fn get_env(vm: *mut JavaVm, env: *mut *mut c_void, _version: i32) -> i32 {
+    *env = (*vm).env as *mut c_void;
+    JNI_OK
+}
+
+unsafe extern "C" fn attach_current_thread(
+    vm: *mut JavaVm,
+    env: *mut *mut c_void,
+    _args: *mut c_void,
+) -> i32 {
+    *env = (*vm).env as *mut c_void;
+    JNI_OK
+}
+
+unsafe extern "C" fn detach_current_thread(_vm: *mut JavaVm) -> i32 {
+    JNI_OK
+}
+
+fn invoke_interface_table() -> [*const c_void; 8] {
+    let mut functions = [unsupported_invoke_function as *const c_void; 8];
+    functions[4] = attach_current_thread as *const c_void;
+    functions[5] = detach_current_thread as *const c_void;
+    functions[6] = get_env as *const c_void;
+    functions[7] = attach_current_thread as *const c_void;
+    functions
+}
+
+// the escapes of JNI's name mangling, e.g. java/lang/String becomes java_lang_String
+fn mangle(name: &str) -> String {
+    let mut mangled = String::new();
+    for c in name.chars() {
+        match c {
+            '/' => mangled.push('_'),
+            '_' => mangled.push_str("_1"),
+            ';' => mangled.push_str("_2"),
+            '[' => mangled.push_str("_3"),
+            c if c.is_ascii_alphanumeric() => mangled.push(c),
+            c => {
+                let mut units = [0u16; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    mangled.push_str(&format!("_0{:04x}", unit));
+                }
+            }
+        }
+    }
+    mangled
+}
+
+fn symbol(handle: *mut c_void, name: &str) -> Option<*const c_void> {
+    let name = CString::new(name).ok()?;
+    let address = unsafe { libc::dlsym(handle, name.as_ptr()) };
+    if address.is_null() {
+        None
+    } else {
+        Some(address as *const c_void)
+    }
+}
+
+// looks up the native in the loaded libraries, first by its short name, then by the long one
+// including the argument types, which is used for overloaded natives
+pub(super) fn find_native(
+    global_memory: &GlobalMemory,
+    class_name: &str,
+    method_name: &str,
+    descriptor: &str,
+) -> Option<*const c_void> {
+    let short_name = format!("Java_{}_{}", mangle(class_name), mangle(method_name));
+    let arguments = descriptor
+        .strip_prefix('(')
+        .and_then(|descriptor| descriptor.split(')').next())
+        .unwrap_or("");
+    let long_name = format!("{short_name}__{}", mangle(arguments));
+    global_memory.native_libraries.iter().find_map(|handle| {
+        symbol(*handle as *mut c_void, &short_name)
+            .or_else(|| symbol(*handle as *mut c_void, &long_name))
+    })
+}
+
+// the arguments passed in registers by the calling conventions of x86-64 System V and AArch64. The
+// natives are always called with eight integer registers, the ones beyond the first six go onto
+// the stack on x86-64, where they are simply ignored.
+#[cfg(target_arch = "x86_64")]
+const INTEGER_REGISTERS: usize = 6;
+#[cfg(target_arch = "aarch64")]
+const INTEGER_REGISTERS: usize = 8;
+#[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+const INTEGER_REGISTERS: usize = 0;
+const FLOAT_REGISTERS: usize = 8;
+
+type IntegerReturningNative = unsafe extern "C" fn(
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+) -> u64;
+type FloatReturningNative = unsafe extern "C" fn(
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    usize,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+    f64,
+) -> f64;
+
+// calls the native of the current frame, whose arguments are in its local variables, and pushes
+// the result onto the operand stack of the invoker
+pub(super) fn run_jni_native(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    function: *const c_void,
+) -> Result<(), Box<dyn Error>> {
+    // there are none without it, as loadLibrary fails
+    if !cfg!(feature = "jni") {
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/UnsatisfiedLinkError",
+            "matebabe was built without the jni feature",
+        );
+    }
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?;
+    let method = current_frame
+        .method
+        .clone()
+        .ok_or("native frame without a method")?;
+
+    // the JNIEnv goes first, see below
+    let mut integers = vec![0];
+    let mut floats = vec![];
+    let mut index = 0;
+    if method.access.r#static {
+        // static natives get their class instead of this
+        let class = global_memory
+            .method_area
+            .classes
+            .by_id(current_frame.class_id)
+            .ok_or(format!("Class not found {} :(", current_frame.class_name()))?
+            .get_java_clone()
+            .ok_or("class without java clone")?;
+        integers.push(class.0 as usize);
+    } else {
+        integers.push(
+            *current_frame
+                .local_variables
+                .first()
+                .ok_or("no item in local_variables")? as usize,
+        );
+        index = 1;
+    }
+    for field_type in method.descriptor.parameter_descriptors.iter() {
+        let slots = current_frame
+            .local_variables
+            .get(index..index + field_type.width())
+            .ok_or("no item in local_variables")?;
+        index += field_type.width();
+        // integral arguments are extended to the full register, like C compilers expect
+        match JavaValue::from_slots(field_type, slots)? {
+            JavaValue::Boolean(value) => integers.push(value as usize),
+            JavaValue::Byte(value) => integers.push(value as isize as usize),
+            JavaValue::Char(value) => integers.push(value as usize),
+            JavaValue::Short(value) => integers.push(value as isize as usize),
+            JavaValue::Int(value) => integers.push(value as isize as usize),
+            JavaValue::Long(value) => integers.push(value as usize),
+            JavaValue::Reference(value) => integers.push(value.0 as usize),
+            // a float is passed in the low bits of a floating-point register
+            JavaValue::Float(value) => floats.push(f64::from_bits(value.to_bits() as u64)),
+            JavaValue::Double(value) => floats.push(value),
+            JavaValue::Void => return Err("void argument".into()),
+        }
+    }
+    if integers.len() > INTEGER_REGISTERS || floats.len() > FLOAT_REGISTERS {
+        let message = format!(
+            "{}.{}{} has too many arguments to be called",
+            current_frame.class_name(),
+            method.name,
+            method.descriptor.descriptor()
+        );
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/UnsatisfiedLinkError",
+            &message,
+        );
+    }
+    let mut env = JniEnv {
+        functions: &function_table(),
+        global_memory,
+        thread,
+    };
+    integers[0] = &mut env as *mut JniEnv as usize;
+    integers.resize(8, 0);
+    floats.resize(8, 0.0);
+    let i = &integers;
+    let f = &floats;
+
+    let return_descriptor = method.descriptor.return_descriptor.clone();
+    let result = match &return_descriptor {
+        crate::parse::ReturnDescriptor::FieldType(
+            field_type @ (crate::parse::FieldType::Float | crate::parse::FieldType::Double),
+        ) => {
+            let function: FloatReturningNative = unsafe { std::mem::transmute(function) };
+            let value = unsafe {
+                function(
+                    i[0], i[1], i[2], i[3], i[4], i[5], i[6], i[7], f[0], f[1], f[2], f[3], f[4],
+                    f[5], f[6], f[7],
+                )
+            };
+            if *field_type == crate::parse::FieldType::Float {
+                Some(JavaValue::Float(f32::from_bits(value.to_bits() as u32)))
+            } else {
+                Some(JavaValue::Double(value))
+            }
+        }
+        _ => {
+            let function: IntegerReturningNative = unsafe { std::mem::transmute(function) };
+            let value = unsafe {
+                function(
+                    i[0], i[1], i[2], i[3], i[4], i[5], i[6], i[7], f[0], f[1], f[2], f[3], f[4],
+                    f[5], f[6], f[7],
+                )
+            };
+            // only the bits of the return type are defined, the rest of the register isn't
+            match &return_descriptor {
+                crate::parse::ReturnDescriptor::VoidDescriptor => None,
+                crate::parse::ReturnDescriptor::FieldType(field_type) => Some(match field_type {
+                    crate::parse::FieldType::Boolean => JavaValue::Boolean(value as u8 != 0),
+                    crate::parse::FieldType::Byte => JavaValue::Byte(value as i8),
+                    crate::parse::FieldType::Char => JavaValue::Char(value as u16),
+                    crate::parse::FieldType::Short => JavaValue::Short(value as i16),
+                    crate::parse::FieldType::Integer => JavaValue::Int(value as i32),
+                    crate::parse::FieldType::LongInteger => JavaValue::Long(value as i64),
+                    _ => JavaValue::Reference(HeapRef(value as u32)),
+                }),
+            }
+        }
+    };
+
+    if thread.is_throwing {
+        // the exception is passed on from the native's frame, whatever it returned
+        return Ok(());
+    }
+    if let (Some(result), crate::parse::ReturnDescriptor::FieldType(field_type)) =
+        (result, return_descriptor)
+    {
+        return thread.return_value(&result.slots(&field_type)?);
+    }
+    Ok(())
+}
+
+fn dlerror() -> String {
+    let message = unsafe { libc::dlerror() };
+    if message.is_null() {
+        "unknown error".to_owned()
+    } else {
+        unsafe { CStr::from_ptr(message) }
+            .to_string_lossy()
+            .into_owned()
+    }
+}
+
+const NATIVE_LIBRARY: &str = "java/lang/ClassLoader$NativeLibrary";
+
+// boolean load0(String name, boolean isBuiltin) opens the library and runs its JNI_OnLoad
+pub(super) fn native_library_load0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let name_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let is_builtin = *current_frame
+        .local_variables
+        .get(2)
+        .ok_or("no item in local_variables")?;
+    // findBuiltinLib never finds a library linked into the vm
+    if is_builtin != 0 {
+        return thread.return_value(&[0]);
+    }
+
+    let name = string_from_java_string(global_memory, HeapRef(name_ref))?;
+    if !cfg!(feature = "jni") {
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/UnsatisfiedLinkError",
+            &format!("{name}: matebabe was built without the jni feature"),
+        );
+    }
+    // none of its natives could be called
+    if INTEGER_REGISTERS == 0 {
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/UnsatisfiedLinkError",
+            &format!("{name}: natives can't be called on this platform"),
+        );
+    }
+    let path = CString::new(name.to_owned())?;
+    let handle = unsafe { libc::dlopen(path.as_ptr(), libc::RTLD_LAZY) };
+    if handle.is_null() {
+        let message = format!("{name}: {}", dlerror());
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/UnsatisfiedLinkError",
+            &message,
+        );
+    }
+    global_memory.native_libraries.push(handle as usize);
+
+    let mut jni_version = JNI_VERSION_1_1;
+    if let Some(on_load) = symbol(handle, "JNI_OnLoad") {
+        let mut env = JniEnv {
+            functions: &function_table(),
+            global_memory,
+            thread,
+        };
+        let mut vm = JavaVm {
+            functions: &invoke_interface_table(),
+            env: &mut env,
+        };
+        let on_load: unsafe extern "C" fn(*mut JavaVm, *mut c_void) -> i32 =
+            unsafe { std::mem::transmute(on_load) };
+        jni_version = unsafe { on_load(&mut vm, std::ptr::null_mut()) };
+    }
+
+    let handle = handle as u64;
+    set_field_value(
+        global_memory,
+        this_ref,
+        NATIVE_LIBRARY,
+        "jniVersion",
+        jni_version as u32,
+    )?;
+    set_long_field_value(global_memory, this_ref, "handle", handle)?;
+    thread.return_value(&[1])
+}
+
+fn set_long_field_value(
+    global_memory: &mut GlobalMemory,
+    objectref: u32,
+    field_name: &str,
+    value: u64,
+) -> Result<(), Box<dyn Error>> {
+    let offset = global_memory
+        .method_area
+        .classes
+        .get(NATIVE_LIBRARY)
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .field_offset_with_strings(NATIVE_LIBRARY.to_owned(), field_name.to_owned())?;
+    let data = &mut global_memory
+        .heap
+        .get_mut(HeapRef(objectref))
+        .ok_or("object not on heap")?
+        .data;
+    if offset + 1 >= data.len() {
+        return Err("field offset out of bounds".into());
+    }
+    data[offset..offset + 2].copy_from_slice(&slots::long_to_slots(value as i64));
+    Ok(())
+}
+
+fn get_long_field_value(
+    global_memory: &GlobalMemory,
+    objectref: u32,
+    field_name: &str,
+) -> Result<u64, Box<dyn Error>> {
+    let offset = global_memory
+        .method_area
+        .classes
+        .get(NATIVE_LIBRARY)
+        .ok_or("class not found")?
+        .as_instance_klass()
+        .ok_or("not an InstanceKlass")?
+        .field_offset_with_strings(NATIVE_LIBRARY.to_owned(), field_name.to_owned())?;
+    let data = &global_memory
+        .heap
+        .get(HeapRef(objectref))
+        .ok_or("object not on heap")?
+        .data;
+    let high = *data.get(offset).ok_or("field offset out of bounds")?;
+    let low = *data.get(offset + 1).ok_or("field offset out of bounds")?;
+    Ok(slots::long_from_slots(high, low) as u64)
+}
+
+// long findEntry(String name)
+pub(super) fn native_library_find_entry(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let this_ref = *current_frame
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?;
+    let name_ref = *current_frame
+        .local_variables
+        .get(1)
+        .ok_or("no item in local_variables")?;
+    let handle = get_long_field_value(global_memory, this_ref, "handle")?;
+    let name = string_from_java_string(global_memory, HeapRef(name_ref))?;
+    let address = symbol(handle as usize as *mut c_void, &name).unwrap_or(std::ptr::null()) as u64;
+    thread.return_value(&slots::long_to_slots(address as i64))
+}
+
+// static void unload(String name, boolean isBuiltin, long handle)
+pub(super) fn native_library_unload(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let current_frame = thread
+        .thread_memory
+        .jvm_stack
+        .last_mut()
+        .ok_or("no item on jvm stack")?;
+    let high = *current_frame
+        .local_variables
+        .get(2)
+        .ok_or("no item in local_variables")?;
+    let low = *current_frame
+        .local_variables
+        .get(3)
+        .ok_or("no item in local_variables")?;
+    let handle = slots::long_from_slots(high, low) as usize;
+    if let Some(index) = global_memory
+        .native_libraries
+        .iter()
+        .position(|library| *library == handle)
+    {
+        // only a class loader being collected unloads its libraries, which never happens to the
+        // bootstrap class loader, so there are no natives of the library left to be called
+        global_memory.native_libraries.remove(index);
+        unsafe { libc::dlclose(handle as *mut c_void) };
+    }
+    Ok(())
+}
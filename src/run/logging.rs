@@ -0,0 +1,166 @@
+// Diagnostics of the vm itself, like the classes it loads or the instructions it runs, written to
+// stderr. Each message belongs to a target, the part of the vm it comes from, and has a level;
+// it is only written if its target is set to that level or a more verbose one, all of them being
+// off by default. The levels are set from a spec like classload=debug,gc=info or just trace, see
+// parse_log_spec, passed with --log or in the MATEBABE_LOG environment variable.
+//
+// The levels are kept in statics so that checking them is cheap enough to do before every
+// instruction, which means they are shared by all vms of a process.
+
+use std::{
+    error::Error,
+    fmt::{self, Arguments},
+    str::FromStr,
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogTarget {
+    // loading, linking and initializing classes
+    ClassLoad,
+    // the instructions run and the frames pushed and popped
+    Interp,
+    // the native methods called
+    Native,
+    // the garbage collector
+    Gc,
+}
+
+const LOG_TARGETS: [LogTarget; 4] = [
+    LogTarget::ClassLoad,
+    LogTarget::Interp,
+    LogTarget::Native,
+    LogTarget::Gc,
+];
+
+// from quiet to verbose
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogTarget {
+    fn name(self) -> &'static str {
+        match self {
+            LogTarget::ClassLoad => "classload",
+            LogTarget::Interp => "interp",
+            LogTarget::Native => "native",
+            LogTarget::Gc => "gc",
+        }
+    }
+}
+
+impl fmt::Display for LogLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            LogLevel::Off => "off",
+            LogLevel::Error => "error",
+            LogLevel::Warn => "warn",
+            LogLevel::Info => "info",
+            LogLevel::Debug => "debug",
+            LogLevel::Trace => "trace",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl FromStr for LogTarget {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<LogTarget, Box<dyn Error>> {
+        LOG_TARGETS
+            .into_iter()
+            .find(|target| target.name() == name)
+            .ok_or_else(|| {
+                format!(
+                    "unknown log target {name}, expected one of classload, interp, native or gc"
+                )
+                .into()
+            })
+    }
+}
+
+impl FromStr for LogLevel {
+    type Err = Box<dyn Error>;
+
+    fn from_str(name: &str) -> Result<LogLevel, Box<dyn Error>> {
+        Ok(match name {
+            "off" => LogLevel::Off,
+            "error" => LogLevel::Error,
+            "warn" => LogLevel::Warn,
+            "info" => LogLevel::Info,
+            "debug" => LogLevel::Debug,
+            "trace" => LogLevel::Trace,
+            _ => {
+                return Err(format!(
+                "unknown log level {name}, expected one of off, error, warn, info, debug or trace"
+            )
+                .into())
+            }
+        })
+    }
+}
+
+// the level of every target, indexed by LOG_TARGETS
+static LEVELS: [AtomicU8; LOG_TARGETS.len()] = [
+    AtomicU8::new(LogLevel::Off as u8),
+    AtomicU8::new(LogLevel::Off as u8),
+    AtomicU8::new(LogLevel::Off as u8),
+    AtomicU8::new(LogLevel::Off as u8),
+];
+
+// parses a comma separated list of target=level pairs, a level without a target applying to all
+// of them, e.g. info,interp=trace
+pub fn parse_log_spec(spec: &str) -> Result<Vec<(LogTarget, LogLevel)>, Box<dyn Error>> {
+    let mut levels = vec![];
+    for item in spec
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+    {
+        match item.split_once('=') {
+            Some((target, level)) => levels.push((target.parse()?, level.parse()?)),
+            None => {
+                let level = item.parse()?;
+                levels.extend(LOG_TARGETS.into_iter().map(|target| (target, level)));
+            }
+        }
+    }
+    Ok(levels)
+}
+
+pub(super) fn set_level(target: LogTarget, level: LogLevel) {
+    LEVELS[target as usize].store(level as u8, Ordering::Relaxed);
+}
+
+pub(super) fn enabled(target: LogTarget, level: LogLevel) -> bool {
+    level as u8 <= LEVELS[target as usize].load(Ordering::Relaxed)
+}
+
+pub(super) fn write(target: LogTarget, level: LogLevel, message: Arguments) {
+    eprintln!("[{level} {}] {message}", target.name());
+}
+
+// writes a message with a target and level, e.g. log!(ClassLoad, Debug, "loading {name}"). The
+// arguments are only formatted if the message is written.
+macro_rules! log {
+    ($target:ident, $level:ident, $($argument:tt)+) => {
+        if $crate::run::logging::enabled(
+            $crate::run::logging::LogTarget::$target,
+            $crate::run::logging::LogLevel::$level,
+        ) {
+            $crate::run::logging::write(
+                $crate::run::logging::LogTarget::$target,
+                $crate::run::logging::LogLevel::$level,
+                format_args!($($argument)+),
+            );
+        }
+    };
+}
+
+pub(super) use log;
@@ -0,0 +1,117 @@
+// The memory handed out by Unsafe.allocateMemory, which java/nio/DirectByteBuffer and friends
+// keep their contents in. It is not real native memory: every allocation is a block of bytes
+// owned by the vm, and the addresses java land sees are made up. They start at BASE_ADDRESS and
+// leave a gap between blocks, so an access running off the end of one block fails instead of
+// ending up in the next one.
+//
+// Unsafe.isBigEndian0 says the vm is big endian, so values are stored with their most significant
+// byte first.
+
+use std::{collections::BTreeMap, error::Error};
+
+const BASE_ADDRESS: u64 = 0x1000_0000;
+
+// blocks start at multiples of this, like the ones malloc returns
+const ALIGNMENT: u64 = 16;
+
+#[derive(Debug, Default)]
+pub(super) struct NativeMemory {
+    // keyed by the address of their first byte
+    blocks: BTreeMap<u64, Vec<u8>>,
+    next_address: u64,
+}
+
+impl NativeMemory {
+    pub(super) fn new() -> NativeMemory {
+        NativeMemory {
+            blocks: BTreeMap::new(),
+            next_address: BASE_ADDRESS,
+        }
+    }
+
+    // a block of size zeroed bytes, 0 for a size of 0 like Unsafe.allocateMemory wants it. None if
+    // there isn't enough memory for it, which is an OutOfMemoryError
+    pub(super) fn allocate(&mut self, size: u64) -> Option<u64> {
+        if size == 0 {
+            return Some(0);
+        }
+        let length = usize::try_from(size).ok()?;
+        let mut block = vec![];
+        block.try_reserve_exact(length).ok()?;
+        block.resize(length, 0);
+        let address = self.next_address;
+        self.next_address = (address + size + ALIGNMENT).next_multiple_of(ALIGNMENT);
+        self.blocks.insert(address, block);
+        Some(address)
+    }
+
+    // moves the block at address to a new one of size bytes, keeping as much of its contents as
+    // fits. A block at address 0 is allocated. None if there isn't enough memory for the new block,
+    // the old one is kept then
+    pub(super) fn reallocate(
+        &mut self,
+        address: u64,
+        size: u64,
+    ) -> Result<Option<u64>, Box<dyn Error>> {
+        if address != 0 && !self.blocks.contains_key(&address) {
+            return Err(format!("no native memory allocated at {address:#x}").into());
+        }
+        let Some(new_address) = self.allocate(size) else {
+            return Ok(None);
+        };
+        let contents = self.blocks.remove(&address).unwrap_or_default();
+        if new_address != 0 {
+            let length = contents.len().min(usize::try_from(size)?);
+            self.slice_mut(new_address, length)?
+                .copy_from_slice(&contents[..length]);
+        }
+        Ok(Some(new_address))
+    }
+
+    // freeing 0 does nothing
+    pub(super) fn free(&mut self, address: u64) -> Result<(), Box<dyn Error>> {
+        if address == 0 {
+            return Ok(());
+        }
+        self.blocks
+            .remove(&address)
+            .ok_or(format!("no native memory allocated at {address:#x}"))?;
+        Ok(())
+    }
+
+    // where the range of length bytes starting at address is, as the start of its block and the
+    // index of address in it
+    fn locate(&self, address: u64, length: usize) -> Result<(u64, usize), Box<dyn Error>> {
+        let (start, block) = self
+            .blocks
+            .range(..=address)
+            .next_back()
+            .ok_or(format!("no native memory allocated at {address:#x}"))?;
+        let index = usize::try_from(address - start)?;
+        if index + length > block.len() {
+            return Err(format!(
+                "{length} bytes at {address:#x} are outside of the native memory allocated at {start:#x}"
+            )
+            .into());
+        }
+        Ok((*start, index))
+    }
+
+    pub(super) fn slice(&self, address: u64, length: usize) -> Result<&[u8], Box<dyn Error>> {
+        let (start, index) = self.locate(address, length)?;
+        Ok(&self.blocks[&start][index..index + length])
+    }
+
+    pub(super) fn slice_mut(
+        &mut self,
+        address: u64,
+        length: usize,
+    ) -> Result<&mut [u8], Box<dyn Error>> {
+        let (start, index) = self.locate(address, length)?;
+        let block = self
+            .blocks
+            .get_mut(&start)
+            .ok_or("native memory block vanished")?;
+        Ok(&mut block[index..index + length])
+    }
+}
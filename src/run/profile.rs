@@ -0,0 +1,135 @@
+// The profiler of --profile, which counts the instructions run in every stack of methods and
+// writes them as collapsed stacks once the vm exits, one line per stack with its methods from the
+// outermost to the innermost, separated by semicolons, followed by the count:
+//
+//     Main.main;Main.fib;Main.fib 1234
+//
+// which is what flamegraph.pl and inferno-flamegraph take. A native method counts as a single
+// instruction per call, as what it does isn't seen.
+//
+// The stacks form a tree of calls. Every frame remembers its node in it once it ran its first
+// instruction, so counting an instruction is a matter of bumping the count of the node of the
+// current frame.
+
+use std::{
+    collections::HashMap,
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use super::{GlobalMemory, Thread};
+
+const ROOT: usize = 0;
+
+#[derive(Debug)]
+struct Node {
+    parent: usize,
+    // the index of the method in methods, meaningless for the root
+    method: usize,
+    children: HashMap<usize, usize>,
+    instructions: u64,
+}
+
+#[derive(Debug)]
+pub(super) struct Profiler {
+    path: String,
+    // the call tree, the root standing for the bottom of all stacks
+    nodes: Vec<Node>,
+    // the methods as Class.method, and their indices
+    methods: Vec<String>,
+    method_indices: HashMap<String, usize>,
+}
+
+impl Profiler {
+    pub(super) fn new(path: &str) -> Profiler {
+        Profiler {
+            path: path.to_owned(),
+            nodes: vec![Node {
+                parent: ROOT,
+                method: 0,
+                children: HashMap::new(),
+                instructions: 0,
+            }],
+            methods: vec![],
+            method_indices: HashMap::new(),
+        }
+    }
+
+    fn method_index(&mut self, method: String) -> usize {
+        if let Some(index) = self.method_indices.get(&method) {
+            return *index;
+        }
+        self.methods.push(method.to_owned());
+        self.method_indices.insert(method, self.methods.len() - 1);
+        self.methods.len() - 1
+    }
+
+    fn child(&mut self, parent: usize, method: usize) -> usize {
+        if let Some(child) = self.nodes[parent].children.get(&method) {
+            return *child;
+        }
+        self.nodes.push(Node {
+            parent,
+            method,
+            children: HashMap::new(),
+            instructions: 0,
+        });
+        let child = self.nodes.len() - 1;
+        self.nodes[parent].children.insert(method, child);
+        child
+    }
+
+    // writes the stacks that ran any instructions, in no particular order
+    pub(super) fn write(&self) -> Result<(), Box<dyn Error>> {
+        let file =
+            File::create(&self.path).map_err(|e| format!("could not create {}: {e}", self.path))?;
+        let mut out = BufWriter::new(file);
+        for (index, node) in self.nodes.iter().enumerate().skip(1) {
+            if node.instructions == 0 {
+                continue;
+            }
+            let mut stack = vec![];
+            let mut current = index;
+            while current != ROOT {
+                stack.push(self.methods[self.nodes[current].method].as_str());
+                current = self.nodes[current].parent;
+            }
+            stack.reverse();
+            writeln!(out, "{} {}", stack.join(";"), node.instructions)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+}
+
+// called before a thread runs the instruction of its current frame, or the native method of it
+pub(super) fn before_instruction(thread: &mut Thread, global_memory: &mut GlobalMemory) {
+    let Some(profiler) = global_memory.profiler.as_mut() else {
+        return;
+    };
+    let stack = &mut thread.thread_memory.jvm_stack;
+    let Some((frame, invokers)) = stack.split_last_mut() else {
+        return;
+    };
+    let node = match frame.profile_node {
+        Some(node) => node,
+        None => {
+            let Some(method) = frame.method.as_ref() else {
+                return;
+            };
+            // stub frames have no node, so the frames above them hang off their invoker
+            let parent = invokers
+                .iter()
+                .rev()
+                .find_map(|invoker| invoker.profile_node)
+                .unwrap_or(ROOT);
+            // semicolons separate the methods, so descriptors are left out
+            let method = profiler.method_index(format!("{}.{}", frame.class_name(), method.name));
+            let node = profiler.child(parent, method);
+            frame.profile_node = Some(node);
+            node
+        }
+    };
+    profiler.nodes[node].instructions += 1;
+}
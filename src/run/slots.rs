@@ -0,0 +1,20 @@
+// Longs and doubles take up two slots, in the local variables, on the operand stack and in the
+// fields of objects, the slot with the high 32 bits coming first (JVMS §2.6.1, §2.6.2). These
+// split them into their slots and put them back together, so that this is done the same way
+// everywhere; a double is split like the long with its bits.
+
+pub(super) fn long_to_slots(value: i64) -> [u32; 2] {
+    [(value >> 32) as u32, value as u32]
+}
+
+pub(super) fn long_from_slots(high: u32, low: u32) -> i64 {
+    ((high as u64) << 32 | low as u64) as i64
+}
+
+pub(super) fn double_to_slots(value: f64) -> [u32; 2] {
+    long_to_slots(value.to_bits() as i64)
+}
+
+pub(super) fn double_from_slots(high: u32, low: u32) -> f64 {
+    f64::from_bits(long_from_slots(high, low) as u64)
+}
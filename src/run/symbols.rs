@@ -0,0 +1,52 @@
+// Interned strings, each standing for a u32 id. The heap keeps the field descriptors of the
+// objects in it this way, so an object header is an id rather than a String of its own, and
+// telling whether two objects are of the same class is comparing two numbers. The strings are
+// only looked at where they are needed, like for messages or for parsing the descriptor. The
+// method area interns the names of the classes the same way, see ClassId.
+
+use std::{collections::HashMap, sync::Arc};
+
+#[derive(Debug, Default)]
+pub(super) struct Symbols {
+    // indexed by id
+    names: Vec<Arc<str>>,
+    ids: HashMap<Arc<str>, u32>,
+}
+
+impl Symbols {
+    // the id of a string, which is added if it wasn't interned yet
+    pub(super) fn intern(&mut self, name: &str) -> u32 {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+        let name: Arc<str> = Arc::from(name);
+        let id = self.names.len() as u32;
+        self.names.push(name.clone());
+        self.ids.insert(name, id);
+        id
+    }
+
+    // the id of a string, if it was interned already
+    pub(super) fn lookup(&self, name: &str) -> Option<u32> {
+        self.ids.get(name).copied()
+    }
+
+    pub(super) fn name(&self, id: u32) -> &str {
+        &self.names[id as usize]
+    }
+
+    // like name, for keeping it around without borrowing the symbols
+    pub(super) fn shared_name(&self, id: u32) -> Arc<str> {
+        Arc::clone(&self.names[id as usize])
+    }
+}
+
+// the id of the name of a class, as interned in MethodArea::classes. Frames, resolved methods,
+// vtables and inline caches refer to classes this way.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(super) struct ClassId(pub(super) u32);
+
+impl ClassId {
+    // the class of stub frames, which doesn't name any class
+    pub(super) const STUB: ClassId = ClassId(u32::MAX);
+}
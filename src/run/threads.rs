@@ -0,0 +1,855 @@
+// The threads started from java land. Each one runs on a thread of its own, but only one of them
+// interprets at a time: the one holding the vm lock. GlobalMemory goes along with the lock: giving
+// it up moves GlobalMemory out of the place the thread keeps it in into the Scheduler, and taking
+// it over moves it into the place of the next thread. What is left in its place is one of the
+// empty GlobalMemories the Scheduler keeps for that, so handing the lock on doesn't build a new
+// one. Everything in GlobalMemory is Send for this, the parts of classes that frames share are
+// behind Arcs and host natives have to be Send + Sync, see Vm::register_native. A thread that
+// hands the lock on in its outermost loop moves its Thread into GlobalMemory as well, where the gc
+// and the debugger find its frames, see park.
+//
+// The thread holding the lock hands it on at a safepoint every SLICE instructions if another
+// thread is waiting for it, and whenever it blocks: entering a monitor another thread owns,
+// waiting on an object or waiting for the other threads to end. The threads get the lock in the
+// order they asked for it. A blocked thread doesn't queue up for the lock again before another
+// thread changed something it may be waiting for, e.g. gave up a monitor, see changed, or its
+// deadline passed.
+//
+// The gc collects at a safepoint of the outermost interpreter loop, and has to know about the
+// references of the other threads as well. A thread that handed the lock on in its outermost
+// loop has all of them in its frames, which become roots. A thread that handed it on anywhere
+// else, e.g. in the nested loop of a native, might hold some the gc can't see, so collecting is
+// put off until it is back.
+//
+// The thread calling into the vm, e.g. through Vm::run_main, holds the lock from the start. The
+// other threads only run while it runs java code, or waits for them at the end of run_main. The
+// ones still running when the vm is dropped, like daemons, are stopped then, see stop.
+//
+// With -Xthreads:green, no host threads are started at all. The started threads are parked in
+// GlobalMemory::green_threads instead, and the thread that would hand the lock on runs each of
+// them for a slice right there, nested in its own loop, see run_green_threads. A green thread run
+// like this yields by returning from its outermost loop, leaving its frames as they are, so it
+// can be resumed by running that loop again. Where it can't return, e.g. in the nested loop of a
+// lambda, it runs the other parked green threads in turn. The threads below it on the host stack
+// can't run until it returns though, so waiting for one of them there never ends. Which thread
+// runs when only depends on the program, which makes runs reproducible.
+
+use std::{
+    collections::VecDeque,
+    error::Error,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Condvar, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::parse::MethodDescriptor;
+
+use super::{
+    get_field_value, jdwp, long_argument, set_field_value, thread_run_method, Frame, GlobalMemory,
+    HeapRef, Thread,
+};
+
+// how many backward branches a thread of normal priority takes before the next one gets to run
+pub(super) const SLICE: usize = 1_000;
+
+// Thread.NORM_PRIORITY, the slices of the other priorities are as much longer or shorter
+const NORM_PRIORITY: usize = 5;
+
+// the id of the thread creating the vm, which runs the main method
+pub(super) const MAIN_THREAD_ID: u64 = 1;
+
+// how long a blocked green thread sleeps before checking again when none of the others got anything
+// done either
+const BLOCKED_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// selected with -Xthreads, see the top of this file
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ThreadMode {
+    #[default]
+    Native,
+    Green,
+}
+
+#[derive(Debug)]
+pub(super) struct Scheduler {
+    state: Mutex<LockState>,
+    // signalled whenever the lock is given up or a thread changed something, see changed
+    released: Condvar,
+    // the threads in the queue, which every safepoint checks
+    waiting: AtomicUsize,
+    // the threads waiting for a change before queueing up, see wait_for_change
+    blocked: AtomicUsize,
+    next_id: AtomicU64,
+}
+
+#[derive(Debug)]
+struct LockState {
+    holder: Option<u64>,
+    queue: VecDeque<u64>,
+    // GlobalMemory while nobody holds the lock
+    memory: Option<GlobalMemory>,
+    // what the threads taking the lock over left in the place of GlobalMemory, for the ones
+    // giving it up to leave in its place, see the top of this file
+    vacant: Vec<GlobalMemory>,
+    // counts the changes, see changed
+    changes: u64,
+}
+
+impl Scheduler {
+    // a scheduler whose lock is held by the main thread
+    pub(super) fn new() -> Scheduler {
+        Scheduler {
+            state: Mutex::new(LockState {
+                holder: Some(MAIN_THREAD_ID),
+                queue: VecDeque::new(),
+                memory: None,
+                vacant: vec![],
+                changes: 0,
+            }),
+            released: Condvar::new(),
+            waiting: AtomicUsize::new(0),
+            blocked: AtomicUsize::new(0),
+            next_id: AtomicU64::new(MAIN_THREAD_ID + 1),
+        }
+    }
+
+    fn new_thread_id(&self) -> u64 {
+        self.next_id.fetch_add(1, Ordering::SeqCst)
+    }
+
+    fn has_waiting_threads(&self) -> bool {
+        self.waiting.load(Ordering::SeqCst) > 0
+    }
+
+    // takes the lock over once it's the turn of the thread, moving GlobalMemory into its place
+    fn acquire(&self, id: u64, global_memory: &mut GlobalMemory) {
+        let mut state = self.take_over(id);
+        let memory = state.memory.take().expect("free vm lock without memory");
+        let vacant = std::mem::replace(global_memory, memory);
+        state.vacant.push(vacant);
+    }
+
+    // like acquire, for a thread that just started and has no place for GlobalMemory yet
+    fn acquire_memory(&self, id: u64) -> GlobalMemory {
+        let mut state = self.take_over(id);
+        state.memory.take().expect("free vm lock without memory")
+    }
+
+    fn take_over(&self, id: u64) -> MutexGuard<'_, LockState> {
+        let mut state = self.state.lock().unwrap();
+        state.queue.push_back(id);
+        self.waiting.fetch_add(1, Ordering::SeqCst);
+        while state.holder.is_some() || state.queue.front() != Some(&id) {
+            state = self.released.wait(state).unwrap();
+        }
+        state.queue.pop_front();
+        self.waiting.fetch_sub(1, Ordering::SeqCst);
+        state.holder = Some(id);
+        // the next one in the queue may be able to go once this one gives the lock up again
+        self.released.notify_all();
+        state
+    }
+
+    // gives the lock up, moving GlobalMemory out of the place of the thread
+    fn release(&self, global_memory: &mut GlobalMemory) {
+        let mut state = self.state.lock().unwrap();
+        let vacant = state.vacant.pop().unwrap_or_default();
+        let memory = std::mem::replace(global_memory, vacant);
+        self.hand_over(&mut state, memory);
+    }
+
+    // like release, for a thread that ends and doesn't need a place for GlobalMemory anymore
+    fn release_memory(&self, global_memory: GlobalMemory) {
+        let mut state = self.state.lock().unwrap();
+        self.hand_over(&mut state, global_memory);
+    }
+
+    fn hand_over(&self, state: &mut LockState, global_memory: GlobalMemory) {
+        state.memory = Some(global_memory);
+        state.holder = None;
+        self.released.notify_all();
+    }
+
+    // gives the lock up until another thread changed something or the deadline passed, then takes
+    // it over again
+    fn wait_for_change(
+        &self,
+        id: u64,
+        deadline: Option<Instant>,
+        global_memory: &mut GlobalMemory,
+    ) {
+        let mut state = self.state.lock().unwrap();
+        let changes = state.changes;
+        // counted before giving the lock up, so that the next thread holding it sees it
+        self.blocked.fetch_add(1, Ordering::SeqCst);
+        let vacant = state.vacant.pop().unwrap_or_default();
+        let memory = std::mem::replace(global_memory, vacant);
+        self.hand_over(&mut state, memory);
+        while state.changes == changes {
+            match deadline {
+                None => state = self.released.wait(state).unwrap(),
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        break;
+                    }
+                    state = self.released.wait_timeout(state, deadline - now).unwrap().0;
+                }
+            }
+        }
+        self.blocked.fetch_sub(1, Ordering::SeqCst);
+        drop(state);
+        self.acquire(id, global_memory);
+    }
+
+    fn changed(&self) {
+        if self.blocked.load(Ordering::SeqCst) > 0 {
+            self.state.lock().unwrap().changes += 1;
+            self.released.notify_all();
+        }
+    }
+}
+
+// only for the empty GlobalMemories left behind in the place of the real one, see LockState
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
+
+// the monitor of an object, see try_monitor_enter
+#[derive(Debug, Default)]
+pub(super) struct Monitor {
+    owner: Option<u64>,
+    // how often the owner entered it
+    count: usize,
+    // the threads in Object.wait
+    waiting: Vec<Waiter>,
+}
+
+#[derive(Debug)]
+struct Waiter {
+    id: u64,
+    // how often the thread had entered the monitor before waiting
+    count: usize,
+    deadline: Option<Instant>,
+    notified: bool,
+}
+
+// hands the vm lock on to the threads waiting for it and waits for it to come back. thread is the
+// one handing it on, if it is suspended somewhere its frames can be used as roots.
+pub(super) fn suspend(thread: Option<&mut Thread>, global_memory: &mut GlobalMemory) {
+    suspended(thread, global_memory, |scheduler, id, global_memory| {
+        scheduler.release(global_memory);
+        scheduler.acquire(id, global_memory);
+    });
+}
+
+// like suspend, but the lock only comes back once another thread changed something, see changed,
+// or the deadline passed
+fn suspend_blocked(
+    thread: Option<&mut Thread>,
+    global_memory: &mut GlobalMemory,
+    deadline: Option<Instant>,
+) {
+    suspended(thread, global_memory, |scheduler, id, global_memory| {
+        scheduler.wait_for_change(id, deadline, global_memory);
+    });
+}
+
+fn suspended(
+    thread: Option<&mut Thread>,
+    global_memory: &mut GlobalMemory,
+    hand_on: impl FnOnce(&Scheduler, u64, &mut GlobalMemory),
+) {
+    let scheduler = global_memory.scheduler.clone();
+    let id = global_memory.current_thread;
+    let thread = park(id, thread, global_memory);
+
+    hand_on(&scheduler, id, global_memory);
+
+    unpark(id, thread, global_memory);
+}
+
+// moves the thread with the id into GlobalMemory::suspended_threads while it lets others run, so
+// that the gc and the debugger find it there, if its frames can be used as roots, see the top of
+// this file. Returns where unpark moves it back to.
+fn park<'a>(
+    id: u64,
+    thread: Option<&'a mut Thread>,
+    global_memory: &mut GlobalMemory,
+) -> Option<&'a mut Thread> {
+    let mut thread = thread.filter(|thread| thread.interpreter_depth <= 1);
+    let parked = thread
+        .as_deref_mut()
+        .map(|thread| std::mem::replace(thread, Thread::new(id, 0, None)));
+    global_memory.suspended_threads.insert(id, parked);
+    thread
+}
+
+fn unpark(id: u64, thread: Option<&mut Thread>, global_memory: &mut GlobalMemory) {
+    let parked = global_memory.suspended_threads.remove(&id).flatten();
+    if let (Some(thread), Some(parked)) = (thread, parked) {
+        *thread = parked;
+    }
+    global_memory.current_thread = id;
+}
+
+// wakes the threads that blocked, as they may be able to go on now. Called whenever a monitor is
+// given up, a thread notified or ended, a class initialized or the vm halts.
+pub(super) fn changed(global_memory: &GlobalMemory) {
+    global_memory.scheduler.changed();
+}
+
+// runs the other green threads for a slice each, see the top of this file. thread is the one
+// running them, like in suspend. Returns whether any of them got anything done, instead of just
+// blocking again.
+fn run_green_threads(thread: Option<&mut Thread>, global_memory: &mut GlobalMemory) -> bool {
+    let id = global_memory.current_thread;
+    let thread = park(id, thread, global_memory);
+
+    let mut progressed = false;
+    for _ in 0..global_memory.green_threads.len() {
+        let Some(mut green_thread) = global_memory.green_threads.pop_front() else {
+            break;
+        };
+        global_memory.current_thread = green_thread.id;
+        green_thread.driven = true;
+        green_thread.yielding = false;
+        let result = green_thread.run(global_memory);
+        green_thread.driven = false;
+        progressed |= !green_thread.yielding;
+
+        // the run method returned or threw once only the stub below it is left
+        let result = match result {
+            Ok(()) if green_thread.thread_memory.jvm_stack.len() == 1 => {
+                end_green_thread(&mut green_thread, global_memory).map(|_| true)
+            }
+            Ok(()) => Ok(false),
+            Err(e) => Err(e),
+        };
+        match result {
+            Ok(true) => {}
+            Ok(false) => global_memory.green_threads.push_back(green_thread),
+            Err(e) => {
+                // like an error of the vm on the main thread, this halts the vm
+                eprintln!("Error in thread {}: {e}", green_thread.id);
+                global_memory.exit_status.get_or_insert(1);
+                global_memory.threads.remove(&green_thread.id);
+                changed(global_memory);
+            }
+        }
+    }
+
+    unpark(id, thread, global_memory);
+    progressed
+}
+
+fn end_green_thread(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let thread_ref = thread.java_clone.ok_or("green thread without object")?;
+    thread.end_thread(global_memory, thread_ref.0)?;
+    exit(global_memory, thread.id, thread_ref)
+}
+
+// lets the other threads run, until this one may be able to go on: another one changed something
+// or the deadline passed
+fn run_others(
+    thread: Option<&mut Thread>,
+    global_memory: &mut GlobalMemory,
+    deadline: Option<Instant>,
+) {
+    match global_memory.thread_mode {
+        ThreadMode::Native => suspend_blocked(thread, global_memory, deadline),
+        ThreadMode::Green => {
+            if !run_green_threads(thread, global_memory) {
+                std::thread::sleep(BLOCKED_POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+// called at the safepoints of the interpreter, lets the other threads run once the thread used
+// up its slice. Returns whether the loop has to return, as the thread yields to the scheduler.
+pub(super) fn safepoint(thread: &mut Thread, global_memory: &mut GlobalMemory) -> bool {
+    // a backward branch is noticed by the instruction_counter going back in the same frame
+    let position = (
+        thread.thread_memory.jvm_stack.len(),
+        thread
+            .thread_memory
+            .jvm_stack
+            .last()
+            .map_or(0, |frame| frame.instruction_counter),
+    );
+    if position.0 == thread.last_instruction.0 && position.1 < thread.last_instruction.1 {
+        thread.slice_left = thread.slice_left.saturating_sub(1);
+    }
+    thread.last_instruction = position;
+    if thread.slice_left > 0 {
+        return false;
+    }
+    thread.slice_left = slice(thread, global_memory);
+    match global_memory.thread_mode {
+        ThreadMode::Native => {
+            if global_memory.scheduler.has_waiting_threads() {
+                suspend(Some(thread), global_memory);
+            }
+            false
+        }
+        ThreadMode::Green if thread.driven && thread.interpreter_depth == 1 => true,
+        ThreadMode::Green => {
+            run_green_threads(Some(thread), global_memory);
+            false
+        }
+    }
+}
+
+// how many backward branches the next slice of a thread has, made from the priority of its thread
+// object, see setPriority0
+fn slice(thread: &Thread, global_memory: &GlobalMemory) -> usize {
+    let priority = thread
+        .java_clone
+        .and_then(|thread_ref| {
+            get_field_value(global_memory, thread_ref.0, "java/lang/Thread", "priority").ok()
+        })
+        .map_or(NORM_PRIORITY, |priority| priority.clamp(1, 10) as usize);
+    SLICE * priority / NORM_PRIORITY
+}
+
+// called when a thread can't go on until another one did something, or until the deadline.
+// Returns whether the loop has to return, as the thread yields to the scheduler, otherwise the
+// other threads ran in between.
+pub(super) fn block(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+    deadline: Option<Instant>,
+) -> bool {
+    if global_memory.thread_mode == ThreadMode::Green
+        && thread.driven
+        && thread.interpreter_depth == 1
+    {
+        thread.yielding = true;
+        return true;
+    }
+    run_others(Some(thread), global_memory, deadline);
+    false
+}
+
+// lets the other threads run until the condition holds, checking it every time this one gets to
+// run again. The vm halting ends the wait as well, the caller has to check for that.
+pub(super) fn block_until(
+    mut thread: Option<&mut Thread>,
+    global_memory: &mut GlobalMemory,
+    mut condition: impl FnMut(&mut GlobalMemory) -> bool,
+) {
+    while !condition(global_memory) && global_memory.exit_status.is_none() {
+        run_others(thread.as_deref_mut(), global_memory, None);
+    }
+}
+
+// whether the gc can find the references of all threads, see the top of this file
+pub(super) fn can_collect(global_memory: &GlobalMemory) -> bool {
+    global_memory
+        .suspended_threads
+        .values()
+        .all(|stack| stack.is_some())
+}
+
+// the threads that handed the lock on in their outermost loop and the parked green threads,
+// whose frames are roots
+pub(super) fn other_threads(global_memory: &GlobalMemory) -> impl Iterator<Item = &Thread> {
+    global_memory.suspended_threads.values().flatten().chain(
+        global_memory
+            .green_threads
+            .iter()
+            .map(|thread| thread.as_ref()),
+    )
+}
+
+// starts a thread running the run method of a thread object, on a host thread of its own or as a
+// green thread
+pub(super) fn start(
+    global_memory: &mut GlobalMemory,
+    thread_ref: HeapRef,
+    class_name: String,
+) -> Result<(), Box<dyn Error>> {
+    let scheduler = global_memory.scheduler.clone();
+    let id = scheduler.new_thread_id();
+    global_memory.threads.insert(id, thread_ref);
+    if get_field_value(global_memory, thread_ref.0, "java/lang/Thread", "daemon")? != 0 {
+        global_memory.daemon_threads.insert(id);
+    }
+    set_eetop(global_memory, thread_ref, id)?;
+    if global_memory.debugger.is_some() {
+        jdwp::thread_changed(global_memory, thread_ref.0, true)?;
+    }
+    let max_stack_depth = global_memory.max_stack_depth;
+
+    if global_memory.thread_mode == ThreadMode::Green {
+        let declaring_class = thread_run_method(global_memory, &class_name)?;
+        let mut frame = Frame::new(
+            global_memory,
+            declaring_class,
+            "run".to_owned(),
+            MethodDescriptor {
+                parameter_descriptors: vec![],
+                return_descriptor: crate::parse::ReturnDescriptor::VoidDescriptor,
+            },
+        )?;
+        frame.set_local_variable(0, thread_ref.0)?;
+        let mut thread = Box::new(Thread::new(id, max_stack_depth, Some(thread_ref)));
+        thread.thread_memory.jvm_stack.push(Frame::new_stub()?);
+        thread.thread_memory.jvm_stack.push(frame);
+        global_memory.green_threads.push_back(thread);
+        return Ok(());
+    }
+
+    let host_thread = std::thread::Builder::new()
+        .name(format!("matebabe-{id}"))
+        .spawn(move || {
+            let mut memory = scheduler.acquire_memory(id);
+            let global_memory = &mut memory;
+            global_memory.current_thread = id;
+
+            let mut thread = Thread::new(id, max_stack_depth, Some(thread_ref));
+            // what an uncaught exception is passed on to
+            thread
+                .thread_memory
+                .jvm_stack
+                .push(Frame::new_stub().unwrap());
+            // a panic must not end the host thread without giving up the lock
+            let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                thread
+                    .run_to_completion(global_memory, thread_ref.0, &class_name)
+                    .and_then(|_| exit(global_memory, id, thread_ref))
+                    .map_err(|e| e.to_string())
+            }));
+            if let Ok(Err(e)) = &result {
+                eprintln!("Error in thread {id}: {e}");
+            }
+            if !matches!(result, Ok(Ok(()))) {
+                // like an error of the vm on the main thread, this halts the vm
+                global_memory.exit_status.get_or_insert(1);
+                global_memory.threads.remove(&id);
+                changed(global_memory);
+            }
+            scheduler.release_memory(memory);
+        })?;
+    global_memory.host_threads.push(host_thread);
+    Ok(())
+}
+
+// stops the host threads of the threads still running before the vm is dropped, e.g. those of
+// daemons, which would be left without GlobalMemory otherwise. They end like once the vm halted:
+// every one of them returns from its loops as soon as it gets the lock.
+pub(super) fn stop(global_memory: &mut GlobalMemory) {
+    let scheduler = global_memory.scheduler.clone();
+    let id = global_memory.current_thread;
+    while !global_memory.host_threads.is_empty() {
+        global_memory.exit_status.get_or_insert(0);
+        changed(global_memory);
+        let host_threads = std::mem::take(&mut global_memory.host_threads);
+        scheduler.release(global_memory);
+        for host_thread in host_threads {
+            // they catch their panics themselves
+            let _ = host_thread.join();
+        }
+        scheduler.acquire(id, global_memory);
+    }
+}
+
+// isAlive of newer JDKs checks eetop, which is where hotspot keeps the address of its own thread.
+// Here, it holds the id of the thread, which is never 0, in its upper half.
+pub(super) fn set_eetop(
+    global_memory: &mut GlobalMemory,
+    thread_ref: HeapRef,
+    id: u64,
+) -> Result<(), Box<dyn Error>> {
+    set_field_value(
+        global_memory,
+        thread_ref.0,
+        "java/lang/Thread",
+        "eetop",
+        id as u32,
+    )
+}
+
+// a thread ended: it isn't alive anymore, which wakes the threads joining it
+pub(super) fn exit(
+    global_memory: &mut GlobalMemory,
+    id: u64,
+    thread_ref: HeapRef,
+) -> Result<(), Box<dyn Error>> {
+    global_memory.threads.remove(&id);
+    global_memory.daemon_threads.remove(&id);
+    set_eetop(global_memory, thread_ref, 0)?;
+    notify(global_memory, thread_ref, usize::MAX);
+    // the monitors it still owns, e.g. as it was left by an exception, are freed
+    for monitor in global_memory.monitors.values_mut() {
+        if monitor.owner == Some(id) {
+            monitor.owner = None;
+            monitor.count = 0;
+        }
+    }
+    global_memory
+        .monitors
+        .retain(|_, monitor| monitor.owner.is_some() || !monitor.waiting.is_empty());
+    changed(global_memory);
+    if global_memory.debugger.is_some() {
+        jdwp::thread_changed(global_memory, thread_ref.0, false)?;
+    }
+    Ok(())
+}
+
+// waits until every thread that isn't a daemon ended, or the vm halts. The daemons are left as
+// they are until the vm is dropped, see stop.
+pub(super) fn join_non_daemon_threads(thread: &mut Thread, global_memory: &mut GlobalMemory) {
+    block_until(Some(thread), global_memory, |global_memory| {
+        global_memory
+            .threads
+            .keys()
+            .all(|id| global_memory.daemon_threads.contains(id))
+    });
+}
+
+// enters the monitor of an object, unless another thread owns it
+pub(super) fn try_monitor_enter(
+    global_memory: &mut GlobalMemory,
+    id: u64,
+    objectref: HeapRef,
+) -> bool {
+    let monitor = global_memory.monitors.entry(objectref).or_default();
+    if monitor.owner.is_some_and(|owner| owner != id) {
+        return false;
+    }
+    monitor.owner = Some(id);
+    monitor.count += 1;
+    true
+}
+
+// exits the monitor of an object once, returning whether the thread owned it at all
+pub(super) fn monitor_exit(global_memory: &mut GlobalMemory, id: u64, objectref: HeapRef) -> bool {
+    let Some(monitor) = global_memory.monitors.get_mut(&objectref) else {
+        return false;
+    };
+    if monitor.owner != Some(id) {
+        return false;
+    }
+    monitor.count -= 1;
+    if monitor.count == 0 {
+        monitor.owner = None;
+        if monitor.waiting.is_empty() {
+            global_memory.monitors.remove(&objectref);
+        }
+        changed(global_memory);
+    }
+    true
+}
+
+// wakes up to count of the threads waiting on an object
+fn notify(global_memory: &mut GlobalMemory, objectref: HeapRef, count: usize) {
+    if let Some(monitor) = global_memory.monitors.get_mut(&objectref) {
+        for waiter in monitor
+            .waiting
+            .iter_mut()
+            .filter(|waiter| !waiter.notified)
+            .take(count)
+        {
+            waiter.notified = true;
+        }
+    }
+    changed(global_memory);
+}
+
+// ends the wait of a thread in Object.wait once it was notified or timed out and it could enter
+// the monitor again, returning whether it did
+fn end_wait(global_memory: &mut GlobalMemory, id: u64, objectref: HeapRef) -> bool {
+    let Some(monitor) = global_memory.monitors.get_mut(&objectref) else {
+        return true;
+    };
+    let Some(index) = monitor.waiting.iter().position(|waiter| waiter.id == id) else {
+        return true;
+    };
+    let waiter = &monitor.waiting[index];
+    let timed_out = waiter
+        .deadline
+        .is_some_and(|deadline| Instant::now() >= deadline);
+    if !waiter.notified && !timed_out {
+        return false;
+    }
+    if waiter.count > 0 {
+        if monitor.owner.is_some() {
+            return false;
+        }
+        monitor.owner = Some(id);
+        monitor.count = waiter.count;
+    }
+    monitor.waiting.remove(index);
+    if monitor.owner.is_none() && monitor.waiting.is_empty() {
+        global_memory.monitors.remove(&objectref);
+    }
+    true
+}
+
+fn this_argument(thread: &Thread) -> Result<u32, Box<dyn Error>> {
+    Ok(*thread
+        .thread_memory
+        .jvm_stack
+        .last()
+        .ok_or("no item on jvm stack")?
+        .local_variables
+        .first()
+        .ok_or("no item in local_variables")?)
+}
+
+fn owns_monitor(global_memory: &GlobalMemory, id: u64, objectref: HeapRef) -> bool {
+    global_memory
+        .monitors
+        .get(&objectref)
+        .is_some_and(|monitor| monitor.owner == Some(id))
+}
+
+// void wait(long timeoutMillis). The monitor is given up while waiting and entered again as often
+// as before afterwards.
+pub(super) fn object_wait(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let objectref = this_argument(thread)?;
+    let timeout = long_argument(thread, 1)?;
+    if timeout < 0 {
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/IllegalArgumentException",
+            "timeout value is negative",
+        );
+    }
+    let deadline = (timeout > 0).then(|| Instant::now() + Duration::from_millis(timeout as u64));
+
+    let id = thread.id;
+    let monitor = global_memory
+        .monitors
+        .entry(HeapRef(objectref))
+        .or_default();
+    // a green thread that yielded runs this again, already waiting
+    if !monitor.waiting.iter().any(|waiter| waiter.id == id) {
+        if monitor.owner != Some(id) {
+            return thread.throw_exception_with_message(
+                global_memory,
+                "java/lang/IllegalMonitorStateException",
+                "current thread is not owner",
+            );
+        }
+        monitor.owner = None;
+        let count = std::mem::take(&mut monitor.count);
+        monitor.waiting.push(Waiter {
+            id,
+            count,
+            deadline,
+            notified: false,
+        });
+        changed(global_memory);
+    }
+
+    while !end_wait(global_memory, id, HeapRef(objectref)) && global_memory.exit_status.is_none() {
+        if block(thread, global_memory, deadline) {
+            return Ok(());
+        }
+    }
+    Ok(())
+}
+
+pub(super) fn object_notify(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let objectref = this_argument(thread)?;
+    if !owns_monitor(global_memory, thread.id, HeapRef(objectref)) {
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/IllegalMonitorStateException",
+            "current thread is not owner",
+        );
+    }
+    notify(global_memory, HeapRef(objectref), 1);
+    Ok(())
+}
+
+pub(super) fn object_notify_all(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let objectref = this_argument(thread)?;
+    if !owns_monitor(global_memory, thread.id, HeapRef(objectref)) {
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/IllegalMonitorStateException",
+            "current thread is not owner",
+        );
+    }
+    notify(global_memory, HeapRef(objectref), usize::MAX);
+    Ok(())
+}
+
+// static void sleep(long millis). The other threads run in the meantime.
+pub(super) fn thread_sleep(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let millis = long_argument(thread, 0)?;
+    if millis < 0 {
+        return thread.throw_exception_with_message(
+            global_memory,
+            "java/lang/IllegalArgumentException",
+            "timeout value is negative",
+        );
+    }
+
+    // a green thread that yielded runs this again, already sleeping
+    let deadline = *thread
+        .sleeping_until
+        .get_or_insert_with(|| Instant::now() + Duration::from_millis(millis as u64));
+    while Instant::now() < deadline && global_memory.exit_status.is_none() {
+        if block(thread, global_memory, Some(deadline)) {
+            return Ok(());
+        }
+    }
+    thread.sleeping_until = None;
+    Ok(())
+}
+
+// static void yield(). The slice of the thread ends, so the next safepoint lets the others run.
+pub(super) fn thread_yield(
+    thread: &mut Thread,
+    _global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    thread.slice_left = 0;
+    Ok(())
+}
+
+// void setPriority0(int newPriority), called by setPriority once it set the priority field. The
+// other threads get slices of the new length from their next one on, the current one has what is
+// left of its slice cut down right away.
+pub(super) fn thread_set_priority0(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let this_ref = this_argument(thread)?;
+    if thread.java_clone == Some(HeapRef(this_ref)) {
+        thread.slice_left = thread.slice_left.min(slice(thread, global_memory));
+    }
+    Ok(())
+}
+
+// the threads that are alive, see GlobalMemory::threads
+pub(super) fn is_alive(global_memory: &GlobalMemory, thread_ref: HeapRef) -> bool {
+    global_memory
+        .threads
+        .values()
+        .any(|objectref| *objectref == thread_ref)
+}
@@ -0,0 +1,146 @@
+// The execution trace written with --trace: the methods entered and left and the instructions run
+// in between, for the classes matching one of the filters passed with --trace-filter, or all
+// classes without any. Every line starts with the id of the thread and the depth of its stack, so
+// the lines of a thread can be picked out and nested calls told apart:
+//
+//     1:3 > Main.main([Ljava/lang/String;)V
+//     1:3   Main.main pc 0: iconst_2
+//     1:3   Main.main pc 1: invokestatic    #7    // Method Main.square:(I)I
+//     1:4 > Main.square(I)I
+//     ...
+//     1:4 < Main.square(I)I
+//
+// A method that is left by an exception is marked as having thrown.
+
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+use crate::disasm::instruction_text;
+
+use super::{Frame, GlobalMemory, Thread};
+
+#[derive(Debug)]
+pub(super) struct Trace {
+    out: BufWriter<File>,
+    // class names with slashes, those ending in * matching every class starting with the rest
+    filters: Vec<String>,
+}
+
+impl Trace {
+    pub(super) fn new(path: &str, filters: &[String]) -> Result<Trace, Box<dyn Error>> {
+        let file = File::create(path).map_err(|e| format!("could not create {path}: {e}"))?;
+        Ok(Trace {
+            out: BufWriter::new(file),
+            filters: filters
+                .iter()
+                .map(|filter| filter.replace('.', "/"))
+                .collect(),
+        })
+    }
+
+    fn traces(&self, class_name: &str) -> bool {
+        self.filters.is_empty()
+            || self
+                .filters
+                .iter()
+                .any(|filter| match filter.strip_suffix('*') {
+                    Some(prefix) => class_name.starts_with(prefix),
+                    None => class_name == filter,
+                })
+    }
+
+    // writes whatever is still buffered, which happens when the vm exits
+    pub(super) fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+fn method_signature(frame: &Frame) -> String {
+    match frame.method.as_ref() {
+        Some(method) => format!(
+            "{}.{}{}",
+            frame.class_name(),
+            method.name,
+            method.descriptor.descriptor()
+        ),
+        None => frame.class_name().to_owned(),
+    }
+}
+
+// called before a thread runs the instruction of its current frame, or the native method of it,
+// which is entered the first time it gets here
+pub(super) fn before_instruction(
+    thread: &mut Thread,
+    global_memory: &mut GlobalMemory,
+) -> Result<(), Box<dyn Error>> {
+    let depth = thread.thread_memory.jvm_stack.len();
+    let id = thread.id;
+    let Some(frame) = thread.thread_memory.jvm_stack.last_mut() else {
+        return Ok(());
+    };
+    let Some(trace) = global_memory.trace.as_mut() else {
+        return Ok(());
+    };
+    if frame.method.is_none() || !trace.traces(frame.class_name()) {
+        return Ok(());
+    }
+    if !frame.entered {
+        frame.entered = true;
+        let native = if frame.code.is_none() {
+            " (native)"
+        } else {
+            ""
+        };
+        writeln!(
+            trace.out,
+            "{id}:{depth} > {}{native}",
+            method_signature(frame)
+        )?;
+    }
+    let Some(code) = frame.code.as_ref() else {
+        return Ok(());
+    };
+    let instruction = code
+        .instruction_at(frame.instruction_counter)
+        .ok_or("no instruction at instruction_counter")?;
+    let text = match global_memory.method_area.parsed_class(frame.class_name()) {
+        Ok(class) => instruction_text(&class, instruction),
+        // e.g. the classes spun for lambdas
+        Err(_) => instruction.mnemonic().to_owned(),
+    };
+    let trace = global_memory.trace.as_mut().ok_or("no trace")?;
+    writeln!(
+        trace.out,
+        "{id}:{depth}   {} pc {}: {text}",
+        frame.method_display_name(),
+        frame.instruction_counter
+    )?;
+    Ok(())
+}
+
+// called when a frame was popped off the stack of a thread, which was depth frames deep with it
+pub(super) fn frame_popped(
+    thread: &Thread,
+    global_memory: &mut GlobalMemory,
+    frame: &Frame,
+    depth: usize,
+) -> Result<(), Box<dyn Error>> {
+    let Some(trace) = global_memory.trace.as_mut() else {
+        return Ok(());
+    };
+    if !frame.entered || !trace.traces(frame.class_name()) {
+        return Ok(());
+    }
+    let threw = if thread.is_throwing { " threw" } else { "" };
+    writeln!(
+        trace.out,
+        "{}:{depth} < {}{threw}",
+        thread.id,
+        method_signature(frame)
+    )?;
+    Ok(())
+}
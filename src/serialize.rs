@@ -0,0 +1,491 @@
+// Writes a parsed class back into the class file format (JVMS §4). The constant pool keeps the
+// order of the parsed class, so every index in the bytecode and in uninterpreted attributes stays
+// valid. Constants that are referenced by name, but are missing from the pool, are appended.
+
+use std::error::Error;
+
+use byteorder::{BigEndian, WriteBytesExt};
+
+use crate::parse::{
+    encode_modified_utf8, Attribute, ClassInfo, Constant, Field, Method, StackMapFrame,
+    VerificationType,
+};
+
+struct ConstantPoolWriter {
+    constants: Vec<Constant>,
+}
+
+impl ConstantPoolWriter {
+    // the index of the constant, which is added to the end of the pool if it isn't there yet
+    fn index_of(&mut self, constant: Constant) -> Result<u16, Box<dyn Error>> {
+        if let Some(index) = self
+            .constants
+            .iter()
+            .position(|existing| *existing == constant)
+        {
+            return Ok(index as u16 + 1);
+        }
+        let takes_two_entries = matches!(constant, Constant::Long(_) | Constant::Double(_));
+        self.constants.push(constant);
+        let index = self.constants.len();
+        if takes_two_entries {
+            self.constants.push(Constant::Unusable);
+        }
+        if self.constants.len() >= u16::MAX as usize {
+            return Err("constant pool has too many entries".into());
+        }
+        Ok(index as u16)
+    }
+
+    fn utf8(&mut self, value: &str) -> Result<u16, Box<dyn Error>> {
+        self.index_of(Constant::Utf8(value.encode_utf16().collect()))
+    }
+
+    fn class(&mut self, name: &str) -> Result<u16, Box<dyn Error>> {
+        self.index_of(Constant::Class(ClassInfo {
+            name: name.to_owned(),
+        }))
+    }
+
+    fn name_and_type(&mut self, constant: &Constant) -> Result<u16, Box<dyn Error>> {
+        match constant {
+            Constant::NameAndType(..) => self.index_of(constant.to_owned()),
+            _ => Err(format!("expected a name and type, found {constant:?}").into()),
+        }
+    }
+
+    // writes all constants, including the ones appended while writing them
+    fn write(&mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut bytes = vec![];
+        let mut index = 0;
+        while index < self.constants.len() {
+            let constant = self.constants[index].to_owned();
+            index += 1;
+            match constant {
+                Constant::Utf8(value) => {
+                    let value = encode_modified_utf8(&value);
+                    bytes.write_u8(1)?;
+                    bytes.write_u16::<BigEndian>(
+                        value
+                            .len()
+                            .try_into()
+                            .map_err(|_| "utf8 constant is too long")?,
+                    )?;
+                    bytes.extend_from_slice(&value);
+                }
+                Constant::Integer(value) => {
+                    bytes.write_u8(3)?;
+                    bytes.write_i32::<BigEndian>(value)?;
+                }
+                Constant::Float(value) => {
+                    bytes.write_u8(4)?;
+                    bytes.write_u32::<BigEndian>(value.to_bits())?;
+                }
+                Constant::Long(value) => {
+                    bytes.write_u8(5)?;
+                    bytes.write_i64::<BigEndian>(value)?;
+                }
+                Constant::Double(value) => {
+                    bytes.write_u8(6)?;
+                    bytes.write_u64::<BigEndian>(value.to_bits())?;
+                }
+                Constant::Class(class_info) => {
+                    let name_index = self.utf8(&class_info.name)?;
+                    bytes.write_u8(7)?;
+                    bytes.write_u16::<BigEndian>(name_index)?;
+                }
+                Constant::String(value) => {
+                    let string_index = self.index_of(Constant::Utf8(value))?;
+                    bytes.write_u8(8)?;
+                    bytes.write_u16::<BigEndian>(string_index)?;
+                }
+                Constant::FieldRef(class_info, name_and_type)
+                | Constant::MethodRef(class_info, name_and_type)
+                | Constant::InterfaceMethodRef(class_info, name_and_type) => {
+                    let tag = match self.constants[index - 1] {
+                        Constant::FieldRef(..) => 9,
+                        Constant::MethodRef(..) => 10,
+                        _ => 11,
+                    };
+                    let class_index = self.class(&class_info.name)?;
+                    let name_and_type_index = self.name_and_type(&name_and_type)?;
+                    bytes.write_u8(tag)?;
+                    bytes.write_u16::<BigEndian>(class_index)?;
+                    bytes.write_u16::<BigEndian>(name_and_type_index)?;
+                }
+                Constant::NameAndType(name, descriptor) => {
+                    let name_index = self.utf8(&name)?;
+                    let descriptor_index = self.utf8(&descriptor)?;
+                    bytes.write_u8(12)?;
+                    bytes.write_u16::<BigEndian>(name_index)?;
+                    bytes.write_u16::<BigEndian>(descriptor_index)?;
+                }
+                Constant::MethodHandle(ref_kind, reference) => {
+                    let reference_index = self.index_of(*reference)?;
+                    bytes.write_u8(15)?;
+                    bytes.write_u8(ref_kind.reference_kind())?;
+                    bytes.write_u16::<BigEndian>(reference_index)?;
+                }
+                Constant::MethodType(descriptor) => {
+                    let descriptor_index = self.utf8(&descriptor)?;
+                    bytes.write_u8(16)?;
+                    bytes.write_u16::<BigEndian>(descriptor_index)?;
+                }
+                Constant::Dynamic(bootstrap_method_attr_index, name_and_type)
+                | Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type) => {
+                    let tag = match self.constants[index - 1] {
+                        Constant::Dynamic(..) => 17,
+                        _ => 18,
+                    };
+                    let name_and_type_index = self.name_and_type(&name_and_type)?;
+                    bytes.write_u8(tag)?;
+                    bytes.write_u16::<BigEndian>(bootstrap_method_attr_index)?;
+                    bytes.write_u16::<BigEndian>(name_and_type_index)?;
+                }
+                Constant::Module(name) => {
+                    let name_index = self.utf8(&name)?;
+                    bytes.write_u8(19)?;
+                    bytes.write_u16::<BigEndian>(name_index)?;
+                }
+                Constant::Package(name) => {
+                    let name_index = self.utf8(&name)?;
+                    bytes.write_u8(20)?;
+                    bytes.write_u16::<BigEndian>(name_index)?;
+                }
+                // the second entry of a long or double is not written
+                Constant::Unusable => {}
+                Constant::Placeholder => {
+                    return Err(format!("constant {index} was never resolved").into());
+                }
+            }
+        }
+        Ok(bytes)
+    }
+}
+
+fn write_attributes(
+    bytes: &mut Vec<u8>,
+    attributes: &[Attribute],
+    constant_pool: &mut ConstantPoolWriter,
+) -> Result<(), Box<dyn Error>> {
+    bytes.write_u16::<BigEndian>(attributes.len() as u16)?;
+    for attribute in attributes.iter() {
+        write_attribute(bytes, attribute, constant_pool)?;
+    }
+    Ok(())
+}
+
+fn write_attribute(
+    bytes: &mut Vec<u8>,
+    attribute: &Attribute,
+    constant_pool: &mut ConstantPoolWriter,
+) -> Result<(), Box<dyn Error>> {
+    let mut info = vec![];
+    let name = match attribute {
+        Attribute::Code {
+            max_stack,
+            max_locals,
+            bytes: code,
+            exception_table,
+            attributes,
+        } => {
+            info.write_u16::<BigEndian>(*max_stack as u16)?;
+            info.write_u16::<BigEndian>(*max_locals as u16)?;
+            info.write_u32::<BigEndian>(code.len() as u32)?;
+            info.extend_from_slice(code);
+            info.write_u16::<BigEndian>(exception_table.len() as u16)?;
+            for item in exception_table.iter() {
+                info.write_u16::<BigEndian>(item.start_pc as u16)?;
+                info.write_u16::<BigEndian>(item.end_pc as u16)?;
+                info.write_u16::<BigEndian>(item.handler_pc as u16)?;
+                info.write_u16::<BigEndian>(item.catch_type as u16)?;
+            }
+            write_attributes(&mut info, attributes, constant_pool)?;
+            "Code"
+        }
+        Attribute::BootstrapMethods(bootstrap_methods) => {
+            info.write_u16::<BigEndian>(bootstrap_methods.len() as u16)?;
+            for bootstrap_method in bootstrap_methods.iter() {
+                info.write_u16::<BigEndian>(bootstrap_method.method_ref)?;
+                info.write_u16::<BigEndian>(bootstrap_method.arguments.len() as u16)?;
+                for argument in bootstrap_method.arguments.iter() {
+                    info.write_u16::<BigEndian>(*argument)?;
+                }
+            }
+            "BootstrapMethods"
+        }
+        Attribute::StackMapTable(frames) => {
+            info.write_u16::<BigEndian>(frames.len() as u16)?;
+            for frame in frames.iter() {
+                write_stack_map_frame(&mut info, frame)?;
+            }
+            "StackMapTable"
+        }
+        Attribute::LineNumberTable(entries) => {
+            info.write_u16::<BigEndian>(entries.len() as u16)?;
+            for entry in entries.iter() {
+                info.write_u16::<BigEndian>(entry.start_pc as u16)?;
+                info.write_u16::<BigEndian>(entry.line_number)?;
+            }
+            "LineNumberTable"
+        }
+        Attribute::LocalVariableTable(entries) => {
+            info.write_u16::<BigEndian>(entries.len() as u16)?;
+            for entry in entries.iter() {
+                info.write_u16::<BigEndian>(entry.start_pc as u16)?;
+                info.write_u16::<BigEndian>(entry.length as u16)?;
+                info.write_u16::<BigEndian>(constant_pool.utf8(&entry.name)?)?;
+                info.write_u16::<BigEndian>(constant_pool.utf8(&entry.descriptor)?)?;
+                info.write_u16::<BigEndian>(entry.index as u16)?;
+            }
+            "LocalVariableTable"
+        }
+        Attribute::Signature(signature) => {
+            info.write_u16::<BigEndian>(constant_pool.utf8(signature)?)?;
+            "Signature"
+        }
+        Attribute::SourceFile(source_file) => {
+            info.write_u16::<BigEndian>(constant_pool.utf8(source_file)?)?;
+            "SourceFile"
+        }
+        Attribute::InnerClasses(classes) => {
+            info.write_u16::<BigEndian>(classes.len() as u16)?;
+            for class in classes.iter() {
+                info.write_u16::<BigEndian>(constant_pool.class(&class.inner_class)?)?;
+                info.write_u16::<BigEndian>(match &class.outer_class {
+                    Some(outer_class) => constant_pool.class(outer_class)?,
+                    None => 0,
+                })?;
+                info.write_u16::<BigEndian>(match &class.inner_name {
+                    Some(inner_name) => constant_pool.utf8(inner_name)?,
+                    None => 0,
+                })?;
+                info.write_u16::<BigEndian>(class.inner_class_access_flags)?;
+            }
+            "InnerClasses"
+        }
+        Attribute::EnclosingMethod(enclosing_method) => {
+            info.write_u16::<BigEndian>(constant_pool.class(&enclosing_method.class)?)?;
+            info.write_u16::<BigEndian>(match &enclosing_method.method {
+                Some((name, descriptor)) => constant_pool.index_of(Constant::NameAndType(
+                    name.to_owned(),
+                    descriptor.to_owned(),
+                ))?,
+                None => 0,
+            })?;
+            "EnclosingMethod"
+        }
+        Attribute::NestHost(host) => {
+            info.write_u16::<BigEndian>(constant_pool.class(host)?)?;
+            "NestHost"
+        }
+        Attribute::NestMembers(classes) | Attribute::PermittedSubclasses(classes) => {
+            info.write_u16::<BigEndian>(classes.len() as u16)?;
+            for class in classes.iter() {
+                info.write_u16::<BigEndian>(constant_pool.class(class)?)?;
+            }
+            if matches!(attribute, Attribute::NestMembers(_)) {
+                "NestMembers"
+            } else {
+                "PermittedSubclasses"
+            }
+        }
+        Attribute::Record(components) => {
+            info.write_u16::<BigEndian>(components.len() as u16)?;
+            for component in components.iter() {
+                info.write_u16::<BigEndian>(constant_pool.utf8(&component.name)?)?;
+                info.write_u16::<BigEndian>(constant_pool.utf8(&component.descriptor)?)?;
+                write_attributes(&mut info, &component.attributes, constant_pool)?;
+            }
+            "Record"
+        }
+        Attribute::ConstantValue(index) => {
+            info.write_u16::<BigEndian>(*index)?;
+            "ConstantValue"
+        }
+        Attribute::Unknown { name, info: raw } => {
+            info.extend_from_slice(raw);
+            name.as_str()
+        }
+    };
+
+    bytes.write_u16::<BigEndian>(constant_pool.utf8(name)?)?;
+    bytes.write_u32::<BigEndian>(info.len() as u32)?;
+    bytes.extend_from_slice(&info);
+    Ok(())
+}
+
+fn write_stack_map_frame(bytes: &mut Vec<u8>, frame: &StackMapFrame) -> Result<(), Box<dyn Error>> {
+    // the compact frame types are used whenever the offset_delta fits into them
+    match frame {
+        StackMapFrame::Same { offset_delta } => {
+            if *offset_delta <= 63 {
+                bytes.write_u8(*offset_delta as u8)?;
+            } else {
+                bytes.write_u8(251)?;
+                bytes.write_u16::<BigEndian>(*offset_delta)?;
+            }
+        }
+        StackMapFrame::SameLocals1StackItem {
+            offset_delta,
+            stack,
+        } => {
+            if *offset_delta <= 63 {
+                bytes.write_u8(64 + *offset_delta as u8)?;
+            } else {
+                bytes.write_u8(247)?;
+                bytes.write_u16::<BigEndian>(*offset_delta)?;
+            }
+            write_verification_type(bytes, stack)?;
+        }
+        StackMapFrame::Chop {
+            offset_delta,
+            count,
+        } => {
+            bytes.write_u8(251 - count)?;
+            bytes.write_u16::<BigEndian>(*offset_delta)?;
+        }
+        StackMapFrame::Append {
+            offset_delta,
+            locals,
+        } => {
+            bytes.write_u8(251 + locals.len() as u8)?;
+            bytes.write_u16::<BigEndian>(*offset_delta)?;
+            for local in locals.iter() {
+                write_verification_type(bytes, local)?;
+            }
+        }
+        StackMapFrame::Full {
+            offset_delta,
+            locals,
+            stack,
+        } => {
+            bytes.write_u8(255)?;
+            bytes.write_u16::<BigEndian>(*offset_delta)?;
+            bytes.write_u16::<BigEndian>(locals.len() as u16)?;
+            for local in locals.iter() {
+                write_verification_type(bytes, local)?;
+            }
+            bytes.write_u16::<BigEndian>(stack.len() as u16)?;
+            for item in stack.iter() {
+                write_verification_type(bytes, item)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_verification_type(
+    bytes: &mut Vec<u8>,
+    verification_type: &VerificationType,
+) -> Result<(), Box<dyn Error>> {
+    match verification_type {
+        VerificationType::Top => bytes.write_u8(0)?,
+        VerificationType::Integer => bytes.write_u8(1)?,
+        VerificationType::Float => bytes.write_u8(2)?,
+        VerificationType::Double => bytes.write_u8(3)?,
+        VerificationType::Long => bytes.write_u8(4)?,
+        VerificationType::Null => bytes.write_u8(5)?,
+        VerificationType::UninitializedThis => bytes.write_u8(6)?,
+        VerificationType::Object(class_index) => {
+            bytes.write_u8(7)?;
+            bytes.write_u16::<BigEndian>(*class_index)?;
+        }
+        VerificationType::Uninitialized(offset) => {
+            bytes.write_u8(8)?;
+            bytes.write_u16::<BigEndian>(*offset)?;
+        }
+    }
+    Ok(())
+}
+
+fn write_field(
+    bytes: &mut Vec<u8>,
+    field: &Field,
+    constant_pool: &mut ConstantPoolWriter,
+) -> Result<(), Box<dyn Error>> {
+    bytes.write_u16::<BigEndian>(field.access.flags)?;
+    bytes.write_u16::<BigEndian>(constant_pool.utf8(&field.name)?)?;
+    bytes.write_u16::<BigEndian>(constant_pool.utf8(&field.descriptor.field_type.descriptor())?)?;
+    write_attributes(bytes, &field.attributes, constant_pool)
+}
+
+fn write_method(
+    bytes: &mut Vec<u8>,
+    method: &Method,
+    constant_pool: &mut ConstantPoolWriter,
+) -> Result<(), Box<dyn Error>> {
+    bytes.write_u16::<BigEndian>(method.access.flags)?;
+    bytes.write_u16::<BigEndian>(constant_pool.utf8(&method.name)?)?;
+    bytes.write_u16::<BigEndian>(constant_pool.utf8(&method.descriptor.descriptor())?)?;
+    write_attributes(bytes, &method.attributes, constant_pool)
+}
+
+pub fn serialize_class(class: &crate::parse::Class) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut constant_pool = ConstantPoolWriter {
+        constants: class.constant_pool.to_owned(),
+    };
+
+    // everything after the constant pool is written first, as it may add constants
+    let mut body = vec![];
+    body.write_u16::<BigEndian>(class.access.flags)?;
+    body.write_u16::<BigEndian>(constant_pool.class(&class.this_class.name)?)?;
+    body.write_u16::<BigEndian>(match &class.super_class {
+        Some(super_class) => constant_pool.class(&super_class.name)?,
+        None => 0,
+    })?;
+    body.write_u16::<BigEndian>(class.interfaces.len() as u16)?;
+    for interface in class.interfaces.iter() {
+        body.write_u16::<BigEndian>(constant_pool.class(&interface.name)?)?;
+    }
+    body.write_u16::<BigEndian>(class.fields.len() as u16)?;
+    for field in class.fields.iter() {
+        write_field(&mut body, field, &mut constant_pool)?;
+    }
+    body.write_u16::<BigEndian>(class.methods.len() as u16)?;
+    for method in class.methods.iter() {
+        write_method(&mut body, method, &mut constant_pool)?;
+    }
+    write_attributes(&mut body, &class.attributes, &mut constant_pool)?;
+
+    let constant_pool_bytes = constant_pool.write()?;
+
+    let mut bytes = vec![];
+    bytes.write_u32::<BigEndian>(0xcafebabe)?;
+    bytes.write_u16::<BigEndian>(class.minor_version)?;
+    bytes.write_u16::<BigEndian>(class.major_version)?;
+    bytes.write_u16::<BigEndian>(constant_pool.constants.len() as u16 + 1)?;
+    bytes.extend_from_slice(&constant_pool_bytes);
+    bytes.extend_from_slice(&body);
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{deserialize::deserialize_class_bytes, parse::parse};
+
+    // deserializes, parses and serializes the class file at path, relative to the crate
+    fn round_trip(path: &str) -> (Vec<u8>, Vec<u8>) {
+        let bytes = std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join(path))
+            .expect("failed to read the class file");
+        let class = parse(deserialize_class_bytes(&bytes).expect("failed to deserialize"))
+            .expect("failed to parse");
+        let serialized = serialize_class(&class).expect("failed to serialize");
+        (bytes, serialized)
+    }
+
+    #[test]
+    fn round_trips_operations() {
+        let (bytes, serialized) = round_trip("tests/Operations.class");
+        assert_eq!(serialized, bytes);
+    }
+
+    // its constants have an unpaired surrogate and a null char, which modified UTF-8 encodes in
+    // ways standard UTF-8 doesn't
+    #[test]
+    fn round_trips_strings() {
+        let (bytes, serialized) = round_trip("tests/Strings.class");
+        assert_eq!(serialized, bytes);
+    }
+}
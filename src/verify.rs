@@ -0,0 +1,1214 @@
+// A simplified version of the type checking verifier of JVMS §4.10.1, which is run before a
+// class is linked. Method bodies are checked against their StackMapTable: every instruction has
+// to find the operands it expects, branches have to land on instructions with a matching stack
+// map frame, and execution must not fall off the end of the code. Reference types are not
+// checked against each other, as that would require loading every class involved.
+
+use std::{collections::BTreeMap, error::Error};
+
+use crate::instruction::{decode, decode_all};
+use crate::parse::{
+    parse_field_descriptor, parse_method_descriptor, Attribute, Class, Constant,
+    ExceptionTableItem, FieldType, Method, ReturnDescriptor, StackMapFrame, VerificationType,
+};
+
+// controls which classes are verified, see the -Xverify option
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum VerifyMode {
+    None,
+    // only classes not coming from the JDK, which is the default
+    #[default]
+    Remote,
+    All,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    // null, objects and arrays alike
+    Reference,
+    // created by the new instruction at the offset, but no constructor was called yet
+    Uninitialized(usize),
+    // this in a constructor, before the super constructor was called
+    UninitializedThis,
+}
+
+impl Type {
+    fn width(&self) -> usize {
+        match self {
+            Type::Long | Type::Double => 2,
+            _ => 1,
+        }
+    }
+
+    fn from_field_type(field_type: &FieldType) -> Type {
+        match field_type {
+            FieldType::Integer
+            | FieldType::Boolean
+            | FieldType::Byte
+            | FieldType::Char
+            | FieldType::Short => Type::Integer,
+            FieldType::Float => Type::Float,
+            FieldType::LongInteger => Type::Long,
+            FieldType::Double => Type::Double,
+            FieldType::ClassInstance(_) | FieldType::Array(_) => Type::Reference,
+        }
+    }
+
+    fn is_assignable_to(&self, other: &Type) -> bool {
+        *other == Type::Top || self == other
+    }
+
+    fn is_reference(&self) -> bool {
+        matches!(
+            self,
+            Type::Reference | Type::Uninitialized(_) | Type::UninitializedThis
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+struct TypeState {
+    // one entry per slot, the second slot of longs and doubles is Top
+    locals: Vec<Type>,
+    // one entry per value, longs and doubles take up a single entry
+    stack: Vec<Type>,
+}
+
+impl TypeState {
+    fn is_assignable_to(&self, other: &TypeState) -> bool {
+        self.locals.len() == other.locals.len()
+            && self.stack.len() == other.stack.len()
+            && self
+                .locals
+                .iter()
+                .zip(other.locals.iter())
+                .all(|(from, to)| from.is_assignable_to(to))
+            && self
+                .stack
+                .iter()
+                .zip(other.stack.iter())
+                .all(|(from, to)| from.is_assignable_to(to))
+    }
+
+    fn stack_width(&self) -> usize {
+        self.stack.iter().map(|t| t.width()).sum()
+    }
+}
+
+// everything needed to check the instructions of a single method
+struct MethodVerifier<'a> {
+    class: &'a Class,
+    method: &'a Method,
+    code: &'a [u8],
+    max_stack: usize,
+    max_locals: usize,
+    frames: BTreeMap<usize, TypeState>,
+    state: TypeState,
+}
+
+// the format checks on access flags and members of JVMS §4.1, §4.5 and §4.6, which don't need
+// to look at any code
+fn check_format(class: &Class) -> Result<(), Box<dyn Error>> {
+    let name = &class.this_class.name;
+    let access = &class.access;
+    // module-info has none of the members checked below
+    if access.module {
+        return Ok(());
+    }
+    if access.interface {
+        if !access.r#abstract || access.is_final || access.is_super || access.r#enum {
+            return Err(format!("ClassFormatError: illegal interface flags in {name}").into());
+        }
+    } else if access.annotation || (access.is_final && access.r#abstract) {
+        return Err(format!("ClassFormatError: illegal class flags in {name}").into());
+    }
+    match &class.super_class {
+        None if name != "java/lang/Object" => {
+            return Err(format!("ClassFormatError: {name} has no super class").into())
+        }
+        Some(super_class) if access.interface && super_class.name != "java/lang/Object" => {
+            return Err(format!(
+                "ClassFormatError: the super class of interface {name} is not java/lang/Object"
+            )
+            .into())
+        }
+        _ => {}
+    }
+
+    let mut fields = vec![];
+    for field in class.fields.iter() {
+        let field_access = &field.access;
+        let visibilities = [
+            field_access.public,
+            field_access.private,
+            field_access.protected,
+        ];
+        if visibilities.iter().filter(|set| **set).count() > 1
+            || (field_access.r#final && field_access.volatile)
+            || (access.interface
+                && (!field_access.public
+                    || !field_access.r#static
+                    || !field_access.r#final
+                    || field_access.volatile
+                    || field_access.transient
+                    || field_access.r#enum))
+        {
+            return Err(format!(
+                "ClassFormatError: illegal flags for field {name}.{}",
+                field.name
+            )
+            .into());
+        }
+        let descriptor = field.descriptor.field_type.descriptor();
+        if fields.contains(&(&field.name, descriptor.clone())) {
+            return Err(format!("ClassFormatError: duplicate field {name}.{}", field.name).into());
+        }
+        fields.push((&field.name, descriptor));
+    }
+
+    let mut methods = vec![];
+    for method in class.methods.iter() {
+        let method_access = &method.access;
+        let visibilities = [
+            method_access.public,
+            method_access.private,
+            method_access.protected,
+        ];
+        let mut legal = visibilities.iter().filter(|set| **set).count() <= 1;
+        if method.name == "<clinit>" {
+            // apart from static, the flags of a class initializer are ignored
+            legal = class.major_version < 51 || method_access.r#static;
+        } else if method.name == "<init>" {
+            legal &= !access.interface
+                && !method_access.r#static
+                && !method_access.r#final
+                && !method_access.synchronized
+                && !method_access.native
+                && !method_access.r#abstract;
+        } else if access.interface {
+            // before Java 8, interfaces could only declare abstract methods
+            legal &= if class.major_version < 52 {
+                method_access.public && method_access.r#abstract
+            } else {
+                !method_access.protected
+                    && !method_access.r#final
+                    && !method_access.synchronized
+                    && !method_access.native
+            };
+        }
+        // strictfp is implied for every method since Java 17, so it may no longer be combined
+        let strict_matters = (46..61).contains(&class.major_version);
+        if method_access.r#abstract && method.name != "<clinit>" {
+            legal &= !method_access.private
+                && !method_access.r#static
+                && !method_access.r#final
+                && !method_access.synchronized
+                && !method_access.native
+                && (!strict_matters || !method_access.strict);
+        }
+        if !legal {
+            return Err(format!(
+                "ClassFormatError: illegal flags for method {name}.{}",
+                method.name
+            )
+            .into());
+        }
+        let descriptor = method.descriptor.descriptor();
+        if methods.contains(&(&method.name, descriptor.clone())) {
+            return Err(format!(
+                "ClassFormatError: duplicate method {name}.{}{descriptor}",
+                method.name
+            )
+            .into());
+        }
+        methods.push((&method.name, descriptor));
+    }
+    Ok(())
+}
+
+pub fn verify_class(class: &Class) -> Result<(), Box<dyn Error>> {
+    check_format(class)?;
+    for method in class.methods.iter() {
+        let code = method
+            .attributes
+            .iter()
+            .find(|attribute| matches!(attribute, Attribute::Code { .. }));
+        let has_body = !method.access.native && !method.access.r#abstract;
+        match (code, has_body) {
+            (
+                Some(Attribute::Code {
+                    max_stack,
+                    max_locals,
+                    bytes,
+                    exception_table,
+                    attributes,
+                }),
+                true,
+            ) => {
+                let stack_map_table = attributes
+                    .iter()
+                    .find_map(|attribute| attribute.as_stack_map_table());
+                verify_method(
+                    class,
+                    method,
+                    bytes,
+                    *max_stack,
+                    *max_locals,
+                    exception_table,
+                    stack_map_table,
+                )
+                .map_err(|e| {
+                    format!(
+                        "VerifyError: {e} in {}.{}",
+                        class.this_class.name, method.name
+                    )
+                })?;
+            }
+            (None, false) => {}
+            (Some(_), false) => {
+                return Err(format!(
+                    "VerifyError: native or abstract method {}.{} has code",
+                    class.this_class.name, method.name
+                )
+                .into())
+            }
+            (_, true) => {
+                return Err(format!(
+                    "VerifyError: method {}.{} has no code",
+                    class.this_class.name, method.name
+                )
+                .into())
+            }
+        }
+    }
+    Ok(())
+}
+
+// which local variables and operand stack slots hold references before an instruction
+#[derive(Debug, Clone)]
+pub struct ReferenceSlots {
+    pub locals: Vec<bool>,
+    // one entry per slot like on the operand stack of a frame, so longs and doubles take up two
+    pub stack: Vec<bool>,
+}
+
+// the reference slots before every instruction of a method, which the garbage collector needs to
+// tell references from other values. They come from verifying the method, so there are none for
+// class files without stack maps.
+pub fn reference_slots(
+    class: &Class,
+    method: &Method,
+) -> Result<Option<BTreeMap<usize, ReferenceSlots>>, Box<dyn Error>> {
+    let (max_stack, max_locals, bytes, exception_table, attributes) = match method
+        .attributes
+        .iter()
+        .find(|attribute| matches!(attribute, Attribute::Code { .. }))
+    {
+        Some(Attribute::Code {
+            max_stack,
+            max_locals,
+            bytes,
+            exception_table,
+            attributes,
+        }) => (max_stack, max_locals, bytes, exception_table, attributes),
+        _ => return Err(format!("method {} has no code", method.name).into()),
+    };
+    let stack_map_table = attributes
+        .iter()
+        .find_map(|attribute| attribute.as_stack_map_table());
+    let states = verify_method(
+        class,
+        method,
+        bytes,
+        *max_stack,
+        *max_locals,
+        exception_table,
+        stack_map_table,
+    )?;
+    if states.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(
+        states
+            .into_iter()
+            .map(|(pc, state)| {
+                let mut stack = vec![];
+                for value in state.stack.iter() {
+                    for _ in 0..value.width() {
+                        stack.push(value.is_reference());
+                    }
+                }
+                let slots = ReferenceSlots {
+                    locals: state
+                        .locals
+                        .iter()
+                        .map(|local| local.is_reference())
+                        .collect(),
+                    stack,
+                };
+                (pc, slots)
+            })
+            .collect(),
+    ))
+}
+
+fn verify_method(
+    class: &Class,
+    method: &Method,
+    code: &[u8],
+    max_stack: usize,
+    max_locals: usize,
+    exception_table: &[ExceptionTableItem],
+    stack_map_table: Option<&Vec<StackMapFrame>>,
+) -> Result<BTreeMap<usize, TypeState>, Box<dyn Error>> {
+    if code.is_empty() || code.len() >= 65536 {
+        return Err(format!("invalid code length {}", code.len()).into());
+    }
+
+    // the offsets of all instructions
+    let instructions: Vec<usize> = decode_all(code)?
+        .iter()
+        .map(|instruction| instruction.pc)
+        .collect();
+
+    for item in exception_table.iter() {
+        if item.start_pc >= item.end_pc
+            || instructions.binary_search(&item.start_pc).is_err()
+            || (item.end_pc != code.len() && instructions.binary_search(&item.end_pc).is_err())
+            || instructions.binary_search(&item.handler_pc).is_err()
+        {
+            return Err(format!("invalid exception handler {item:?}").into());
+        }
+        if item.catch_type != 0
+            && !matches!(
+                class.constant_pool.get(item.catch_type - 1),
+                Some(Constant::Class(_))
+            )
+        {
+            return Err(format!("catch type {} is not a class", item.catch_type).into());
+        }
+    }
+
+    // class files before java 6 come without stack maps and would need the type inferencing
+    // verifier, which isn't implemented
+    if class.major_version < 50 {
+        return Ok(BTreeMap::new());
+    }
+
+    let mut verifier = MethodVerifier {
+        class,
+        method,
+        code,
+        max_stack,
+        max_locals,
+        frames: BTreeMap::new(),
+        state: TypeState {
+            locals: vec![],
+            stack: vec![],
+        },
+    };
+    let initial_locals = verifier.initial_locals()?;
+    verifier.state = verifier.expand_frame(&initial_locals, vec![])?;
+    verifier.decode_stack_map_table(initial_locals, stack_map_table, &instructions)?;
+
+    // the type state before every instruction
+    let mut states = BTreeMap::new();
+    // whether the previous instruction might continue with the next one
+    let mut falls_through = true;
+    for pc in instructions.iter() {
+        let pc = *pc;
+        if let Some(frame) = verifier.frames.get(&pc) {
+            if falls_through && !verifier.state.is_assignable_to(frame) {
+                return Err(format!(
+                    "stack map frame {frame:?} doesn't match {:?} at {pc}",
+                    verifier.state
+                )
+                .into());
+            }
+            verifier.state = frame.clone();
+        } else if !falls_through {
+            return Err(format!("no stack map frame after unconditional branch at {pc}").into());
+        }
+
+        for item in exception_table.iter() {
+            if item.start_pc <= pc && pc < item.end_pc {
+                verifier.check_exception_handler(pc, item.handler_pc)?;
+            }
+        }
+
+        states.insert(pc, verifier.state.clone());
+        falls_through = verifier.execute(pc)?;
+        if verifier.state.stack_width() > max_stack {
+            return Err(format!("operand stack exceeds max_stack {max_stack} at {pc}").into());
+        }
+    }
+    if falls_through {
+        return Err("execution falls off the end of the code".into());
+    }
+
+    Ok(states)
+}
+
+fn read_u16(code: &[u8], pc: usize) -> Result<u16, Box<dyn Error>> {
+    let bytes = code.get(pc..pc + 2).ok_or("code is truncated")?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+impl<'a> MethodVerifier<'a> {
+    // this and the parameters, in the compressed form used by the StackMapTable
+    fn initial_locals(&self) -> Result<Vec<Type>, Box<dyn Error>> {
+        let mut locals = vec![];
+        if !self.method.access.r#static {
+            if self.method.name == "<init>" && self.class.this_class.name != "java/lang/Object" {
+                locals.push(Type::UninitializedThis);
+            } else {
+                locals.push(Type::Reference);
+            }
+        }
+        for parameter in self.method.descriptor.parameter_descriptors.iter() {
+            locals.push(Type::from_field_type(parameter));
+        }
+        Ok(locals)
+    }
+
+    // turns the compressed locals and the stack of a stack map frame into a TypeState
+    fn expand_frame(&self, locals: &[Type], stack: Vec<Type>) -> Result<TypeState, Box<dyn Error>> {
+        let mut expanded_locals = vec![];
+        for local in locals.iter() {
+            expanded_locals.push(local.clone());
+            if local.width() == 2 {
+                expanded_locals.push(Type::Top);
+            }
+        }
+        if expanded_locals.len() > self.max_locals {
+            return Err(format!(
+                "{} locals exceed max_locals {}",
+                expanded_locals.len(),
+                self.max_locals
+            )
+            .into());
+        }
+        expanded_locals.resize(self.max_locals, Type::Top);
+
+        let state = TypeState {
+            locals: expanded_locals,
+            stack,
+        };
+        if state.stack_width() > self.max_stack {
+            return Err(format!("stack map frame exceeds max_stack {}", self.max_stack).into());
+        }
+        Ok(state)
+    }
+
+    fn convert_verification_type(
+        &self,
+        verification_type: &VerificationType,
+    ) -> Result<Type, Box<dyn Error>> {
+        Ok(match verification_type {
+            VerificationType::Top => Type::Top,
+            VerificationType::Integer => Type::Integer,
+            VerificationType::Float => Type::Float,
+            VerificationType::Double => Type::Double,
+            VerificationType::Long => Type::Long,
+            VerificationType::Null => Type::Reference,
+            VerificationType::UninitializedThis => Type::UninitializedThis,
+            VerificationType::Object(index) => {
+                self.class_constant(*index)?;
+                Type::Reference
+            }
+            VerificationType::Uninitialized(offset) => {
+                if self.code.get(*offset as usize) != Some(&0xbb) {
+                    return Err(format!("uninitialized type at {offset} without new").into());
+                }
+                Type::Uninitialized(*offset as usize)
+            }
+        })
+    }
+
+    fn decode_stack_map_table(
+        &mut self,
+        initial_locals: Vec<Type>,
+        stack_map_table: Option<&Vec<StackMapFrame>>,
+        instructions: &[usize],
+    ) -> Result<(), Box<dyn Error>> {
+        let mut locals = initial_locals;
+        let mut offset = None;
+        for frame in stack_map_table
+            .map(|table| table.iter())
+            .into_iter()
+            .flatten()
+        {
+            // the first frame is at offset_delta, every other one offset_delta + 1 after the
+            // previous one
+            let frame_offset = match offset {
+                None => frame.offset_delta() as usize,
+                Some(offset) => offset + frame.offset_delta() as usize + 1,
+            };
+            offset = Some(frame_offset);
+            if instructions.binary_search(&frame_offset).is_err() {
+                return Err(
+                    format!("stack map frame at {frame_offset} is not an instruction").into(),
+                );
+            }
+
+            let stack = match frame {
+                StackMapFrame::Same { .. } => vec![],
+                StackMapFrame::SameLocals1StackItem { stack, .. } => {
+                    vec![self.convert_verification_type(stack)?]
+                }
+                StackMapFrame::Chop { count, .. } => {
+                    let count = *count as usize;
+                    if count > locals.len() {
+                        return Err(format!("chop frame at {frame_offset} removes too much").into());
+                    }
+                    locals.truncate(locals.len() - count);
+                    vec![]
+                }
+                StackMapFrame::Append {
+                    locals: appended, ..
+                } => {
+                    for local in appended.iter() {
+                        locals.push(self.convert_verification_type(local)?);
+                    }
+                    vec![]
+                }
+                StackMapFrame::Full {
+                    locals: full_locals,
+                    stack,
+                    ..
+                } => {
+                    locals = vec![];
+                    for local in full_locals.iter() {
+                        locals.push(self.convert_verification_type(local)?);
+                    }
+                    let mut converted_stack = vec![];
+                    for item in stack.iter() {
+                        converted_stack.push(self.convert_verification_type(item)?);
+                    }
+                    converted_stack
+                }
+            };
+            let state = self.expand_frame(&locals, stack)?;
+            self.frames.insert(frame_offset, state);
+        }
+        Ok(())
+    }
+
+    fn check_exception_handler(&self, pc: usize, handler_pc: usize) -> Result<(), Box<dyn Error>> {
+        let handler_frame = self.frames.get(&handler_pc).ok_or(format!(
+            "no stack map frame at exception handler {handler_pc}"
+        ))?;
+        let locals_match = self
+            .state
+            .locals
+            .iter()
+            .zip(handler_frame.locals.iter())
+            .all(|(from, to)| from.is_assignable_to(to));
+        if !locals_match || handler_frame.stack != vec![Type::Reference] {
+            return Err(format!(
+                "exception handler {handler_pc} doesn't match {:?} at {pc}",
+                self.state
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    // checks every branch target of the instruction at pc
+    fn check_branches(&self, pc: usize) -> Result<(), Box<dyn Error>> {
+        for target in decode(self.code, pc)?.branch_targets() {
+            self.check_branch(pc, target)?;
+        }
+        Ok(())
+    }
+
+    fn check_branch(&self, pc: usize, target: usize) -> Result<(), Box<dyn Error>> {
+        let frame = self.frames.get(&target).ok_or(format!(
+            "no stack map frame at branch target {target} at {pc}"
+        ))?;
+        if !self.state.is_assignable_to(frame) {
+            return Err(format!(
+                "stack map frame {frame:?} at branch target {target} doesn't match {:?} at {pc}",
+                self.state
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, t: Type) {
+        self.state.stack.push(t);
+    }
+
+    fn pop(&mut self, expected: Type) -> Result<Type, Box<dyn Error>> {
+        let actual = self.state.stack.pop().ok_or("operand stack underflow")?;
+        if actual != expected {
+            return Err(
+                format!("expected {expected:?} on the operand stack, found {actual:?}").into(),
+            );
+        }
+        Ok(actual)
+    }
+
+    // pops a value of width slots, which might consist of two values of category 1
+    fn pop_slots(&mut self, width: usize) -> Result<Vec<Type>, Box<dyn Error>> {
+        let mut values = vec![];
+        let mut popped = 0;
+        while popped < width {
+            let value = self.state.stack.pop().ok_or("operand stack underflow")?;
+            popped += value.width();
+            values.insert(0, value);
+        }
+        if popped != width {
+            return Err("value of category 2 does not fit".into());
+        }
+        Ok(values)
+    }
+
+    fn push_all(&mut self, values: &[Type]) {
+        for value in values.iter() {
+            self.push(value.clone());
+        }
+    }
+
+    fn load(&mut self, index: usize, expected: Type) -> Result<(), Box<dyn Error>> {
+        let actual = self
+            .state
+            .locals
+            .get(index)
+            .ok_or(format!("local variable {index} exceeds max_locals"))?
+            .clone();
+        let matches = match expected {
+            // aload also loads objects which are not initialized yet
+            Type::Reference => matches!(
+                actual,
+                Type::Reference | Type::Uninitialized(_) | Type::UninitializedThis
+            ),
+            _ => actual == expected,
+        };
+        if !matches {
+            return Err(format!("expected {expected:?} in local {index}, found {actual:?}").into());
+        }
+        self.push(actual);
+        Ok(())
+    }
+
+    fn store(&mut self, index: usize, expected: Type) -> Result<(), Box<dyn Error>> {
+        let value = self.state.stack.pop().ok_or("operand stack underflow")?;
+        let matches = match expected {
+            Type::Reference => matches!(
+                value,
+                Type::Reference | Type::Uninitialized(_) | Type::UninitializedThis
+            ),
+            _ => value == expected,
+        };
+        if !matches {
+            return Err(
+                format!("expected {expected:?} on the operand stack, found {value:?}").into(),
+            );
+        }
+        if index + value.width() > self.state.locals.len() {
+            return Err(format!("local variable {index} exceeds max_locals").into());
+        }
+        // overwriting the second half of a long or double invalidates it
+        if index > 0 && self.state.locals[index - 1].width() == 2 {
+            self.state.locals[index - 1] = Type::Top;
+        }
+        if value.width() == 2 {
+            self.state.locals[index + 1] = Type::Top;
+        }
+        self.state.locals[index] = value;
+        Ok(())
+    }
+
+    // pops a reference to an initialized object
+    fn pop_reference(&mut self) -> Result<(), Box<dyn Error>> {
+        self.pop(Type::Reference)?;
+        Ok(())
+    }
+
+    fn binary(&mut self, t: Type) -> Result<(), Box<dyn Error>> {
+        self.pop(t.clone())?;
+        self.pop(t.clone())?;
+        self.push(t);
+        Ok(())
+    }
+
+    fn convert(&mut self, from: Type, to: Type) -> Result<(), Box<dyn Error>> {
+        self.pop(from)?;
+        self.push(to);
+        Ok(())
+    }
+
+    fn constant(&self, index: u16) -> Result<&Constant, Box<dyn Error>> {
+        if index == 0 {
+            return Err("constant pool index 0".into());
+        }
+        self.class
+            .constant_pool
+            .get(index as usize - 1)
+            .ok_or(format!("constant pool index {index} out of bounds").into())
+    }
+
+    fn class_constant(&self, index: u16) -> Result<(), Box<dyn Error>> {
+        match self.constant(index)? {
+            Constant::Class(_) => Ok(()),
+            constant => Err(format!("expected a class at {index}, found {constant:?}").into()),
+        }
+    }
+
+    fn field_type(&self, index: u16) -> Result<Type, Box<dyn Error>> {
+        let field_ref = self
+            .constant(index)?
+            .as_field_ref()
+            .ok_or(format!("expected a field ref at {index}"))?;
+        let (_, descriptor) = field_ref
+            .1
+            .as_name_and_type()
+            .ok_or("expected a name and type")?;
+        Ok(Type::from_field_type(
+            &parse_field_descriptor(&descriptor)?.field_type,
+        ))
+    }
+
+    fn invoke(&mut self, pc: usize, opcode: u8) -> Result<(), Box<dyn Error>> {
+        let index = read_u16(self.code, pc + 1)?;
+        let (name, descriptor) = match self.constant(index)? {
+            Constant::MethodRef(_, name_and_type)
+            | Constant::InterfaceMethodRef(_, name_and_type) => name_and_type
+                .as_name_and_type()
+                .ok_or("expected a name and type")?,
+            Constant::InvokeDynamic(_, name_and_type) if opcode == 0xba => name_and_type
+                .as_name_and_type()
+                .ok_or("expected a name and type")?,
+            constant => {
+                return Err(format!("expected a method ref at {index}, found {constant:?}").into())
+            }
+        };
+        let descriptor = parse_method_descriptor(descriptor)?;
+        if name.starts_with('<') && (name != "<init>" || opcode != 0xb7) {
+            return Err(format!("{name} can't be invoked by opcode 0x{opcode:02x}").into());
+        }
+        if opcode == 0xb9 {
+            let count = self.code[pc + 3] as usize;
+            let width = 1 + descriptor
+                .parameter_descriptors
+                .iter()
+                .map(|field_type| field_type.width())
+                .sum::<usize>();
+            if count != width || self.code[pc + 4] != 0 {
+                return Err("invalid invokeinterface count".into());
+            }
+        }
+        if opcode == 0xba && (self.code[pc + 3] != 0 || self.code[pc + 4] != 0) {
+            return Err("invokedynamic needs two zero bytes".into());
+        }
+
+        for parameter in descriptor.parameter_descriptors.iter().rev() {
+            self.pop(Type::from_field_type(parameter))?;
+        }
+        if opcode == 0xb7 && name == "<init>" {
+            // the constructor initializes every copy of the object
+            let receiver = self.state.stack.pop().ok_or("operand stack underflow")?;
+            if !matches!(receiver, Type::Uninitialized(_) | Type::UninitializedThis) {
+                return Err(format!("<init> invoked on {receiver:?}").into());
+            }
+            for t in self
+                .state
+                .locals
+                .iter_mut()
+                .chain(self.state.stack.iter_mut())
+            {
+                if *t == receiver {
+                    *t = Type::Reference;
+                }
+            }
+        } else if opcode != 0xb8 && opcode != 0xba {
+            self.pop_reference()?;
+        }
+
+        if let ReturnDescriptor::FieldType(field_type) = descriptor.return_descriptor {
+            self.push(Type::from_field_type(&field_type));
+        }
+        Ok(())
+    }
+
+    fn check_return(&self, t: Option<Type>) -> Result<(), Box<dyn Error>> {
+        let expected = match &self.method.descriptor.return_descriptor {
+            ReturnDescriptor::VoidDescriptor => None,
+            ReturnDescriptor::FieldType(field_type) => Some(Type::from_field_type(field_type)),
+        };
+        if expected != t {
+            return Err(format!("returns {t:?}, but the method returns {expected:?}").into());
+        }
+        if self.method.name == "<init>" && self.state.locals.contains(&Type::UninitializedThis) {
+            return Err("constructor returns before calling the super constructor".into());
+        }
+        Ok(())
+    }
+
+    // checks the instruction at pc and applies it to the current state. Returns whether
+    // execution might continue with the next instruction.
+    fn execute(&mut self, pc: usize) -> Result<bool, Box<dyn Error>> {
+        let code = self.code;
+        let opcode = code[pc];
+        match opcode {
+            // nop
+            0x00 => {}
+            // aconst_null
+            0x01 => self.push(Type::Reference),
+            // iconst_<i>, bipush, sipush
+            0x02..=0x08 | 0x10 | 0x11 => self.push(Type::Integer),
+            // lconst_<l>
+            0x09 | 0x0a => self.push(Type::Long),
+            // fconst_<f>
+            0x0b..=0x0d => self.push(Type::Float),
+            // dconst_<d>
+            0x0e | 0x0f => self.push(Type::Double),
+            // ldc, ldc_w, ldc2_w
+            0x12..=0x14 => {
+                let index = if opcode == 0x12 {
+                    code[pc + 1] as u16
+                } else {
+                    read_u16(code, pc + 1)?
+                };
+                let t = match (self.constant(index)?, opcode) {
+                    (Constant::Integer(_), 0x12 | 0x13) => Type::Integer,
+                    (Constant::Float(_), 0x12 | 0x13) => Type::Float,
+                    (
+                        Constant::String(_)
+                        | Constant::Class(_)
+                        | Constant::MethodType(_)
+                        | Constant::MethodHandle(..),
+                        0x12 | 0x13,
+                    ) => Type::Reference,
+                    (Constant::Long(_), 0x14) => Type::Long,
+                    (Constant::Double(_), 0x14) => Type::Double,
+                    (Constant::Dynamic(_, name_and_type), _) => {
+                        let (_, descriptor) = name_and_type
+                            .as_name_and_type()
+                            .ok_or("expected a name and type")?;
+                        let t =
+                            Type::from_field_type(&parse_field_descriptor(&descriptor)?.field_type);
+                        if (t.width() == 2) != (opcode == 0x14) {
+                            return Err(format!(
+                                "can't load dynamic constant {descriptor} with opcode 0x{opcode:02x} at {pc}"
+                            )
+                            .into());
+                        }
+                        t
+                    }
+                    (constant, _) => {
+                        return Err(format!("can't load constant {constant:?} at {pc}").into())
+                    }
+                };
+                self.push(t);
+            }
+            // iload, lload, fload, dload, aload
+            0x15..=0x19 => {
+                let t = load_store_type(opcode - 0x15);
+                self.load(code[pc + 1] as usize, t)?;
+            }
+            // <x>load_<n>
+            0x1a..=0x2d => {
+                let t = load_store_type((opcode - 0x1a) / 4);
+                self.load(((opcode - 0x1a) % 4) as usize, t)?;
+            }
+            // iaload, laload, faload, daload, aaload, baload, caload, saload
+            0x2e..=0x35 => {
+                self.pop(Type::Integer)?;
+                self.pop_reference()?;
+                self.push(array_element_type(opcode - 0x2e));
+            }
+            // istore, lstore, fstore, dstore, astore
+            0x36..=0x3a => {
+                let t = load_store_type(opcode - 0x36);
+                self.store(code[pc + 1] as usize, t)?;
+            }
+            // <x>store_<n>
+            0x3b..=0x4e => {
+                let t = load_store_type((opcode - 0x3b) / 4);
+                self.store(((opcode - 0x3b) % 4) as usize, t)?;
+            }
+            // iastore, lastore, fastore, dastore, aastore, bastore, castore, sastore
+            0x4f..=0x56 => {
+                self.pop(array_element_type(opcode - 0x4f))?;
+                self.pop(Type::Integer)?;
+                self.pop_reference()?;
+            }
+            // pop
+            0x57 => {
+                self.pop_slots(1)?;
+            }
+            // pop2
+            0x58 => {
+                self.pop_slots(2)?;
+            }
+            // dup
+            0x59 => {
+                let value = self.pop_slots(1)?;
+                self.push_all(&value);
+                self.push_all(&value);
+            }
+            // dup_x1, dup_x2
+            0x5a | 0x5b => {
+                let value = self.pop_slots(1)?;
+                let below = self.pop_slots((opcode - 0x59) as usize)?;
+                self.push_all(&value);
+                self.push_all(&below);
+                self.push_all(&value);
+            }
+            // dup2
+            0x5c => {
+                let value = self.pop_slots(2)?;
+                self.push_all(&value);
+                self.push_all(&value);
+            }
+            // dup2_x1, dup2_x2
+            0x5d | 0x5e => {
+                let value = self.pop_slots(2)?;
+                let below = self.pop_slots((opcode - 0x5c) as usize)?;
+                self.push_all(&value);
+                self.push_all(&below);
+                self.push_all(&value);
+            }
+            // swap
+            0x5f => {
+                let value1 = self.pop_slots(1)?;
+                let value2 = self.pop_slots(1)?;
+                self.push_all(&value1);
+                self.push_all(&value2);
+            }
+            // add, sub, mul, div, rem
+            0x60..=0x73 => self.binary(arithmetic_type(opcode - 0x60))?,
+            // neg
+            0x74..=0x77 => {
+                let t = arithmetic_type(opcode - 0x74);
+                self.convert(t.clone(), t)?;
+            }
+            // ishl, lshl, ishr, lshr, iushr, lushr
+            0x78..=0x7d => {
+                let t = arithmetic_type((opcode - 0x78) % 2);
+                self.pop(Type::Integer)?;
+                self.convert(t.clone(), t)?;
+            }
+            // iand, land, ior, lor, ixor, lxor
+            0x7e..=0x83 => self.binary(arithmetic_type((opcode - 0x7e) % 2))?,
+            // iinc
+            0x84 => {
+                let index = code[pc + 1] as usize;
+                if self.state.locals.get(index) != Some(&Type::Integer) {
+                    return Err(format!("iinc of non-int local {index} at {pc}").into());
+                }
+            }
+            // i2l, i2f, i2d
+            0x85..=0x87 => self.convert(Type::Integer, arithmetic_type(opcode - 0x85 + 1))?,
+            // l2i, l2f, l2d
+            0x88 => self.convert(Type::Long, Type::Integer)?,
+            0x89 => self.convert(Type::Long, Type::Float)?,
+            0x8a => self.convert(Type::Long, Type::Double)?,
+            // f2i, f2l, f2d
+            0x8b => self.convert(Type::Float, Type::Integer)?,
+            0x8c => self.convert(Type::Float, Type::Long)?,
+            0x8d => self.convert(Type::Float, Type::Double)?,
+            // d2i, d2l, d2f
+            0x8e => self.convert(Type::Double, Type::Integer)?,
+            0x8f => self.convert(Type::Double, Type::Long)?,
+            0x90 => self.convert(Type::Double, Type::Float)?,
+            // i2b, i2c, i2s
+            0x91..=0x93 => self.convert(Type::Integer, Type::Integer)?,
+            // lcmp, fcmpl, fcmpg, dcmpl, dcmpg
+            0x94..=0x98 => {
+                let t = match opcode {
+                    0x94 => Type::Long,
+                    0x95 | 0x96 => Type::Float,
+                    _ => Type::Double,
+                };
+                self.pop(t.clone())?;
+                self.pop(t)?;
+                self.push(Type::Integer);
+            }
+            // if<cond>, if_icmp<cond>, if_acmp<cond>, ifnull, ifnonnull
+            0x99..=0xa6 | 0xc6 | 0xc7 => {
+                match opcode {
+                    0x99..=0x9e => {
+                        self.pop(Type::Integer)?;
+                    }
+                    0x9f..=0xa4 => {
+                        self.pop(Type::Integer)?;
+                        self.pop(Type::Integer)?;
+                    }
+                    0xa5 | 0xa6 => {
+                        self.pop_reference()?;
+                        self.pop_reference()?;
+                    }
+                    _ => {
+                        self.pop_reference()?;
+                    }
+                }
+                self.check_branches(pc)?;
+            }
+            // goto, goto_w
+            0xa7 | 0xc8 => {
+                self.check_branches(pc)?;
+                return Ok(false);
+            }
+            // jsr, ret, jsr_w
+            0xa8 | 0xa9 | 0xc9 => {
+                return Err(format!("jsr and ret are not allowed with stack maps at {pc}").into())
+            }
+            // tableswitch, lookupswitch
+            0xaa | 0xab => {
+                self.pop(Type::Integer)?;
+                self.check_branches(pc)?;
+                return Ok(false);
+            }
+            // ireturn, lreturn, freturn, dreturn, areturn
+            0xac..=0xb0 => {
+                let t = load_store_type(opcode - 0xac);
+                self.pop(t.clone())?;
+                self.check_return(Some(t))?;
+                return Ok(false);
+            }
+            // return
+            0xb1 => {
+                self.check_return(None)?;
+                return Ok(false);
+            }
+            // getstatic
+            0xb2 => {
+                let t = self.field_type(read_u16(code, pc + 1)?)?;
+                self.push(t);
+            }
+            // putstatic
+            0xb3 => {
+                let t = self.field_type(read_u16(code, pc + 1)?)?;
+                self.pop(t)?;
+            }
+            // getfield
+            0xb4 => {
+                let t = self.field_type(read_u16(code, pc + 1)?)?;
+                self.pop_reference()?;
+                self.push(t);
+            }
+            // putfield
+            0xb5 => {
+                let t = self.field_type(read_u16(code, pc + 1)?)?;
+                self.pop(t)?;
+                // constructors may assign their fields before calling the super constructor
+                let objectref = self.state.stack.pop().ok_or("operand stack underflow")?;
+                if !matches!(objectref, Type::Reference | Type::UninitializedThis) {
+                    return Err(format!("putfield on {objectref:?} at {pc}").into());
+                }
+            }
+            // invokevirtual, invokespecial, invokestatic, invokeinterface, invokedynamic
+            0xb6..=0xba => self.invoke(pc, opcode)?,
+            // new
+            0xbb => {
+                self.class_constant(read_u16(code, pc + 1)?)?;
+                self.push(Type::Uninitialized(pc));
+            }
+            // newarray
+            0xbc => {
+                if !(4..=11).contains(&code[pc + 1]) {
+                    return Err(format!("invalid array type {} at {pc}", code[pc + 1]).into());
+                }
+                self.convert(Type::Integer, Type::Reference)?;
+            }
+            // anewarray
+            0xbd => {
+                self.class_constant(read_u16(code, pc + 1)?)?;
+                self.convert(Type::Integer, Type::Reference)?;
+            }
+            // arraylength
+            0xbe => self.convert(Type::Reference, Type::Integer)?,
+            // athrow
+            0xbf => {
+                self.pop_reference()?;
+                return Ok(false);
+            }
+            // checkcast
+            0xc0 => {
+                self.class_constant(read_u16(code, pc + 1)?)?;
+                self.convert(Type::Reference, Type::Reference)?;
+            }
+            // instanceof
+            0xc1 => {
+                self.class_constant(read_u16(code, pc + 1)?)?;
+                self.convert(Type::Reference, Type::Integer)?;
+            }
+            // monitorenter, monitorexit
+            0xc2 | 0xc3 => self.pop_reference()?,
+            // wide
+            0xc4 => {
+                let opcode = code[pc + 1];
+                let index = read_u16(code, pc + 2)? as usize;
+                match opcode {
+                    0x15..=0x19 => self.load(index, load_store_type(opcode - 0x15))?,
+                    0x36..=0x3a => self.store(index, load_store_type(opcode - 0x36))?,
+                    0x84 => {
+                        if self.state.locals.get(index) != Some(&Type::Integer) {
+                            return Err(format!("iinc of non-int local {index} at {pc}").into());
+                        }
+                    }
+                    _ => {
+                        return Err(
+                            format!("jsr and ret are not allowed with stack maps at {pc}").into(),
+                        )
+                    }
+                }
+            }
+            // multianewarray
+            0xc5 => {
+                self.class_constant(read_u16(code, pc + 1)?)?;
+                let dimensions = code[pc + 3];
+                if dimensions == 0 {
+                    return Err(format!("multianewarray without dimensions at {pc}").into());
+                }
+                for _ in 0..dimensions {
+                    self.pop(Type::Integer)?;
+                }
+                self.push(Type::Reference);
+            }
+            _ => return Err(format!("invalid opcode 0x{opcode:02x} at {pc}").into()),
+        }
+        Ok(true)
+    }
+}
+
+// the types of the i, l, f, d and a variants of load, store and return
+fn load_store_type(variant: u8) -> Type {
+    match variant {
+        0 => Type::Integer,
+        1 => Type::Long,
+        2 => Type::Float,
+        3 => Type::Double,
+        _ => Type::Reference,
+    }
+}
+
+// the types of the i, l, f and d variants of arithmetic instructions
+fn arithmetic_type(variant: u8) -> Type {
+    match variant % 4 {
+        0 => Type::Integer,
+        1 => Type::Long,
+        2 => Type::Float,
+        _ => Type::Double,
+    }
+}
+
+// the types of the i, l, f, d, a, b, c and s variants of array loads and stores
+fn array_element_type(variant: u8) -> Type {
+    match variant {
+        0 | 5 | 6 | 7 => Type::Integer,
+        1 => Type::Long,
+        2 => Type::Float,
+        3 => Type::Double,
+        _ => Type::Reference,
+    }
+}
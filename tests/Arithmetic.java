@@ -0,0 +1,68 @@
+package tests;
+
+public class Arithmetic {
+    public static void main(String[] args) {
+        intOperations();
+    }
+
+    // the operations of Operations, printing the results instead of asserting them, as assert is
+    // disabled without -ea. The expected ones are in Arithmetic.expected
+    public static void intOperations() {
+        {
+            int left = 1;
+            int right = -99;
+            int plus = left + right;
+            System.out.print("1 + -99 = ");
+            System.out.println(plus);
+        }
+        {
+            int left = 1;
+            int right = 99;
+            int minus = left - right;
+            System.out.print("1 - 99 = ");
+            System.out.println(minus);
+        }
+        {
+            int left = 3;
+            int right = -9;
+            int multiply = left * right;
+            System.out.print("3 * -9 = ");
+            System.out.println(multiply);
+        }
+        {
+            int left = 10;
+            int right = -3;
+            int division = left / right;
+            System.out.print("10 / -3 = ");
+            System.out.println(division);
+        }
+        {
+            int left = 10;
+            int right = -3;
+            int modulo = left % right;
+            System.out.print("10 % -3 = ");
+            System.out.println(modulo);
+        }
+        {
+            int left = 2;
+            int right = 3;
+            int lsh = left << right;
+            System.out.print("2 << 3 = ");
+            System.out.println(lsh);
+        }
+        {
+            int left = 17;
+            int right = 3;
+            int rsh = left >> right;
+            System.out.print("17 >> 3 = ");
+            System.out.println(rsh);
+        }
+        {
+            int left = -17;
+            int right = 3;
+            int rsh = left >>> right;
+            System.out.print("-17 >>> 3 = ");
+            System.out.println(rsh);
+        }
+    }
+}
@@ -0,0 +1,37 @@
+package tests;
+
+public class Arrays {
+    public static void main(String[] args) {
+        // anewarray with a class and with an array class as the component
+        String[] strings = new String[3];
+        strings[0] = "a";
+        strings[1] = "b";
+        strings[2] = "c";
+        String[][] nested = new String[2][];
+        nested[0] = strings;
+        int[][] matrix = new int[2][];
+        matrix[0] = new int[] {1, 2};
+        matrix[1] = new int[] {3, 4};
+        System.out.println(strings.getClass().getName());
+        System.out.println(nested.getClass().getName());
+        System.out.println(matrix.getClass().getName());
+
+        // arraycopy between arrays of both kinds
+        Object[] objects = new Object[3];
+        System.arraycopy(strings, 0, objects, 0, 3);
+        System.out.println(objects[2]);
+        System.arraycopy(nested, 0, nested, 1, 1);
+        System.out.println(nested[1][1]);
+        int[][] copy = new int[2][];
+        System.arraycopy(matrix, 0, copy, 0, 2);
+        System.out.println(copy[1][0] + copy[0][1]);
+        Object[] rows = new Object[2];
+        System.arraycopy(matrix, 0, rows, 0, 2);
+        System.out.println(((int[]) rows[1])[1]);
+        try {
+            System.arraycopy(objects, 0, nested, 0, 1);
+        } catch (ArrayStoreException e) {
+            System.out.println("ArrayStoreException");
+        }
+    }
+}
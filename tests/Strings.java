@@ -0,0 +1,18 @@
+package tests;
+
+public class Strings {
+    public static void main(String[] args) {
+        // an unpaired surrogate, which its constant keeps as it is
+        String lone = "\uD800";
+        System.out.println((int) lone.charAt(0));
+        // the null char, which takes two bytes in a class file
+        String withNull = "a\0b";
+        System.out.println(withNull.length());
+        System.out.println((int) withNull.charAt(1));
+        // a char outside the basic multilingual plane, written as two surrogates
+        String pair = "\uD83D\uDE00";
+        System.out.println(pair.length());
+        System.out.println(pair.codePointAt(0));
+        System.out.println(("x" + lone).charAt(1) == lone.charAt(0));
+    }
+}